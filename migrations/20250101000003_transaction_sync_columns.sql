@@ -0,0 +1,14 @@
+-- Track when each transaction was confirmed on-chain
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS block_time TIMESTAMPTZ;
+
+-- A single Solana transaction can move several tokens for the same wallet, and every
+-- tracked wallet taking part gets its own copy, so uniqueness is per (wallet,
+-- transaction, token) rather than per transaction
+ALTER TABLE transactions DROP CONSTRAINT IF EXISTS transactions_transaction_hash_key;
+ALTER TABLE transactions
+    ADD CONSTRAINT transactions_wallet_hash_token_key
+    UNIQUE (wallet_id, transaction_hash, token_address);
+
+COMMENT ON COLUMN transactions.transaction_hash IS 'Solana transaction signature (base58)';
+COMMENT ON COLUMN transactions.block_number IS 'Slot the transaction was processed in';
+COMMENT ON COLUMN transactions.block_time IS 'Block time of the transaction as reported by the RPC node';
@@ -0,0 +1,7 @@
+-- Track when each wallet was last synced from the Solana RPC
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS last_synced_at TIMESTAMPTZ;
+
+-- The scheduler picks the least recently synced wallets first
+CREATE INDEX IF NOT EXISTS wallets_last_synced_at_idx ON wallets (last_synced_at NULLS FIRST);
+
+COMMENT ON COLUMN wallets.last_synced_at IS 'Timestamp of the last successful transaction sync';
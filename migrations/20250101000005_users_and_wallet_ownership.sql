@@ -0,0 +1,25 @@
+-- Users own wallets and authenticate with an API key
+CREATE TABLE IF NOT EXISTS users (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    name TEXT,
+    api_key_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_users_updated_at
+BEFORE UPDATE ON users
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Wallets created before multi-user support have no owner and are not visible to anyone
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS user_id UUID REFERENCES users(id) ON DELETE CASCADE;
+CREATE INDEX IF NOT EXISTS wallets_user_id_idx ON wallets (user_id);
+
+-- Different users may track the same address
+ALTER TABLE wallets DROP CONSTRAINT IF EXISTS wallets_address_key;
+ALTER TABLE wallets ADD CONSTRAINT wallets_user_id_address_key UNIQUE (user_id, address);
+
+COMMENT ON TABLE users IS 'API users who own wallets';
+COMMENT ON COLUMN users.api_key_hash IS 'SHA-256 hex digest of the user''s API key';
+COMMENT ON COLUMN wallets.user_id IS 'User who owns the wallet';
@@ -0,0 +1,18 @@
+-- Users signing in with their Solana wallet have no API key
+ALTER TABLE users ALTER COLUMN api_key_hash DROP NOT NULL;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS siws_address TEXT UNIQUE;
+
+-- One-time challenges for Sign-In-With-Solana
+CREATE TABLE IF NOT EXISTS siws_nonces (
+    nonce TEXT PRIMARY KEY,
+    address TEXT NOT NULL,
+    message TEXT NOT NULL,
+    expires_at TIMESTAMPTZ NOT NULL,
+    used_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS siws_nonces_expires_at_idx ON siws_nonces (expires_at);
+
+COMMENT ON COLUMN users.siws_address IS 'Solana address the user signs in with (Sign-In-With-Solana)';
+COMMENT ON TABLE siws_nonces IS 'One-time sign-in challenges issued to Solana wallets';
@@ -0,0 +1,6 @@
+-- Support keyset pagination ordered newest first by (created_at, id)
+CREATE INDEX IF NOT EXISTS wallets_user_created_at_id_idx
+    ON wallets (user_id, created_at DESC, id DESC);
+
+CREATE INDEX IF NOT EXISTS transactions_wallet_created_at_id_idx
+    ON transactions (wallet_id, created_at DESC, id DESC);
@@ -0,0 +1,47 @@
+-- Outgoing webhooks: users register URLs to be notified of portfolio events
+CREATE TABLE IF NOT EXISTS webhook_subscriptions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    event_types TEXT[] NOT NULL,
+    wallet_id UUID REFERENCES wallets(id) ON DELETE CASCADE,
+    threshold_usd DOUBLE PRECISION,
+    last_balance_usd DOUBLE PRECISION,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS webhook_subscriptions_user_id_idx ON webhook_subscriptions (user_id);
+CREATE INDEX IF NOT EXISTS webhook_subscriptions_wallet_id_idx ON webhook_subscriptions (wallet_id);
+
+CREATE TRIGGER update_webhook_subscriptions_updated_at
+BEFORE UPDATE ON webhook_subscriptions
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Delivery log and retry queue
+CREATE TABLE IF NOT EXISTS webhook_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    subscription_id UUID NOT NULL REFERENCES webhook_subscriptions(id) ON DELETE CASCADE,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'succeeded', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    next_attempt_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_status_code INTEGER,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS webhook_deliveries_due_idx
+    ON webhook_deliveries (next_attempt_at)
+    WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS webhook_deliveries_subscription_created_at_id_idx
+    ON webhook_deliveries (subscription_id, created_at DESC, id DESC);
+
+COMMENT ON TABLE webhook_subscriptions IS 'URLs users want notified of portfolio events';
+COMMENT ON COLUMN webhook_subscriptions.secret IS 'HMAC-SHA256 key used to sign deliveries';
+COMMENT ON COLUMN webhook_subscriptions.last_balance_usd IS 'Wallet value at the last threshold check';
+COMMENT ON TABLE webhook_deliveries IS 'Outgoing webhook deliveries, retried with exponential backoff';
@@ -0,0 +1,24 @@
+-- Cached token metadata (symbol, name, decimals, logo) fetched from the Metaplex DAS API
+CREATE TABLE IF NOT EXISTS tokens (
+    mint TEXT PRIMARY KEY,
+    symbol TEXT,
+    name TEXT,
+    decimals INTEGER,
+    logo_uri TEXT,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_tokens_updated_at
+BEFORE UPDATE ON tokens
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Native SOL has no Metaplex metadata; never refresh it
+INSERT INTO tokens (mint, symbol, name, decimals, fetched_at)
+VALUES ('So11111111111111111111111111111111111111112', 'SOL', 'Solana', 9, 'infinity')
+ON CONFLICT (mint) DO NOTHING;
+
+COMMENT ON TABLE tokens IS 'Token metadata cache, refreshed from the DAS API when stale';
+COMMENT ON COLUMN tokens.fetched_at IS 'When the metadata was last fetched; infinity for static entries';
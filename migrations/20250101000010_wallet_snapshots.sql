@@ -0,0 +1,11 @@
+-- Daily snapshots of each wallet's total value, used for historical charts
+CREATE TABLE IF NOT EXISTS snapshots (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    snapshot_date DATE NOT NULL,
+    total_value_usd DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (wallet_id, snapshot_date)
+);
+
+COMMENT ON TABLE snapshots IS 'One row per wallet and UTC day with the wallet''s total USD value';
@@ -0,0 +1,9 @@
+-- Category assigned to each balance change by the transaction classifier
+ALTER TABLE transactions
+    ADD COLUMN IF NOT EXISTS category TEXT
+    CHECK (category IN ('swap_buy', 'swap_sell', 'transfer_in', 'transfer_out', 'airdrop', 'fee'));
+
+CREATE INDEX IF NOT EXISTS transactions_wallet_category_idx
+    ON transactions (wallet_id, category, created_at DESC, id DESC);
+
+COMMENT ON COLUMN transactions.category IS 'Classifier category; NULL for rows recorded before classification';
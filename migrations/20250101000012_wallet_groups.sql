@@ -0,0 +1,26 @@
+-- Named groups of wallets, e.g. "my wallets" vs "whales I copy"
+CREATE TABLE IF NOT EXISTS wallet_groups (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CONSTRAINT wallet_groups_user_name_key UNIQUE (user_id, name)
+);
+
+CREATE TRIGGER update_wallet_groups_updated_at
+BEFORE UPDATE ON wallet_groups
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- A wallet can belong to any number of groups
+CREATE TABLE IF NOT EXISTS wallet_group_members (
+    group_id UUID NOT NULL REFERENCES wallet_groups(id) ON DELETE CASCADE,
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    added_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (group_id, wallet_id)
+);
+
+CREATE INDEX IF NOT EXISTS wallet_group_members_wallet_idx ON wallet_group_members (wallet_id);
+
+COMMENT ON TABLE wallet_groups IS 'User-defined named sets of wallets aggregated into one portfolio';
@@ -0,0 +1,8 @@
+-- Free-form context attached to a wallet by its owner
+ALTER TABLE wallets
+    ADD COLUMN IF NOT EXISTS notes TEXT,
+    ADD COLUMN IF NOT EXISTS metadata JSONB
+    CHECK (metadata IS NULL OR jsonb_typeof(metadata) = 'object');
+
+COMMENT ON COLUMN wallets.notes IS 'Owner-provided notes, e.g. "funded from CEX"';
+COMMENT ON COLUMN wallets.metadata IS 'Owner-provided JSON object of arbitrary per-wallet context';
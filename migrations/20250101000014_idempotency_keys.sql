@@ -0,0 +1,18 @@
+-- Responses of requests sent with an Idempotency-Key, replayed when the request is retried
+CREATE TABLE IF NOT EXISTS idempotency_keys (
+    -- Hash of the credential the request was sent with, so keys never collide across clients
+    scope TEXT NOT NULL,
+    key TEXT NOT NULL,
+    -- Hash of the method, path and body, to detect a key reused for another request
+    request_hash TEXT NOT NULL,
+    -- NULL while the first request is still being handled
+    status_code INTEGER,
+    content_type TEXT,
+    response_body BYTEA,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (scope, key)
+);
+
+CREATE INDEX IF NOT EXISTS idempotency_keys_created_at_idx ON idempotency_keys (created_at);
+
+COMMENT ON TABLE idempotency_keys IS 'Stored responses of idempotent requests, kept for 24 hours';
@@ -0,0 +1,75 @@
+-- Each wallet's net position per token, kept in step with the transactions table so
+-- holdings are read without aggregating every transaction
+CREATE TABLE IF NOT EXISTS holdings (
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    token_address TEXT NOT NULL,
+    token_symbol TEXT NOT NULL,
+    -- Same type as transactions.amount; zero once a position is fully sold
+    amount DECIMAL(78, 18) NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (wallet_id, token_address)
+);
+
+CREATE INDEX IF NOT EXISTS holdings_token_address_idx ON holdings (token_address);
+
+-- Applies the net change of a statement on transactions to holdings, in the same
+-- database transaction. Statement-level, so bulk loads update each position once.
+CREATE OR REPLACE FUNCTION apply_transaction_changes_to_holdings()
+RETURNS TRIGGER AS $$
+BEGIN
+    IF TG_OP IN ('UPDATE', 'DELETE') THEN
+        -- Positions of deleted wallets are already gone; nothing is re-created
+        UPDATE holdings h
+        SET amount = h.amount - removed.amount,
+            updated_at = NOW()
+        FROM (
+            SELECT wallet_id, token_address, SUM(amount) AS amount
+            FROM old_rows
+            GROUP BY wallet_id, token_address
+        ) removed
+        WHERE h.wallet_id = removed.wallet_id
+          AND h.token_address = removed.token_address;
+    END IF;
+
+    IF TG_OP IN ('INSERT', 'UPDATE') THEN
+        INSERT INTO holdings (wallet_id, token_address, token_symbol, amount)
+        SELECT wallet_id, token_address, MAX(token_symbol), SUM(amount)
+        FROM new_rows
+        GROUP BY wallet_id, token_address
+        ON CONFLICT (wallet_id, token_address) DO UPDATE
+        SET amount = holdings.amount + EXCLUDED.amount,
+            token_symbol = GREATEST(holdings.token_symbol, EXCLUDED.token_symbol),
+            updated_at = NOW();
+    END IF;
+
+    RETURN NULL;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER transactions_inserted_update_holdings
+AFTER INSERT ON transactions
+REFERENCING NEW TABLE AS new_rows
+FOR EACH STATEMENT
+EXECUTE FUNCTION apply_transaction_changes_to_holdings();
+
+CREATE TRIGGER transactions_updated_update_holdings
+AFTER UPDATE ON transactions
+REFERENCING OLD TABLE AS old_rows NEW TABLE AS new_rows
+FOR EACH STATEMENT
+EXECUTE FUNCTION apply_transaction_changes_to_holdings();
+
+CREATE TRIGGER transactions_deleted_update_holdings
+AFTER DELETE ON transactions
+REFERENCING OLD TABLE AS old_rows
+FOR EACH STATEMENT
+EXECUTE FUNCTION apply_transaction_changes_to_holdings();
+
+-- Backfill from the transactions recorded so far
+INSERT INTO holdings (wallet_id, token_address, token_symbol, amount)
+SELECT wallet_id, token_address, MAX(token_symbol), SUM(amount)
+FROM transactions
+GROUP BY wallet_id, token_address
+ON CONFLICT (wallet_id, token_address) DO NOTHING;
+
+COMMENT ON TABLE holdings IS 'Net token position per wallet, maintained from transactions by triggers';
+COMMENT ON COLUMN holdings.amount IS 'Sum of the wallet''s transaction amounts for the token';
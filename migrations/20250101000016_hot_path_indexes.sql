@@ -0,0 +1,15 @@
+-- Indexes for the hot query paths; the integration tests assert that their plans
+-- avoid sequential scans.
+--
+-- Already covered by earlier migrations:
+-- * wallet addresses are unique per owner (wallets_user_id_address_key); they are not
+--   unique across users, who each track their own copy of a wallet, and
+--   wallets_address_idx serves lookups by address alone
+-- * transactions are looked up by signature through transactions_transaction_hash_idx
+--
+-- Archived wallets are those no user owns (see degen::retention); only the retention
+-- job reads them, so there is no partial index on owned wallets.
+
+-- Time-ranged reads of a wallet's activity, e.g. per tax year or history window
+CREATE INDEX IF NOT EXISTS transactions_wallet_block_time_idx
+    ON transactions (wallet_id, block_time DESC);
@@ -0,0 +1,18 @@
+-- Progress and outcome of each wallet's latest sync or backfill, written by the sync
+-- workers and reported by GET /wallets/{id}/sync-status
+CREATE TABLE IF NOT EXISTS wallet_sync_state (
+    wallet_id UUID PRIMARY KEY REFERENCES wallets(id) ON DELETE CASCADE,
+    status TEXT NOT NULL CHECK (status IN ('idle', 'running', 'failed')),
+    kind TEXT NOT NULL CHECK (kind IN ('sync', 'backfill')),
+    last_signature TEXT,
+    signatures_processed BIGINT NOT NULL DEFAULT 0,
+    -- Unknown until every signature of the run has been listed
+    signatures_total BIGINT,
+    last_error TEXT,
+    started_at TIMESTAMPTZ NOT NULL,
+    finished_at TIMESTAMPTZ,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE wallet_sync_state IS 'Latest sync or backfill run of each wallet';
+COMMENT ON COLUMN wallet_sync_state.last_signature IS 'Signature most recently processed by the run';
@@ -0,0 +1,44 @@
+-- Persistent queue of background work: wallet syncs, snapshots and webhook deliveries.
+-- Workers claim due rows with FOR UPDATE SKIP LOCKED, so several instances share the
+-- queue and work left unfinished by a stopped instance is picked up again.
+CREATE TABLE IF NOT EXISTS jobs (
+    id UUID PRIMARY KEY,
+    kind TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    -- Identifies the work, e.g. 'sync_wallet:<wallet id>', so it is queued at most once
+    dedupe_key TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'running', 'succeeded', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    -- A running job whose lease expired is claimed again, its worker presumed dead
+    locked_until TIMESTAMPTZ,
+    last_error TEXT,
+    finished_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS jobs_due_idx ON jobs (run_at) WHERE status = 'pending';
+CREATE INDEX IF NOT EXISTS jobs_leased_idx ON jobs (locked_until) WHERE status = 'running';
+CREATE INDEX IF NOT EXISTS jobs_succeeded_finished_at_idx
+    ON jobs (finished_at) WHERE status = 'succeeded';
+CREATE UNIQUE INDEX IF NOT EXISTS jobs_active_dedupe_key_idx
+    ON jobs (dedupe_key) WHERE status IN ('pending', 'running');
+
+CREATE TRIGGER update_jobs_updated_at
+BEFORE UPDATE ON jobs
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Deliveries still pending were scheduled by the old delivery worker; queue them as jobs
+INSERT INTO jobs (id, kind, payload, dedupe_key, attempts, max_attempts, run_at)
+SELECT gen_random_uuid(), 'deliver_webhook',
+       jsonb_build_object('kind', 'deliver_webhook', 'delivery_id', id),
+       'deliver_webhook:' || id, attempts, 8, next_attempt_at
+FROM webhook_deliveries
+WHERE status = 'pending';
+
+COMMENT ON TABLE jobs IS 'Queued background work, retried with exponential backoff';
+COMMENT ON COLUMN jobs.payload IS 'The job as serialized by degen::jobs::Job';
@@ -0,0 +1,42 @@
+-- User-defined alerts on token prices and wallet values, checked by the alert evaluator
+CREATE TABLE IF NOT EXISTS alerts (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    -- degen::alerts::AlertCondition, e.g. {"type": "token_price", "mint": ..., ...}
+    condition JSONB NOT NULL,
+    -- Wallet named by the condition, so its alerts are deleted with it
+    wallet_id UUID REFERENCES wallets(id) ON DELETE CASCADE,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Whether a price condition held at the last evaluation; it fires again only
+    -- after having stopped to hold
+    condition_met BOOLEAN NOT NULL DEFAULT FALSE,
+    -- Highest wallet value since the alert was created or last fired, for value drops
+    peak_value_usd DOUBLE PRECISION,
+    last_evaluated_at TIMESTAMPTZ,
+    last_triggered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS alerts_user_id_created_at_idx ON alerts (user_id, created_at, id);
+CREATE INDEX IF NOT EXISTS alerts_wallet_id_idx ON alerts (wallet_id);
+
+CREATE TRIGGER update_alerts_updated_at
+BEFORE UPDATE ON alerts
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Each firing of an alert
+CREATE TABLE IF NOT EXISTS alert_events (
+    id UUID PRIMARY KEY,
+    alert_id UUID NOT NULL REFERENCES alerts(id) ON DELETE CASCADE,
+    value_usd DOUBLE PRECISION NOT NULL,
+    message TEXT NOT NULL,
+    fired_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS alert_events_alert_fired_at_idx
+    ON alert_events (alert_id, fired_at DESC, id DESC);
+
+COMMENT ON TABLE alerts IS 'Conditions on token prices and wallet values users want to be alerted of';
+COMMENT ON TABLE alert_events IS 'Firings of alerts, with the reading that triggered them';
@@ -0,0 +1,40 @@
+-- Channels users receive push notifications on, one per kind
+CREATE TABLE IF NOT EXISTS notification_channels (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CONSTRAINT notification_channels_kind_check CHECK (kind IN ('telegram')),
+    -- Where messages go, e.g. a Telegram chat ID
+    target TEXT NOT NULL,
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Whether alert firings are sent
+    alerts BOOLEAN NOT NULL DEFAULT TRUE,
+    -- USD value from which detected transactions are sent; none are if NULL
+    large_transaction_usd DOUBLE PRECISION,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, kind)
+);
+
+CREATE TRIGGER update_notification_channels_updated_at
+BEFORE UPDATE ON notification_channels
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Messages sent or being sent, each by a send_notification job
+CREATE TABLE IF NOT EXISTS notifications (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    kind TEXT NOT NULL,
+    target TEXT NOT NULL,
+    message TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending' CHECK (status IN ('pending', 'sent', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    sent_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS notifications_user_id_created_at_idx
+    ON notifications (user_id, created_at DESC);
+
+COMMENT ON TABLE notification_channels IS 'Per-user push notification channels and what is sent on them';
+COMMENT ON TABLE notifications IS 'Outbox of push notifications and their delivery status';
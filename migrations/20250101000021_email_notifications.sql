@@ -0,0 +1,13 @@
+-- Email notification channels and daily portfolio summaries
+ALTER TABLE notification_channels DROP CONSTRAINT notification_channels_kind_check;
+ALTER TABLE notification_channels
+    ADD CONSTRAINT notification_channels_kind_check CHECK (kind IN ('telegram', 'email'));
+
+ALTER TABLE notification_channels
+    -- Whether a portfolio summary is sent once a day
+    ADD COLUMN IF NOT EXISTS daily_summary BOOLEAN NOT NULL DEFAULT FALSE,
+    -- UTC day of the last summary sent on the channel
+    ADD COLUMN IF NOT EXISTS last_summary_on DATE;
+
+-- Subject line of email notifications
+ALTER TABLE notifications ADD COLUMN IF NOT EXISTS subject TEXT;
@@ -0,0 +1,33 @@
+-- Daily and weekly portfolio reports, generated on a cron schedule
+CREATE TABLE IF NOT EXISTS reports (
+    id UUID PRIMARY KEY,
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    period TEXT NOT NULL CHECK (period IN ('daily', 'weekly')),
+    period_start DATE NOT NULL,
+    -- UTC day the report was generated; one report per user, period and day
+    period_end DATE NOT NULL,
+    total_value_usd DOUBLE PRECISION NOT NULL,
+    previous_value_usd DOUBLE PRECISION,
+    change_usd DOUBLE PRECISION,
+    change_percent DOUBLE PRECISION,
+    realized_pnl_usd DOUBLE PRECISION NOT NULL,
+    -- Arrays of degen::reports::TokenMove
+    top_gainers JSONB NOT NULL,
+    top_losers JSONB NOT NULL,
+    -- Array of degen::reports::WalletValue
+    wallets JSONB NOT NULL,
+    -- Array of degen::models::Holding, also the reference for the next report's movers
+    holdings JSONB NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (user_id, period, period_end)
+);
+
+CREATE INDEX IF NOT EXISTS reports_user_id_created_at_idx
+    ON reports (user_id, created_at DESC, id DESC);
+
+-- Reports replace the daily summary, which keeps its opt-in as the daily report's
+ALTER TABLE notification_channels
+    ADD COLUMN IF NOT EXISTS weekly_summary BOOLEAN NOT NULL DEFAULT FALSE,
+    DROP COLUMN IF EXISTS last_summary_on;
+
+COMMENT ON TABLE reports IS 'Generated daily and weekly portfolio reports of each user';
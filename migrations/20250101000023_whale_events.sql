@@ -0,0 +1,24 @@
+-- Newly recorded transactions worth at least WHALE_THRESHOLD_USD at the time
+CREATE TABLE IF NOT EXISTS whale_events (
+    id UUID PRIMARY KEY,
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    transaction_hash TEXT NOT NULL,
+    token_address TEXT NOT NULL,
+    token_symbol TEXT NOT NULL,
+    amount DECIMAL(78, 18) NOT NULL,
+    -- Price and value when the movement was detected
+    price_usd DOUBLE PRECISION NOT NULL,
+    value_usd DOUBLE PRECISION NOT NULL,
+    block_time TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (wallet_id, transaction_hash, token_address)
+);
+
+CREATE INDEX IF NOT EXISTS whale_events_wallet_id_created_at_idx
+    ON whale_events (wallet_id, created_at DESC, id DESC);
+
+-- Whether whale movements of the user's wallets are sent
+ALTER TABLE notification_channels
+    ADD COLUMN IF NOT EXISTS whales BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON TABLE whale_events IS 'Transactions of tracked wallets flagged as whale movements';
@@ -0,0 +1,4 @@
+-- Wallets whose owner keeps them off the public PnL leaderboard
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS leaderboard_opt_out BOOLEAN NOT NULL DEFAULT FALSE;
+
+COMMENT ON COLUMN wallets.leaderboard_opt_out IS 'Whether the wallet is left out of the PnL leaderboard';
@@ -0,0 +1,11 @@
+-- Tokens each user has marked as spam, hidden from their holdings
+CREATE TABLE IF NOT EXISTS spam_tokens (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    token_address TEXT NOT NULL,
+    -- Why the token was blocked, for the user's own reference
+    reason TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (user_id, token_address)
+);
+
+COMMENT ON TABLE spam_tokens IS 'Per-user blocklist of spam token mints';
@@ -0,0 +1,18 @@
+-- Network fees and Jito tips each wallet paid, one row per transaction
+CREATE TABLE IF NOT EXISTS transaction_fees (
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    transaction_hash TEXT NOT NULL,
+    block_time TIMESTAMPTZ,
+    -- Base and priority fee together; 0 if another account paid the fee
+    fee_lamports BIGINT NOT NULL,
+    -- Part of fee_lamports above the base fee; NULL if it could not be told apart
+    priority_fee_lamports BIGINT,
+    jito_tip_lamports BIGINT NOT NULL DEFAULT 0,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (wallet_id, transaction_hash)
+);
+
+CREATE INDEX IF NOT EXISTS transaction_fees_wallet_id_block_time_idx
+    ON transaction_fees (wallet_id, block_time DESC);
+
+COMMENT ON TABLE transaction_fees IS 'Fees and tips paid by wallets in ingested transactions';
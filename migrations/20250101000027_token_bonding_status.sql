@@ -0,0 +1,5 @@
+-- Where a pump.fun token trades: on its bonding curve or, once graduated, on an AMM
+ALTER TABLE tokens ADD COLUMN IF NOT EXISTS bonding_status TEXT
+    CHECK (bonding_status IN ('bonding', 'graduated'));
+
+COMMENT ON COLUMN tokens.bonding_status IS 'Pump.fun bonding curve status; NULL for tokens not launched on pump.fun';
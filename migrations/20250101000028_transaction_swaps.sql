@@ -0,0 +1,4 @@
+-- Swaps decoded from DEX and aggregator instructions: program, route, tokens and amounts
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS swap JSONB;
+
+COMMENT ON COLUMN transactions.swap IS 'Decoded swap of the transaction; NULL if it made none or could not be decoded';
@@ -0,0 +1,4 @@
+-- .sol domains of wallets, resolved when added by domain or looked up in reverse
+ALTER TABLE wallets ADD COLUMN IF NOT EXISTS domain TEXT;
+
+COMMENT ON COLUMN wallets.domain IS 'Solana Name Service domain of the wallet, refreshed on sync';
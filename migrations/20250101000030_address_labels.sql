@@ -0,0 +1,36 @@
+-- Known entities transactions are sent to or received from: exchanges, bridges, protocols
+CREATE TABLE IF NOT EXISTS address_labels (
+    address TEXT PRIMARY KEY,
+    label TEXT NOT NULL,
+    category TEXT NOT NULL CONSTRAINT address_labels_category_check
+        CHECK (category IN ('exchange', 'bridge', 'protocol', 'other')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE TRIGGER update_address_labels_updated_at
+BEFORE UPDATE ON address_labels
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+INSERT INTO address_labels (address, label, category) VALUES
+    ('5tzFkiKscXHK5ZXCGbXZxdw7gTjjD1mBwuoFbhUvuAi9', 'Binance Hot Wallet', 'exchange'),
+    ('2ojv9BAiHUrvsm9gxDe7fJSzbNZSJcxZvf8dqmWGHG8S', 'Binance Hot Wallet', 'exchange'),
+    ('H8sMJSCQxfKiFTCfDR3DUMLPwcRbM61LGFJ8N4dK3WjS', 'Coinbase Hot Wallet', 'exchange'),
+    ('GJRs4FwHtemZ5ZE9x3FNvJ8TMwitKTh21yxdRPqn7npE', 'Coinbase Hot Wallet', 'exchange'),
+    ('5VCwKtCXgCJ6kit5FybXjvriW3xELsFDhYrPSqtJNmcD', 'OKX Hot Wallet', 'exchange'),
+    ('FWznbcNXWQuHTawe9RxvQ2LdCENssh12dsznf4RiouN5', 'Kraken Hot Wallet', 'exchange'),
+    ('AC5RDfQFmDS1deWZos921JfqscXdByf8BKHs5ACWjtW2', 'Bybit Hot Wallet', 'exchange'),
+    ('wormDTUJ6AWPNvk59vGQbDvGJmqbDTdgWgAqcLBCgUb', 'Wormhole Token Bridge', 'bridge'),
+    ('worm2ZoG2kUd4vFXhvjh93UUH596ayRfgQ2MgjNMTth', 'Wormhole Core Bridge', 'bridge'),
+    ('DEbrdGj3HsRsAzx6uH4MKyREKxVAfBydijLUF3ygsFfh', 'deBridge', 'bridge'),
+    ('5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1', 'Raydium AMM V4 Vault', 'protocol'),
+    ('GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL', 'Raydium CPMM Vault', 'protocol'),
+    ('CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM', 'Pump.fun Fees', 'protocol')
+ON CONFLICT (address) DO NOTHING;
+
+-- Account on the other side of a transaction's balance change
+ALTER TABLE transactions ADD COLUMN IF NOT EXISTS counterparty TEXT;
+
+COMMENT ON TABLE address_labels IS 'Names of known exchange, bridge and protocol addresses';
+COMMENT ON COLUMN transactions.counterparty IS 'Owner of the account whose balance of the token moved the opposite way by the most; NULL if none did';
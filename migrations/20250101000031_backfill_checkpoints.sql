@@ -0,0 +1,15 @@
+-- Where an unfinished backfill resumes: it has written every balance change of the
+-- signatures from the newest down to before_signature. Removed once the backfill reaches
+-- the wallet's first transaction.
+CREATE TABLE IF NOT EXISTS backfill_checkpoints (
+    wallet_id UUID PRIMARY KEY REFERENCES wallets(id) ON DELETE CASCADE,
+    before_signature TEXT NOT NULL,
+    signatures_processed BIGINT NOT NULL,
+    transactions_upserted BIGINT NOT NULL,
+    transactions_inserted BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE backfill_checkpoints IS 'Progress of interrupted backfills, so they resume instead of starting over';
+COMMENT ON COLUMN backfill_checkpoints.before_signature IS 'Oldest signature written; the backfill resumes with the signatures before it';
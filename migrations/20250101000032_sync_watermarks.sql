@@ -0,0 +1,7 @@
+-- Newest signature processed by a sync, so later syncs only fetch the signatures after it
+ALTER TABLE wallet_sync_state ADD COLUMN IF NOT EXISTS watermark_signature TEXT;
+-- Slot of the watermark signature; syncs stop listing below it if the signature itself
+-- is no longer returned, e.g. because its fork was dropped
+ALTER TABLE wallet_sync_state ADD COLUMN IF NOT EXISTS watermark_slot BIGINT;
+
+COMMENT ON COLUMN wallet_sync_state.watermark_signature IS 'Newest signature processed by a successful sync; NULL until the first one';
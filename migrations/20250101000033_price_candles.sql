@@ -0,0 +1,28 @@
+-- OHLCV price candles of tokens, cached from the configured candle source so charts are
+-- served without calling it on every request
+CREATE TABLE IF NOT EXISTS price_candles (
+    mint TEXT NOT NULL,
+    interval TEXT NOT NULL,
+    source TEXT NOT NULL,
+    open_time TIMESTAMPTZ NOT NULL,
+    open DOUBLE PRECISION NOT NULL,
+    high DOUBLE PRECISION NOT NULL,
+    low DOUBLE PRECISION NOT NULL,
+    close DOUBLE PRECISION NOT NULL,
+    volume DOUBLE PRECISION NOT NULL,
+    PRIMARY KEY (mint, interval, source, open_time)
+);
+
+-- When each series was last fetched from its source, and from when it is complete
+CREATE TABLE IF NOT EXISTS price_candle_fetches (
+    mint TEXT NOT NULL,
+    interval TEXT NOT NULL,
+    source TEXT NOT NULL,
+    covered_from TIMESTAMPTZ NOT NULL,
+    fetched_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (mint, interval, source)
+);
+
+COMMENT ON TABLE price_candles IS 'OHLCV candles of token prices by interval, as reported by their source';
+COMMENT ON COLUMN price_candles.source IS 'birdeye (USD prices) or swaps (SOL prices aggregated from ingested swaps)';
+COMMENT ON COLUMN price_candle_fetches.covered_from IS 'Open time of the oldest candle fetched; no candles are missing after it as of fetched_at';
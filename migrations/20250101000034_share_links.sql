@@ -0,0 +1,13 @@
+-- Revocable links exposing a read-only snapshot of a wallet's holdings and PnL without
+-- an API key; only the hash of each link's token is stored
+CREATE TABLE IF NOT EXISTS share_links (
+    id UUID PRIMARY KEY,
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    token_hash TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS share_links_wallet_idx ON share_links (wallet_id, created_at);
+
+COMMENT ON TABLE share_links IS 'Public read-only portfolio links of wallets; deleting a row revokes its link';
+COMMENT ON COLUMN share_links.token_hash IS 'SHA-256 of the token in the link, hex encoded';
@@ -0,0 +1,5 @@
+-- When each user last made an authenticated request, refreshed at most hourly, for
+-- counting active users
+ALTER TABLE users ADD COLUMN IF NOT EXISTS last_seen_at TIMESTAMPTZ;
+
+COMMENT ON COLUMN users.last_seen_at IS 'When the user last authenticated a request, to within an hour; NULL if never';
@@ -0,0 +1,16 @@
+-- Requested deletions of user accounts: confirmed with a one-time token, then purged
+-- once the grace period has passed unless cancelled
+CREATE TABLE IF NOT EXISTS account_deletions (
+    user_id UUID PRIMARY KEY REFERENCES users(id) ON DELETE CASCADE,
+    confirmation_hash TEXT NOT NULL,
+    confirm_before TIMESTAMPTZ NOT NULL,
+    purge_after TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS account_deletions_purge_idx ON account_deletions (purge_after)
+    WHERE purge_after IS NOT NULL;
+
+COMMENT ON TABLE account_deletions IS 'Pending deletions of user accounts; deleting a row cancels its deletion';
+COMMENT ON COLUMN account_deletions.confirmation_hash IS 'SHA-256 of the confirmation token, hex encoded';
+COMMENT ON COLUMN account_deletions.purge_after IS 'When the account is purged; NULL until the deletion is confirmed';
@@ -0,0 +1,15 @@
+-- Runs of the data retention job and the rows each deleted, or would have deleted in
+-- a dry run
+CREATE TABLE IF NOT EXISTS retention_runs (
+    id UUID PRIMARY KEY,
+    dry_run BOOLEAN NOT NULL,
+    webhook_deliveries BIGINT NOT NULL,
+    snapshots BIGINT NOT NULL,
+    transactions BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS retention_runs_created_at_idx ON retention_runs (created_at);
+
+COMMENT ON TABLE retention_runs IS 'Runs of the data retention job with the number of rows pruned per table';
+COMMENT ON COLUMN retention_runs.dry_run IS 'Whether rows were only counted, not deleted';
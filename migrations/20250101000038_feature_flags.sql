@@ -0,0 +1,9 @@
+-- Feature flags overridden by operators; features without a row are as configured
+CREATE TABLE IF NOT EXISTS feature_flags (
+    name TEXT PRIMARY KEY,
+    enabled BOOLEAN NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON TABLE feature_flags IS 'Operator overrides of feature flags, reloaded by every instance';
+COMMENT ON COLUMN feature_flags.name IS 'Name of the feature, e.g. sync or share_links';
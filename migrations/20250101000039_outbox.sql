@@ -0,0 +1,20 @@
+-- Events recorded in the same transaction as the change they announce. The relay fans
+-- them out to webhook deliveries and notifications, so an event is not lost when the
+-- process dies between committing the change and queuing its deliveries.
+CREATE TABLE IF NOT EXISTS outbox (
+    id UUID PRIMARY KEY,
+    wallet_id UUID NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    event_type TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    last_error TEXT,
+    processed_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX IF NOT EXISTS outbox_pending_idx ON outbox (created_at) WHERE processed_at IS NULL;
+CREATE INDEX IF NOT EXISTS outbox_processed_at_idx
+    ON outbox (processed_at) WHERE processed_at IS NOT NULL;
+
+COMMENT ON TABLE outbox IS 'Events awaiting fan-out to webhooks and notifications';
+COMMENT ON COLUMN outbox.payload IS 'The event as serialized by degen::outbox::OutboxEvent';
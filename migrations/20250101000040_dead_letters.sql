@@ -0,0 +1,19 @@
+-- Webhook deliveries and notifications that ran out of attempts, kept for operators to
+-- inspect and replay.
+CREATE TABLE IF NOT EXISTS dead_letters (
+    id UUID PRIMARY KEY,
+    kind TEXT NOT NULL CHECK (kind IN ('webhook', 'notification')),
+    reference_id UUID NOT NULL,
+    job JSONB NOT NULL,
+    attempts INTEGER NOT NULL,
+    last_error TEXT,
+    failed_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    retried_at TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS dead_letters_pending_idx
+    ON dead_letters (failed_at DESC, id DESC) WHERE retried_at IS NULL;
+
+COMMENT ON TABLE dead_letters IS 'Deliveries that exhausted their retries, replayable by operators';
+COMMENT ON COLUMN dead_letters.reference_id IS 'ID in webhook_deliveries or notifications';
+COMMENT ON COLUMN dead_letters.job IS 'The job as queued, see degen::jobs::Job';
@@ -0,0 +1,16 @@
+-- Plan and quota overrides of each user. NULL falls back to DEFAULT_PLAN and the limits
+-- of the plan.
+ALTER TABLE users ADD COLUMN IF NOT EXISTS plan TEXT;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS max_wallets BIGINT CHECK (max_wallets >= 0);
+ALTER TABLE users ADD COLUMN IF NOT EXISTS max_syncs_per_day BIGINT CHECK (max_syncs_per_day >= 0);
+
+-- On-demand syncs requested by each user per UTC day
+CREATE TABLE IF NOT EXISTS sync_usage (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    day DATE NOT NULL,
+    syncs BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, day)
+);
+
+COMMENT ON COLUMN users.plan IS 'free, pro or unlimited; NULL for DEFAULT_PLAN';
+COMMENT ON TABLE sync_usage IS 'On-demand syncs per user and UTC day, counted against max_syncs_per_day';
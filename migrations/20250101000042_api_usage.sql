@@ -0,0 +1,18 @@
+-- Requests of each user per credential, hour and endpoint, written in batches by the
+-- usage meter of every instance
+CREATE TABLE IF NOT EXISTS api_usage (
+    user_id UUID NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key_id TEXT NOT NULL,
+    hour TIMESTAMPTZ NOT NULL,
+    method TEXT NOT NULL,
+    route TEXT NOT NULL,
+    requests BIGINT NOT NULL DEFAULT 0,
+    errors BIGINT NOT NULL DEFAULT 0,
+    request_bytes BIGINT NOT NULL DEFAULT 0,
+    response_bytes BIGINT NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, hour, key_id, method, route)
+);
+
+COMMENT ON TABLE api_usage IS 'Authenticated API requests per user, credential, hour and endpoint';
+COMMENT ON COLUMN api_usage.key_id IS 'First 12 hex digits of the SHA-256 of the API key, or jwt for access tokens';
+COMMENT ON COLUMN api_usage.route IS 'Matched route without the version prefix, e.g. /wallets/:id';
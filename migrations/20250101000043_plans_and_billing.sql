@@ -0,0 +1,36 @@
+-- Limits of each plan, tunable without a redeploy, and the Stripe price a subscription
+-- to it is billed at
+CREATE TABLE IF NOT EXISTS plans (
+    name TEXT PRIMARY KEY CHECK (name IN ('free', 'pro', 'unlimited')),
+    max_wallets BIGINT CHECK (max_wallets >= 0),
+    max_syncs_per_day BIGINT CHECK (max_syncs_per_day >= 0),
+    stripe_price_id TEXT UNIQUE,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+INSERT INTO plans (name, max_wallets, max_syncs_per_day) VALUES
+    ('free', 10, 100),
+    ('pro', 500, 5000),
+    ('unlimited', NULL, NULL)
+ON CONFLICT (name) DO NOTHING;
+
+CREATE TRIGGER update_plans_updated_at
+BEFORE UPDATE ON plans
+FOR EACH ROW
+EXECUTE FUNCTION update_updated_at_column();
+
+-- Stripe customer of each user, and when the last applied subscription event happened,
+-- so events delivered out of order do not undo newer ones
+ALTER TABLE users ADD COLUMN IF NOT EXISTS stripe_customer_id TEXT UNIQUE;
+ALTER TABLE users ADD COLUMN IF NOT EXISTS billing_updated_at TIMESTAMPTZ;
+
+-- Stripe events already handled, as Stripe delivers an event more than once
+CREATE TABLE IF NOT EXISTS stripe_events (
+    id TEXT PRIMARY KEY,
+    event_type TEXT NOT NULL,
+    received_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+COMMENT ON COLUMN plans.max_wallets IS 'Most wallets a user on the plan tracks; NULL for no limit';
+COMMENT ON COLUMN plans.stripe_price_id IS 'Stripe price whose active subscriptions put users on the plan';
+COMMENT ON TABLE stripe_events IS 'IDs of handled Stripe webhook events';
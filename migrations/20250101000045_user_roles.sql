@@ -0,0 +1,5 @@
+-- Role of each user, deciding which requests they may make
+ALTER TABLE users ADD COLUMN IF NOT EXISTS role TEXT NOT NULL DEFAULT 'user'
+    CHECK (role IN ('admin', 'user', 'readonly'));
+
+COMMENT ON COLUMN users.role IS 'admin, user or readonly; see degen::auth::roles';
@@ -0,0 +1,15 @@
+-- Failed sign-ins per client address and per account, so brute-force lockouts hold
+-- across replicas
+CREATE TABLE IF NOT EXISTS login_attempts (
+    subject TEXT PRIMARY KEY,
+    failures INTEGER NOT NULL DEFAULT 0,
+    lockouts INTEGER NOT NULL DEFAULT 0,
+    last_failure_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_until TIMESTAMPTZ
+);
+
+CREATE INDEX IF NOT EXISTS login_attempts_last_failure_at_idx ON login_attempts (last_failure_at);
+
+COMMENT ON COLUMN login_attempts.subject IS 'ip:<address> or address:<Solana address> signing in';
+COMMENT ON COLUMN login_attempts.failures IS 'Consecutive failures since the last lockout';
+COMMENT ON COLUMN login_attempts.lockouts IS 'Lockouts in a row, doubling the next one';
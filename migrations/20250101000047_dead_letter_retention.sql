@@ -0,0 +1,4 @@
+-- Dead letters are pruned by the data retention job like the deliveries they replay
+ALTER TABLE retention_runs ADD COLUMN IF NOT EXISTS dead_letters BIGINT NOT NULL DEFAULT 0;
+
+CREATE INDEX IF NOT EXISTS dead_letters_failed_at_idx ON dead_letters (failed_at);
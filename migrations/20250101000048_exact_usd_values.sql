@@ -0,0 +1,13 @@
+-- USD values of snapshots, reports and whale movements are exact like the transactions
+-- they are computed from; prices and percentages stay floats
+ALTER TABLE snapshots
+    ALTER COLUMN total_value_usd TYPE NUMERIC USING total_value_usd::NUMERIC;
+
+ALTER TABLE reports
+    ALTER COLUMN total_value_usd TYPE NUMERIC USING total_value_usd::NUMERIC,
+    ALTER COLUMN previous_value_usd TYPE NUMERIC USING previous_value_usd::NUMERIC,
+    ALTER COLUMN change_usd TYPE NUMERIC USING change_usd::NUMERIC,
+    ALTER COLUMN realized_pnl_usd TYPE NUMERIC USING realized_pnl_usd::NUMERIC;
+
+ALTER TABLE whale_events
+    ALTER COLUMN value_usd TYPE NUMERIC USING value_usd::NUMERIC;
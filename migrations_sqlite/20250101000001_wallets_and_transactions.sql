@@ -0,0 +1,68 @@
+-- Schema of the repository layer for SQLite: the wallets and transactions the API
+-- reads and writes through the repositories, and the address labels transactions
+-- are listed with. Mirrors the Postgres tables after all of `migrations/`; UUIDs are
+-- stored as 16-byte blobs, timestamps as RFC 3339 text and amounts as decimal text.
+
+CREATE TABLE IF NOT EXISTS wallets (
+    id BLOB PRIMARY KEY,
+    user_id BLOB NOT NULL,
+    address TEXT NOT NULL,
+    domain TEXT,
+    name TEXT,
+    notes TEXT,
+    metadata TEXT CHECK (metadata IS NULL OR json_type(metadata) = 'object'),
+    leaderboard_opt_out INTEGER NOT NULL DEFAULT 0,
+    last_synced_at TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    UNIQUE (user_id, address)
+);
+
+CREATE INDEX IF NOT EXISTS wallets_user_created_at_id_idx
+    ON wallets (user_id, created_at DESC, id DESC);
+
+CREATE TABLE IF NOT EXISTS transactions (
+    id BLOB PRIMARY KEY,
+    wallet_id BLOB NOT NULL REFERENCES wallets(id) ON DELETE CASCADE,
+    token_address TEXT NOT NULL,
+    token_symbol TEXT NOT NULL,
+    amount TEXT NOT NULL,
+    buy_price_usd TEXT NOT NULL DEFAULT '0',
+    buy_price_sol TEXT NOT NULL DEFAULT '0',
+    transaction_hash TEXT NOT NULL,
+    block_number INTEGER NOT NULL,
+    block_time TEXT,
+    category TEXT CHECK (category IN (
+        'swap_buy', 'swap_sell', 'transfer_in', 'transfer_out', 'airdrop', 'fee'
+    )),
+    swap TEXT,
+    counterparty TEXT,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    UNIQUE (wallet_id, transaction_hash, token_address)
+);
+
+CREATE INDEX IF NOT EXISTS transactions_wallet_created_at_id_idx
+    ON transactions (wallet_id, created_at DESC, id DESC);
+
+CREATE TABLE IF NOT EXISTS address_labels (
+    address TEXT PRIMARY KEY,
+    label TEXT NOT NULL,
+    category TEXT NOT NULL CHECK (category IN ('exchange', 'bridge', 'protocol', 'other'))
+);
+
+INSERT INTO address_labels (address, label, category) VALUES
+    ('5tzFkiKscXHK5ZXCGbXZxdw7gTjjD1mBwuoFbhUvuAi9', 'Binance Hot Wallet', 'exchange'),
+    ('2ojv9BAiHUrvsm9gxDe7fJSzbNZSJcxZvf8dqmWGHG8S', 'Binance Hot Wallet', 'exchange'),
+    ('H8sMJSCQxfKiFTCfDR3DUMLPwcRbM61LGFJ8N4dK3WjS', 'Coinbase Hot Wallet', 'exchange'),
+    ('GJRs4FwHtemZ5ZE9x3FNvJ8TMwitKTh21yxdRPqn7npE', 'Coinbase Hot Wallet', 'exchange'),
+    ('5VCwKtCXgCJ6kit5FybXjvriW3xELsFDhYrPSqtJNmcD', 'OKX Hot Wallet', 'exchange'),
+    ('FWznbcNXWQuHTawe9RxvQ2LdCENssh12dsznf4RiouN5', 'Kraken Hot Wallet', 'exchange'),
+    ('AC5RDfQFmDS1deWZos921JfqscXdByf8BKHs5ACWjtW2', 'Bybit Hot Wallet', 'exchange'),
+    ('wormDTUJ6AWPNvk59vGQbDvGJmqbDTdgWgAqcLBCgUb', 'Wormhole Token Bridge', 'bridge'),
+    ('worm2ZoG2kUd4vFXhvjh93UUH596ayRfgQ2MgjNMTth', 'Wormhole Core Bridge', 'bridge'),
+    ('DEbrdGj3HsRsAzx6uH4MKyREKxVAfBydijLUF3ygsFfh', 'deBridge', 'bridge'),
+    ('5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1', 'Raydium AMM V4 Vault', 'protocol'),
+    ('GpMZbSM2GgvTKHJirzeGfMFoaZ8UR2X7F4v8vHTvxFbL', 'Raydium CPMM Vault', 'protocol'),
+    ('CebN5WGQ4jvEPvsVU4EoHEpgzq1VV7AbicfhtW4xC9iM', 'Pump.fun Fees', 'protocol')
+ON CONFLICT (address) DO NOTHING;
@@ -0,0 +1,511 @@
+//! Export and deletion of a user's account data.
+//!
+//! `GET /me/export` streams every record the user owns as one JSON document: their
+//! profile, wallets with their transactions, reports, alerts, groups, blocked spam
+//! tokens, notification channels and webhook subscriptions. Transactions are read page
+//! by page while the client downloads, so exports of large accounts are never held in
+//! memory.
+//!
+//! Deleting an account takes two steps: `DELETE /me` answers a confirmation token,
+//! and `DELETE /me?confirm=<token>` schedules the purge after a grace period, during
+//! which `DELETE /me/deletion` cancels it. The purge removes the user and everything
+//! they own in one database transaction.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::{
+    body::{Bytes, StreamBody},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::alerts;
+use crate::auth::hash_api_key;
+use crate::groups;
+use crate::models::{User, Wallet};
+use crate::notifications;
+use crate::pagination::Cursor;
+use crate::reports::{Report, REPORT_COLUMNS};
+use crate::repository::{
+    RepositoryError, TransactionQuery, TransactionRepository, WalletQuery, WalletRepository,
+};
+use crate::secrets::SecretBox;
+use crate::spam;
+use crate::webhooks::WebhookSubscription;
+use crate::AppError;
+
+/// Number of wallets or transactions read from storage per streamed chunk
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Chunks buffered ahead of a slow client before reading from storage pauses
+const EXPORT_CHANNEL_CAPACITY: usize = 4;
+
+/// How long a deletion confirmation token can be used
+pub const CONFIRMATION_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// Prefix of deletion confirmation tokens
+const CONFIRMATION_TOKEN_PREFIX: &str = "del_";
+
+/// Profile of the exported user
+#[derive(Debug, Serialize, sqlx::FromRow)]
+struct ExportedUser {
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    user: User,
+    /// Solana address the user signs in with, if any
+    siws_address: Option<String>,
+    /// When the user last authenticated a request
+    last_seen_at: Option<DateTime<Utc>>,
+}
+
+/// Why an export stopped early
+enum ExportError {
+    /// The client went away; nothing is left to send to
+    Disconnected,
+    /// Reading from storage failed
+    Failed(AppError),
+}
+
+impl From<AppError> for ExportError {
+    fn from(err: AppError) -> Self {
+        Self::Failed(err)
+    }
+}
+
+impl From<sqlx::Error> for ExportError {
+    fn from(err: sqlx::Error) -> Self {
+        Self::Failed(err.into())
+    }
+}
+
+impl From<RepositoryError> for ExportError {
+    fn from(err: RepositoryError) -> Self {
+        Self::Failed(err.into())
+    }
+}
+
+impl From<serde_json::Error> for ExportError {
+    fn from(err: serde_json::Error) -> Self {
+        Self::Failed(AppError::InternalServerError(format!(
+            "Failed to encode export: {err}"
+        )))
+    }
+}
+
+/// Buffers the export's JSON and sends it to the client in chunks
+struct ExportWriter {
+    sender: mpsc::Sender<Result<Bytes, AppError>>,
+    chunk: Vec<u8>,
+}
+
+impl ExportWriter {
+    /// Appends raw JSON syntax
+    fn raw(&mut self, json: &str) {
+        self.chunk.extend_from_slice(json.as_bytes());
+    }
+
+    /// Appends a value as JSON
+    fn value<T: Serialize>(&mut self, value: &T) -> Result<(), ExportError> {
+        serde_json::to_writer(&mut self.chunk, value)?;
+        Ok(())
+    }
+
+    /// Appends `"key":` and the values as a JSON array
+    fn array<T: Serialize>(&mut self, key: &str, values: &[T]) -> Result<(), ExportError> {
+        self.raw(&format!(",\"{key}\":["));
+        for (i, value) in values.iter().enumerate() {
+            if i > 0 {
+                self.raw(",");
+            }
+            self.value(value)?;
+        }
+        self.raw("]");
+        Ok(())
+    }
+
+    /// Sends the buffered JSON, waiting while the client is behind
+    async fn flush(&mut self) -> Result<(), ExportError> {
+        let chunk = Bytes::from(std::mem::take(&mut self.chunk));
+        self.sender
+            .send(Ok(chunk))
+            .await
+            .map_err(|_| ExportError::Disconnected)
+    }
+}
+
+/// Streams every record of the user as one JSON object
+///
+/// A storage failure ends the stream with an error, which aborts the response, so a
+/// cut-short export is never mistaken for a complete one.
+pub fn export(
+    pool: PgPool,
+    secrets: SecretBox,
+    wallets: Arc<dyn WalletRepository>,
+    transactions: Arc<dyn TransactionRepository>,
+    user_id: Uuid,
+) -> ReceiverStream<Result<Bytes, AppError>> {
+    let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let mut writer = ExportWriter {
+            sender: sender.clone(),
+            chunk: Vec::new(),
+        };
+        let result = write_export(
+            &mut writer,
+            &pool,
+            &secrets,
+            wallets.as_ref(),
+            transactions.as_ref(),
+            user_id,
+        )
+        .await;
+        if let Err(ExportError::Failed(err)) = result {
+            error!("Export of user {} failed: {}", user_id, err);
+            let _ = sender.send(Err(err)).await;
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+/// Writes the export of the user, flushing after every page read from storage
+async fn write_export(
+    writer: &mut ExportWriter,
+    pool: &PgPool,
+    secrets: &SecretBox,
+    wallets: &dyn WalletRepository,
+    transactions: &dyn TransactionRepository,
+    user_id: Uuid,
+) -> Result<(), ExportError> {
+    let user = sqlx::query_as::<_, ExportedUser>(
+        r#"
+        SELECT id, name, siws_address, last_seen_at, created_at, updated_at
+        FROM users
+        WHERE id = $1
+        "#,
+    )
+    .bind(user_id)
+    .fetch_one(pool)
+    .await?;
+
+    writer.raw("{\"exported_at\":");
+    writer.value(&Utc::now())?;
+    writer.raw(",\"user\":");
+    writer.value(&user)?;
+    writer.raw(",\"wallets\":[");
+    writer.flush().await?;
+
+    let mut first_wallet = true;
+    let mut wallet_cursor = None;
+    loop {
+        let page = wallets
+            .list(
+                user_id,
+                &WalletQuery {
+                    cursor: wallet_cursor,
+                    limit: EXPORT_PAGE_SIZE,
+                    ..WalletQuery::default()
+                },
+            )
+            .await?;
+        for wallet in &page {
+            if !first_wallet {
+                writer.raw(",");
+            }
+            first_wallet = false;
+            write_wallet(writer, transactions, wallet).await?;
+        }
+
+        match page.last() {
+            Some(last) if page.len() as i64 == EXPORT_PAGE_SIZE => {
+                wallet_cursor = Some(Cursor::new(last.created_at, last.id));
+            }
+            _ => break,
+        }
+    }
+    writer.raw("]");
+
+    let reports = sqlx::query_as::<_, Report>(&format!(
+        "SELECT {REPORT_COLUMNS} FROM reports WHERE user_id = $1 ORDER BY created_at, id"
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    writer.array("reports", &reports)?;
+    writer.flush().await?;
+
+    let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
+        r#"
+        SELECT id, url, event_types, wallet_id, threshold_usd, created_at
+        FROM webhook_subscriptions
+        WHERE user_id = $1
+        ORDER BY created_at, id
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+
+    writer.array("alerts", &alerts::list_alerts(pool, user_id).await?)?;
+    writer.array("groups", &groups::list_groups(pool, user_id).await?)?;
+    writer.array("spam_tokens", &spam::list_blocked(pool, user_id).await?)?;
+    writer.array(
+        "notification_channels",
+        &notifications::list_channels(pool, secrets, user_id).await?,
+    )?;
+    writer.array("webhook_subscriptions", &subscriptions)?;
+    writer.raw("}");
+    writer.flush().await
+}
+
+/// Writes a wallet and all of its transactions, newest first
+async fn write_wallet(
+    writer: &mut ExportWriter,
+    transactions: &dyn TransactionRepository,
+    wallet: &Wallet,
+) -> Result<(), ExportError> {
+    writer.raw("{\"wallet\":");
+    writer.value(wallet)?;
+    writer.raw(",\"transactions\":[");
+
+    let mut first = true;
+    let mut cursor = None;
+    loop {
+        let page = transactions
+            .list(
+                wallet.id,
+                &TransactionQuery {
+                    cursor,
+                    category: None,
+                    limit: EXPORT_PAGE_SIZE,
+                },
+            )
+            .await?;
+        for transaction in &page {
+            if !first {
+                writer.raw(",");
+            }
+            first = false;
+            writer.value(transaction)?;
+        }
+        writer.flush().await?;
+
+        match page.last() {
+            Some(last) if page.len() as i64 == EXPORT_PAGE_SIZE => {
+                cursor = Some(Cursor::new(last.created_at, last.id));
+            }
+            _ => break,
+        }
+    }
+
+    writer.raw("]}");
+    Ok(())
+}
+
+/// Sends an export stream as a chunked JSON download
+pub fn export_response(stream: ReceiverStream<Result<Bytes, AppError>>, user_id: Uuid) -> Response {
+    (
+        [
+            (header::CONTENT_TYPE, "application/json".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"degen-export-{user_id}.json\""),
+            ),
+        ],
+        StreamBody::new(stream),
+    )
+        .into_response()
+}
+
+/// Stage of an account deletion
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeletionStatus {
+    /// Requested; waiting for the confirmation token
+    AwaitingConfirmation,
+    /// Confirmed; the account is purged after the grace period unless cancelled
+    Scheduled,
+}
+
+/// A pending deletion of the caller's account
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AccountDeletion {
+    /// Stage of the deletion
+    pub status: DeletionStatus,
+    /// Token confirming the deletion at `DELETE /me?confirm=<token>`; only returned
+    /// when the deletion is requested
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub confirmation_token: Option<String>,
+    /// When the confirmation token expires
+    pub confirm_before: Option<DateTime<Utc>>,
+    /// When the account and all its data are purged, once confirmed
+    pub purge_after: Option<DateTime<Utc>>,
+}
+
+/// Query parameters of `DELETE /me`
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteAccountParams {
+    /// Token confirming a requested deletion
+    pub confirm: Option<String>,
+}
+
+/// Generates a new random confirmation token
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!(
+        "{CONFIRMATION_TOKEN_PREFIX}{}",
+        bs58::encode(bytes).into_string()
+    )
+}
+
+/// Requests the deletion of the user's account, returning a confirmation token
+///
+/// Requesting again replaces the token. Fails with `409 Conflict` once the deletion
+/// is confirmed; cancel it first to start over.
+pub async fn request_deletion(pool: &PgPool, user_id: Uuid) -> Result<AccountDeletion, AppError> {
+    let token = generate_token();
+    let confirm_before = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"
+        INSERT INTO account_deletions (user_id, confirmation_hash, confirm_before)
+        VALUES ($1, $2, NOW() + make_interval(secs => $3))
+        ON CONFLICT (user_id) DO UPDATE
+        SET confirmation_hash = EXCLUDED.confirmation_hash,
+            confirm_before = EXCLUDED.confirm_before,
+            created_at = NOW()
+        WHERE account_deletions.purge_after IS NULL
+        RETURNING confirm_before
+        "#,
+    )
+    .bind(user_id)
+    .bind(hash_api_key(&token))
+    .bind(CONFIRMATION_TTL.as_secs_f64())
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| {
+        AppError::Conflict("Account deletion is already scheduled; cancel it first".to_string())
+    })?;
+
+    Ok(AccountDeletion {
+        status: DeletionStatus::AwaitingConfirmation,
+        confirmation_token: Some(token),
+        confirm_before: Some(confirm_before),
+        purge_after: None,
+    })
+}
+
+/// Confirms a requested deletion, scheduling the purge of the account after `grace`
+///
+/// Fails with `400 Bad Request` if the token is wrong or expired.
+pub async fn confirm_deletion(
+    pool: &PgPool,
+    user_id: Uuid,
+    token: &str,
+    grace: Duration,
+) -> Result<AccountDeletion, AppError> {
+    let purge_after = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"
+        UPDATE account_deletions
+        SET purge_after = NOW() + make_interval(secs => $3)
+        WHERE user_id = $1
+          AND confirmation_hash = $2
+          AND confirm_before > NOW()
+          AND purge_after IS NULL
+        RETURNING purge_after
+        "#,
+    )
+    .bind(user_id)
+    .bind(hash_api_key(token))
+    .bind(grace.as_secs_f64())
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Invalid or expired confirmation token".to_string()))?;
+
+    Ok(AccountDeletion {
+        status: DeletionStatus::Scheduled,
+        confirmation_token: None,
+        confirm_before: None,
+        purge_after: Some(purge_after),
+    })
+}
+
+/// Cancels a requested or scheduled deletion, returning whether there was one
+pub async fn cancel_deletion(pool: &PgPool, user_id: Uuid) -> Result<bool, AppError> {
+    let result = sqlx::query("DELETE FROM account_deletions WHERE user_id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes the user and everything they own in one transaction
+///
+/// Wallets, transactions, reports, alerts, groups and the other records cascade from
+/// the user; jobs still queued for the user or their wallets and dead letters of their
+/// webhook deliveries and notifications are dropped with them.
+pub async fn purge_user(pool: &PgPool, user_id: Uuid) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    sqlx::query(
+        r#"
+        DELETE FROM jobs
+        WHERE status = 'pending'
+          AND (payload->>'user_id' = $1::TEXT
+               OR payload->>'wallet_id' IN (SELECT id::TEXT FROM wallets WHERE user_id = $1))
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query(
+        r#"
+        DELETE FROM dead_letters
+        WHERE (kind = 'webhook'
+               AND reference_id IN (
+                   SELECT d.id
+                   FROM webhook_deliveries d
+                   JOIN webhook_subscriptions s ON s.id = d.subscription_id
+                   WHERE s.user_id = $1))
+           OR (kind = 'notification'
+               AND reference_id IN (SELECT id FROM notifications WHERE user_id = $1))
+        "#,
+    )
+    .bind(user_id)
+    .execute(&mut *tx)
+    .await?;
+    sqlx::query("DELETE FROM users WHERE id = $1")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+
+    tx.commit().await
+}
+
+/// Purges every account whose confirmed deletion is past its grace period and returns
+/// how many were purged
+pub async fn purge_due(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let due = sqlx::query_scalar::<_, Uuid>(
+        "SELECT user_id FROM account_deletions WHERE purge_after <= NOW()",
+    )
+    .fetch_all(pool)
+    .await?;
+
+    for &user_id in &due {
+        purge_user(pool, user_id).await?;
+        info!("Purged the account of user {}", user_id);
+    }
+
+    Ok(due.len())
+}
@@ -0,0 +1,510 @@
+//! Price and wallet value alerts.
+//!
+//! Users define [`AlertCondition`]s, such as a token's price rising above a level or a
+//! wallet's value dropping by a percentage. The evaluator, run periodically through the
+//! [job queue](crate::jobs), checks every enabled alert against the price feed and
+//! records an [`AlertEvent`] in `alert_events` each time one fires, which is also
+//! pushed to the user's [notification channels](crate::notifications) and published on the
+//! [event bus](crate::events) as [`AlertFired`].
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analytics;
+use crate::events::{AlertFired, DomainEvent};
+use crate::jobs::{self, Job};
+use crate::models::WalletAddress;
+use crate::notifications;
+use crate::scheduler::{self, LeaderLock};
+use crate::AppError;
+use crate::AppState;
+
+/// Most recent events returned for an alert
+const MAX_EVENTS: i64 = 100;
+
+/// Side of a price level a token price alert watches
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PriceDirection {
+    /// The price is at or above the level
+    Above,
+    /// The price is at or below the level
+    Below,
+}
+
+/// What an alert watches for
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertCondition {
+    /// A token's USD price reaches a level. Fires when the condition starts to hold,
+    /// and again only after it stopped holding in between.
+    TokenPrice {
+        /// Mint address of the token
+        #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+        mint: String,
+        /// Whether the price must be at or above, or at or below, `price_usd`
+        direction: PriceDirection,
+        /// Price level in USD
+        #[schema(example = 0.00003)]
+        price_usd: f64,
+    },
+    /// A wallet's total USD value falls `percent` below its highest value since the
+    /// alert was created or last fired
+    WalletValueDrop {
+        /// ID of one of the caller's wallets
+        wallet_id: Uuid,
+        /// Drop from the highest value, in percent between 0 and 100
+        #[schema(example = 20.0)]
+        percent: f64,
+    },
+}
+
+impl AlertCondition {
+    /// Wallet the condition is about, if any
+    fn wallet_id(&self) -> Option<Uuid> {
+        match self {
+            Self::TokenPrice { .. } => None,
+            Self::WalletValueDrop { wallet_id, .. } => Some(*wallet_id),
+        }
+    }
+}
+
+/// A user's alert
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Alert {
+    /// Unique identifier of the alert
+    pub id: Uuid,
+    /// What the alert watches for
+    #[sqlx(json)]
+    pub condition: AlertCondition,
+    /// Whether the alert is evaluated
+    pub enabled: bool,
+    /// When the alert last fired
+    pub last_triggered_at: Option<DateTime<Utc>>,
+    /// When the alert was created
+    pub created_at: DateTime<Utc>,
+    /// When the alert was last changed or evaluated
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request payload for creating an alert
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateAlert {
+    /// What the alert watches for
+    pub condition: AlertCondition,
+    /// Whether the alert is evaluated; defaults to `true`
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+/// Request payload for updating an alert; omitted fields are left unchanged
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateAlert {
+    /// New condition, replacing the current one and starting its evaluation afresh
+    pub condition: Option<AlertCondition>,
+    /// Enables or disables the alert
+    pub enabled: Option<bool>,
+}
+
+/// A firing of an alert
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AlertEvent {
+    /// Unique identifier of the event
+    pub id: Uuid,
+    /// ID of the alert that fired
+    pub alert_id: Uuid,
+    /// Token price or wallet value that made the alert fire, in USD
+    pub value_usd: f64,
+    /// Human-readable description of what happened
+    #[schema(
+        example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263 is at $0.000031, at or above $0.00003"
+    )]
+    pub message: String,
+    /// When the alert fired
+    pub fired_at: DateTime<Utc>,
+}
+
+/// Columns of an alert as returned by the API
+const ALERT_COLUMNS: &str = "id, condition, enabled, last_triggered_at, created_at, updated_at";
+
+/// Checks a condition, normalizing the mint address, and ensures a wallet it names
+/// belongs to `user_id`
+async fn validate_condition(
+    pool: &PgPool,
+    user_id: Uuid,
+    condition: AlertCondition,
+) -> Result<AlertCondition, AppError> {
+    match condition {
+        AlertCondition::TokenPrice {
+            mint,
+            direction,
+            price_usd,
+        } => {
+            let mint = WalletAddress::parse(&mint)
+                .map_err(|err| AppError::BadRequest(format!("Invalid mint address: {err}")))?;
+            if !price_usd.is_finite() || price_usd <= 0.0 {
+                return Err(AppError::UnprocessableEntity(
+                    "price_usd must be a positive number".to_string(),
+                ));
+            }
+            Ok(AlertCondition::TokenPrice {
+                mint: mint.to_string(),
+                direction,
+                price_usd,
+            })
+        }
+        AlertCondition::WalletValueDrop { wallet_id, percent } => {
+            if !(percent > 0.0 && percent < 100.0) {
+                return Err(AppError::UnprocessableEntity(
+                    "percent must be between 0 and 100".to_string(),
+                ));
+            }
+            let owned: bool = sqlx::query_scalar(
+                "SELECT EXISTS (SELECT 1 FROM wallets WHERE id = $1 AND user_id = $2)",
+            )
+            .bind(wallet_id)
+            .bind(user_id)
+            .fetch_one(pool)
+            .await?;
+            if !owned {
+                return Err(AppError::NotFound(format!(
+                    "Wallet with ID {wallet_id} not found"
+                )));
+            }
+            Ok(AlertCondition::WalletValueDrop { wallet_id, percent })
+        }
+    }
+}
+
+/// Validates and stores a new alert for `user_id`
+pub async fn create_alert(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: CreateAlert,
+) -> Result<Alert, AppError> {
+    let condition = validate_condition(pool, user_id, request.condition).await?;
+
+    let alert = sqlx::query_as::<_, Alert>(&format!(
+        r#"
+        INSERT INTO alerts (id, user_id, condition, wallet_id, enabled)
+        VALUES ($1, $2, $3, $4, $5)
+        RETURNING {ALERT_COLUMNS}
+        "#
+    ))
+    .bind(Uuid::now_v7())
+    .bind(user_id)
+    .bind(sqlx::types::Json(&condition))
+    .bind(condition.wallet_id())
+    .bind(request.enabled)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(alert)
+}
+
+/// Lists the alerts of `user_id`, oldest first
+pub async fn list_alerts(pool: &PgPool, user_id: Uuid) -> Result<Vec<Alert>, sqlx::Error> {
+    sqlx::query_as::<_, Alert>(&format!(
+        "SELECT {ALERT_COLUMNS} FROM alerts WHERE user_id = $1 ORDER BY created_at, id"
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Looks up an alert owned by `user_id`, returning `404 Not Found` if it does not
+/// exist or belongs to someone else
+pub async fn find_alert(pool: &PgPool, user_id: Uuid, alert_id: Uuid) -> Result<Alert, AppError> {
+    sqlx::query_as::<_, Alert>(&format!(
+        "SELECT {ALERT_COLUMNS} FROM alerts WHERE id = $1 AND user_id = $2"
+    ))
+    .bind(alert_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Alert with ID {alert_id} not found")))
+}
+
+/// Replaces an alert's condition and/or enables or disables it
+pub async fn update_alert(
+    pool: &PgPool,
+    user_id: Uuid,
+    alert_id: Uuid,
+    request: UpdateAlert,
+) -> Result<Alert, AppError> {
+    let condition = match request.condition {
+        Some(condition) => Some(validate_condition(pool, user_id, condition).await?),
+        None => None,
+    };
+
+    // A new condition forgets what was observed under the old one
+    sqlx::query_as::<_, Alert>(&format!(
+        r#"
+        UPDATE alerts
+        SET condition = COALESCE($3, condition),
+            wallet_id = CASE WHEN $3 IS NULL THEN wallet_id ELSE $4 END,
+            condition_met = CASE WHEN $3 IS NULL THEN condition_met ELSE FALSE END,
+            peak_value_usd = CASE WHEN $3 IS NULL THEN peak_value_usd END,
+            enabled = COALESCE($5, enabled)
+        WHERE id = $1 AND user_id = $2
+        RETURNING {ALERT_COLUMNS}
+        "#
+    ))
+    .bind(alert_id)
+    .bind(user_id)
+    .bind(condition.as_ref().map(sqlx::types::Json))
+    .bind(condition.as_ref().and_then(AlertCondition::wallet_id))
+    .bind(request.enabled)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Alert with ID {alert_id} not found")))
+}
+
+/// Deletes an alert and its events, returning `404 Not Found` if it does not exist
+pub async fn delete_alert(pool: &PgPool, user_id: Uuid, alert_id: Uuid) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM alerts WHERE id = $1 AND user_id = $2")
+        .bind(alert_id)
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Alert with ID {alert_id} not found"
+        )));
+    }
+    Ok(())
+}
+
+/// Most recent firings of an alert, newest first
+pub async fn list_events(pool: &PgPool, alert_id: Uuid) -> Result<Vec<AlertEvent>, sqlx::Error> {
+    sqlx::query_as::<_, AlertEvent>(
+        r#"
+        SELECT id, alert_id, value_usd, message, fired_at
+        FROM alert_events
+        WHERE alert_id = $1
+        ORDER BY fired_at DESC, id DESC
+        LIMIT $2
+        "#,
+    )
+    .bind(alert_id)
+    .bind(MAX_EVENTS)
+    .fetch_all(pool)
+    .await
+}
+
+/// Evaluation state of an alert, as stored
+#[derive(Debug, sqlx::FromRow)]
+struct AlertState {
+    id: Uuid,
+    user_id: Uuid,
+    #[sqlx(json)]
+    condition: AlertCondition,
+    condition_met: bool,
+    peak_value_usd: Option<f64>,
+}
+
+/// Result of checking an alert against a reading
+#[derive(Debug, PartialEq)]
+struct Check {
+    /// Description of the firing, if the alert fired
+    fired: Option<String>,
+    /// Whether the condition holds
+    condition_met: bool,
+    /// Highest wallet value to compare future readings with
+    peak_value_usd: Option<f64>,
+}
+
+/// Checks an alert against the current token price or wallet value
+fn check(alert: &AlertState, value_usd: f64) -> Check {
+    match &alert.condition {
+        AlertCondition::TokenPrice {
+            mint,
+            direction,
+            price_usd,
+        } => {
+            let condition_met = match direction {
+                PriceDirection::Above => value_usd >= *price_usd,
+                PriceDirection::Below => value_usd <= *price_usd,
+            };
+            let side = match direction {
+                PriceDirection::Above => "at or above",
+                PriceDirection::Below => "at or below",
+            };
+            Check {
+                fired: (condition_met && !alert.condition_met)
+                    .then(|| format!("{mint} is at ${value_usd}, {side} ${price_usd}")),
+                condition_met,
+                peak_value_usd: None,
+            }
+        }
+        AlertCondition::WalletValueDrop { percent, .. } => {
+            let peak = alert.peak_value_usd.unwrap_or(value_usd).max(value_usd);
+            let dropped = peak > 0.0 && value_usd <= peak * (1.0 - percent / 100.0);
+            match dropped {
+                // Measured from the value at firing, so a further drop fires again
+                true => Check {
+                    fired: Some(format!(
+                        "Wallet value dropped {:.1}% from ${peak:.2} to ${value_usd:.2}",
+                        (1.0 - value_usd / peak) * 100.0
+                    )),
+                    condition_met: false,
+                    peak_value_usd: Some(value_usd),
+                },
+                false => Check {
+                    fired: None,
+                    condition_met: false,
+                    peak_value_usd: Some(peak),
+                },
+            }
+        }
+    }
+}
+
+/// Spawns the background task queueing an evaluation of all alerts every `interval`
+///
+/// Only the instance holding `leader` queues.
+pub fn spawn_alert_evaluator(
+    state: AppState,
+    interval: Duration,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!("Starting alert evaluator with interval {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if !scheduler::is_leader(&leader).await {
+                continue;
+            }
+
+            if let Err(err) = jobs::enqueue(&state.db_pool, &Job::EvaluateAlerts).await {
+                error!("Queueing alert evaluation failed: {}", err);
+            }
+        }
+    })
+}
+
+/// Checks every enabled alert against the price feed, records those that fire and
+/// queues notifications of them; returns how many fired
+///
+/// Prices are fetched once for all token price alerts. Alerts whose token has no
+/// known price, or whose wallet could not be valued, are skipped until the next run.
+pub async fn evaluate(state: &AppState) -> Result<usize, AppError> {
+    let pool = &state.db_pool;
+    let alerts = sqlx::query_as::<_, AlertState>(
+        "SELECT id, user_id, condition, condition_met, peak_value_usd FROM alerts WHERE enabled",
+    )
+    .fetch_all(pool)
+    .await?;
+    if alerts.is_empty() {
+        return Ok(0);
+    }
+
+    let mut mints = Vec::new();
+    let mut wallet_ids = HashSet::new();
+    for alert in &alerts {
+        match &alert.condition {
+            AlertCondition::TokenPrice { mint, .. } => mints.push(mint.clone()),
+            AlertCondition::WalletValueDrop { wallet_id, .. } => {
+                wallet_ids.insert(*wallet_id);
+            }
+        }
+    }
+    mints.sort();
+    mints.dedup();
+    let prices = if mints.is_empty() {
+        HashMap::new()
+    } else {
+        state.prices.prices_usd(&mints).await?
+    };
+    let mut wallet_values = HashMap::new();
+    for wallet_id in wallet_ids {
+        match analytics::wallet_holdings(pool, state.prices.as_ref(), wallet_id).await {
+            Ok(holdings) => {
+                wallet_values.insert(wallet_id, holdings.total_value_usd.to_f64());
+            }
+            Err(err) => warn!("Valuing wallet {} for alerts failed: {}", wallet_id, err),
+        }
+    }
+
+    let mut fired = 0;
+    for alert in &alerts {
+        let value_usd = match &alert.condition {
+            AlertCondition::TokenPrice { mint, .. } => prices.get(mint),
+            AlertCondition::WalletValueDrop { wallet_id, .. } => wallet_values.get(wallet_id),
+        };
+        let Some(&value_usd) = value_usd else {
+            debug!("No reading for alert {}", alert.id);
+            continue;
+        };
+
+        let check = check(alert, value_usd);
+        let mut tx = pool.begin().await?;
+        sqlx::query(
+            r#"
+            UPDATE alerts
+            SET condition_met = $2,
+                peak_value_usd = $3,
+                last_evaluated_at = NOW(),
+                last_triggered_at = CASE WHEN $4 THEN NOW() ELSE last_triggered_at END
+            WHERE id = $1
+            "#,
+        )
+        .bind(alert.id)
+        .bind(check.condition_met)
+        .bind(check.peak_value_usd)
+        .bind(check.fired.is_some())
+        .execute(&mut *tx)
+        .await?;
+        if let Some(message) = &check.fired {
+            sqlx::query(
+                "INSERT INTO alert_events (id, alert_id, value_usd, message) VALUES ($1, $2, $3, $4)",
+            )
+            .bind(Uuid::now_v7())
+            .bind(alert.id)
+            .bind(value_usd)
+            .bind(message)
+            .execute(&mut *tx)
+            .await?;
+            notifications::notify_alert(&mut tx, alert.user_id, message).await?;
+            info!("Alert {} fired: {}", alert.id, message);
+            fired += 1;
+        }
+        tx.commit().await?;
+
+        if let Some(message) = check.fired {
+            state
+                .events
+                .publish_event(DomainEvent::AlertFired(AlertFired {
+                    alert_id: alert.id,
+                    user_id: alert.user_id,
+                    wallet_id: match &alert.condition {
+                        AlertCondition::TokenPrice { .. } => None,
+                        AlertCondition::WalletValueDrop { wallet_id, .. } => Some(*wallet_id),
+                    },
+                    value_usd,
+                    message,
+                    occurred_at: Utc::now(),
+                }));
+        }
+    }
+
+    Ok(fired)
+}
@@ -0,0 +1,150 @@
+//! Portfolio allocation: the share of a wallet's value in each token and asset class.
+
+use std::cmp::Reverse;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::{Holding, WalletHoldings};
+use crate::money::Usd;
+use crate::sync::NATIVE_SOL_MINT;
+
+/// Mints treated as USD stablecoins: USDC, USDT and PYUSD
+pub const STABLECOIN_MINTS: [&str; 3] = [
+    "EPjFWdd5AufqSSqeM2qN1xzybapC8G4wEGGkZwyTDt1v",
+    "Es9vMFrzaCERmJfrF4H2FYD4KCoNkY11McCe8BenwNYB",
+    "2b1kV6DkPAnxd5ixfnxCpjxmKwqjjaYmCZfHsFu24GXo",
+];
+
+/// Asset class a token is allocated to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum AssetCategory {
+    /// Native or wrapped SOL
+    Sol,
+    /// USD stablecoins
+    Stablecoins,
+    /// Every other token
+    Memecoins,
+}
+
+impl AssetCategory {
+    /// Category of the token with the given mint
+    pub fn of(token_address: &str) -> Self {
+        if token_address == NATIVE_SOL_MINT {
+            Self::Sol
+        } else if STABLECOIN_MINTS.contains(&token_address) {
+            Self::Stablecoins
+        } else {
+            Self::Memecoins
+        }
+    }
+}
+
+/// Share of the wallet's value in one token
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenAllocation {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// Asset class of the token
+    pub category: AssetCategory,
+    /// Current USD value of the position
+    #[schema(value_type = f64)]
+    pub value_usd: Usd,
+    /// Share of the allocated value, in percent
+    pub percent: f64,
+}
+
+/// Share of the wallet's value in one asset class
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CategoryAllocation {
+    /// Asset class
+    pub category: AssetCategory,
+    /// Current USD value of the positions in the class
+    #[schema(value_type = f64)]
+    pub value_usd: Usd,
+    /// Share of the allocated value, in percent
+    pub percent: f64,
+}
+
+/// Breakdown of a wallet's value by token and by asset class
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletAllocation {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// USD value the percentages are shares of
+    #[schema(value_type = f64)]
+    pub total_value_usd: Usd,
+    /// USD value of the positions below the dust threshold, left out of the breakdown
+    #[schema(value_type = f64)]
+    pub dust_value_usd: Usd,
+    /// Tokens from the largest position down
+    pub tokens: Vec<TokenAllocation>,
+    /// Asset classes from the largest down
+    pub categories: Vec<CategoryAllocation>,
+}
+
+/// Breaks down the priced positions worth at least `dust_threshold_usd`
+///
+/// Positions without a price cannot be allocated and are left out.
+pub fn allocation(holdings: WalletHoldings, dust_threshold_usd: f64) -> WalletAllocation {
+    let dust_threshold_usd = Usd::from_f64(dust_threshold_usd);
+    let priced: Vec<(Holding, Usd)> = holdings
+        .holdings
+        .into_iter()
+        .filter_map(|holding| holding.value_usd.map(|value| (holding, value)))
+        .filter(|(_, value)| value.is_positive())
+        .collect();
+    let (kept, dust): (Vec<_>, Vec<_>) = priced
+        .into_iter()
+        .partition(|(_, value)| *value >= dust_threshold_usd);
+
+    let total_value_usd: Usd = kept.iter().map(|(_, value)| value).sum();
+    let percent = |value: Usd| value.percent_of(total_value_usd).unwrap_or_default();
+
+    let mut tokens: Vec<TokenAllocation> = kept
+        .into_iter()
+        .map(|(holding, value_usd)| TokenAllocation {
+            category: AssetCategory::of(&holding.token_address),
+            token_address: holding.token_address,
+            token_symbol: holding.token_symbol,
+            value_usd,
+            percent: percent(value_usd),
+        })
+        .collect();
+    tokens.sort_by_key(|token| Reverse(token.value_usd));
+
+    let mut categories: Vec<CategoryAllocation> = [
+        AssetCategory::Sol,
+        AssetCategory::Stablecoins,
+        AssetCategory::Memecoins,
+    ]
+    .into_iter()
+    .filter_map(|category| {
+        let value_usd: Usd = tokens
+            .iter()
+            .filter(|token| token.category == category)
+            .map(|token| token.value_usd)
+            .sum();
+        value_usd.is_positive().then(|| CategoryAllocation {
+            category,
+            value_usd,
+            percent: percent(value_usd),
+        })
+    })
+    .collect();
+    categories.sort_by_key(|category| Reverse(category.value_usd));
+
+    WalletAllocation {
+        wallet_id: holdings.wallet_id,
+        total_value_usd,
+        dust_value_usd: dust.iter().map(|(_, value)| value).sum(),
+        tokens,
+        categories,
+    }
+}
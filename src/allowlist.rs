@@ -0,0 +1,77 @@
+//! Network allowlist of the admin endpoints.
+//!
+//! With `ADMIN_ALLOWED_CIDRS` set, requests to `/admin/*` are only accepted from
+//! client addresses in the listed networks and answer `403` with the code `forbidden`
+//! otherwise, whatever credential they carry. The client address is resolved as
+//! described in [`client_ip`], so deployments behind a reverse proxy list it in
+//! `TRUSTED_PROXIES`. Requests whose address is unknown, such as over a Unix socket,
+//! are refused.
+
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use ipnet::IpNet;
+use tracing::warn;
+
+use crate::client_ip::{self, ClientIp};
+use crate::config::Config;
+use crate::maintenance::is_admin;
+use crate::AppError;
+
+/// Networks the admin endpoints accept requests from
+#[derive(Debug, Clone, Default)]
+pub struct AdminAllowlist {
+    /// Allowed client networks, or `None` to allow any client
+    allowed: Option<Vec<IpNet>>,
+}
+
+impl AdminAllowlist {
+    /// Allowlist of `ADMIN_ALLOWED_CIDRS`
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            allowed: config.admin_allowed_cidrs.clone(),
+        }
+    }
+
+    /// Whether a request from `client` may reach the admin endpoints
+    pub fn permits(&self, client: Option<IpAddr>) -> bool {
+        match &self.allowed {
+            Some(allowed) => client.is_some_and(|ip| client_ip::is_listed(ip, allowed)),
+            None => true,
+        }
+    }
+}
+
+/// Middleware refusing admin requests from clients outside the allowlist
+pub async fn admin_allowlist_middleware<B>(
+    State(allowlist): State<Arc<AdminAllowlist>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !is_admin(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let client = request
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| *ip);
+    if allowlist.permits(client) {
+        return next.run(request).await;
+    }
+
+    warn!(
+        "Refused {} {} from {} outside the admin allowlist",
+        request.method(),
+        request.uri().path(),
+        client.map_or("an unknown address".to_string(), |ip| ip.to_string())
+    );
+    AppError::Forbidden("Admin endpoints are not available from this address".to_string())
+        .into_response()
+}
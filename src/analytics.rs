@@ -0,0 +1,612 @@
+use std::collections::{HashMap, VecDeque};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::classify::TransactionCategory;
+use crate::holdings;
+use crate::models::{Holding, Portfolio, WalletHoldings};
+use crate::money::{self, TokenAmount, Usd};
+use crate::prices::PriceSource;
+use crate::sync::NATIVE_SOL_MINT;
+use crate::AppError;
+
+/// How the cost of tokens sold is matched against earlier purchases
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CostBasisMethod {
+    /// First in, first out: the oldest lots are sold first
+    #[default]
+    Fifo,
+    /// Last in, first out: the newest lots are sold first
+    Lifo,
+    /// Average cost: every unit carries the running average purchase price
+    Avg,
+}
+
+/// A single balance change of one token, in chronological order
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Trade {
+    /// Signed token amount: positive for tokens received, negative for tokens sent
+    pub amount: TokenAmount,
+    /// USD price per token at the time of the trade
+    pub price_usd: Usd,
+    /// When the trade happened
+    pub time: DateTime<Utc>,
+    /// Classifier category, or `None` for transactions recorded before classification
+    pub category: Option<TransactionCategory>,
+}
+
+/// Result of running the cost-basis engine over a token's trades
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CostBasis {
+    /// Profit realized by the sells, in USD
+    pub realized_pnl_usd: Usd,
+    /// Amount of the token still held
+    pub amount_held: TokenAmount,
+    /// Total USD cost of the amount still held
+    pub cost_basis_usd: Usd,
+}
+
+impl CostBasis {
+    /// Unrealized profit of the remaining position at the given price
+    pub fn unrealized_pnl_usd(&self, price_usd: Usd) -> Usd {
+        self.amount_held * price_usd - self.cost_basis_usd
+    }
+}
+
+/// Part of a sell matched against one purchase lot
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Disposal {
+    /// When the matched tokens were bought, or `None` for tokens sold beyond what
+    /// was bought, which carry zero cost
+    ///
+    /// Under the average-cost method this is the earliest purchase of the pooled lot.
+    pub acquired_at: Option<DateTime<Utc>>,
+    /// When the tokens were sold
+    pub sold_at: DateTime<Utc>,
+    /// Amount of the token sold
+    pub amount: TokenAmount,
+    /// USD received for the tokens
+    pub proceeds_usd: Usd,
+    /// USD paid for the tokens
+    pub cost_basis_usd: Usd,
+}
+
+impl Disposal {
+    /// Profit realized by the disposal, in USD
+    pub fn gain_usd(&self) -> Usd {
+        self.proceeds_usd - self.cost_basis_usd
+    }
+}
+
+/// An open purchase lot
+#[derive(Debug, Clone, Copy)]
+struct Lot {
+    amount: TokenAmount,
+    price_usd: Usd,
+    acquired_at: DateTime<Utc>,
+}
+
+/// Matches each sell against the open lots, reporting every match to `on_disposal`
+/// along with the index of the sell, and returns the lots left open
+fn match_lots(
+    trades: &[Trade],
+    method: CostBasisMethod,
+    mut on_disposal: impl FnMut(usize, Disposal),
+) -> VecDeque<Lot> {
+    let mut lots: VecDeque<Lot> = VecDeque::new();
+
+    for (index, trade) in trades.iter().enumerate() {
+        if trade.amount.is_positive() {
+            let lot = Lot {
+                amount: trade.amount,
+                price_usd: trade.price_usd,
+                acquired_at: trade.time,
+            };
+            match method {
+                CostBasisMethod::Avg => {
+                    // Keep a single lot carrying the running average price
+                    let lot = match lots.pop_front() {
+                        Some(held) => {
+                            let amount = held.amount + lot.amount;
+                            Lot {
+                                amount,
+                                price_usd: (held.amount * held.price_usd
+                                    + lot.amount * lot.price_usd)
+                                    / amount,
+                                acquired_at: held.acquired_at,
+                            }
+                        }
+                        None => lot,
+                    };
+                    lots.push_back(lot);
+                }
+                CostBasisMethod::Fifo | CostBasisMethod::Lifo => lots.push_back(lot),
+            }
+            continue;
+        }
+
+        let mut to_sell = -trade.amount;
+        while to_sell.is_positive() {
+            let next = match method {
+                CostBasisMethod::Lifo => lots.back_mut(),
+                CostBasisMethod::Fifo | CostBasisMethod::Avg => lots.front_mut(),
+            };
+            let Some(lot) = next else {
+                break;
+            };
+
+            let matched = lot.amount.min(to_sell);
+            on_disposal(
+                index,
+                Disposal {
+                    acquired_at: Some(lot.acquired_at),
+                    sold_at: trade.time,
+                    amount: matched,
+                    proceeds_usd: matched * trade.price_usd,
+                    cost_basis_usd: matched * lot.price_usd,
+                },
+            );
+            lot.amount -= matched;
+            to_sell -= matched;
+
+            if !lot.amount.is_positive() {
+                match method {
+                    CostBasisMethod::Lifo => lots.pop_back(),
+                    CostBasisMethod::Fifo | CostBasisMethod::Avg => lots.pop_front(),
+                };
+            }
+        }
+
+        if to_sell.is_positive() {
+            on_disposal(
+                index,
+                Disposal {
+                    acquired_at: None,
+                    sold_at: trade.time,
+                    amount: to_sell,
+                    proceeds_usd: to_sell * trade.price_usd,
+                    cost_basis_usd: Usd::ZERO,
+                },
+            );
+        }
+    }
+
+    lots
+}
+
+/// Computes realized PnL and the remaining cost basis of a token's trades
+///
+/// Trades must be in chronological order. Tokens sold beyond what was bought
+/// (e.g. received before tracking started) are treated as having zero cost.
+pub fn cost_basis(trades: &[Trade], method: CostBasisMethod) -> CostBasis {
+    let mut realized = Usd::ZERO;
+    let lots = match_lots(trades, method, |_, disposal| {
+        realized += disposal.gain_usd()
+    });
+
+    CostBasis {
+        realized_pnl_usd: realized,
+        amount_held: lots.iter().map(|l| l.amount).sum(),
+        cost_basis_usd: lots.iter().map(|l| l.amount * l.price_usd).sum(),
+    }
+}
+
+/// Splits a token's sells into disposals, one per purchase lot they are matched with
+///
+/// Trades must be in chronological order, as for [`cost_basis`], whose realized PnL
+/// is the sum of the disposals' gains.
+pub fn disposals(trades: &[Trade], method: CostBasisMethod) -> Vec<Disposal> {
+    let mut disposals = Vec::new();
+    match_lots(trades, method, |_, disposal| disposals.push(disposal));
+    disposals
+}
+
+/// Computes a wallet's net position in each token, valued at current USD prices
+pub async fn wallet_holdings(
+    pool: &PgPool,
+    prices: &dyn PriceSource,
+    wallet_id: Uuid,
+) -> Result<WalletHoldings, AppError> {
+    let positions = holdings::positions(pool, &[wallet_id]).await?;
+
+    let mints: Vec<String> = positions.iter().map(|(mint, _, _)| mint.clone()).collect();
+    let prices = prices.prices_usd(&mints).await?;
+
+    let holdings = value_positions(positions, &prices);
+    let total_value_usd = holdings.iter().filter_map(|h| h.value_usd).sum();
+
+    Ok(WalletHoldings {
+        wallet_id,
+        holdings,
+        total_value_usd,
+    })
+}
+
+/// Merges the positions of all of a user's wallets, valued in USD and SOL
+pub async fn user_portfolio(
+    pool: &PgPool,
+    prices: &dyn PriceSource,
+    user_id: Uuid,
+) -> Result<Portfolio, AppError> {
+    let wallet_ids = sqlx::query_scalar::<_, Uuid>("SELECT id FROM wallets WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?;
+
+    wallets_portfolio(pool, prices, &wallet_ids).await
+}
+
+/// Merges the positions of the given wallets, valued in USD and SOL
+pub async fn wallets_portfolio(
+    pool: &PgPool,
+    prices: &dyn PriceSource,
+    wallet_ids: &[Uuid],
+) -> Result<Portfolio, AppError> {
+    let positions = holdings::positions(pool, wallet_ids).await?;
+
+    let mut mints: Vec<String> = positions.iter().map(|(mint, _, _)| mint.clone()).collect();
+    if !mints.iter().any(|mint| mint == NATIVE_SOL_MINT) {
+        mints.push(NATIVE_SOL_MINT.to_string());
+    }
+    let prices = prices.prices_usd(&mints).await?;
+    let sol_price_usd = prices.get(NATIVE_SOL_MINT).copied().filter(|p| *p > 0.0);
+
+    let holdings = value_positions(positions, &prices);
+    let total_value_usd: Usd = holdings.iter().filter_map(|h| h.value_usd).sum();
+
+    Ok(Portfolio {
+        wallet_count: wallet_ids.len() as i64,
+        holdings,
+        total_value_usd,
+        total_value_sol: sol_price_usd.map(|price| total_value_usd.to_f64() / price),
+        sol_price_usd,
+    })
+}
+
+/// Turns `(mint, symbol, amount)` positions into holdings valued at the given prices
+fn value_positions(
+    positions: Vec<(String, String, TokenAmount)>,
+    prices: &HashMap<String, f64>,
+) -> Vec<Holding> {
+    positions
+        .into_iter()
+        .map(|(token_address, token_symbol, amount)| {
+            let price_usd = prices.get(&token_address).copied();
+            let value_usd = price_usd.map(|price| amount * Usd::from_f64(price));
+            Holding {
+                token_address,
+                token_symbol,
+                token_name: None,
+                logo_uri: None,
+                amount,
+                price_usd,
+                value_usd,
+            }
+        })
+        .collect()
+}
+
+/// Trades of one token for a wallet
+#[derive(Debug, Clone)]
+pub struct TokenTrades {
+    /// Mint address of the token
+    pub token_address: String,
+    /// Token symbol, if known
+    pub token_symbol: String,
+    /// Trades in chronological order
+    pub trades: Vec<Trade>,
+}
+
+/// Loads a wallet's transactions grouped per token, in chronological order
+pub async fn load_token_trades(
+    pool: &PgPool,
+    wallet_id: Uuid,
+) -> Result<Vec<TokenTrades>, sqlx::Error> {
+    load_wallets_token_trades(pool, &[wallet_id]).await
+}
+
+/// Loads the transactions of several wallets grouped per token, interleaved in
+/// chronological order as if they were one wallet
+pub async fn load_wallets_token_trades(
+    pool: &PgPool,
+    wallet_ids: &[Uuid],
+) -> Result<Vec<TokenTrades>, sqlx::Error> {
+    let rows = sqlx::query_as::<
+        _,
+        (
+            String,
+            String,
+            TokenAmount,
+            Usd,
+            DateTime<Utc>,
+            Option<String>,
+        ),
+    >(
+        r#"
+        SELECT token_address, token_symbol, amount, buy_price_usd,
+            COALESCE(block_time, created_at), category
+        FROM transactions
+        WHERE wallet_id = ANY($1)
+        ORDER BY token_address, COALESCE(block_time, created_at), block_number, id
+        "#,
+    )
+    .bind(wallet_ids)
+    .fetch_all(pool)
+    .await?;
+
+    let mut tokens: Vec<TokenTrades> = Vec::new();
+    for (token_address, token_symbol, amount, price_usd, time, category) in rows {
+        let trade = Trade {
+            amount,
+            price_usd,
+            time,
+            category: category.as_deref().and_then(TransactionCategory::from_name),
+        };
+        match tokens.last_mut() {
+            Some(last) if last.token_address == token_address => {
+                if last.token_symbol.is_empty() {
+                    last.token_symbol = token_symbol;
+                }
+                last.trades.push(trade);
+            }
+            _ => tokens.push(TokenTrades {
+                token_address,
+                token_symbol,
+                trades: vec![trade],
+            }),
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Profit and loss of a wallet's position in one token
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenPnl {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// Amount of the token still held
+    #[serde(with = "money::as_number")]
+    #[schema(value_type = f64)]
+    pub amount_held: TokenAmount,
+    /// Total USD cost of the amount still held
+    #[schema(value_type = f64)]
+    pub cost_basis_usd: Usd,
+    /// Profit realized by sells, in USD
+    #[schema(value_type = f64)]
+    pub realized_pnl_usd: Usd,
+    /// Current USD price per token, if available
+    pub price_usd: Option<f64>,
+    /// Unrealized profit of the remaining position, if the price is available
+    #[schema(value_type = Option<f64>)]
+    pub unrealized_pnl_usd: Option<Usd>,
+}
+
+/// Runs the cost-basis engine over each token's trades and values what is still held
+pub fn token_pnl(
+    token_trades: Vec<TokenTrades>,
+    prices: &HashMap<String, f64>,
+    method: CostBasisMethod,
+) -> Vec<TokenPnl> {
+    token_trades
+        .into_iter()
+        .map(|token| {
+            let basis = cost_basis(&token.trades, method);
+            let price_usd = prices.get(&token.token_address).copied();
+            TokenPnl {
+                unrealized_pnl_usd: price_usd
+                    .map(|price| basis.unrealized_pnl_usd(Usd::from_f64(price))),
+                token_address: token.token_address,
+                token_symbol: token.token_symbol,
+                amount_held: basis.amount_held,
+                cost_basis_usd: basis.cost_basis_usd,
+                realized_pnl_usd: basis.realized_pnl_usd,
+                price_usd,
+            }
+        })
+        .collect()
+}
+
+/// Profit and loss of a wallet across all tokens
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletPnl {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Cost-basis method used
+    pub method: CostBasisMethod,
+    /// Per-token breakdown
+    pub tokens: Vec<TokenPnl>,
+    /// Sum of realized PnL across tokens, in USD
+    #[schema(value_type = f64)]
+    pub total_realized_pnl_usd: Usd,
+    /// Sum of unrealized PnL across tokens with a known price, in USD
+    #[schema(value_type = f64)]
+    pub total_unrealized_pnl_usd: Usd,
+}
+
+/// A sell closing all or part of a position, matched against the lots it sold
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ClosedTrade {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// When the tokens were sold
+    pub sold_at: DateTime<Utc>,
+    /// Amount of the token sold
+    #[serde(with = "money::as_number")]
+    #[schema(value_type = f64)]
+    pub amount: TokenAmount,
+    /// Profit realized by the sell, in USD
+    #[schema(value_type = f64)]
+    pub pnl_usd: Usd,
+    /// How long the sold tokens were held on average, weighted by amount, or `None` if
+    /// none of them were bought while tracked
+    pub hold_secs: Option<f64>,
+}
+
+/// Statistics of a wallet's closed trades
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TradeStats {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Cost-basis method used to match sells with purchases
+    pub method: CostBasisMethod,
+    /// Number of closed trades
+    pub trades: usize,
+    /// Number of trades closed at a profit
+    pub wins: usize,
+    /// Number of trades closed at a loss
+    pub losses: usize,
+    /// Share of the trades closed at a profit, in percent, or `None` without trades
+    pub win_rate_percent: Option<f64>,
+    /// Mean of the trades' hold times, in seconds
+    pub average_hold_secs: Option<f64>,
+    /// Median of the trades' hold times, in seconds
+    pub median_hold_secs: Option<f64>,
+    /// Trade with the largest profit, if any was profitable
+    pub largest_win: Option<ClosedTrade>,
+    /// Trade with the largest loss, if any lost money
+    pub largest_loss: Option<ClosedTrade>,
+    /// SOL spent on transactions classified as fees
+    #[serde(with = "money::as_number")]
+    #[schema(value_type = f64)]
+    pub total_fees_sol: TokenAmount,
+    /// USD value of the fees at the time they were paid
+    #[schema(value_type = f64)]
+    pub total_fees_usd: Usd,
+}
+
+/// Disposals of one sell, added up
+#[derive(Debug, Default)]
+struct SellTotals {
+    /// Index of the sell in the token's trades
+    index: usize,
+    gain_usd: Usd,
+    amount: TokenAmount,
+    /// Hold time in seconds times amount, over the tokens bought while tracked
+    held_secs_amount: f64,
+    /// Amount of the tokens bought while tracked
+    tracked_amount: f64,
+}
+
+/// Splits a token's sells into closed trades
+///
+/// Every sell is matched against the open lots like in [`cost_basis`], but only swap
+/// sells and unclassified sells count as trades; transfers out and fees use up lots
+/// without closing a trade.
+pub fn closed_trades(token: &TokenTrades, method: CostBasisMethod) -> Vec<ClosedTrade> {
+    let mut sells: Vec<SellTotals> = Vec::new();
+    match_lots(&token.trades, method, |index, disposal| {
+        if !matches!(
+            token.trades[index].category,
+            None | Some(TransactionCategory::SwapSell)
+        ) {
+            return;
+        }
+        if sells.last().map(|sell| sell.index) != Some(index) {
+            sells.push(SellTotals {
+                index,
+                ..SellTotals::default()
+            });
+        }
+        let sell = sells.last_mut().expect("sell was just pushed");
+        sell.gain_usd += disposal.gain_usd();
+        sell.amount += disposal.amount;
+        if let Some(acquired_at) = disposal.acquired_at {
+            let held = (disposal.sold_at - acquired_at).num_milliseconds() as f64 / 1000.0;
+            sell.held_secs_amount += held * disposal.amount.to_f64();
+            sell.tracked_amount += disposal.amount.to_f64();
+        }
+    });
+
+    sells
+        .into_iter()
+        .map(|sell| ClosedTrade {
+            token_address: token.token_address.clone(),
+            token_symbol: token.token_symbol.clone(),
+            sold_at: token.trades[sell.index].time,
+            amount: sell.amount,
+            pnl_usd: sell.gain_usd,
+            hold_secs: (sell.tracked_amount > 0.0)
+                .then(|| sell.held_secs_amount / sell.tracked_amount),
+        })
+        .collect()
+}
+
+/// Computes win rate, hold times and the best and worst trade of a wallet's trades
+pub fn trade_stats(
+    wallet_id: Uuid,
+    token_trades: &[TokenTrades],
+    method: CostBasisMethod,
+) -> TradeStats {
+    let trades: Vec<ClosedTrade> = token_trades
+        .iter()
+        .flat_map(|token| closed_trades(token, method))
+        .collect();
+    let wins = trades
+        .iter()
+        .filter(|trade| trade.pnl_usd.is_positive())
+        .count();
+    let losses = trades
+        .iter()
+        .filter(|trade| trade.pnl_usd.is_negative())
+        .count();
+
+    let mut holds: Vec<f64> = trades.iter().filter_map(|trade| trade.hold_secs).collect();
+    holds.sort_by(f64::total_cmp);
+    let average_hold_secs =
+        (!holds.is_empty()).then(|| holds.iter().sum::<f64>() / holds.len() as f64);
+    let median_hold_secs = match holds.len() {
+        0 => None,
+        len if len % 2 == 0 => Some((holds[len / 2 - 1] + holds[len / 2]) / 2.0),
+        len => Some(holds[len / 2]),
+    };
+
+    let largest_win = trades
+        .iter()
+        .filter(|trade| trade.pnl_usd.is_positive())
+        .max_by_key(|trade| trade.pnl_usd)
+        .cloned();
+    let largest_loss = trades
+        .iter()
+        .filter(|trade| trade.pnl_usd.is_negative())
+        .min_by_key(|trade| trade.pnl_usd)
+        .cloned();
+
+    let fees = token_trades
+        .iter()
+        .filter(|token| token.token_address == NATIVE_SOL_MINT)
+        .flat_map(|token| &token.trades)
+        .filter(|trade| trade.category == Some(TransactionCategory::Fee));
+    let (total_fees_sol, total_fees_usd) = fees
+        .fold((TokenAmount::ZERO, Usd::ZERO), |(sol, usd), trade| {
+            (sol - trade.amount, usd - trade.amount * trade.price_usd)
+        });
+
+    TradeStats {
+        wallet_id,
+        method,
+        trades: trades.len(),
+        wins,
+        losses,
+        win_rate_percent: (!trades.is_empty()).then(|| wins as f64 / trades.len() as f64 * 100.0),
+        average_hold_secs,
+        median_hold_secs,
+        largest_win,
+        largest_loss,
+        total_fees_sol,
+        total_fees_usd,
+    }
+}
@@ -0,0 +1,81 @@
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::roles::Role;
+use crate::AppError;
+
+/// Claims carried by access tokens issued by this server
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Claims {
+    /// ID of the authenticated user
+    pub sub: Uuid,
+    /// Issued-at time as a Unix timestamp
+    pub iat: i64,
+    /// Expiry time as a Unix timestamp
+    pub exp: i64,
+    /// ID of the [session](super::sessions) the token was issued to; tokens issued
+    /// before sessions existed have none and cannot be revoked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sid: Option<Uuid>,
+    /// [Role](super::roles) of the user when the token was issued; tokens issued before
+    /// roles existed have none and act with the user's current role
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub role: Option<Role>,
+}
+
+/// Issues and verifies HS256-signed JWT access tokens
+#[derive(Clone)]
+pub struct JwtKeys {
+    encoding: EncodingKey,
+    decoding: DecodingKey,
+    ttl: Duration,
+}
+
+impl JwtKeys {
+    /// Creates signing keys from a shared secret; tokens are valid for `ttl_secs`
+    pub fn new(secret: &[u8], ttl_secs: u64) -> Self {
+        Self {
+            encoding: EncodingKey::from_secret(secret),
+            decoding: DecodingKey::from_secret(secret),
+            ttl: Duration::seconds(ttl_secs as i64),
+        }
+    }
+
+    /// Issues a token for `user_id` with `role` in a session, returning it with its
+    /// expiry time
+    pub fn issue(
+        &self,
+        user_id: Uuid,
+        session_id: Uuid,
+        role: Role,
+    ) -> Result<(String, DateTime<Utc>), AppError> {
+        let now = Utc::now();
+        let expires_at = now + self.ttl;
+        let claims = Claims {
+            sub: user_id,
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+            sid: Some(session_id),
+            role: Some(role),
+        };
+
+        let token = encode(&Header::default(), &claims, &self.encoding)
+            .map_err(|e| AppError::InternalServerError(format!("Failed to issue token: {e}")))?;
+
+        Ok((token, expires_at))
+    }
+
+    /// Verifies a token's signature and expiry and returns its claims
+    pub fn verify(&self, token: &str) -> Result<Claims, AppError> {
+        decode::<Claims>(token, &self.decoding, &Validation::default())
+            .map(|data| data.claims)
+            .map_err(|e| AppError::Unauthorized(format!("Invalid token: {e}")))
+    }
+}
+
+/// Whether a bearer credential is shaped like a JWT rather than an API key
+pub fn looks_like_jwt(credential: &str) -> bool {
+    credential.split('.').count() == 3
+}
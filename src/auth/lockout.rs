@@ -0,0 +1,272 @@
+//! Brute-force protection of sign-ins.
+//!
+//! Failed sign-ins are counted per client address and, for Sign-In-With-Solana, per
+//! signing address from each client and per signing address overall, in the
+//! `login_attempts` table, so every replica sees the same counts. Nonces are handed to
+//! anyone, so a signing address is locked out from one client after [`MAX_FAILURES`]
+//! failures there, but from every client only after [`MAX_ACCOUNT_FAILURES`] across
+//! them: others cannot cheaply lock its user out, and guessing from many clients is
+//! still stopped. A locked subject stays locked first for [`BASE_LOCKOUT`] and twice
+//! as long with every lockout after, up to [`MAX_LOCKOUT`]. Failures are forgotten
+//! after [`FAILURE_WINDOW`] without one, and the doubling after [`LOCKOUT_MEMORY`]. A
+//! successful sign-in clears the counts of the signing address but not of the client
+//! address, so that guessing with one good key in hand does not reset the limit.
+//!
+//! The user of a locked signing address is told on all their notification channels.
+
+use std::net::IpAddr;
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use tracing::warn;
+use uuid::Uuid;
+
+use crate::notifications::{self, templates};
+use crate::AppError;
+
+/// Failures in a row locking a client, or a signing address from one client, out
+pub const MAX_FAILURES: i32 = 5;
+
+/// Failures in a row from any clients locking a signing address out everywhere
+pub const MAX_ACCOUNT_FAILURES: i32 = 20;
+
+/// Length of the first lockout
+pub const BASE_LOCKOUT: Duration = Duration::minutes(1);
+
+/// Longest lockout, however many came before
+pub const MAX_LOCKOUT: Duration = Duration::days(1);
+
+/// Quiet period after which failures are forgotten
+pub const FAILURE_WINDOW: Duration = Duration::hours(1);
+
+/// Quiet period after which earlier lockouts no longer lengthen the next one
+pub const LOCKOUT_MEMORY: Duration = Duration::days(1);
+
+/// Who a sign-in attempt came from and which account it was for
+#[derive(Debug, Clone, Default)]
+pub struct SignInAttempt {
+    /// Address of the client, if known
+    pub client_ip: Option<IpAddr>,
+    /// Solana address signing in, for Sign-In-With-Solana
+    pub address: Option<String>,
+}
+
+impl SignInAttempt {
+    fn ip_subject(&self) -> Option<String> {
+        self.client_ip.map(|ip| format!("ip:{ip}"))
+    }
+
+    /// The client address, or `unknown` for requests without one
+    fn client(&self) -> String {
+        self.client_ip
+            .map_or_else(|| "unknown".to_string(), |ip| ip.to_string())
+    }
+
+    fn address_subject(&self) -> Option<String> {
+        self.address
+            .as_ref()
+            .map(|address| format!("address:{address}@{}", self.client()))
+    }
+
+    fn account_subject(&self) -> Option<String> {
+        self.address
+            .as_ref()
+            .map(|address| format!("address:{address}"))
+    }
+
+    fn subjects(&self) -> impl Iterator<Item = String> {
+        self.ip_subject()
+            .into_iter()
+            .chain(self.address_subject())
+            .chain(self.account_subject())
+    }
+
+    /// Fails with [`AppError::TooManyRequests`] while the client, the account from
+    /// this client or the account everywhere is locked out
+    pub async fn ensure_allowed(&self, pool: &PgPool) -> Result<(), AppError> {
+        let subjects: Vec<String> = self.subjects().collect();
+        if subjects.is_empty() {
+            return Ok(());
+        }
+
+        let locked_until = sqlx::query_scalar::<_, Option<DateTime<Utc>>>(
+            "SELECT MAX(locked_until) FROM login_attempts WHERE subject = ANY($1) AND locked_until > NOW()",
+        )
+        .bind(&subjects)
+        .fetch_one(pool)
+        .await?;
+
+        match locked_until {
+            Some(until) => Err(AppError::TooManyRequests(format!(
+                "Too many failed sign-ins, try again in {} seconds",
+                (until - Utc::now()).num_seconds().max(1)
+            ))),
+            None => Ok(()),
+        }
+    }
+
+    /// Counts a failed sign-in, locking out every subject reaching its limit
+    pub async fn failed(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        if let Some(subject) = self.ip_subject() {
+            if let Some(until) = record_failure(pool, &subject, MAX_FAILURES).await? {
+                warn!(
+                    "Locked out sign-ins from {} until {}",
+                    subject.trim_start_matches("ip:"),
+                    until
+                );
+            }
+        }
+
+        if let (Some(subject), Some(address)) = (self.address_subject(), &self.address) {
+            if let Some(until) = record_failure(pool, &subject, MAX_FAILURES).await? {
+                let client = self.client();
+                warn!(
+                    "Locked out sign-ins with {} from {} until {}",
+                    address, client, until
+                );
+                let message = templates::ACCOUNT_LOCKED.render(&[
+                    ("failures", &MAX_FAILURES.to_string()),
+                    ("address", address),
+                    ("client", &client),
+                    ("until", &format_until(until)),
+                ]);
+                notify_locked(pool, address, &message).await?;
+            }
+        }
+
+        if let (Some(subject), Some(address)) = (self.account_subject(), &self.address) {
+            if let Some(until) = record_failure(pool, &subject, MAX_ACCOUNT_FAILURES).await? {
+                warn!(
+                    "Locked out sign-ins with {} from every client until {}",
+                    address, until
+                );
+                let message = templates::ACCOUNT_LOCKED_EVERYWHERE.render(&[
+                    ("failures", &MAX_ACCOUNT_FAILURES.to_string()),
+                    ("address", address),
+                    ("until", &format_until(until)),
+                ]);
+                notify_locked(pool, address, &message).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Clears the failures of the account after a successful sign-in
+    pub async fn succeeded(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let subjects: Vec<String> = self
+            .address_subject()
+            .into_iter()
+            .chain(self.account_subject())
+            .collect();
+        if !subjects.is_empty() {
+            sqlx::query("DELETE FROM login_attempts WHERE subject = ANY($1)")
+                .bind(subjects)
+                .execute(pool)
+                .await?;
+        }
+        Ok(())
+    }
+}
+
+/// Length of a lockout after `lockouts` earlier ones
+pub fn lockout_duration(lockouts: i32) -> Duration {
+    let doublings = lockouts.clamp(0, 20) as u32;
+    (BASE_LOCKOUT * 2i32.pow(doublings)).min(MAX_LOCKOUT)
+}
+
+/// Counts a failure of `subject`, returning until when it is locked out if this
+/// failure was its `max_failures`th in a row
+async fn record_failure(
+    pool: &PgPool,
+    subject: &str,
+    max_failures: i32,
+) -> Result<Option<DateTime<Utc>>, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let (failures, lockouts) = sqlx::query_as::<_, (i32, i32)>(
+        r#"
+        INSERT INTO login_attempts (subject, failures, last_failure_at)
+        VALUES ($1, 1, NOW())
+        ON CONFLICT (subject) DO UPDATE SET
+            failures = CASE
+                WHEN login_attempts.last_failure_at < NOW() - make_interval(secs => $2) THEN 1
+                ELSE login_attempts.failures + 1
+            END,
+            lockouts = CASE
+                WHEN login_attempts.last_failure_at < NOW() - make_interval(secs => $3) THEN 0
+                ELSE login_attempts.lockouts
+            END,
+            last_failure_at = NOW()
+        RETURNING failures, lockouts
+        "#,
+    )
+    .bind(subject)
+    .bind(FAILURE_WINDOW.num_seconds() as f64)
+    .bind(LOCKOUT_MEMORY.num_seconds() as f64)
+    .fetch_one(&mut *tx)
+    .await?;
+
+    if failures < max_failures {
+        tx.commit().await?;
+        return Ok(None);
+    }
+
+    let until = sqlx::query_scalar::<_, DateTime<Utc>>(
+        r#"
+        UPDATE login_attempts
+        SET failures = 0,
+            lockouts = lockouts + 1,
+            locked_until = NOW() + make_interval(secs => $2)
+        WHERE subject = $1
+        RETURNING locked_until
+        "#,
+    )
+    .bind(subject)
+    .bind(lockout_duration(lockouts).num_seconds() as f64)
+    .fetch_one(&mut *tx)
+    .await?;
+    tx.commit().await?;
+
+    Ok(Some(until))
+}
+
+/// When a lockout ends, as written in notifications
+fn format_until(until: DateTime<Utc>) -> String {
+    until.format("%Y-%m-%d %H:%M UTC").to_string()
+}
+
+/// Sends `message` about a lockout to the user signing in with `address`, if there is
+/// one
+async fn notify_locked(
+    pool: &PgPool,
+    address: &str,
+    message: &templates::Rendered,
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let user_id = sqlx::query_scalar::<_, Uuid>("SELECT id FROM users WHERE siws_address = $1")
+        .bind(address)
+        .fetch_optional(&mut *tx)
+        .await?;
+    if let Some(user_id) = user_id {
+        notifications::notify_security(&mut tx, user_id, message).await?;
+    }
+    tx.commit().await?;
+    Ok(())
+}
+
+/// Deletes the records of subjects neither failing nor locked out for a while, and
+/// returns how many
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM login_attempts
+        WHERE last_failure_at < NOW() - make_interval(secs => $1)
+          AND (locked_until IS NULL OR locked_until < NOW())
+        "#,
+    )
+    .bind(LOCKOUT_MEMORY.num_seconds() as f64)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,280 @@
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{header, request::Parts, HeaderMap},
+};
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+use self::roles::{Permission, Role};
+use crate::helius::secret_matches;
+use crate::metering::{self, MeteredCaller};
+use crate::models::User;
+use crate::{AppError, AppState};
+
+/// JWT access token issuance and verification
+pub mod jwt;
+
+/// Sign-In-With-Solana nonce challenges and signature verification
+pub mod siws;
+
+/// Sessions with rotating refresh tokens and revocation
+pub mod sessions;
+
+/// Roles of users and the permissions they grant
+pub mod roles;
+
+/// Lockouts of clients and accounts after repeated failed sign-ins
+pub mod lockout;
+
+/// Header carrying the API key as an alternative to `Authorization: Bearer`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// ID every request acts as in demo mode
+pub const DEMO_USER_ID: Uuid = Uuid::nil();
+
+/// Prefix of generated API keys, so they are recognizable in logs and config files
+const API_KEY_PREFIX: &str = "dgn_";
+
+/// How stale a user's `last_seen_at` may get before a request refreshes it, so that
+/// authenticating does not write to the database on every request
+const LAST_SEEN_RESOLUTION: Duration = Duration::hours(1);
+
+/// The user making the request, authenticated by API key or JWT access token
+///
+/// The credential is read from `Authorization: Bearer <credential>` or the
+/// `X-API-Key` header. Credentials shaped like a JWT are verified as access tokens of
+/// a [session](sessions) that was not revoked, acting with the role they carry;
+/// anything else is looked up as an API key. Handlers taking this extractor reject
+/// unauthenticated requests with `401`, and requests the user's [role](roles) does not
+/// permit, such as changes by read-only users, with `403`. Their requests are counted
+/// by the [usage meter](crate::metering).
+///
+/// In demo mode no credential is needed: every request acts as [`DEMO_USER_ID`] with
+/// the `user` role.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuthUser {
+    /// ID of the authenticated user
+    pub id: Uuid,
+    /// Role the user acts with
+    pub role: Role,
+}
+
+#[async_trait]
+impl FromRequestParts<AppState> for AuthUser {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, AppError> {
+        let user = authenticate(parts, state).await?;
+        roles::ensure(user.role, Permission::for_method(&parts.method))?;
+        Ok(user)
+    }
+}
+
+/// Identifies the caller without checking what their role permits
+async fn authenticate(parts: &Parts, state: &AppState) -> Result<AuthUser, AppError> {
+    if state.config.is_demo() {
+        return Ok(AuthUser {
+            id: DEMO_USER_ID,
+            role: Role::User,
+        });
+    }
+
+    let api_key = api_key_from_parts(parts)
+        .ok_or_else(|| AppError::Unauthorized("Missing API key".to_string()))?;
+
+    if jwt::looks_like_jwt(api_key) {
+        let claims = state.jwt.verify(api_key)?;
+        let (user_id, last_seen_at, role, session_active) =
+            sqlx::query_as::<_, (Uuid, Option<DateTime<Utc>>, String, bool)>(
+                r#"
+                SELECT u.id, u.last_seen_at, u.role, s.id IS NOT NULL AND s.revoked_at IS NULL
+                FROM users u
+                LEFT JOIN auth_sessions s ON s.id = $2 AND s.user_id = u.id
+                WHERE u.id = $1
+                "#,
+            )
+            .bind(claims.sub)
+            .bind(claims.sid)
+            .fetch_optional(&state.db_pool)
+            .await?
+            .ok_or_else(|| AppError::Unauthorized("Unknown user".to_string()))?;
+        if claims.sid.is_some() && !session_active {
+            return Err(AppError::Unauthorized(
+                "Session has been revoked".to_string(),
+            ));
+        }
+        touch_last_seen(&state.db_pool, user_id, last_seen_at).await?;
+        record_caller(parts, user_id, metering::JWT_KEY_ID.to_string());
+        let role = match claims.role {
+            Some(role) => role,
+            None => role.parse().map_err(AppError::InternalServerError)?,
+        };
+        return Ok(AuthUser { id: user_id, role });
+    }
+
+    let api_key_hash = hash_api_key(api_key);
+    let (user_id, last_seen_at, role) = sqlx::query_as::<_, (Uuid, Option<DateTime<Utc>>, String)>(
+        "SELECT id, last_seen_at, role FROM users WHERE api_key_hash = $1",
+    )
+    .bind(&api_key_hash)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?;
+    touch_last_seen(&state.db_pool, user_id, last_seen_at).await?;
+    record_caller(parts, user_id, metering::key_id(&api_key_hash));
+
+    Ok(AuthUser {
+        id: user_id,
+        role: role.parse().map_err(AppError::InternalServerError)?,
+    })
+}
+
+/// Whether `credential` is the admin key, a user's API key or a valid access token,
+/// without authenticating a request
+///
+/// Used by the [rate limiter](crate::rate_limit), so revoked sessions still count as
+/// valid here. Always `false` in demo mode, where credentials are not checked.
+pub(crate) async fn is_valid_credential(state: &AppState, credential: &str) -> bool {
+    if state.config.is_demo() {
+        return false;
+    }
+    if let Some(expected) = state.config.admin_api_key.as_deref() {
+        if secret_matches(credential, expected) {
+            return true;
+        }
+    }
+    if jwt::looks_like_jwt(credential) {
+        return state.jwt.verify(credential).is_ok();
+    }
+
+    sqlx::query_scalar::<_, bool>("SELECT EXISTS (SELECT 1 FROM users WHERE api_key_hash = $1)")
+        .bind(hash_api_key(credential))
+        .fetch_one(&state.db_pool)
+        .await
+        .unwrap_or(false)
+}
+
+/// Tells the [usage meter](crate::metering) who made the request
+fn record_caller(parts: &Parts, user_id: Uuid, key_id: String) {
+    if let Some(caller) = parts.extensions.get::<MeteredCaller>() {
+        caller.record(user_id, key_id);
+    }
+}
+
+/// Records that the user was just seen, unless they were seen recently
+async fn touch_last_seen(
+    pool: &PgPool,
+    user_id: Uuid,
+    last_seen_at: Option<DateTime<Utc>>,
+) -> Result<(), AppError> {
+    if last_seen_at.is_some_and(|seen| Utc::now() - seen < LAST_SEEN_RESOLUTION) {
+        return Ok(());
+    }
+
+    sqlx::query("UPDATE users SET last_seen_at = NOW() WHERE id = $1")
+        .bind(user_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// An operator of the server, authenticated by the configured admin API key or as a
+/// user with the `admin` [role](roles)
+///
+/// The key is read like a user's API key, from `Authorization: Bearer` or
+/// `X-API-Key`; other users are refused with `401`. Admin endpoints answer `503` to
+/// anyone but admins while no admin key is configured. In demo mode only the admin key
+/// is accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AdminAuth;
+
+#[async_trait]
+impl FromRequestParts<AppState> for AdminAuth {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &AppState) -> Result<Self, AppError> {
+        let expected = state.config.admin_api_key.as_deref();
+        let api_key = api_key_from_parts(parts);
+        if let (Some(expected), Some(api_key)) = (expected, api_key) {
+            if secret_matches(api_key, expected) {
+                return Ok(Self);
+            }
+        }
+
+        let rejected = || match (expected, api_key) {
+            (None, _) => {
+                AppError::ServiceUnavailable("Admin endpoints are not configured".to_string())
+            }
+            (Some(_), None) => AppError::Unauthorized("Missing admin API key".to_string()),
+            (Some(_), Some(_)) => AppError::Unauthorized("Invalid admin API key".to_string()),
+        };
+        if state.config.is_demo() {
+            return Err(rejected());
+        }
+
+        // Users without the role are refused like any other credential
+        match authenticate(parts, state).await {
+            Ok(user) if user.role.permits(Permission::Admin) => Ok(Self),
+            Ok(_) | Err(AppError::Unauthorized(_)) => Err(rejected()),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+/// Extracts the raw API key from the request headers
+fn api_key_from_parts(parts: &Parts) -> Option<&str> {
+    api_key_from_headers(&parts.headers)
+}
+
+/// Reads the credential from `Authorization: Bearer` or `X-API-Key`, if present
+pub(crate) fn api_key_from_headers(headers: &HeaderMap) -> Option<&str> {
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    bearer
+        .or_else(|| {
+            headers
+                .get(API_KEY_HEADER)
+                .and_then(|value| value.to_str().ok())
+        })
+        .map(str::trim)
+        .filter(|key| !key.is_empty())
+}
+
+/// Generates a new random API key
+pub fn generate_api_key() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{API_KEY_PREFIX}{}", bs58::encode(bytes).into_string())
+}
+
+/// Hashes an API key for storage; only the hash is ever persisted
+pub fn hash_api_key(api_key: &str) -> String {
+    hex::encode(Sha256::digest(api_key.as_bytes()))
+}
+
+/// Creates a user and returns it together with its newly generated API key
+pub async fn create_user(pool: &PgPool, name: Option<&str>) -> Result<(User, String), AppError> {
+    let api_key = generate_api_key();
+
+    let user = sqlx::query_as::<_, User>(
+        r#"
+        INSERT INTO users (id, name, api_key_hash)
+        VALUES ($1, $2, $3)
+        RETURNING id, name, created_at, updated_at
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(name)
+    .bind(hash_api_key(&api_key))
+    .fetch_one(pool)
+    .await?;
+
+    Ok((user, api_key))
+}
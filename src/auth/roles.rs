@@ -0,0 +1,174 @@
+//! Roles of users and the permissions they grant.
+//!
+//! Every user has a [`Role`]: `user` by default, `readonly` for users who may only
+//! read their data, or `admin` for users who may also call the `/admin` endpoints,
+//! which manage address labels, quotas, feature flags and other operator settings.
+//! [`AuthUser`](super::AuthUser) checks the permission each request needs from its
+//! method, so read-only users are refused any change with `403` and the code
+//! `forbidden`, and [`AdminAuth`](super::AdminAuth) admits admins alongside the
+//! configured admin API key.
+//!
+//! Access tokens carry the role they were issued with. Changing a user's role through
+//! `PUT /admin/users/{id}/role` revokes their [sessions](super::sessions), so no token
+//! keeps a role the user lost.
+
+use std::fmt;
+use std::str::FromStr;
+
+use axum::http::Method;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgExecutor, PgPool};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::AppError;
+
+/// What a user may do
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Everything users may do, plus the `/admin` endpoints
+    Admin,
+    /// Read and change their own data; the default
+    #[default]
+    User,
+    /// Only read their own data
+    Readonly,
+}
+
+/// What a request needs its caller's role to permit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Permission {
+    /// Read the caller's own data
+    Read,
+    /// Create, change or delete the caller's own data
+    Write,
+    /// Call the `/admin` endpoints
+    Admin,
+}
+
+impl Role {
+    /// Every role, from the most to the least privileged
+    pub const ALL: [Role; 3] = [Self::Admin, Self::User, Self::Readonly];
+
+    /// Name of the role as stored in `users.role`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Admin => "admin",
+            Self::User => "user",
+            Self::Readonly => "readonly",
+        }
+    }
+
+    /// Whether the role grants `permission`
+    pub fn permits(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::Read => true,
+            Permission::Write => *self != Self::Readonly,
+            Permission::Admin => *self == Self::Admin,
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|role| role.as_str() == s)
+            .ok_or_else(|| format!("Unknown role {s:?}"))
+    }
+}
+
+impl Permission {
+    /// Permission a request with `method` needs: writing for anything that is not a
+    /// plain read
+    pub fn for_method(method: &Method) -> Self {
+        match *method {
+            Method::GET | Method::HEAD | Method::OPTIONS => Self::Read,
+            _ => Self::Write,
+        }
+    }
+}
+
+/// Fails with `forbidden` unless `role` grants `permission`
+pub fn ensure(role: Role, permission: Permission) -> Result<(), AppError> {
+    if role.permits(permission) {
+        return Ok(());
+    }
+    Err(AppError::Forbidden(match permission {
+        Permission::Read => "Reading is not permitted".to_string(),
+        Permission::Write => format!("The {role} role may not make changes"),
+        Permission::Admin => "The admin role is required".to_string(),
+    }))
+}
+
+/// Request body changing a user's role
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SetUserRole {
+    /// Role to give the user
+    pub role: Role,
+}
+
+/// A user's role
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct UserRole {
+    /// ID of the user
+    pub user_id: Uuid,
+    /// Role of the user
+    pub role: Role,
+}
+
+/// Role of a user, or `None` if there is no such user
+pub async fn get<'e>(
+    executor: impl PgExecutor<'e>,
+    user_id: Uuid,
+) -> Result<Option<Role>, AppError> {
+    let role = sqlx::query_scalar::<_, String>("SELECT role FROM users WHERE id = $1")
+        .bind(user_id)
+        .fetch_optional(executor)
+        .await?;
+
+    role.map(|role| role.parse().map_err(AppError::InternalServerError))
+        .transpose()
+}
+
+/// Gives a user a role and revokes their sessions if it changed
+///
+/// Returns `None` if there is no such user.
+pub async fn set(pool: &PgPool, user_id: Uuid, role: Role) -> Result<Option<UserRole>, AppError> {
+    let mut tx = pool.begin().await?;
+    let Some(previous) =
+        sqlx::query_scalar::<_, String>("SELECT role FROM users WHERE id = $1 FOR UPDATE")
+            .bind(user_id)
+            .fetch_optional(&mut *tx)
+            .await?
+    else {
+        return Ok(None);
+    };
+
+    if previous != role.as_str() {
+        sqlx::query("UPDATE users SET role = $2 WHERE id = $1")
+            .bind(user_id)
+            .bind(role.as_str())
+            .execute(&mut *tx)
+            .await?;
+        sqlx::query(
+            "UPDATE auth_sessions SET revoked_at = NOW() WHERE user_id = $1 AND revoked_at IS NULL",
+        )
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(Some(UserRole { user_id, role }))
+}
@@ -0,0 +1,261 @@
+//! Sessions with short-lived access tokens and rotating refresh tokens.
+//!
+//! Signing in, with an API key through `/auth/login` or with Sign-In-With-Solana,
+//! starts a session and returns a JWT access token naming it together with a refresh
+//! token. Once the access token expires, `/auth/refresh` trades the refresh token for a
+//! new pair; every refresh token works once, and presenting a used one again revokes
+//! the whole session, as only a stolen copy would be replayed. `/auth/logout` revokes
+//! the session, and [`AuthUser`](super::AuthUser) rejects the access tokens of revoked
+//! sessions before they expire.
+//!
+//! Only hashes of refresh tokens are stored.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use tracing::warn;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::{hash_api_key, roles};
+use crate::{AppError, AppState};
+
+/// Prefix of refresh tokens, so they are not mistaken for API keys
+const REFRESH_TOKEN_PREFIX: &str = "dgr_";
+
+/// How long ended sessions are kept before being pruned
+const ENDED_RETENTION: Duration = Duration::days(1);
+
+/// Request payload exchanging an API key for a session
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LoginRequest {
+    /// API key of the user signing in
+    pub api_key: String,
+}
+
+/// Request payload trading a refresh token for new tokens
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct RefreshRequest {
+    /// Refresh token returned by the last sign-in or refresh
+    pub refresh_token: String,
+}
+
+/// Request payload ending a session
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct LogoutRequest {
+    /// Refresh token of the session to end
+    pub refresh_token: String,
+    /// Whether to end all the user's sessions instead of only this one
+    #[serde(default)]
+    pub all: bool,
+}
+
+/// Tokens issued after a successful sign-in or refresh
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TokenResponse {
+    /// JWT to send as `Authorization: Bearer <token>`
+    pub access_token: String,
+    /// Always `Bearer`
+    pub token_type: String,
+    /// When the access token expires
+    pub expires_at: DateTime<Utc>,
+    /// One-time token to trade for new tokens at `/auth/refresh`
+    pub refresh_token: String,
+    /// When the refresh token expires
+    pub refresh_expires_at: DateTime<Utc>,
+    /// ID of the signed-in user
+    pub user_id: Uuid,
+}
+
+fn generate_refresh_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!(
+        "{REFRESH_TOKEN_PREFIX}{}",
+        bs58::encode(bytes).into_string()
+    )
+}
+
+/// Issues an access token of the session and a new refresh token, valid from now
+async fn issue_tokens(
+    conn: &mut PgConnection,
+    state: &AppState,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<TokenResponse, AppError> {
+    let refresh_token = generate_refresh_token();
+    let refresh_expires_at =
+        Utc::now() + Duration::seconds(state.config.refresh_token_ttl_secs as i64);
+
+    sqlx::query(
+        "INSERT INTO refresh_tokens (token_hash, session_id, expires_at) VALUES ($1, $2, $3)",
+    )
+    .bind(hash_api_key(&refresh_token))
+    .bind(session_id)
+    .bind(refresh_expires_at)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query("UPDATE auth_sessions SET expires_at = $2 WHERE id = $1")
+        .bind(session_id)
+        .bind(refresh_expires_at)
+        .execute(&mut *conn)
+        .await?;
+
+    let role = roles::get(&mut *conn, user_id)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Unknown user".to_string()))?;
+    let (access_token, expires_at) = state.jwt.issue(user_id, session_id, role)?;
+    Ok(TokenResponse {
+        access_token,
+        token_type: "Bearer".to_string(),
+        expires_at,
+        refresh_token,
+        refresh_expires_at,
+        user_id,
+    })
+}
+
+/// Starts a session of the user and returns its first tokens
+pub async fn start(state: &AppState, user_id: Uuid) -> Result<TokenResponse, AppError> {
+    let session_id = Uuid::now_v7();
+    let mut tx = state.db_pool.begin().await?;
+    sqlx::query("INSERT INTO auth_sessions (id, user_id, expires_at) VALUES ($1, $2, NOW())")
+        .bind(session_id)
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    let tokens = issue_tokens(&mut tx, state, user_id, session_id).await?;
+    tx.commit().await?;
+
+    Ok(tokens)
+}
+
+/// Starts a session of the user owning an API key
+pub async fn login(state: &AppState, api_key: &str) -> Result<TokenResponse, AppError> {
+    let user_id = sqlx::query_scalar::<_, Uuid>("SELECT id FROM users WHERE api_key_hash = $1")
+        .bind(hash_api_key(api_key.trim()))
+        .fetch_optional(&state.db_pool)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid API key".to_string()))?;
+
+    start(state, user_id).await
+}
+
+/// Refresh token claimed for rotation, with its session
+#[derive(sqlx::FromRow)]
+struct ClaimedToken {
+    session_id: Uuid,
+    user_id: Uuid,
+    expires_at: DateTime<Utc>,
+    used_at: Option<DateTime<Utc>>,
+    revoked_at: Option<DateTime<Utc>>,
+}
+
+/// Trades a refresh token for new tokens of its session
+///
+/// Presenting a refresh token that was already traded revokes the session.
+pub async fn refresh(state: &AppState, refresh_token: &str) -> Result<TokenResponse, AppError> {
+    let invalid = || AppError::Unauthorized("Invalid or expired refresh token".to_string());
+
+    let mut tx = state.db_pool.begin().await?;
+    let token = sqlx::query_as::<_, ClaimedToken>(
+        r#"
+        SELECT t.session_id, s.user_id, t.expires_at, t.used_at, s.revoked_at
+        FROM refresh_tokens t
+        JOIN auth_sessions s ON s.id = t.session_id
+        WHERE t.token_hash = $1
+        FOR UPDATE
+        "#,
+    )
+    .bind(hash_api_key(refresh_token.trim()))
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(invalid)?;
+
+    if token.revoked_at.is_some() || token.expires_at < Utc::now() {
+        return Err(invalid());
+    }
+    if token.used_at.is_some() {
+        sqlx::query("UPDATE auth_sessions SET revoked_at = NOW() WHERE id = $1")
+            .bind(token.session_id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+        warn!(
+            "Revoked session {} of user {} after its used refresh token was presented again",
+            token.session_id, token.user_id
+        );
+        return Err(AppError::Unauthorized(
+            "Refresh token was already used; the session has been revoked".to_string(),
+        ));
+    }
+
+    sqlx::query("UPDATE refresh_tokens SET used_at = NOW() WHERE token_hash = $1")
+        .bind(hash_api_key(refresh_token.trim()))
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("UPDATE auth_sessions SET last_refreshed_at = NOW() WHERE id = $1")
+        .bind(token.session_id)
+        .execute(&mut *tx)
+        .await?;
+    let tokens = issue_tokens(&mut tx, state, token.user_id, token.session_id).await?;
+    tx.commit().await?;
+
+    Ok(tokens)
+}
+
+/// Revokes the session of a refresh token, or all sessions of its user if `all` is set
+///
+/// Returns the number of sessions revoked, or `None` if the token is unknown.
+pub async fn logout(
+    pool: &PgPool,
+    refresh_token: &str,
+    all: bool,
+) -> Result<Option<u64>, AppError> {
+    let Some((session_id, user_id)) = sqlx::query_as::<_, (Uuid, Uuid)>(
+        r#"
+        SELECT s.id, s.user_id
+        FROM refresh_tokens t
+        JOIN auth_sessions s ON s.id = t.session_id
+        WHERE t.token_hash = $1
+        "#,
+    )
+    .bind(hash_api_key(refresh_token.trim()))
+    .fetch_optional(pool)
+    .await?
+    else {
+        return Ok(None);
+    };
+
+    let revoked = sqlx::query(
+        r#"
+        UPDATE auth_sessions
+        SET revoked_at = NOW()
+        WHERE revoked_at IS NULL AND (id = $1 OR ($3 AND user_id = $2))
+        "#,
+    )
+    .bind(session_id)
+    .bind(user_id)
+    .bind(all)
+    .execute(pool)
+    .await?;
+
+    Ok(Some(revoked.rows_affected()))
+}
+
+/// Deletes sessions that expired or were revoked a while ago, and refresh tokens that
+/// expired, returning how many sessions were deleted
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let cutoff = Utc::now() - ENDED_RETENTION;
+    let sessions =
+        sqlx::query("DELETE FROM auth_sessions WHERE expires_at < $1 OR revoked_at < $1")
+            .bind(cutoff)
+            .execute(pool)
+            .await?;
+    sqlx::query("DELETE FROM refresh_tokens WHERE expires_at < NOW()")
+        .execute(pool)
+        .await?;
+
+    Ok(sessions.rows_affected())
+}
@@ -0,0 +1,168 @@
+//! Sign-In-With-Solana: authenticate by signing a server-issued nonce with a wallet keypair.
+//!
+//! 1. The client requests a nonce for its address and receives a message to sign.
+//! 2. The client signs the exact message bytes with its ed25519 keypair.
+//! 3. The server verifies the signature against the address, consumes the nonce and
+//!    starts a [session](super::sessions) of the user linked to that address (creating
+//!    it on first sign-in).
+
+use chrono::{DateTime, Duration, Utc};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::WalletAddress;
+use crate::AppError;
+
+/// How long an issued nonce can be used to sign in
+const NONCE_TTL_MINUTES: i64 = 10;
+
+/// Request payload for a sign-in nonce
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NonceRequest {
+    /// Base58-encoded Solana address that will sign the message
+    #[schema(value_type = String, example = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU")]
+    pub address: WalletAddress,
+}
+
+/// A sign-in challenge to be signed by the wallet
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct NonceResponse {
+    /// One-time nonce embedded in the message
+    pub nonce: String,
+    /// Exact message the wallet must sign (UTF-8 bytes)
+    pub message: String,
+    /// When the nonce stops being accepted
+    pub expires_at: DateTime<Utc>,
+}
+
+/// Request payload proving ownership of an address
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct VerifyRequest {
+    /// Base58-encoded Solana address that signed the message
+    #[schema(value_type = String, example = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU")]
+    pub address: WalletAddress,
+    /// Nonce returned by the nonce endpoint
+    pub nonce: String,
+    /// Base58-encoded ed25519 signature of the message
+    pub signature: String,
+}
+
+/// Builds the human-readable message a wallet signs to sign in
+pub fn sign_in_message(
+    domain: &str,
+    address: &str,
+    nonce: &str,
+    issued_at: DateTime<Utc>,
+) -> String {
+    format!(
+        "{domain} wants you to sign in with your Solana account:\n{address}\n\nNonce: {nonce}\nIssued At: {}",
+        issued_at.to_rfc3339()
+    )
+}
+
+/// Issues and stores a new nonce for `address`
+pub async fn issue_nonce(
+    pool: &PgPool,
+    domain: &str,
+    address: &WalletAddress,
+) -> Result<NonceResponse, AppError> {
+    let mut bytes = [0u8; 16];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let nonce = bs58::encode(bytes).into_string();
+
+    let issued_at = Utc::now();
+    let expires_at = issued_at + Duration::minutes(NONCE_TTL_MINUTES);
+    let message = sign_in_message(domain, address.as_str(), &nonce, issued_at);
+
+    sqlx::query(
+        r#"
+        INSERT INTO siws_nonces (nonce, address, message, expires_at)
+        VALUES ($1, $2, $3, $4)
+        "#,
+    )
+    .bind(&nonce)
+    .bind(address.as_str())
+    .bind(&message)
+    .bind(expires_at)
+    .execute(pool)
+    .await?;
+
+    Ok(NonceResponse {
+        nonce,
+        message,
+        expires_at,
+    })
+}
+
+/// Verifies a signed nonce and returns the ID of the user linked to the address
+///
+/// The nonce is consumed even if it has expired, so every nonce can be tried once.
+pub async fn verify_sign_in(pool: &PgPool, request: &VerifyRequest) -> Result<Uuid, AppError> {
+    let invalid = || AppError::Unauthorized("Invalid or expired sign-in nonce".to_string());
+
+    let mut tx = pool.begin().await?;
+
+    let (message, expires_at) = sqlx::query_as::<_, (String, DateTime<Utc>)>(
+        r#"
+        UPDATE siws_nonces
+        SET used_at = NOW()
+        WHERE nonce = $1 AND address = $2 AND used_at IS NULL
+        RETURNING message, expires_at
+        "#,
+    )
+    .bind(&request.nonce)
+    .bind(request.address.as_str())
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(invalid)?;
+
+    if expires_at < Utc::now() {
+        tx.commit().await?;
+        return Err(invalid());
+    }
+
+    if let Err(err) = verify_signature(&request.address, message.as_bytes(), &request.signature) {
+        tx.commit().await?;
+        return Err(err);
+    }
+
+    let user_id = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO users (id, siws_address)
+        VALUES ($1, $2)
+        ON CONFLICT (siws_address) DO UPDATE SET updated_at = NOW()
+        RETURNING id
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(request.address.as_str())
+    .fetch_one(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+    Ok(user_id)
+}
+
+/// Verifies a base58 ed25519 `signature` of `message` by the key behind `address`
+pub fn verify_signature(
+    address: &WalletAddress,
+    message: &[u8],
+    signature: &str,
+) -> Result<(), AppError> {
+    let invalid = || AppError::Unauthorized("Invalid signature".to_string());
+
+    let key = VerifyingKey::from_bytes(&address.to_bytes()).map_err(|_| invalid())?;
+
+    let mut bytes = [0u8; 64];
+    match bs58::decode(signature).into(&mut bytes) {
+        Ok(64) => {}
+        _ => return Err(invalid()),
+    }
+
+    key.verify(message, &Signature::from_bytes(&bytes))
+        .map_err(|_| invalid())
+}
@@ -0,0 +1,158 @@
+//! Live on-chain balances, reconciled against the holdings computed from transactions.
+//!
+//! Holdings are the sum of a wallet's recorded balance changes, so they drift from the
+//! chain when its transaction history is incomplete. The live balances are read from
+//! the node: the wallet's lamports and every SPL token account it owns under the Token
+//! and Token-2022 programs. Wrapped SOL counts as SOL, as it does in synced
+//! transactions.
+
+use std::collections::{BTreeSet, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::holdings;
+use crate::models::Wallet;
+use crate::money::TokenAmount;
+use crate::sync::{
+    format_units, known_symbol, SolanaRpcClient, SyncError, NATIVE_SOL_DECIMALS, NATIVE_SOL_MINT,
+};
+use crate::AppError;
+
+/// The SPL Token program
+pub const TOKEN_PROGRAM_ID: &str = "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA";
+
+/// The SPL Token-2022 program
+pub const TOKEN_2022_PROGRAM_ID: &str = "TokenzQdBNbLqP5VEhdkAS6EPFLC1PHnBqCXEpPxuEb";
+
+/// Live and computed balance of one token
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenBalance {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// Balance on-chain, as a decimal string
+    #[schema(example = "1500000.5")]
+    pub live_amount: String,
+    /// Balance computed from the recorded transactions, as a decimal string
+    #[schema(example = "1500000.5")]
+    pub computed_amount: String,
+    /// Live less computed balance: positive when the wallet holds more than its
+    /// recorded history accounts for
+    #[schema(example = "0")]
+    pub difference: String,
+    /// Whether the live and computed balances agree
+    pub matches: bool,
+}
+
+/// A wallet's live balances reconciled against its computed holdings
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletBalances {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Every token held on-chain or in the computed holdings, by mint
+    pub balances: Vec<TokenBalance>,
+    /// Number of tokens whose balances disagree
+    pub discrepancies: usize,
+    /// When the live balances were read
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Raw live balances of `owner` per mint, with their decimals
+pub async fn live_balances(
+    rpc: &SolanaRpcClient,
+    owner: &str,
+) -> Result<HashMap<String, (i128, u32)>, SyncError> {
+    let mut balances: HashMap<String, (i128, u32)> = HashMap::new();
+    balances.insert(
+        NATIVE_SOL_MINT.to_string(),
+        (rpc.get_balance(owner).await? as i128, NATIVE_SOL_DECIMALS),
+    );
+
+    for program_id in [TOKEN_PROGRAM_ID, TOKEN_2022_PROGRAM_ID] {
+        for account in rpc.get_token_accounts_by_owner(owner, program_id).await? {
+            let Some((mint, raw, decimals)) = token_account_balance(&account) else {
+                continue;
+            };
+            let balance = balances.entry(mint).or_insert((0, decimals));
+            balance.0 += raw;
+        }
+    }
+
+    Ok(balances)
+}
+
+/// Mint, raw amount and decimals of a `jsonParsed` token account
+fn token_account_balance(account: &Value) -> Option<(String, i128, u32)> {
+    let info = &account["account"]["data"]["parsed"]["info"];
+    let mint = info["mint"].as_str()?;
+    let raw = info["tokenAmount"]["amount"].as_str()?.parse().ok()?;
+    let decimals = info["tokenAmount"]["decimals"].as_u64()? as u32;
+
+    Some((mint.to_string(), raw, decimals))
+}
+
+/// Reads the wallet's live balances and compares them with its computed holdings
+///
+/// Tokens whose live and computed balances are both zero are left out.
+pub async fn reconcile(
+    pool: &PgPool,
+    rpc: &SolanaRpcClient,
+    wallet: &Wallet,
+) -> Result<WalletBalances, AppError> {
+    let live = live_balances(rpc, &wallet.address).await?;
+    let checked_at = Utc::now();
+    let computed: HashMap<String, (String, TokenAmount)> = holdings::positions(pool, &[wallet.id])
+        .await?
+        .into_iter()
+        .map(|(mint, symbol, amount)| (mint, (symbol, amount)))
+        .collect();
+
+    let mints: BTreeSet<&str> = live
+        .keys()
+        .chain(computed.keys())
+        .map(String::as_str)
+        .collect();
+    let balances: Vec<TokenBalance> = mints
+        .into_iter()
+        .filter_map(|mint| {
+            let computed = computed.get(mint);
+            let computed_amount = computed.map_or(TokenAmount::ZERO, |(_, amount)| *amount);
+            let (live_raw, decimals) = live
+                .get(mint)
+                .copied()
+                .unwrap_or_else(|| (0, computed_amount.decimal().normalize().scale()));
+            let computed_raw = computed_amount.to_raw(decimals).unwrap_or(0);
+            if live_raw == 0 && computed_raw == 0 {
+                return None;
+            }
+
+            let symbol = computed
+                .map(|(symbol, _)| symbol.as_str())
+                .filter(|symbol| !symbol.is_empty())
+                .unwrap_or_else(|| known_symbol(mint));
+            Some(TokenBalance {
+                token_address: mint.to_string(),
+                token_symbol: symbol.to_string(),
+                live_amount: format_units(live_raw, decimals),
+                computed_amount: format_units(computed_raw, decimals),
+                difference: format_units(live_raw - computed_raw, decimals),
+                matches: live_raw == computed_raw,
+            })
+        })
+        .collect();
+
+    Ok(WalletBalances {
+        wallet_id: wallet.id,
+        discrepancies: balances.iter().filter(|balance| !balance.matches).count(),
+        balances,
+        checked_at,
+    })
+}
@@ -0,0 +1,335 @@
+//! Stripe billing of hosted instances.
+//!
+//! Stripe reports subscription changes to `POST /webhooks/stripe`, signed with the
+//! endpoint's secret (`STRIPE_WEBHOOK_SECRET`). A subscription that is active, trialing
+//! or past due puts its user on the [plan](crate::quotas::Plan) whose
+//! `plans.stripe_price_id` it is billed at; once it is cancelled or lapses, the user
+//! falls back to `DEFAULT_PLAN`. Users are found by the `user_id` in the subscription's
+//! metadata, or by their Stripe customer, which a completed Checkout session with the
+//! user's ID as `client_reference_id` links to them.
+//!
+//! Each event is handled once, and events older than the last one applied to a user
+//! are ignored, as Stripe retries deliveries and does not keep them in order. A
+//! subscription event that cannot be applied yet, because its customer is not linked
+//! to a user or its price is not a plan's, is rejected without being recorded, so
+//! Stripe delivers it again later.
+
+use axum::http::HeaderMap;
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use tracing::{debug, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::quotas::Plan;
+
+/// Header carrying the signature of a delivery
+pub const SIGNATURE_HEADER: &str = "stripe-signature";
+
+/// How old a signed delivery may be, against replays
+const SIGNATURE_TOLERANCE_SECS: i64 = 300;
+
+/// Subscription statuses that keep the user on the subscribed plan
+const ACTIVE_STATUSES: [&str; 3] = ["active", "trialing", "past_due"];
+
+/// Why a delivery's signature was rejected
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum SignatureError {
+    /// No `Stripe-Signature` header was sent
+    #[error("Missing Stripe-Signature header")]
+    Missing,
+    /// The header has no timestamp or `v1` signature
+    #[error("Malformed Stripe-Signature header")]
+    Malformed,
+    /// The delivery was signed too long ago
+    #[error("Stripe signature timestamp is outside the tolerance")]
+    Expired,
+    /// No `v1` signature matches the payload
+    #[error("Invalid Stripe signature")]
+    Mismatch,
+}
+
+/// Computes the `Stripe-Signature` value of a payload signed at `timestamp`
+pub fn sign(secret: &str, timestamp: i64, payload: &[u8]) -> String {
+    format!(
+        "t={timestamp},v1={}",
+        hex::encode(mac(secret, timestamp, payload).finalize().into_bytes())
+    )
+}
+
+fn mac(secret: &str, timestamp: i64, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload);
+    mac
+}
+
+/// Checks the `Stripe-Signature` of a delivery against the endpoint's secret
+///
+/// Any of the header's `v1` signatures may match, so deliveries keep verifying while
+/// Stripe rolls the secret.
+pub fn verify_signature(
+    secret: &str,
+    headers: &HeaderMap,
+    payload: &[u8],
+    now: DateTime<Utc>,
+) -> Result<(), SignatureError> {
+    let header = headers
+        .get(SIGNATURE_HEADER)
+        .ok_or(SignatureError::Missing)?
+        .to_str()
+        .map_err(|_| SignatureError::Malformed)?;
+
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.extend(hex::decode(value).ok()),
+            _ => {}
+        }
+    }
+    let timestamp = timestamp.ok_or(SignatureError::Malformed)?;
+    if signatures.is_empty() {
+        return Err(SignatureError::Malformed);
+    }
+    if (now.timestamp() - timestamp).abs() > SIGNATURE_TOLERANCE_SECS {
+        return Err(SignatureError::Expired);
+    }
+
+    let expected = mac(secret, timestamp, payload);
+    if signatures
+        .iter()
+        .any(|signature| expected.clone().verify_slice(signature).is_ok())
+    {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
+    }
+}
+
+/// Why a Stripe event could not be applied
+#[derive(Debug, Error)]
+pub enum EventError {
+    /// The subscription's customer is not linked to a user, e.g. because the
+    /// Checkout session completing it has not been delivered yet
+    #[error("Stripe event {0} concerns no known user yet")]
+    UnknownSubscriber(String),
+
+    /// The subscription is billed at no price of a plan
+    #[error("Stripe event {0} bills no price of a plan")]
+    UnknownPrice(String),
+
+    /// The database failed
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+}
+
+/// A Stripe event, as delivered to the webhook endpoint
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeEvent {
+    /// ID of the event, e.g. `evt_1N...`
+    pub id: String,
+    /// Type of the event, e.g. `customer.subscription.updated`
+    #[serde(rename = "type")]
+    pub event_type: String,
+    /// When the event happened, as a Unix timestamp
+    pub created: i64,
+    /// Object the event is about
+    pub data: StripeEventData,
+}
+
+/// Payload of a [`StripeEvent`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StripeEventData {
+    /// The Checkout session, subscription or other object the event is about
+    pub object: Value,
+}
+
+/// Outcome of a Stripe event
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct StripeEventReport {
+    /// ID of the event
+    pub event_id: String,
+    /// Type of the event
+    pub event_type: String,
+    /// Whether the event changed anything; `false` for duplicates, stale events and
+    /// events of other types
+    pub handled: bool,
+    /// User the event applied to
+    pub user_id: Option<Uuid>,
+    /// Plan the user was put on, `null` for `DEFAULT_PLAN`
+    pub plan: Option<Plan>,
+}
+
+/// Applies a verified event to the user it concerns
+///
+/// Subscription events that cannot be applied yet fail without being recorded as
+/// handled, so their redelivery is applied.
+pub async fn handle_event(
+    pool: &PgPool,
+    event: &StripeEvent,
+) -> Result<StripeEventReport, EventError> {
+    let mut report = StripeEventReport {
+        event_id: event.id.clone(),
+        event_type: event.event_type.clone(),
+        handled: false,
+        user_id: None,
+        plan: None,
+    };
+
+    let mut tx = pool.begin().await?;
+    let first_delivery = sqlx::query(
+        "INSERT INTO stripe_events (id, event_type) VALUES ($1, $2) ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(&event.id)
+    .bind(&event.event_type)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected()
+        > 0;
+    if !first_delivery {
+        debug!("Ignoring Stripe event {} delivered again", event.id);
+        return Ok(report);
+    }
+
+    let object = &event.data.object;
+    match event.event_type.as_str() {
+        "checkout.session.completed" => {
+            let user_id = str_field(object, "client_reference_id").and_then(|id| id.parse().ok());
+            if let (Some(user_id), Some(customer)) = (user_id, str_field(object, "customer")) {
+                report.handled = link_customer(&mut tx, user_id, customer).await?;
+                report.user_id = Some(user_id);
+            }
+        }
+        "customer.subscription.created"
+        | "customer.subscription.updated"
+        | "customer.subscription.deleted" => {
+            // Returning drops the transaction, rolling back the event's record
+            let Some(user_id) = subscriber(&mut tx, object).await? else {
+                return Err(EventError::UnknownSubscriber(event.id.clone()));
+            };
+            report.user_id = Some(user_id);
+
+            let status = str_field(object, "status").unwrap_or_default();
+            let active = event.event_type != "customer.subscription.deleted"
+                && ACTIVE_STATUSES.contains(&status);
+            let plan = match active {
+                true => match subscribed_plan(&mut tx, object).await? {
+                    Some(plan) => Some(plan),
+                    None => return Err(EventError::UnknownPrice(event.id.clone())),
+                },
+                false => None,
+            };
+
+            let happened_at = DateTime::from_timestamp(event.created, 0).unwrap_or_else(Utc::now);
+            report.handled = sqlx::query(
+                r#"
+                UPDATE users
+                SET plan = $2, billing_updated_at = $3
+                WHERE id = $1 AND (billing_updated_at IS NULL OR billing_updated_at <= $3)
+                "#,
+            )
+            .bind(user_id)
+            .bind(plan.map(|plan| plan.as_str()))
+            .bind(happened_at)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected()
+                > 0;
+            if report.handled {
+                report.plan = plan;
+                info!(
+                    "Stripe put user {} on the {} plan",
+                    user_id,
+                    plan.map_or("default", |plan| plan.as_str())
+                );
+            }
+        }
+        other => debug!("Ignoring Stripe event {} of type {}", event.id, other),
+    }
+    tx.commit().await?;
+
+    Ok(report)
+}
+
+fn str_field<'a>(object: &'a Value, field: &str) -> Option<&'a str> {
+    object.get(field).and_then(Value::as_str)
+}
+
+/// Records the Stripe customer of a user, returning whether the user exists
+async fn link_customer(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    customer: &str,
+) -> Result<bool, sqlx::Error> {
+    let linked = sqlx::query("UPDATE users SET stripe_customer_id = $2 WHERE id = $1")
+        .bind(user_id)
+        .bind(customer)
+        .execute(conn)
+        .await?;
+    Ok(linked.rows_affected() > 0)
+}
+
+/// User of a subscription: the `user_id` of its metadata, linking the customer to
+/// them, or else the user linked to its customer
+async fn subscriber(
+    conn: &mut PgConnection,
+    subscription: &Value,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let customer = str_field(subscription, "customer");
+    let metadata_user = subscription
+        .get("metadata")
+        .and_then(|metadata| str_field(metadata, "user_id"))
+        .and_then(|id| id.parse::<Uuid>().ok());
+
+    match (metadata_user, customer) {
+        (Some(user_id), Some(customer)) => Ok(link_customer(conn, user_id, customer)
+            .await?
+            .then_some(user_id)),
+        (Some(user_id), None) => {
+            sqlx::query_scalar("SELECT id FROM users WHERE id = $1")
+                .bind(user_id)
+                .fetch_optional(conn)
+                .await
+        }
+        (None, Some(customer)) => {
+            sqlx::query_scalar("SELECT id FROM users WHERE stripe_customer_id = $1")
+                .bind(customer)
+                .fetch_optional(conn)
+                .await
+        }
+        (None, None) => Ok(None),
+    }
+}
+
+/// Plan one of the subscription's prices belongs to
+async fn subscribed_plan(
+    conn: &mut PgConnection,
+    subscription: &Value,
+) -> Result<Option<Plan>, sqlx::Error> {
+    let prices: Vec<String> = subscription
+        .pointer("/items/data")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|item| item.pointer("/price/id").and_then(Value::as_str))
+        .map(str::to_string)
+        .collect();
+
+    let name = sqlx::query_scalar::<_, String>(
+        "SELECT name FROM plans WHERE stripe_price_id = ANY($1) ORDER BY max_wallets DESC NULLS FIRST LIMIT 1",
+    )
+    .bind(&prices)
+    .fetch_optional(conn)
+    .await?;
+
+    Ok(name.and_then(|name| name.parse().ok()))
+}
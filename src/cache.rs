@@ -0,0 +1,202 @@
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use moka::future::Cache as MokaStore;
+use moka::Expiry;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use tracing::warn;
+use utoipa::ToSchema;
+
+/// Entries kept by the in-process cache before the least used are evicted
+pub const DEFAULT_CACHE_CAPACITY: u64 = 100_000;
+
+/// Key-value cache with a time to live per entry
+///
+/// Values are opaque bytes, so the cache can live outside the process, e.g. in
+/// Redis, and be shared between instances. [`MokaCache`] keeps entries in process
+/// memory for single-instance deployments. Caching is best effort: a backend that
+/// fails should log and behave as a miss rather than fail the request.
+#[async_trait]
+pub trait Cache: Send + Sync {
+    /// Returns the value stored under `key`, unless it has expired
+    async fn get(&self, key: &str) -> Option<Vec<u8>>;
+
+    /// Stores `value` under `key` for `ttl`, replacing any earlier value
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration);
+
+    /// Removes the value stored under `key`
+    async fn remove(&self, key: &str);
+}
+
+/// Looks up a JSON-encoded value, treating undecodable entries as missing
+pub async fn get_json<T: DeserializeOwned>(cache: &dyn Cache, key: &str) -> Option<T> {
+    let bytes = cache.get(key).await?;
+    match serde_json::from_slice(&bytes) {
+        Ok(value) => Some(value),
+        Err(err) => {
+            warn!("Ignoring undecodable cache entry {}: {}", key, err);
+            None
+        }
+    }
+}
+
+/// Stores a value JSON-encoded for `ttl`
+pub async fn set_json<T: Serialize>(cache: &dyn Cache, key: &str, value: &T, ttl: Duration) {
+    match serde_json::to_vec(value) {
+        Ok(bytes) => cache.set(key, bytes, ttl).await,
+        Err(err) => warn!("Failed to encode cache entry {}: {}", key, err),
+    }
+}
+
+/// A cached value and how long it lives
+#[derive(Debug, Clone)]
+struct Entry {
+    value: Vec<u8>,
+    ttl: Duration,
+}
+
+/// Expires each entry after the TTL it was stored with
+struct EntryExpiry;
+
+impl Expiry<String, Entry> for EntryExpiry {
+    fn expire_after_create(&self, _key: &String, entry: &Entry, _now: Instant) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+
+    fn expire_after_update(
+        &self,
+        _key: &String,
+        entry: &Entry,
+        _now: Instant,
+        _remaining: Option<Duration>,
+    ) -> Option<Duration> {
+        Some(entry.ttl)
+    }
+}
+
+/// Cache kept in process memory by [`moka`], bounded to a number of entries
+///
+/// Entries are lost on restart and not shared between instances.
+#[derive(Clone)]
+pub struct MokaCache {
+    store: MokaStore<String, Entry>,
+}
+
+impl MokaCache {
+    /// Creates a cache holding up to `capacity` entries
+    pub fn new(capacity: u64) -> Self {
+        Self {
+            store: MokaStore::builder()
+                .max_capacity(capacity)
+                .expire_after(EntryExpiry)
+                .build(),
+        }
+    }
+}
+
+impl Default for MokaCache {
+    fn default() -> Self {
+        Self::new(DEFAULT_CACHE_CAPACITY)
+    }
+}
+
+#[async_trait]
+impl Cache for MokaCache {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        self.store.get(key).await.map(|entry| entry.value)
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) {
+        if ttl.is_zero() {
+            self.store.invalidate(key).await;
+            return;
+        }
+        self.store
+            .insert(key.to_string(), Entry { value, ttl })
+            .await;
+    }
+
+    async fn remove(&self, key: &str) {
+        self.store.invalidate(key).await;
+    }
+}
+
+/// Hits and misses of the lookups of one kind of cache entry
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct CacheCounts {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups of missing or expired entries
+    pub misses: u64,
+}
+
+impl CacheCounts {
+    /// Share of lookups answered from the cache, or `None` before any lookup
+    pub fn hit_rate(&self) -> Option<f64> {
+        let lookups = self.hits + self.misses;
+        (lookups > 0).then(|| self.hits as f64 / lookups as f64)
+    }
+}
+
+/// Hits and misses of a cache's lookups since the process started, by kind of entry
+///
+/// The kind of an entry is the part of its key before the first `:`, e.g. `price_usd`
+/// or `token_metadata`.
+#[derive(Debug, Default)]
+pub struct CacheMetrics {
+    counts: Mutex<BTreeMap<String, CacheCounts>>,
+}
+
+impl CacheMetrics {
+    /// Counts a lookup of `key`
+    fn record(&self, key: &str, hit: bool) {
+        let kind = key.split_once(':').map_or(key, |(kind, _)| kind);
+        let mut counts = self.counts.lock().unwrap_or_else(|e| e.into_inner());
+        let counts = counts.entry(kind.to_string()).or_default();
+        if hit {
+            counts.hits += 1;
+        } else {
+            counts.misses += 1;
+        }
+    }
+
+    /// Hits and misses so far, by kind of entry
+    pub fn counts(&self) -> BTreeMap<String, CacheCounts> {
+        self.counts
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+}
+
+/// Cache counting the hits and misses of another in [`CacheMetrics`]
+pub struct MeteredCache {
+    inner: Arc<dyn Cache>,
+    metrics: Arc<CacheMetrics>,
+}
+
+impl MeteredCache {
+    /// Wraps `inner`, counting its lookups in `metrics`
+    pub fn new(inner: Arc<dyn Cache>, metrics: Arc<CacheMetrics>) -> Self {
+        Self { inner, metrics }
+    }
+}
+
+#[async_trait]
+impl Cache for MeteredCache {
+    async fn get(&self, key: &str) -> Option<Vec<u8>> {
+        let value = self.inner.get(key).await;
+        self.metrics.record(key, value.is_some());
+        value
+    }
+
+    async fn set(&self, key: &str, value: Vec<u8>, ttl: Duration) {
+        self.inner.set(key, value, ttl).await;
+    }
+
+    async fn remove(&self, key: &str) {
+        self.inner.remove(key).await;
+    }
+}
@@ -0,0 +1,608 @@
+//! OHLCV price candles of tokens, for charting.
+//!
+//! Candles come from a [`CandleSource`]: Birdeye's OHLCV API, quoted in USD, when a
+//! Birdeye API key is configured, else the SOL swaps ingested from tracked wallets
+//! aggregated into candles quoted in SOL. Either way they are stored in
+//! `price_candles` and re-fetched at most once a minute per series, so the frontend
+//! can chart held tokens without calling third parties itself.
+
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::resilience::{
+    CircuitBreaker, CircuitOpen, DEFAULT_FAILURE_THRESHOLD, DEFAULT_OPEN_DURATION,
+};
+use crate::sync::NATIVE_SOL_MINT;
+use crate::AppError;
+
+/// Default Birdeye API endpoint
+pub const DEFAULT_BIRDEYE_API_URL: &str = "https://public-api.birdeye.so";
+
+/// Most candles returned by one request
+pub const MAX_CANDLES: i64 = 1000;
+
+/// Longest range of candles, in days
+pub const MAX_CANDLE_RANGE_DAYS: i64 = 365;
+
+/// How long fetched candles are served before the series is fetched again
+pub const CANDLE_REFRESH: Duration = Duration::from_secs(60);
+
+/// Errors that can occur while fetching candles
+#[derive(Debug, Error)]
+pub enum CandleError {
+    /// The HTTP request to the candle API failed
+    #[error("Candle request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The candle API returned an unexpected response
+    #[error("Invalid candle response: {0}")]
+    InvalidResponse(String),
+
+    /// The candle API is failing and requests are rejected until it recovers
+    #[error(transparent)]
+    Unavailable(#[from] CircuitOpen),
+
+    /// Reading or storing candles failed
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+impl From<CandleError> for AppError {
+    fn from(err: CandleError) -> Self {
+        match err {
+            CandleError::Http(err) => err.into(),
+            CandleError::Unavailable(open) => open.into(),
+            CandleError::Database(err) => err.into(),
+            other => AppError::UpstreamError(other.to_string()),
+        }
+    }
+}
+
+/// Duration of one candle
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum CandleInterval {
+    /// One minute
+    OneMinute,
+    /// Five minutes
+    FiveMinutes,
+    /// Fifteen minutes
+    FifteenMinutes,
+    /// One hour
+    #[default]
+    OneHour,
+    /// Four hours
+    FourHours,
+    /// One day
+    OneDay,
+}
+
+impl CandleInterval {
+    const ALL: [Self; 6] = [
+        Self::OneMinute,
+        Self::FiveMinutes,
+        Self::FifteenMinutes,
+        Self::OneHour,
+        Self::FourHours,
+        Self::OneDay,
+    ];
+
+    /// Name of the interval as accepted by the API and stored
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::OneMinute => "1m",
+            Self::FiveMinutes => "5m",
+            Self::FifteenMinutes => "15m",
+            Self::OneHour => "1h",
+            Self::FourHours => "4h",
+            Self::OneDay => "1d",
+        }
+    }
+
+    /// Length of the interval in seconds
+    pub fn seconds(&self) -> i64 {
+        match self {
+            Self::OneMinute => 60,
+            Self::FiveMinutes => 300,
+            Self::FifteenMinutes => 900,
+            Self::OneHour => 3600,
+            Self::FourHours => 14_400,
+            Self::OneDay => 86_400,
+        }
+    }
+
+    /// Open time of the candle containing `time`
+    pub fn open_time(&self, time: DateTime<Utc>) -> DateTime<Utc> {
+        let seconds = time.timestamp().div_euclid(self.seconds()) * self.seconds();
+        Utc.timestamp_opt(seconds, 0).single().unwrap_or(time)
+    }
+}
+
+impl TryFrom<String> for CandleInterval {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::ALL
+            .into_iter()
+            .find(|interval| interval.as_str() == value)
+            .ok_or_else(|| {
+                format!("Invalid interval {value:?}: expected one of 1m, 5m, 15m, 1h, 4h or 1d")
+            })
+    }
+}
+
+impl From<CandleInterval> for String {
+    fn from(interval: CandleInterval) -> Self {
+        interval.as_str().to_string()
+    }
+}
+
+impl fmt::Display for CandleInterval {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Period covered by a candle series, written as a number of hours or days such as
+/// `24h` or `7d`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct CandleRange {
+    count: i64,
+    unit: char,
+}
+
+impl CandleRange {
+    /// Length of the range in seconds
+    pub fn seconds(&self) -> i64 {
+        match self.unit {
+            'h' => self.count * 3600,
+            _ => self.count * 86_400,
+        }
+    }
+}
+
+impl Default for CandleRange {
+    fn default() -> Self {
+        Self {
+            count: 7,
+            unit: 'd',
+        }
+    }
+}
+
+impl TryFrom<String> for CandleRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let invalid =
+            || format!("Invalid range {value:?}: expected 1h to {MAX_CANDLE_RANGE_DAYS}d, e.g. 7d");
+        let unit = value
+            .chars()
+            .last()
+            .filter(|unit| matches!(unit, 'h' | 'd'));
+        let count = value
+            .get(..value.len().saturating_sub(1))
+            .and_then(|count| count.parse::<i64>().ok());
+        let (Some(unit), Some(count)) = (unit, count) else {
+            return Err(invalid());
+        };
+
+        let range = Self { count, unit };
+        if count < 1 || range.seconds() > MAX_CANDLE_RANGE_DAYS * 86_400 {
+            return Err(invalid());
+        }
+        Ok(range)
+    }
+}
+
+impl From<CandleRange> for String {
+    fn from(range: CandleRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for CandleRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.count, self.unit)
+    }
+}
+
+/// Currency candle prices are quoted in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CandleQuote {
+    /// US dollars
+    Usd,
+    /// SOL
+    Sol,
+}
+
+/// Prices of a token over one interval
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Candle {
+    /// Start of the interval
+    pub open_time: DateTime<Utc>,
+    /// Price at the start of the interval
+    pub open: f64,
+    /// Highest price in the interval
+    pub high: f64,
+    /// Lowest price in the interval
+    pub low: f64,
+    /// Price at the end of the interval
+    pub close: f64,
+    /// Amount of the token traded in the interval
+    pub volume: f64,
+}
+
+/// A token's candles over a range
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PriceCandles {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub mint: String,
+    /// Duration of each candle
+    #[schema(value_type = String, example = "1h")]
+    pub interval: CandleInterval,
+    /// Period covered
+    #[schema(value_type = String, example = "7d")]
+    pub range: CandleRange,
+    /// Currency of the prices
+    pub quote: CandleQuote,
+    /// Where the candles come from: `birdeye` or `swaps`
+    #[schema(example = "birdeye")]
+    pub source: String,
+    /// Candles oldest first; intervals without trades have none
+    pub candles: Vec<Candle>,
+}
+
+/// A source of price candles
+#[async_trait]
+pub trait CandleSource: Send + Sync {
+    /// Name of the source, stored with its candles
+    fn name(&self) -> &'static str;
+
+    /// Currency the source quotes prices in
+    fn quote(&self) -> CandleQuote;
+
+    /// Fetches the candles of `mint` opening from `from` up to `to`
+    async fn fetch(
+        &self,
+        mint: &str,
+        interval: CandleInterval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Candle>, CandleError>;
+}
+
+/// Candle source backed by Birdeye's OHLCV API, quoted in USD
+#[derive(Debug, Clone)]
+pub struct BirdeyeCandleSource {
+    http: reqwest::Client,
+    url: String,
+    api_key: String,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl BirdeyeCandleSource {
+    /// Creates a source for the Birdeye API at `url`
+    pub fn new(url: impl Into<String>, api_key: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            url: url.into().trim_end_matches('/').to_string(),
+            api_key: api_key.into(),
+            breaker: Arc::new(CircuitBreaker::new(
+                "Birdeye API",
+                DEFAULT_FAILURE_THRESHOLD,
+                DEFAULT_OPEN_DURATION,
+            )),
+        }
+    }
+
+    /// Guards requests with the given breaker instead of the default one
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Arc::new(breaker);
+        self
+    }
+
+    /// Birdeye's name of an interval
+    fn interval_type(interval: CandleInterval) -> &'static str {
+        match interval {
+            CandleInterval::OneMinute => "1m",
+            CandleInterval::FiveMinutes => "5m",
+            CandleInterval::FifteenMinutes => "15m",
+            CandleInterval::OneHour => "1H",
+            CandleInterval::FourHours => "4H",
+            CandleInterval::OneDay => "1D",
+        }
+    }
+
+    /// Requests the OHLCV items of a series
+    async fn request(
+        &self,
+        mint: &str,
+        interval: CandleInterval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Value, CandleError> {
+        Ok(self
+            .http
+            .get(format!("{}/defi/ohlcv", self.url))
+            .header("X-API-KEY", &self.api_key)
+            .header("x-chain", "solana")
+            .query(&[
+                ("address", mint.to_string()),
+                ("type", Self::interval_type(interval).to_string()),
+                ("time_from", from.timestamp().to_string()),
+                ("time_to", to.timestamp().to_string()),
+            ])
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+}
+
+#[async_trait]
+impl CandleSource for BirdeyeCandleSource {
+    fn name(&self) -> &'static str {
+        "birdeye"
+    }
+
+    fn quote(&self) -> CandleQuote {
+        CandleQuote::Usd
+    }
+
+    async fn fetch(
+        &self,
+        mint: &str,
+        interval: CandleInterval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Candle>, CandleError> {
+        let response = self
+            .breaker
+            .call(|| self.request(mint, interval, from, to), |_| true)
+            .await??;
+        let items = response["data"]["items"]
+            .as_array()
+            .ok_or_else(|| CandleError::InvalidResponse(response.to_string()))?;
+
+        Ok(items
+            .iter()
+            .filter_map(|item| {
+                Some(Candle {
+                    open_time: Utc.timestamp_opt(item["unixTime"].as_i64()?, 0).single()?,
+                    open: item["o"].as_f64()?,
+                    high: item["h"].as_f64()?,
+                    low: item["l"].as_f64()?,
+                    close: item["c"].as_f64()?,
+                    volume: item["v"].as_f64().unwrap_or(0.0),
+                })
+            })
+            .collect())
+    }
+}
+
+/// Candle source aggregating the swaps between the token and SOL recorded on tracked
+/// wallets' transactions, quoted in SOL
+///
+/// Only trades of tracked wallets are seen, so candles are sparse and volumes partial,
+/// but no third party is needed.
+#[derive(Debug, Clone)]
+pub struct SwapCandleSource {
+    pool: PgPool,
+}
+
+impl SwapCandleSource {
+    /// Creates a source aggregating the swaps stored in `pool`
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+}
+
+#[async_trait]
+impl CandleSource for SwapCandleSource {
+    fn name(&self) -> &'static str {
+        "swaps"
+    }
+
+    fn quote(&self) -> CandleQuote {
+        CandleQuote::Sol
+    }
+
+    async fn fetch(
+        &self,
+        mint: &str,
+        interval: CandleInterval,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Result<Vec<Candle>, CandleError> {
+        // Every row of a transaction carries its swap, so each is counted once
+        Ok(sqlx::query_as::<_, Candle>(
+            r#"
+            WITH trades AS (
+                SELECT DISTINCT ON (transaction_hash)
+                       transaction_hash,
+                       block_time,
+                       CASE WHEN swap->>'input_mint' = $1
+                            THEN (swap->>'output_amount')::NUMERIC
+                                 / NULLIF((swap->>'input_amount')::NUMERIC, 0)
+                            ELSE (swap->>'input_amount')::NUMERIC
+                                 / NULLIF((swap->>'output_amount')::NUMERIC, 0)
+                       END AS price,
+                       CASE WHEN swap->>'input_mint' = $1
+                            THEN (swap->>'input_amount')::NUMERIC
+                            ELSE (swap->>'output_amount')::NUMERIC
+                       END AS volume
+                FROM transactions
+                WHERE swap IS NOT NULL
+                  AND block_time >= $3
+                  AND block_time < $4
+                  AND ((swap->>'input_mint' = $1 AND swap->>'output_mint' = $2)
+                       OR (swap->>'output_mint' = $1 AND swap->>'input_mint' = $2))
+                ORDER BY transaction_hash
+            )
+            SELECT to_timestamp(floor(extract(epoch FROM block_time) / $5) * $5) AS open_time,
+                   ((array_agg(price ORDER BY block_time, transaction_hash))[1])::FLOAT8 AS open,
+                   MAX(price)::FLOAT8 AS high,
+                   MIN(price)::FLOAT8 AS low,
+                   ((array_agg(price ORDER BY block_time DESC, transaction_hash DESC))[1])::FLOAT8
+                       AS close,
+                   SUM(volume)::FLOAT8 AS volume
+            FROM trades
+            WHERE price IS NOT NULL
+            GROUP BY 1
+            ORDER BY 1
+            "#,
+        )
+        .bind(mint)
+        .bind(NATIVE_SOL_MINT)
+        .bind(from)
+        .bind(to)
+        .bind(interval.seconds() as f64)
+        .fetch_all(&self.pool)
+        .await?)
+    }
+}
+
+/// Candles of `mint` over `range`, ending with the one of the current interval
+///
+/// Served from `price_candles`, which is refreshed from `source` first when the series
+/// was last fetched over [`CANDLE_REFRESH`] ago or does not reach back far enough.
+pub async fn candles(
+    pool: &PgPool,
+    source: &dyn CandleSource,
+    mint: &str,
+    interval: CandleInterval,
+    range: CandleRange,
+) -> Result<PriceCandles, AppError> {
+    let count = (range.seconds() + interval.seconds() - 1) / interval.seconds();
+    if count > MAX_CANDLES {
+        return Err(AppError::BadRequest(format!(
+            "Range {range} holds more than {MAX_CANDLES} {interval} candles; use a longer interval"
+        )));
+    }
+
+    let now = Utc::now();
+    let from =
+        interval.open_time(now) - chrono::Duration::seconds(interval.seconds() * (count - 1));
+
+    let fetched = sqlx::query_as::<_, (DateTime<Utc>, DateTime<Utc>)>(
+        r#"
+        SELECT covered_from, fetched_at
+        FROM price_candle_fetches
+        WHERE mint = $1 AND interval = $2 AND source = $3
+        "#,
+    )
+    .bind(mint)
+    .bind(interval.as_str())
+    .bind(source.name())
+    .fetch_optional(pool)
+    .await?;
+    let fresh = fetched.is_some_and(|(covered_from, fetched_at)| {
+        covered_from <= from && (now - fetched_at).to_std().unwrap_or_default() < CANDLE_REFRESH
+    });
+    if !fresh {
+        let candles = source.fetch(mint, interval, from, now).await?;
+        store(pool, source.name(), mint, interval, from, &candles).await?;
+    }
+
+    let candles = sqlx::query_as::<_, Candle>(
+        r#"
+        SELECT open_time, open, high, low, close, volume
+        FROM price_candles
+        WHERE mint = $1 AND interval = $2 AND source = $3 AND open_time >= $4
+        ORDER BY open_time
+        "#,
+    )
+    .bind(mint)
+    .bind(interval.as_str())
+    .bind(source.name())
+    .bind(from)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(PriceCandles {
+        mint: mint.to_string(),
+        interval,
+        range,
+        quote: source.quote(),
+        source: source.name().to_string(),
+        candles,
+    })
+}
+
+/// Stores fetched candles of `source`, replacing older versions of the same intervals,
+/// and records that the series is complete from `from`
+pub async fn store(
+    pool: &PgPool,
+    source: &str,
+    mint: &str,
+    interval: CandleInterval,
+    from: DateTime<Utc>,
+    candles: &[Candle],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    for candle in candles {
+        sqlx::query(
+            r#"
+            INSERT INTO price_candles (
+                mint, interval, source, open_time, open, high, low, close, volume
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            ON CONFLICT (mint, interval, source, open_time) DO UPDATE
+            SET open = EXCLUDED.open,
+                high = EXCLUDED.high,
+                low = EXCLUDED.low,
+                close = EXCLUDED.close,
+                volume = EXCLUDED.volume
+            "#,
+        )
+        .bind(mint)
+        .bind(interval.as_str())
+        .bind(source)
+        .bind(candle.open_time)
+        .bind(candle.open)
+        .bind(candle.high)
+        .bind(candle.low)
+        .bind(candle.close)
+        .bind(candle.volume)
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    sqlx::query(
+        r#"
+        INSERT INTO price_candle_fetches (mint, interval, source, covered_from, fetched_at)
+        VALUES ($1, $2, $3, $4, NOW())
+        ON CONFLICT (mint, interval, source) DO UPDATE
+        SET covered_from = LEAST(price_candle_fetches.covered_from, EXCLUDED.covered_from),
+            fetched_at = EXCLUDED.fetched_at
+        "#,
+    )
+    .bind(mint)
+    .bind(interval.as_str())
+    .bind(source)
+    .bind(from)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await
+}
@@ -0,0 +1,587 @@
+//! Transaction classification.
+//!
+//! Every recorded balance change is tagged with a [`TransactionCategory`] derived from
+//! the programs the transaction invoked and how balances moved for the wallet and the
+//! other accounts involved. The rules are heuristics:
+//!
+//! - A transaction is a swap if it invoked a known DEX or aggregator program, bought or
+//!   sold on a pump.fun bonding curve, or if the wallet signed it and received one token
+//!   while sending another. Tokens received
+//!   are `swap_buy`, tokens sent are `swap_sell`.
+//! - Swaps through Jupiter, and direct swaps on Raydium, Orca and the other known DEXes,
+//!   are decoded into [`SwapDetails`]: the token sold, the token bought and the exact
+//!   amounts, read from Jupiter's swap events and from the token transfers the DEX made.
+//!   Only those two tokens are the swap; SOL the wallet spent alongside on the network
+//!   fee or account rent is a `fee`.
+//! - Outgoing SOL that is the wallet's only change and was not credited to any other
+//!   account (e.g. rent or priority tips burned by a program) is a `fee`.
+//! - Tokens received without the wallet signing, in a transaction that credited the
+//!   same mint to several owners, are an `airdrop`.
+//! - Everything else is a `transfer_in` or `transfer_out`.
+
+use std::collections::{HashMap, HashSet};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::pumpfun::{self, PumpInstruction};
+use crate::sync::{format_units, TokenDelta, NATIVE_SOL_MINT};
+
+/// Programs whose invocation marks a transaction as a swap
+///
+/// Pump.fun is not listed: launching a token on it is no swap, so its instructions are
+/// decoded by [`crate::pumpfun`] instead.
+pub const SWAP_PROGRAM_IDS: [&str; 6] = [
+    // Jupiter aggregator v6
+    JUPITER_PROGRAM_ID,
+    // Raydium AMM v4
+    "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8",
+    // Raydium concentrated liquidity
+    "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaEMNBHG3Rs2k",
+    // Orca Whirlpools
+    "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc",
+    // Meteora DLMM
+    "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo",
+    // Phoenix order book
+    "PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY",
+];
+
+/// The Jupiter aggregator v6 program, whose swap events describe every hop of a route
+pub const JUPITER_PROGRAM_ID: &str = "JUP6LkbZbjS1jKKwapdHNy74zcZ3tLUZoi5QNyVTaV4";
+
+/// Display names of the programs swaps are made with or routed through
+const PROGRAM_NAMES: [(&str, &str); 11] = [
+    (JUPITER_PROGRAM_ID, "Jupiter"),
+    (
+        "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8",
+        "Raydium AMM",
+    ),
+    (
+        "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaEMNBHG3Rs2k",
+        "Raydium CLMM",
+    ),
+    (
+        "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK",
+        "Raydium CLMM",
+    ),
+    (
+        "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C",
+        "Raydium CPMM",
+    ),
+    (
+        "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc",
+        "Orca Whirlpools",
+    ),
+    (
+        "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo",
+        "Meteora DLMM",
+    ),
+    ("Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB", "Meteora"),
+    (crate::pumpfun::PUMP_FUN_PROGRAM_ID, "Pump.fun"),
+    ("pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA", "PumpSwap"),
+    ("PhoeNiXZ8ByJGLkxNfZRnkUfjvmuYqLR89jjFHGqdXY", "Phoenix"),
+];
+
+/// Prefix of the data of the instructions Anchor programs emit events with
+const EVENT_INSTRUCTION_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+
+/// Discriminator of Jupiter's `SwapEvent`
+const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [64, 198, 205, 232, 38, 8, 113, 226];
+
+/// Minimum number of owners credited with the same mint for a receipt to count as an airdrop
+pub const AIRDROP_MIN_RECIPIENTS: usize = 3;
+
+/// Category of a recorded balance change
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TransactionCategory {
+    /// Tokens received in a swap
+    SwapBuy,
+    /// Tokens sent in a swap
+    SwapSell,
+    /// Tokens received from another account
+    TransferIn,
+    /// Tokens sent to another account
+    TransferOut,
+    /// Tokens received unsolicited as part of a distribution to many wallets
+    Airdrop,
+    /// SOL paid without being credited to another account
+    Fee,
+}
+
+impl TransactionCategory {
+    /// Name of the category as stored and returned by the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::SwapBuy => "swap_buy",
+            Self::SwapSell => "swap_sell",
+            Self::TransferIn => "transfer_in",
+            Self::TransferOut => "transfer_out",
+            Self::Airdrop => "airdrop",
+            Self::Fee => "fee",
+        }
+    }
+
+    /// Category with the given stored name, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::SwapBuy,
+            Self::SwapSell,
+            Self::TransferIn,
+            Self::TransferOut,
+            Self::Airdrop,
+            Self::Fee,
+        ]
+        .into_iter()
+        .find(|category| category.as_str() == name)
+    }
+}
+
+/// What a transaction did beyond the wallet's own balance changes
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TransactionFacts {
+    /// The transaction invoked a swap program or was reported as a swap
+    pub is_swap: bool,
+    /// The wallet signed the transaction
+    pub signer: bool,
+    /// Number of distinct owners whose balance of each mint increased
+    pub recipients: HashMap<String, usize>,
+    /// Some account other than the wallet gained lamports
+    pub sol_credited_elsewhere: bool,
+    /// The swap the wallet made, if it could be decoded
+    pub swap: Option<SwapDetails>,
+}
+
+impl TransactionFacts {
+    /// Reads the facts of a `jsonParsed` transaction from the point of view of `owner`
+    pub fn from_parsed(transaction: &Value, owner: &str) -> Self {
+        let message = &transaction["transaction"]["message"];
+        let meta = &transaction["meta"];
+
+        let inner = meta["innerInstructions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|group| group["instructions"].as_array().into_iter().flatten());
+        let is_swap = message["instructions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .chain(inner)
+            .filter_map(|ix| ix["programId"].as_str())
+            .any(|program| SWAP_PROGRAM_IDS.contains(&program))
+            || pumpfun::instructions(transaction)
+                .iter()
+                .any(PumpInstruction::is_trade);
+
+        let keys = message["accountKeys"].as_array();
+        let owner_index = keys.and_then(|keys| {
+            keys.iter()
+                .position(|k| k["pubkey"].as_str() == Some(owner) || k.as_str() == Some(owner))
+        });
+        let signer = match (keys, owner_index) {
+            (Some(keys), Some(index)) => keys[index]["signer"]
+                .as_bool()
+                // Legacy encodings list the signers first
+                .unwrap_or(index == 0),
+            _ => false,
+        };
+
+        let pre = meta["preBalances"].as_array();
+        let post = meta["postBalances"].as_array();
+        let sol_credited_elsewhere = match (pre, post) {
+            (Some(pre), Some(post)) => pre
+                .iter()
+                .zip(post)
+                .enumerate()
+                .filter(|(index, _)| Some(*index) != owner_index)
+                .any(|(_, (pre, post))| post.as_u64() > pre.as_u64()),
+            _ => false,
+        };
+
+        let mut balances: HashMap<(&str, &str), i128> = HashMap::new();
+        for (key, sign) in [("preTokenBalances", -1), ("postTokenBalances", 1)] {
+            for entry in meta[key].as_array().into_iter().flatten() {
+                let (Some(mint), Some(holder), Some(raw)) = (
+                    entry["mint"].as_str(),
+                    entry["owner"].as_str(),
+                    entry["uiTokenAmount"]["amount"]
+                        .as_str()
+                        .and_then(|a| a.parse::<i128>().ok()),
+                ) else {
+                    continue;
+                };
+                *balances.entry((mint, holder)).or_default() += sign * raw;
+            }
+        }
+
+        let swap = SwapDetails::from_parsed(transaction, owner);
+
+        Self {
+            is_swap: is_swap || swap.is_some(),
+            signer,
+            recipients: count_recipients(
+                balances
+                    .into_iter()
+                    .filter(|(_, raw)| *raw > 0)
+                    .map(|(key, _)| key),
+            ),
+            sol_credited_elsewhere,
+            swap,
+        }
+    }
+}
+
+/// A swap decoded from the instructions of a transaction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct SwapDetails {
+    /// Program the wallet swapped with
+    #[schema(example = "Jupiter")]
+    pub program: String,
+    /// Venues the swap was routed through, in order
+    #[schema(example = json!(["Raydium AMM", "Orca Whirlpools"]))]
+    pub route: Vec<String>,
+    /// Mint of the token sold
+    #[schema(example = "So11111111111111111111111111111111111111112")]
+    pub input_mint: String,
+    /// Amount of the token sold as a decimal string
+    #[schema(example = "0.5")]
+    pub input_amount: String,
+    /// Mint of the token bought
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub output_mint: String,
+    /// Amount of the token bought as a decimal string
+    #[schema(example = "1500000")]
+    pub output_amount: String,
+}
+
+/// One hop of a Jupiter route, as emitted in a `SwapEvent`
+struct SwapEvent {
+    amm: String,
+    input_mint: String,
+    input_amount: u64,
+    output_mint: String,
+    output_amount: u64,
+}
+
+impl SwapEvent {
+    /// Decodes an instruction's base58 data as a swap event
+    fn decode(data: &str) -> Option<Self> {
+        let data = bs58::decode(data).into_vec().ok()?;
+        if data.get(..8)? != EVENT_INSTRUCTION_TAG || data.get(8..16)? != SWAP_EVENT_DISCRIMINATOR {
+            return None;
+        }
+        let key = |offset: usize| Some(bs58::encode(data.get(offset..offset + 32)?).into_string());
+        let amount = |offset: usize| {
+            Some(u64::from_le_bytes(
+                data.get(offset..offset + 8)?.try_into().ok()?,
+            ))
+        };
+
+        Some(Self {
+            amm: key(16)?,
+            input_mint: key(48)?,
+            input_amount: amount(80)?,
+            output_mint: key(88)?,
+            output_amount: amount(120)?,
+        })
+    }
+}
+
+/// A token account listed in a transaction's token balances
+struct TokenAccount<'a> {
+    mint: &'a str,
+    owner: &'a str,
+}
+
+impl SwapDetails {
+    /// Decodes the swap `owner` made in a `jsonParsed` transaction, if it swapped
+    /// through Jupiter or directly on a known DEX
+    pub fn from_parsed(transaction: &Value, owner: &str) -> Option<Self> {
+        let message = &transaction["transaction"]["message"];
+        let meta = &transaction["meta"];
+        let keys = message["accountKeys"].as_array();
+
+        let mut accounts = HashMap::new();
+        let mut decimals = HashMap::new();
+        for entry in ["preTokenBalances", "postTokenBalances"]
+            .into_iter()
+            .flat_map(|key| meta[key].as_array().into_iter().flatten())
+        {
+            let (Some(mint), Some(holder)) = (entry["mint"].as_str(), entry["owner"].as_str())
+            else {
+                continue;
+            };
+            if let Some(places) = entry["uiTokenAmount"]["decimals"].as_u64() {
+                decimals.insert(mint, places as u32);
+            }
+            let address = entry["accountIndex"]
+                .as_u64()
+                .and_then(|index| keys?.get(index as usize))
+                .and_then(|key| key["pubkey"].as_str().or(key.as_str()));
+            if let Some(address) = address {
+                accounts.insert(
+                    address,
+                    TokenAccount {
+                        mint,
+                        owner: holder,
+                    },
+                );
+            }
+        }
+        let decimals = |mint: &str| {
+            if mint == NATIVE_SOL_MINT {
+                Some(9)
+            } else {
+                decimals.get(mint).copied()
+            }
+        };
+
+        let outer: Vec<&Value> = message["instructions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .collect();
+        let groups: Vec<(Option<u64>, Vec<&Value>)> = meta["innerInstructions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|group| {
+                let instructions = group["instructions"].as_array().into_iter().flatten();
+                (group["index"].as_u64(), instructions.collect())
+            })
+            .collect();
+
+        // Jupiter's events do not name the user, who signs the route
+        let signed = keys.into_iter().flatten().any(|key| {
+            key["pubkey"].as_str() == Some(owner) && key["signer"].as_bool() == Some(true)
+        });
+        let all = outer
+            .iter()
+            .copied()
+            .chain(groups.iter().flat_map(|(_, group)| group.iter().copied()));
+        if let Some(swap) = signed
+            .then(|| Self::from_jupiter_events(all, decimals))
+            .flatten()
+        {
+            return Some(swap);
+        }
+
+        // A direct swap moves the tokens in the inner instructions of the DEX instruction,
+        // or of the instruction of whatever program called the DEX
+        groups.iter().find_map(|(index, group)| {
+            let caller = index.and_then(|index| outer.get(index as usize).copied());
+            let program = caller
+                .into_iter()
+                .chain(group.iter().copied())
+                .filter_map(|ix| ix["programId"].as_str())
+                .find(|program| {
+                    *program != JUPITER_PROGRAM_ID && SWAP_PROGRAM_IDS.contains(program)
+                })?;
+            Self::from_transfers(program, group, owner, &accounts, decimals)
+        })
+    }
+
+    /// Decodes a Jupiter route from the swap events among `instructions`, given the
+    /// decimals of each mint
+    ///
+    /// The route sells the first hop's input and buys the last hop's output; hops of a
+    /// split route that sell or buy the same token are added up.
+    pub(crate) fn from_jupiter_events<'a>(
+        instructions: impl IntoIterator<Item = &'a Value>,
+        decimals: impl Fn(&str) -> Option<u32>,
+    ) -> Option<Self> {
+        let events: Vec<SwapEvent> = instructions
+            .into_iter()
+            .filter(|ix| ix["programId"].as_str() == Some(JUPITER_PROGRAM_ID))
+            .filter_map(|ix| SwapEvent::decode(ix["data"].as_str()?))
+            .collect();
+        let (first, last) = (events.first()?, events.last()?);
+
+        let input_amount: u64 = events
+            .iter()
+            .filter(|event| event.input_mint == first.input_mint)
+            .map(|event| event.input_amount)
+            .sum();
+        let output_amount: u64 = events
+            .iter()
+            .filter(|event| event.output_mint == last.output_mint)
+            .map(|event| event.output_amount)
+            .sum();
+        let mut route: Vec<String> = Vec::new();
+        for event in &events {
+            let venue = program_name(&event.amm);
+            if !route.contains(&venue) {
+                route.push(venue);
+            }
+        }
+
+        Some(Self {
+            program: program_name(JUPITER_PROGRAM_ID),
+            route,
+            input_amount: format_units(input_amount as i128, decimals(&first.input_mint)?),
+            input_mint: first.input_mint.clone(),
+            output_amount: format_units(output_amount as i128, decimals(&last.output_mint)?),
+            output_mint: last.output_mint.clone(),
+        })
+    }
+
+    /// Decodes a direct swap on `program` from the token transfers among `instructions`:
+    /// the wallet's transfers out are what it sold, transfers into its token accounts
+    /// what it bought
+    fn from_transfers(
+        program: &str,
+        instructions: &[&Value],
+        owner: &str,
+        accounts: &HashMap<&str, TokenAccount>,
+        decimals: impl Fn(&str) -> Option<u32>,
+    ) -> Option<Self> {
+        let mut sold: Vec<(&str, u64)> = Vec::new();
+        let mut bought: Vec<(&str, u64)> = Vec::new();
+        for ix in instructions {
+            let kind = ix["parsed"]["type"].as_str();
+            if ix["program"].as_str() != Some("spl-token")
+                || !matches!(kind, Some("transfer" | "transferChecked"))
+            {
+                continue;
+            }
+            let info = &ix["parsed"]["info"];
+            let (Some(source), Some(destination)) =
+                (info["source"].as_str(), info["destination"].as_str())
+            else {
+                continue;
+            };
+            let Some(amount) = info["amount"]
+                .as_str()
+                .or(info["tokenAmount"]["amount"].as_str())
+                .and_then(|amount| amount.parse::<u64>().ok())
+            else {
+                continue;
+            };
+            let mint = info["mint"].as_str().or_else(|| {
+                accounts
+                    .get(source)
+                    .or(accounts.get(destination))
+                    .map(|account| account.mint)
+            });
+            let Some(mint) = mint else {
+                continue;
+            };
+
+            let authority = info["authority"]
+                .as_str()
+                .or(info["multisigAuthority"].as_str());
+            if authority == Some(owner) {
+                sold.push((mint, amount));
+            } else if accounts
+                .get(destination)
+                .is_some_and(|account| account.owner == owner)
+            {
+                bought.push((mint, amount));
+            }
+        }
+
+        let input_mint = sold.first()?.0;
+        let output_mint = bought.iter().find(|(mint, _)| *mint != input_mint)?.0;
+        let total = |transfers: &[(&str, u64)], mint: &str| -> u64 {
+            transfers
+                .iter()
+                .filter(|(m, _)| *m == mint)
+                .map(|(_, amount)| amount)
+                .sum()
+        };
+
+        let name = program_name(program);
+        Some(Self {
+            program: name.clone(),
+            route: vec![name],
+            input_mint: input_mint.to_string(),
+            input_amount: format_units(total(&sold, input_mint) as i128, decimals(input_mint)?),
+            output_mint: output_mint.to_string(),
+            output_amount: format_units(
+                total(&bought, output_mint) as i128,
+                decimals(output_mint)?,
+            ),
+        })
+    }
+}
+
+/// Display name of a program, or its address if it is not a known one
+fn program_name(program: &str) -> String {
+    PROGRAM_NAMES
+        .iter()
+        .find(|(id, _)| *id == program)
+        .map_or(program, |(_, name)| name)
+        .to_string()
+}
+
+/// Counts the distinct owners per mint among `(mint, owner)` credits
+pub(crate) fn count_recipients<'a>(
+    credits: impl IntoIterator<Item = (&'a str, &'a str)>,
+) -> HashMap<String, usize> {
+    let mut owners: HashMap<&str, HashSet<&str>> = HashMap::new();
+    for (mint, owner) in credits {
+        owners.entry(mint).or_default().insert(owner);
+    }
+
+    owners
+        .into_iter()
+        .map(|(mint, owners)| (mint.to_string(), owners.len()))
+        .collect()
+}
+
+/// Categorizes one of the wallet's balance changes within a transaction
+///
+/// `deltas` are all of the wallet's changes in the transaction, `delta` included.
+pub fn classify(
+    facts: &TransactionFacts,
+    deltas: &[TokenDelta],
+    delta: &TokenDelta,
+) -> TransactionCategory {
+    let incoming = !delta.amount.is_negative();
+
+    let received_other = deltas
+        .iter()
+        .any(|d| d.mint != delta.mint && !d.amount.is_negative());
+    let sent_other = deltas
+        .iter()
+        .any(|d| d.mint != delta.mint && d.amount.is_negative());
+    let traded = facts.signer && if incoming { sent_other } else { received_other };
+
+    if let Some(swap) = &facts.swap {
+        if !incoming && delta.mint == swap.input_mint {
+            return TransactionCategory::SwapSell;
+        }
+        if incoming && delta.mint == swap.output_mint {
+            return TransactionCategory::SwapBuy;
+        }
+        // SOL spent alongside the swap went to the network fee and account rent
+        if !incoming && delta.mint == NATIVE_SOL_MINT && facts.signer {
+            return TransactionCategory::Fee;
+        }
+    } else if facts.is_swap || traded {
+        return if incoming {
+            TransactionCategory::SwapBuy
+        } else {
+            TransactionCategory::SwapSell
+        };
+    }
+
+    if incoming {
+        let recipients = facts.recipients.get(&delta.mint).copied().unwrap_or(0);
+        if !facts.signer && delta.mint != NATIVE_SOL_MINT && recipients >= AIRDROP_MIN_RECIPIENTS {
+            TransactionCategory::Airdrop
+        } else {
+            TransactionCategory::TransferIn
+        }
+    } else if delta.mint == NATIVE_SOL_MINT
+        && deltas.len() == 1
+        && facts.signer
+        && !facts.sol_credited_elsewhere
+    {
+        TransactionCategory::Fee
+    } else {
+        TransactionCategory::TransferOut
+    }
+}
@@ -0,0 +1,258 @@
+//! Typed HTTP client of the API
+//!
+//! [`DegenClient`] wraps the versioned endpoints in methods taking and returning the
+//! same [`models`](crate::models) types the server uses, so bots and scripts do not
+//! have to build requests and parse responses by hand:
+//!
+//! ```no_run
+//! # async fn run() -> Result<(), degen::client::ClientError> {
+//! use degen::client::DegenClient;
+//! use degen::models::CreateWallet;
+//!
+//! let client = DegenClient::new("http://localhost:3000").with_api_key("dgn_...");
+//! let wallet = client
+//!     .add_wallet(&CreateWallet {
+//!         address: "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU".parse().unwrap(),
+//!         domain: None,
+//!         name: Some("Main".to_string()),
+//!         notes: None,
+//!         metadata: None,
+//!     })
+//!     .await?;
+//! let holdings = client.holdings(wallet.id).await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Only available with the `client` feature.
+
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::analytics::{CostBasisMethod, TradeStats, WalletPnl};
+use crate::handlers::{
+    HoldingsParams, PaginatedTransactions, PaginatedWallets, PnlParams, TransactionListParams,
+    WalletListParams,
+};
+use crate::models::{
+    CreateUser, CreateWallet, CreatedUser, Portfolio, UpdateWallet, Wallet, WalletHoldings,
+};
+use crate::router::ApiVersion;
+use crate::sync::SyncReport;
+use crate::sync_state::SyncStatus;
+
+/// Errors returned by [`DegenClient`]
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The request could not be sent or its response could not be read
+    #[error("Request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The API answered with an error status
+    #[error("API answered {status}: {message}")]
+    Api {
+        /// HTTP status of the response
+        status: StatusCode,
+        /// Error message of the response body
+        message: String,
+        /// Machine-readable error code, if the API gave one
+        code: Option<String>,
+        /// ID of the failed request, to quote when reporting the error
+        request_id: Option<String>,
+    },
+}
+
+/// Error body of failed requests, see [`crate::error::ErrorResponse`]
+#[derive(Debug, Deserialize)]
+struct ErrorBody {
+    error: String,
+    code: Option<String>,
+    request_id: Option<String>,
+}
+
+/// Client of a Degen API server
+#[derive(Debug, Clone)]
+pub struct DegenClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl DegenClient {
+    /// Creates a client of the server at `base_url`, e.g. `http://localhost:3000`
+    pub fn new(base_url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            api_key: None,
+        }
+    }
+
+    /// Authenticates requests with an API key or JWT access token
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = Some(api_key.into());
+        self
+    }
+
+    /// Builds a request to `path` under the current API version
+    fn request(&self, method: Method, path: &str) -> RequestBuilder {
+        let url = format!("{}{}{path}", self.base_url, ApiVersion::V1.prefix());
+        let request = self.http.request(method, url);
+        match &self.api_key {
+            Some(key) => request.bearer_auth(key),
+            None => request,
+        }
+    }
+
+    /// Sends a request, decoding a successful JSON response or turning an error
+    /// response into [`ClientError::Api`]
+    async fn send<T: DeserializeOwned>(request: RequestBuilder) -> Result<T, ClientError> {
+        let response = request.send().await?;
+        let status = response.status();
+        if status.is_success() {
+            return Ok(response.json().await?);
+        }
+
+        let text = response.text().await?;
+        Err(match serde_json::from_str::<ErrorBody>(&text) {
+            Ok(body) => ClientError::Api {
+                status,
+                message: body.error,
+                code: body.code,
+                request_id: body.request_id,
+            },
+            Err(_) => ClientError::Api {
+                status,
+                message: text,
+                code: None,
+                request_id: None,
+            },
+        })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ClientError> {
+        Self::send(self.request(Method::GET, path)).await
+    }
+
+    async fn get_with<T: DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &impl Serialize,
+    ) -> Result<T, ClientError> {
+        Self::send(self.request(Method::GET, path).query(query)).await
+    }
+
+    /// Creates a user, whose API key is only returned here
+    pub async fn create_user(&self, user: &CreateUser) -> Result<CreatedUser, ClientError> {
+        Self::send(self.request(Method::POST, "/users").json(user)).await
+    }
+
+    /// Starts tracking a wallet
+    pub async fn add_wallet(&self, wallet: &CreateWallet) -> Result<Wallet, ClientError> {
+        Self::send(self.request(Method::POST, "/wallets").json(wallet)).await
+    }
+
+    /// Fetches a wallet by ID
+    pub async fn wallet(&self, wallet_id: Uuid) -> Result<Wallet, ClientError> {
+        self.get(&format!("/wallets/{wallet_id}")).await
+    }
+
+    /// Fetches a wallet by its address
+    pub async fn wallet_by_address(&self, address: &str) -> Result<Wallet, ClientError> {
+        self.get(&format!("/wallets/by-address/{address}")).await
+    }
+
+    /// Changes a wallet's name, notes, metadata or leaderboard opt-out
+    pub async fn update_wallet(
+        &self,
+        wallet_id: Uuid,
+        changes: &UpdateWallet,
+    ) -> Result<Wallet, ClientError> {
+        Self::send(
+            self.request(Method::PATCH, &format!("/wallets/{wallet_id}"))
+                .json(changes),
+        )
+        .await
+    }
+
+    /// Lists a page of the user's wallets
+    pub async fn list_wallets(
+        &self,
+        params: &WalletListParams,
+    ) -> Result<PaginatedWallets, ClientError> {
+        self.get_with("/wallets", params).await
+    }
+
+    /// Lists a page of a wallet's transactions, newest first
+    pub async fn transactions(
+        &self,
+        wallet_id: Uuid,
+        params: &TransactionListParams,
+    ) -> Result<PaginatedTransactions, ClientError> {
+        self.get_with(&format!("/wallets/{wallet_id}/transactions"), params)
+            .await
+    }
+
+    /// Syncs a wallet's transactions from the Solana RPC
+    pub async fn sync_wallet(&self, wallet_id: Uuid) -> Result<SyncReport, ClientError> {
+        Self::send(self.request(Method::POST, &format!("/wallets/{wallet_id}/sync"))).await
+    }
+
+    /// Progress and outcome of a wallet's syncs
+    pub async fn sync_status(&self, wallet_id: Uuid) -> Result<SyncStatus, ClientError> {
+        self.get(&format!("/wallets/{wallet_id}/sync-status")).await
+    }
+
+    /// A wallet's holdings valued at current prices, without spam tokens
+    pub async fn holdings(&self, wallet_id: Uuid) -> Result<WalletHoldings, ClientError> {
+        self.holdings_with(wallet_id, &HoldingsParams::default())
+            .await
+    }
+
+    /// A wallet's holdings valued at current prices
+    pub async fn holdings_with(
+        &self,
+        wallet_id: Uuid,
+        params: &HoldingsParams,
+    ) -> Result<WalletHoldings, ClientError> {
+        self.get_with(&format!("/wallets/{wallet_id}/holdings"), params)
+            .await
+    }
+
+    /// A wallet's profit and loss using the given cost-basis method
+    pub async fn pnl(
+        &self,
+        wallet_id: Uuid,
+        method: CostBasisMethod,
+    ) -> Result<WalletPnl, ClientError> {
+        self.get_with(&format!("/wallets/{wallet_id}/pnl"), &PnlParams { method })
+            .await
+    }
+
+    /// A wallet's trade statistics using the given cost-basis method
+    pub async fn trade_stats(
+        &self,
+        wallet_id: Uuid,
+        method: CostBasisMethod,
+    ) -> Result<TradeStats, ClientError> {
+        self.get_with(
+            &format!("/wallets/{wallet_id}/stats"),
+            &PnlParams { method },
+        )
+        .await
+    }
+
+    /// Holdings of all the user's wallets combined
+    pub async fn portfolio(&self) -> Result<Portfolio, ClientError> {
+        self.get("/portfolio").await
+    }
+}
@@ -0,0 +1,179 @@
+//! Client IP addresses of requests behind reverse proxies.
+//!
+//! The address a request came from is its connection's peer, unless the peer is one
+//! of the configured trusted proxies (`TRUSTED_PROXIES`). Requests relayed by a trusted
+//! proxy are attributed to the address it appended to the standard `Forwarded` header,
+//! or to `X-Forwarded-For` if there is none. The list is read from the right, skipping
+//! further trusted proxies, so entries a client made up are never believed. Without
+//! trusted proxies both headers are ignored.
+//!
+//! [`client_ip_middleware`] resolves the address once per request, for the rate
+//! limiter, the admin allowlist, request logs and handlers taking [`ClientIp`].
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{ConnectInfo, FromRequestParts, State},
+    http::{request::Parts, HeaderMap, Request},
+    middleware::Next,
+    response::Response,
+};
+use ipnet::IpNet;
+
+use crate::AppError;
+
+/// Standard header describing the proxies a request passed through (RFC 7239)
+pub const FORWARDED: &str = "forwarded";
+
+/// Header listing the addresses a request was forwarded for, client first
+pub const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Address of the client that made a request
+///
+/// Taken as an extractor, rejects requests with `400` if the address is unknown, as
+/// for requests over a Unix socket; take `Option<ClientIp>` to accept those.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ClientIp(pub IpAddr);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ClientIp {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, AppError> {
+        parts
+            .extensions
+            .get::<ClientIp>()
+            .copied()
+            .ok_or_else(|| AppError::BadRequest("The client address is unknown".to_string()))
+    }
+}
+
+/// Parses a network in CIDR notation, or a single address as a network of its own
+pub fn parse_network(network: &str) -> Option<IpNet> {
+    let network = network.trim();
+    network
+        .parse()
+        .ok()
+        .or_else(|| network.parse::<IpAddr>().ok().map(IpNet::from))
+}
+
+/// Whether `ip` is in any of `networks`
+pub fn is_listed(ip: IpAddr, networks: &[IpNet]) -> bool {
+    let ip = canonical(ip);
+    networks.iter().any(|network| network.contains(&ip))
+}
+
+/// Maps IPv4-mapped IPv6 addresses, as dual-stack listeners report IPv4 peers, to IPv4
+fn canonical(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+/// Parses the node of a `for=` parameter of `Forwarded`: an address, optionally
+/// quoted, with a port or IPv6 brackets
+///
+/// Obfuscated identifiers and `unknown` give `None`.
+fn parse_forwarded_node(node: &str) -> Option<IpAddr> {
+    let node = node.trim().trim_matches('"');
+    if let Ok(ip) = node.parse() {
+        return Some(ip);
+    }
+    if let Ok(addr) = node.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    node.strip_prefix('[')
+        .and_then(|node| node.strip_suffix(']'))
+        .and_then(|node| node.parse().ok())
+}
+
+/// `for=` nodes of the `Forwarded` headers, client first, or `None` without any
+///
+/// Elements without `for=` are taken as unknown nodes, which end the chain.
+fn forwarded_for(headers: &HeaderMap) -> Option<Vec<Option<IpAddr>>> {
+    let values: Vec<&str> = headers
+        .get_all(FORWARDED)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .collect();
+    if values.is_empty() {
+        return None;
+    }
+
+    Some(
+        values
+            .into_iter()
+            .flat_map(|value| value.split(','))
+            .map(|element| {
+                element
+                    .split(';')
+                    .filter_map(|pair| pair.split_once('='))
+                    .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                    .and_then(|(_, node)| parse_forwarded_node(node))
+            })
+            .collect(),
+    )
+}
+
+/// Addresses of `X-Forwarded-For`, client first
+fn x_forwarded_for(headers: &HeaderMap) -> Vec<Option<IpAddr>> {
+    headers
+        .get_all(X_FORWARDED_FOR)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|hop| hop.trim().parse().ok())
+        .collect()
+}
+
+/// Address of the client of a request whose connection came from `peer`
+///
+/// Returns `None` if the peer is unknown, as for requests over a Unix socket.
+pub fn resolve(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[IpNet],
+) -> Option<IpAddr> {
+    let mut client = canonical(peer?);
+    if !is_listed(client, trusted_proxies) {
+        return Some(client);
+    }
+
+    let hops = forwarded_for(headers).unwrap_or_else(|| x_forwarded_for(headers));
+    for hop in hops.into_iter().rev() {
+        // A malformed or hidden entry ends the chain: what is left of it cannot be
+        // trusted
+        let Some(hop) = hop else {
+            break;
+        };
+        client = canonical(hop);
+        if !is_listed(client, trusted_proxies) {
+            break;
+        }
+    }
+
+    Some(client)
+}
+
+/// Middleware resolving the [`ClientIp`] of every request behind `trusted_proxies`
+pub async fn client_ip_middleware<B>(
+    State(trusted_proxies): State<Arc<Vec<IpNet>>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(peer)| peer.ip());
+    if let Some(ip) = resolve(peer, request.headers(), &trusted_proxies) {
+        request.extensions_mut().insert(ClientIp(ip));
+    }
+
+    next.run(request).await
+}
@@ -0,0 +1,98 @@
+use axum::{
+    body::{boxed, Full},
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::internal_error;
+use crate::AppError;
+
+/// Format of HTTP dates, e.g. `Sat, 19 Jul 2025 17:00:00 GMT`
+const HTTP_DATE_FORMAT: &str = "%a, %d %b %Y %H:%M:%S GMT";
+
+/// Formats a time as an HTTP date
+pub fn http_date(time: DateTime<Utc>) -> String {
+    time.format(HTTP_DATE_FORMAT).to_string()
+}
+
+/// Parses an HTTP date, ignoring malformed ones
+fn parse_http_date(value: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(value.trim())
+        .ok()
+        .map(|time| time.with_timezone(&Utc))
+}
+
+/// Strong entity tag of a response body
+fn etag(body: &[u8]) -> String {
+    // 128 bits are plenty to tell versions of one resource apart
+    format!("\"{}\"", &hex::encode(Sha256::digest(body))[..32])
+}
+
+/// Whether the client's cached copy, described by its conditional headers, is current
+///
+/// `If-None-Match` takes precedence over `If-Modified-Since`, as in RFC 9110.
+fn not_modified(
+    request_headers: &HeaderMap,
+    etag: &str,
+    last_modified: Option<DateTime<Utc>>,
+) -> bool {
+    if let Some(if_none_match) = request_headers.get(header::IF_NONE_MATCH) {
+        let Ok(if_none_match) = if_none_match.to_str() else {
+            return false;
+        };
+        return if_none_match.split(',').map(str::trim).any(|tag| {
+            // Weak comparison: W/"x" matches "x"
+            tag == "*" || tag.strip_prefix("W/").unwrap_or(tag) == etag
+        });
+    }
+
+    let since = request_headers
+        .get(header::IF_MODIFIED_SINCE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(parse_http_date);
+    match (since, last_modified) {
+        // HTTP dates have second precision
+        (Some(since), Some(modified)) => modified.timestamp() <= since.timestamp(),
+        _ => false,
+    }
+}
+
+/// Responds with `value` as JSON carrying `ETag` and `Last-Modified`, or with
+/// `304 Not Modified` if the client's conditional headers show its copy is current
+///
+/// The `ETag` is a hash of the serialized body, so it changes with any field.
+pub fn conditional_json<T: Serialize>(
+    request_headers: &HeaderMap,
+    value: &T,
+    last_modified: Option<DateTime<Utc>>,
+) -> Result<Response, AppError> {
+    let body = serde_json::to_vec(value).map_err(internal_error)?;
+    let etag = etag(&body);
+
+    let not_modified = not_modified(request_headers, &etag, last_modified);
+    let mut response = Response::builder()
+        .status(if not_modified {
+            StatusCode::NOT_MODIFIED
+        } else {
+            StatusCode::OK
+        })
+        .header(header::ETAG, &etag);
+    if let Some(last_modified) = last_modified {
+        response = response.header(header::LAST_MODIFIED, http_date(last_modified));
+    }
+
+    let response = if not_modified {
+        response.body(boxed(Full::default()))
+    } else {
+        response
+            .header(
+                header::CONTENT_TYPE,
+                HeaderValue::from_static("application/json"),
+            )
+            .body(boxed(Full::from(body)))
+    };
+    response.map_err(internal_error)
+}
@@ -0,0 +1,134 @@
+//! Connecting to the database at startup.
+//!
+//! In container deployments the API often starts before Postgres accepts connections,
+//! so connecting is retried with exponential backoff instead of failing on the first
+//! refused connection. Invalid configuration, such as a malformed `DATABASE_URL`,
+//! fails at once.
+
+use std::env;
+use std::time::Duration;
+
+use sqlx::migrate::MigrateError;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::secrets::SecretError;
+
+/// Connection attempts made before giving up, unless configured otherwise
+pub const DEFAULT_CONNECT_ATTEMPTS: u32 = 10;
+
+/// Delay before the second connection attempt
+const BASE_DELAY: Duration = Duration::from_millis(500);
+
+/// Longest delay between two connection attempts
+const MAX_DELAY: Duration = Duration::from_secs(30);
+
+/// Errors that can occur while setting up the database and application state at startup
+#[derive(Debug, Error)]
+pub enum StartupError {
+    /// `DATABASE_URL` is not set
+    #[error("DATABASE_URL must be set")]
+    MissingDatabaseUrl,
+
+    /// The database could not be connected to
+    #[error("Failed to connect to database after {attempts} attempts: {source}")]
+    Connect {
+        /// Attempts made
+        attempts: u32,
+        /// Why the last attempt failed
+        #[source]
+        source: sqlx::Error,
+    },
+
+    /// The migrations could not be applied
+    #[error("Failed to run migrations: {0}")]
+    Migrate(#[from] MigrateError),
+
+    /// The secrets master key is malformed or cannot be read
+    #[error(transparent)]
+    Secrets(#[from] SecretError),
+}
+
+/// The database URL from `DATABASE_URL`
+pub fn database_url() -> Result<String, StartupError> {
+    env::var("DATABASE_URL").map_err(|_| StartupError::MissingDatabaseUrl)
+}
+
+/// Delay before the attempt following `failures` failed ones, doubling with each
+pub fn retry_delay(failures: u32) -> Duration {
+    BASE_DELAY
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_DELAY)
+}
+
+/// Opens a pool with `options`, retrying failed attempts with exponential backoff
+///
+/// Makes at most `attempts` attempts, or retries until the database is reachable if
+/// `attempts` is `0`.
+pub async fn connect(
+    options: PgPoolOptions,
+    url: &str,
+    attempts: u32,
+) -> Result<PgPool, StartupError> {
+    let mut failures = 0;
+    loop {
+        match options.clone().connect(url).await {
+            Ok(pool) => return Ok(pool),
+            // Retrying cannot fix the URL
+            Err(source @ sqlx::Error::Configuration(_)) => {
+                return Err(StartupError::Connect {
+                    attempts: failures + 1,
+                    source,
+                })
+            }
+            Err(source) => {
+                failures += 1;
+                if attempts > 0 && failures >= attempts {
+                    return Err(StartupError::Connect {
+                        attempts: failures,
+                        source,
+                    });
+                }
+                let delay = retry_delay(failures);
+                warn!(
+                    "Database not reachable yet (attempt {}): {}; retrying in {:?}",
+                    failures, source, delay
+                );
+                tokio::time::sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// Applies the pending migrations
+pub async fn migrate(pool: &PgPool) -> Result<(), StartupError> {
+    sqlx::migrate!().run(pool).await?;
+    Ok(())
+}
+
+/// Opens the SQLite database at `url`, creating the file if it does not exist, and
+/// applies the SQLite migrations
+#[cfg(feature = "sqlite")]
+pub async fn connect_sqlite(url: &str) -> Result<sqlx::SqlitePool, StartupError> {
+    use std::str::FromStr;
+
+    use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
+
+    let connect = |source| StartupError::Connect {
+        attempts: 1,
+        source,
+    };
+    let options = SqliteConnectOptions::from_str(url)
+        .map_err(connect)?
+        .create_if_missing(true)
+        .foreign_keys(true);
+    let pool = SqlitePoolOptions::new()
+        .connect_with(options)
+        .await
+        .map_err(connect)?;
+
+    sqlx::migrate!("./migrations_sqlite").run(&pool).await?;
+    Ok(pool)
+}
@@ -0,0 +1,154 @@
+//! Dead-letter queue of failed deliveries.
+//!
+//! A webhook delivery or notification whose job runs out of attempts is recorded in the
+//! `dead_letters` table instead of being forgotten among failed jobs. Operators list the
+//! entries through `GET /admin/dlq` and replay one with `POST /admin/dlq/:id/retry`,
+//! which resets the delivery and queues it again with fresh attempts.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::AppError;
+use crate::jobs::{self, Job};
+use crate::pagination::Cursor;
+
+/// A delivery that ran out of attempts
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct DeadLetter {
+    /// Unique identifier of the entry
+    pub id: Uuid,
+    /// `webhook` or `notification`
+    pub kind: String,
+    /// ID of the webhook delivery or notification
+    pub reference_id: Uuid,
+    /// The job as queued
+    #[schema(value_type = Object)]
+    pub job: Value,
+    /// Number of attempts made
+    pub attempts: i32,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+    /// When the delivery was given up on
+    pub failed_at: DateTime<Utc>,
+}
+
+/// Kind and delivery ID of a job whose failures are dead-lettered
+fn target(job: &Job) -> Option<(&'static str, Uuid)> {
+    match *job {
+        Job::DeliverWebhook { delivery_id } => Some(("webhook", delivery_id)),
+        Job::SendNotification { notification_id } => Some(("notification", notification_id)),
+        _ => None,
+    }
+}
+
+/// Records a job that ran out of attempts, if it is a delivery
+///
+/// Returns whether an entry was recorded.
+pub async fn record(
+    pool: &PgPool,
+    job: &Job,
+    attempts: i32,
+    last_error: &str,
+) -> Result<bool, sqlx::Error> {
+    let Some((kind, reference_id)) = target(job) else {
+        return Ok(false);
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO dead_letters (id, kind, reference_id, job, attempts, last_error)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(kind)
+    .bind(reference_id)
+    .bind(sqlx::types::Json(job))
+    .bind(attempts)
+    .bind(last_error)
+    .execute(pool)
+    .await?;
+
+    Ok(true)
+}
+
+/// Entries not retried yet, most recently failed first
+///
+/// Fetches up to `limit` entries after `cursor`, built from `failed_at` and `id`.
+pub async fn list(
+    pool: &PgPool,
+    cursor: Option<Cursor>,
+    limit: i64,
+) -> Result<Vec<DeadLetter>, sqlx::Error> {
+    sqlx::query_as::<_, DeadLetter>(
+        r#"
+        SELECT id, kind, reference_id, job, attempts, last_error, failed_at
+        FROM dead_letters
+        WHERE retried_at IS NULL
+          AND ($1::TIMESTAMPTZ IS NULL OR (failed_at, id) < ($1, $2))
+        ORDER BY failed_at DESC, id DESC
+        LIMIT $3
+        "#,
+    )
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.id))
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Replays an entry: resets its delivery to pending and queues the job again
+///
+/// Returns the entry; fails with `NotFound` if there is no such entry or it was
+/// already retried, and with `Conflict`, leaving the entry queued, if its delivery is
+/// gone or no longer failed.
+pub async fn retry(pool: &PgPool, id: Uuid) -> Result<DeadLetter, AppError> {
+    let mut tx = pool.begin().await?;
+    let entry = sqlx::query_as::<_, DeadLetter>(
+        r#"
+        UPDATE dead_letters
+        SET retried_at = NOW()
+        WHERE id = $1 AND retried_at IS NULL
+        RETURNING id, kind, reference_id, job, attempts, last_error, failed_at
+        "#,
+    )
+    .bind(id)
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(entry) = entry else {
+        return Err(AppError::NotFound(format!(
+            "Dead letter with ID {id} not found"
+        )));
+    };
+    let job: Job = serde_json::from_value(entry.job.clone())
+        .map_err(|err| sqlx::Error::Decode(Box::new(err)))?;
+
+    let reset = match entry.kind.as_str() {
+        "webhook" => {
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = NOW()
+            WHERE id = $1 AND status = 'failed'
+            "#
+        }
+        _ => "UPDATE notifications SET status = 'pending', attempts = 0 WHERE id = $1 AND status = 'failed'",
+    };
+    let reset = sqlx::query(reset)
+        .bind(entry.reference_id)
+        .execute(&mut *tx)
+        .await?;
+    if reset.rows_affected() == 0 {
+        return Err(AppError::Conflict(format!(
+            "The {} {} of dead letter {id} is gone or no longer failed",
+            entry.kind, entry.reference_id
+        )));
+    }
+    jobs::enqueue(&mut *tx, &job).await?;
+    tx.commit().await?;
+
+    Ok(entry)
+}
@@ -0,0 +1,207 @@
+//! Solana Name Service (`.sol`) domains of wallets.
+//!
+//! Wallets can be added by domain, which is resolved to the address it points at, and
+//! wallets added by address are looked up in reverse for their owner's primary domain.
+//! Either way the domain is stored on the wallet and refreshed when it is synced, as
+//! domains can be transferred or repointed at any time.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::warn;
+
+use crate::models::Wallet;
+use crate::AppError;
+
+/// Top-level domain of the Solana Name Service
+pub const SOL_TLD: &str = ".sol";
+
+/// Errors that can occur while resolving domains
+#[derive(Debug, Error)]
+pub enum DomainError {
+    /// The HTTP request to the SNS API failed
+    #[error("Domain request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The SNS API returned an unexpected response
+    #[error("Invalid domain response: {0}")]
+    InvalidResponse(String),
+}
+
+impl From<DomainError> for AppError {
+    fn from(err: DomainError) -> Self {
+        match err {
+            DomainError::Http(err) => err.into(),
+            other => AppError::UpstreamError(other.to_string()),
+        }
+    }
+}
+
+/// Normalizes `input` to a lowercase `.sol` domain, or `None` if it is not one
+pub fn parse_domain(input: &str) -> Option<String> {
+    let domain = input.trim().to_lowercase();
+    let name = domain.strip_suffix(SOL_TLD)?;
+    let valid = !name.is_empty()
+        && name
+            .split('.')
+            .all(|label| !label.is_empty() && !label.contains(['/', '?', '#', '%']))
+        && !name.chars().any(char::is_whitespace);
+
+    valid.then_some(domain)
+}
+
+/// A resolver of `.sol` domains
+#[async_trait]
+pub trait DomainResolver: Send + Sync {
+    /// Address a domain points at, or `None` if it is not registered
+    async fn resolve(&self, domain: &str) -> Result<Option<String>, DomainError>;
+
+    /// Primary domain of an address, or `None` if it has not set one
+    async fn reverse(&self, address: &str) -> Result<Option<String>, DomainError>;
+}
+
+/// Resolver backed by Bonfida's SNS API proxy
+#[derive(Debug, Clone)]
+pub struct SnsResolver {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl SnsResolver {
+    /// Creates a resolver for the SNS API at `url`
+    pub fn new(url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            url: url.into().trim_end_matches('/').to_string(),
+        }
+    }
+
+    /// Fetches `path`, returning its result or `None` if the API reports an error,
+    /// which is how it answers for unknown domains and addresses
+    async fn get(&self, path: &str) -> Result<Option<Value>, DomainError> {
+        let response: Value = self
+            .http
+            .get(format!("{}/{path}", self.url))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        match response["s"].as_str() {
+            Some("ok") => Ok(Some(response["result"].clone())),
+            Some("error") => Ok(None),
+            _ => Err(DomainError::InvalidResponse(response.to_string())),
+        }
+    }
+}
+
+#[async_trait]
+impl DomainResolver for SnsResolver {
+    async fn resolve(&self, domain: &str) -> Result<Option<String>, DomainError> {
+        let name = domain.strip_suffix(SOL_TLD).unwrap_or(domain);
+        let result = self.get(&format!("resolve/{name}")).await?;
+
+        Ok(result.and_then(|address| address.as_str().map(str::to_string)))
+    }
+
+    async fn reverse(&self, address: &str) -> Result<Option<String>, DomainError> {
+        let result = self.get(&format!("favorite-domain/{address}")).await?;
+
+        Ok(result
+            .and_then(|favorite| favorite["reverse"].as_str().map(str::to_string))
+            .and_then(|name| parse_domain(&format!("{name}{SOL_TLD}"))))
+    }
+}
+
+/// Resolver knowing a fixed set of domains, for tests and offline use
+#[derive(Debug, Clone, Default)]
+pub struct StaticDomainResolver {
+    domains: HashMap<String, String>,
+}
+
+impl StaticDomainResolver {
+    /// Creates a resolver where each domain points at its address and is the primary
+    /// domain of that address
+    pub fn new<D: Into<String>, A: Into<String>>(
+        domains: impl IntoIterator<Item = (D, A)>,
+    ) -> Self {
+        Self {
+            domains: domains
+                .into_iter()
+                .map(|(domain, address)| (domain.into(), address.into()))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl DomainResolver for StaticDomainResolver {
+    async fn resolve(&self, domain: &str) -> Result<Option<String>, DomainError> {
+        Ok(self.domains.get(domain).cloned())
+    }
+
+    async fn reverse(&self, address: &str) -> Result<Option<String>, DomainError> {
+        let mut domains: Vec<&String> = self
+            .domains
+            .iter()
+            .filter(|(_, owner)| *owner == address)
+            .map(|(domain, _)| domain)
+            .collect();
+        domains.sort();
+
+        Ok(domains.first().map(|domain| domain.to_string()))
+    }
+}
+
+/// Current domain of `wallet`: its stored domain while it still points at the wallet,
+/// else the address' primary domain
+pub async fn current_domain(
+    resolver: &dyn DomainResolver,
+    wallet: &Wallet,
+) -> Result<Option<String>, DomainError> {
+    if let Some(domain) = &wallet.domain {
+        if resolver.resolve(domain).await?.as_deref() == Some(wallet.address.as_str()) {
+            return Ok(Some(domain.clone()));
+        }
+    }
+
+    resolver.reverse(&wallet.address).await
+}
+
+/// Refreshes the domain stored on `wallet`
+///
+/// Domains are cosmetic, so a failing resolver is logged and the stored domain kept.
+pub async fn refresh(
+    pool: &PgPool,
+    resolver: &dyn DomainResolver,
+    wallet: &Wallet,
+) -> Result<(), sqlx::Error> {
+    let domain = match current_domain(resolver, wallet).await {
+        Ok(domain) => domain,
+        Err(err) => {
+            warn!("Domain lookup of wallet {} failed: {}", wallet.id, err);
+            return Ok(());
+        }
+    };
+    if domain == wallet.domain {
+        return Ok(());
+    }
+
+    sqlx::query("UPDATE wallets SET domain = $2 WHERE id = $1")
+        .bind(wallet.id)
+        .bind(domain)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
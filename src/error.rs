@@ -1,13 +1,29 @@
 use axum::{
+    extract::rejection::{JsonRejection, QueryRejection},
     http::StatusCode,
     response::{IntoResponse, Response},
     Json,
 };
 use serde::Serialize;
+use std::collections::BTreeMap;
 use std::fmt;
 use thiserror::Error;
 use tracing::{error, instrument};
 
+use crate::request_id;
+
+/// Unique constraint on `(wallet_id, transaction_hash, token_address)`
+///
+/// Ingestion upserts against it, so only inserts that bypass the ingestion path can
+/// violate it; those are reported as `409 Conflict`, never as a server error.
+pub const TRANSACTION_UNIQUE_CONSTRAINT: &str = "transactions_wallet_hash_token_key";
+
+/// Unique constraint on `(user_id, name)` of `wallet_groups`
+pub const GROUP_NAME_UNIQUE_CONSTRAINT: &str = "wallet_groups_user_name_key";
+
+/// Unique constraint on `stripe_price_id` of `plans`
+pub const PLAN_PRICE_UNIQUE_CONSTRAINT: &str = "plans_stripe_price_id_key";
+
 /// A set of errors that can occur during request handling
 #[derive(Debug, Error)]
 pub enum AppError {
@@ -15,6 +31,18 @@ pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    /// Return `401 Unauthorized`
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+
+    /// Return `403 Forbidden` when the caller's role does not permit the request
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    /// Return `403 Forbidden` when the user's plan allows no more tracked wallets
+    #[error("Wallet quota exceeded: {0}")]
+    WalletQuotaExceeded(String),
+
     /// Return `404 Not Found`
     #[error("Not found: {0}")]
     NotFound(String),
@@ -23,19 +51,99 @@ pub enum AppError {
     #[error("Conflict: {0}")]
     Conflict(String),
 
+    /// Return `413 Payload Too Large`
+    #[error("Payload too large: {0}")]
+    PayloadTooLarge(String),
+
     /// Return `422 Unprocessable Entity`
     #[error("Unprocessable entity: {0}")]
     UnprocessableEntity(String),
 
+    /// Return `422 Unprocessable Entity` listing the problems of each invalid field
+    #[error("Validation failed: {0}")]
+    Validation(ValidationErrors),
+
+    /// Return `429 Too Many Requests`
+    #[error("Too many requests: {0}")]
+    TooManyRequests(String),
+
+    /// Return `429 Too Many Requests` when the user used up today's syncs
+    #[error("Sync quota exceeded: {0}")]
+    SyncQuotaExceeded(String),
+
     /// Return `500 Internal Server Error`
     #[error("Internal server error: {0}")]
     InternalServerError(String),
 
+    /// Return `502 Bad Gateway` when an upstream service fails or answers with an error
+    #[error("Upstream error: {0}")]
+    UpstreamError(String),
+
     /// Return `503 Service Unavailable`
     #[error("Service unavailable: {0}")]
     ServiceUnavailable(String),
+
+    /// Return `504 Gateway Timeout` when an upstream service does not answer in time
+    #[error("Timeout: {0}")]
+    Timeout(String),
 }
 
+/// Problems with the fields of a request payload, keyed by field name
+///
+/// Serialized as an object mapping each invalid field to its messages, e.g.
+/// `{"address": ["Address is too long (max 44 characters)"]}`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors(BTreeMap<String, Vec<String>>);
+
+impl ValidationErrors {
+    /// Creates an empty set of errors
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a problem with `field`
+    pub fn add(&mut self, field: &str, message: impl Into<String>) {
+        self.0
+            .entry(field.to_string())
+            .or_default()
+            .push(message.into());
+    }
+
+    /// Whether no problem was recorded
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Messages recorded for `field`, empty if it is valid
+    pub fn field(&self, field: &str) -> &[String] {
+        self.0.get(field).map(Vec::as_slice).unwrap_or_default()
+    }
+
+    /// `Ok` if no problem was recorded, otherwise the errors themselves
+    pub fn into_result(self) -> Result<(), Self> {
+        if self.is_empty() {
+            Ok(())
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (index, (field, messages)) in self.0.iter().enumerate() {
+            if index > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{field}: {}", messages.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
 /// Error response payload
 #[derive(Serialize)]
 pub struct ErrorResponse {
@@ -47,6 +155,12 @@ pub struct ErrorResponse {
     /// Optional additional details
     #[serde(skip_serializing_if = "Option::is_none")]
     pub details: Option<String>,
+    /// Problems of each invalid field, for validation errors
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub errors: Option<ValidationErrors>,
+    /// ID of the failed request, to quote when reporting the error
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
 }
 
 impl AppError {
@@ -54,11 +168,17 @@ impl AppError {
     pub fn status_code(&self) -> StatusCode {
         match self {
             Self::BadRequest(_) => StatusCode::BAD_REQUEST,
+            Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) | Self::WalletQuotaExceeded(_) => StatusCode::FORBIDDEN,
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::Conflict(_) => StatusCode::CONFLICT,
-            Self::UnprocessableEntity(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            Self::UnprocessableEntity(_) | Self::Validation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            Self::TooManyRequests(_) | Self::SyncQuotaExceeded(_) => StatusCode::TOO_MANY_REQUESTS,
             Self::InternalServerError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             Self::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            Self::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
         }
     }
 
@@ -66,11 +186,20 @@ impl AppError {
     pub fn code(&self) -> &'static str {
         match self {
             Self::BadRequest(_) => "bad_request",
+            Self::Unauthorized(_) => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::WalletQuotaExceeded(_) => "wallet_quota_exceeded",
             Self::NotFound(_) => "not_found",
             Self::Conflict(_) => "conflict",
+            Self::PayloadTooLarge(_) => "payload_too_large",
             Self::UnprocessableEntity(_) => "unprocessable_entity",
+            Self::Validation(_) => "validation_failed",
+            Self::TooManyRequests(_) => "too_many_requests",
+            Self::SyncQuotaExceeded(_) => "sync_quota_exceeded",
             Self::InternalServerError(_) => "internal_server_error",
+            Self::UpstreamError(_) => "upstream_error",
             Self::ServiceUnavailable(_) => "service_unavailable",
+            Self::Timeout(_) => "timeout",
         }
     }
 }
@@ -83,6 +212,7 @@ impl IntoResponse for AppError {
         let status = self.status_code();
         let code = self.code();
         let message = self.to_string();
+        let request_id = request_id::current();
 
         // Log internal server errors
         if status.is_server_error() {
@@ -94,10 +224,17 @@ impl IntoResponse for AppError {
             );
         }
 
+        let errors = match self {
+            Self::Validation(errors) => Some(errors),
+            _ => None,
+        };
+
         let body = Json(ErrorResponse {
             error: message,
             code: Some(code),
             details: None,
+            errors,
+            request_id,
         });
 
         (status, body).into_response()
@@ -110,8 +247,18 @@ impl From<sqlx::Error> for AppError {
         match err {
             sqlx::Error::Database(db_err) => {
                 // Handle unique constraint violations
-                if db_err.code().map(|c| c == "23505").unwrap_or(false) {
-                    return Self::Conflict("A record with these values already exists".to_string());
+                if db_err.is_unique_violation() {
+                    let message = match db_err.constraint() {
+                        Some(TRANSACTION_UNIQUE_CONSTRAINT) => {
+                            "Transaction already recorded for this wallet and token"
+                        }
+                        Some(GROUP_NAME_UNIQUE_CONSTRAINT) => "Group with this name already exists",
+                        Some(PLAN_PRICE_UNIQUE_CONSTRAINT) => {
+                            "Another plan is already sold at this Stripe price"
+                        }
+                        _ => "A record with these values already exists",
+                    };
+                    return Self::Conflict(message.to_string());
                 }
 
                 // Handle foreign key violations
@@ -129,15 +276,57 @@ impl From<sqlx::Error> for AppError {
     }
 }
 
+// Convert per-field validation failures to AppError
+impl From<ValidationErrors> for AppError {
+    fn from(errors: ValidationErrors) -> Self {
+        Self::Validation(errors)
+    }
+}
+
+// Convert failed requests to upstream services (RPC, price and metadata APIs) to AppError
+impl From<reqwest::Error> for AppError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            Self::Timeout(format!("Upstream request timed out: {err}"))
+        } else {
+            Self::UpstreamError(format!("Upstream request failed: {err}"))
+        }
+    }
+}
+
+// Convert JSON body extraction failures to AppError so they use the standard error payload
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        match rejection {
+            JsonRejection::JsonDataError(err) => Self::UnprocessableEntity(err.body_text()),
+            JsonRejection::JsonSyntaxError(err) => Self::BadRequest(err.body_text()),
+            JsonRejection::MissingJsonContentType(err) => Self::BadRequest(err.body_text()),
+            other => (other.status(), other.body_text()).into(),
+        }
+    }
+}
+
+// Convert query string extraction failures to AppError
+impl From<QueryRejection> for AppError {
+    fn from(rejection: QueryRejection) -> Self {
+        Self::BadRequest(rejection.body_text())
+    }
+}
+
 // Convert (StatusCode, String) to AppError
 impl From<(StatusCode, String)> for AppError {
     fn from((status, message): (StatusCode, String)) -> Self {
         match status {
             StatusCode::BAD_REQUEST => Self::BadRequest(message),
+            StatusCode::UNAUTHORIZED => Self::Unauthorized(message),
             StatusCode::NOT_FOUND => Self::NotFound(message),
             StatusCode::CONFLICT => Self::Conflict(message),
+            StatusCode::PAYLOAD_TOO_LARGE => Self::PayloadTooLarge(message),
             StatusCode::UNPROCESSABLE_ENTITY => Self::UnprocessableEntity(message),
+            StatusCode::TOO_MANY_REQUESTS => Self::TooManyRequests(message),
+            StatusCode::BAD_GATEWAY => Self::UpstreamError(message),
             StatusCode::SERVICE_UNAVAILABLE => Self::ServiceUnavailable(message),
+            StatusCode::GATEWAY_TIMEOUT => Self::Timeout(message),
             _ => Self::InternalServerError(message),
         }
     }
@@ -0,0 +1,172 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::webhooks::DetectedTransaction;
+use crate::whales::WhaleEvent;
+
+/// Number of events buffered per subscriber before slow subscribers start missing events
+const DEFAULT_CAPACITY: usize = 1024;
+
+/// Something that happened to a wallet
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletEvent {
+    /// Wallet the event concerns
+    pub wallet_id: Uuid,
+    /// When the event occurred
+    pub occurred_at: DateTime<Utc>,
+    /// What happened
+    #[serde(flatten)]
+    pub kind: WalletEventKind,
+}
+
+/// Kinds of wallet activity published on the event bus
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum WalletEventKind {
+    /// The wallet started being tracked
+    WalletCreated {
+        /// Address of the wallet
+        address: String,
+        /// Name given to the wallet
+        name: Option<String>,
+    },
+    /// Transactions not seen before were recorded
+    TransactionsDetected {
+        /// The newly recorded transaction rows
+        transactions: Vec<DetectedTransaction>,
+    },
+    /// A sync from the Solana RPC completed
+    WalletSynced {
+        /// Number of signatures returned by the RPC node
+        signatures_fetched: usize,
+        /// Number of transaction rows inserted or updated
+        transactions_upserted: usize,
+    },
+    /// A newly recorded transaction was flagged as a whale movement
+    WhaleMovement {
+        /// The flagged movement
+        event: WhaleEvent,
+    },
+}
+
+impl WalletEventKind {
+    /// Name of the event kind, used as the SSE event name
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::WalletCreated { .. } => "wallet_created",
+            Self::TransactionsDetected { .. } => "transactions_detected",
+            Self::WalletSynced { .. } => "wallet_synced",
+            Self::WhaleMovement { .. } => "whale_movement",
+        }
+    }
+}
+
+/// An alert's condition was met
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct AlertFired {
+    /// Alert that fired
+    pub alert_id: Uuid,
+    /// Owner of the alert
+    pub user_id: Uuid,
+    /// Wallet the alert watches, for wallet value alerts
+    pub wallet_id: Option<Uuid>,
+    /// Value that met the condition, in USD
+    pub value_usd: f64,
+    /// Human-readable description of the firing
+    pub message: String,
+    /// When the alert fired
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Event published on the bus
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DomainEvent {
+    /// Activity of one wallet
+    Wallet(WalletEvent),
+    /// An alert fired
+    AlertFired(AlertFired),
+}
+
+impl DomainEvent {
+    /// Name of the event, e.g. `transactions_detected` or `alert_fired`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Wallet(event) => event.kind.name(),
+            Self::AlertFired(_) => "alert_fired",
+        }
+    }
+
+    /// Wallet the event concerns, if any
+    pub fn wallet_id(&self) -> Option<Uuid> {
+        match self {
+            Self::Wallet(event) => Some(event.wallet_id),
+            Self::AlertFired(event) => event.wallet_id,
+        }
+    }
+}
+
+/// Publish/subscribe bus that handlers, sync and the schedulers publish domain events
+/// to, and live consumers such as the SSE stream subscribe to
+///
+/// Events are plain serializable values, so the bus can live outside the process, e.g.
+/// in Redis pub/sub, and fan out events between instances. [`BroadcastEventBus`] keeps
+/// them in process for single-instance deployments. Delivery is best effort: webhooks
+/// and notifications that must not be lost are queued in the database instead.
+pub trait EventBus: Send + Sync {
+    /// Publishes an event to all current subscribers, without blocking
+    fn publish_event(&self, event: DomainEvent);
+
+    /// Subscribes to all events published from now on
+    fn subscribe(&self) -> broadcast::Receiver<Arc<DomainEvent>>;
+}
+
+impl dyn EventBus + '_ {
+    /// Publishes activity of a wallet that occurred now
+    pub fn publish(&self, wallet_id: Uuid, kind: WalletEventKind) {
+        self.publish_event(DomainEvent::Wallet(WalletEvent {
+            wallet_id,
+            occurred_at: Utc::now(),
+            kind,
+        }));
+    }
+}
+
+/// In-process event bus on a Tokio broadcast channel
+///
+/// Publishing never blocks; subscribers that fall more than the bus capacity behind
+/// miss the oldest events and are told how many they skipped.
+#[derive(Debug, Clone)]
+pub struct BroadcastEventBus {
+    sender: broadcast::Sender<Arc<DomainEvent>>,
+}
+
+impl Default for BroadcastEventBus {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY)
+    }
+}
+
+impl BroadcastEventBus {
+    /// Creates a bus buffering up to `capacity` events per subscriber
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        Self { sender }
+    }
+}
+
+impl EventBus for BroadcastEventBus {
+    fn publish_event(&self, event: DomainEvent) {
+        // Sending only fails when nobody is subscribed, which is fine
+        let _ = self.sender.send(Arc::new(event));
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Arc<DomainEvent>> {
+        self.sender.subscribe()
+    }
+}
@@ -0,0 +1,239 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+
+use axum::{
+    body::{Bytes, StreamBody},
+    http::header,
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use serde::Deserialize;
+use tokio::sync::mpsc;
+use tokio_stream::{wrappers::ReceiverStream, Stream};
+use tracing::error;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::classify::TransactionCategory;
+use crate::models::{Holding, Transaction};
+use crate::pagination::Cursor;
+use crate::repository::{RepositoryError, TransactionQuery, TransactionRepository};
+use crate::tax::RealizedGain;
+
+/// Number of transactions read from storage per streamed chunk
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// Chunks buffered ahead of a slow client before reading from storage pauses
+const EXPORT_CHANNEL_CAPACITY: usize = 4;
+
+/// Header row of a transaction export
+const TRANSACTION_CSV_HEADER: &str = "id,created_at,block_time,transaction_hash,block_number,token_address,token_symbol,token_name,amount,buy_price_usd,category\r\n";
+
+/// Header row of a holdings export
+const HOLDING_CSV_HEADER: &str =
+    "token_address,token_symbol,token_name,amount,price_usd,value_usd\r\n";
+
+/// Header row of a tax report
+const TAX_REPORT_CSV_HEADER: &str = "token_address,token_symbol,date_acquired,date_sold,amount,proceeds_usd,cost_basis_usd,gain_usd\r\n";
+
+/// Format of the dates in a tax report
+const TAX_REPORT_DATE_FORMAT: &str = "%Y-%m-%d";
+
+/// File format of an export
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    /// Comma-separated values with a header row (RFC 4180)
+    #[default]
+    Csv,
+}
+
+/// Query parameters of the export endpoints
+#[derive(Debug, Default, Deserialize)]
+pub struct ExportParams {
+    /// File format, `csv` (default)
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Only export transactions of this category
+    pub category: Option<TransactionCategory>,
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\r', '\n']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Quotes free text, such as token names chosen by their creators, and defuses values
+/// a spreadsheet would run as a formula
+fn csv_text(value: &str) -> Cow<'_, str> {
+    if value.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(csv_field(&format!("'{value}")).into_owned())
+    } else {
+        csv_field(value)
+    }
+}
+
+/// Formats an optional number, leaving the field empty if it is unknown
+fn csv_number(value: Option<f64>) -> String {
+    value.map(|v| v.to_string()).unwrap_or_default()
+}
+
+/// One CSV row of a transaction
+fn transaction_row(t: &Transaction) -> String {
+    format!(
+        "{},{},{},{},{},{},{},{},{},{},{}\r\n",
+        t.id,
+        t.created_at.to_rfc3339(),
+        t.block_time
+            .map(|time| time.to_rfc3339())
+            .unwrap_or_default(),
+        csv_field(&t.transaction_hash),
+        t.block_number,
+        csv_field(&t.token_address),
+        csv_text(&t.token_symbol),
+        csv_text(t.token_name.as_deref().unwrap_or_default()),
+        t.amount,
+        t.buy_price_usd,
+        t.category.as_deref().unwrap_or_default(),
+    )
+}
+
+/// One CSV row of a holding
+fn holding_row(h: &Holding) -> String {
+    format!(
+        "{},{},{},{},{},{}\r\n",
+        csv_field(&h.token_address),
+        csv_text(&h.token_symbol),
+        csv_text(h.token_name.as_deref().unwrap_or_default()),
+        h.amount,
+        csv_number(h.price_usd),
+        csv_number(h.value_usd.map(|value| value.to_f64())),
+    )
+}
+
+/// One CSV row of a realized gain
+///
+/// Tokens without a known purchase leave the acquisition date empty.
+fn realized_gain_row(g: &RealizedGain) -> String {
+    let d = &g.disposal;
+    format!(
+        "{},{},{},{},{},{},{},{}\r\n",
+        csv_field(&g.token_address),
+        csv_text(&g.token_symbol),
+        d.acquired_at
+            .map(|time| time.format(TAX_REPORT_DATE_FORMAT).to_string())
+            .unwrap_or_default(),
+        d.sold_at.format(TAX_REPORT_DATE_FORMAT),
+        d.amount,
+        d.proceeds_usd,
+        d.cost_basis_usd,
+        d.gain_usd(),
+    )
+}
+
+/// Streams a wallet's transactions as CSV, newest first
+///
+/// Transactions are read page by page while the client consumes the stream, so the
+/// export is never held in memory. A storage failure ends the stream with an error,
+/// which aborts the response.
+pub fn transactions_csv(
+    transactions: Arc<dyn TransactionRepository>,
+    wallet_id: Uuid,
+    category: Option<TransactionCategory>,
+) -> ReceiverStream<Result<Bytes, RepositoryError>> {
+    let (sender, receiver) = mpsc::channel(EXPORT_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        if sender
+            .send(Ok(Bytes::from_static(TRANSACTION_CSV_HEADER.as_bytes())))
+            .await
+            .is_err()
+        {
+            return;
+        }
+
+        let mut cursor = None;
+        loop {
+            let query = TransactionQuery {
+                cursor,
+                category,
+                limit: EXPORT_PAGE_SIZE,
+            };
+            let page = match transactions.list(wallet_id, &query).await {
+                Ok(page) => page,
+                Err(err) => {
+                    error!(
+                        "Export of wallet {} transactions failed: {}",
+                        wallet_id, err
+                    );
+                    let _ = sender.send(Err(err)).await;
+                    return;
+                }
+            };
+
+            let Some(last) = page.last() else {
+                return;
+            };
+            cursor = Some(Cursor::new(last.created_at, last.id));
+
+            let chunk: String = page.iter().map(transaction_row).collect();
+            // Stops reading once the client has gone away
+            if sender.send(Ok(Bytes::from(chunk))).await.is_err() {
+                return;
+            }
+            if (page.len() as i64) < EXPORT_PAGE_SIZE {
+                return;
+            }
+        }
+    });
+
+    ReceiverStream::new(receiver)
+}
+
+/// Streams a header row and then `rows`, one chunk per row
+fn rows_csv(
+    header: &'static str,
+    rows: impl Iterator<Item = String>,
+) -> impl Stream<Item = Result<Bytes, BoxError>> {
+    let rows = std::iter::once(header.to_string()).chain(rows);
+    tokio_stream::iter(rows.map(|row| Ok(Bytes::from(row))))
+}
+
+/// Streams holdings as CSV, one chunk per row
+pub fn holdings_csv(holdings: Vec<Holding>) -> impl Stream<Item = Result<Bytes, BoxError>> {
+    rows_csv(
+        HOLDING_CSV_HEADER,
+        holdings.into_iter().map(|holding| holding_row(&holding)),
+    )
+}
+
+/// Streams a tax report's realized gains as CSV, one chunk per row
+pub fn tax_report_csv(gains: Vec<RealizedGain>) -> impl Stream<Item = Result<Bytes, BoxError>> {
+    rows_csv(
+        TAX_REPORT_CSV_HEADER,
+        gains.into_iter().map(|gain| realized_gain_row(&gain)),
+    )
+}
+
+/// Sends a CSV stream as a chunked download named `filename`
+pub fn csv_response<S, E>(filename: &str, stream: S) -> Response
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{filename}\""),
+            ),
+        ],
+        StreamBody::new(stream),
+    )
+        .into_response()
+}
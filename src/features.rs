@@ -0,0 +1,259 @@
+//! Feature flags switching risky subsystems on and off without a redeploy.
+//!
+//! Every flag starts as configured: all features are on unless listed in
+//! `FEATURES_DISABLED`. An operator can override a flag with
+//! `PUT /admin/features/{feature}`, which is stored in `feature_flags` so that it
+//! survives restarts and reaches every instance, each reloading the overrides every
+//! `FEATURE_REFRESH_SECS`. The router answers requests to a disabled feature's routes
+//! with `503`, and background work of a disabled feature is held back until it is
+//! switched on again.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use axum::{
+    extract::State,
+    http::Request,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use utoipa::ToSchema;
+
+use crate::AppError;
+
+/// A subsystem that can be switched off
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Wallet syncs, on demand and in the background
+    Sync,
+    /// Public share links and the portfolio widget served through them
+    ShareLinks,
+    /// Ingestion of Helius webhook pushes
+    HeliusWebhooks,
+}
+
+impl Feature {
+    /// Every feature, in the order they are listed
+    pub const ALL: [Feature; 3] = [Self::Sync, Self::ShareLinks, Self::HeliusWebhooks];
+
+    /// Name of the feature in configuration and the admin endpoints
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::ShareLinks => "share_links",
+            Self::HeliusWebhooks => "helius_webhooks",
+        }
+    }
+
+    /// Description of the feature for error messages
+    fn description(&self) -> &'static str {
+        match self {
+            Self::Sync => "Wallet sync",
+            Self::ShareLinks => "Sharing portfolios publicly",
+            Self::HeliusWebhooks => "Ingestion of Helius webhooks",
+        }
+    }
+}
+
+impl fmt::Display for Feature {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Feature {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|feature| feature.as_str() == s)
+            .ok_or_else(|| format!("Unknown feature {s:?}"))
+    }
+}
+
+/// State of a feature flag as shown by the admin endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct FeatureStatus {
+    /// The feature
+    pub feature: Feature,
+    /// Whether the feature is on
+    pub enabled: bool,
+    /// Whether an operator overrode the configured state
+    pub overridden: bool,
+}
+
+/// Request to switch a feature on or off
+#[derive(Debug, Clone, Copy, Deserialize, ToSchema)]
+pub struct SetFeature {
+    /// Whether the feature is on; `null` drops the override, restoring the configured
+    /// state
+    pub enabled: Option<bool>,
+}
+
+/// The feature flags of an instance
+#[derive(Debug, Default)]
+pub struct FeatureFlags {
+    /// Features switched off by configuration
+    disabled: Vec<Feature>,
+    /// States overridden by operators, as last loaded from the database
+    overrides: RwLock<BTreeMap<Feature, bool>>,
+}
+
+impl FeatureFlags {
+    /// Creates the flags with every feature on, except those in `disabled`
+    pub fn new(disabled: Vec<Feature>) -> Self {
+        Self {
+            disabled,
+            overrides: RwLock::default(),
+        }
+    }
+
+    /// Whether the feature is on
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.status(feature).enabled
+    }
+
+    /// Fails with `503 Service Unavailable` if the feature is off
+    pub fn ensure(&self, feature: Feature) -> Result<(), AppError> {
+        if self.is_enabled(feature) {
+            return Ok(());
+        }
+        Err(AppError::ServiceUnavailable(format!(
+            "{} is disabled on this instance",
+            feature.description()
+        )))
+    }
+
+    /// The state of a feature
+    pub fn status(&self, feature: Feature) -> FeatureStatus {
+        let overrides = self.overrides.read().unwrap_or_else(|e| e.into_inner());
+        match overrides.get(&feature) {
+            Some(&enabled) => FeatureStatus {
+                feature,
+                enabled,
+                overridden: true,
+            },
+            None => FeatureStatus {
+                feature,
+                enabled: !self.disabled.contains(&feature),
+                overridden: false,
+            },
+        }
+    }
+
+    /// The state of every feature
+    pub fn list(&self) -> Vec<FeatureStatus> {
+        Feature::ALL
+            .into_iter()
+            .map(|feature| self.status(feature))
+            .collect()
+    }
+
+    /// Reloads the overrides from the database
+    ///
+    /// Rows naming features this version does not know are ignored.
+    pub async fn refresh(&self, pool: &PgPool) -> Result<(), sqlx::Error> {
+        let rows: Vec<(String, bool)> = sqlx::query_as("SELECT name, enabled FROM feature_flags")
+            .fetch_all(pool)
+            .await?;
+
+        let overrides = rows
+            .into_iter()
+            .filter_map(|(name, enabled)| Some((name.parse().ok()?, enabled)))
+            .collect();
+        *self.overrides.write().unwrap_or_else(|e| e.into_inner()) = overrides;
+        Ok(())
+    }
+
+    /// Overrides the state of a feature, or with `None` drops its override, and
+    /// returns the resulting state
+    ///
+    /// Takes effect on this instance at once and on the others with their next
+    /// refresh.
+    pub async fn set(
+        &self,
+        pool: &PgPool,
+        feature: Feature,
+        enabled: Option<bool>,
+    ) -> Result<FeatureStatus, sqlx::Error> {
+        match enabled {
+            Some(enabled) => {
+                sqlx::query(
+                    r#"
+                    INSERT INTO feature_flags (name, enabled)
+                    VALUES ($1, $2)
+                    ON CONFLICT (name) DO UPDATE
+                    SET enabled = EXCLUDED.enabled, updated_at = NOW()
+                    "#,
+                )
+                .bind(feature.as_str())
+                .bind(enabled)
+                .execute(pool)
+                .await?;
+            }
+            None => {
+                sqlx::query("DELETE FROM feature_flags WHERE name = $1")
+                    .bind(feature.as_str())
+                    .execute(pool)
+                    .await?;
+            }
+        }
+
+        let mut overrides = self.overrides.write().unwrap_or_else(|e| e.into_inner());
+        match enabled {
+            Some(enabled) => overrides.insert(feature, enabled),
+            None => overrides.remove(&feature),
+        };
+        drop(overrides);
+
+        Ok(self.status(feature))
+    }
+}
+
+/// Middleware answering `503 Service Unavailable` while the feature is off
+pub async fn require_feature<B>(
+    State((flags, feature)): State<(Arc<FeatureFlags>, Feature)>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    match flags.ensure(feature) {
+        Ok(()) => next.run(request).await,
+        Err(err) => err.into_response(),
+    }
+}
+
+/// Spawns the background task reloading the overrides every `interval`, starting now
+pub fn spawn_refresher(
+    flags: Arc<FeatureFlags>,
+    pool: PgPool,
+    interval: Duration,
+) -> JoinHandle<()> {
+    info!(
+        "Starting feature flag refresher with interval {:?}",
+        interval
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if let Err(err) = flags.refresh(&pool).await {
+                error!("Refreshing feature flags failed: {}", err);
+            }
+        }
+    })
+}
@@ -0,0 +1,204 @@
+//! Fees paid by tracked wallets.
+//!
+//! Every ingested transaction the wallet paid for records its network fee, split into
+//! the base fee of 5000 lamports per signature and the priority fee on top, along with
+//! any SOL it tipped to a Jito tip account to get its bundle landed.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::snapshots::HistoryRange;
+
+/// Base fee charged per transaction signature, in lamports
+pub const LAMPORTS_PER_SIGNATURE: u64 = 5000;
+
+/// Lamports in one SOL
+pub const LAMPORTS_PER_SOL: f64 = 1_000_000_000.0;
+
+/// Accounts Jito block engines collect tips on
+pub const JITO_TIP_ACCOUNTS: [&str; 8] = [
+    "96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5",
+    "HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe",
+    "Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY",
+    "ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49",
+    "DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh",
+    "ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt",
+    "DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL",
+    "3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT",
+];
+
+/// Fees a wallet paid in one transaction
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TransactionFees {
+    /// Network fee, base and priority fee together, in lamports; zero if another
+    /// account paid it
+    pub fee_lamports: u64,
+    /// Part of the network fee above the base fee, if known
+    pub priority_fee_lamports: Option<u64>,
+    /// SOL sent to Jito tip accounts, in lamports
+    pub jito_tip_lamports: u64,
+}
+
+impl TransactionFees {
+    /// Reads the fees `owner` paid in a `jsonParsed` transaction, or `None` if it paid
+    /// neither the network fee nor a tip
+    pub fn from_parsed(transaction: &Value, owner: &str) -> Option<Self> {
+        let keys = transaction["transaction"]["message"]["accountKeys"].as_array();
+        let payer = keys
+            .and_then(|keys| keys.first())
+            .and_then(|key| key["pubkey"].as_str().or(key.as_str()));
+
+        let (fee_lamports, priority_fee_lamports) = if payer == Some(owner) {
+            let fee = transaction["meta"]["fee"].as_u64().unwrap_or(0);
+            let signatures = keys
+                .into_iter()
+                .flatten()
+                .filter(|key| key["signer"].as_bool() == Some(true))
+                .count()
+                .max(1) as u64;
+            let priority = fee.saturating_sub(signatures * LAMPORTS_PER_SIGNATURE);
+            (fee, Some(priority))
+        } else {
+            (0, None)
+        };
+
+        let inner = transaction["meta"]["innerInstructions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .flat_map(|group| group["instructions"].as_array().into_iter().flatten());
+        let jito_tip_lamports = transaction["transaction"]["message"]["instructions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .chain(inner)
+            .filter(|instruction| {
+                instruction["program"].as_str() == Some("system")
+                    && instruction["parsed"]["type"].as_str() == Some("transfer")
+            })
+            .map(|instruction| &instruction["parsed"]["info"])
+            .filter(|info| {
+                info["source"].as_str() == Some(owner)
+                    && info["destination"]
+                        .as_str()
+                        .is_some_and(|to| JITO_TIP_ACCOUNTS.contains(&to))
+            })
+            .filter_map(|info| info["lamports"].as_u64())
+            .sum();
+
+        Self {
+            fee_lamports,
+            priority_fee_lamports,
+            jito_tip_lamports,
+        }
+        .paid()
+    }
+
+    /// The fees, if any were paid
+    pub fn paid(self) -> Option<Self> {
+        (self.fee_lamports > 0 || self.jito_tip_lamports > 0).then_some(self)
+    }
+}
+
+/// Records the fees a wallet paid in a transaction; recording the same transaction
+/// again updates it, keeping a known priority fee
+pub async fn record(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    signature: &str,
+    block_time: Option<DateTime<Utc>>,
+    fees: &TransactionFees,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO transaction_fees (
+            wallet_id, transaction_hash, block_time, fee_lamports, priority_fee_lamports,
+            jito_tip_lamports
+        )
+        VALUES ($1, $2, $3, $4, $5, $6)
+        ON CONFLICT (wallet_id, transaction_hash) DO UPDATE
+        SET block_time = EXCLUDED.block_time,
+            fee_lamports = EXCLUDED.fee_lamports,
+            priority_fee_lamports = COALESCE(
+                EXCLUDED.priority_fee_lamports, transaction_fees.priority_fee_lamports
+            ),
+            jito_tip_lamports = EXCLUDED.jito_tip_lamports
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(signature)
+    .bind(block_time)
+    .bind(fees.fee_lamports as i64)
+    .bind(fees.priority_fee_lamports.map(|lamports| lamports as i64))
+    .bind(fees.jito_tip_lamports as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// What a wallet spent on fees over a period
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct FeeSummary {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Requested period
+    #[schema(value_type = String, example = "30d")]
+    pub period: HistoryRange,
+    /// Number of transactions the wallet paid fees or tips in
+    pub transactions: i64,
+    /// Base fees, in SOL; includes the priority fees of transactions where they
+    /// could not be told apart
+    pub base_fees_sol: f64,
+    /// Priority fees, in SOL
+    pub priority_fees_sol: f64,
+    /// Jito tips, in SOL
+    pub jito_tips_sol: f64,
+    /// Everything spent on fees and tips, in SOL
+    pub total_sol: f64,
+    /// Everything spent on fees and tips at the current SOL price, if available
+    pub total_usd: Option<f64>,
+    /// Average spent per transaction, in SOL
+    pub average_per_transaction_sol: Option<f64>,
+}
+
+/// Adds up the fees a wallet paid in the last `period` days; `total_usd` is left for
+/// the caller to price
+pub async fn summary(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    period: HistoryRange,
+) -> Result<FeeSummary, sqlx::Error> {
+    let (transactions, fees, priority, tips) = sqlx::query_as::<_, (i64, i64, i64, i64)>(
+        r#"
+        SELECT COUNT(*),
+            COALESCE(SUM(fee_lamports), 0)::BIGINT,
+            COALESCE(SUM(priority_fee_lamports), 0)::BIGINT,
+            COALESCE(SUM(jito_tip_lamports), 0)::BIGINT
+        FROM transaction_fees
+        WHERE wallet_id = $1
+          AND COALESCE(block_time, created_at) >= NOW() - make_interval(days => $2::INTEGER)
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(period.num_days() as i32)
+    .fetch_one(pool)
+    .await?;
+
+    let total_sol = (fees + tips) as f64 / LAMPORTS_PER_SOL;
+    Ok(FeeSummary {
+        wallet_id,
+        period,
+        transactions,
+        base_fees_sol: (fees - priority) as f64 / LAMPORTS_PER_SOL,
+        priority_fees_sol: priority as f64 / LAMPORTS_PER_SOL,
+        jito_tips_sol: tips as f64 / LAMPORTS_PER_SOL,
+        total_sol,
+        total_usd: None,
+        average_per_transaction_sol: (transactions > 0).then(|| total_sol / transactions as f64),
+    })
+}
@@ -0,0 +1,366 @@
+//! Fiat exchange rates and display currencies.
+//!
+//! Values are computed and stored in USD. Value-bearing endpoints accept
+//! `?currency=EUR|GBP|NGN` and answer with every `*_usd` field of their JSON body
+//! converted at the current USD exchange rate; field names stay the same, and the
+//! `Content-Currency` response header names the currency the values are in. Rates are
+//! fetched from an exchange rate API and refreshed periodically.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use async_trait::async_trait;
+use axum::{
+    body::{boxed, Full},
+    extract::{Query, State},
+    http::{header, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::RwLock;
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+use crate::{AppError, AppState};
+
+/// Default exchange rate API endpoint, answering rates against USD
+pub const DEFAULT_FX_API_URL: &str = "https://open.er-api.com/v6/latest/USD";
+
+/// Response header naming the currency of a response's values
+pub const CONTENT_CURRENCY: &str = "content-currency";
+
+/// Errors that can occur while fetching exchange rates
+#[derive(Debug, Error)]
+pub enum FiatError {
+    /// The HTTP request to the exchange rate API failed
+    #[error("Exchange rate request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The exchange rate API answered without a rate of a supported currency
+    #[error("Invalid exchange rate response: {0}")]
+    InvalidResponse(String),
+}
+
+impl From<FiatError> for AppError {
+    fn from(err: FiatError) -> Self {
+        match err {
+            FiatError::Http(err) => err.into(),
+            FiatError::InvalidResponse(message) => Self::UpstreamError(message),
+        }
+    }
+}
+
+/// A currency values can be displayed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum Currency {
+    /// US dollar, the currency values are computed in
+    #[default]
+    Usd,
+    /// Euro
+    Eur,
+    /// Pound sterling
+    Gbp,
+    /// Nigerian naira
+    Ngn,
+}
+
+impl Currency {
+    /// Every supported currency
+    pub const ALL: [Currency; 4] = [Self::Usd, Self::Eur, Self::Gbp, Self::Ngn];
+
+    /// ISO 4217 code of the currency
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::Usd => "USD",
+            Self::Eur => "EUR",
+            Self::Gbp => "GBP",
+            Self::Ngn => "NGN",
+        }
+    }
+}
+
+impl fmt::Display for Currency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl FromStr for Currency {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        Self::ALL
+            .into_iter()
+            .find(|currency| currency.code().eq_ignore_ascii_case(value))
+            .ok_or_else(|| {
+                let codes: Vec<&str> = Self::ALL.iter().map(Currency::code).collect();
+                format!(
+                    "Unsupported currency {value:?}: expected one of {}",
+                    codes.join(", ")
+                )
+            })
+    }
+}
+
+/// Query parameter selecting the currency of a response's values
+#[derive(Debug, Default, Deserialize)]
+pub struct CurrencyParams {
+    /// Currency to display values in, `USD` (default), `EUR`, `GBP` or `NGN`
+    pub currency: Option<String>,
+}
+
+/// A source of USD exchange rates
+#[async_trait]
+pub trait RateSource: Send + Sync {
+    /// Fetches how many units of each supported currency one US dollar buys
+    async fn usd_rates(&self) -> Result<HashMap<Currency, f64>, FiatError>;
+}
+
+/// Rate source backed by an ExchangeRate-API compatible endpoint
+///
+/// The endpoint answers `{"rates": {"EUR": 0.92, ...}}` with rates against USD.
+#[derive(Debug, Clone)]
+pub struct HttpRateSource {
+    http: reqwest::Client,
+    url: String,
+}
+
+/// Body of an exchange rate API response
+#[derive(Debug, Deserialize)]
+struct RatesResponse {
+    rates: HashMap<String, f64>,
+}
+
+impl HttpRateSource {
+    /// Creates a rate source for the given exchange rate API endpoint
+    pub fn new(url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            url: url.into(),
+        }
+    }
+}
+
+#[async_trait]
+impl RateSource for HttpRateSource {
+    async fn usd_rates(&self) -> Result<HashMap<Currency, f64>, FiatError> {
+        let response: RatesResponse = self
+            .http
+            .get(&self.url)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+
+        Currency::ALL
+            .into_iter()
+            .map(|currency| {
+                let rate = match currency {
+                    Currency::Usd => Some(1.0),
+                    _ => response.rates.get(currency.code()).copied(),
+                };
+                rate.filter(|rate| rate.is_finite() && *rate > 0.0)
+                    .map(|rate| (currency, rate))
+                    .ok_or_else(|| FiatError::InvalidResponse(format!("No {currency} rate")))
+            })
+            .collect()
+    }
+}
+
+/// Rate source answering fixed rates, e.g. in tests
+#[derive(Debug, Clone, Default)]
+pub struct StaticRateSource {
+    rates: HashMap<Currency, f64>,
+}
+
+impl StaticRateSource {
+    /// Creates a source answering `rates`; USD is always 1
+    pub fn new(rates: HashMap<Currency, f64>) -> Self {
+        Self { rates }
+    }
+}
+
+#[async_trait]
+impl RateSource for StaticRateSource {
+    async fn usd_rates(&self) -> Result<HashMap<Currency, f64>, FiatError> {
+        let mut rates = self.rates.clone();
+        rates.insert(Currency::Usd, 1.0);
+        Ok(rates)
+    }
+}
+
+/// Current exchange rates, fetched from a [`RateSource`] and kept for a fixed TTL
+pub struct ExchangeRates {
+    source: Arc<dyn RateSource>,
+    ttl: Duration,
+    rates: RwLock<Option<(HashMap<Currency, f64>, Instant)>>,
+}
+
+impl ExchangeRates {
+    /// Creates rates fetched from `source` and refetched once older than `ttl`
+    pub fn new(source: Arc<dyn RateSource>, ttl: Duration) -> Self {
+        Self {
+            source,
+            ttl,
+            rates: RwLock::new(None),
+        }
+    }
+
+    /// Fetches the rates from the source, keeping them on success
+    pub async fn refresh(&self) -> Result<HashMap<Currency, f64>, FiatError> {
+        let rates = self.source.usd_rates().await?;
+        *self.rates.write().await = Some((rates.clone(), Instant::now()));
+        Ok(rates)
+    }
+
+    /// Units of `currency` one US dollar buys
+    ///
+    /// Rates older than the TTL are refetched; if that fails, the previous rates are
+    /// used rather than failing the request. Fails only when no rate was ever fetched.
+    pub async fn rate(&self, currency: Currency) -> Result<f64, AppError> {
+        if currency == Currency::Usd {
+            return Ok(1.0);
+        }
+
+        let cached = self.rates.read().await.clone();
+        let rates = match cached {
+            Some((rates, fetched_at)) if fetched_at.elapsed() < self.ttl => rates,
+            stale => match self.refresh().await {
+                Ok(rates) => rates,
+                Err(err) => match stale {
+                    Some((rates, _)) => {
+                        warn!("Using stale exchange rates: {}", err);
+                        rates
+                    }
+                    None => {
+                        return Err(AppError::ServiceUnavailable(format!(
+                            "Exchange rates are unavailable: {err}"
+                        )))
+                    }
+                },
+            },
+        };
+
+        rates
+            .get(&currency)
+            .copied()
+            .ok_or_else(|| AppError::ServiceUnavailable(format!("No exchange rate for {currency}")))
+    }
+}
+
+/// Multiplies every number under a key ending in `_usd` by `rate`, at any depth
+pub fn convert(value: &mut Value, rate: f64) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                match field {
+                    Value::Number(number) if key.ends_with("_usd") => {
+                        if let Some(converted) = number
+                            .as_f64()
+                            .and_then(|usd| serde_json::Number::from_f64(usd * rate))
+                        {
+                            *field = Value::Number(converted);
+                        }
+                    }
+                    _ => convert(field, rate),
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(|item| convert(item, rate)),
+        _ => {}
+    }
+}
+
+/// Whether a response carries a JSON body
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"))
+}
+
+/// Re-encodes a JSON response body with its values converted at `rate`
+async fn to_currency(response: Response, rate: f64) -> Result<Response, AppError> {
+    let (mut parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to read response: {e}")))?;
+    let mut value: Value = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InternalServerError(format!("Invalid JSON response: {e}")))?;
+    convert(&mut value, rate);
+    let body = serde_json::to_vec(&value)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to encode JSON: {e}")))?;
+
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Ok(Response::from_parts(parts, boxed(Full::from(body))))
+}
+
+/// Middleware converting the values of successful JSON responses to the currency of
+/// the request's `currency` parameter
+///
+/// Responses carry the currency their values are in as `Content-Currency`: streamed
+/// and non-JSON responses such as CSV and NDJSON exports stay in USD.
+pub async fn currency_middleware<B>(
+    State(state): State<AppState>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let currency = match Query::<CurrencyParams>::try_from_uri(request.uri()) {
+        Ok(Query(params)) => params.currency.as_deref().map(Currency::from_str),
+        Err(rejection) => return AppError::BadRequest(rejection.body_text()).into_response(),
+    };
+    let currency = match currency.transpose() {
+        Ok(currency) => currency.unwrap_or_default(),
+        Err(message) => return AppError::BadRequest(message).into_response(),
+    };
+    let rate = match state.fx.rate(currency).await {
+        Ok(rate) => rate,
+        Err(err) => return err.into_response(),
+    };
+
+    let response = next.run(request).await;
+    if !response.status().is_success() || !is_json(&response) {
+        return response;
+    }
+    let mut response = if currency == Currency::Usd {
+        response
+    } else {
+        match to_currency(response, rate).await {
+            Ok(response) => response,
+            Err(err) => return err.into_response(),
+        }
+    };
+    response
+        .headers_mut()
+        .insert(CONTENT_CURRENCY, HeaderValue::from_static(currency.code()));
+    response
+}
+
+/// Spawns a task refetching the exchange rates every `interval`, so requests rarely
+/// wait on the exchange rate API
+pub fn spawn_rate_refresher(rates: Arc<ExchangeRates>, interval: Duration) -> JoinHandle<()> {
+    info!("Refreshing exchange rates every {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = rates.refresh().await {
+                warn!("Refreshing exchange rates failed: {}", err);
+            }
+        }
+    })
+}
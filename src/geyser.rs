@@ -0,0 +1,864 @@
+//! Streaming ingestion from a Yellowstone (Geyser) gRPC feed.
+//!
+//! Self-hosters running a validator or RPC node with the Yellowstone Geyser plugin, or
+//! with access to a provider's feed, can have transactions of tracked wallets pushed
+//! as they are processed instead of polling the RPC for them. The worker subscribes to
+//! the transactions touching every tracked address, converts each update to the
+//! `jsonParsed` shape `getTransaction` returns, and records it exactly like a synced
+//! transaction, so a transaction seen by the stream and by a sync ends up as one row
+//! per token.
+
+use std::collections::{HashMap, HashSet};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use thiserror::Error;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+use tracing::{debug, error, info, warn};
+
+use crate::balances::{TOKEN_2022_PROGRAM_ID, TOKEN_PROGRAM_ID};
+use crate::events::{EventBus, WalletEventKind};
+use crate::models::Wallet;
+use crate::rpc::backoff;
+use crate::scheduler::{is_leader, LeaderLock};
+use crate::sync::{self, SignatureInfo};
+use crate::webhooks::DetectedTransaction;
+use crate::AppState;
+
+/// The System program
+const SYSTEM_PROGRAM_ID: &str = "11111111111111111111111111111111";
+
+/// How often the worker checks for wallets added or removed since it subscribed
+pub const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Messages of the Yellowstone `geyser.Geyser/Subscribe` call
+///
+/// Only the fields needed to subscribe to and decode transactions are modelled; the
+/// tags match `geyser.proto` and `solana-storage.proto`, and other fields are skipped
+/// when decoding.
+pub mod proto {
+    use std::collections::HashMap;
+
+    /// Commitment level of the updates sent
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, prost::Enumeration)]
+    #[repr(i32)]
+    pub enum CommitmentLevel {
+        /// Processed by the node
+        Processed = 0,
+        /// Voted on by a supermajority
+        Confirmed = 1,
+        /// Rooted
+        Finalized = 2,
+    }
+
+    /// Subscription request, which replaces the filters of the stream when resent
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequest {
+        /// Transaction filters by name
+        #[prost(map = "string, message", tag = "3")]
+        pub transactions: HashMap<String, SubscribeRequestFilterTransactions>,
+        /// Commitment level of the updates
+        #[prost(enumeration = "CommitmentLevel", optional, tag = "6")]
+        pub commitment: Option<i32>,
+        /// Answer to a ping of the server, keeping the stream alive
+        #[prost(message, optional, tag = "9")]
+        pub ping: Option<SubscribeRequestPing>,
+    }
+
+    /// Filter of the transactions sent
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestFilterTransactions {
+        /// Whether vote transactions are included, or only them
+        #[prost(bool, optional, tag = "1")]
+        pub vote: Option<bool>,
+        /// Whether failed transactions are included, or only them
+        #[prost(bool, optional, tag = "2")]
+        pub failed: Option<bool>,
+        /// Transactions touching any of these accounts are sent
+        #[prost(string, repeated, tag = "3")]
+        pub account_include: Vec<String>,
+    }
+
+    /// Ping sent by the client
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeRequestPing {
+        /// Echoed by the server's pong
+        #[prost(int32, tag = "1")]
+        pub id: i32,
+    }
+
+    /// An update sent by the server
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdate {
+        /// Names of the filters the update matched
+        #[prost(string, repeated, tag = "1")]
+        pub filters: Vec<String>,
+        /// The update; `None` for kinds of update not modelled
+        #[prost(oneof = "subscribe_update::UpdateOneof", tags = "4, 6")]
+        pub update_oneof: Option<subscribe_update::UpdateOneof>,
+        /// When the server created the update
+        #[prost(message, optional, tag = "11")]
+        pub created_at: Option<Timestamp>,
+    }
+
+    /// Kinds of [`SubscribeUpdate`]
+    pub mod subscribe_update {
+        /// The update carried by a [`super::SubscribeUpdate`]
+        // Mostly transactions are received, so boxing them would only add allocations
+        #[allow(clippy::large_enum_variant)]
+        #[derive(Clone, PartialEq, prost::Oneof)]
+        pub enum UpdateOneof {
+            /// A transaction matching a filter
+            #[prost(message, tag = "4")]
+            Transaction(super::SubscribeUpdateTransaction),
+            /// A keep-alive ping
+            #[prost(message, tag = "6")]
+            Ping(super::SubscribeUpdatePing),
+        }
+    }
+
+    /// A `google.protobuf.Timestamp`
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Timestamp {
+        /// Seconds since the Unix epoch
+        #[prost(int64, tag = "1")]
+        pub seconds: i64,
+        /// Nanoseconds within the second
+        #[prost(int32, tag = "2")]
+        pub nanos: i32,
+    }
+
+    /// Keep-alive ping sent by the server
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdatePing {}
+
+    /// A processed transaction
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateTransaction {
+        /// The transaction and its status
+        #[prost(message, optional, tag = "1")]
+        pub transaction: Option<SubscribeUpdateTransactionInfo>,
+        /// Slot the transaction was processed in
+        #[prost(uint64, tag = "2")]
+        pub slot: u64,
+    }
+
+    /// A transaction and its status
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct SubscribeUpdateTransactionInfo {
+        /// First signature of the transaction
+        #[prost(bytes = "vec", tag = "1")]
+        pub signature: Vec<u8>,
+        /// Whether it is a vote transaction
+        #[prost(bool, tag = "2")]
+        pub is_vote: bool,
+        /// The transaction
+        #[prost(message, optional, tag = "3")]
+        pub transaction: Option<Transaction>,
+        /// Its status
+        #[prost(message, optional, tag = "4")]
+        pub meta: Option<TransactionStatusMeta>,
+    }
+
+    /// A signed transaction
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Transaction {
+        /// Signatures, in order of the signing accounts
+        #[prost(bytes = "vec", repeated, tag = "1")]
+        pub signatures: Vec<Vec<u8>>,
+        /// The message signed
+        #[prost(message, optional, tag = "2")]
+        pub message: Option<Message>,
+    }
+
+    /// A transaction message
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct Message {
+        /// Signing and read-only account counts
+        #[prost(message, optional, tag = "1")]
+        pub header: Option<MessageHeader>,
+        /// Accounts listed in the message
+        #[prost(bytes = "vec", repeated, tag = "2")]
+        pub account_keys: Vec<Vec<u8>>,
+        /// Top-level instructions
+        #[prost(message, repeated, tag = "4")]
+        pub instructions: Vec<CompiledInstruction>,
+    }
+
+    /// Signing and read-only account counts of a message
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct MessageHeader {
+        /// Number of signing accounts, listed first
+        #[prost(uint32, tag = "1")]
+        pub num_required_signatures: u32,
+        /// Number of read-only signing accounts, last among the signers
+        #[prost(uint32, tag = "2")]
+        pub num_readonly_signed_accounts: u32,
+        /// Number of read-only other accounts, last among the accounts
+        #[prost(uint32, tag = "3")]
+        pub num_readonly_unsigned_accounts: u32,
+    }
+
+    /// An instruction referring to accounts by index
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct CompiledInstruction {
+        /// Index of the program invoked
+        #[prost(uint32, tag = "1")]
+        pub program_id_index: u32,
+        /// Indexes of the accounts passed
+        #[prost(bytes = "vec", tag = "2")]
+        pub accounts: Vec<u8>,
+        /// Instruction data
+        #[prost(bytes = "vec", tag = "3")]
+        pub data: Vec<u8>,
+    }
+
+    /// Status of a processed transaction
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TransactionStatusMeta {
+        /// Error, if the transaction failed
+        #[prost(message, optional, tag = "1")]
+        pub err: Option<TransactionError>,
+        /// Network fee in lamports
+        #[prost(uint64, tag = "2")]
+        pub fee: u64,
+        /// Lamports of each account before the transaction
+        #[prost(uint64, repeated, tag = "3")]
+        pub pre_balances: Vec<u64>,
+        /// Lamports of each account after the transaction
+        #[prost(uint64, repeated, tag = "4")]
+        pub post_balances: Vec<u64>,
+        /// Instructions invoked by the top-level instructions
+        #[prost(message, repeated, tag = "5")]
+        pub inner_instructions: Vec<InnerInstructions>,
+        /// Token balances before the transaction
+        #[prost(message, repeated, tag = "7")]
+        pub pre_token_balances: Vec<TokenBalance>,
+        /// Token balances after the transaction
+        #[prost(message, repeated, tag = "8")]
+        pub post_token_balances: Vec<TokenBalance>,
+        /// Writable accounts loaded from address lookup tables
+        #[prost(bytes = "vec", repeated, tag = "12")]
+        pub loaded_writable_addresses: Vec<Vec<u8>>,
+        /// Read-only accounts loaded from address lookup tables
+        #[prost(bytes = "vec", repeated, tag = "13")]
+        pub loaded_readonly_addresses: Vec<Vec<u8>>,
+    }
+
+    /// Bincode-encoded transaction error
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TransactionError {
+        /// The encoded error
+        #[prost(bytes = "vec", tag = "1")]
+        pub err: Vec<u8>,
+    }
+
+    /// Inner instructions of one top-level instruction
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InnerInstructions {
+        /// Index of the top-level instruction
+        #[prost(uint32, tag = "1")]
+        pub index: u32,
+        /// The instructions it invoked
+        #[prost(message, repeated, tag = "2")]
+        pub instructions: Vec<InnerInstruction>,
+    }
+
+    /// An instruction invoked by another one
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct InnerInstruction {
+        /// Index of the program invoked
+        #[prost(uint32, tag = "1")]
+        pub program_id_index: u32,
+        /// Indexes of the accounts passed
+        #[prost(bytes = "vec", tag = "2")]
+        pub accounts: Vec<u8>,
+        /// Instruction data
+        #[prost(bytes = "vec", tag = "3")]
+        pub data: Vec<u8>,
+    }
+
+    /// Balance of a token account
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct TokenBalance {
+        /// Index of the token account among the transaction's accounts
+        #[prost(uint32, tag = "1")]
+        pub account_index: u32,
+        /// Mint of the token
+        #[prost(string, tag = "2")]
+        pub mint: String,
+        /// The balance
+        #[prost(message, optional, tag = "3")]
+        pub ui_token_amount: Option<UiTokenAmount>,
+        /// Owner of the token account
+        #[prost(string, tag = "4")]
+        pub owner: String,
+    }
+
+    /// A token amount
+    #[derive(Clone, PartialEq, prost::Message)]
+    pub struct UiTokenAmount {
+        /// Decimals of the token
+        #[prost(uint32, tag = "2")]
+        pub decimals: u32,
+        /// Raw amount, as a decimal string
+        #[prost(string, tag = "3")]
+        pub amount: String,
+    }
+}
+
+/// Errors that can occur while streaming from a Geyser feed
+#[derive(Debug, Error)]
+pub enum GeyserError {
+    /// Connecting to the feed failed
+    #[error("Geyser connection failed: {0}")]
+    Transport(#[from] tonic::transport::Error),
+
+    /// The feed rejected the subscription or ended the stream with an error
+    #[error("Geyser stream failed: {0}")]
+    Status(#[from] tonic::Status),
+
+    /// Recording a transaction failed
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// A successful transaction received from the feed, in `jsonParsed` shape
+#[derive(Debug, Clone)]
+pub struct GeyserTransaction {
+    /// Base58 transaction signature
+    pub signature: String,
+    /// Slot the transaction was processed in
+    pub slot: u64,
+    /// When the update was created, standing in for the block time the feed does not
+    /// send
+    pub block_time: i64,
+    /// The transaction as `getTransaction` returns it with `jsonParsed` encoding
+    pub transaction: Value,
+}
+
+impl GeyserTransaction {
+    /// Converts a transaction update, or returns `None` for other updates and for vote
+    /// or failed transactions, neither of which are recorded
+    ///
+    /// System and SPL token transfers are parsed as the node would; other instructions
+    /// are left as their program, accounts and base58 data.
+    pub fn from_update(update: &proto::SubscribeUpdate) -> Option<Self> {
+        let Some(proto::subscribe_update::UpdateOneof::Transaction(update_transaction)) =
+            &update.update_oneof
+        else {
+            return None;
+        };
+        let info = update_transaction.transaction.as_ref()?;
+        let meta = info.meta.as_ref()?;
+        if info.is_vote || meta.err.is_some() {
+            return None;
+        }
+        let message = info.transaction.as_ref()?.message.as_ref()?;
+        let header = message.header.clone().unwrap_or_default();
+
+        let statics = message.account_keys.len();
+        let signers = header.num_required_signatures as usize;
+        let keys: Vec<String> = message
+            .account_keys
+            .iter()
+            .chain(&meta.loaded_writable_addresses)
+            .chain(&meta.loaded_readonly_addresses)
+            .map(|key| bs58::encode(key).into_string())
+            .collect();
+        let account_keys: Vec<Value> = keys
+            .iter()
+            .enumerate()
+            .map(|(index, key)| {
+                let writable = if index < signers {
+                    index < signers.saturating_sub(header.num_readonly_signed_accounts as usize)
+                } else if index < statics {
+                    index < statics.saturating_sub(header.num_readonly_unsigned_accounts as usize)
+                } else {
+                    index < statics + meta.loaded_writable_addresses.len()
+                };
+                json!({ "pubkey": key, "signer": index < signers, "writable": writable })
+            })
+            .collect();
+
+        let instructions: Vec<Value> = message
+            .instructions
+            .iter()
+            .map(|ix| parsed_instruction(&keys, ix.program_id_index, &ix.accounts, &ix.data))
+            .collect();
+        let inner_instructions: Vec<Value> = meta
+            .inner_instructions
+            .iter()
+            .map(|group| {
+                let instructions: Vec<Value> = group
+                    .instructions
+                    .iter()
+                    .map(|ix| {
+                        parsed_instruction(&keys, ix.program_id_index, &ix.accounts, &ix.data)
+                    })
+                    .collect();
+                json!({ "index": group.index, "instructions": instructions })
+            })
+            .collect();
+
+        let block_time = update
+            .created_at
+            .as_ref()
+            .map_or_else(|| Utc::now().timestamp(), |created| created.seconds);
+
+        Some(Self {
+            signature: bs58::encode(&info.signature).into_string(),
+            slot: update_transaction.slot,
+            block_time,
+            transaction: json!({
+                "slot": update_transaction.slot,
+                "blockTime": block_time,
+                "meta": {
+                    "err": null,
+                    "fee": meta.fee,
+                    "preBalances": meta.pre_balances,
+                    "postBalances": meta.post_balances,
+                    "preTokenBalances": token_balances(&meta.pre_token_balances),
+                    "postTokenBalances": token_balances(&meta.post_token_balances),
+                    "innerInstructions": inner_instructions,
+                },
+                "transaction": {
+                    "message": {
+                        "accountKeys": account_keys,
+                        "instructions": instructions,
+                    }
+                }
+            }),
+        })
+    }
+
+    /// Addresses of the accounts the transaction touched
+    fn addresses(&self) -> impl Iterator<Item = &str> {
+        self.transaction["transaction"]["message"]["accountKeys"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|key| key["pubkey"].as_str())
+            .chain(
+                ["preTokenBalances", "postTokenBalances"]
+                    .into_iter()
+                    .flat_map(|field| self.transaction["meta"][field].as_array().into_iter())
+                    .flatten()
+                    .filter_map(|balance| balance["owner"].as_str()),
+            )
+    }
+}
+
+/// Token balances in `jsonParsed` shape
+fn token_balances(balances: &[proto::TokenBalance]) -> Vec<Value> {
+    balances
+        .iter()
+        .map(|balance| {
+            let amount = balance.ui_token_amount.clone().unwrap_or_default();
+            json!({
+                "accountIndex": balance.account_index,
+                "mint": balance.mint,
+                "owner": balance.owner,
+                "uiTokenAmount": { "amount": amount.amount, "decimals": amount.decimals },
+            })
+        })
+        .collect()
+}
+
+/// A compiled instruction in `jsonParsed` shape
+fn parsed_instruction(keys: &[String], program_index: u32, accounts: &[u8], data: &[u8]) -> Value {
+    let key = |index: usize| keys.get(index).map_or("", String::as_str);
+    let program_id = key(program_index as usize);
+    let accounts: Vec<&str> = accounts.iter().map(|index| key(*index as usize)).collect();
+    let account = |index: usize| accounts.get(index).copied().unwrap_or_default();
+    let amount = |at: usize| {
+        data.get(at..at + 8)
+            .and_then(|bytes| bytes.try_into().ok())
+            .map(u64::from_le_bytes)
+    };
+
+    let parsed = match program_id {
+        SYSTEM_PROGRAM_ID if data.get(..4) == Some(&2u32.to_le_bytes()) => {
+            amount(4).map(|lamports| {
+                json!({
+                    "program": "system",
+                    "type": "transfer",
+                    "info": { "source": account(0), "destination": account(1), "lamports": lamports },
+                })
+            })
+        }
+        TOKEN_PROGRAM_ID | TOKEN_2022_PROGRAM_ID => {
+            let program = if program_id == TOKEN_PROGRAM_ID {
+                "spl-token"
+            } else {
+                "spl-token-2022"
+            };
+            match data.first() {
+                Some(3) => amount(1).map(|amount| {
+                    json!({
+                        "program": program,
+                        "type": "transfer",
+                        "info": {
+                            "source": account(0),
+                            "destination": account(1),
+                            "authority": account(2),
+                            "amount": amount.to_string(),
+                        },
+                    })
+                }),
+                Some(12) => amount(1).zip(data.get(9)).map(|(amount, decimals)| {
+                    json!({
+                        "program": program,
+                        "type": "transferChecked",
+                        "info": {
+                            "source": account(0),
+                            "mint": account(1),
+                            "destination": account(2),
+                            "authority": account(3),
+                            "tokenAmount": { "amount": amount.to_string(), "decimals": decimals },
+                        },
+                    })
+                }),
+                _ => None,
+            }
+        }
+        _ => None,
+    };
+
+    match parsed {
+        Some(parsed) => json!({
+            "program": parsed["program"],
+            "programId": program_id,
+            "parsed": { "type": parsed["type"], "info": parsed["info"] },
+        }),
+        None => json!({
+            "programId": program_id,
+            "accounts": accounts,
+            "data": bs58::encode(data).into_string(),
+        }),
+    }
+}
+
+/// Stream of the transactions touching the subscribed addresses
+pub type TransactionStream =
+    Pin<Box<dyn Stream<Item = Result<GeyserTransaction, GeyserError>> + Send>>;
+
+/// A feed of processed transactions
+#[async_trait]
+pub trait GeyserSource: Send + Sync {
+    /// Subscribes to the successful transactions touching any of `addresses`
+    async fn subscribe(&self, addresses: &[String]) -> Result<TransactionStream, GeyserError>;
+}
+
+/// Feed from a Yellowstone gRPC endpoint
+#[derive(Debug, Clone)]
+pub struct YellowstoneSource {
+    endpoint: String,
+    x_token: Option<String>,
+}
+
+impl YellowstoneSource {
+    /// Creates a source for the endpoint at `endpoint`, authenticating with `x_token`
+    /// if the provider requires one
+    pub fn new(endpoint: impl Into<String>, x_token: Option<String>) -> Self {
+        Self {
+            endpoint: endpoint.into(),
+            x_token,
+        }
+    }
+}
+
+#[async_trait]
+impl GeyserSource for YellowstoneSource {
+    async fn subscribe(&self, addresses: &[String]) -> Result<TransactionStream, GeyserError> {
+        let mut endpoint = tonic::transport::Endpoint::from_shared(self.endpoint.clone())?
+            .connect_timeout(Duration::from_secs(10))
+            .http2_keep_alive_interval(Duration::from_secs(30));
+        if self.endpoint.starts_with("https://") {
+            endpoint = endpoint
+                .tls_config(tonic::transport::ClientTlsConfig::new().with_webpki_roots())?;
+        }
+        let mut client = tonic::client::Grpc::new(endpoint.connect().await?);
+        client
+            .ready()
+            .await
+            .map_err(|err| tonic::Status::unavailable(err.to_string()))?;
+
+        // The request stream stays open for the life of the subscription, to answer the
+        // server's pings
+        let (requests, outgoing) = mpsc::channel(4);
+        let subscription = proto::SubscribeRequest {
+            transactions: HashMap::from([(
+                "wallets".to_string(),
+                proto::SubscribeRequestFilterTransactions {
+                    vote: Some(false),
+                    failed: Some(false),
+                    account_include: addresses.to_vec(),
+                },
+            )]),
+            commitment: Some(proto::CommitmentLevel::Confirmed as i32),
+            ping: None,
+        };
+        requests
+            .send(subscription)
+            .await
+            .expect("Request stream is open");
+
+        let mut request = tonic::Request::new(ReceiverStream::new(outgoing));
+        if let Some(token) = &self.x_token {
+            let token = token
+                .parse()
+                .map_err(|_| tonic::Status::invalid_argument("Invalid Geyser x-token"))?;
+            request.metadata_mut().insert("x-token", token);
+        }
+        let updates = client
+            .streaming(
+                request,
+                tonic::codegen::http::uri::PathAndQuery::from_static("/geyser.Geyser/Subscribe"),
+                tonic::codec::ProstCodec::<proto::SubscribeRequest, proto::SubscribeUpdate>::default(),
+            )
+            .await?
+            .into_inner();
+
+        Ok(Box::pin(updates.filter_map(move |update| match update {
+            Ok(update) => {
+                if let Some(proto::subscribe_update::UpdateOneof::Ping(_)) = update.update_oneof {
+                    let _ = requests.try_send(proto::SubscribeRequest {
+                        ping: Some(proto::SubscribeRequestPing { id: 1 }),
+                        ..Default::default()
+                    });
+                }
+                GeyserTransaction::from_update(&update).map(Ok)
+            }
+            Err(status) => Some(Err(status.into())),
+        })))
+    }
+}
+
+/// Feed of a fixed set of transactions, for tests and replays
+///
+/// Each subscription streams the transactions touching the subscribed addresses, then
+/// ends.
+#[derive(Debug, Default)]
+pub struct StaticGeyserSource {
+    transactions: Vec<GeyserTransaction>,
+    subscriptions: Mutex<Vec<Vec<String>>>,
+}
+
+impl StaticGeyserSource {
+    /// Creates a feed of `transactions`
+    pub fn new(transactions: Vec<GeyserTransaction>) -> Self {
+        Self {
+            transactions,
+            subscriptions: Mutex::default(),
+        }
+    }
+
+    /// Addresses of each subscription made so far, sorted
+    pub fn subscriptions(&self) -> Vec<Vec<String>> {
+        self.subscriptions.lock().unwrap().clone()
+    }
+}
+
+#[async_trait]
+impl GeyserSource for StaticGeyserSource {
+    async fn subscribe(&self, addresses: &[String]) -> Result<TransactionStream, GeyserError> {
+        self.subscriptions.lock().unwrap().push(addresses.to_vec());
+
+        let matching: Vec<Result<GeyserTransaction, GeyserError>> = self
+            .transactions
+            .iter()
+            .filter(|tx| {
+                tx.addresses()
+                    .any(|address| addresses.iter().any(|a| a == address))
+            })
+            .cloned()
+            .map(Ok)
+            .collect();
+        Ok(Box::pin(tokio_stream::iter(matching)))
+    }
+}
+
+/// Summary of a subscription's run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StreamReport {
+    /// Number of transactions received
+    pub transactions_received: usize,
+    /// Number of balance changes processed, including ones already recorded
+    pub transactions_upserted: usize,
+    /// Number of balance changes not recorded before
+    pub transactions_inserted: usize,
+}
+
+/// Addresses of every tracked wallet, sorted and deduplicated
+async fn tracked_addresses(pool: &PgPool) -> Result<Vec<String>, sqlx::Error> {
+    sqlx::query_scalar("SELECT DISTINCT address FROM wallets ORDER BY address")
+        .fetch_all(pool)
+        .await
+}
+
+/// Records the balance changes of every tracked wallet the transaction touched
+///
+/// Newly recorded transactions are announced on the event bus and to webhooks, and
+/// checked for whale movements from `whale_threshold_usd`. Returns the number of
+/// balance changes upserted and newly inserted.
+pub async fn record(
+    pool: &PgPool,
+    events: &dyn EventBus,
+    tx: &GeyserTransaction,
+    whale_threshold_usd: Option<f64>,
+) -> Result<(usize, usize), sqlx::Error> {
+    let addresses: Vec<&str> = tx.addresses().collect::<HashSet<_>>().into_iter().collect();
+    // The same address may be tracked by several users
+    let wallets = sqlx::query_as::<_, Wallet>(
+        r#"
+        SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+        FROM wallets
+        WHERE address = ANY($1)
+        "#,
+    )
+    .bind(&addresses)
+    .fetch_all(pool)
+    .await?;
+
+    let info = SignatureInfo {
+        signature: tx.signature.clone(),
+        slot: tx.slot,
+        err: None,
+        block_time: Some(tx.block_time),
+    };
+    let mut upserted = 0;
+    let mut inserted = 0;
+    for wallet in &wallets {
+        let (rows, detected): (usize, Vec<DetectedTransaction>) =
+            sync::record_transaction(pool, wallet, &info, &tx.transaction, whale_threshold_usd)
+                .await?;
+        upserted += rows;
+        inserted += detected.len();
+        if detected.is_empty() {
+            continue;
+        }
+
+        events.publish(
+            wallet.id,
+            WalletEventKind::TransactionsDetected {
+                transactions: detected,
+            },
+        );
+    }
+
+    Ok((upserted, inserted))
+}
+
+/// Subscribes to the transactions of every tracked wallet and records them as they
+/// arrive
+///
+/// Returns when the stream ends or fails, or once the set of tracked addresses has
+/// changed when checked every `resubscribe_every`, so that the caller subscribes
+/// again with the new set.
+pub async fn stream(
+    pool: &PgPool,
+    events: &dyn EventBus,
+    source: &dyn GeyserSource,
+    whale_threshold_usd: Option<f64>,
+    resubscribe_every: Duration,
+) -> Result<StreamReport, GeyserError> {
+    let addresses = tracked_addresses(pool).await?;
+    let mut report = StreamReport::default();
+    if addresses.is_empty() {
+        tokio::time::sleep(resubscribe_every).await;
+        return Ok(report);
+    }
+
+    info!("Streaming transactions of {} addresses", addresses.len());
+    let mut transactions = source.subscribe(&addresses).await?;
+    let mut check = tokio::time::interval(resubscribe_every);
+    check.tick().await;
+
+    loop {
+        tokio::select! {
+            transaction = transactions.next() => {
+                let Some(transaction) = transaction else {
+                    return Ok(report);
+                };
+                let transaction = transaction?;
+                let (upserted, inserted) =
+                    record(pool, events, &transaction, whale_threshold_usd).await?;
+                report.transactions_received += 1;
+                report.transactions_upserted += upserted;
+                report.transactions_inserted += inserted;
+                debug!(
+                    "Streamed transaction {}: {} rows upserted",
+                    transaction.signature, upserted
+                );
+            }
+            _ = check.tick() => {
+                if tracked_addresses(pool).await? != addresses {
+                    info!("Tracked wallets changed; resubscribing");
+                    return Ok(report);
+                }
+            }
+        }
+    }
+}
+
+/// Spawns the worker streaming transactions from `source` into the database
+///
+/// The stream is resubscribed whenever it ends, fails or the tracked wallets change,
+/// backing off after consecutive failures. With several instances, only the one holding
+/// `leader` streams.
+pub fn spawn_geyser_worker(
+    state: AppState,
+    source: Arc<dyn GeyserSource>,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!("Starting Geyser streaming worker");
+
+    tokio::spawn(async move {
+        let mut failures = 0;
+        loop {
+            if !is_leader(&leader).await {
+                tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+                continue;
+            }
+
+            match stream(
+                &state.db_pool,
+                state.events.as_ref(),
+                source.as_ref(),
+                state.config.whale_threshold_usd,
+                RESUBSCRIBE_INTERVAL,
+            )
+            .await
+            {
+                Ok(report) => {
+                    failures = 0;
+                    if report.transactions_received > 0 {
+                        info!(
+                            "Geyser subscription ended after {} transactions",
+                            report.transactions_received
+                        );
+                    }
+                }
+                Err(err) => {
+                    failures += 1;
+                    let delay = backoff(failures);
+                    if failures > 1 {
+                        error!("Geyser stream failed {} times: {}", failures, err);
+                    } else {
+                        warn!("Geyser stream failed: {}", err);
+                    }
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,266 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgPool, Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analytics::{self, CostBasisMethod, TokenPnl};
+use crate::models::Portfolio;
+use crate::money::Usd;
+use crate::prices::PriceSource;
+use crate::AppError;
+
+/// Columns of a group, with its members aggregated earliest added first
+const GROUP_COLUMNS: &str = r#"
+    g.id, g.name,
+    COALESCE(
+        ARRAY_AGG(m.wallet_id ORDER BY m.added_at, m.wallet_id) FILTER (WHERE m.wallet_id IS NOT NULL),
+        '{}'::UUID[]
+    ) AS wallet_ids,
+    g.created_at, g.updated_at
+"#;
+
+/// A named set of wallets
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WalletGroup {
+    /// Unique identifier of the group
+    pub id: Uuid,
+    /// Name of the group, unique per user
+    #[schema(example = "Whales I copy")]
+    pub name: String,
+    /// IDs of the wallets in the group, earliest added first
+    pub wallet_ids: Vec<Uuid>,
+    /// When the group was created
+    pub created_at: DateTime<Utc>,
+    /// When the group was last renamed or its members changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request payload for creating a group
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateWalletGroup {
+    /// Name of the group
+    #[schema(example = "Whales I copy")]
+    pub name: String,
+    /// IDs of the caller's wallets to put in the group
+    #[serde(default)]
+    pub wallet_ids: Vec<Uuid>,
+}
+
+/// Request payload for updating a group; omitted fields are left unchanged
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct UpdateWalletGroup {
+    /// New name of the group
+    pub name: Option<String>,
+    /// Complete new list of member wallets, replacing the current one
+    pub wallet_ids: Option<Vec<Uuid>>,
+}
+
+/// Combined holdings and profit/loss of a group's wallets
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct GroupPortfolio {
+    /// ID of the group
+    pub group_id: Uuid,
+    /// Name of the group
+    pub name: String,
+    /// Holdings of the member wallets, merged per token
+    #[serde(flatten)]
+    pub portfolio: Portfolio,
+    /// Cost-basis method used for the PnL
+    pub method: CostBasisMethod,
+    /// Per-token PnL, treating the member wallets' trades as one history
+    pub tokens: Vec<TokenPnl>,
+    /// Sum of realized PnL across tokens, in USD
+    #[schema(value_type = f64)]
+    pub total_realized_pnl_usd: Usd,
+    /// Sum of unrealized PnL across tokens with a known price, in USD
+    #[schema(value_type = f64)]
+    pub total_unrealized_pnl_usd: Usd,
+}
+
+/// Trims a group name, rejecting empty ones
+fn validate_name(name: &str) -> Result<&str, AppError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::UnprocessableEntity(
+            "Group name must not be empty".to_string(),
+        ));
+    }
+    Ok(name)
+}
+
+/// Ensures every wallet in `wallet_ids` belongs to `user_id`
+async fn check_wallets(
+    tx: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    wallet_ids: &[Uuid],
+) -> Result<(), AppError> {
+    let owned =
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM wallets WHERE id = ANY($1) AND user_id = $2")
+            .bind(wallet_ids)
+            .bind(user_id)
+            .fetch_all(&mut **tx)
+            .await?;
+
+    match wallet_ids.iter().find(|id| !owned.contains(id)) {
+        Some(missing) => Err(AppError::NotFound(format!(
+            "Wallet with ID {missing} not found"
+        ))),
+        None => Ok(()),
+    }
+}
+
+/// Adds the wallets to the group, ignoring those already in it
+async fn add_members(
+    tx: &mut Transaction<'_, Postgres>,
+    group_id: Uuid,
+    wallet_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO wallet_group_members (group_id, wallet_id)
+        SELECT $1, wallet_id FROM UNNEST($2::UUID[]) AS w(wallet_id)
+        ON CONFLICT (group_id, wallet_id) DO NOTHING
+        "#,
+    )
+    .bind(group_id)
+    .bind(wallet_ids)
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+/// Validates and stores a new group for `user_id`
+pub async fn create_group(
+    pool: &PgPool,
+    user_id: Uuid,
+    request: &CreateWalletGroup,
+) -> Result<WalletGroup, AppError> {
+    let name = validate_name(&request.name)?;
+
+    let mut tx = pool.begin().await?;
+    check_wallets(&mut tx, user_id, &request.wallet_ids).await?;
+
+    let group_id = Uuid::now_v7();
+    sqlx::query("INSERT INTO wallet_groups (id, user_id, name) VALUES ($1, $2, $3)")
+        .bind(group_id)
+        .bind(user_id)
+        .bind(name)
+        .execute(&mut *tx)
+        .await?;
+    add_members(&mut tx, group_id, &request.wallet_ids).await?;
+    tx.commit().await?;
+
+    find_group(pool, user_id, group_id).await
+}
+
+/// Lists the groups of `user_id` by name
+pub async fn list_groups(pool: &PgPool, user_id: Uuid) -> Result<Vec<WalletGroup>, sqlx::Error> {
+    sqlx::query_as::<_, WalletGroup>(&format!(
+        r#"
+        SELECT {GROUP_COLUMNS}
+        FROM wallet_groups g
+        LEFT JOIN wallet_group_members m ON m.group_id = g.id
+        WHERE g.user_id = $1
+        GROUP BY g.id
+        ORDER BY g.name, g.id
+        "#
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Looks up a group owned by `user_id`, returning `404 Not Found` if it does not
+/// exist or belongs to someone else
+pub async fn find_group(
+    pool: &PgPool,
+    user_id: Uuid,
+    group_id: Uuid,
+) -> Result<WalletGroup, AppError> {
+    sqlx::query_as::<_, WalletGroup>(&format!(
+        r#"
+        SELECT {GROUP_COLUMNS}
+        FROM wallet_groups g
+        LEFT JOIN wallet_group_members m ON m.group_id = g.id
+        WHERE g.id = $1 AND g.user_id = $2
+        GROUP BY g.id
+        "#
+    ))
+    .bind(group_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Group with ID {group_id} not found")))
+}
+
+/// Renames a group and/or replaces its members
+pub async fn update_group(
+    pool: &PgPool,
+    user_id: Uuid,
+    group_id: Uuid,
+    request: &UpdateWalletGroup,
+) -> Result<WalletGroup, AppError> {
+    let name = request.name.as_deref().map(validate_name).transpose()?;
+
+    let mut tx = pool.begin().await?;
+    // Touches `updated_at` even when only the members change
+    sqlx::query_scalar::<_, Uuid>(
+        r#"
+        UPDATE wallet_groups
+        SET name = COALESCE($3, name), updated_at = NOW()
+        WHERE id = $1 AND user_id = $2
+        RETURNING id
+        "#,
+    )
+    .bind(group_id)
+    .bind(user_id)
+    .bind(name)
+    .fetch_optional(&mut *tx)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Group with ID {group_id} not found")))?;
+
+    if let Some(wallet_ids) = &request.wallet_ids {
+        check_wallets(&mut tx, user_id, wallet_ids).await?;
+        sqlx::query(
+            "DELETE FROM wallet_group_members WHERE group_id = $1 AND NOT (wallet_id = ANY($2))",
+        )
+        .bind(group_id)
+        .bind(wallet_ids)
+        .execute(&mut *tx)
+        .await?;
+        add_members(&mut tx, group_id, wallet_ids).await?;
+    }
+    tx.commit().await?;
+
+    find_group(pool, user_id, group_id).await
+}
+
+/// Merges the holdings and trade histories of a group's wallets
+pub async fn group_portfolio(
+    pool: &PgPool,
+    prices: &dyn PriceSource,
+    group: WalletGroup,
+    method: CostBasisMethod,
+) -> Result<GroupPortfolio, AppError> {
+    let portfolio = analytics::wallets_portfolio(pool, prices, &group.wallet_ids).await?;
+
+    let token_trades = analytics::load_wallets_token_trades(pool, &group.wallet_ids).await?;
+    let mints: Vec<String> = token_trades
+        .iter()
+        .map(|t| t.token_address.clone())
+        .collect();
+    let prices = prices.prices_usd(&mints).await?;
+    let tokens = analytics::token_pnl(token_trades, &prices, method);
+
+    Ok(GroupPortfolio {
+        group_id: group.id,
+        name: group.name,
+        portfolio,
+        method,
+        total_realized_pnl_usd: tokens.iter().map(|t| t.realized_pnl_usd).sum(),
+        total_unrealized_pnl_usd: tokens.iter().filter_map(|t| t.unrealized_pnl_usd).sum(),
+        tokens,
+    })
+}
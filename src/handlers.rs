@@ -1,25 +1,484 @@
+use std::collections::HashMap;
+use std::convert::Infallible;
+
 use axum::{
-    extract::{Path, Query, State},
+    body::Bytes,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        Path, Query, State,
+    },
+    http::{header, HeaderMap, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
     Json,
 };
-use bs58;
+use chrono::{DateTime, Datelike, Utc};
 use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::{errors::BroadcastStreamRecvError, BroadcastStream, ReceiverStream};
+use tokio_stream::{Stream, StreamExt};
 use tracing::{info, warn};
 use utoipa::ToSchema;
 use uuid::Uuid;
 
-use crate::models::{CreateWallet, Wallet};
+use crate::account::{self, AccountDeletion, DeleteAccountParams};
+use crate::alerts::{self, Alert, AlertEvent, CreateAlert, UpdateAlert};
+use crate::allocation::{self, WalletAllocation};
+use crate::analytics::{self, CostBasisMethod, TradeStats, WalletPnl};
+use crate::auth::lockout::SignInAttempt;
+use crate::auth::roles::{self, SetUserRole, UserRole};
+use crate::auth::sessions::{self, LoginRequest, LogoutRequest, RefreshRequest, TokenResponse};
+use crate::auth::siws::{self, NonceRequest, NonceResponse, VerifyRequest};
+use crate::auth::{self, AdminAuth, AuthUser};
+use crate::balances::{self, WalletBalances};
+use crate::billing::{self, EventError, StripeEvent, StripeEventReport};
+use crate::cache;
+use crate::candles::{self, CandleInterval, CandleRange, PriceCandles};
+use crate::classify::TransactionCategory;
+use crate::client_ip::ClientIp;
+use crate::conditional::conditional_json;
+use crate::config::WalletCountMode;
+use crate::dlq::{self, DeadLetter};
+use crate::domains;
+use crate::error::{internal_error, ValidationErrors};
+use crate::events::{DomainEvent, WalletEventKind};
+use crate::export::{self, ExportFormat, ExportParams};
+use crate::features::{Feature, FeatureStatus, SetFeature};
+use crate::fees::{self, FeeSummary};
+use crate::groups::{self, CreateWalletGroup, GroupPortfolio, UpdateWalletGroup, WalletGroup};
+use crate::helius::{self, EnhancedTransaction, WebhookReport};
+use crate::labels::{self, AddressLabel, SetAddressLabel};
+use crate::leaderboard::{self, Leaderboard};
+use crate::maintenance::ReadOnlyStatus;
+use crate::metering::{self, UsageParams, UsageReport};
+use crate::models::{
+    CreateUser, CreateWalletRequest, CreatedUser, Holding, Portfolio, Transaction, UpdateWallet,
+    Wallet, WalletAddress, WalletHoldings,
+};
+use crate::money::Usd;
+use crate::ndjson;
+use crate::notifications::{self, ChannelKind, ConfigureChannel, NotificationChannel};
+use crate::pagination::{self, clamp_per_page, default_per_page, Cursor, CursorParams, SortOrder};
+use crate::quotas::{self, Plan, PlanLimits, SetPlanLimits, SetUserQuota, UserQuota};
+use crate::reports::{self, Report, ReportPeriod, REPORT_COLUMNS};
+use crate::repository::{TransactionQuery, WalletFilter, WalletQuery, WalletSort};
+use crate::risk::{self, TokenRisk};
+use crate::share::{self, CreatedShareLink, PortfolioWidget, PublicPortfolio, ShareLink};
+use crate::snapshots::{self, HistoryRange, WalletHistory};
+use crate::spam::{self, BlockSpamToken, SpamFilter, SpamToken};
+use crate::stats::{self, ServerStats};
+use crate::sync::{self, SyncReport, NATIVE_SOL_MINT};
+use crate::sync_state::{self, SyncStatus};
+use crate::tax::{self, TaxReportParams};
+use crate::tokens::{self, TokenDetails, TokenMetadata};
+use crate::webhooks::{
+    self, CreateWebhookSubscription, CreatedWebhookSubscription, WebhookDelivery,
+    WebhookSubscription,
+};
+use crate::whales::{WhaleEvent, WHALE_EVENT_COLUMNS};
 use crate::{AppError, AppState};
 
 /// Helper function to create a conflict error
-fn validation_error(message: &str) -> AppError {
-    AppError::UnprocessableEntity(message.to_string())
-}
-
 fn conflict_error(message: &str) -> AppError {
     AppError::Conflict(message.to_string())
 }
 
+/// Looks up a wallet owned by `user`, returning `404 Not Found` if it does not
+/// exist or belongs to someone else
+async fn find_wallet(
+    state: &AppState,
+    user: AuthUser,
+    wallet_id: Uuid,
+) -> Result<Wallet, AppError> {
+    state
+        .wallets
+        .find(user.id, wallet_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Wallet with ID {wallet_id} not found")))
+}
+
+/// Evaluates balance threshold webhooks after new transactions, logging failures
+///
+/// A price feed outage must not fail the sync or webhook delivery that triggered it.
+async fn check_balance_thresholds(state: &AppState, wallet_id: Uuid) {
+    if let Err(err) =
+        webhooks::check_balance_thresholds(&state.db_pool, state.prices.as_ref(), wallet_id).await
+    {
+        warn!(
+            "Balance threshold check of wallet {} failed: {}",
+            wallet_id, err
+        );
+    }
+}
+
+/// Looks up the cached metadata of the given mints, fetching unknown or stale ones
+///
+/// Demo mode has no cache table, so the source is asked directly and failures leave
+/// the tokens unlabeled.
+async fn token_metadata(
+    state: &AppState,
+    mints: &[String],
+) -> Result<HashMap<String, TokenMetadata>, AppError> {
+    if state.config.is_demo() {
+        return Ok(state.metadata.metadata(mints).await.unwrap_or_default());
+    }
+
+    Ok(tokens::metadata_for(
+        &state.db_pool,
+        state.cache.as_ref(),
+        state.metadata.as_ref(),
+        mints,
+        state.config.token_metadata_ttl(),
+    )
+    .await?)
+}
+
+/// Create a new user
+///
+/// Creates a user and returns its API key. The key is shown only once.
+#[utoipa::path(
+    post,
+    path = "/users",
+    request_body = CreateUser,
+    responses(
+        (status = 200, description = "User created successfully", body = CreatedUser),
+        (status = 422, description = "Invalid input", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn create_user(
+    State(state): State<AppState>,
+    payload: Result<Json<CreateUser>, JsonRejection>,
+) -> Result<Json<CreatedUser>, AppError> {
+    let Json(payload) = payload?;
+
+    let (user, api_key) = auth::create_user(&state.db_pool, payload.name.as_deref()).await?;
+    info!("Created user with ID: {}", user.id);
+
+    Ok(Json(CreatedUser { user, api_key }))
+}
+
+/// Export the caller's data
+///
+/// Downloads everything stored about the caller as one JSON document: their profile,
+/// wallets with all their transactions, reports, alerts, groups, blocked spam tokens,
+/// notification channels and webhook subscriptions. The file is streamed in chunks
+/// while it is read from storage.
+#[utoipa::path(
+    get,
+    path = "/me/export",
+    tag = "account",
+    responses(
+        (status = 200, description = "JSON document of the caller's data", content_type = "application/json", body = Object),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn export_account(user: AuthUser, State(state): State<AppState>) -> Response {
+    info!("Exporting the data of user {}", user.id);
+
+    let stream = account::export(
+        state.db_pool.clone(),
+        state.secrets.clone(),
+        state.wallets.clone(),
+        state.transactions.clone(),
+        user.id,
+    );
+    account::export_response(stream, user.id)
+}
+
+/// Get the caller's API usage
+///
+/// Counts the caller's requests of the last `days` days, in total, per credential and
+/// per endpoint, with the bytes of the request and response bodies. Requests are
+/// written in batches, so the latest few seconds may be missing.
+#[utoipa::path(
+    get,
+    path = "/me/usage",
+    tag = "account",
+    params(
+        ("days" = Option<u32>, Query, description = "Number of days covered, counting today (default 30, max 90)")
+    ),
+    responses(
+        (status = 200, description = "Usage of the caller", body = UsageReport),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_usage(
+    user: AuthUser,
+    State(state): State<AppState>,
+    params: Result<Query<UsageParams>, QueryRejection>,
+) -> Result<Json<UsageReport>, AppError> {
+    let Query(params) = params?;
+    Ok(Json(
+        metering::report(&state.read_pool, user.id, params.days).await?,
+    ))
+}
+
+/// Delete the caller's account
+///
+/// Without `confirm`, requests the deletion and returns a confirmation token valid for
+/// 15 minutes. With `confirm=<token>`, schedules the purge of the account and all its
+/// data after the grace period (`ACCOUNT_DELETION_GRACE_SECS`, 7 days by default),
+/// which `DELETE /me/deletion` cancels until then.
+#[utoipa::path(
+    delete,
+    path = "/me",
+    tag = "account",
+    params(
+        ("confirm" = Option<String>, Query, description = "Confirmation token of a requested deletion")
+    ),
+    responses(
+        (status = 202, description = "Deletion requested or scheduled", body = AccountDeletion),
+        (status = 400, description = "Invalid or expired confirmation token, or demo mode", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 409, description = "Deletion already scheduled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn delete_account(
+    user: AuthUser,
+    State(state): State<AppState>,
+    params: Result<Query<DeleteAccountParams>, QueryRejection>,
+) -> Result<(StatusCode, Json<AccountDeletion>), AppError> {
+    let Query(params) = params?;
+    if state.config.is_demo() {
+        return Err(AppError::BadRequest(
+            "Accounts cannot be deleted in demo mode".to_string(),
+        ));
+    }
+
+    let deletion = match params.confirm.as_deref() {
+        None => account::request_deletion(&state.db_pool, user.id).await?,
+        Some(token) => {
+            let deletion = account::confirm_deletion(
+                &state.db_pool,
+                user.id,
+                token,
+                state.config.account_deletion_grace(),
+            )
+            .await?;
+            info!(
+                "Scheduled the deletion of user {} after {:?}",
+                user.id, deletion.purge_after
+            );
+            deletion
+        }
+    };
+
+    Ok((StatusCode::ACCEPTED, Json(deletion)))
+}
+
+/// Cancel the deletion of the caller's account
+///
+/// Cancels a requested or scheduled deletion before the account is purged.
+#[utoipa::path(
+    delete,
+    path = "/me/deletion",
+    tag = "account",
+    responses(
+        (status = 204, description = "Deletion cancelled"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "No deletion requested", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn cancel_account_deletion(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    if !account::cancel_deletion(&state.db_pool, user.id).await? {
+        return Err(AppError::NotFound(
+            "No account deletion requested".to_string(),
+        ));
+    }
+
+    info!("Cancelled the deletion of user {}", user.id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Request a Sign-In-With-Solana nonce
+///
+/// Returns a one-time message that the wallet must sign to sign in.
+#[utoipa::path(
+    post,
+    path = "/auth/siws/nonce",
+    request_body = NonceRequest,
+    responses(
+        (status = 200, description = "Nonce issued", body = NonceResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 422, description = "Invalid wallet address", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn siws_nonce(
+    State(state): State<AppState>,
+    payload: Result<Json<NonceRequest>, JsonRejection>,
+) -> Result<Json<NonceResponse>, AppError> {
+    let Json(payload) = payload?;
+
+    let nonce =
+        siws::issue_nonce(&state.db_pool, &state.config.siws_domain, &payload.address).await?;
+
+    Ok(Json(nonce))
+}
+
+/// Complete a Sign-In-With-Solana flow
+///
+/// Verifies the wallet's signature of the nonce message and starts a session with a
+/// JWT access token and a refresh token. The first sign-in of an address creates its
+/// user. Repeated failures lock out the address and the client for a while.
+#[utoipa::path(
+    post,
+    path = "/auth/siws/verify",
+    request_body = VerifyRequest,
+    responses(
+        (status = 200, description = "Signed in", body = TokenResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Invalid signature or nonce", body = ErrorResponse),
+        (status = 422, description = "Invalid wallet address", body = ErrorResponse),
+        (status = 429, description = "Too many failed sign-ins", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn siws_verify(
+    State(state): State<AppState>,
+    client: Option<ClientIp>,
+    payload: Result<Json<VerifyRequest>, JsonRejection>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let Json(payload) = payload?;
+
+    let attempt = SignInAttempt {
+        client_ip: client.map(|ClientIp(ip)| ip),
+        address: Some(payload.address.to_string()),
+    };
+    attempt.ensure_allowed(&state.db_pool).await?;
+    let user_id = match siws::verify_sign_in(&state.db_pool, &payload).await {
+        Ok(user_id) => user_id,
+        Err(err @ AppError::Unauthorized(_)) => {
+            attempt.failed(&state.db_pool).await?;
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+    attempt.succeeded(&state.db_pool).await?;
+    let tokens = sessions::start(&state, user_id).await?;
+    info!("User {} signed in with {}", user_id, payload.address);
+
+    Ok(Json(tokens))
+}
+
+/// Sign in with an API key
+///
+/// Starts a session with a short-lived JWT access token and a refresh token, so the
+/// API key does not have to be sent with every request. Repeated failures lock out the
+/// client for a while.
+#[utoipa::path(
+    post,
+    path = "/auth/login",
+    request_body = LoginRequest,
+    responses(
+        (status = 200, description = "Signed in", body = TokenResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Invalid API key", body = ErrorResponse),
+        (status = 429, description = "Too many failed sign-ins from the client", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn login(
+    State(state): State<AppState>,
+    client: Option<ClientIp>,
+    payload: Result<Json<LoginRequest>, JsonRejection>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let Json(payload) = payload?;
+
+    let attempt = SignInAttempt {
+        client_ip: client.map(|ClientIp(ip)| ip),
+        address: None,
+    };
+    attempt.ensure_allowed(&state.db_pool).await?;
+    let tokens = match sessions::login(&state, &payload.api_key).await {
+        Ok(tokens) => tokens,
+        Err(err @ AppError::Unauthorized(_)) => {
+            attempt.failed(&state.db_pool).await?;
+            return Err(err);
+        }
+        Err(err) => return Err(err),
+    };
+    info!(
+        "User {} signed in with an API key from {}",
+        tokens.user_id,
+        client.map_or("an unknown address".to_string(), |ClientIp(ip)| ip
+            .to_string())
+    );
+
+    Ok(Json(tokens))
+}
+
+/// Refresh an access token
+///
+/// Trades a refresh token for a new access token and refresh token of the same
+/// session. Every refresh token works once; presenting a used one again revokes the
+/// session.
+#[utoipa::path(
+    post,
+    path = "/auth/refresh",
+    request_body = RefreshRequest,
+    responses(
+        (status = 200, description = "Tokens refreshed", body = TokenResponse),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Invalid, expired, used or revoked refresh token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn refresh_token(
+    State(state): State<AppState>,
+    payload: Result<Json<RefreshRequest>, JsonRejection>,
+) -> Result<Json<TokenResponse>, AppError> {
+    let Json(payload) = payload?;
+
+    Ok(Json(
+        sessions::refresh(&state, &payload.refresh_token).await?,
+    ))
+}
+
+/// Sign out
+///
+/// Revokes the session of the refresh token, or all the user's sessions with
+/// `"all": true`. Access tokens of revoked sessions stop working immediately.
+#[utoipa::path(
+    post,
+    path = "/auth/logout",
+    request_body = LogoutRequest,
+    responses(
+        (status = 204, description = "Signed out"),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Unknown refresh token", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn logout(
+    State(state): State<AppState>,
+    payload: Result<Json<LogoutRequest>, JsonRejection>,
+) -> Result<StatusCode, AppError> {
+    let Json(payload) = payload?;
+
+    let revoked = sessions::logout(&state.db_pool, &payload.refresh_token, payload.all)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid refresh token".to_string()))?;
+    info!("Revoked {} sessions", revoked);
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
 /// Create a new wallet
 ///
 /// This endpoint creates a new wallet with the provided address.
@@ -27,75 +486,84 @@ fn conflict_error(message: &str) -> AppError {
     post,
     path = "/wallets",
     request_body = CreateWallet,
+    params(
+        ("Idempotency-Key" = Option<String>, Header, description = "Client-chosen key; retries with the same key and body replay the first response for 24 hours")
+    ),
     responses(
         (status = 200, description = "Wallet created successfully", body = Wallet),
         (status = 400, description = "Invalid input"),
-        (status = 409, description = "Wallet already exists"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 403, description = "The user's plan allows no more wallets (`wallet_quota_exceeded`)", body = ErrorResponse),
+        (status = 409, description = "Wallet already exists, or a request with the same Idempotency-Key is in progress"),
+        (status = 422, description = "Invalid fields, listed per field in `errors`, or an Idempotency-Key reused for a different request", body = ErrorResponse),
         (status = 500, description = "Internal server error")
-    )
+    ),
+    security(("api_key" = []))
 )]
 pub async fn add_wallet(
+    user: AuthUser,
     State(state): State<AppState>,
-    Json(payload): Json<CreateWallet>,
+    payload: Result<Json<CreateWalletRequest>, JsonRejection>,
 ) -> Result<Json<Wallet>, AppError> {
+    let Json(mut payload) = payload?;
     info!("Adding new wallet: {:?}", payload);
 
-    // Validate wallet address
-    let address = payload.address.trim();
-    if address.is_empty() {
-        return Err(validation_error("Address cannot be empty"));
-    }
-
-    // Validate address length
-    if address.len() > 44 {
-        return Err(validation_error("Address is too long (max 44 characters)"));
-    }
-
-    // Validate base58 encoding
-    if bs58::decode(address).into_vec().is_err() {
-        return Err(validation_error("Invalid address: must be base58 encoded"));
+    let domain = payload.address.as_deref().and_then(domains::parse_domain);
+    if let Some(domain) = &domain {
+        match state.domains.resolve(domain).await? {
+            Some(address) => payload.address = Some(address),
+            None => {
+                let mut errors = ValidationErrors::new();
+                errors.add("address", format!("Domain {domain} is not registered"));
+                return Err(errors.into());
+            }
+        }
     }
 
-    // Check for existing wallet with same address
-    let exists: bool = sqlx::query_scalar!(
-        "SELECT EXISTS(SELECT 1 FROM wallets WHERE address = $1)",
-        address
-    )
-    .fetch_one(&state.db_pool)
-    .await?
-    .unwrap_or(false);
+    let mut payload = payload.validate(state.config.require_on_curve_addresses)?;
+    let address = payload.address.as_str();
 
-    if exists {
+    // Check for an existing wallet with the same address for this user
+    if state
+        .wallets
+        .find_by_address(user.id, address)
+        .await?
+        .is_some()
+    {
         warn!("Attempt to add duplicate wallet address: {}", address);
         return Err(conflict_error("Wallet with this address already exists"));
     }
 
-    let id = Uuid::now_v7();
-    let now = chrono::Utc::now();
+    payload.domain = match domain {
+        Some(domain) => Some(domain),
+        // Domains are cosmetic, so a failing lookup does not keep the wallet from being added
+        None => state.domains.reverse(address).await.unwrap_or_else(|err| {
+            warn!("Domain lookup of {} failed: {}", address, err);
+            None
+        }),
+    };
 
-    let wallet = sqlx::query_as::<_, Wallet>(
-        r#"
-        INSERT INTO wallets (id, address, name, created_at, updated_at)
-        VALUES ($1, $2, $3, $4, $5)
-        RETURNING id, address, name, created_at, updated_at
-        "#,
-    )
-    .bind(id)
-    .bind(address)
-    .bind(payload.name)
-    .bind(now)
-    .bind(now)
-    .fetch_one(&state.db_pool)
-    .await?;
+    let slot = quotas::reserve_wallet(&state, user.id).await?;
+    let wallet = state.wallets.create(user.id, &payload).await?;
+    slot.release().await?;
+    state.events.publish(
+        wallet.id,
+        WalletEventKind::WalletCreated {
+            address: wallet.address.clone(),
+            name: wallet.name.clone(),
+        },
+    );
 
-    info!("Created wallet with ID: {}", id);
+    info!("Created wallet with ID: {}", wallet.id);
 
     Ok(Json(wallet))
 }
 
 /// Get wallet by ID
 ///
-/// Returns the wallet with the specified ID if it exists.
+/// Returns the wallet with the specified ID if it exists. The response carries an
+/// `ETag` and `Last-Modified`; a matching `If-None-Match` or `If-Modified-Since` gets
+/// `304 Not Modified` instead.
 #[utoipa::path(
     get,
     path = "/wallets/{id}",
@@ -104,31 +572,27 @@ pub async fn add_wallet(
     ),
     responses(
         (status = 200, description = "Wallet found", body = Wallet),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag or `If-Modified-Since` date"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
         (status = 404, description = "Wallet not found"),
         (status = 500, description = "Internal server error")
-    )
+    ),
+    security(("api_key" = []))
 )]
 pub async fn get_wallet(
+    user: AuthUser,
     Path(wallet_id): Path<Uuid>,
     State(state): State<AppState>,
-) -> Result<Json<Wallet>, AppError> {
+    headers: HeaderMap,
+) -> Result<Response, AppError> {
     info!("Fetching wallet with ID: {}", wallet_id);
 
-    let wallet = sqlx::query_as::<_, Wallet>(
-        r#"
-        SELECT id, address, name, created_at, updated_at
-        FROM wallets
-        WHERE id = $1
-        "#,
-    )
-    .bind(wallet_id)
-    .fetch_optional(&state.db_pool)
-    .await?;
+    let wallet = state.wallets.find(user.id, wallet_id).await?;
 
     match wallet {
         Some(wallet) => {
             info!("Found wallet with ID: {wallet_id}");
-            Ok(Json(wallet))
+            conditional_json(&headers, &wallet, Some(wallet.updated_at))
         }
         None => {
             warn!("Wallet not found with ID: {wallet_id}");
@@ -139,22 +603,161 @@ pub async fn get_wallet(
     }
 }
 
-/// Pagination parameters for list endpoints
-#[derive(Debug, Deserialize, ToSchema)]
-pub struct PaginationParams {
+/// Update a wallet
+///
+/// Changes the wallet's name, notes, metadata and/or leaderboard opt-out. Omitted fields are left
+/// unchanged and `null` clears a field; metadata is replaced as a whole.
+#[utoipa::path(
+    patch,
+    path = "/wallets/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    request_body = UpdateWallet,
+    responses(
+        (status = 200, description = "Wallet updated", body = Wallet),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 422, description = "Invalid notes or metadata, listed per field in `errors`", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn update_wallet(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    payload: Result<Json<UpdateWallet>, JsonRejection>,
+) -> Result<Json<Wallet>, AppError> {
+    let Json(payload) = payload?;
+    info!("Updating wallet with ID: {}", wallet_id);
+
+    payload.validate()?;
+
+    state
+        .wallets
+        .update(user.id, wallet_id, &payload)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Wallet with ID {wallet_id} not found")))
+}
+
+/// Delete a wallet
+///
+/// Stops tracking the wallet and deletes its transactions, holdings, snapshots and
+/// alerts, freeing a slot of the user's wallet quota.
+#[utoipa::path(
+    delete,
+    path = "/wallets/{id}",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 204, description = "Wallet deleted"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn delete_wallet(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    info!("Deleting wallet with ID: {}", wallet_id);
+
+    if !state.wallets.delete(user.id, wallet_id).await? {
+        return Err(AppError::NotFound(format!(
+            "Wallet with ID {wallet_id} not found"
+        )));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get wallet by address
+///
+/// Resolves one of the caller's wallets from its base58 address.
+#[utoipa::path(
+    get,
+    path = "/wallets/by-address/{address}",
+    params(
+        ("address" = String, Path, description = "Base58-encoded wallet address")
+    ),
+    responses(
+        (status = 200, description = "Wallet found", body = Wallet),
+        (status = 400, description = "Invalid wallet address", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Address not tracked", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_wallet_by_address(
+    user: AuthUser,
+    Path(address): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Wallet>, AppError> {
+    let address = WalletAddress::parse(&address)
+        .map_err(|err| AppError::BadRequest(format!("Invalid wallet address: {err}")))?;
+    info!("Fetching wallet with address: {}", address);
+
+    state
+        .wallets
+        .find_by_address(user.id, address.as_str())
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Wallet with address {address} not found")))
+}
+
+/// Query parameters for listing wallets
+///
+/// Pass `cursor` (the previous page's `next_cursor`) for keyset pagination;
+/// `page` is the legacy offset-based alternative and is ignored when a cursor is given.
+/// Cursors are only available for the default order, newest first.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletListParams {
     /// Page number (1-based)
     #[serde(default = "default_page")]
     pub page: i64,
     /// Number of items per page (max 100)
     #[serde(default = "default_per_page")]
     pub per_page: i64,
+    /// Cursor returned as `next_cursor` by the previous page
+    pub cursor: Option<String>,
+    /// Only wallets whose name contains this text, ignoring case
+    pub name_contains: Option<String>,
+    /// Only wallets added at or after this time
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only wallets added before this time
+    pub created_before: Option<DateTime<Utc>>,
+    /// Column to sort by
+    #[serde(default)]
+    pub sort: WalletSort,
+    /// Sort direction
+    #[serde(default)]
+    pub order: SortOrder,
 }
 
 fn default_page() -> i64 {
     1
 }
-fn default_per_page() -> i64 {
-    50
+
+impl Default for WalletListParams {
+    fn default() -> Self {
+        Self {
+            page: default_page(),
+            per_page: default_per_page(),
+            cursor: None,
+            name_contains: None,
+            created_after: None,
+            created_before: None,
+            sort: WalletSort::default(),
+            order: SortOrder::default(),
+        }
+    }
 }
 
 /// Paginated response wrapper
@@ -162,86 +765,3048 @@ fn default_per_page() -> i64 {
 pub struct PaginatedWallets {
     /// List of wallets in the current page
     pub items: Vec<Wallet>,
-    /// Total number of items across all pages
-    pub total: i64,
-    /// Current page number (1-based)
+    /// Total number of items across all pages; an estimate or a few seconds old if
+    /// the server is configured so, and `null` if it does not count them
+    pub total: Option<i64>,
+    /// Current page number (1-based); only meaningful for offset pagination
     pub page: i64,
     /// Number of items per page
     pub per_page: i64,
-    /// Total number of pages
-    pub total_pages: i64,
+    /// Total number of pages, `null` along with `total`
+    pub total_pages: Option<i64>,
+    /// Cursor of the next page, or `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Total of a user's wallets matching the filter, computed as configured
+async fn wallet_total(
+    state: &AppState,
+    user_id: Uuid,
+    filter: &WalletFilter,
+) -> Result<Option<i64>, AppError> {
+    let total = match state.config.wallet_count_mode {
+        WalletCountMode::Exact => state.wallets.count(user_id, filter).await?,
+        WalletCountMode::Estimated => state.wallets.estimate_count(user_id, filter).await?,
+        WalletCountMode::Cached => {
+            let key = format!(
+                "wallet_count:{user_id}:{}",
+                serde_json::json!([
+                    filter.name_contains,
+                    filter.created_after,
+                    filter.created_before
+                ])
+            );
+            match cache::get_json(state.cache.as_ref(), &key).await {
+                Some(total) => total,
+                None => {
+                    let total = state.wallets.count(user_id, filter).await?;
+                    let ttl = state.config.wallet_count_cache_ttl();
+                    cache::set_json(state.cache.as_ref(), &key, &total, ttl).await;
+                    total
+                }
+            }
+        }
+        WalletCountMode::Off => return Ok(None),
+    };
+    Ok(Some(total))
 }
 
 /// List wallets with pagination
 ///
-/// Returns a paginated list of wallets in the system.
+/// Returns a paginated list of the caller's wallets, newest first unless another
+/// order is requested. Supports conditional requests like `GET /wallets/{id}`.
 #[utoipa::path(
     get,
     path = "/wallets",
     params(
-        ("page" = Option<i64>, Query, description = "Page number (1-based)"),
-        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)")
+        ("page" = Option<i64>, Query, description = "Page number (1-based), for offset pagination"),
+        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page to fetch, from `next_cursor`"),
+        ("name_contains" = Option<String>, Query, description = "Only wallets whose name contains this text, ignoring case"),
+        ("created_after" = Option<String>, Query, description = "Only wallets added at or after this RFC 3339 time"),
+        ("created_before" = Option<String>, Query, description = "Only wallets added before this RFC 3339 time"),
+        ("sort" = Option<WalletSort>, Query, description = "Column to sort by: created_at (default) or name"),
+        ("order" = Option<SortOrder>, Query, description = "Sort direction: desc (default) or asc")
     ),
     responses(
         (status = 200, description = "Paginated list of wallets", body = PaginatedWallets),
-        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 304, description = "Not modified since the `If-None-Match` ETag or `If-Modified-Since` date"),
+        (status = 400, description = "Invalid pagination, filter or sort parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
         (status = 500, description = "Internal server error", body = ErrorResponse)
-    )
+    ),
+    security(("api_key" = []))
 )]
 pub async fn list_wallets(
+    user: AuthUser,
     State(state): State<AppState>,
-    pagination: Option<Query<PaginationParams>>,
-) -> Result<Json<PaginatedWallets>, AppError> {
-    info!("Listing wallets with pagination: {:?}", pagination);
-
-    let pagination = pagination.unwrap_or_else(|| {
-        Query(PaginationParams {
-            page: default_page(),
-            per_page: default_per_page(),
-        })
-    });
+    headers: HeaderMap,
+    params: Result<Query<WalletListParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    info!("Listing wallets with parameters: {:?}", params);
 
-    let page = pagination.page.max(1);
-    let per_page = pagination.per_page.clamp(1, 100); // Cap at 100 items per page
-    let offset = (page - 1) * per_page;
+    let default_order = params.sort == WalletSort::CreatedAt && params.order == SortOrder::Desc;
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    if cursor.is_some() && !default_order {
+        return Err(AppError::BadRequest(
+            "Cursor pagination is only supported with sort=created_at&order=desc".to_string(),
+        ));
+    }
 
-    // Get total count
-    let total_result =
-        sqlx::query_scalar::<_, Option<i64>>(r#"SELECT COUNT(*) as count FROM wallets"#)
-            .fetch_one(&state.db_pool)
-            .await?;
+    let page = params.page.max(1);
+    let per_page = clamp_per_page(params.per_page);
+    let offset = if cursor.is_some() {
+        0
+    } else {
+        (page - 1) * per_page
+    };
+    let filter = WalletFilter {
+        name_contains: params.name_contains,
+        created_after: params.created_after,
+        created_before: params.created_before,
+    };
 
-    let total = total_result.unwrap_or(0);
+    let total = wallet_total(&state, user.id, &filter).await?;
 
-    // Get paginated results
-    let wallets = sqlx::query_as!(
-        Wallet,
-        r#"
-        SELECT id, address, name, created_at, updated_at 
-        FROM wallets 
-        ORDER BY created_at DESC
-        LIMIT $1 OFFSET $2
-        "#,
-        per_page,
-        offset
-    )
-    .fetch_all(&state.db_pool)
-    .await?;
+    // Get paginated results, fetching one extra row to detect the next page
+    let wallets = state
+        .wallets
+        .list(
+            user.id,
+            &WalletQuery {
+                filter,
+                sort: params.sort,
+                order: params.order,
+                cursor,
+                limit: per_page + 1,
+                offset,
+            },
+        )
+        .await?;
 
-    let total_pages = (total as f64 / per_page as f64).ceil() as i64;
+    let (wallets, next_cursor) =
+        pagination::next_page(wallets, per_page, |w| Cursor::new(w.created_at, w.id));
+    // Cursors only follow the default order
+    let next_cursor = next_cursor.filter(|_| default_order);
+    let total_pages = total.map(|total| (total as f64 / per_page as f64).ceil() as i64);
 
     info!(
-        "Returning {} wallets (page {} of {})",
+        "Returning {} wallets (page {} of {:?})",
         wallets.len(),
         page,
         total_pages
     );
 
-    Ok(Json(PaginatedWallets {
+    // Removed wallets do not move the date back, so only the ETag reflects them
+    let last_modified = wallets.iter().map(|w| w.updated_at).max();
+    let page = PaginatedWallets {
         items: wallets,
         total,
         page,
         per_page,
         total_pages,
+        next_cursor,
+    };
+    conditional_json(&headers, &page, last_modified)
+}
+
+/// A page of a wallet's transactions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PaginatedTransactions {
+    /// Transactions in the current page, newest first
+    pub items: Vec<Transaction>,
+    /// Number of items per page
+    pub per_page: i64,
+    /// Cursor of the next page, or `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Query parameters for listing a wallet's transactions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct TransactionListParams {
+    /// Cursor returned as `next_cursor` by the previous page; omit for the first page
+    pub cursor: Option<String>,
+    /// Number of items per page (max 100)
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+    /// Only return transactions of this category
+    pub category: Option<TransactionCategory>,
+}
+
+impl Default for TransactionListParams {
+    fn default() -> Self {
+        Self {
+            cursor: None,
+            per_page: default_per_page(),
+            category: None,
+        }
+    }
+}
+
+/// Transactions labelled with token metadata per NDJSON chunk
+const NDJSON_BATCH_SIZE: usize = 500;
+
+/// NDJSON chunks buffered ahead of a slow client before reading from storage pauses
+const NDJSON_CHANNEL_CAPACITY: usize = 4;
+
+/// Streams a wallet's transactions after `cursor` as NDJSON, labelled with token
+/// metadata in batches
+fn stream_transactions(
+    state: AppState,
+    wallet_id: Uuid,
+    cursor: Option<Cursor>,
+    category: Option<TransactionCategory>,
+) -> Response {
+    let (sender, receiver) = mpsc::channel::<Result<Bytes, AppError>>(NDJSON_CHANNEL_CAPACITY);
+
+    tokio::spawn(async move {
+        let result: Result<(), AppError> = async {
+            let mut rows = state
+                .transactions
+                .stream(wallet_id, cursor, category)
+                .await?;
+            let mut labels: HashMap<String, TokenMetadata> = HashMap::new();
+            let mut batch = Vec::with_capacity(NDJSON_BATCH_SIZE);
+
+            loop {
+                let row = rows.next().await.transpose()?;
+                let done = row.is_none();
+                batch.extend(row);
+                if batch.len() < NDJSON_BATCH_SIZE && !done {
+                    continue;
+                }
+                if batch.is_empty() {
+                    return Ok(());
+                }
+
+                let mut mints: Vec<String> = batch
+                    .iter()
+                    .map(|t| t.token_address.clone())
+                    .filter(|mint| !labels.contains_key(mint))
+                    .collect();
+                mints.sort();
+                mints.dedup();
+                if !mints.is_empty() {
+                    labels.extend(token_metadata(&state, &mints).await?);
+                }
+
+                let mut chunk = Vec::new();
+                for transaction in batch.drain(..) {
+                    let token = labels.get(&transaction.token_address);
+                    ndjson::push_line(&mut chunk, &transaction.with_metadata(token))
+                        .map_err(internal_error)?;
+                }
+                // Stops reading once the client has gone away
+                if sender.send(Ok(Bytes::from(chunk))).await.is_err() || done {
+                    return Ok(());
+                }
+            }
+        }
+        .await;
+
+        if let Err(err) = result {
+            warn!(
+                "Streaming transactions of wallet {} failed: {}",
+                wallet_id, err
+            );
+            let _ = sender.send(Err(err)).await;
+        }
+    });
+
+    ndjson::ndjson_response(ReceiverStream::new(receiver))
+}
+
+/// List wallet transactions
+///
+/// Returns the wallet's recorded transactions, newest first, using cursor pagination.
+///
+/// With `Accept: application/x-ndjson`, every transaction after the cursor is
+/// streamed instead, one JSON object per line, and `per_page` is ignored. Rows are
+/// sent as they are read from storage, so lists of any size can be downloaded.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/transactions",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page to fetch, from `next_cursor`"),
+        ("category" = Option<TransactionCategory>, Query, description = "Only return transactions of this category"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Page of transactions, or all transactions as NDJSON", content(
+            ("application/json" = PaginatedTransactions),
+            ("application/x-ndjson" = Transaction)
+        )),
+        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_transactions(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    params: Result<Query<TransactionListParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    info!("Listing transactions for wallet with ID: {}", wallet_id);
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    if ndjson::accepts_ndjson(&headers) {
+        return Ok(stream_transactions(
+            state,
+            wallet.id,
+            cursor,
+            params.category,
+        ));
+    }
+    let per_page = clamp_per_page(params.per_page);
+
+    let transactions = state
+        .transactions
+        .list(
+            wallet.id,
+            &TransactionQuery {
+                cursor,
+                category: params.category,
+                limit: per_page + 1,
+            },
+        )
+        .await?;
+
+    let (items, next_cursor) =
+        pagination::next_page(transactions, per_page, |t| Cursor::new(t.created_at, t.id));
+
+    let mut mints: Vec<String> = items.iter().map(|t| t.token_address.clone()).collect();
+    mints.sort();
+    mints.dedup();
+    let metadata = token_metadata(&state, &mints).await?;
+    let items = items
+        .into_iter()
+        .map(|t| {
+            let token = metadata.get(&t.token_address);
+            t.with_metadata(token)
+        })
+        .collect();
+
+    Ok(Json(PaginatedTransactions {
+        items,
+        per_page,
+        next_cursor,
+    })
+    .into_response())
+}
+
+/// Sync wallet transactions from the Solana RPC
+///
+/// Fetches the wallet's most recent confirmed signatures and upserts the parsed
+/// transactions into the transactions table.
+#[utoipa::path(
+    post,
+    path = "/wallets/{id}/sync",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 200, description = "Wallet synced", body = SyncReport),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 429, description = "The user used up today's syncs (`sync_quota_exceeded`)", body = ErrorResponse),
+        (status = 503, description = "Solana RPC unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn sync_wallet(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<SyncReport>, AppError> {
+    info!("Syncing transactions for wallet with ID: {}", wallet_id);
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    quotas::consume_sync(&state, user.id).await?;
+
+    let report = sync::sync_wallet(
+        &state.db_pool,
+        &state.rpc,
+        state.events.as_ref(),
+        &wallet,
+        state.config.sync_signature_limit,
+        state.config.whale_threshold_usd,
+    )
+    .await?;
+
+    info!(
+        "Synced wallet {}: {} transactions upserted",
+        wallet_id, report.transactions_upserted
+    );
+    domains::refresh(&state.db_pool, state.domains.as_ref(), &wallet).await?;
+
+    if report.transactions_inserted > 0 {
+        check_balance_thresholds(&state, wallet.id).await;
+    }
+
+    Ok(Json(report))
+}
+
+/// Get wallet sync status
+///
+/// Reports whether the wallet is being synced or backfilled, how far the latest run
+/// has got and how it ended. Wallets never synced are reported idle.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/sync-status",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 200, description = "Wallet sync status", body = SyncStatus),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_sync_status(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<SyncStatus>, AppError> {
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+
+    Ok(Json(sync_state::status(&state.db_pool, wallet.id).await?))
+}
+
+/// Query parameters for the holdings endpoint
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct HoldingsParams {
+    /// Whether tokens filtered out as spam are included
+    #[serde(default)]
+    pub include_spam: bool,
+}
+
+/// Get wallet holdings
+///
+/// Returns the wallet's net position in each token, aggregated from its
+/// transactions and valued at current USD prices. Tokens on the caller's spam
+/// blocklist, worth less than the server's spam threshold or labelled like scam
+/// airdrops are left out unless `include_spam=true`.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/holdings",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("include_spam" = Option<bool>, Query, description = "Include tokens filtered out as spam (default false)"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Wallet holdings", body = WalletHoldings),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_holdings(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<HoldingsParams>, QueryRejection>,
+) -> Result<Json<WalletHoldings>, AppError> {
+    let Query(params) = params?;
+    info!("Fetching holdings for wallet with ID: {}", wallet_id);
+
+    let user_id = user.id;
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let mut holdings =
+        analytics::wallet_holdings(&state.db_pool, state.prices.as_ref(), wallet.id).await?;
+    holdings.holdings = label_holdings(&state, holdings.holdings).await?;
+
+    if !params.include_spam {
+        let filter = SpamFilter::load(&state.read_pool, &state.config, user_id).await?;
+        holdings.holdings.retain(|holding| !filter.is_spam(holding));
+        holdings.total_value_usd = holdings.holdings.iter().filter_map(|h| h.value_usd).sum();
+    }
+
+    Ok(Json(holdings))
+}
+
+/// Get live wallet balances
+///
+/// Reads the wallet's SOL and SPL token balances live from the Solana RPC and compares
+/// them with the holdings computed from its recorded transactions. Tokens whose
+/// balances disagree reveal gaps in the transaction history.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/balances",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 200, description = "Live balances reconciled against holdings", body = WalletBalances),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 502, description = "Solana RPC error", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_balances(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<WalletBalances>, AppError> {
+    info!("Reconciling live balances of wallet with ID: {}", wallet_id);
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let balances = balances::reconcile(&state.db_pool, &state.rpc, &wallet).await?;
+    if balances.discrepancies > 0 {
+        info!(
+            "Wallet {} has {} balance discrepancies",
+            wallet.id, balances.discrepancies
+        );
+    }
+
+    Ok(Json(balances))
+}
+
+/// Query parameters for the allocation endpoint
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct AllocationParams {
+    /// Positions worth less than this many USD are left out as dust (default 0)
+    #[serde(default)]
+    pub dust_threshold_usd: f64,
+}
+
+/// Get wallet allocation
+///
+/// Breaks the wallet's current value down by token and by asset class (SOL,
+/// stablecoins and memecoins), as percentages suitable for a pie chart. Positions
+/// without a price or below the dust threshold are left out.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/allocation",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("dust_threshold_usd" = Option<f64>, Query, description = "Leave out positions worth less than this many USD (default 0)"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Wallet allocation", body = WalletAllocation),
+        (status = 400, description = "Invalid dust threshold", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_allocation(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<AllocationParams>, QueryRejection>,
+) -> Result<Json<WalletAllocation>, AppError> {
+    let Query(params) = params?;
+    if !params.dust_threshold_usd.is_finite() || params.dust_threshold_usd < 0.0 {
+        return Err(AppError::BadRequest(
+            "dust_threshold_usd must be a non-negative number".to_string(),
+        ));
+    }
+    info!("Computing allocation for wallet with ID: {}", wallet_id);
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let mut holdings =
+        analytics::wallet_holdings(&state.read_pool, state.prices.as_ref(), wallet.id).await?;
+    holdings.holdings = label_holdings(&state, holdings.holdings).await?;
+
+    Ok(Json(allocation::allocation(
+        holdings,
+        params.dust_threshold_usd,
+    )))
+}
+
+/// Export wallet transactions
+///
+/// Downloads all of the wallet's transactions, newest first, as CSV. The file is
+/// streamed in chunks while it is read from storage.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/transactions/export",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("format" = Option<ExportFormat>, Query, description = "File format: csv (default)"),
+        ("category" = Option<TransactionCategory>, Query, description = "Only export transactions of this category")
+    ),
+    responses(
+        (status = 200, description = "CSV file of the transactions", content_type = "text/csv", body = String),
+        (status = 400, description = "Invalid format or category", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn export_transactions(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<ExportParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    info!("Exporting transactions of wallet with ID: {}", wallet_id);
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let filename = format!("wallet-{}-transactions.csv", wallet.id);
+
+    match params.format {
+        ExportFormat::Csv => Ok(export::csv_response(
+            &filename,
+            export::transactions_csv(state.transactions.clone(), wallet.id, params.category),
+        )),
+    }
+}
+
+/// Export wallet holdings
+///
+/// Downloads the wallet's current holdings, valued at current USD prices, as CSV.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/holdings/export",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("format" = Option<ExportFormat>, Query, description = "File format: csv (default)")
+    ),
+    responses(
+        (status = 200, description = "CSV file of the holdings", content_type = "text/csv", body = String),
+        (status = 400, description = "Invalid format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn export_holdings(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<ExportParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    info!("Exporting holdings of wallet with ID: {}", wallet_id);
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let holdings =
+        analytics::wallet_holdings(&state.read_pool, state.prices.as_ref(), wallet.id).await?;
+    let holdings = label_holdings(&state, holdings.holdings).await?;
+    let filename = format!("wallet-{}-holdings.csv", wallet.id);
+
+    match params.format {
+        ExportFormat::Csv => Ok(export::csv_response(
+            &filename,
+            export::holdings_csv(holdings),
+        )),
+    }
+}
+
+/// Query parameters for the history endpoint
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct HistoryParams {
+    /// Number of days to return, e.g. `30d` (default)
+    #[serde(default)]
+    #[schema(value_type = String, example = "30d")]
+    pub range: HistoryRange,
+}
+
+/// Get wallet value history
+///
+/// Returns the wallet's total value as recorded by the daily snapshot job,
+/// one point per day, for charting.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/history",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("range" = Option<String>, Query, description = "Number of days to return, e.g. 7d or 30d (default)"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Daily value time series", body = WalletHistory),
+        (status = 400, description = "Invalid range", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_history(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<HistoryParams>, QueryRejection>,
+) -> Result<Json<WalletHistory>, AppError> {
+    let Query(params) = params?;
+    info!(
+        "Fetching {} history for wallet with ID: {}",
+        params.range, wallet_id
+    );
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let history = snapshots::wallet_history(&state.read_pool, wallet.id, params.range).await?;
+
+    Ok(Json(history))
+}
+
+/// Query parameters for the leaderboard
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct LeaderboardParams {
+    /// Period to rank over, e.g. `7d` (default)
+    #[serde(default = "default_leaderboard_period")]
+    #[schema(value_type = String, example = "7d")]
+    pub period: HistoryRange,
+    /// Number of wallets to return (default 50, max 100)
+    pub limit: Option<i64>,
+}
+
+fn default_leaderboard_period() -> HistoryRange {
+    HistoryRange::days(7)
+}
+
+/// Get the PnL leaderboard
+///
+/// Ranks tracked wallets by their realized and unrealized PnL over the period, as a
+/// percentage, based on the daily snapshots. Wallets opted out with
+/// `leaderboard_opt_out` are left out; the caller's own wallets carry their ID.
+#[utoipa::path(
+    get,
+    path = "/leaderboard",
+    tag = "leaderboard",
+    params(
+        ("period" = Option<String>, Query, description = "Period to rank over, e.g. 7d (default) or 30d"),
+        ("limit" = Option<i64>, Query, description = "Number of wallets to return (default 50, max 100)"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Leaderboard", body = Leaderboard),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_leaderboard(
+    user: AuthUser,
+    State(state): State<AppState>,
+    params: Result<Query<LeaderboardParams>, QueryRejection>,
+) -> Result<Json<Leaderboard>, AppError> {
+    let Query(params) = params?;
+    let limit = params
+        .limit
+        .unwrap_or(leaderboard::DEFAULT_LEADERBOARD_ENTRIES)
+        .clamp(1, leaderboard::MAX_LEADERBOARD_ENTRIES);
+    info!("Fetching the {} leaderboard", params.period);
+
+    Ok(Json(
+        leaderboard::leaderboard(&state.read_pool, user.id, params.period, limit).await?,
+    ))
+}
+
+/// Get the portfolio across all wallets
+///
+/// Merges the holdings of every wallet of the caller into a single view,
+/// with totals in USD and SOL.
+#[utoipa::path(
+    get,
+    path = "/portfolio",
+    params(
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Combined holdings", body = Portfolio),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_portfolio(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Portfolio>, AppError> {
+    info!("Fetching portfolio of user {}", user.id);
+
+    let mut portfolio =
+        analytics::user_portfolio(&state.read_pool, state.prices.as_ref(), user.id).await?;
+    portfolio.holdings = label_holdings(&state, portfolio.holdings).await?;
+
+    Ok(Json(portfolio))
+}
+
+/// Adds token metadata to each holding
+async fn label_holdings(
+    state: &AppState,
+    holdings: Vec<Holding>,
+) -> Result<Vec<Holding>, AppError> {
+    let mints: Vec<String> = holdings.iter().map(|h| h.token_address.clone()).collect();
+    let metadata = token_metadata(state, &mints).await?;
+
+    Ok(holdings
+        .into_iter()
+        .map(|h| {
+            let token = metadata.get(&h.token_address);
+            h.with_metadata(token)
+        })
+        .collect())
+}
+
+/// Stream wallet events
+///
+/// Returns a Server-Sent Events stream of the wallet's activity. Each event is named
+/// after its `type` and carries the JSON-encoded [`WalletEvent`](crate::events::WalletEvent). A `lagged` event
+/// reports how many events a slow client missed.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/events",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 200, description = "Stream of wallet events", body = WalletEvent, content_type = "text/event-stream"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn wallet_events(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    info!("Streaming events for wallet with ID: {}", wallet.id);
+
+    let stream =
+        BroadcastStream::new(state.events.subscribe()).filter_map(move |event| match event {
+            Ok(event) => match event.as_ref() {
+                DomainEvent::Wallet(event) if event.wallet_id == wallet.id => {
+                    Some(Ok(Event::default()
+                        .event(event.kind.name())
+                        .json_data(event)
+                        .unwrap_or_else(|_| {
+                            Event::default().comment("unserializable event")
+                        })))
+                }
+                _ => None,
+            },
+            Err(BroadcastStreamRecvError::Lagged(skipped)) => Some(Ok(Event::default()
+                .event("lagged")
+                .data(skipped.to_string()))),
+        });
+
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Query parameters of `GET /events/whale`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct WhaleEventParams {
+    /// Only list movements of this wallet
+    pub wallet_id: Option<Uuid>,
+    /// Only list movements worth at least this many USD
+    pub min_value_usd: Option<f64>,
+    /// Cursor returned as `next_cursor` by the previous page; omit for the first page
+    pub cursor: Option<String>,
+    /// Number of items per page (max 100)
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+}
+
+/// A page of whale movements
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PaginatedWhaleEvents {
+    /// Movements in the current page, newest first
+    pub items: Vec<WhaleEvent>,
+    /// Number of items per page
+    pub per_page: i64,
+    /// Cursor of the next page, or `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// List whale movements
+///
+/// Returns the transactions of the caller's wallets flagged as whale movements, newest
+/// first, using cursor pagination. Transactions are flagged when they are recorded and
+/// worth at least the server's `WHALE_THRESHOLD_USD`.
+#[utoipa::path(
+    get,
+    path = "/events/whale",
+    params(
+        ("wallet_id" = Option<Uuid>, Query, description = "Only list movements of this wallet"),
+        ("min_value_usd" = Option<f64>, Query, description = "Only list movements worth at least this many USD"),
+        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page to fetch, from `next_cursor`"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Page of whale movements", body = PaginatedWhaleEvents),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_whale_events(
+    user: AuthUser,
+    State(state): State<AppState>,
+    params: Result<Query<WhaleEventParams>, QueryRejection>,
+) -> Result<Json<PaginatedWhaleEvents>, AppError> {
+    let Query(params) = params?;
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let per_page = clamp_per_page(params.per_page);
+
+    let events = sqlx::query_as::<_, WhaleEvent>(&format!(
+        r#"
+        SELECT {WHALE_EVENT_COLUMNS}
+        FROM whale_events
+        WHERE wallet_id IN (SELECT id FROM wallets WHERE user_id = $1)
+          AND ($2::UUID IS NULL OR wallet_id = $2)
+          AND ($3::NUMERIC IS NULL OR value_usd >= $3)
+          AND ($4::TIMESTAMPTZ IS NULL OR (created_at, id) < ($4, $5))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $6
+        "#
+    ))
+    .bind(user.id)
+    .bind(params.wallet_id)
+    .bind(params.min_value_usd.map(Usd::from_f64))
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.id))
+    .bind(per_page + 1)
+    .fetch_all(&state.read_pool)
+    .await?;
+
+    let (items, next_cursor) =
+        pagination::next_page(events, per_page, |e| Cursor::new(e.created_at, e.id));
+
+    Ok(Json(PaginatedWhaleEvents {
+        items,
+        per_page,
+        next_cursor,
+    }))
+}
+
+/// Query parameters for the PnL endpoint
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct PnlParams {
+    /// Cost-basis method (`fifo`, `lifo` or `avg`)
+    #[serde(default)]
+    pub method: CostBasisMethod,
+}
+
+/// Get wallet profit and loss
+///
+/// Computes realized and unrealized profit per token from the wallet's
+/// transactions using the requested cost-basis method.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/pnl",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("method" = Option<CostBasisMethod>, Query, description = "Cost-basis method: fifo (default), lifo or avg"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Wallet PnL", body = WalletPnl),
+        (status = 400, description = "Invalid cost-basis method", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_pnl(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<PnlParams>, QueryRejection>,
+) -> Result<Json<WalletPnl>, AppError> {
+    let Query(params) = params?;
+    info!(
+        "Computing {:?} PnL for wallet with ID: {}",
+        params.method, wallet_id
+    );
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let token_trades = analytics::load_token_trades(&state.read_pool, wallet.id).await?;
+
+    let mints: Vec<String> = token_trades
+        .iter()
+        .map(|t| t.token_address.clone())
+        .collect();
+    let prices = state.prices.prices_usd(&mints).await?;
+
+    let tokens = analytics::token_pnl(token_trades, &prices, params.method);
+
+    Ok(Json(WalletPnl {
+        wallet_id: wallet.id,
+        method: params.method,
+        total_realized_pnl_usd: tokens.iter().map(|t| t.realized_pnl_usd).sum(),
+        total_unrealized_pnl_usd: tokens.iter().filter_map(|t| t.unrealized_pnl_usd).sum(),
+        tokens,
+    }))
+}
+
+/// Get wallet trade statistics
+///
+/// Matches the wallet's swap sells with its purchases using the requested cost-basis
+/// method and reports the win rate, average and median hold time, the largest win and
+/// loss, and the fees paid.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/stats",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("method" = Option<CostBasisMethod>, Query, description = "Cost-basis method: fifo (default), lifo or avg"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Trade statistics", body = TradeStats),
+        (status = 400, description = "Invalid cost-basis method", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_trade_stats(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<PnlParams>, QueryRejection>,
+) -> Result<Json<TradeStats>, AppError> {
+    let Query(params) = params?;
+    info!(
+        "Computing {:?} trade statistics for wallet with ID: {}",
+        params.method, wallet_id
+    );
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let token_trades = analytics::load_token_trades(&state.read_pool, wallet.id).await?;
+
+    Ok(Json(analytics::trade_stats(
+        wallet.id,
+        &token_trades,
+        params.method,
+    )))
+}
+
+/// Query parameters for the fees endpoint
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct FeeParams {
+    /// Number of days to sum up, e.g. `30d` (default)
+    #[serde(default)]
+    #[schema(value_type = String, example = "30d")]
+    pub period: HistoryRange,
+}
+
+/// Get wallet fees
+///
+/// Sums up the network fees, priority fees and Jito tips the wallet paid in its
+/// recorded transactions over the period, in SOL and at the current SOL price.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/fees",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("period" = Option<String>, Query, description = "Number of days to sum up, e.g. 7d or 30d (default)"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Fee summary", body = FeeSummary),
+        (status = 400, description = "Invalid period", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_fees(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<FeeParams>, QueryRejection>,
+) -> Result<Json<FeeSummary>, AppError> {
+    let Query(params) = params?;
+    info!(
+        "Summing up {} of fees for wallet with ID: {}",
+        params.period, wallet_id
+    );
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let mut summary = fees::summary(&state.read_pool, wallet.id, params.period).await?;
+
+    // The summary is useful without a price, so a failing price feed only leaves out USD
+    match state
+        .prices
+        .prices_usd(&[NATIVE_SOL_MINT.to_string()])
+        .await
+    {
+        Ok(prices) => {
+            summary.total_usd = prices
+                .get(NATIVE_SOL_MINT)
+                .map(|price| price * summary.total_sol)
+        }
+        Err(err) => warn!("Could not price the fees of wallet {}: {}", wallet.id, err),
+    }
+
+    Ok(Json(summary))
+}
+
+/// Get wallet tax report
+///
+/// Downloads the realized gains of the sells made in a calendar year (UTC) as CSV:
+/// one row per purchase lot a sell is matched with by the cost-basis method, with
+/// the dates acquired and sold, proceeds, cost basis and gain in USD. Sells are
+/// matched against the wallet's whole history, including earlier years.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/tax-report",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("year" = i32, Query, description = "Calendar year of the sells, e.g. 2024"),
+        ("method" = Option<CostBasisMethod>, Query, description = "Cost-basis method: fifo (default), lifo or avg"),
+        ("format" = Option<ExportFormat>, Query, description = "File format: csv (default)")
+    ),
+    responses(
+        (status = 200, description = "CSV file of the realized gains", content_type = "text/csv", body = String),
+        (status = 400, description = "Missing or invalid year, method or format", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_tax_report(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<TaxReportParams>, QueryRejection>,
+) -> Result<Response, AppError> {
+    let Query(params) = params?;
+    let current_year = Utc::now().year();
+    if !(tax::MIN_TAX_YEAR..=current_year).contains(&params.year) {
+        return Err(AppError::BadRequest(format!(
+            "year must be between {} and {current_year}",
+            tax::MIN_TAX_YEAR
+        )));
+    }
+    info!(
+        "Generating {} {:?} tax report for wallet with ID: {}",
+        params.year, params.method, wallet_id
+    );
+
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let token_trades = analytics::load_token_trades(&state.read_pool, wallet.id).await?;
+    let gains = tax::realized_gains(&token_trades, params.year, params.method);
+    let filename = format!("wallet-{}-tax-report-{}.csv", wallet.id, params.year);
+
+    match params.format {
+        ExportFormat::Csv => Ok(export::csv_response(
+            &filename,
+            export::tax_report_csv(gains),
+        )),
+    }
+}
+
+/// Get token details
+///
+/// Returns a token's metadata, current price and 24h change, and the number of
+/// tracked wallets holding it.
+#[utoipa::path(
+    get,
+    path = "/tokens/{mint}",
+    tag = "tokens",
+    params(
+        ("mint" = String, Path, description = "Token mint address"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Token details", body = TokenDetails),
+        (status = 400, description = "Invalid mint address", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Token not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_token(
+    _user: AuthUser,
+    Path(mint): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TokenDetails>, AppError> {
+    let mint = WalletAddress::parse(&mint)
+        .map_err(|err| AppError::BadRequest(format!("Invalid mint address: {err}")))?;
+    info!("Fetching details of token {}", mint);
+
+    tokens::token_details(
+        &state.db_pool,
+        state.cache.as_ref(),
+        state.prices.as_ref(),
+        state.metadata.as_ref(),
+        mint.as_str(),
+        state.config.token_metadata_ttl(),
+    )
+    .await?
+    .map(Json)
+    .ok_or_else(|| AppError::NotFound(format!("Token {mint} not found")))
+}
+
+/// Get a token's risk score
+///
+/// Reads the mint from the Solana RPC and scores it: mint and freeze authorities that
+/// are still set, the share of the supply owned by the 10 largest holders, and
+/// whether a known AMM pool holds the token each add points. Scores are cached for a
+/// few minutes.
+#[utoipa::path(
+    get,
+    path = "/tokens/{mint}/risk",
+    tag = "tokens",
+    params(
+        ("mint" = String, Path, description = "Token mint address")
+    ),
+    responses(
+        (status = 200, description = "Risk assessment", body = TokenRisk),
+        (status = 400, description = "Invalid mint address", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Token not found", body = ErrorResponse),
+        (status = 422, description = "Address is not a token mint", body = ErrorResponse),
+        (status = 502, description = "Solana RPC error", body = ErrorResponse),
+        (status = 503, description = "Solana RPC unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_token_risk(
+    _user: AuthUser,
+    Path(mint): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<TokenRisk>, AppError> {
+    let mint = WalletAddress::parse(&mint)
+        .map_err(|err| AppError::BadRequest(format!("Invalid mint address: {err}")))?;
+    info!("Scoring the risk of token {}", mint);
+
+    risk::token_risk(
+        &state.rpc,
+        state.cache.as_ref(),
+        mint.as_str(),
+        state.config.token_risk_ttl(),
+    )
+    .await?
+    .map(Json)
+    .ok_or_else(|| AppError::NotFound(format!("Token {mint} not found")))
+}
+
+/// Query parameters for the candles endpoint
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct CandleParams {
+    /// Duration of each candle: `1m`, `5m`, `15m`, `1h` (default), `4h` or `1d`
+    #[serde(default)]
+    #[schema(value_type = String, example = "1h")]
+    pub interval: CandleInterval,
+    /// Period covered, in hours or days, e.g. `24h` or `7d` (default)
+    #[serde(default)]
+    #[schema(value_type = String, example = "7d")]
+    pub range: CandleRange,
+}
+
+/// Get a token's price candles
+///
+/// Returns OHLCV candles of the token over the range, oldest first, for charting.
+/// Candles come from Birdeye, in USD, when it is configured, else from the swaps against
+/// SOL recorded on tracked wallets, in SOL; they are cached and refreshed at most once
+/// a minute.
+#[utoipa::path(
+    get,
+    path = "/tokens/{mint}/candles",
+    tag = "tokens",
+    params(
+        ("mint" = String, Path, description = "Token mint address"),
+        ("interval" = Option<String>, Query, description = "Candle duration: 1m, 5m, 15m, 1h (default), 4h or 1d"),
+        ("range" = Option<String>, Query, description = "Period covered, e.g. 24h or 7d (default); at most 1000 candles")
+    ),
+    responses(
+        (status = 200, description = "Price candles", body = PriceCandles),
+        (status = 400, description = "Invalid mint address, interval or range", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 502, description = "Candle API error", body = ErrorResponse),
+        (status = 503, description = "Candle API unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_token_candles(
+    _user: AuthUser,
+    Path(mint): Path<String>,
+    State(state): State<AppState>,
+    params: Result<Query<CandleParams>, QueryRejection>,
+) -> Result<Json<PriceCandles>, AppError> {
+    let Query(params) = params?;
+    let mint = WalletAddress::parse(&mint)
+        .map_err(|err| AppError::BadRequest(format!("Invalid mint address: {err}")))?;
+    info!(
+        "Fetching {} candles of token {} over {}",
+        params.interval, mint, params.range
+    );
+
+    let candles = candles::candles(
+        &state.db_pool,
+        state.candles.as_ref(),
+        mint.as_str(),
+        params.interval,
+        params.range,
+    )
+    .await?;
+
+    Ok(Json(candles))
+}
+
+/// List blocked spam tokens
+///
+/// Returns the tokens the caller marked as spam, most recently blocked first.
+#[utoipa::path(
+    get,
+    path = "/spam-tokens",
+    tag = "tokens",
+    responses(
+        (status = 200, description = "Blocked tokens", body = Vec<SpamToken>),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_spam_tokens(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<SpamToken>>, AppError> {
+    Ok(Json(spam::list_blocked(&state.db_pool, user.id).await?))
+}
+
+/// Block a spam token
+///
+/// Adds the mint to the caller's spam blocklist, hiding it from their holdings. Blocking
+/// a token again replaces its reason.
+#[utoipa::path(
+    put,
+    path = "/spam-tokens/{mint}",
+    tag = "tokens",
+    params(
+        ("mint" = String, Path, description = "Token mint address")
+    ),
+    request_body = BlockSpamToken,
+    responses(
+        (status = 200, description = "Token blocked", body = SpamToken),
+        (status = 400, description = "Invalid mint address or input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 422, description = "Reason too long", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn block_spam_token(
+    user: AuthUser,
+    Path(mint): Path<String>,
+    State(state): State<AppState>,
+    payload: Result<Json<BlockSpamToken>, JsonRejection>,
+) -> Result<Json<SpamToken>, AppError> {
+    let Json(payload) = payload?;
+    let mint = WalletAddress::parse(&mint)
+        .map_err(|err| AppError::BadRequest(format!("Invalid mint address: {err}")))?;
+
+    let token = spam::block(&state.db_pool, user.id, mint.as_str(), &payload).await?;
+    info!("User {} blocked token {} as spam", user.id, mint);
+
+    Ok(Json(token))
+}
+
+/// Unblock a spam token
+///
+/// Removes the mint from the caller's spam blocklist. The token may still be hidden by
+/// the server's spam threshold or heuristics.
+#[utoipa::path(
+    delete,
+    path = "/spam-tokens/{mint}",
+    tag = "tokens",
+    params(
+        ("mint" = String, Path, description = "Token mint address")
+    ),
+    responses(
+        (status = 204, description = "Token unblocked"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Token not blocked", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn unblock_spam_token(
+    user: AuthUser,
+    Path(mint): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    spam::unblock(&state.db_pool, user.id, &mint).await?;
+
+    info!("User {} unblocked token {}", user.id, mint);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List address labels
+///
+/// Returns every known-entity address transactions are labeled with, by label.
+#[utoipa::path(
+    get,
+    path = "/admin/address-labels",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Labeled addresses", body = Vec<AddressLabel>),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn list_address_labels(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AddressLabel>>, AppError> {
+    Ok(Json(labels::list(&state.db_pool).await?))
+}
+
+/// Label an address
+///
+/// Names the entity an address belongs to, so transactions sent to or received from it
+/// are labeled. Labeling an address again replaces its label.
+#[utoipa::path(
+    put,
+    path = "/admin/address-labels/{address}",
+    tag = "admin",
+    params(
+        ("address" = String, Path, description = "Solana address")
+    ),
+    request_body = SetAddressLabel,
+    responses(
+        (status = 200, description = "Address labeled", body = AddressLabel),
+        (status = 400, description = "Invalid address or input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 422, description = "Empty or too long label", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn set_address_label(
+    _admin: AdminAuth,
+    Path(address): Path<String>,
+    State(state): State<AppState>,
+    payload: Result<Json<SetAddressLabel>, JsonRejection>,
+) -> Result<Json<AddressLabel>, AppError> {
+    let Json(payload) = payload?;
+    let address = WalletAddress::parse(&address)
+        .map_err(|err| AppError::BadRequest(format!("Invalid address: {err}")))?;
+
+    let label = labels::set(&state.db_pool, address.as_str(), &payload).await?;
+    info!("Labeled address {} as {}", address, label.label);
+
+    Ok(Json(label))
+}
+
+/// Remove an address label
+///
+/// Stops labeling transactions sent to or received from the address.
+#[utoipa::path(
+    delete,
+    path = "/admin/address-labels/{address}",
+    tag = "admin",
+    params(
+        ("address" = String, Path, description = "Solana address")
+    ),
+    responses(
+        (status = 204, description = "Label removed"),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 404, description = "Address not labeled", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn delete_address_label(
+    _admin: AdminAuth,
+    Path(address): Path<String>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    labels::remove(&state.db_pool, &address).await?;
+
+    info!("Removed the label of address {}", address);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get instance statistics
+///
+/// Returns the number of users, active users, wallets and transactions, the sync
+/// backlog, this instance's cache hit rates since it started and the size of the
+/// database.
+#[utoipa::path(
+    get,
+    path = "/admin/stats",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Instance statistics", body = ServerStats),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn get_admin_stats(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<Json<ServerStats>, AppError> {
+    Ok(Json(
+        stats::collect(&state.db_pool, &state.cache_metrics).await?,
+    ))
+}
+
+/// Get read-only mode
+///
+/// Returns whether this instance rejects requests that change data.
+#[utoipa::path(
+    get,
+    path = "/admin/read-only",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Read-only mode", body = ReadOnlyStatus),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn get_read_only(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Json<ReadOnlyStatus> {
+    Json(ReadOnlyStatus {
+        enabled: state.read_only.is_enabled(),
+    })
+}
+
+/// Set read-only mode
+///
+/// Switches read-only maintenance mode on or off for this instance. While it is on,
+/// every request that could change data, other than to the admin endpoints, answers
+/// `503` with a `Retry-After` header, reads keep working and the job worker pauses.
+#[utoipa::path(
+    put,
+    path = "/admin/read-only",
+    tag = "admin",
+    request_body = ReadOnlyStatus,
+    responses(
+        (status = 200, description = "Read-only mode set", body = ReadOnlyStatus),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn set_read_only(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+    payload: Result<Json<ReadOnlyStatus>, JsonRejection>,
+) -> Result<Json<ReadOnlyStatus>, AppError> {
+    let Json(payload) = payload?;
+    state.read_only.set(payload.enabled);
+    warn!(
+        "Read-only maintenance mode switched {}",
+        if payload.enabled { "on" } else { "off" }
+    );
+
+    Ok(Json(payload))
+}
+
+/// List feature flags
+///
+/// Returns whether each switchable subsystem is on, and whether an operator overrode
+/// its configured state.
+#[utoipa::path(
+    get,
+    path = "/admin/features",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Feature flags", body = [FeatureStatus]),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn list_features(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Json<Vec<FeatureStatus>> {
+    Json(state.features.list())
+}
+
+/// Switch a feature on or off
+///
+/// Overrides the configured state of a subsystem until the override is dropped by
+/// sending `null`. Takes effect on this instance at once and on the others within
+/// `FEATURE_REFRESH_SECS`. While a feature is off its endpoints answer `503` and its
+/// background work waits.
+#[utoipa::path(
+    put,
+    path = "/admin/features/{feature}",
+    tag = "admin",
+    params(
+        ("feature" = Feature, Path, description = "Feature, e.g. `sync` or `share_links`")
+    ),
+    request_body = SetFeature,
+    responses(
+        (status = 200, description = "Feature flag set", body = FeatureStatus),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 404, description = "Unknown feature", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn set_feature(
+    _admin: AdminAuth,
+    Path(feature): Path<String>,
+    State(state): State<AppState>,
+    payload: Result<Json<SetFeature>, JsonRejection>,
+) -> Result<Json<FeatureStatus>, AppError> {
+    let Json(payload) = payload?;
+    let feature: Feature = feature.parse().map_err(AppError::NotFound)?;
+
+    let status = state
+        .features
+        .set(&state.db_pool, feature, payload.enabled)
+        .await?;
+    warn!(
+        "Feature {} switched {}{}",
+        feature,
+        if status.enabled { "on" } else { "off" },
+        if status.overridden {
+            ""
+        } else {
+            " as configured"
+        }
+    );
+
+    Ok(Json(status))
+}
+
+/// A page of the dead-letter queue
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PaginatedDeadLetters {
+    /// Entries in the current page, most recently failed first
+    pub items: Vec<DeadLetter>,
+    /// Number of items per page
+    pub per_page: i64,
+    /// Cursor of the next page, or `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// List the dead-letter queue
+///
+/// Returns the webhook deliveries and notifications that ran out of attempts and were
+/// not retried yet, most recently failed first, using cursor pagination.
+#[utoipa::path(
+    get,
+    path = "/admin/dlq",
+    tag = "admin",
+    params(
+        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page to fetch, from `next_cursor`")
+    ),
+    responses(
+        (status = 200, description = "Page of dead letters", body = PaginatedDeadLetters),
+        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn list_dead_letters(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+    params: Result<Query<CursorParams>, QueryRejection>,
+) -> Result<Json<PaginatedDeadLetters>, AppError> {
+    let Query(params) = params?;
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let per_page = clamp_per_page(params.per_page);
+
+    let entries = dlq::list(&state.db_pool, cursor, per_page + 1).await?;
+    let (items, next_cursor) =
+        pagination::next_page(entries, per_page, |e| Cursor::new(e.failed_at, e.id));
+
+    Ok(Json(PaginatedDeadLetters {
+        items,
+        per_page,
+        next_cursor,
+    }))
+}
+
+/// Retry a dead letter
+///
+/// Resets the delivery or notification to pending and queues it again with fresh
+/// attempts. The entry leaves the queue; if the retry fails again, a new entry is
+/// recorded.
+#[utoipa::path(
+    post,
+    path = "/admin/dlq/{id}/retry",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "Dead letter ID")
+    ),
+    responses(
+        (status = 200, description = "Delivery queued again", body = DeadLetter),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 404, description = "Dead letter not found or already retried", body = ErrorResponse),
+        (status = 409, description = "Delivery gone or no longer failed", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn retry_dead_letter(
+    _admin: AdminAuth,
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<DeadLetter>, AppError> {
+    let entry = dlq::retry(&state.db_pool, id).await?;
+
+    info!(
+        "Retrying dead-lettered {} {}",
+        entry.kind, entry.reference_id
+    );
+    Ok(Json(entry))
+}
+
+/// Get a user's quotas
+///
+/// Returns the user's plan, the limits in effect and how much of them is used.
+#[utoipa::path(
+    get,
+    path = "/admin/users/{id}/quota",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "User ID")
+    ),
+    responses(
+        (status = 200, description = "Quotas of the user", body = UserQuota),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn get_user_quota(
+    _admin: AdminAuth,
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<UserQuota>, AppError> {
+    quotas::get(&state, user_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("User with ID {user_id} not found")))
+}
+
+/// Set a user's quotas
+///
+/// Puts the user on a plan and optionally overrides its wallet or daily sync limit.
+/// Omitted fields fall back to `DEFAULT_PLAN` and the plan's limits. Takes effect on
+/// the user's next request.
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/quota",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "User ID")
+    ),
+    request_body = SetUserQuota,
+    responses(
+        (status = 200, description = "Quotas set", body = UserQuota),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 422, description = "Negative limit", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn set_user_quota(
+    _admin: AdminAuth,
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    payload: Result<Json<SetUserQuota>, JsonRejection>,
+) -> Result<Json<UserQuota>, AppError> {
+    let Json(payload) = payload?;
+    payload.validate()?;
+
+    let quota = quotas::set(&state, user_id, &payload)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User with ID {user_id} not found")))?;
+    info!(
+        "User {} put on the {} plan ({:?} wallets, {:?} syncs per day)",
+        user_id, quota.plan, quota.max_wallets, quota.max_syncs_per_day
+    );
+
+    Ok(Json(quota))
+}
+
+/// Set a user's role
+///
+/// Gives the user the `admin`, `user` or `readonly` role. Changing the role revokes
+/// the user's sessions, so they sign in again to act with the new role; API keys
+/// act with it on the next request.
+#[utoipa::path(
+    put,
+    path = "/admin/users/{id}/role",
+    tag = "admin",
+    params(
+        ("id" = Uuid, Path, description = "User ID")
+    ),
+    request_body = SetUserRole,
+    responses(
+        (status = 200, description = "Role set", body = UserRole),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 404, description = "User not found", body = ErrorResponse),
+        (status = 422, description = "Unknown role", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn set_user_role(
+    _admin: AdminAuth,
+    Path(user_id): Path<Uuid>,
+    State(state): State<AppState>,
+    payload: Result<Json<SetUserRole>, JsonRejection>,
+) -> Result<Json<UserRole>, AppError> {
+    let Json(payload) = payload?;
+
+    let role = roles::set(&state.db_pool, user_id, payload.role)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("User with ID {user_id} not found")))?;
+    info!("User {} now has the {} role", user_id, role.role);
+
+    Ok(Json(role))
+}
+
+/// List plans
+///
+/// Returns the limits of every plan and the Stripe price it is sold at.
+#[utoipa::path(
+    get,
+    path = "/admin/plans",
+    tag = "admin",
+    responses(
+        (status = 200, description = "Plans, from the most to the least limited", body = Vec<PlanLimits>),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn list_plans(
+    _admin: AdminAuth,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<PlanLimits>>, AppError> {
+    Ok(Json(quotas::plans(&state).await?))
+}
+
+/// Set a plan's limits
+///
+/// Replaces the plan's wallet and daily sync limits, omitted ones meaning no limit,
+/// and the Stripe price whose subscriptions put users on it. Takes effect on the next
+/// request of every user on the plan.
+#[utoipa::path(
+    put,
+    path = "/admin/plans/{plan}",
+    tag = "admin",
+    params(
+        ("plan" = Plan, Path, description = "Plan to change")
+    ),
+    request_body = SetPlanLimits,
+    responses(
+        (status = 200, description = "Plan changed", body = PlanLimits),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid admin API key", body = ErrorResponse),
+        (status = 409, description = "Another plan is sold at the Stripe price", body = ErrorResponse),
+        (status = 422, description = "Negative limit or blank price", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse),
+        (status = 503, description = "Admin endpoints not configured", body = ErrorResponse)
+    ),
+    security(("admin_key" = []))
+)]
+pub async fn set_plan_limits(
+    _admin: AdminAuth,
+    Path(plan): Path<Plan>,
+    State(state): State<AppState>,
+    payload: Result<Json<SetPlanLimits>, JsonRejection>,
+) -> Result<Json<PlanLimits>, AppError> {
+    let Json(payload) = payload?;
+    payload.validate()?;
+
+    let limits = quotas::set_plan(&state, plan, &payload).await?;
+    info!(
+        "The {} plan now allows {:?} wallets and {:?} syncs per day",
+        plan, limits.max_wallets, limits.max_syncs_per_day
+    );
+
+    Ok(Json(limits))
+}
+
+/// Create a wallet group
+///
+/// Creates a named group of the caller's wallets. A wallet can belong to any number
+/// of groups.
+#[utoipa::path(
+    post,
+    path = "/groups",
+    tag = "groups",
+    request_body = CreateWalletGroup,
+    responses(
+        (status = 200, description = "Group created", body = WalletGroup),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 409, description = "Group with this name already exists", body = ErrorResponse),
+        (status = 422, description = "Invalid group", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn create_group(
+    user: AuthUser,
+    State(state): State<AppState>,
+    payload: Result<Json<CreateWalletGroup>, JsonRejection>,
+) -> Result<Json<WalletGroup>, AppError> {
+    let Json(payload) = payload?;
+
+    let group = groups::create_group(&state.db_pool, user.id, &payload).await?;
+    info!("Created group {} for user {}", group.id, user.id);
+
+    Ok(Json(group))
+}
+
+/// List wallet groups
+///
+/// Returns the caller's groups ordered by name.
+#[utoipa::path(
+    get,
+    path = "/groups",
+    tag = "groups",
+    responses(
+        (status = 200, description = "Wallet groups", body = [WalletGroup]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_groups(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WalletGroup>>, AppError> {
+    Ok(Json(groups::list_groups(&state.db_pool, user.id).await?))
+}
+
+/// Get a wallet group
+///
+/// Returns the group with its member wallet IDs.
+#[utoipa::path(
+    get,
+    path = "/groups/{id}",
+    tag = "groups",
+    params(
+        ("id" = Uuid, Path, description = "Group ID")
+    ),
+    responses(
+        (status = 200, description = "Group found", body = WalletGroup),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Group not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_group(
+    user: AuthUser,
+    Path(group_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<WalletGroup>, AppError> {
+    Ok(Json(
+        groups::find_group(&state.db_pool, user.id, group_id).await?,
+    ))
+}
+
+/// Update a wallet group
+///
+/// Renames the group and/or replaces its members. Omitted fields are left unchanged.
+#[utoipa::path(
+    patch,
+    path = "/groups/{id}",
+    tag = "groups",
+    params(
+        ("id" = Uuid, Path, description = "Group ID")
+    ),
+    request_body = UpdateWalletGroup,
+    responses(
+        (status = 200, description = "Group updated", body = WalletGroup),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Group or wallet not found", body = ErrorResponse),
+        (status = 409, description = "Group with this name already exists", body = ErrorResponse),
+        (status = 422, description = "Invalid group", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn update_group(
+    user: AuthUser,
+    Path(group_id): Path<Uuid>,
+    State(state): State<AppState>,
+    payload: Result<Json<UpdateWalletGroup>, JsonRejection>,
+) -> Result<Json<WalletGroup>, AppError> {
+    let Json(payload) = payload?;
+
+    let group = groups::update_group(&state.db_pool, user.id, group_id, &payload).await?;
+    info!("Updated group {}", group.id);
+
+    Ok(Json(group))
+}
+
+/// Delete a wallet group
+///
+/// Removes the group. Its wallets are not affected.
+#[utoipa::path(
+    delete,
+    path = "/groups/{id}",
+    tag = "groups",
+    params(
+        ("id" = Uuid, Path, description = "Group ID")
+    ),
+    responses(
+        (status = 204, description = "Group deleted"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Group not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn delete_group(
+    user: AuthUser,
+    Path(group_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    let result = sqlx::query("DELETE FROM wallet_groups WHERE id = $1 AND user_id = $2")
+        .bind(group_id)
+        .bind(user.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Group with ID {group_id} not found"
+        )));
+    }
+
+    info!("Deleted group {}", group_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get a wallet group's portfolio
+///
+/// Merges the holdings of the group's wallets and computes their combined profit
+/// and loss, treating the members' trades of each token as one history.
+#[utoipa::path(
+    get,
+    path = "/groups/{id}/portfolio",
+    tag = "groups",
+    params(
+        ("id" = Uuid, Path, description = "Group ID"),
+        ("method" = Option<CostBasisMethod>, Query, description = "Cost-basis method: fifo (default), lifo or avg"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Combined holdings and PnL", body = GroupPortfolio),
+        (status = 400, description = "Invalid cost-basis method", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Group not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_group_portfolio(
+    user: AuthUser,
+    Path(group_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<PnlParams>, QueryRejection>,
+) -> Result<Json<GroupPortfolio>, AppError> {
+    let Query(params) = params?;
+    info!("Fetching portfolio of group {}", group_id);
+
+    let group = groups::find_group(&state.db_pool, user.id, group_id).await?;
+    let mut portfolio = groups::group_portfolio(
+        &state.read_pool,
+        state.prices.as_ref(),
+        group,
+        params.method,
+    )
+    .await?;
+    portfolio.portfolio.holdings = label_holdings(&state, portfolio.portfolio.holdings).await?;
+
+    Ok(Json(portfolio))
+}
+
+/// Share a wallet's portfolio
+///
+/// Creates a revocable link to a read-only snapshot of the wallet's holdings and PnL,
+/// readable without an API key at `GET /public/portfolio/{token}`. The token is shown
+/// only once.
+#[utoipa::path(
+    post,
+    path = "/wallets/{id}/share",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 200, description = "Share link created", body = CreatedShareLink),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn create_share_link(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<CreatedShareLink>, AppError> {
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    let created = share::create_link(&state.db_pool, wallet.id).await?;
+    info!(
+        "Created share link {} of wallet {}",
+        created.link.id, wallet.id
+    );
+
+    Ok(Json(created))
+}
+
+/// List a wallet's share links
+///
+/// Returns the wallet's active share links, newest first. Their tokens are not shown.
+#[utoipa::path(
+    get,
+    path = "/wallets/{id}/share",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID")
+    ),
+    responses(
+        (status = 200, description = "Share links", body = [ShareLink]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_share_links(
+    user: AuthUser,
+    Path(wallet_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ShareLink>>, AppError> {
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+
+    Ok(Json(share::list_links(&state.db_pool, wallet.id).await?))
+}
+
+/// Revoke a share link
+///
+/// Deletes the link; its token answers `404 Not Found` from then on.
+#[utoipa::path(
+    delete,
+    path = "/wallets/{id}/share/{link_id}",
+    params(
+        ("id" = Uuid, Path, description = "Wallet ID"),
+        ("link_id" = Uuid, Path, description = "Share link ID")
+    ),
+    responses(
+        (status = 204, description = "Share link revoked"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet or share link not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn revoke_share_link(
+    user: AuthUser,
+    Path((wallet_id, link_id)): Path<(Uuid, Uuid)>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    let wallet = find_wallet(&state, user, wallet_id).await?;
+    if !share::revoke_link(&state.db_pool, wallet.id, link_id).await? {
+        return Err(AppError::NotFound(format!(
+            "Share link with ID {link_id} not found"
+        )));
+    }
+
+    info!("Revoked share link {} of wallet {}", link_id, wallet.id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Get a shared portfolio
+///
+/// Returns a read-only snapshot of the holdings and PnL of the wallet shared by the
+/// link's token. No API key is needed; unknown and revoked tokens answer `404`. Tokens
+/// the owner blocked as spam are left out.
+#[utoipa::path(
+    get,
+    path = "/public/portfolio/{token}",
+    params(
+        ("token" = String, Path, description = "Token of the share link"),
+        ("method" = Option<CostBasisMethod>, Query, description = "Cost-basis method: fifo (default), lifo or avg"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Shared portfolio", body = PublicPortfolio),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn get_public_portfolio(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+    params: Result<Query<PnlParams>, QueryRejection>,
+) -> Result<Json<PublicPortfolio>, AppError> {
+    let Query(params) = params?;
+    let wallet = share::shared_wallet(&state.db_pool, &token).await?;
+    info!("Serving shared portfolio of wallet {}", wallet.wallet_id);
+
+    let holdings =
+        analytics::wallet_holdings(&state.read_pool, state.prices.as_ref(), wallet.wallet_id)
+            .await?;
+    let filter = SpamFilter::load(&state.read_pool, &state.config, wallet.user_id).await?;
+    let mut holdings = label_holdings(&state, holdings.holdings).await?;
+    holdings.retain(|holding| !filter.is_spam(holding));
+
+    let token_trades = analytics::load_token_trades(&state.read_pool, wallet.wallet_id).await?;
+    let mints: Vec<String> = token_trades
+        .iter()
+        .map(|t| t.token_address.clone())
+        .collect();
+    let prices = state.prices.prices_usd(&mints).await?;
+    let tokens = analytics::token_pnl(token_trades, &prices, params.method);
+
+    Ok(Json(PublicPortfolio {
+        wallet_address: wallet.address,
+        total_value_usd: holdings.iter().filter_map(|h| h.value_usd).sum(),
+        holdings,
+        method: params.method,
+        total_realized_pnl_usd: tokens.iter().map(|t| t.realized_pnl_usd).sum(),
+        total_unrealized_pnl_usd: tokens.iter().filter_map(|t| t.unrealized_pnl_usd).sum(),
+        tokens,
+        generated_at: Utc::now(),
+    }))
+}
+
+/// Get a shared portfolio widget
+///
+/// Returns a minimal snapshot of the wallet shared by the link's token, its total
+/// value, 24h change and five largest holdings, for embedding in web pages. No API key
+/// is needed. Snapshots are cached for `WIDGET_CACHE_SECS`, which the `Cache-Control`
+/// header lets browsers and CDNs do too.
+#[utoipa::path(
+    get,
+    path = "/public/widget/{token}",
+    params(
+        ("token" = String, Path, description = "Token of the share link"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Portfolio widget", body = PortfolioWidget),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 404, description = "Share link not found", body = ErrorResponse),
+        (status = 503, description = "Price feed unavailable", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn get_portfolio_widget(
+    Path(token): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Response, AppError> {
+    let wallet = share::shared_wallet(&state.db_pool, &token).await?;
+    let ttl = state.config.widget_cache_ttl();
+
+    let key = format!("widget:{}", wallet.wallet_id);
+    let widget = match cache::get_json(state.cache.as_ref(), &key).await {
+        Some(widget) => widget,
+        None => {
+            let holdings = analytics::wallet_holdings(
+                &state.read_pool,
+                state.prices.as_ref(),
+                wallet.wallet_id,
+            )
+            .await?;
+            let filter = SpamFilter::load(&state.read_pool, &state.config, wallet.user_id).await?;
+            let mut holdings = label_holdings(&state, holdings.holdings).await?;
+            holdings.retain(|holding| !filter.is_spam(holding));
+
+            let mints: Vec<String> = holdings.iter().map(|h| h.token_address.clone()).collect();
+            let changes = state.prices.price_changes_24h(&mints).await?;
+            let widget = share::widget(&holdings, &changes);
+            cache::set_json(state.cache.as_ref(), &key, &widget, ttl).await;
+            widget
+        }
+    };
+
+    let cache_control = format!(
+        "public, max-age={0}, stale-while-revalidate={0}",
+        ttl.as_secs()
+    );
+    Ok((
+        [(header::CACHE_CONTROL, cache_control)],
+        Json::<PortfolioWidget>(widget),
+    )
+        .into_response())
+}
+
+/// Create an alert
+///
+/// Creates an alert on a token's price or a wallet's value. Enabled alerts are checked
+/// against the price feed every `ALERT_INTERVAL_SECS`, and every firing is recorded.
+#[utoipa::path(
+    post,
+    path = "/alerts",
+    tag = "alerts",
+    request_body = CreateAlert,
+    responses(
+        (status = 200, description = "Alert created", body = Alert),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 422, description = "Invalid alert condition", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn create_alert(
+    user: AuthUser,
+    State(state): State<AppState>,
+    payload: Result<Json<CreateAlert>, JsonRejection>,
+) -> Result<Json<Alert>, AppError> {
+    let Json(payload) = payload?;
+
+    let alert = alerts::create_alert(&state.db_pool, user.id, payload).await?;
+    info!("Created alert {} for user {}", alert.id, user.id);
+
+    Ok(Json(alert))
+}
+
+/// List alerts
+///
+/// Returns the caller's alerts, oldest first.
+#[utoipa::path(
+    get,
+    path = "/alerts",
+    tag = "alerts",
+    responses(
+        (status = 200, description = "Alerts", body = [Alert]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_alerts(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<Alert>>, AppError> {
+    Ok(Json(alerts::list_alerts(&state.db_pool, user.id).await?))
+}
+
+/// Get an alert
+#[utoipa::path(
+    get,
+    path = "/alerts/{id}",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Alert ID")
+    ),
+    responses(
+        (status = 200, description = "Alert found", body = Alert),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Alert not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_alert(
+    user: AuthUser,
+    Path(alert_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Alert>, AppError> {
+    Ok(Json(
+        alerts::find_alert(&state.db_pool, user.id, alert_id).await?,
+    ))
+}
+
+/// Update an alert
+///
+/// Replaces the alert's condition and/or enables or disables it. Omitted fields are
+/// left unchanged.
+#[utoipa::path(
+    patch,
+    path = "/alerts/{id}",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Alert ID")
+    ),
+    request_body = UpdateAlert,
+    responses(
+        (status = 200, description = "Alert updated", body = Alert),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Alert or wallet not found", body = ErrorResponse),
+        (status = 422, description = "Invalid alert condition", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn update_alert(
+    user: AuthUser,
+    Path(alert_id): Path<Uuid>,
+    State(state): State<AppState>,
+    payload: Result<Json<UpdateAlert>, JsonRejection>,
+) -> Result<Json<Alert>, AppError> {
+    let Json(payload) = payload?;
+
+    let alert = alerts::update_alert(&state.db_pool, user.id, alert_id, payload).await?;
+    info!("Updated alert {}", alert.id);
+
+    Ok(Json(alert))
+}
+
+/// Delete an alert
+///
+/// Removes the alert together with its recorded firings.
+#[utoipa::path(
+    delete,
+    path = "/alerts/{id}",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Alert ID")
+    ),
+    responses(
+        (status = 204, description = "Alert deleted"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Alert not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn delete_alert(
+    user: AuthUser,
+    Path(alert_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    alerts::delete_alert(&state.db_pool, user.id, alert_id).await?;
+
+    info!("Deleted alert {}", alert_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// List an alert's events
+///
+/// Returns the alert's 100 most recent firings, newest first.
+#[utoipa::path(
+    get,
+    path = "/alerts/{id}/events",
+    tag = "alerts",
+    params(
+        ("id" = Uuid, Path, description = "Alert ID"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Alert firings", body = [AlertEvent]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Alert not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_alert_events(
+    user: AuthUser,
+    Path(alert_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AlertEvent>>, AppError> {
+    let alert = alerts::find_alert(&state.db_pool, user.id, alert_id).await?;
+
+    Ok(Json(alerts::list_events(&state.db_pool, alert.id).await?))
+}
+
+/// List notification channels
+///
+/// Returns the caller's configured notification channels.
+#[utoipa::path(
+    get,
+    path = "/notifications/channels",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Notification channels", body = [NotificationChannel]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_notification_channels(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<NotificationChannel>>, AppError> {
+    Ok(Json(
+        notifications::list_channels(&state.db_pool, &state.secrets, user.id).await?,
+    ))
+}
+
+/// Configure a notification channel
+///
+/// Creates or replaces the caller's channel of the given kind. Alert firings are sent
+/// on it unless `alerts` is `false`, detected transactions when they are worth at
+/// least `large_transaction_usd`, the daily and weekly portfolio reports if
+/// `daily_summary` or `weekly_summary` is `true`, and whale movements if `whales` is
+/// `true`.
+#[utoipa::path(
+    put,
+    path = "/notifications/channels/{kind}",
+    tag = "notifications",
+    params(
+        ("kind" = ChannelKind, Path, description = "Channel kind")
+    ),
+    request_body = ConfigureChannel,
+    responses(
+        (status = 200, description = "Channel configured", body = NotificationChannel),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 422, description = "Invalid target or threshold, or the channel kind is not enabled on this server", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn configure_notification_channel(
+    user: AuthUser,
+    Path(kind): Path<ChannelKind>,
+    State(state): State<AppState>,
+    payload: Result<Json<ConfigureChannel>, JsonRejection>,
+) -> Result<Json<NotificationChannel>, AppError> {
+    let Json(payload) = payload?;
+    let enabled = match kind {
+        ChannelKind::Telegram => state.config.telegram_bot_token.is_some(),
+        ChannelKind::Email => state.config.smtp_url.is_some(),
+    };
+    if !enabled {
+        return Err(AppError::UnprocessableEntity(format!(
+            "{} notifications are not enabled on this server",
+            kind.as_str()
+        )));
+    }
+
+    let channel =
+        notifications::configure_channel(&state.db_pool, &state.secrets, user.id, kind, &payload)
+            .await?;
+    info!(
+        "Configured {} notifications for user {}",
+        kind.as_str(),
+        user.id
+    );
+
+    Ok(Json(channel))
+}
+
+/// Delete a notification channel
+///
+/// Stops all notifications on the caller's channel of the given kind.
+#[utoipa::path(
+    delete,
+    path = "/notifications/channels/{kind}",
+    tag = "notifications",
+    params(
+        ("kind" = ChannelKind, Path, description = "Channel kind")
+    ),
+    responses(
+        (status = 204, description = "Channel deleted"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Channel not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn delete_notification_channel(
+    user: AuthUser,
+    Path(kind): Path<ChannelKind>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    notifications::delete_channel(&state.db_pool, user.id, kind).await?;
+
+    info!(
+        "Deleted {} notifications of user {}",
+        kind.as_str(),
+        user.id
+    );
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Query parameters of `GET /reports`
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReportListParams {
+    /// Only list reports of this period
+    pub period: Option<ReportPeriod>,
+    /// Cursor returned as `next_cursor` by the previous page; omit for the first page
+    pub cursor: Option<String>,
+    /// Number of items per page (max 100)
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+}
+
+/// A page of generated reports
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PaginatedReports {
+    /// Reports in the current page, newest first
+    pub items: Vec<Report>,
+    /// Number of items per page
+    pub per_page: i64,
+    /// Cursor of the next page, or `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// List reports
+///
+/// Returns the caller's generated daily and weekly portfolio reports, newest first,
+/// using cursor pagination.
+#[utoipa::path(
+    get,
+    path = "/reports",
+    tag = "reports",
+    params(
+        ("period" = Option<ReportPeriod>, Query, description = "Only list reports of this period"),
+        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page to fetch, from `next_cursor`"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Page of reports", body = PaginatedReports),
+        (status = 400, description = "Invalid query parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_reports(
+    user: AuthUser,
+    State(state): State<AppState>,
+    params: Result<Query<ReportListParams>, QueryRejection>,
+) -> Result<Json<PaginatedReports>, AppError> {
+    let Query(params) = params?;
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let per_page = clamp_per_page(params.per_page);
+
+    let reports = sqlx::query_as::<_, Report>(&format!(
+        r#"
+        SELECT {REPORT_COLUMNS}
+        FROM reports
+        WHERE user_id = $1
+          AND ($2::TEXT IS NULL OR period = $2)
+          AND ($3::TIMESTAMPTZ IS NULL OR (created_at, id) < ($3, $4))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $5
+        "#
+    ))
+    .bind(user.id)
+    .bind(params.period.map(|p| p.as_str()))
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.id))
+    .bind(per_page + 1)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let (items, next_cursor) =
+        pagination::next_page(reports, per_page, |r| Cursor::new(r.created_at, r.id));
+
+    Ok(Json(PaginatedReports {
+        items,
+        per_page,
+        next_cursor,
+    }))
+}
+
+/// Get a report
+///
+/// Returns one of the caller's generated reports, including the holdings it was
+/// computed from.
+#[utoipa::path(
+    get,
+    path = "/reports/{id}",
+    tag = "reports",
+    params(
+        ("id" = Uuid, Path, description = "Report ID"),
+        ("currency" = Option<String>, Query, description = "Currency to display values in: USD (default), EUR, GBP or NGN")
+    ),
+    responses(
+        (status = 200, description = "Report", body = Report),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Report not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn get_report(
+    user: AuthUser,
+    Path(report_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<Json<Report>, AppError> {
+    Ok(Json(
+        reports::find_report(&state.db_pool, user.id, report_id).await?,
+    ))
+}
+
+/// Receive a Helius enhanced-transaction webhook
+///
+/// Verifies the shared secret in the `Authorization` header and records the swap and
+/// transfer balance changes of every tracked wallet in the payload.
+#[utoipa::path(
+    post,
+    path = "/webhooks/helius",
+    request_body(
+        content = Vec<Object>,
+        description = "Helius enhanced transactions",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 200, description = "Payload processed", body = WebhookReport),
+        (status = 400, description = "Invalid payload", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid webhook secret", body = ErrorResponse),
+        (status = 422, description = "Invalid payload", body = ErrorResponse),
+        (status = 503, description = "Webhook receiver not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn helius_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    payload: Result<Json<Vec<EnhancedTransaction>>, JsonRejection>,
+) -> Result<Json<WebhookReport>, AppError> {
+    let secret = state
+        .config
+        .helius_webhook_secret
+        .as_deref()
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable("Helius webhook receiver is not configured".to_string())
+        })?;
+
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    if !helius::secret_matches(presented, secret) {
+        warn!("Rejected Helius webhook with an invalid secret");
+        return Err(AppError::Unauthorized("Invalid webhook secret".to_string()));
+    }
+
+    let Json(transactions) = payload?;
+    let report = helius::ingest(
+        &state.db_pool,
+        state.events.as_ref(),
+        &transactions,
+        state.config.whale_threshold_usd,
+    )
+    .await?;
+    for wallet_id in &report.updated_wallets {
+        check_balance_thresholds(&state, *wallet_id).await;
+    }
+    info!(
+        "Helius webhook: {} transactions received, {} rows upserted",
+        report.transactions_received, report.transactions_upserted
+    );
+
+    Ok(Json(report))
+}
+
+/// Receive a Stripe webhook
+///
+/// Verifies the `Stripe-Signature` header of the event and puts the subscribing user
+/// on the plan of their subscription, or back on `DEFAULT_PLAN` once it ends.
+/// Duplicate, stale and other events are acknowledged without changes. Subscription
+/// events of customers not linked to a user yet, or billed at no plan's price, are
+/// answered with `409` so Stripe retries them.
+#[utoipa::path(
+    post,
+    path = "/webhooks/stripe",
+    request_body(
+        content = Object,
+        description = "Stripe event",
+        content_type = "application/json"
+    ),
+    responses(
+        (status = 200, description = "Event processed", body = StripeEventReport),
+        (status = 401, description = "Missing or invalid signature", body = ErrorResponse),
+        (status = 409, description = "Event cannot be applied yet; Stripe retries it", body = ErrorResponse),
+        (status = 422, description = "Invalid event", body = ErrorResponse),
+        (status = 503, description = "Stripe billing not configured", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    )
+)]
+pub async fn stripe_webhook(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<Json<StripeEventReport>, AppError> {
+    let secret = state
+        .config
+        .stripe_webhook_secret
+        .as_deref()
+        .ok_or_else(|| {
+            AppError::ServiceUnavailable("Stripe billing is not configured".to_string())
+        })?;
+
+    // The signature covers the exact bytes sent, so they are verified before parsing
+    if let Err(err) = billing::verify_signature(secret, &headers, &body, Utc::now()) {
+        warn!("Rejected Stripe webhook: {}", err);
+        return Err(AppError::Unauthorized(err.to_string()));
+    }
+    let event: StripeEvent = serde_json::from_slice(&body)
+        .map_err(|err| AppError::UnprocessableEntity(format!("Invalid Stripe event: {err}")))?;
+
+    let report = match billing::handle_event(&state.db_pool, &event).await {
+        Ok(report) => report,
+        Err(EventError::Database(err)) => return Err(err.into()),
+        // Stripe delivers the event again after a non-2xx response
+        Err(err) => {
+            warn!("Deferring Stripe webhook: {}", err);
+            return Err(AppError::Conflict(err.to_string()));
+        }
+    };
+    info!(
+        "Stripe webhook: {} event {} (handled: {})",
+        report.event_type, report.event_id, report.handled
+    );
+
+    Ok(Json(report))
+}
+
+/// Register a webhook
+///
+/// Subscribes a URL to portfolio events. The returned secret signs every delivery and
+/// is shown only once.
+#[utoipa::path(
+    post,
+    path = "/webhooks/subscriptions",
+    tag = "webhooks",
+    request_body = CreateWebhookSubscription,
+    responses(
+        (status = 200, description = "Webhook registered", body = CreatedWebhookSubscription),
+        (status = 400, description = "Invalid input", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Wallet not found", body = ErrorResponse),
+        (status = 422, description = "Invalid subscription", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn create_webhook_subscription(
+    user: AuthUser,
+    State(state): State<AppState>,
+    payload: Result<Json<CreateWebhookSubscription>, JsonRejection>,
+) -> Result<Json<CreatedWebhookSubscription>, AppError> {
+    let Json(payload) = payload?;
+
+    let created = webhooks::create_subscription(
+        &state.db_pool,
+        &state.secrets,
+        state.config.webhook_allow_private_urls,
+        user.id,
+        &payload,
+    )
+    .await?;
+    info!(
+        "Registered webhook {} for user {}",
+        created.subscription.id, user.id
+    );
+
+    Ok(Json(created))
+}
+
+/// List webhooks
+///
+/// Returns the caller's webhook subscriptions, newest first.
+#[utoipa::path(
+    get,
+    path = "/webhooks/subscriptions",
+    tag = "webhooks",
+    responses(
+        (status = 200, description = "Webhook subscriptions", body = [WebhookSubscription]),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_webhook_subscriptions(
+    user: AuthUser,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<WebhookSubscription>>, AppError> {
+    let subscriptions = sqlx::query_as::<_, WebhookSubscription>(
+        r#"
+        SELECT id, url, event_types, wallet_id, threshold_usd, created_at
+        FROM webhook_subscriptions
+        WHERE user_id = $1
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .bind(user.id)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    Ok(Json(subscriptions))
+}
+
+/// Delete a webhook
+///
+/// Removes the subscription together with its delivery log.
+#[utoipa::path(
+    delete,
+    path = "/webhooks/subscriptions/{id}",
+    tag = "webhooks",
+    params(
+        ("id" = Uuid, Path, description = "Subscription ID")
+    ),
+    responses(
+        (status = 204, description = "Webhook deleted"),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn delete_webhook_subscription(
+    user: AuthUser,
+    Path(subscription_id): Path<Uuid>,
+    State(state): State<AppState>,
+) -> Result<StatusCode, AppError> {
+    let result = sqlx::query("DELETE FROM webhook_subscriptions WHERE id = $1 AND user_id = $2")
+        .bind(subscription_id)
+        .bind(user.id)
+        .execute(&state.db_pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Webhook with ID {subscription_id} not found"
+        )));
+    }
+
+    info!("Deleted webhook {}", subscription_id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// A page of a webhook's delivery log
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PaginatedWebhookDeliveries {
+    /// Deliveries in the current page, newest first
+    pub items: Vec<WebhookDelivery>,
+    /// Number of items per page
+    pub per_page: i64,
+    /// Cursor of the next page, or `null` on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// List webhook deliveries
+///
+/// Returns the delivery log of a subscription, newest first, using cursor pagination.
+#[utoipa::path(
+    get,
+    path = "/webhooks/subscriptions/{id}/deliveries",
+    tag = "webhooks",
+    params(
+        ("id" = Uuid, Path, description = "Subscription ID"),
+        ("per_page" = Option<i64>, Query, description = "Number of items per page (max 100)"),
+        ("cursor" = Option<String>, Query, description = "Cursor of the page to fetch, from `next_cursor`")
+    ),
+    responses(
+        (status = 200, description = "Page of deliveries", body = PaginatedWebhookDeliveries),
+        (status = 400, description = "Invalid pagination parameters", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid API key", body = ErrorResponse),
+        (status = 404, description = "Webhook not found", body = ErrorResponse),
+        (status = 500, description = "Internal server error", body = ErrorResponse)
+    ),
+    security(("api_key" = []))
+)]
+pub async fn list_webhook_deliveries(
+    user: AuthUser,
+    Path(subscription_id): Path<Uuid>,
+    State(state): State<AppState>,
+    params: Result<Query<CursorParams>, QueryRejection>,
+) -> Result<Json<PaginatedWebhookDeliveries>, AppError> {
+    let Query(params) = params?;
+
+    sqlx::query_scalar::<_, Uuid>(
+        "SELECT id FROM webhook_subscriptions WHERE id = $1 AND user_id = $2",
+    )
+    .bind(subscription_id)
+    .bind(user.id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Webhook with ID {subscription_id} not found")))?;
+
+    let cursor = params.cursor.as_deref().map(Cursor::decode).transpose()?;
+    let per_page = clamp_per_page(params.per_page);
+
+    let deliveries = sqlx::query_as::<_, WebhookDelivery>(
+        r#"
+        SELECT id, event_type, payload, status, attempts, next_attempt_at,
+               last_status_code, last_error, delivered_at, created_at
+        FROM webhook_deliveries
+        WHERE subscription_id = $1
+          AND ($2::TIMESTAMPTZ IS NULL OR (created_at, id) < ($2, $3))
+        ORDER BY created_at DESC, id DESC
+        LIMIT $4
+        "#,
+    )
+    .bind(subscription_id)
+    .bind(cursor.map(|c| c.created_at))
+    .bind(cursor.map(|c| c.id))
+    .bind(per_page + 1)
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let (items, next_cursor) =
+        pagination::next_page(deliveries, per_page, |d| Cursor::new(d.created_at, d.id));
+
+    Ok(Json(PaginatedWebhookDeliveries {
+        items,
+        per_page,
+        next_cursor,
     }))
 }
@@ -0,0 +1,156 @@
+use std::time::{Duration, Instant};
+
+use axum::{extract::State, http::StatusCode, Json};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::warn;
+use utoipa::ToSchema;
+
+use crate::rpc::EndpointHealth;
+use crate::AppState;
+
+/// Maximum time the readiness probe waits for the database
+const DB_PING_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Liveness of the process
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct HealthStatus {
+    /// Always `ok` while the process is serving requests
+    #[schema(example = "ok")]
+    pub status: String,
+}
+
+/// Readiness of the service and its dependencies
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReadinessStatus {
+    /// `ready` when every dependency is reachable, `unavailable` otherwise
+    #[schema(example = "ready")]
+    pub status: String,
+    /// Database connectivity
+    pub database: DatabaseStatus,
+    /// Read replica connectivity, when `DATABASE_READ_URL` is set
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub read_replica: Option<DatabaseStatus>,
+    /// Health of the Solana RPC endpoints, in order of preference; informational, as
+    /// the API keeps serving stored data while sync is degraded
+    pub rpc_endpoints: Vec<EndpointHealth>,
+}
+
+/// Result of pinging the database
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct DatabaseStatus {
+    /// Whether the database answered within the timeout
+    pub reachable: bool,
+    /// Round-trip time of the ping in milliseconds, if it succeeded
+    pub latency_ms: Option<f64>,
+    /// Latest applied migration version, if known
+    #[schema(example = 20250101000007i64)]
+    pub migration_version: Option<i64>,
+    /// Why the database is unreachable
+    pub error: Option<String>,
+}
+
+/// Liveness probe
+///
+/// Always returns `200 OK` while the process is running.
+#[utoipa::path(
+    get,
+    path = "/healthz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Process is alive", body = HealthStatus)
+    )
+)]
+pub async fn healthz() -> Json<HealthStatus> {
+    Json(HealthStatus {
+        status: "ok".to_string(),
+    })
+}
+
+/// Readiness probe
+///
+/// Pings the database with a 1 second timeout and reports the applied migration version
+/// and the health of the Solana RPC endpoints.
+#[utoipa::path(
+    get,
+    path = "/readyz",
+    tag = "health",
+    responses(
+        (status = 200, description = "Service is ready", body = ReadinessStatus),
+        (status = 503, description = "A dependency is unavailable", body = ReadinessStatus)
+    )
+)]
+pub async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadinessStatus>) {
+    let database = check_database(&state.db_pool).await;
+    let read_replica = match state.config.database_read_url {
+        Some(_) => Some(check_database(&state.read_pool).await),
+        None => None,
+    };
+
+    let replica_reachable = read_replica
+        .as_ref()
+        .is_none_or(|replica| replica.reachable);
+    let (code, status) = if database.reachable && replica_reachable {
+        (StatusCode::OK, "ready")
+    } else {
+        warn!(
+            "Readiness check failed: database {:?}, read replica {:?}",
+            database.error,
+            read_replica
+                .as_ref()
+                .and_then(|replica| replica.error.as_ref())
+        );
+        (StatusCode::SERVICE_UNAVAILABLE, "unavailable")
+    };
+
+    (
+        code,
+        Json(ReadinessStatus {
+            status: status.to_string(),
+            database,
+            read_replica,
+            rpc_endpoints: state.rpc.health(),
+        }),
+    )
+}
+
+/// Pings the database and reads the latest applied migration version
+pub async fn check_database(pool: &PgPool) -> DatabaseStatus {
+    let started = Instant::now();
+    let ping = tokio::time::timeout(
+        DB_PING_TIMEOUT,
+        sqlx::query_scalar::<_, i32>("SELECT 1").fetch_one(pool),
+    )
+    .await;
+
+    let error = match ping {
+        Ok(Ok(_)) => None,
+        Ok(Err(err)) => Some(err.to_string()),
+        Err(_) => Some(format!("Timed out after {}ms", DB_PING_TIMEOUT.as_millis())),
+    };
+    if error.is_some() {
+        return DatabaseStatus {
+            reachable: false,
+            latency_ms: None,
+            migration_version: None,
+            error,
+        };
+    }
+    let latency_ms = started.elapsed().as_secs_f64() * 1000.0;
+
+    // The migrations table is missing when migrations are managed outside the app
+    let migration_version = sqlx::query_scalar::<_, Option<i64>>(
+        "SELECT MAX(version) FROM _sqlx_migrations WHERE success",
+    )
+    .fetch_one(pool)
+    .await
+    .ok()
+    .flatten();
+
+    DatabaseStatus {
+        reachable: true,
+        latency_ms: Some(latency_ms),
+        migration_version,
+        error: None,
+    }
+}
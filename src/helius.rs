@@ -0,0 +1,445 @@
+//! Receiver for Helius enhanced-transaction webhooks.
+//!
+//! Helius pushes parsed transactions for the addresses registered on a webhook. Each
+//! payload is matched against the tracked wallets and its balance changes are stored
+//! the same way as transactions pulled by [`crate::sync`], so a transaction seen by
+//! both paths ends up as one row per token.
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::debug;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::classify::{self, SwapDetails, TransactionFacts};
+use crate::events::{EventBus, WalletEventKind};
+use crate::fees::{self, TransactionFees, JITO_TIP_ACCOUNTS};
+use crate::money::TokenAmount;
+use crate::outbox::{self, OutboxEvent};
+use crate::pumpfun::{self, PumpInstruction};
+use crate::repository::NewTransaction;
+use crate::sync::{self, TokenDelta, NATIVE_SOL_MINT};
+use crate::webhooks::DetectedTransaction;
+
+/// Transaction types whose balance changes are recorded
+const RECORDED_TYPES: [&str; 2] = ["SWAP", "TRANSFER"];
+
+/// A transaction in Helius' enhanced format
+///
+/// Only the fields needed to derive per-wallet balance changes are modelled.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EnhancedTransaction {
+    /// Base58 transaction signature
+    pub signature: String,
+    /// Slot the transaction was processed in
+    #[serde(default)]
+    pub slot: u64,
+    /// Block time as a Unix timestamp
+    pub timestamp: Option<i64>,
+    /// Helius transaction type, e.g. `SWAP` or `TRANSFER`
+    #[serde(rename = "type", default)]
+    pub kind: String,
+    /// Account that paid the network fee
+    #[serde(default)]
+    pub fee_payer: String,
+    /// Network fee in lamports
+    #[serde(default)]
+    pub fee: u64,
+    /// Error of a failed transaction
+    pub transaction_error: Option<serde_json::Value>,
+    /// Per-account balance changes
+    #[serde(default)]
+    pub account_data: Vec<AccountData>,
+    /// SOL transfers between accounts
+    #[serde(default)]
+    pub native_transfers: Vec<NativeTransfer>,
+    /// Instructions with their inner instructions, as `programId`, `accounts` and
+    /// base58 `data`
+    #[serde(default)]
+    pub instructions: Vec<serde_json::Value>,
+}
+
+/// A SOL transfer in an enhanced transaction
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NativeTransfer {
+    /// Sending account
+    #[serde(default)]
+    pub from_user_account: String,
+    /// Receiving account
+    #[serde(default)]
+    pub to_user_account: String,
+    /// Lamports transferred
+    #[serde(default)]
+    pub amount: u64,
+}
+
+/// Balance changes of one account in an enhanced transaction
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountData {
+    /// Account address
+    pub account: String,
+    /// Change of the account's lamport balance
+    #[serde(default)]
+    pub native_balance_change: i64,
+    /// Changes of token accounts, keyed by their owner
+    #[serde(default)]
+    pub token_balance_changes: Vec<TokenBalanceChange>,
+}
+
+/// Change of a token account's balance
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenBalanceChange {
+    /// Owner of the token account
+    pub user_account: String,
+    /// Token mint address
+    pub mint: String,
+    /// Raw signed amount and decimals
+    pub raw_token_amount: RawTokenAmount,
+}
+
+/// Raw integer token amount with its decimals
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RawTokenAmount {
+    /// Signed raw amount as a decimal string
+    pub token_amount: String,
+    /// Number of decimals of the mint
+    pub decimals: u32,
+}
+
+impl EnhancedTransaction {
+    /// Whether the transaction succeeded and is of a recorded type or traded on a
+    /// pump.fun bonding curve
+    pub fn is_recorded(&self) -> bool {
+        self.transaction_error.is_none()
+            && (RECORDED_TYPES.contains(&self.kind.as_str()) || self.is_pump_trade())
+    }
+
+    /// Pump.fun instructions of the transaction
+    pub fn pump_instructions(&self) -> Vec<PumpInstruction> {
+        pumpfun::enhanced_instructions(&self.instructions)
+    }
+
+    /// Whether the transaction bought or sold on a pump.fun bonding curve
+    fn is_pump_trade(&self) -> bool {
+        self.pump_instructions()
+            .iter()
+            .any(PumpInstruction::is_trade)
+    }
+
+    /// Addresses whose balances changed in the transaction
+    pub fn touched_addresses(&self) -> HashSet<&str> {
+        self.account_data
+            .iter()
+            .flat_map(|data| {
+                std::iter::once(data.account.as_str()).chain(
+                    data.token_balance_changes
+                        .iter()
+                        .map(|change| change.user_account.as_str()),
+                )
+            })
+            .collect()
+    }
+
+    /// Reads the classification facts of the transaction from the point of view of `owner`
+    ///
+    /// Enhanced transactions do not list their signers, so the fee payer is taken as the
+    /// only signer.
+    pub fn facts(&self, owner: &str) -> TransactionFacts {
+        let credits = self.account_data.iter().flat_map(|data| {
+            data.token_balance_changes
+                .iter()
+                .filter(|change| {
+                    change
+                        .raw_token_amount
+                        .token_amount
+                        .parse::<i128>()
+                        .is_ok_and(|raw| raw > 0)
+                })
+                .map(|change| (change.mint.as_str(), change.user_account.as_str()))
+        });
+
+        let swap = self.swap(owner);
+
+        TransactionFacts {
+            is_swap: self.kind == "SWAP" || self.is_pump_trade() || swap.is_some(),
+            signer: self.fee_payer == owner,
+            recipients: classify::count_recipients(credits),
+            sol_credited_elsewhere: self
+                .account_data
+                .iter()
+                .any(|data| data.account != owner && data.native_balance_change > 0),
+            swap,
+        }
+    }
+
+    /// The Jupiter route `owner` swapped through, decoded from the swap events among
+    /// the instructions
+    ///
+    /// Direct swaps on a DEX are not decoded, as enhanced transactions do not parse the
+    /// token transfers they make.
+    pub fn swap(&self, owner: &str) -> Option<SwapDetails> {
+        if self.fee_payer != owner {
+            return None;
+        }
+        let decimals: HashMap<&str, u32> = self
+            .account_data
+            .iter()
+            .flat_map(|data| &data.token_balance_changes)
+            .map(|change| (change.mint.as_str(), change.raw_token_amount.decimals))
+            .collect();
+        let instructions = self.instructions.iter().flat_map(|instruction| {
+            std::iter::once(instruction).chain(
+                instruction["innerInstructions"]
+                    .as_array()
+                    .into_iter()
+                    .flatten(),
+            )
+        });
+
+        SwapDetails::from_jupiter_events(instructions, |mint| {
+            if mint == NATIVE_SOL_MINT {
+                Some(9)
+            } else {
+                decimals.get(mint).copied()
+            }
+        })
+    }
+
+    /// Fees `owner` paid in the transaction, or `None` if it paid neither the network
+    /// fee nor a Jito tip
+    ///
+    /// Enhanced transactions only carry the total network fee, so the priority fee is
+    /// left unknown.
+    pub fn fees(&self, owner: &str) -> Option<TransactionFees> {
+        TransactionFees {
+            fee_lamports: if self.fee_payer == owner { self.fee } else { 0 },
+            priority_fee_lamports: None,
+            jito_tip_lamports: self
+                .native_transfers
+                .iter()
+                .filter(|transfer| {
+                    transfer.from_user_account == owner
+                        && JITO_TIP_ACCOUNTS.contains(&transfer.to_user_account.as_str())
+                })
+                .map(|transfer| transfer.amount)
+                .sum(),
+        }
+        .paid()
+    }
+
+    /// Computes `owner`'s per-mint balance changes
+    ///
+    /// Native SOL changes exclude the network fee when `owner` paid it, matching
+    /// [`sync::token_deltas`].
+    pub fn token_deltas(&self, owner: &str) -> Vec<TokenDelta> {
+        let mut balances: HashMap<(&str, &str), (i128, u32)> = HashMap::new();
+        let mut lamports: HashMap<&str, i128> = HashMap::new();
+
+        for data in &self.account_data {
+            *lamports.entry(data.account.as_str()).or_default() +=
+                data.native_balance_change as i128;
+
+            for change in &data.token_balance_changes {
+                let Ok(raw) = change.raw_token_amount.token_amount.parse::<i128>() else {
+                    continue;
+                };
+                let balance = balances
+                    .entry((change.user_account.as_str(), change.mint.as_str()))
+                    .or_insert((0, change.raw_token_amount.decimals));
+                balance.0 += raw;
+            }
+        }
+
+        *lamports.entry(self.fee_payer.as_str()).or_default() += self.fee as i128;
+
+        let mut deltas: Vec<TokenDelta> = balances
+            .iter()
+            .filter(|((holder, _), (raw, _))| *holder == owner && *raw != 0)
+            .filter_map(|((_, mint), (raw, decimals))| {
+                Some(TokenDelta {
+                    mint: mint.to_string(),
+                    amount: TokenAmount::from_raw(*raw, *decimals)?,
+                    counterparty: sync::counterparty(
+                        balances
+                            .iter()
+                            .filter(|((_, other), _)| other == mint)
+                            .map(|((holder, _), (raw, _))| (*holder, *raw)),
+                        owner,
+                        *raw,
+                    ),
+                })
+            })
+            .collect();
+
+        let sol_delta = lamports.get(owner).filter(|delta| **delta != 0);
+        if let Some((&delta, amount)) =
+            sol_delta.and_then(|delta| Some((delta, TokenAmount::from_raw(*delta, 9)?)))
+        {
+            deltas.push(TokenDelta {
+                mint: NATIVE_SOL_MINT.to_string(),
+                amount,
+                counterparty: sync::counterparty(
+                    lamports.iter().map(|(account, delta)| (*account, *delta)),
+                    owner,
+                    delta,
+                ),
+            });
+        }
+
+        deltas.sort_by(|a, b| a.mint.cmp(&b.mint));
+        deltas
+    }
+}
+
+/// Summary of a processed webhook delivery
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WebhookReport {
+    /// Number of transactions in the payload
+    pub transactions_received: usize,
+    /// Number of balance changes processed, including ones already recorded
+    pub transactions_upserted: usize,
+    /// Wallets that received transactions not recorded before
+    #[serde(skip)]
+    pub updated_wallets: Vec<Uuid>,
+}
+
+/// Stores the balance changes of every tracked wallet touched by the transactions
+///
+/// Newly recorded transactions are announced on the event bus, and recorded in the
+/// [outbox](crate::outbox) with the rows for webhooks and whale checks from
+/// `whale_threshold_usd`.
+pub async fn ingest(
+    pool: &PgPool,
+    events: &dyn EventBus,
+    transactions: &[EnhancedTransaction],
+    whale_threshold_usd: Option<f64>,
+) -> Result<WebhookReport, sqlx::Error> {
+    // Migrations of pump.fun tokens are not recorded, but graduate the token
+    for tx in transactions
+        .iter()
+        .filter(|tx| tx.transaction_error.is_none())
+    {
+        pumpfun::record_status(pool, &tx.pump_instructions()).await?;
+    }
+
+    let recorded: Vec<&EnhancedTransaction> =
+        transactions.iter().filter(|tx| tx.is_recorded()).collect();
+
+    let addresses: Vec<String> = recorded
+        .iter()
+        .flat_map(|tx| tx.touched_addresses())
+        .collect::<HashSet<_>>()
+        .into_iter()
+        .map(str::to_string)
+        .collect();
+
+    // The same address may be tracked by several users
+    let wallets = sqlx::query_as::<_, (Uuid, String)>(
+        "SELECT id, address FROM wallets WHERE address = ANY($1)",
+    )
+    .bind(&addresses)
+    .fetch_all(pool)
+    .await?;
+
+    let mut upserted = 0;
+    let mut detected: HashMap<Uuid, Vec<DetectedTransaction>> = HashMap::new();
+    for tx in recorded {
+        let block_time = tx
+            .timestamp
+            .and_then(|ts| Utc.timestamp_opt(ts, 0).single());
+
+        for (wallet_id, address) in &wallets {
+            if let Some(paid) = tx.fees(address) {
+                fees::record(pool, *wallet_id, &tx.signature, block_time, &paid).await?;
+            }
+
+            let facts = tx.facts(address);
+            let deltas = tx.token_deltas(address);
+            let mut db_tx = pool.begin().await?;
+            let mut inserted_rows = Vec::new();
+            for delta in &deltas {
+                let row = NewTransaction {
+                    transaction_hash: tx.signature.clone(),
+                    block_number: tx.slot as i64,
+                    block_time,
+                    token_address: delta.mint.clone(),
+                    token_symbol: sync::known_symbol(&delta.mint).to_string(),
+                    amount: delta.amount,
+                    category: classify::classify(&facts, &deltas, delta),
+                    swap: facts.swap.clone(),
+                    counterparty: delta.counterparty.clone(),
+                };
+                let inserted = sync::upsert_transaction(&mut db_tx, *wallet_id, &row).await?;
+                upserted += 1;
+
+                if inserted {
+                    inserted_rows.push(DetectedTransaction {
+                        transaction_hash: tx.signature.clone(),
+                        token_address: delta.mint.clone(),
+                        amount: delta.amount,
+                        block_time,
+                    });
+                }
+            }
+
+            if !inserted_rows.is_empty() {
+                outbox::record(
+                    &mut db_tx,
+                    *wallet_id,
+                    &OutboxEvent::TransactionsDetected {
+                        transactions: inserted_rows.clone(),
+                        whale_threshold_usd,
+                    },
+                )
+                .await?;
+            }
+            db_tx.commit().await?;
+
+            if !inserted_rows.is_empty() {
+                detected
+                    .entry(*wallet_id)
+                    .or_default()
+                    .extend(inserted_rows);
+            }
+        }
+    }
+
+    for (wallet_id, transactions) in &detected {
+        events.publish(
+            *wallet_id,
+            WalletEventKind::TransactionsDetected {
+                transactions: transactions.clone(),
+            },
+        );
+    }
+
+    debug!(
+        "Helius webhook: {} transactions, {} rows upserted",
+        transactions.len(),
+        upserted
+    );
+
+    Ok(WebhookReport {
+        transactions_received: transactions.len(),
+        transactions_upserted: upserted,
+        updated_wallets: detected.into_keys().collect(),
+    })
+}
+
+/// Compares a presented secret with the configured one in constant time
+pub fn secret_matches(presented: &str, expected: &str) -> bool {
+    presented.len() == expected.len()
+        && presented
+            .bytes()
+            .zip(expected.bytes())
+            .fold(0u8, |acc, (a, b)| acc | (a ^ b))
+            == 0
+}
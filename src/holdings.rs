@@ -0,0 +1,60 @@
+use sqlx::PgPool;
+use tracing::info;
+use uuid::Uuid;
+
+use crate::money::TokenAmount;
+
+/// Recomputes the `holdings` table from the transactions, repairing any drift
+///
+/// The table is normally kept in step by triggers on `transactions`; a rebuild is
+/// only needed after changes that bypassed them, e.g. a restore with triggers
+/// disabled. Writes to transactions wait until the rebuild commits. Returns the
+/// number of positions written.
+pub async fn rebuild(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+
+    // Blocks writes, so no change lands between clearing and recomputing
+    sqlx::query("LOCK TABLE transactions IN SHARE MODE")
+        .execute(&mut *tx)
+        .await?;
+    sqlx::query("DELETE FROM holdings")
+        .execute(&mut *tx)
+        .await?;
+    let written = sqlx::query(
+        r#"
+        INSERT INTO holdings (wallet_id, token_address, token_symbol, amount)
+        SELECT wallet_id, token_address, MAX(token_symbol), SUM(amount)
+        FROM transactions
+        GROUP BY wallet_id, token_address
+        "#,
+    )
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+
+    tx.commit().await?;
+    info!("Rebuilt holdings: {} positions", written);
+    Ok(written)
+}
+
+/// Current positions of the given wallets, summed per token and ordered by mint
+///
+/// Fully sold positions are left out. Returns `(mint, symbol, amount)` rows.
+pub async fn positions(
+    pool: &PgPool,
+    wallet_ids: &[Uuid],
+) -> Result<Vec<(String, String, TokenAmount)>, sqlx::Error> {
+    sqlx::query_as::<_, (String, String, TokenAmount)>(
+        r#"
+        SELECT token_address, MAX(token_symbol), SUM(amount)
+        FROM holdings
+        WHERE wallet_id = ANY($1)
+        GROUP BY token_address
+        HAVING SUM(amount) <> 0
+        ORDER BY token_address
+        "#,
+    )
+    .bind(wallet_ids)
+    .fetch_all(pool)
+    .await
+}
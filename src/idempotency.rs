@@ -0,0 +1,258 @@
+use axum::{
+    body::{boxed, Body, Full},
+    extract::State,
+    http::{header, HeaderValue, Method, Request, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use sqlx::PgPool;
+use tracing::{error, info};
+
+use crate::auth::{api_key_from_headers, hash_api_key};
+use crate::router::RequestBody;
+use crate::{AppError, AppState};
+
+/// Header carrying the client-chosen key identifying a request across retries
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Header set on responses replayed from an earlier request with the same key
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Hours a stored response is replayed for; afterwards the key can be used again
+pub const IDEMPOTENCY_KEY_TTL_HOURS: i32 = 24;
+
+/// Longest accepted idempotency key
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// Scope of keys sent without a credential, e.g. in demo mode
+const ANONYMOUS_SCOPE: &str = "anonymous";
+
+/// A stored request and, once handled, its response
+#[derive(Debug, sqlx::FromRow)]
+struct StoredRequest {
+    request_hash: String,
+    status_code: Option<i32>,
+    content_type: Option<String>,
+    response_body: Option<Vec<u8>>,
+}
+
+/// Reads the idempotency key, rejecting empty, overly long or non-printable ones
+fn parse_key(value: &HeaderValue) -> Result<String, AppError> {
+    value
+        .to_str()
+        .ok()
+        .map(str::trim)
+        .filter(|key| {
+            !key.is_empty()
+                && key.len() <= MAX_IDEMPOTENCY_KEY_LEN
+                && key.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Idempotency-Key must be 1 to {MAX_IDEMPOTENCY_KEY_LEN} printable ASCII characters"
+            ))
+        })
+}
+
+/// Fingerprints a request, so a key reused for a different request can be detected
+fn hash_request(method: &Method, uri: &Uri, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(method.as_str().as_bytes());
+    hasher.update(b" ");
+    hasher.update(
+        uri.path_and_query()
+            .map_or(uri.path(), |p| p.as_str())
+            .as_bytes(),
+    );
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+/// Claims the key for a new request, returning `false` if it is already taken
+///
+/// Expired keys are purged first, which also frees this key if its response expired.
+async fn reserve(
+    pool: &PgPool,
+    scope: &str,
+    key: &str,
+    request_hash: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM idempotency_keys WHERE created_at < NOW() - make_interval(hours => $1)",
+    )
+    .bind(IDEMPOTENCY_KEY_TTL_HOURS)
+    .execute(pool)
+    .await?;
+
+    let reserved = sqlx::query_scalar::<_, String>(
+        r#"
+        INSERT INTO idempotency_keys (scope, key, request_hash)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (scope, key) DO NOTHING
+        RETURNING key
+        "#,
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(request_hash)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(reserved.is_some())
+}
+
+/// Responds to a retried request with the stored response of the first one
+async fn replay(
+    pool: &PgPool,
+    scope: &str,
+    key: &str,
+    request_hash: &str,
+) -> Result<Response, AppError> {
+    let stored = sqlx::query_as::<_, StoredRequest>(
+        r#"
+        SELECT request_hash, status_code, content_type, response_body
+        FROM idempotency_keys
+        WHERE scope = $1 AND key = $2
+        "#,
+    )
+    .bind(scope)
+    .bind(key)
+    .fetch_optional(pool)
+    .await?
+    // The first request failed and released the key in the meantime
+    .ok_or_else(|| {
+        AppError::Conflict("Request with this Idempotency-Key failed, retry it".to_string())
+    })?;
+
+    if stored.request_hash != request_hash {
+        return Err(AppError::UnprocessableEntity(
+            "Idempotency-Key was already used for a different request".to_string(),
+        ));
+    }
+
+    let (Some(status_code), Some(body)) = (stored.status_code, stored.response_body) else {
+        return Err(AppError::Conflict(
+            "Request with this Idempotency-Key is still being processed".to_string(),
+        ));
+    };
+
+    info!("Replaying response for Idempotency-Key {}", key);
+
+    let mut response = Response::builder()
+        .status(status_code as u16)
+        .header(IDEMPOTENT_REPLAYED_HEADER, "true");
+    if let Some(content_type) = stored.content_type {
+        response = response.header(header::CONTENT_TYPE, content_type);
+    }
+    response
+        .body(boxed(Full::from(body)))
+        .map_err(|e| AppError::InternalServerError(format!("Invalid stored response: {e}")))
+}
+
+/// Releases a key whose request failed, so a retry is handled afresh
+async fn release(pool: &PgPool, scope: &str, key: &str) {
+    let released = sqlx::query("DELETE FROM idempotency_keys WHERE scope = $1 AND key = $2")
+        .bind(scope)
+        .bind(key)
+        .execute(pool)
+        .await;
+    if let Err(err) = released {
+        error!("Failed to release Idempotency-Key {}: {}", key, err);
+    }
+}
+
+/// Middleware making a route safe to retry with an `Idempotency-Key` header
+///
+/// The response to the first request with a key is stored for
+/// [`IDEMPOTENCY_KEY_TTL_HOURS`] and replayed, marked with `Idempotent-Replayed`,
+/// to retries with the same key and request. Keys are scoped to the credential the
+/// request was sent with. A key reused for a different request is rejected with
+/// `422`, and one whose first request is still in flight with `409`. Server errors
+/// are not stored, so the request can be retried. Requests without the header, and
+/// all requests in demo mode, are passed through unchanged.
+///
+/// Add it to a route with `middleware::from_fn_with_state(state, idempotency_middleware)`.
+pub async fn idempotency_middleware(
+    State(state): State<AppState>,
+    request: Request<RequestBody>,
+    next: Next<RequestBody>,
+) -> Result<Response, AppError> {
+    let Some(value) = request.headers().get(IDEMPOTENCY_KEY_HEADER) else {
+        return Ok(next.run(request).await);
+    };
+    if state.config.is_demo() {
+        return Ok(next.run(request).await);
+    }
+
+    let key = parse_key(value)?;
+    let scope = api_key_from_headers(request.headers())
+        .map(hash_api_key)
+        .unwrap_or_else(|| ANONYMOUS_SCOPE.to_string());
+
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await.map_err(|e| {
+        if e.is::<http_body::LengthLimitError>() {
+            AppError::PayloadTooLarge(e.to_string())
+        } else {
+            AppError::BadRequest(format!("Failed to read request body: {e}"))
+        }
+    })?;
+    let request_hash = hash_request(&parts.method, &parts.uri, &body);
+
+    let pool = &state.db_pool;
+    if !reserve(pool, &scope, &key, &request_hash).await? {
+        return replay(pool, &scope, &key, &request_hash).await;
+    }
+
+    // The body was already read within the limit
+    let request = Request::from_parts(parts, RequestBody::new(Body::from(body), usize::MAX));
+    let response = next.run(request).await;
+    if response.status().is_server_error() {
+        release(pool, &scope, &key).await;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match hyper::body::to_bytes(body).await {
+        Ok(body) => body,
+        Err(err) => {
+            release(pool, &scope, &key).await;
+            return Err(AppError::InternalServerError(format!(
+                "Failed to read response body: {err}"
+            )));
+        }
+    };
+
+    let stored = sqlx::query(
+        r#"
+        UPDATE idempotency_keys
+        SET status_code = $3, content_type = $4, response_body = $5
+        WHERE scope = $1 AND key = $2
+        "#,
+    )
+    .bind(&scope)
+    .bind(&key)
+    .bind(i32::from(parts.status.as_u16()))
+    .bind(
+        parts
+            .headers
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok()),
+    )
+    .bind(body.as_ref())
+    .execute(pool)
+    .await;
+    if let Err(err) = stored {
+        // The response is still sent, but a retry is handled as a new request
+        error!(
+            "Failed to store response for Idempotency-Key {}: {}",
+            key, err
+        );
+        release(pool, &scope, &key).await;
+    }
+
+    Ok(Response::from_parts(parts, boxed(Full::from(body))).into_response())
+}
@@ -0,0 +1,592 @@
+//! Persistent queue of background work.
+//!
+//! Wallet syncs, daily snapshots, alert evaluations, reports, whale detection, outgoing
+//! webhook deliveries and push notifications are queued as rows of the `jobs` table.
+//! Workers claim due rows with `FOR UPDATE SKIP LOCKED`, so several instances share
+//! the queue without running a job twice, and queued work survives restarts. A failed
+//! job is retried with exponential backoff until it runs out of attempts and is marked
+//! `failed`, where it stays for inspection until retried; failed webhook deliveries and
+//! notifications also land in the [dead-letter queue](crate::dlq). A job whose worker
+//! stopped mid-run is claimed again once its lease expires. Events of the
+//! [outbox](crate::outbox) are turned into jobs by the worker before each batch.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgExecutor, PgPool};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::account;
+use crate::alerts;
+use crate::auth::{lockout, sessions};
+use crate::dlq;
+use crate::domains;
+use crate::features::Feature;
+use crate::models::Wallet;
+use crate::notifications::{self, Notifier, SendOutcome};
+use crate::outbox;
+use crate::reports::{self, ReportPeriod};
+use crate::resilience::CircuitBreakers;
+use crate::snapshots;
+use crate::sync::{self, SyncError};
+use crate::webhooks::{self, DeliveryOutcome, DetectedTransaction};
+use crate::whales;
+use crate::AppState;
+
+/// Attempts of a sync or snapshot job before it is marked failed
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Delay before the first retry; doubled after every further failure
+const RETRY_BASE_DELAY: Duration = Duration::from_secs(30);
+
+/// Longest delay between two attempts
+const RETRY_MAX_DELAY: Duration = Duration::from_secs(3600);
+
+/// How long a claimed job is hidden from other workers
+const CLAIM_LEASE: Duration = Duration::from_secs(600);
+
+/// Maximum number of jobs claimed per worker cycle
+const BATCH_SIZE: i64 = 50;
+
+/// How long succeeded jobs are kept before being pruned
+const SUCCEEDED_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+/// A unit of background work
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Job {
+    /// Sync a wallet's recent transactions, then check its balance thresholds
+    SyncWallet {
+        /// ID of the wallet
+        wallet_id: Uuid,
+    },
+    /// Record today's value snapshot of a wallet
+    RecordSnapshot {
+        /// ID of the wallet
+        wallet_id: Uuid,
+    },
+    /// Attempt an outgoing webhook delivery
+    DeliverWebhook {
+        /// ID of the row in `webhook_deliveries`
+        delivery_id: Uuid,
+    },
+    /// Check every enabled alert against the price feed
+    EvaluateAlerts,
+    /// Send a queued push notification
+    SendNotification {
+        /// ID of the row in `notifications`
+        notification_id: Uuid,
+    },
+    /// Generate a user's report of a period and send it to the opted-in channels
+    GenerateReport {
+        /// ID of the user
+        user_id: Uuid,
+        /// Period the report covers
+        period: ReportPeriod,
+    },
+    /// Value newly recorded transactions of a wallet and notify the large ones
+    NotifyLargeTransactions {
+        /// ID of the wallet
+        wallet_id: Uuid,
+        /// The transactions as detected
+        transactions: Vec<DetectedTransaction>,
+    },
+    /// Value newly recorded transactions of a wallet and flag the whale movements
+    DetectWhaleMovements {
+        /// ID of the wallet
+        wallet_id: Uuid,
+        /// The transactions as detected
+        transactions: Vec<DetectedTransaction>,
+    },
+}
+
+impl Job {
+    /// Name of the job as stored in `jobs.kind`
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::SyncWallet { .. } => "sync_wallet",
+            Self::RecordSnapshot { .. } => "record_snapshot",
+            Self::DeliverWebhook { .. } => "deliver_webhook",
+            Self::EvaluateAlerts => "evaluate_alerts",
+            Self::SendNotification { .. } => "send_notification",
+            Self::GenerateReport { .. } => "generate_report",
+            Self::NotifyLargeTransactions { .. } => "notify_large_transactions",
+            Self::DetectWhaleMovements { .. } => "detect_whale_movements",
+        }
+    }
+
+    /// Key under which the job is queued at most once
+    fn dedupe_key(&self) -> String {
+        let id = match self {
+            Self::SyncWallet { wallet_id } | Self::RecordSnapshot { wallet_id } => wallet_id,
+            Self::DeliverWebhook { delivery_id } => delivery_id,
+            Self::SendNotification { notification_id } => notification_id,
+            Self::EvaluateAlerts => return self.kind().to_string(),
+            Self::GenerateReport { user_id, period } => {
+                return format!("{}:{}:{}", self.kind(), period.as_str(), user_id);
+            }
+            // A detection batch is identified by its first transaction, which is
+            // recorded only once
+            Self::NotifyLargeTransactions {
+                wallet_id,
+                transactions,
+            }
+            | Self::DetectWhaleMovements {
+                wallet_id,
+                transactions,
+            } => {
+                let first = transactions.first();
+                return format!(
+                    "{}:{}:{}:{}",
+                    self.kind(),
+                    wallet_id,
+                    first.map_or("", |tx| tx.transaction_hash.as_str()),
+                    first.map_or("", |tx| tx.token_address.as_str()),
+                );
+            }
+        };
+        format!("{}:{}", self.kind(), id)
+    }
+
+    /// Attempts before the job is marked failed
+    fn max_attempts(&self) -> i32 {
+        match self {
+            Self::DeliverWebhook { .. } => webhooks::MAX_ATTEMPTS,
+            Self::SendNotification { .. } => notifications::MAX_ATTEMPTS,
+            // Not retried: the next scheduled evaluation comes soon enough
+            Self::EvaluateAlerts => 1,
+            Self::SyncWallet { .. }
+            | Self::RecordSnapshot { .. }
+            | Self::GenerateReport { .. }
+            | Self::NotifyLargeTransactions { .. }
+            | Self::DetectWhaleMovements { .. } => MAX_ATTEMPTS,
+        }
+    }
+}
+
+/// A job that ran out of attempts
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow)]
+pub struct FailedJob {
+    /// ID of the job
+    pub id: Uuid,
+    /// Kind of the job
+    pub kind: String,
+    /// The job as queued
+    pub payload: Value,
+    /// Number of attempts made
+    pub attempts: i32,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+    /// When the job was given up on
+    pub finished_at: Option<DateTime<Utc>>,
+}
+
+/// How a run of a job ended
+enum Outcome {
+    /// The work is done, or no longer needed
+    Done,
+    /// The work failed and is retried while attempts remain
+    Failed(String),
+    /// The work could not be attempted yet; it runs again after the delay without
+    /// using up an attempt
+    Deferred(Duration),
+}
+
+/// Delay before the attempt following `attempts` failed ones
+pub fn retry_delay(attempts: i32) -> Duration {
+    let exponent = attempts.saturating_sub(1).clamp(0, 16) as u32;
+    RETRY_BASE_DELAY
+        .saturating_mul(2u32.pow(exponent))
+        .min(RETRY_MAX_DELAY)
+}
+
+/// Queues a job to run now, unless the same job is already queued or running
+///
+/// Takes any executor, so a job can be queued in the transaction that creates its
+/// work. Returns whether the job was queued.
+pub async fn enqueue<'e>(executor: impl PgExecutor<'e>, job: &Job) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        INSERT INTO jobs (id, kind, payload, dedupe_key, max_attempts)
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (dedupe_key) WHERE status IN ('pending', 'running') DO NOTHING
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(job.kind())
+    .bind(sqlx::types::Json(job))
+    .bind(job.dedupe_key())
+    .bind(job.max_attempts())
+    .execute(executor)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Jobs that ran out of attempts, most recently failed first
+pub async fn list_failed(pool: &PgPool, limit: i64) -> Result<Vec<FailedJob>, sqlx::Error> {
+    sqlx::query_as::<_, FailedJob>(
+        r#"
+        SELECT id, kind, payload, attempts, last_error, finished_at
+        FROM jobs
+        WHERE status = 'failed'
+        ORDER BY finished_at DESC
+        LIMIT $1
+        "#,
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// Queues a failed job again with fresh attempts
+///
+/// Returns `false` if the job is not failed, or the same work is already queued.
+pub async fn retry(pool: &PgPool, job_id: Uuid) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        UPDATE jobs
+        SET status = 'pending', attempts = 0, run_at = NOW(), finished_at = NULL
+        WHERE id = $1
+          AND status = 'failed'
+          AND NOT EXISTS (
+              SELECT 1 FROM jobs active
+              WHERE active.dedupe_key = jobs.dedupe_key
+                AND active.status IN ('pending', 'running')
+          )
+        "#,
+    )
+    .bind(job_id)
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Deletes succeeded jobs older than the retention period and returns how many
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        r#"
+        DELETE FROM jobs
+        WHERE status = 'succeeded'
+          AND finished_at < NOW() - make_interval(secs => $1)
+        "#,
+    )
+    .bind(SUCCEEDED_RETENTION.as_secs_f64())
+    .execute(pool)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Spawns the background task running due jobs every `interval`
+pub fn spawn_worker(state: AppState, interval: Duration) -> JoinHandle<()> {
+    info!("Starting job worker with interval {:?}", interval);
+
+    tokio::spawn(async move {
+        let worker = Worker::new(state);
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            // Maintenance must not race queued writes
+            if worker.state.read_only.is_enabled() {
+                continue;
+            }
+
+            match worker.run_due().await {
+                Ok(succeeded) if succeeded > 0 => debug!("Ran {} jobs", succeeded),
+                Ok(_) => {}
+                Err(err) => error!("Job worker cycle failed: {}", err),
+            }
+            if let Err(err) = prune(&worker.state.db_pool).await {
+                warn!("Pruning succeeded jobs failed: {}", err);
+            }
+            if let Err(err) = outbox::prune(&worker.state.db_pool).await {
+                warn!("Pruning processed outbox events failed: {}", err);
+            }
+            if let Err(err) = sessions::prune(&worker.state.db_pool).await {
+                warn!("Pruning ended sessions failed: {}", err);
+            }
+            if let Err(err) = lockout::prune(&worker.state.db_pool).await {
+                warn!("Pruning sign-in failures failed: {}", err);
+            }
+            if let Err(err) = account::purge_due(&worker.state.db_pool).await {
+                warn!("Purging deleted accounts failed: {}", err);
+            }
+        }
+    })
+}
+
+/// Runs queued jobs against the application state
+pub struct Worker {
+    state: AppState,
+    http: reqwest::Client,
+    breakers: CircuitBreakers,
+    notifier: Notifier,
+}
+
+impl Worker {
+    /// Creates a worker with the webhook client, circuit breakers and notification
+    /// senders of `state`'s configuration
+    pub fn new(state: AppState) -> Self {
+        let breakers = state.config.circuit_breakers();
+        let notifier = Notifier::from_config(&state.config).with_secrets(state.secrets.clone());
+        let http = webhooks::delivery_client(state.config.webhook_allow_private_urls);
+        Self {
+            state,
+            http,
+            breakers,
+            notifier,
+        }
+    }
+
+    /// Claims a batch of due jobs, runs them one after another and returns how many
+    /// succeeded
+    ///
+    /// Pending [outbox](crate::outbox) events are relayed first, so the jobs they queue
+    /// are run in the same batch.
+    pub async fn run_due(&self) -> Result<usize, sqlx::Error> {
+        let pool = &self.state.db_pool;
+        if let Err(err) = outbox::relay(pool).await {
+            warn!("Relaying outbox events failed: {}", err);
+        }
+        let claimed = sqlx::query_as::<_, (Uuid, Value, i32, i32)>(
+            r#"
+            UPDATE jobs
+            SET status = 'running',
+                attempts = attempts + 1,
+                locked_until = NOW() + make_interval(secs => $1)
+            WHERE id IN (
+                SELECT id FROM jobs
+                WHERE (status = 'pending' AND run_at <= NOW())
+                   OR (status = 'running' AND locked_until <= NOW())
+                ORDER BY run_at
+                LIMIT $2
+                FOR UPDATE SKIP LOCKED
+            )
+            RETURNING id, payload, attempts, max_attempts
+            "#,
+        )
+        .bind(CLAIM_LEASE.as_secs_f64())
+        .bind(BATCH_SIZE)
+        .fetch_all(pool)
+        .await?;
+
+        let mut succeeded = 0;
+        for (id, payload, attempts, max_attempts) in claimed {
+            let retry_in = (attempts < max_attempts).then(|| retry_delay(attempts));
+            let job = match serde_json::from_value::<Job>(payload) {
+                Ok(job) => job,
+                // Not retried: another attempt cannot make the payload readable
+                Err(err) => {
+                    finish(pool, id, Some(&format!("Unreadable job: {err}")), None).await?;
+                    continue;
+                }
+            };
+            let outcome = self.run(&job, retry_in).await?;
+
+            match outcome {
+                Outcome::Done => {
+                    finish(pool, id, None, None).await?;
+                    succeeded += 1;
+                }
+                Outcome::Failed(error) => {
+                    match retry_in {
+                        Some(_) => debug!("Job {} failed, will retry: {}", id, error),
+                        None => {
+                            warn!(
+                                "Giving up on job {} after {} attempts: {}",
+                                id, attempts, error
+                            );
+                            dlq::record(pool, &job, attempts, &error).await?;
+                        }
+                    }
+                    finish(pool, id, Some(&error), retry_in).await?;
+                }
+                Outcome::Deferred(delay) => {
+                    sqlx::query(
+                        r#"
+                        UPDATE jobs
+                        SET status = 'pending', attempts = attempts - 1, locked_until = NULL,
+                            run_at = NOW() + make_interval(secs => $2)
+                        WHERE id = $1
+                        "#,
+                    )
+                    .bind(id)
+                    .bind(delay.as_secs_f64())
+                    .execute(pool)
+                    .await?;
+                }
+            }
+        }
+
+        Ok(succeeded)
+    }
+
+    /// Runs one job; `retry_in` is when a failed attempt is retried, `None` on the
+    /// last attempt
+    async fn run(&self, job: &Job, retry_in: Option<Duration>) -> Result<Outcome, sqlx::Error> {
+        let state = &self.state;
+        let outcome = match job {
+            // Held back until the feature is switched on again
+            Job::SyncWallet { .. } if !state.features.is_enabled(Feature::Sync) => {
+                Outcome::Deferred(state.config.feature_refresh_interval())
+            }
+            &Job::SyncWallet { wallet_id } => {
+                let wallet = sqlx::query_as::<_, Wallet>(
+                    r#"
+                    SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+                    FROM wallets
+                    WHERE id = $1
+                    "#,
+                )
+                .bind(wallet_id)
+                .fetch_optional(&state.db_pool)
+                .await?;
+                // Deleted since the sync was queued
+                let Some(wallet) = wallet else {
+                    return Ok(Outcome::Done);
+                };
+
+                match sync::sync_wallet(
+                    &state.db_pool,
+                    &state.rpc,
+                    state.events.as_ref(),
+                    &wallet,
+                    state.config.sync_signature_limit,
+                    state.config.whale_threshold_usd,
+                )
+                .await
+                {
+                    Ok(report) => {
+                        if report.transactions_inserted > 0 {
+                            if let Err(err) = webhooks::check_balance_thresholds(
+                                &state.db_pool,
+                                state.prices.as_ref(),
+                                wallet.id,
+                            )
+                            .await
+                            {
+                                warn!(
+                                    "Balance threshold check of wallet {} failed: {}",
+                                    wallet.id, err
+                                );
+                            }
+                        }
+                        domains::refresh(&state.db_pool, state.domains.as_ref(), &wallet).await?;
+                        Outcome::Done
+                    }
+                    Err(SyncError::Unavailable(open)) => Outcome::Deferred(open.retry_in),
+                    Err(err) => Outcome::Failed(err.to_string()),
+                }
+            }
+            &Job::RecordSnapshot { wallet_id } => {
+                match snapshots::record_snapshot(state, wallet_id).await {
+                    Ok(_) => Outcome::Done,
+                    Err(err) => Outcome::Failed(err.to_string()),
+                }
+            }
+            Job::EvaluateAlerts => match alerts::evaluate(state).await {
+                Ok(_) => Outcome::Done,
+                Err(err) => Outcome::Failed(err.to_string()),
+            },
+            &Job::DeliverWebhook { delivery_id } => {
+                match webhooks::deliver(
+                    &state.db_pool,
+                    &state.secrets,
+                    state.config.webhook_allow_private_urls,
+                    &self.http,
+                    &self.breakers,
+                    delivery_id,
+                    retry_in,
+                )
+                .await?
+                {
+                    DeliveryOutcome::Delivered | DeliveryOutcome::Cancelled => Outcome::Done,
+                    DeliveryOutcome::Failed(error) => Outcome::Failed(error),
+                    DeliveryOutcome::Deferred(delay) => Outcome::Deferred(delay),
+                }
+            }
+            &Job::SendNotification { notification_id } => {
+                match self
+                    .notifier
+                    .deliver(&state.db_pool, notification_id, retry_in)
+                    .await?
+                {
+                    SendOutcome::Sent | SendOutcome::Cancelled => Outcome::Done,
+                    SendOutcome::Failed(error) => Outcome::Failed(error),
+                }
+            }
+            &Job::GenerateReport { user_id, period } => {
+                match reports::generate_report(state, user_id, period).await {
+                    Ok(_) => Outcome::Done,
+                    Err(err) => Outcome::Failed(err.to_string()),
+                }
+            }
+            Job::NotifyLargeTransactions {
+                wallet_id,
+                transactions,
+            } => match notifications::notify_large_transactions(
+                &state.db_pool,
+                state.prices.as_ref(),
+                *wallet_id,
+                transactions,
+            )
+            .await
+            {
+                Ok(_) => Outcome::Done,
+                Err(err) => Outcome::Failed(err.to_string()),
+            },
+            Job::DetectWhaleMovements {
+                wallet_id,
+                transactions,
+            } => match whales::detect(state, *wallet_id, transactions).await {
+                Ok(_) => Outcome::Done,
+                Err(err) => Outcome::Failed(err.to_string()),
+            },
+        };
+
+        Ok(outcome)
+    }
+}
+
+/// Records the end of a claimed job's run: succeeded if `error` is `None`, otherwise
+/// queued again after `retry_in`, or failed if there is none
+async fn finish(
+    pool: &PgPool,
+    job_id: Uuid,
+    error: Option<&str>,
+    retry_in: Option<Duration>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE jobs
+        SET status = CASE
+                WHEN $2::TEXT IS NULL THEN 'succeeded'
+                WHEN $3::DOUBLE PRECISION IS NULL THEN 'failed'
+                ELSE 'pending'
+            END,
+            last_error = $2,
+            locked_until = NULL,
+            run_at = CASE
+                WHEN $3::DOUBLE PRECISION IS NULL THEN run_at
+                ELSE NOW() + make_interval(secs => $3)
+            END,
+            finished_at = CASE WHEN $3::DOUBLE PRECISION IS NULL THEN NOW() END
+        WHERE id = $1
+        "#,
+    )
+    .bind(job_id)
+    .bind(error)
+    .bind(retry_in.map(|delay| delay.as_secs_f64()))
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
@@ -0,0 +1,140 @@
+//! Labels of known-entity addresses.
+//!
+//! Transactions record the counterparty of each balance change: the account the tokens
+//! were sent to or received from. Counterparties listed in the `address_labels` table,
+//! seeded with the hot wallets of major exchanges, bridges and protocol vaults and
+//! maintained by admins, are named in transaction responses.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::AppError;
+
+/// Longest accepted label, in characters
+pub const MAX_LABEL_LENGTH: usize = 64;
+
+/// Kind of entity an address belongs to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum LabelCategory {
+    /// A centralized exchange
+    Exchange,
+    /// A cross-chain bridge
+    Bridge,
+    /// A DeFi protocol, e.g. an AMM vault
+    Protocol,
+    /// Any other known entity
+    Other,
+}
+
+impl LabelCategory {
+    /// Name of the category as stored and returned by the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Exchange => "exchange",
+            Self::Bridge => "bridge",
+            Self::Protocol => "protocol",
+            Self::Other => "other",
+        }
+    }
+
+    /// Category with the given stored name, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Exchange, Self::Bridge, Self::Protocol, Self::Other]
+            .into_iter()
+            .find(|category| category.as_str() == name)
+    }
+}
+
+/// A labeled address
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct AddressLabel {
+    /// The labeled address
+    #[schema(example = "5tzFkiKscXHK5ZXCGbXZxdw7gTjjD1mBwuoFbhUvuAi9")]
+    pub address: String,
+    /// Name of the entity
+    #[schema(example = "Binance Hot Wallet")]
+    pub label: String,
+    /// Kind of entity
+    #[schema(value_type = LabelCategory, example = "exchange")]
+    pub category: String,
+    /// When the label was added
+    pub created_at: DateTime<Utc>,
+    /// When the label was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request payload for labeling an address
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SetAddressLabel {
+    /// Name of the entity
+    #[schema(example = "Binance Hot Wallet")]
+    pub label: String,
+    /// Kind of entity
+    pub category: LabelCategory,
+}
+
+/// Lists every labeled address, by label
+pub async fn list(pool: &PgPool) -> Result<Vec<AddressLabel>, sqlx::Error> {
+    sqlx::query_as::<_, AddressLabel>(
+        r#"
+        SELECT address, label, category, created_at, updated_at
+        FROM address_labels
+        ORDER BY label, address
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Labels an address, replacing its label if it has one
+pub async fn set(
+    pool: &PgPool,
+    address: &str,
+    request: &SetAddressLabel,
+) -> Result<AddressLabel, AppError> {
+    let label = request.label.trim();
+    if label.is_empty() {
+        return Err(AppError::UnprocessableEntity(
+            "Label must not be empty".to_string(),
+        ));
+    }
+    if label.chars().count() > MAX_LABEL_LENGTH {
+        return Err(AppError::UnprocessableEntity(format!(
+            "Label must be at most {MAX_LABEL_LENGTH} characters"
+        )));
+    }
+
+    Ok(sqlx::query_as::<_, AddressLabel>(
+        r#"
+        INSERT INTO address_labels (address, label, category)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (address) DO UPDATE
+        SET label = EXCLUDED.label, category = EXCLUDED.category
+        RETURNING address, label, category, created_at, updated_at
+        "#,
+    )
+    .bind(address)
+    .bind(label)
+    .bind(request.category.as_str())
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Removes the label of an address
+pub async fn remove(pool: &PgPool, address: &str) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM address_labels WHERE address = $1")
+        .bind(address)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Address {address} is not labeled"
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Leaderboard of tracked wallets ranked by PnL over a period.
+//!
+//! A wallet's PnL over a period is the change in its snapshotted value, less what flowed
+//! in and plus what flowed out in between, which covers realized and unrealized PnL
+//! alike. It is ranked as a percentage of the value at the start of the period plus what
+//! was bought since. Addresses tracked by several users appear once, and an address is
+//! left out if any of its owners opted out.
+
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::money::Usd;
+use crate::snapshots::HistoryRange;
+
+/// Number of entries returned when no limit is given
+pub const DEFAULT_LEADERBOARD_ENTRIES: i64 = 50;
+
+/// Most entries a leaderboard can be requested with
+pub const MAX_LEADERBOARD_ENTRIES: i64 = 100;
+
+/// One ranked wallet
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct LeaderboardEntry {
+    /// Position on the leaderboard, starting at 1
+    pub rank: i64,
+    /// Address of the wallet
+    #[schema(example = "9WzDXwBbmkg8ZTbNMqUxvQRAyrZzDsGYdLVL9zYtAWWM")]
+    pub address: String,
+    /// ID of the caller's wallet with this address, if the caller tracks it
+    pub wallet_id: Option<Uuid>,
+    /// PnL over the period, in USD
+    #[schema(value_type = f64)]
+    pub pnl_usd: Usd,
+    /// PnL over the period as a percentage of the value at stake
+    pub pnl_percent: f64,
+    /// Value of the wallet at its latest snapshot, in USD
+    #[schema(value_type = f64)]
+    pub value_usd: Usd,
+}
+
+/// Wallets ranked by PnL over a period
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Leaderboard {
+    /// Requested period
+    #[schema(value_type = String, example = "7d")]
+    pub period: HistoryRange,
+    /// Wallets from the highest PnL percentage down
+    pub entries: Vec<LeaderboardEntry>,
+}
+
+/// Ranks the wallets that have a snapshot from before `period` and a later one, up to
+/// `limit` of them
+///
+/// `user_id` is the caller, whose own wallets are linked by ID.
+pub async fn leaderboard(
+    pool: &PgPool,
+    user_id: Uuid,
+    period: HistoryRange,
+    limit: i64,
+) -> Result<Leaderboard, sqlx::Error> {
+    let entries = sqlx::query_as::<_, LeaderboardEntry>(
+        r#"
+        WITH tracked AS (
+            SELECT DISTINCT ON (w.address) w.id, w.address
+            FROM wallets w
+            WHERE w.user_id IS NOT NULL
+              AND NOT EXISTS (
+                  SELECT 1 FROM wallets o
+                  WHERE o.address = w.address AND o.leaderboard_opt_out
+              )
+            ORDER BY w.address, w.created_at, w.id
+        ),
+        period_pnl AS (
+            SELECT t.address,
+                first_snapshot.total_value_usd AS start_value_usd,
+                last_snapshot.total_value_usd AS value_usd,
+                COALESCE(flows.net_usd, 0) AS net_flow_usd,
+                COALESCE(flows.bought_usd, 0) AS bought_usd
+            FROM tracked t
+            JOIN LATERAL (
+                SELECT snapshot_date, total_value_usd, created_at
+                FROM snapshots
+                WHERE wallet_id = t.id
+                  AND snapshot_date <= (NOW() AT TIME ZONE 'UTC')::DATE - $1::INTEGER
+                ORDER BY snapshot_date DESC
+                LIMIT 1
+            ) first_snapshot ON TRUE
+            JOIN LATERAL (
+                SELECT snapshot_date, total_value_usd, created_at
+                FROM snapshots
+                WHERE wallet_id = t.id
+                ORDER BY snapshot_date DESC
+                LIMIT 1
+            ) last_snapshot ON last_snapshot.snapshot_date > first_snapshot.snapshot_date
+            LEFT JOIN LATERAL (
+                SELECT SUM(amount * buy_price_usd) AS net_usd,
+                    SUM(amount * buy_price_usd) FILTER (WHERE amount > 0) AS bought_usd
+                FROM transactions
+                WHERE wallet_id = t.id
+                  AND COALESCE(block_time, created_at) > first_snapshot.created_at
+                  AND COALESCE(block_time, created_at) <= last_snapshot.created_at
+            ) flows ON TRUE
+        ),
+        scored AS (
+            SELECT address, value_usd,
+                value_usd - start_value_usd - net_flow_usd AS pnl_usd,
+                ((value_usd - start_value_usd - net_flow_usd)
+                    / (start_value_usd + bought_usd) * 100)::FLOAT8 AS pnl_percent
+            FROM period_pnl
+            WHERE start_value_usd + bought_usd > 0
+        )
+        SELECT ROW_NUMBER() OVER (ORDER BY s.pnl_percent DESC, s.address) AS rank,
+            s.address,
+            (
+                SELECT o.id FROM wallets o
+                WHERE o.address = s.address AND o.user_id = $2
+            ) AS wallet_id,
+            s.pnl_usd, s.pnl_percent, s.value_usd
+        FROM scored s
+        ORDER BY rank
+        LIMIT $3
+        "#,
+    )
+    .bind(period.num_days() as i32)
+    .bind(user_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Leaderboard { period, entries })
+}
@@ -4,15 +4,36 @@
 //! addresses and view their memecoin portfolios.
 //!
 //! ## Features
-//! - Wallet management (CRUD operations)
+//! - Wallet management (CRUD operations), scoped to the owning user
 //! - Transaction tracking
+//! - Automatic transaction sync from the Solana RPC, on demand and in the background
 //! - Portfolio analytics
 
 #![forbid(unsafe_code)]
 #![warn(missing_docs)]
 
-use sqlx::{PgPool, Pool};
-use std::env;
+use sqlx::postgres::PgPoolOptions;
+use sqlx::PgPool;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::auth::jwt::JwtKeys;
+use crate::cache::{Cache, CacheMetrics, MeteredCache, MokaCache};
+use crate::candles::{BirdeyeCandleSource, CandleSource, SwapCandleSource};
+use crate::db::StartupError;
+use crate::domains::{DomainResolver, SnsResolver, StaticDomainResolver};
+use crate::events::{BroadcastEventBus, EventBus};
+use crate::features::FeatureFlags;
+use crate::fiat::{ExchangeRates, HttpRateSource, RateSource};
+use crate::maintenance::ReadOnlyMode;
+use crate::metering::UsageMeter;
+use crate::prices::{CachedPriceSource, JupiterPriceSource};
+use crate::repository::{
+    InMemoryRepository, PgTransactionRepository, PgWalletRepository, TransactionRepository,
+    WalletRepository,
+};
+use crate::secrets::SecretBox;
+use crate::tokens::{DasMetadataSource, StaticMetadataSource};
 
 // Public modules
 
@@ -25,31 +46,556 @@ pub mod handlers;
 /// Custom error types and error handling utilities
 pub mod error;
 
+/// Application configuration loaded from the environment
+pub mod config;
+
+/// Database connection with retries at startup
+pub mod db;
+
+/// Typed HTTP client of the API for Rust programs
+#[cfg(feature = "client")]
+pub mod client;
+
+/// Solana RPC client and wallet transaction sync
+pub mod sync;
+
+/// Persisted progress and outcome of wallet syncs
+pub mod sync_state;
+
+/// Failover, health tracking and rate limiting across Solana RPC endpoints
+pub mod rpc;
+
+/// Circuit breakers failing calls to unavailable integrations fast
+pub mod resilience;
+
+/// Helius enhanced-transaction webhook receiver
+pub mod helius;
+
+/// Streaming ingestion of tracked wallets' transactions from a Yellowstone gRPC feed
+pub mod geyser;
+
+/// In-process event bus for wallet activity
+pub mod events;
+
+/// Outgoing webhooks: subscriptions, signed deliveries and retries
+pub mod webhooks;
+
+/// Background scheduler for periodic wallet refresh
+pub mod scheduler;
+
+/// WebSocket watcher queuing syncs of wallets as soon as they move
+pub mod watcher;
+
+/// Persistent job queue running syncs, snapshots, alerts, reports, webhook deliveries
+/// and notifications
+pub mod jobs;
+
+/// Transactional outbox relaying events to webhooks and notifications
+pub mod outbox;
+
+/// Dead-letter queue of webhook deliveries and notifications that ran out of attempts
+pub mod dlq;
+
+/// Client IP addresses of requests behind trusted reverse proxies
+pub mod client_ip;
+
+/// Network allowlist of the admin endpoints
+pub mod allowlist;
+
+/// Encryption at rest of channel targets and webhook secrets
+pub mod secrets;
+
+/// Plan-based quotas of tracked wallets and on-demand syncs
+pub mod quotas;
+
+/// Stripe billing webhooks putting subscribers on plans
+pub mod billing;
+
+/// Metering of API requests per user, credential and endpoint
+pub mod metering;
+
+/// Price and wallet value alerts and their evaluator
+pub mod alerts;
+
+/// Telegram and email notifications of alerts, large transactions and reports
+pub mod notifications;
+
+/// Scheduled daily and weekly portfolio reports
+pub mod reports;
+
+/// Detection of whale movements in tracked wallets
+pub mod whales;
+
+/// Leaderboard of tracked wallets ranked by PnL over a period
+pub mod leaderboard;
+
+/// Data retention job pruning old webhook deliveries, dead letters, snapshots and
+/// archived transactions
+pub mod retention;
+
+/// Demo users, wallets and transactions for trying the API
+pub mod seed;
+
+/// Health, liveness and readiness probes
+pub mod health;
+
+/// Read-only maintenance mode rejecting writes
+pub mod maintenance;
+
+/// Feature flags switching risky subsystems on and off at runtime
+pub mod features;
+
+/// HTTPS serving with rustls and the HTTP to HTTPS redirect
+pub mod tls;
+
+/// Request ID middleware for log and error correlation
+pub mod request_id;
+
+/// Log output formats and request logging
+pub mod logging;
+
+/// Conditional GET support: `ETag`, `Last-Modified` and `304 Not Modified`
+pub mod conditional;
+
+/// Idempotency-Key middleware replaying responses to retried requests
+pub mod idempotency;
+
+/// Per-client and global request rate limiting
+pub mod rate_limit;
+
+/// Router construction: routes, CORS and API documentation
+pub mod router;
+
+/// Key-value caching behind a trait, with an in-process moka backend
+pub mod cache;
+
+/// Token price feeds with caching
+pub mod prices;
+
+/// Exact decimal amounts of tokens and US dollars
+pub mod money;
+
+/// OHLCV price candles from Birdeye or aggregated from ingested swaps
+pub mod candles;
+
+/// Fiat exchange rates and display currencies of values
+pub mod fiat;
+
+/// Cursor (keyset) pagination helpers
+pub mod pagination;
+
+/// Token metadata from the Metaplex DAS API, cached in the database
+pub mod tokens;
+
+/// Token risk scores from mint authorities, holder concentration and liquidity pools
+pub mod risk;
+
+/// Per-wallet token positions, maintained incrementally from transactions
+pub mod holdings;
+
+/// Portfolio analytics: cost basis and profit/loss
+pub mod analytics;
+
+/// Portfolio allocation by token and asset class
+pub mod allocation;
+
+/// Live on-chain balances reconciled against the computed holdings
+pub mod balances;
+
+/// Spam token filtering: per-user blocklists, a minimum value and label heuristics
+pub mod spam;
+
+/// Network fees, priority fees and Jito tips paid by wallets
+pub mod fees;
+
+/// CSV exports of transactions, holdings and tax reports, streamed in chunks
+pub mod export;
+
+/// Newline-delimited JSON streaming of large lists
+pub mod ndjson;
+
+/// Content negotiation on the `Accept` header, including MessagePack responses
+pub mod negotiate;
+
+/// Tax reports: realized gains per calendar year
+pub mod tax;
+
+/// Transaction classification: swaps, transfers, airdrops and fees
+pub mod classify;
+
+/// Pump.fun bonding curve instruction decoding and token status
+pub mod pumpfun;
+
+/// Solana Name Service `.sol` domain resolution and reverse lookup
+pub mod domains;
+
+/// Labels of known exchange, bridge and protocol addresses, shown on transactions
+pub mod labels;
+
+/// Daily wallet value snapshots and history time series
+pub mod snapshots;
+
+/// Named wallet groups and their combined portfolio
+pub mod groups;
+
+/// Public read-only share links of wallet portfolios
+pub mod share;
+
+/// Statistics of the instance for its operators
+pub mod stats;
+
+/// Wallet and transaction storage behind traits, with Postgres implementations
+pub mod repository;
+
+/// Export and deletion of users' account data
+pub mod account;
+
+/// Authentication: API keys, Sign-In-With-Solana and JWT sessions
+pub mod auth;
+
 // Re-export commonly used types
+pub use crate::auth::AuthUser;
+pub use crate::config::Config;
 pub use crate::error::{
     conflict_error, not_found_error, validation_error, AppError, ErrorResponse,
 };
-pub use crate::handlers::{add_wallet, get_wallet, list_wallets};
-pub use crate::models::{CreateWallet, Wallet};
+pub use crate::handlers::{
+    add_wallet, block_spam_token, cancel_account_deletion, configure_notification_channel,
+    create_alert, create_group, create_share_link, create_user, create_webhook_subscription,
+    delete_account, delete_address_label, delete_alert, delete_group, delete_notification_channel,
+    delete_webhook_subscription, export_account, export_holdings, export_transactions,
+    get_admin_stats, get_alert, get_allocation, get_balances, get_fees, get_group,
+    get_group_portfolio, get_history, get_holdings, get_leaderboard, get_pnl, get_portfolio,
+    get_portfolio_widget, get_public_portfolio, get_read_only, get_report, get_sync_status,
+    get_tax_report, get_token, get_token_candles, get_token_risk, get_trade_stats, get_wallet,
+    get_wallet_by_address, helius_webhook, list_address_labels, list_alert_events, list_alerts,
+    list_features, list_groups, list_notification_channels, list_reports, list_share_links,
+    list_spam_tokens, list_transactions, list_wallets, list_webhook_deliveries,
+    list_webhook_subscriptions, list_whale_events, revoke_share_link, set_address_label,
+    set_feature, set_read_only, siws_nonce, siws_verify, sync_wallet, unblock_spam_token,
+    update_alert, update_group, update_wallet, wallet_events,
+};
+pub use crate::models::{
+    CreateUser, CreateWallet, CreatedUser, Holding, Portfolio, Transaction, UpdateWallet, User,
+    Wallet, WalletAddress, WalletHoldings,
+};
+pub use crate::prices::PriceSource;
+pub use crate::router::{create_app, create_app_with_state};
+pub use crate::sync::{SolanaRpcClient, SyncReport};
+pub use crate::tokens::{TokenMetadata, TokenMetadataSource};
 
 /// Application state
 #[derive(Clone)]
 pub struct AppState {
     /// Database connection pool
     pub db_pool: PgPool,
+    /// Pool that read-only queries (lists, holdings, analytics) run on; the read
+    /// replica when one is configured, else the same pool as [`Self::db_pool`]
+    pub read_pool: PgPool,
+    /// Solana RPC client used to sync wallet transactions
+    pub rpc: SolanaRpcClient,
+    /// Source of current token prices used to value holdings
+    pub prices: Arc<dyn PriceSource>,
+    /// Source of token metadata (symbols, names, logos)
+    pub metadata: Arc<dyn TokenMetadataSource>,
+    /// Resolver of wallets' `.sol` domains
+    pub domains: Arc<dyn DomainResolver>,
+    /// Source of token price candles
+    pub candles: Arc<dyn CandleSource>,
+    /// Exchange rates that values are converted to display currencies with
+    pub fx: Arc<ExchangeRates>,
+    /// Cache of token metadata and prices in front of the database and price feed
+    pub cache: Arc<dyn Cache>,
+    /// Hits and misses of the cache's lookups
+    pub cache_metrics: Arc<CacheMetrics>,
+    /// Storage of wallets
+    pub wallets: Arc<dyn WalletRepository>,
+    /// Storage of recorded transactions
+    pub transactions: Arc<dyn TransactionRepository>,
+    /// Keys used to issue and verify JWT access tokens
+    pub jwt: JwtKeys,
+    /// Seals and opens stored channel targets and webhook secrets
+    pub secrets: SecretBox,
+    /// Bus that handlers, sync and the schedulers publish domain events into
+    pub events: Arc<dyn EventBus>,
+    /// Whether the API currently rejects writes for maintenance
+    pub read_only: Arc<ReadOnlyMode>,
+    /// Which subsystems are switched on
+    pub features: Arc<FeatureFlags>,
+    /// Requests counted since the usage was last written to the database
+    pub usage: Arc<UsageMeter>,
+    /// Application configuration
+    pub config: Arc<Config>,
+}
+
+impl AppState {
+    /// Creates the application state from a database pool and configuration
+    ///
+    /// # Panics
+    ///
+    /// If the configured secrets master key is malformed or cannot be read; see
+    /// [`Self::try_new`] for a version returning the error.
+    pub fn new(db_pool: PgPool, config: Config) -> Self {
+        match Self::try_new(db_pool, config) {
+            Ok(state) => state,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Creates the application state from a database pool and configuration, failing
+    /// if the configured secrets master key is malformed or cannot be read
+    pub fn try_new(db_pool: PgPool, config: Config) -> Result<Self, StartupError> {
+        let cache_metrics = Arc::new(CacheMetrics::default());
+        let cache: Arc<dyn Cache> = Arc::new(MeteredCache::new(
+            Arc::new(MokaCache::new(config.cache_capacity)),
+            cache_metrics.clone(),
+        ));
+        let prices = CachedPriceSource::new(
+            JupiterPriceSource::new(&config.price_api_url)
+                .with_circuit_breaker(config.circuit_breaker("Price API")),
+            Duration::from_secs(config.price_cache_ttl_secs),
+        )
+        .with_cache(cache.clone());
+
+        let jwt_secret = match &config.jwt_secret {
+            Some(secret) => secret.as_bytes().to_vec(),
+            None => {
+                tracing::warn!("JWT_SECRET is not set; using a random secret for this process");
+                let mut secret = vec![0u8; 32];
+                rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut secret);
+                secret
+            }
+        };
+
+        let secrets = SecretBox::from_config(&config)?;
+        if !secrets.is_enabled() {
+            tracing::warn!(
+                "SECRETS_MASTER_KEY is not set; channel targets and webhook secrets are stored unencrypted"
+            );
+        }
+
+        let metadata: Arc<dyn TokenMetadataSource> = match &config.das_api_url {
+            Some(url) => Arc::new(DasMetadataSource::new(url)),
+            None => Arc::new(StaticMetadataSource::default()),
+        };
+        let domains: Arc<dyn DomainResolver> = match &config.sns_api_url {
+            Some(url) => Arc::new(SnsResolver::new(url)),
+            None => Arc::new(StaticDomainResolver::default()),
+        };
+        let candles: Arc<dyn CandleSource> = match &config.birdeye_api_key {
+            Some(key) => Arc::new(
+                BirdeyeCandleSource::new(&config.birdeye_api_url, key)
+                    .with_circuit_breaker(config.circuit_breaker("Birdeye API")),
+            ),
+            None => Arc::new(SwapCandleSource::new(db_pool.clone())),
+        };
+        let fx = ExchangeRates::new(
+            Arc::new(HttpRateSource::new(&config.fx_api_url)),
+            Duration::from_secs(config.fx_refresh_secs),
+        );
+
+        Ok(Self {
+            rpc: SolanaRpcClient::with_endpoints(
+                config.solana_rpc_urls(),
+                config.solana_rpc_requests_per_second,
+            )
+            .with_circuit_breaker(config.circuit_breaker("Solana RPC")),
+            prices: Arc::new(prices),
+            metadata,
+            domains,
+            candles,
+            fx: Arc::new(fx),
+            cache,
+            cache_metrics,
+            wallets: Arc::new(PgWalletRepository::new(db_pool.clone())),
+            transactions: Arc::new(PgTransactionRepository::new(db_pool.clone())),
+            jwt: JwtKeys::new(&jwt_secret, config.jwt_ttl_secs),
+            secrets,
+            events: Arc::new(BroadcastEventBus::default()),
+            read_only: Arc::new(ReadOnlyMode::new(config.read_only)),
+            features: Arc::new(FeatureFlags::new(config.disabled_features.clone())),
+            usage: Arc::new(UsageMeter::new()),
+            read_pool: db_pool.clone(),
+            db_pool,
+            config: Arc::new(config),
+        })
+    }
+
+    /// Creates a state whose wallets and transactions are kept in memory
+    ///
+    /// The database pool connects lazily and is never used by the wallet and transaction
+    /// endpoints, so this runs without Postgres; endpoints that still query the
+    /// database directly fail with a server error when it is unavailable.
+    ///
+    /// # Panics
+    ///
+    /// If the configured secrets master key is malformed or cannot be read; see
+    /// [`Self::try_in_memory`] for a version returning the error.
+    pub fn in_memory(config: Config) -> Self {
+        match Self::try_in_memory(config) {
+            Ok(state) => state,
+            Err(err) => panic!("{err}"),
+        }
+    }
+
+    /// Creates a state whose wallets and transactions are kept in memory, failing if
+    /// the configured secrets master key is malformed or cannot be read
+    pub fn try_in_memory(config: Config) -> Result<Self, StartupError> {
+        let pool = sqlx::postgres::PgPoolOptions::new()
+            .acquire_timeout(Duration::from_secs(1))
+            .connect_lazy_with(sqlx::postgres::PgConnectOptions::new());
+        let repository = Arc::new(InMemoryRepository::new());
+
+        Ok(Self::try_new(pool, config)?
+            .with_wallet_repository(repository.clone())
+            .with_transaction_repository(repository))
+    }
+
+    /// Creates demo-mode state keeping wallets and transactions in a SQLite database,
+    /// so they survive restarts, failing like [`AppState::try_in_memory`]; everything
+    /// else behaves as in [`AppState::in_memory`]
+    #[cfg(feature = "sqlite")]
+    pub fn try_sqlite(pool: sqlx::SqlitePool, config: Config) -> Result<Self, StartupError> {
+        let repository = Arc::new(crate::repository::SqliteRepository::new(pool));
+
+        Ok(Self::try_in_memory(config)?
+            .with_wallet_repository(repository.clone())
+            .with_transaction_repository(repository))
+    }
+
+    /// Sends read-only queries to a read replica, leaving writes on the primary
+    ///
+    /// The Postgres wallet and transaction repositories are recreated to list from the
+    /// replica, so call this before replacing them with [`Self::with_wallet_repository`]
+    /// or [`Self::with_transaction_repository`]. Reads that guard a write, such as
+    /// looking up the wallet a transaction is added to, stay on the primary so they
+    /// never miss a row the replica has not caught up with.
+    pub fn with_read_pool(mut self, read_pool: PgPool) -> Self {
+        self.wallets = Arc::new(
+            PgWalletRepository::new(self.db_pool.clone()).with_read_pool(read_pool.clone()),
+        );
+        self.transactions = Arc::new(
+            PgTransactionRepository::new(self.db_pool.clone()).with_read_pool(read_pool.clone()),
+        );
+        self.read_pool = read_pool;
+        self
+    }
+
+    /// Replaces the price source, e.g. with a mock in tests
+    pub fn with_price_source(mut self, prices: Arc<dyn PriceSource>) -> Self {
+        self.prices = prices;
+        self
+    }
+
+    /// Replaces the token metadata source, e.g. with a mock in tests
+    pub fn with_metadata_source(mut self, metadata: Arc<dyn TokenMetadataSource>) -> Self {
+        self.metadata = metadata;
+        self
+    }
+
+    /// Replaces the domain resolver, e.g. with a mock in tests
+    pub fn with_domain_resolver(mut self, domains: Arc<dyn DomainResolver>) -> Self {
+        self.domains = domains;
+        self
+    }
+
+    /// Replaces the candle source, e.g. with a mock in tests
+    pub fn with_candle_source(mut self, candles: Arc<dyn CandleSource>) -> Self {
+        self.candles = candles;
+        self
+    }
+
+    /// Replaces the source of exchange rates, e.g. with a mock in tests
+    pub fn with_rate_source(mut self, source: Arc<dyn RateSource>) -> Self {
+        self.fx = Arc::new(ExchangeRates::new(
+            source,
+            Duration::from_secs(self.config.fx_refresh_secs),
+        ));
+        self
+    }
+
+    /// Replaces the cache of token metadata, e.g. with one shared between instances
+    ///
+    /// The default price source keeps using the cache it was created with; pass a
+    /// [`CachedPriceSource`] using the new cache to [`Self::with_price_source`] to move
+    /// prices as well. Lookups of the new cache are counted in [`Self::cache_metrics`].
+    pub fn with_cache(mut self, cache: Arc<dyn Cache>) -> Self {
+        self.cache = Arc::new(MeteredCache::new(cache, self.cache_metrics.clone()));
+        self
+    }
+
+    /// Replaces the event bus, e.g. with one shared between instances
+    pub fn with_event_bus(mut self, events: Arc<dyn EventBus>) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Replaces the wallet storage, e.g. with a fake in tests
+    pub fn with_wallet_repository(mut self, wallets: Arc<dyn WalletRepository>) -> Self {
+        self.wallets = wallets;
+        self
+    }
+
+    /// Replaces the transaction storage, e.g. with a fake in tests
+    pub fn with_transaction_repository(
+        mut self,
+        transactions: Arc<dyn TransactionRepository>,
+    ) -> Self {
+        self.transactions = transactions;
+        self
+    }
+}
+
+/// Connects to the database at `DATABASE_URL`, retrying while it is not reachable yet
+///
+/// Makes up to `DATABASE_CONNECT_ATTEMPTS` attempts, see [`db::connect`].
+pub async fn try_establish_connection() -> Result<PgPool, StartupError> {
+    let attempts = Config::from_env().database_connect_attempts;
+    db::connect(PgPoolOptions::new(), &db::database_url()?, attempts).await
 }
 
 /// Establishes a connection to the database using the DATABASE_URL environment variable.
-/// # Panics if DATABASE_URL is not set or if the connection fails.
+/// # Panics if DATABASE_URL is not set or if the connection fails after all retries;
+/// see [`try_establish_connection`] for a version returning the error.
 pub async fn establish_connection() -> PgPool {
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    Pool::connect(&database_url)
-        .await
-        .expect("Failed to connect to database")
+    match try_establish_connection().await {
+        Ok(pool) => pool,
+        Err(err) => panic!("{err}"),
+    }
+}
+
+/// Creates a new application state connected to the database at `DATABASE_URL`,
+/// with the configuration read from the environment
+///
+/// Fails if the database cannot be reached after the configured attempts or the secrets
+/// master key is malformed, leaving embedders to decide how to handle it.
+pub async fn try_create_app_state() -> Result<AppState, StartupError> {
+    let config = Config::from_env();
+    let db_pool = db::connect(
+        PgPoolOptions::new(),
+        &db::database_url()?,
+        config.database_connect_attempts,
+    )
+    .await?;
+    let read_pool = match &config.database_read_url {
+        Some(url) => {
+            Some(db::connect(PgPoolOptions::new(), url, config.database_connect_attempts).await?)
+        }
+        None => None,
+    };
+    let state = AppState::try_new(db_pool, config)?;
+    Ok(match read_pool {
+        Some(pool) => state.with_read_pool(pool),
+        None => state,
+    })
 }
 
 /// Creates a new application state with a database connection pool
+/// # Panics if the database cannot be reached or the secrets master key is malformed;
+/// see [`try_create_app_state`].
 pub async fn create_app_state() -> AppState {
-    let db_pool = establish_connection().await;
-    AppState { db_pool }
+    match try_create_app_state().await {
+        Ok(state) => state,
+        Err(err) => panic!("{err}"),
+    }
 }
@@ -0,0 +1,95 @@
+//! Log output and request logging.
+//!
+//! Logs are written to stdout as human-readable lines, or with `LOG_FORMAT=json` as one
+//! JSON object per line for ingestion into Loki, Datadog and the like. Every request
+//! is logged once it is answered, with its route, status and latency; the fields of the
+//! request's span, such as its ID, are included.
+
+use std::str::FromStr;
+use std::time::Instant;
+
+use axum::{extract::MatchedPath, http::Request, middleware::Next, response::Response};
+use tracing::{debug, info};
+use tracing_subscriber::EnvFilter;
+
+use crate::client_ip::ClientIp;
+
+/// Paths polled by infrastructure, logged at debug level only
+const PROBE_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// How log lines are formatted
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines
+    #[default]
+    Pretty,
+    /// One JSON object per line, with the event's fields at the top level
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "pretty" | "text" => Ok(Self::Pretty),
+            "json" => Ok(Self::Json),
+            other => Err(format!("Unknown log format {other:?}")),
+        }
+    }
+}
+
+/// Installs the global logger, writing events `filter` lets through in `format`
+///
+/// `filter` takes `RUST_LOG` directives such as `info,degen::sync=debug`; an invalid
+/// one falls back to `info`.
+pub fn init(format: LogFormat, filter: &str) {
+    let filter = EnvFilter::try_new(filter).unwrap_or_else(|err| {
+        eprintln!("Invalid log filter {filter:?}, logging at info level: {err}");
+        EnvFilter::new("info")
+    });
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match format {
+        LogFormat::Pretty => builder.init(),
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(false)
+            .init(),
+    }
+}
+
+/// Middleware logging each request once it is answered
+///
+/// Logs the matched route, e.g. `/api/v1/wallets/:id`, rather than the path, so that
+/// requests to the same endpoint can be grouped, and the [`ClientIp`] if known.
+pub async fn request_log_middleware<B>(request: Request<B>, next: Next<B>) -> Response {
+    let started = Instant::now();
+    let method = request.method().clone();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let is_probe = PROBE_PATHS.contains(&request.uri().path());
+    let client_ip = request
+        .extensions()
+        .get::<ClientIp>()
+        .map(|ClientIp(ip)| ip.to_string());
+
+    let response = next.run(request).await;
+
+    let status = response.status().as_u16();
+    // Rounded to microseconds
+    let latency_ms = (started.elapsed().as_secs_f64() * 1e6).round() / 1e3;
+    let route = route.as_deref().unwrap_or("unmatched");
+    let client_ip = client_ip.as_deref().unwrap_or("unknown");
+    if is_probe {
+        debug!(%method, route, status, latency_ms, client_ip, "Request answered");
+    } else {
+        info!(%method, route, status, latency_ms, client_ip, "Request answered");
+    }
+
+    response
+}
@@ -1,100 +1,427 @@
-use axum::{
-    http::Method,
-    response::{Html, IntoResponse, Json},
-    routing::{get, post},
-    Router, Server,
-};
+use axum::{Router, Server};
+use clap::{Parser, Subcommand};
 use dotenv::dotenv;
-use std::{env, net::SocketAddr};
-use tower_http::cors::{Any, CorsLayer};
-use utoipa::OpenApi;
-use utoipa_swagger_ui::SwaggerUi;
+use sqlx::PgPool;
+use std::{env, io, net::SocketAddr, os::unix::fs::FileTypeExt, sync::Arc};
+use tokio::net::UnixListener;
+use tokio_stream::wrappers::UnixListenerStream;
+
+use uuid::Uuid;
 
 use degen::{
-    handlers::{add_wallet, get_wallet, list_wallets},
-    models::{CreateWallet, Wallet},
-    AppState,
+    alerts, db, features, fiat, geyser, holdings, jobs, logging, metering,
+    models::Wallet,
+    reports::{self, ReportPeriod},
+    retention,
+    router::create_app_with_state,
+    scheduler, secrets, seed, snapshots, sync, tls, watcher, AppState, Config,
 };
 
-/// API documentation
-#[derive(OpenApi)]
-#[openapi(
-    paths(
-        degen::handlers::add_wallet,
-        degen::handlers::get_wallet,
-        degen::handlers::list_wallets,
-    ),
-    components(schemas(Wallet, CreateWallet)),
-    tags(
-        (name = "wallets", description = "Wallet management endpoints")
-    )
-)]
-struct ApiDoc;
-
-/// Serve the OpenAPI documentation as HTML
-async fn serve_docs() -> impl IntoResponse {
-    Html(
-        r#"
-        <!DOCTYPE html>
-        <html>
-            <head>
-                <title>Degen API Documentation</title>
-                <meta charset="utf-8"/>
-                <meta name="viewport" content="width=device-width, initial-scale=1">
-                <style>
-                    body { margin: 0; padding: 20px; font-family: Arial, sans-serif; }
-                    h1 { color: #333; }
-                    .endpoint { margin-bottom: 20px; padding: 15px; background: #f5f5f5; border-radius: 5px; }
-                    .method { font-weight: bold; color: #fff; padding: 3px 8px; border-radius: 3px; display: inline-block; margin-right: 10px; }
-                    .get { background: #61affe; }
-                    .post { background: #49cc90; }
-                    .path { font-family: monospace; font-size: 16px; }
-                    .description { margin: 10px 0; }
-                </style>
-            </head>
-            <body>
-                <h1>Degen API Documentation</h1>
-                
-                <div class="endpoint">
-                    <div><span class="method get">GET</span> <span class="path">/wallets</span></div>
-                    <div class="description">List all wallets</div>
-                </div>
-
-                <div class="endpoint">
-                    <div><span class="method get">GET</span> <span class="path">/wallets/:id</span></div>
-                    <div class="description">Get wallet by ID</div>
-                </div>
-
-                <div class="endpoint">
-                    <div><span class="method post">POST</span> <span class="path">/wallets</span></div>
-                    <div class="description">Create a new wallet</div>
-                    <div>Example request body: {"address": "0x...", "name": "My Wallet"}</div>
-                </div>
-
-                <div style="margin-top: 30px;">
-                    <h3>Interactive Documentation</h3>
-                    <p>For an interactive API documentation, visit the <a href="/swagger-ui">Swagger UI</a>.</p>
-                    <p>Or download the <a href="/openapi.json">OpenAPI specification</a>.</p>
-                </div>
-            </body>
-        </html>
-        "#,
-    )
+/// Solana memecoin portfolio tracker API
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    /// What to do; serves the API if omitted
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-/// Serve the OpenAPI JSON specification
-async fn serve_openapi() -> impl IntoResponse {
-    Json(ApiDoc::openapi())
+/// Commands of the binary; all but `serve` run against the database and exit
+#[derive(Subcommand)]
+enum Command {
+    /// Serve the API (the default)
+    Serve,
+    /// Apply pending database migrations
+    Migrate,
+    /// Sync a tracked wallet's recent transactions from the Solana RPC
+    Sync {
+        /// Base58 address of the wallet; every user's wallet with it is synced
+        #[arg(long)]
+        wallet: String,
+    },
+    /// Create a demo user with wallets and transaction histories
+    Seed {
+        /// Seed the demo data set
+        #[arg(long, required = true)]
+        demo: bool,
+        /// Number of demo wallets to create
+        #[arg(long, default_value_t = seed::DEFAULT_DEMO_WALLETS)]
+        wallets: usize,
+        /// Seed to generate the data from; the same seed gives the same data
+        #[arg(long, default_value_t = seed::DEFAULT_SEED)]
+        seed: u64,
+    },
+    /// Rebuild every wallet's holdings from its transactions
+    RebuildHoldings,
+    /// Fetch a wallet's complete transaction history
+    BackfillWallet {
+        /// ID of the wallet
+        wallet_id: Uuid,
+    },
+    /// List jobs that failed permanently
+    FailedJobs,
+    /// Queue a failed job again
+    RetryJob {
+        /// ID of the job
+        job_id: Uuid,
+    },
+    /// Prune data past its retention period
+    PruneData {
+        /// Only count the rows that would be pruned
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
-    tracing_subscriber::fmt::init();
+    let cli = Cli::parse();
+    let config = Config::from_env();
+    logging::init(config.log_format, &config.log_filter);
+
+    match cli.command.unwrap_or(Command::Serve) {
+        Command::Serve => run_server(config).await,
+        command => run_command(command, config).await,
+    }
+}
+
+/// Serves the API until the server fails
+async fn run_server(config: Config) {
+    let state = if config.is_demo() {
+        demo_state(config).await
+    } else {
+        let read_pool = connect_read_replica(&config).await;
+        let state = new_state(connect_database().await, config);
+        match read_pool {
+            Some(pool) => state.with_read_pool(pool),
+            None => state,
+        }
+    };
+
+    // The background jobs work on the database, which demo mode does without
+    if !state.config.is_demo() {
+        spawn_background_jobs(&state);
+    }
+
+    // Build our application with routes
+    let config = state.config.clone();
+    let app = create_app_with_state(state);
+
+    if let Err(err) = serve(app, &config).await {
+        tracing::error!("Server failed: {}", err);
+        std::process::exit(1);
+    }
+}
+
+/// State of demo mode: wallets and transactions are kept in the SQLite database of
+/// `DATABASE_URL` if it names one and SQLite support is built in, else in memory
+async fn demo_state(config: Config) -> AppState {
+    #[cfg(feature = "sqlite")]
+    if let Some(url) = env::var("DATABASE_URL")
+        .ok()
+        .filter(|url| url.starts_with("sqlite:"))
+    {
+        tracing::warn!(
+            "Running in demo mode: wallets are kept in {} and every request acts as the demo user",
+            url
+        );
+        return match db::connect_sqlite(&url).await {
+            Ok(pool) => state_or_exit(AppState::try_sqlite(pool, config)),
+            Err(err) => {
+                tracing::error!("{}", err);
+                std::process::exit(1);
+            }
+        };
+    }
+
+    tracing::warn!(
+        "Running in demo mode: wallets are kept in memory and every request acts as the demo user"
+    );
+    state_or_exit(AppState::try_in_memory(config))
+}
 
+/// Serves the app on the configured Unix socket, or else on `HOST:PORT`, over HTTPS if a
+/// certificate is configured
+async fn serve(app: Router, config: &Config) -> io::Result<()> {
+    if let Some(path) = &config.unix_socket {
+        // A socket left behind by an earlier run would fail the bind; other files are kept
+        if std::fs::symlink_metadata(path).is_ok_and(|meta| meta.file_type().is_socket()) {
+            std::fs::remove_file(path)?;
+        }
+        let listener = UnixListener::bind(path)?;
+        println!("Server running on Unix socket {}", path.display());
+
+        // Clients behind the socket have no IP address, so unauthenticated requests
+        // share one rate limit bucket
+        return Server::builder(hyper::server::accept::from_stream(UnixListenerStream::new(
+            listener,
+        )))
+        .serve(app.into_make_service())
+        .await
+        .map_err(io::Error::other);
+    }
+
+    let addr = config.bind_addr();
+    match (&config.tls_cert_path, &config.tls_key_path) {
+        (Some(cert_path), Some(key_path)) => {
+            let tls = tls::load_config(cert_path, key_path).await?;
+            if let Some(port) = config.http_redirect_port {
+                let redirect = SocketAddr::new(config.host, port);
+                tokio::spawn(async move {
+                    if let Err(err) = tls::serve_redirect(redirect, addr.port()).await {
+                        tracing::error!("HTTP to HTTPS redirect failed: {}", err);
+                    }
+                });
+            }
+            println!("Server running at https://{addr}/docs");
+            println!("Swagger UI available at https://{addr}/swagger-ui");
+
+            return axum_server::bind_rustls(addr, tls)
+                .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+                .await;
+        }
+        (None, None) => {}
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "TLS_CERT_PATH and TLS_KEY_PATH must be set together",
+            ))
+        }
+    }
+
+    let server = Server::try_bind(&addr).map_err(io::Error::other)?;
+    println!("Server running at http://{addr}/docs");
+    println!("Swagger UI available at http://{addr}/swagger-ui");
+
+    server
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await
+        .map_err(io::Error::other)
+}
+
+/// Runs a maintenance command given on the command line
+async fn run_command(command: Command, config: Config) {
+    match command {
+        Command::Serve => unreachable!("serving is not a maintenance command"),
+        Command::Migrate => {
+            // Connecting applies pending migrations
+            connect_database().await;
+            println!("Database is up to date");
+        }
+        Command::Sync { wallet } => {
+            let state = new_state(connect_database().await, config);
+            let wallets = sqlx::query_as::<_, Wallet>(
+                r#"
+                SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+                FROM wallets
+                WHERE address = $1
+                ORDER BY created_at
+                "#,
+            )
+            .bind(&wallet)
+            .fetch_all(&state.db_pool)
+            .await
+            .expect("Failed to look up wallets");
+            if wallets.is_empty() {
+                eprintln!("No wallet with address {wallet} is tracked");
+                std::process::exit(1);
+            }
+
+            for wallet in &wallets {
+                let report = sync::sync_wallet(
+                    &state.db_pool,
+                    &state.rpc,
+                    state.events.as_ref(),
+                    wallet,
+                    state.config.sync_signature_limit,
+                    state.config.whale_threshold_usd,
+                )
+                .await
+                .expect("Failed to sync wallet");
+                println!(
+                    "Synced wallet {}: {} signatures, {} balance changes, {} new",
+                    wallet.id,
+                    report.signatures_fetched,
+                    report.transactions_upserted,
+                    report.transactions_inserted
+                );
+            }
+        }
+        Command::Seed {
+            demo: _,
+            wallets,
+            seed,
+        } => {
+            let state = new_state(connect_database().await, config);
+            let report = seed::seed_demo(&state, &seed::SeedOptions { wallets, seed })
+                .await
+                .expect("Failed to seed demo data");
+            for wallet in &report.wallets {
+                println!("{}\t{}", wallet.id, wallet.address);
+            }
+            println!(
+                "Seeded {} wallets with {} transactions and {} price candles for user {}",
+                report.wallets.len(),
+                report.transactions,
+                report.candles,
+                report.user_id
+            );
+            println!("API key: {}", report.api_key);
+        }
+        Command::RebuildHoldings => {
+            let pool = connect_database().await;
+            let written = holdings::rebuild(&pool)
+                .await
+                .expect("Failed to rebuild holdings");
+            println!("Rebuilt {written} holdings from transactions");
+        }
+        Command::BackfillWallet { wallet_id } => {
+            let state = new_state(connect_database().await, config);
+            let wallet = sqlx::query_as::<_, Wallet>(
+                r#"
+                SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+                FROM wallets
+                WHERE id = $1
+                "#,
+            )
+            .bind(wallet_id)
+            .fetch_optional(&state.db_pool)
+            .await
+            .expect("Failed to look up wallet");
+            let Some(wallet) = wallet else {
+                eprintln!("Wallet {wallet_id} not found");
+                std::process::exit(1);
+            };
+
+            let report = sync::backfill_wallet(
+                &state.db_pool,
+                &state.rpc,
+                state.transactions.as_ref(),
+                &wallet,
+                sync::BACKFILL_BATCH_ROWS,
+            )
+            .await
+            .expect("Failed to backfill wallet");
+            println!(
+                "Backfilled {} signatures: {} balance changes, {} new",
+                report.signatures_fetched,
+                report.transactions_upserted,
+                report.transactions_inserted
+            );
+        }
+        Command::FailedJobs => {
+            let pool = connect_database().await;
+            let failed = jobs::list_failed(&pool, 100)
+                .await
+                .expect("Failed to list failed jobs");
+            for job in &failed {
+                println!(
+                    "{}\t{}\t{} attempts\t{}\t{}",
+                    job.id,
+                    job.kind,
+                    job.attempts,
+                    job.payload,
+                    job.last_error.as_deref().unwrap_or("")
+                );
+            }
+            println!("{} failed jobs", failed.len());
+        }
+        Command::RetryJob { job_id } => {
+            let pool = connect_database().await;
+            if jobs::retry(&pool, job_id)
+                .await
+                .expect("Failed to retry job")
+            {
+                println!("Queued job {job_id} again");
+            } else {
+                eprintln!("Job {job_id} is not failed, or the same work is already queued");
+                std::process::exit(1);
+            }
+        }
+        Command::PruneData { dry_run } => {
+            let pool = connect_database().await;
+            let mut policy = config.retention_policy();
+            policy.dry_run |= dry_run;
+            let report = retention::prune(&pool, &policy)
+                .await
+                .expect("Failed to prune data");
+            println!(
+                "{} {} webhook deliveries, {} dead letters, {} snapshots and {} archived transactions",
+                if report.dry_run {
+                    "Would prune"
+                } else {
+                    "Pruned"
+                },
+                report.webhook_deliveries,
+                report.dead_letters,
+                report.snapshots,
+                report.transactions
+            );
+        }
+    }
+}
+
+/// Connects to the database configured in the environment and runs pending migrations,
+/// exiting if that fails
+async fn connect_database() -> PgPool {
+    match try_connect_database().await {
+        Ok(pool) => pool,
+        Err(err) => {
+            tracing::error!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Creates the application state, exiting if the secrets master key is malformed or
+/// cannot be read
+fn new_state(db_pool: PgPool, config: Config) -> AppState {
+    state_or_exit(AppState::try_new(db_pool, config))
+}
+
+/// The state if it could be created, or else exits
+fn state_or_exit(state: Result<AppState, db::StartupError>) -> AppState {
+    match state {
+        Ok(state) => state,
+        Err(err) => {
+            tracing::error!("{}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Connects to the read replica at `DATABASE_READ_URL` if one is configured, exiting if
+/// that fails
+async fn connect_read_replica(config: &Config) -> Option<PgPool> {
+    let url = config.database_read_url.as_deref()?;
+    match db::connect(pool_options(), url, config.database_connect_attempts).await {
+        Ok(pool) => {
+            tracing::info!("Reading lists, holdings and analytics from the read replica");
+            Some(pool)
+        }
+        Err(err) => {
+            tracing::error!("Read replica: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Connects to the database, retrying while it is not reachable yet, and migrates it
+async fn try_connect_database() -> Result<PgPool, db::StartupError> {
     // Database configuration with sensible defaults and environment overrides
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
+    let database_url = db::database_url()?;
+    let attempts = Config::from_env().database_connect_attempts;
+    let pool = db::connect(pool_options(), &database_url, attempts).await?;
 
+    // Run migrations
+    db::migrate(&pool).await?;
+
+    Ok(pool)
+}
+
+/// Pool options shared by the primary and the read replica, from the environment
+fn pool_options() -> sqlx::postgres::PgPoolOptions {
     // Configure connection pool with environment variable overrides
     let max_connections = env::var("DATABASE_MAX_CONNECTIONS")
         .ok()
@@ -124,53 +451,124 @@ async fn main() {
         .and_then(|s| s.parse::<u64>().ok())
         .unwrap_or(600); // 10 minutes default idle timeout
 
-    let pool = sqlx::postgres::PgPoolOptions::new()
+    let options = sqlx::postgres::PgPoolOptions::new()
         .max_connections(max_connections)
         .min_connections(min_connections)
         .acquire_timeout(std::time::Duration::from_secs(acquire_timeout_seconds))
         .idle_timeout(std::time::Duration::from_secs(idle_timeout_seconds))
-        .test_before_acquire(true) // Test connections before giving them out
-        .connect(&database_url)
-        .await
-        .expect("Failed to connect to database");
+        .test_before_acquire(true); // Test connections before giving them out
 
     tracing::info!(
-        "Database connection pool initialized with {} min and {} max connections",
+        "Database connection pool configured with {} min and {} max connections",
         min_connections,
         max_connections
     );
 
-    // Run migrations
-    sqlx::migrate!()
-        .run(&pool)
-        .await
-        .expect("Failed to run migrations");
+    options
+}
 
-    // Enable CORS
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(vec![Method::GET, Method::POST, Method::PUT, Method::DELETE])
-        .allow_headers(Any);
+/// Starts the periodic sync or Geyser stream, wallet watcher, snapshot, retention, alert and report schedulers and the job worker
+fn spawn_background_jobs(state: &AppState) {
+    // With several instances, only the one holding the lock runs the schedulers
+    let leader = Arc::new(scheduler::LeaderLock::new(
+        state.db_pool.clone(),
+        scheduler::SCHEDULER_LOCK_KEY,
+    ));
 
-    // Create Swagger UI
-    let swagger_ui = SwaggerUi::new("/swagger-ui").url("/api-doc/openapi.json", ApiDoc::openapi());
+    // Encrypt credentials stored before a master key was configured
+    let (pool, secrets) = (state.db_pool.clone(), state.secrets.clone());
+    tokio::spawn(async move {
+        match secrets::seal_stored(&pool, &secrets).await {
+            Ok(0) => {}
+            Ok(sealed) => tracing::info!("Encrypted {} stored credentials", sealed),
+            Err(err) => tracing::warn!("Encrypting stored credentials failed: {}", err),
+        }
+    });
 
-    // Build our application with routes
-    let app = Router::new()
-        .merge(swagger_ui)
-        .route("/docs", get(serve_docs))
-        .route("/openapi.json", get(serve_openapi))
-        .route("/wallets", post(add_wallet).get(list_wallets))
-        .route("/wallets/:id", get(get_wallet))
-        .with_state(AppState { db_pool: pool })
-        .layer(cors);
-
-    let addr = SocketAddr::from(([127, 0, 0, 1], 3000));
-    println!("Server running at https://{addr}/docs");
-    println!("Swagger UI available at https://{addr}/swagger-ui");
-
-    Server::bind(&addr)
-        .serve(app.into_make_service())
-        .await
-        .unwrap();
+    // Write the API usage counted by this instance
+    metering::spawn_flusher(
+        state.usage.clone(),
+        state.db_pool.clone(),
+        metering::FLUSH_INTERVAL,
+    );
+
+    // Pick up feature flags switched by operators on any instance
+    features::spawn_refresher(
+        state.features.clone(),
+        state.db_pool.clone(),
+        state.config.feature_refresh_interval(),
+    );
+
+    // Stream tracked wallets' transactions from Geyser if configured, else periodically
+    // re-sync them in the background
+    if let Some(url) = &state.config.geyser_grpc_url {
+        let source = geyser::YellowstoneSource::new(url, state.config.geyser_x_token.clone());
+        geyser::spawn_geyser_worker(state.clone(), Arc::new(source), leader.clone());
+        tracing::info!("Streaming from Geyser; background wallet sync disabled");
+    } else {
+        match state.config.sync_interval() {
+            Some(interval) => {
+                scheduler::spawn_sync_scheduler(state.clone(), interval, leader.clone());
+            }
+            None => tracing::info!("Background wallet sync disabled"),
+        }
+    }
+
+    // Sync wallets as soon as the node reports activity on them
+    if let Some(url) = &state.config.solana_ws_url {
+        watcher::spawn_wallet_watcher(state.clone(), url.clone(), leader.clone());
+    }
+
+    // Keep the exchange rates of display currencies current
+    if let Some(interval) = state.config.fx_refresh_interval() {
+        fiat::spawn_rate_refresher(state.fx.clone(), interval);
+    }
+
+    // Run queued syncs, snapshots and webhook deliveries
+    match state.config.job_worker_interval() {
+        Some(interval) => {
+            jobs::spawn_worker(state.clone(), interval);
+        }
+        None => tracing::info!("Job worker disabled"),
+    }
+
+    // Record each wallet's value once per day for history charts
+    match state.config.snapshot_interval() {
+        Some(interval) => {
+            snapshots::spawn_snapshot_job(state.clone(), interval, leader.clone());
+        }
+        None => tracing::info!("Wallet snapshot job disabled"),
+    }
+
+    // Prune data past its retention period
+    match state.config.retention_interval() {
+        Some(interval) => {
+            retention::spawn_retention_job(
+                state.db_pool.clone(),
+                state.config.retention_policy(),
+                interval,
+                leader.clone(),
+            );
+        }
+        None => tracing::info!("Data retention job disabled"),
+    }
+
+    // Check users' price and wallet value alerts
+    match state.config.alert_interval() {
+        Some(interval) => {
+            alerts::spawn_alert_evaluator(state.clone(), interval, leader.clone());
+        }
+        None => tracing::info!("Alert evaluator disabled"),
+    }
+
+    // Generate daily and weekly portfolio reports and send them to opted-in channels
+    for period in [ReportPeriod::Daily, ReportPeriod::Weekly] {
+        match state.config.report_schedule(period) {
+            Ok(Some(schedule)) => {
+                reports::spawn_report_scheduler(state.clone(), period, schedule, leader.clone());
+            }
+            Ok(None) => tracing::info!("{} reports disabled", period.as_str()),
+            Err(err) => tracing::error!("Invalid {} report schedule: {}", period.as_str(), err),
+        }
+    }
 }
@@ -0,0 +1,89 @@
+//! Read-only maintenance mode.
+//!
+//! While read-only mode is on, every request that could change data answers `503`
+//! with a `Retry-After` header, and reads keep working, so a hosted deployment can be
+//! migrated or restored without losing writes made halfway through. The admin
+//! endpoints stay writable so the mode can be switched off again, and the job worker
+//! pauses until it is. The mode starts as configured by `READ_ONLY` and is switched at
+//! runtime with `PUT /admin/read-only`, per instance.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use axum::{
+    extract::State,
+    http::{header, HeaderValue, Method, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::router::ApiVersion;
+use crate::AppError;
+
+/// Seconds clients are asked to wait before retrying a rejected write
+const RETRY_AFTER_SECS: &str = "60";
+
+/// Whether the API currently rejects writes
+#[derive(Debug, Default)]
+pub struct ReadOnlyMode(AtomicBool);
+
+impl ReadOnlyMode {
+    /// Creates the mode, on if `enabled`
+    pub fn new(enabled: bool) -> Self {
+        Self(AtomicBool::new(enabled))
+    }
+
+    /// Whether writes are rejected
+    pub fn is_enabled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Switches read-only mode on or off
+    pub fn set(&self, enabled: bool) {
+        self.0.store(enabled, Ordering::Relaxed);
+    }
+}
+
+/// Read-only mode as shown and set by the admin endpoints
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct ReadOnlyStatus {
+    /// Whether requests that change data are rejected
+    pub enabled: bool,
+}
+
+/// Whether a request may change data: anything but `GET`, `HEAD` and `OPTIONS`
+fn is_write(method: &Method) -> bool {
+    !matches!(*method, Method::GET | Method::HEAD | Method::OPTIONS)
+}
+
+/// Whether the request is to an admin endpoint, which read-only mode leaves writable
+pub(crate) fn is_admin(path: &str) -> bool {
+    path.strip_prefix(ApiVersion::V1.prefix())
+        .unwrap_or(path)
+        .starts_with("/admin/")
+}
+
+/// Middleware rejecting writes with `503 Service Unavailable` while read-only mode is on
+pub async fn read_only_middleware<B>(
+    State(mode): State<Arc<ReadOnlyMode>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !mode.is_enabled() || !is_write(request.method()) || is_admin(request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let mut response = AppError::ServiceUnavailable(
+        "The API is read-only for maintenance: reads work as usual, but changes are not \
+         accepted right now. Please try again in a few minutes."
+            .to_string(),
+    )
+    .into_response();
+    response.headers_mut().insert(
+        header::RETRY_AFTER,
+        HeaderValue::from_static(RETRY_AFTER_SECS),
+    );
+    response
+}
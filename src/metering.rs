@@ -0,0 +1,428 @@
+//! Metering of API usage per user and credential.
+//!
+//! The [`usage_middleware`] counts every request that authenticated as a user, with
+//! its status and the bytes of its request and response bodies, by credential, hour
+//! and endpoint. Counts are added up in memory and written to `api_usage` in batches
+//! every [`FLUSH_INTERVAL`], so metering does not add a write to each request. Users
+//! read their consumption through `GET /me/usage`.
+//!
+//! Credentials are identified by a key ID, the first 12 hex digits of the SHA-256 of
+//! the API key, so usage can be told apart per key without storing it; requests made
+//! with JWT access tokens are counted as `jwt`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    body::HttpBody,
+    extract::{MatchedPath, State},
+    http::{header, Request},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, DurationRound, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::router::ApiVersion;
+
+/// How often counted requests are written to the database
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(15);
+
+/// Key ID under which requests authenticated by JWT access tokens are counted
+pub const JWT_KEY_ID: &str = "jwt";
+
+/// Most days a usage report covers
+const MAX_REPORT_DAYS: u32 = 90;
+
+/// Key ID of an API key, given the hash it is stored under
+pub fn key_id(api_key_hash: &str) -> String {
+    api_key_hash.chars().take(12).collect()
+}
+
+/// Slot the [`AuthUser`](crate::auth::AuthUser) extractor fills with the caller of a
+/// metered request
+#[derive(Debug, Clone, Default)]
+pub(crate) struct MeteredCaller(Arc<Mutex<Option<(Uuid, String)>>>);
+
+impl MeteredCaller {
+    /// Records who made the request and with which credential
+    pub(crate) fn record(&self, user_id: Uuid, key_id: String) {
+        *self.0.lock().unwrap() = Some((user_id, key_id));
+    }
+
+    fn take(&self) -> Option<(Uuid, String)> {
+        self.0.lock().unwrap().take()
+    }
+}
+
+/// What requests are counted under
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct UsageKey {
+    user_id: Uuid,
+    key_id: String,
+    hour: DateTime<Utc>,
+    method: String,
+    route: String,
+}
+
+/// Counts of requests not written to the database yet
+#[derive(Debug, Default)]
+pub struct UsageMeter {
+    pending: Mutex<HashMap<UsageKey, UsageTotals>>,
+}
+
+impl UsageMeter {
+    /// Creates a meter with nothing counted
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn record(&self, key: UsageKey, counts: UsageTotals) {
+        self.pending
+            .lock()
+            .unwrap()
+            .entry(key)
+            .or_default()
+            .add(&counts);
+    }
+
+    /// Writes the counted requests to the database in one statement and returns how
+    /// many rows of `api_usage` were written
+    ///
+    /// Counts that fail to be written are kept for the next flush. Counts of users
+    /// deleted in the meantime are dropped.
+    pub async fn flush(&self, pool: &PgPool) -> Result<usize, sqlx::Error> {
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        if pending.is_empty() {
+            return Ok(0);
+        }
+
+        let mut columns = UsageColumns::default();
+        for (key, counts) in &pending {
+            columns.push(key, counts);
+        }
+        let result = sqlx::query(
+            r#"
+            INSERT INTO api_usage
+                (user_id, key_id, hour, method, route, requests, errors, request_bytes, response_bytes)
+            SELECT u.*
+            FROM UNNEST(
+                $1::UUID[], $2::TEXT[], $3::TIMESTAMPTZ[], $4::TEXT[], $5::TEXT[],
+                $6::BIGINT[], $7::BIGINT[], $8::BIGINT[], $9::BIGINT[]
+            ) AS u(user_id, key_id, hour, method, route, requests, errors, request_bytes, response_bytes)
+            WHERE EXISTS (SELECT 1 FROM users WHERE id = u.user_id)
+            ON CONFLICT (user_id, hour, key_id, method, route) DO UPDATE
+            SET requests = api_usage.requests + EXCLUDED.requests,
+                errors = api_usage.errors + EXCLUDED.errors,
+                request_bytes = api_usage.request_bytes + EXCLUDED.request_bytes,
+                response_bytes = api_usage.response_bytes + EXCLUDED.response_bytes
+            "#,
+        )
+        .bind(columns.user_ids)
+        .bind(columns.key_ids)
+        .bind(columns.hours)
+        .bind(columns.methods)
+        .bind(columns.routes)
+        .bind(columns.requests)
+        .bind(columns.errors)
+        .bind(columns.request_bytes)
+        .bind(columns.response_bytes)
+        .execute(pool)
+        .await;
+
+        match result {
+            Ok(result) => Ok(result.rows_affected() as usize),
+            Err(err) => {
+                for (key, counts) in pending {
+                    self.record(key, counts);
+                }
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Counted requests as the column arrays of a batch insert
+#[derive(Default)]
+struct UsageColumns {
+    user_ids: Vec<Uuid>,
+    key_ids: Vec<String>,
+    hours: Vec<DateTime<Utc>>,
+    methods: Vec<String>,
+    routes: Vec<String>,
+    requests: Vec<i64>,
+    errors: Vec<i64>,
+    request_bytes: Vec<i64>,
+    response_bytes: Vec<i64>,
+}
+
+impl UsageColumns {
+    fn push(&mut self, key: &UsageKey, counts: &UsageTotals) {
+        self.user_ids.push(key.user_id);
+        self.key_ids.push(key.key_id.clone());
+        self.hours.push(key.hour);
+        self.methods.push(key.method.clone());
+        self.routes.push(key.route.clone());
+        self.requests.push(counts.requests);
+        self.errors.push(counts.errors);
+        self.request_bytes.push(counts.request_bytes);
+        self.response_bytes.push(counts.response_bytes);
+    }
+}
+
+/// Middleware counting the requests that authenticated as a user
+///
+/// Bodies are counted by their `Content-Length` or exact size, so streamed responses
+/// such as exports and event streams count no bytes. Requests answered with `4xx` or
+/// `5xx` also count as errors.
+pub async fn usage_middleware<B: HttpBody>(
+    State(meter): State<Arc<UsageMeter>>,
+    mut request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let caller = MeteredCaller::default();
+    request.extensions_mut().insert(caller.clone());
+    let method = request.method().to_string();
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| normalize_route(path.as_str()));
+    let request_bytes = content_length(request.headers())
+        .or_else(|| request.body().size_hint().exact())
+        .unwrap_or(0);
+
+    let response = next.run(request).await;
+
+    let (Some((user_id, key_id)), Some(route)) = (caller.take(), route) else {
+        return response;
+    };
+    let response_bytes = content_length(response.headers())
+        .or_else(|| response.body().size_hint().exact())
+        .unwrap_or(0);
+    let hour = Utc::now()
+        .duration_trunc(TimeDelta::hours(1))
+        .unwrap_or_else(|_| Utc::now());
+    meter.record(
+        UsageKey {
+            user_id,
+            key_id,
+            hour,
+            method,
+            route,
+        },
+        UsageTotals {
+            requests: 1,
+            errors: i64::from(response.status().as_u16() >= 400),
+            request_bytes: request_bytes as i64,
+            response_bytes: response_bytes as i64,
+        },
+    );
+
+    response
+}
+
+/// Strips the version prefix, so versioned and legacy paths count as one endpoint
+fn normalize_route(route: &str) -> String {
+    match route.strip_prefix(ApiVersion::V1.prefix()) {
+        Some(rest) if rest.starts_with('/') => rest.to_string(),
+        _ => route.to_string(),
+    }
+}
+
+fn content_length(headers: &axum::http::HeaderMap) -> Option<u64> {
+    headers
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+/// Spawns the background task writing counted requests every `interval`
+pub fn spawn_flusher(meter: Arc<UsageMeter>, pool: PgPool, interval: Duration) -> JoinHandle<()> {
+    info!("Starting usage meter flushing every {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            match meter.flush(&pool).await {
+                Ok(0) => {}
+                Ok(rows) => debug!("Wrote {} rows of API usage", rows),
+                Err(err) => warn!("Writing API usage failed: {}", err),
+            }
+        }
+    })
+}
+
+/// Query parameters of the usage report
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct UsageParams {
+    /// Number of days the report covers, counting today (max 90)
+    #[serde(default = "default_report_days")]
+    pub days: u32,
+}
+
+fn default_report_days() -> u32 {
+    30
+}
+
+/// Requests counted under one credential or endpoint
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct UsageTotals {
+    /// Number of requests
+    pub requests: i64,
+    /// Number of requests answered with `4xx` or `5xx`
+    pub errors: i64,
+    /// Bytes of the request bodies
+    pub request_bytes: i64,
+    /// Bytes of the response bodies
+    pub response_bytes: i64,
+}
+
+/// Usage of one credential
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct KeyUsage {
+    /// First 12 hex digits of the SHA-256 of the API key, or `jwt` for access tokens
+    pub key_id: String,
+    /// Requests made with the credential
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    /// Start of the last hour the credential was used in
+    pub last_used_hour: DateTime<Utc>,
+}
+
+/// Usage of one endpoint
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct EndpointUsage {
+    /// HTTP method
+    pub method: String,
+    /// Route without the version prefix, e.g. `/wallets/:id`
+    pub route: String,
+    /// Requests to the endpoint
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+}
+
+/// A user's API consumption over a period
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct UsageReport {
+    /// Start of the first hour covered
+    pub since: DateTime<Utc>,
+    /// All requests of the period
+    #[serde(flatten)]
+    pub totals: UsageTotals,
+    /// Requests per credential, most used first
+    pub keys: Vec<KeyUsage>,
+    /// Requests per endpoint, most requested first
+    pub endpoints: Vec<EndpointUsage>,
+}
+
+#[derive(sqlx::FromRow)]
+struct UsageRow {
+    key_id: String,
+    hour: DateTime<Utc>,
+    method: String,
+    route: String,
+    requests: i64,
+    errors: i64,
+    request_bytes: i64,
+    response_bytes: i64,
+}
+
+impl UsageRow {
+    fn totals(&self) -> UsageTotals {
+        UsageTotals {
+            requests: self.requests,
+            errors: self.errors,
+            request_bytes: self.request_bytes,
+            response_bytes: self.response_bytes,
+        }
+    }
+}
+
+impl UsageTotals {
+    fn add(&mut self, other: &UsageTotals) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.request_bytes += other.request_bytes;
+        self.response_bytes += other.response_bytes;
+    }
+}
+
+/// Usage of a user over the last `days` days, as written by the last flush
+pub async fn report(pool: &PgPool, user_id: Uuid, days: u32) -> Result<UsageReport, sqlx::Error> {
+    let days = days.clamp(1, MAX_REPORT_DAYS);
+    let today = Utc::now()
+        .duration_trunc(TimeDelta::days(1))
+        .unwrap_or_else(|_| Utc::now());
+    let since = today - TimeDelta::days(i64::from(days) - 1);
+
+    let rows = sqlx::query_as::<_, UsageRow>(
+        r#"
+        SELECT key_id, hour, method, route, requests, errors, request_bytes, response_bytes
+        FROM api_usage
+        WHERE user_id = $1 AND hour >= $2
+        "#,
+    )
+    .bind(user_id)
+    .bind(since)
+    .fetch_all(pool)
+    .await?;
+
+    let mut totals = UsageTotals::default();
+    let mut keys: HashMap<String, KeyUsage> = HashMap::new();
+    let mut endpoints: HashMap<(String, String), EndpointUsage> = HashMap::new();
+    for row in &rows {
+        let row_totals = row.totals();
+        totals.add(&row_totals);
+
+        let key = keys.entry(row.key_id.clone()).or_insert_with(|| KeyUsage {
+            key_id: row.key_id.clone(),
+            totals: UsageTotals::default(),
+            last_used_hour: row.hour,
+        });
+        key.totals.add(&row_totals);
+        key.last_used_hour = key.last_used_hour.max(row.hour);
+
+        endpoints
+            .entry((row.method.clone(), row.route.clone()))
+            .or_insert_with(|| EndpointUsage {
+                method: row.method.clone(),
+                route: row.route.clone(),
+                totals: UsageTotals::default(),
+            })
+            .totals
+            .add(&row_totals);
+    }
+
+    let mut keys: Vec<KeyUsage> = keys.into_values().collect();
+    keys.sort_by(|a, b| {
+        b.totals
+            .requests
+            .cmp(&a.totals.requests)
+            .then_with(|| a.key_id.cmp(&b.key_id))
+    });
+    let mut endpoints: Vec<EndpointUsage> = endpoints.into_values().collect();
+    endpoints.sort_by(|a, b| {
+        b.totals
+            .requests
+            .cmp(&a.totals.requests)
+            .then_with(|| (&a.route, &a.method).cmp(&(&b.route, &b.method)))
+    });
+
+    Ok(UsageReport {
+        since,
+        totals,
+        keys,
+        endpoints,
+    })
+}
@@ -1,10 +1,142 @@
-use serde::{Deserialize, Serialize};
+use curve25519_dalek::edwards::CompressedEdwardsY;
+use serde::{Deserialize, Deserializer, Serialize};
+use serde_json::Value;
 use sqlx::types::chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
 use utoipa::ToSchema;
 use uuid::Uuid;
 
+use crate::classify::SwapDetails;
+use crate::error::ValidationErrors;
+use crate::money::{TokenAmount, Usd};
+use crate::tokens::TokenMetadata;
+
+/// Maximum length of a base58-encoded 32-byte public key
+const MAX_ADDRESS_LENGTH: usize = 44;
+
+/// Maximum length of a wallet's notes, in characters
+pub const MAX_WALLET_NOTES_LENGTH: usize = 4096;
+
+/// Maximum size of a wallet's JSON metadata, in bytes once serialized
+pub const MAX_WALLET_METADATA_BYTES: usize = 16 * 1024;
+
+/// Reasons a string is not a valid Solana wallet address
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum InvalidAddress {
+    /// The address is empty or only whitespace
+    #[error("Address cannot be empty")]
+    Empty,
+
+    /// The address is longer than any base58-encoded public key
+    #[error("Address is too long (max 44 characters)")]
+    TooLong,
+
+    /// The address contains characters outside the base58 alphabet
+    #[error("Invalid address: must be base58 encoded")]
+    NotBase58,
+
+    /// The address does not decode to a 32-byte public key
+    #[error("Invalid address: must decode to 32 bytes, got {0}")]
+    WrongLength(usize),
+}
+
+/// A Solana wallet address: a base58-encoded 32-byte ed25519 public key
+///
+/// Parsing checks the encoding and length only. Program-derived addresses are
+/// deliberately off the ed25519 curve, so on-curve verification is a separate,
+/// opt-in check via [`WalletAddress::is_on_curve`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct WalletAddress(String);
+
+impl WalletAddress {
+    /// Parses and validates a base58-encoded address, ignoring surrounding whitespace
+    pub fn parse(address: &str) -> Result<Self, InvalidAddress> {
+        match Self::problems(address).into_iter().next() {
+            Some(problem) => Err(problem),
+            None => Ok(Self(address.trim().to_string())),
+        }
+    }
+
+    /// Every reason `address` is not a valid address, none if it is one
+    pub fn problems(address: &str) -> Vec<InvalidAddress> {
+        let address = address.trim();
+        if address.is_empty() {
+            return vec![InvalidAddress::Empty];
+        }
+
+        let too_long = address.len() > MAX_ADDRESS_LENGTH;
+        let mut problems = Vec::new();
+        if too_long {
+            problems.push(InvalidAddress::TooLong);
+        }
+
+        match bs58::decode(address).into_vec() {
+            Err(_) => problems.push(InvalidAddress::NotBase58),
+            // An overly long address cannot decode to 32 bytes; saying so again adds nothing
+            Ok(bytes) if bytes.len() != 32 && !too_long => {
+                problems.push(InvalidAddress::WrongLength(bytes.len()))
+            }
+            Ok(_) => {}
+        }
+
+        problems
+    }
+
+    /// The base58-encoded address
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The decoded 32-byte public key
+    pub fn to_bytes(&self) -> [u8; 32] {
+        let mut bytes = [0u8; 32];
+        bs58::decode(&self.0)
+            .into(&mut bytes)
+            .expect("WalletAddress is validated on construction");
+        bytes
+    }
+
+    /// Whether the public key is a valid point on the ed25519 curve
+    ///
+    /// Keypair-backed wallets are always on the curve; program-derived addresses never are.
+    pub fn is_on_curve(&self) -> bool {
+        CompressedEdwardsY(self.to_bytes()).decompress().is_some()
+    }
+}
+
+impl FromStr for WalletAddress {
+    type Err = InvalidAddress;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for WalletAddress {
+    type Error = InvalidAddress;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<WalletAddress> for String {
+    fn from(address: WalletAddress) -> Self {
+        address.0
+    }
+}
+
+impl fmt::Display for WalletAddress {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
 /// Represents a cryptocurrency wallet in the system
-#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
 pub struct Wallet {
     /// Unique identifier for the wallet
     #[schema(example = "123e4567-e89b-12d3-a456-426614174000")]
@@ -14,10 +146,29 @@ pub struct Wallet {
     #[schema(example = "0x742d35Cc6634C0532925a3b844Bc454e4438f44e")]
     pub address: String,
 
+    /// `.sol` domain of the wallet, if it has one
+    #[schema(example = "degen.sol")]
+    pub domain: Option<String>,
+
     /// Optional name for the wallet
     #[schema(example = "My Solana Wallet")]
     pub name: Option<String>,
 
+    /// Free-form notes about the wallet
+    #[schema(example = "Funded from CEX")]
+    pub notes: Option<String>,
+
+    /// Arbitrary JSON object of client-defined context
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Value>,
+
+    /// Whether the wallet is left out of the public PnL leaderboard
+    pub leaderboard_opt_out: bool,
+
+    /// When the wallet's transactions were last synced from the Solana RPC
+    #[schema(example = "2025-07-19T17:05:00Z")]
+    pub last_synced_at: Option<DateTime<Utc>>,
+
     /// When the wallet was first added to the system
     #[schema(example = "2025-07-19T17:00:00Z")]
     pub created_at: DateTime<Utc>,
@@ -27,14 +178,379 @@ pub struct Wallet {
     pub updated_at: DateTime<Utc>,
 }
 
+/// A recorded balance change of one token in a wallet
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Transaction {
+    /// Unique identifier for the transaction row
+    pub id: Uuid,
+
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+
+    /// Token name, if known
+    #[schema(example = "Bonk")]
+    #[sqlx(default)]
+    pub token_name: Option<String>,
+
+    /// URI of the token's logo image, if known
+    #[sqlx(default)]
+    pub logo_uri: Option<String>,
+
+    /// Signed token amount as a decimal string: positive when received, negative when sent
+    #[schema(value_type = String, example = "1500000")]
+    pub amount: TokenAmount,
+
+    /// USD price per token at the time of the transaction
+    #[schema(value_type = f64)]
+    pub buy_price_usd: Usd,
+
+    /// Signature of the on-chain transaction
+    pub transaction_hash: String,
+
+    /// Slot the transaction was processed in
+    pub block_number: i64,
+
+    /// When the transaction was processed on-chain, if known
+    pub block_time: Option<DateTime<Utc>>,
+
+    /// Category assigned by the classifier; `null` for rows recorded before classification
+    #[schema(value_type = Option<crate::classify::TransactionCategory>, example = "swap_buy")]
+    pub category: Option<String>,
+
+    /// The swap the transaction made, if its instructions could be decoded
+    #[sqlx(json(nullable))]
+    pub swap: Option<SwapDetails>,
+
+    /// Account on the other side of the balance change: who the tokens were sent to or
+    /// received from
+    #[sqlx(default)]
+    pub counterparty: Option<String>,
+
+    /// Name of the counterparty, if it is a known entity
+    #[schema(example = "Binance Hot Wallet")]
+    #[sqlx(default)]
+    pub counterparty_label: Option<String>,
+
+    /// Kind of known entity the counterparty is
+    #[schema(value_type = Option<crate::labels::LabelCategory>, example = "exchange")]
+    #[sqlx(default)]
+    pub counterparty_category: Option<String>,
+
+    /// When the transaction was recorded
+    pub created_at: DateTime<Utc>,
+}
+
+impl Transaction {
+    /// Labels the transaction with the token's cached metadata
+    pub fn with_metadata(mut self, metadata: Option<&TokenMetadata>) -> Self {
+        if let Some(metadata) = metadata {
+            if let Some(symbol) = &metadata.symbol {
+                self.token_symbol = symbol.clone();
+            }
+            self.token_name = metadata.name.clone();
+            self.logo_uri = metadata.logo_uri.clone();
+        }
+        self
+    }
+}
+
 /// Request payload for creating a new wallet
 #[derive(Debug, Serialize, Deserialize, ToSchema)]
 pub struct CreateWallet {
-    /// Blockchain address of the wallet
-    #[schema(example = "0x742d35Cc6634C0532925a3b844Bc454e4438f44e")]
-    pub address: String,
+    /// Base58-encoded Solana address of the wallet, or a `.sol` domain resolving to it
+    #[schema(value_type = String, example = "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU")]
+    pub address: WalletAddress,
+
+    /// `.sol` domain of the wallet, resolved by the server
+    #[serde(skip)]
+    pub domain: Option<String>,
 
     /// Optional name for the wallet
     #[schema(example = "My Wallet")]
     pub name: Option<String>,
+
+    /// Free-form notes about the wallet
+    #[serde(default)]
+    #[schema(example = "Funded from CEX")]
+    pub notes: Option<String>,
+
+    /// Arbitrary JSON object of client-defined context
+    #[serde(default)]
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Value>,
+}
+
+/// Request payload for creating a new wallet, as received
+///
+/// The address is kept as a string so that every problem with the payload can be
+/// reported per field, see [`CreateWalletRequest::validate`].
+#[derive(Debug, Deserialize)]
+pub struct CreateWalletRequest {
+    /// Base58-encoded Solana address of the wallet
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Optional name for the wallet
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Free-form notes about the wallet
+    #[serde(default)]
+    pub notes: Option<String>,
+    /// Arbitrary JSON object of client-defined context
+    #[serde(default)]
+    pub metadata: Option<Value>,
+}
+
+impl CreateWalletRequest {
+    /// Validates every field, turning the request into a [`CreateWallet`]
+    ///
+    /// With `require_on_curve`, addresses off the ed25519 curve are rejected too.
+    pub fn validate(self, require_on_curve: bool) -> Result<CreateWallet, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+
+        let address = match self.address.as_deref() {
+            None => {
+                errors.add("address", "Address is required");
+                None
+            }
+            Some(address) => match WalletAddress::parse(address) {
+                Ok(address) if require_on_curve && !address.is_on_curve() => {
+                    errors.add("address", "Invalid address: not a valid ed25519 public key");
+                    None
+                }
+                Ok(address) => Some(address),
+                Err(_) => {
+                    for problem in WalletAddress::problems(address) {
+                        errors.add("address", problem.to_string());
+                    }
+                    None
+                }
+            },
+        };
+        check_wallet_details(&mut errors, self.notes.as_deref(), self.metadata.as_ref());
+
+        match address {
+            Some(address) if errors.is_empty() => Ok(CreateWallet {
+                address,
+                domain: None,
+                name: self.name,
+                notes: self.notes,
+                metadata: self.metadata,
+            }),
+            _ => Err(errors),
+        }
+    }
+}
+
+/// Request payload for updating a wallet
+///
+/// Omitted fields are left unchanged; `null` clears a field. Serializing keeps that
+/// distinction: `None` fields are left out and `Some(None)` is written as `null`.
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct UpdateWallet {
+    /// New name for the wallet
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, example = "My Wallet")]
+    pub name: Option<Option<String>>,
+
+    /// New notes about the wallet
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<String>, example = "Funded from CEX")]
+    pub notes: Option<Option<String>>,
+
+    /// New JSON metadata object, replacing the current one
+    #[serde(
+        default,
+        deserialize_with = "present",
+        skip_serializing_if = "Option::is_none"
+    )]
+    #[schema(value_type = Option<Object>)]
+    pub metadata: Option<Option<Value>>,
+
+    /// Leave the wallet out of (`true`) or show it on (`false`) the PnL leaderboard
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub leaderboard_opt_out: Option<bool>,
+}
+
+/// Deserializes a field that is present in the input, so `null` becomes `Some(None)`
+/// while an omitted field keeps its `None` default
+fn present<'de, D, T>(deserializer: D) -> Result<Option<Option<T>>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de>,
+{
+    Option::<T>::deserialize(deserializer).map(Some)
+}
+
+impl UpdateWallet {
+    /// Checks the size of the new notes and metadata
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        check_wallet_details(
+            &mut errors,
+            self.notes.as_ref().and_then(|notes| notes.as_deref()),
+            self.metadata
+                .as_ref()
+                .and_then(|metadata| metadata.as_ref()),
+        );
+        errors.into_result()
+    }
+}
+
+/// Records problems with the size of a wallet's notes and the shape of its metadata
+fn check_wallet_details(
+    errors: &mut ValidationErrors,
+    notes: Option<&str>,
+    metadata: Option<&Value>,
+) {
+    if notes.is_some_and(|notes| notes.chars().count() > MAX_WALLET_NOTES_LENGTH) {
+        errors.add(
+            "notes",
+            format!("Notes are too long (max {MAX_WALLET_NOTES_LENGTH} characters)"),
+        );
+    }
+
+    if let Some(metadata) = metadata {
+        if !metadata.is_object() {
+            errors.add("metadata", "Metadata must be a JSON object");
+        } else if metadata.to_string().len() > MAX_WALLET_METADATA_BYTES {
+            errors.add(
+                "metadata",
+                format!("Metadata is too large (max {MAX_WALLET_METADATA_BYTES} bytes)"),
+            );
+        }
+    }
+}
+
+/// Net position of a wallet in a single token, derived from its transactions
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Holding {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+
+    /// Token name, if known
+    #[schema(example = "Bonk")]
+    pub token_name: Option<String>,
+
+    /// URI of the token's logo image, if known
+    pub logo_uri: Option<String>,
+
+    /// Net token amount held, as a decimal string
+    #[schema(value_type = String, example = "1500000.5")]
+    pub amount: TokenAmount,
+
+    /// Current USD price per token, if available
+    #[schema(example = 0.0000215)]
+    pub price_usd: Option<f64>,
+
+    /// Current USD value of the position, if the price is available
+    #[schema(value_type = Option<f64>, example = 32.25)]
+    pub value_usd: Option<Usd>,
+}
+
+impl Holding {
+    /// Labels the holding with the token's cached metadata
+    pub fn with_metadata(mut self, metadata: Option<&TokenMetadata>) -> Self {
+        if let Some(metadata) = metadata {
+            if let Some(symbol) = &metadata.symbol {
+                self.token_symbol = symbol.clone();
+            }
+            self.token_name = metadata.name.clone();
+            self.logo_uri = metadata.logo_uri.clone();
+        }
+        self
+    }
+}
+
+/// Current holdings of a wallet
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletHoldings {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+
+    /// Non-zero token positions
+    pub holdings: Vec<Holding>,
+
+    /// Total USD value of the positions with a known price
+    #[schema(value_type = f64)]
+    pub total_value_usd: Usd,
+}
+
+/// Combined holdings of all of a user's wallets
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct Portfolio {
+    /// Number of wallets the user tracks
+    pub wallet_count: i64,
+
+    /// Non-zero token positions, summed across wallets
+    pub holdings: Vec<Holding>,
+
+    /// Total USD value of the positions with a known price
+    #[schema(value_type = f64)]
+    pub total_value_usd: Usd,
+
+    /// Total value expressed in SOL, if the SOL price is available
+    pub total_value_sol: Option<f64>,
+
+    /// USD price of SOL used for the SOL total, if available
+    pub sol_price_usd: Option<f64>,
+}
+
+/// An API user who owns wallets
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct User {
+    /// Unique identifier for the user
+    #[schema(example = "0190c8a2-7b3e-7c1d-9f4a-2b6e8d1c3a5f")]
+    pub id: Uuid,
+
+    /// Optional display name
+    #[schema(example = "degen.trader")]
+    pub name: Option<String>,
+
+    /// When the user was created
+    #[schema(example = "2025-07-19T17:00:00Z")]
+    pub created_at: DateTime<Utc>,
+
+    /// When the user was last updated
+    #[schema(example = "2025-07-19T17:00:00Z")]
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request payload for creating a new user
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct CreateUser {
+    /// Optional display name
+    #[schema(example = "degen.trader")]
+    pub name: Option<String>,
+}
+
+/// A newly created user together with its API key
+///
+/// The API key is only ever returned here; the server stores just its hash.
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreatedUser {
+    /// The created user
+    #[serde(flatten)]
+    pub user: User,
+
+    /// API key to send as `Authorization: Bearer <key>` or `X-API-Key`
+    #[schema(example = "dgn_4Nd1mBQtrMJVYVfKf2PJy9NZUZdTAsp7D4xWLs4gDB4T")]
+    pub api_key: String,
 }
@@ -0,0 +1,342 @@
+//! Exact decimal amounts of tokens and US dollars.
+//!
+//! Amounts are stored as `NUMERIC` and read into [`Decimal`]s rather than floats, so
+//! holdings and profit and loss add up to the cent and token amounts to their last
+//! decimal. Token amounts serialize as decimal strings, which JSON clients cannot round;
+//! USD values serialize as JSON numbers, rounded only when written.
+
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+use rust_decimal::prelude::{FromPrimitive, ToPrimitive};
+use rust_decimal::Decimal;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+/// Largest number of decimals a token amount can carry
+pub const MAX_DECIMALS: u32 = 28;
+
+/// An exact, signed amount of a token, in whole tokens
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct TokenAmount(Decimal);
+
+impl TokenAmount {
+    /// No tokens
+    pub const ZERO: Self = Self(Decimal::ZERO);
+
+    /// Wraps an exact decimal amount
+    pub fn new(amount: Decimal) -> Self {
+        Self(amount.normalize())
+    }
+
+    /// Amount of `raw` base units of a token with `decimals` decimals, e.g. lamports
+    /// with 9; `None` if it exceeds the range of a [`Decimal`]
+    pub fn from_raw(raw: i128, decimals: u32) -> Option<Self> {
+        if decimals > MAX_DECIMALS {
+            return None;
+        }
+        Decimal::try_from_i128_with_scale(raw, decimals)
+            .ok()
+            .map(Self::new)
+    }
+
+    /// Number of base units of a token with `decimals` decimals, dropping digits
+    /// beyond them; the inverse of [`Self::from_raw`]
+    pub fn to_raw(&self, decimals: u32) -> Option<i128> {
+        let scale = Decimal::from_i128_with_scale(10i128.checked_pow(decimals)?, 0);
+        self.0.checked_mul(scale)?.trunc().to_i128()
+    }
+
+    /// The exact decimal amount
+    pub fn decimal(&self) -> Decimal {
+        self.0
+    }
+
+    /// The amount as a float, for statistics that do not need to be exact
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or_default()
+    }
+
+    /// Whether the amount is zero
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    /// Whether the amount is above zero, i.e. tokens were received
+    pub fn is_positive(&self) -> bool {
+        self.0 > Decimal::ZERO
+    }
+
+    /// Whether the amount is below zero, i.e. tokens were sent
+    pub fn is_negative(&self) -> bool {
+        self.0 < Decimal::ZERO
+    }
+
+    /// The absolute amount
+    pub fn abs(&self) -> Self {
+        Self(self.0.abs())
+    }
+
+    /// The smaller of two amounts
+    pub fn min(self, other: Self) -> Self {
+        Self(self.0.min(other.0))
+    }
+}
+
+impl fmt::Display for TokenAmount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0.normalize(), f)
+    }
+}
+
+/// Parses a plain or scientific decimal, e.g. `1500000.5` or `1e-5`
+fn parse_decimal(value: &str) -> Result<Decimal, rust_decimal::Error> {
+    let value = value.trim();
+    Decimal::from_str_exact(value).or_else(|err| Decimal::from_scientific(value).map_err(|_| err))
+}
+
+impl FromStr for TokenAmount {
+    type Err = rust_decimal::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse_decimal(value).map(Self::new)
+    }
+}
+
+impl From<Decimal> for TokenAmount {
+    fn from(amount: Decimal) -> Self {
+        Self::new(amount)
+    }
+}
+
+impl Serialize for TokenAmount {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for TokenAmount {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match DecimalRepr::deserialize(deserializer)? {
+            DecimalRepr::Text(text) => text.parse().map_err(de::Error::custom),
+            DecimalRepr::Number(number) => number.to_string().parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// Serializes a [`TokenAmount`] as a JSON number, for fields that were numbers before
+/// amounts were exact
+pub mod as_number {
+    use super::*;
+
+    /// Writes the amount as a number
+    pub fn serialize<S: Serializer>(
+        amount: &TokenAmount,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(amount.to_f64())
+    }
+
+    /// Reads the amount from a number or a decimal string
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<TokenAmount, D::Error> {
+        TokenAmount::deserialize(deserializer)
+    }
+}
+
+/// An exact amount of US dollars
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, sqlx::Type)]
+#[sqlx(transparent)]
+pub struct Usd(Decimal);
+
+impl Usd {
+    /// No dollars
+    pub const ZERO: Self = Self(Decimal::ZERO);
+
+    /// Wraps an exact decimal amount of dollars
+    pub fn new(amount: Decimal) -> Self {
+        Self(amount)
+    }
+
+    /// Dollars of a float, e.g. a price from a price feed; zero if not finite
+    pub fn from_f64(amount: f64) -> Self {
+        Self(Decimal::from_f64(amount).unwrap_or_default())
+    }
+
+    /// The exact decimal amount
+    pub fn decimal(&self) -> Decimal {
+        self.0
+    }
+
+    /// The amount as a float
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or_default()
+    }
+
+    /// Whether the amount is above zero
+    pub fn is_positive(&self) -> bool {
+        self.0 > Decimal::ZERO
+    }
+
+    /// Whether the amount is below zero
+    pub fn is_negative(&self) -> bool {
+        self.0 < Decimal::ZERO
+    }
+
+    /// The amount as a percentage of `total`; `None` if `total` is zero
+    pub fn percent_of(&self, total: Usd) -> Option<f64> {
+        let ratio = self.0.checked_div(total.0)?;
+        ratio.checked_mul(Decimal::ONE_HUNDRED)?.to_f64()
+    }
+}
+
+impl fmt::Display for Usd {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0.normalize(), f)
+    }
+}
+
+impl FromStr for Usd {
+    type Err = rust_decimal::Error;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        parse_decimal(value).map(Self)
+    }
+}
+
+impl Serialize for Usd {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(self.to_f64())
+    }
+}
+
+impl<'de> Deserialize<'de> for Usd {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match DecimalRepr::deserialize(deserializer)? {
+            DecimalRepr::Text(text) => text.parse().map_err(de::Error::custom),
+            DecimalRepr::Number(number) => number.to_string().parse().map_err(de::Error::custom),
+        }
+    }
+}
+
+/// A decimal read from JSON, as text or a number
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DecimalRepr {
+    Text(String),
+    Number(serde_json::Number),
+}
+
+// Arithmetic saturates rather than panicking on amounts outside the range of a
+// `Decimal`, which only spam tokens reach
+
+impl Add for TokenAmount {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for TokenAmount {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Neg for TokenAmount {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl AddAssign for TokenAmount {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for TokenAmount {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for TokenAmount {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+/// Value of an amount of tokens at a price per token
+impl Mul<Usd> for TokenAmount {
+    type Output = Usd;
+
+    fn mul(self, price: Usd) -> Usd {
+        Usd(self.0.saturating_mul(price.0))
+    }
+}
+
+/// Price per token of a value
+impl Div<TokenAmount> for Usd {
+    type Output = Usd;
+
+    fn div(self, amount: TokenAmount) -> Usd {
+        Usd(self.0.checked_div(amount.0).unwrap_or_default())
+    }
+}
+
+impl Add for Usd {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self(self.0.saturating_add(other.0))
+    }
+}
+
+impl Sub for Usd {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self(self.0.saturating_sub(other.0))
+    }
+}
+
+impl Neg for Usd {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self(-self.0)
+    }
+}
+
+impl AddAssign for Usd {
+    fn add_assign(&mut self, other: Self) {
+        *self = *self + other;
+    }
+}
+
+impl SubAssign for Usd {
+    fn sub_assign(&mut self, other: Self) {
+        *self = *self - other;
+    }
+}
+
+impl Sum for Usd {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, Add::add)
+    }
+}
+
+impl<'a> Sum<&'a Usd> for Usd {
+    fn sum<I: Iterator<Item = &'a Usd>>(iter: I) -> Self {
+        iter.copied().sum()
+    }
+}
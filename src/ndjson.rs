@@ -0,0 +1,46 @@
+use axum::{
+    body::{Bytes, StreamBody},
+    http::{header, HeaderMap},
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use serde::Serialize;
+use tokio_stream::Stream;
+
+use crate::negotiate;
+
+/// Media type of newline-delimited JSON
+pub const NDJSON_CONTENT_TYPE: &str = "application/x-ndjson";
+
+/// Media types accepted as a request for newline-delimited JSON
+const NDJSON_MEDIA_TYPES: [&str; 2] = [NDJSON_CONTENT_TYPE, "application/ndjson"];
+
+/// Whether the client asked for newline-delimited JSON in its `Accept` header
+///
+/// Clients asking for both JSON and NDJSON get NDJSON.
+pub fn accepts_ndjson(headers: &HeaderMap) -> bool {
+    negotiate::accepts(headers, &NDJSON_MEDIA_TYPES)
+}
+
+/// Appends a value to a chunk as one line of JSON
+pub fn push_line<T: Serialize>(chunk: &mut Vec<u8>, value: &T) -> Result<(), serde_json::Error> {
+    serde_json::to_writer(&mut *chunk, value)?;
+    chunk.push(b'\n');
+    Ok(())
+}
+
+/// Sends a stream of NDJSON chunks as a chunked response
+///
+/// An error ends the stream, which aborts the response, so clients see the transfer
+/// fail rather than a list that is silently cut short.
+pub fn ndjson_response<S, E>(stream: S) -> Response
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + 'static,
+    E: Into<BoxError>,
+{
+    (
+        [(header::CONTENT_TYPE, NDJSON_CONTENT_TYPE)],
+        StreamBody::new(stream),
+    )
+        .into_response()
+}
@@ -0,0 +1,87 @@
+use axum::{
+    body::{boxed, Full},
+    http::{header, HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::AppError;
+
+/// Media type of MessagePack
+pub const MSGPACK_CONTENT_TYPE: &str = "application/msgpack";
+
+/// Media types accepted as a request for MessagePack
+const MSGPACK_MEDIA_TYPES: [&str; 2] = [MSGPACK_CONTENT_TYPE, "application/x-msgpack"];
+
+/// Whether the `Accept` header lists any of the media types
+///
+/// Quality values are ignored: a listed type is taken as preferred over JSON.
+pub fn accepts(headers: &HeaderMap, media_types: &[&str]) -> bool {
+    headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .filter_map(|media_range| media_range.split(';').next())
+        .any(|media_type| {
+            media_types
+                .iter()
+                .any(|accepted| media_type.trim().eq_ignore_ascii_case(accepted))
+        })
+}
+
+/// Whether a response carries a JSON body
+fn is_json(response: &Response) -> bool {
+    response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"))
+}
+
+/// Re-encodes a JSON response body as MessagePack, with maps keyed by field name
+async fn to_msgpack(response: Response) -> Result<Response, AppError> {
+    let (mut parts, body) = response.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map_err(|e| AppError::InternalServerError(format!("Failed to read response: {e}")))?;
+    let value: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(|e| AppError::InternalServerError(format!("Invalid JSON response: {e}")))?;
+    let packed = rmp_serde::to_vec_named(&value)
+        .map_err(|e| AppError::InternalServerError(format!("Failed to encode MessagePack: {e}")))?;
+
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static(MSGPACK_CONTENT_TYPE),
+    );
+    parts.headers.remove(header::CONTENT_LENGTH);
+    // The tag identifies the JSON body; this one is only semantically equivalent
+    if let Some(etag) = parts.headers.get(header::ETAG).cloned() {
+        if !etag.as_bytes().starts_with(b"W/") {
+            let mut weak = b"W/".to_vec();
+            weak.extend_from_slice(etag.as_bytes());
+            if let Ok(weak) = HeaderValue::from_bytes(&weak) {
+                parts.headers.insert(header::ETAG, weak);
+            }
+        }
+    }
+
+    Ok(Response::from_parts(parts, boxed(Full::from(packed))))
+}
+
+/// Middleware answering clients that send `Accept: application/msgpack` with
+/// MessagePack instead of JSON
+///
+/// JSON stays the default. Every JSON response, errors included, is re-encoded;
+/// streamed and non-JSON responses such as CSV, NDJSON and event streams are left
+/// alone. The router's CORS layer adds `Vary: Accept` to every response.
+pub async fn msgpack_middleware<B>(request: Request<B>, next: Next<B>) -> Response {
+    let wants_msgpack = accepts(request.headers(), &MSGPACK_MEDIA_TYPES);
+    let response = next.run(request).await;
+    if !wants_msgpack || !is_json(&response) {
+        return response;
+    }
+
+    to_msgpack(response)
+        .await
+        .unwrap_or_else(IntoResponse::into_response)
+}
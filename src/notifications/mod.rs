@@ -0,0 +1,637 @@
+//! Push notifications to users' chats and inboxes.
+//!
+//! Users configure a [`NotificationChannel`] per [`ChannelKind`]: the Telegram chat the
+//! bot from `TELEGRAM_BOT_TOKEN` writes to, or an email address mailed through
+//! `SMTP_URL`. Alert firings, detected transactions worth at least a channel's
+//! `large_transaction_usd`, opted-in [portfolio reports](crate::reports) and
+//! [whale movements](crate::whales) are rendered from [`templates`] and recorded in
+//! the `notifications` outbox together with a job sending them, retried with exponential backoff by the [job queue](crate::jobs).
+//!
+//! Targets are stored [sealed](crate::secrets) and only opened to list channels and to
+//! send.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::jobs::{self, Job};
+use crate::prices::PriceSource;
+use crate::reports::ReportPeriod;
+use crate::secrets::{SecretBox, Slot};
+use crate::webhooks::DetectedTransaction;
+use crate::AppError;
+
+pub mod email;
+pub mod telegram;
+pub mod templates;
+
+pub use email::EmailSender;
+pub use telegram::TelegramSender;
+use templates::Rendered;
+
+/// Attempts of a notification before it is marked failed
+pub const MAX_ATTEMPTS: i32 = 5;
+
+/// Kinds of channels notifications are sent on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelKind {
+    /// A Telegram chat, written to by the configured bot
+    Telegram,
+    /// An email address, mailed through the configured SMTP server
+    Email,
+}
+
+impl ChannelKind {
+    /// Name of the channel kind as stored in `notification_channels.kind`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Telegram => "telegram",
+            Self::Email => "email",
+        }
+    }
+}
+
+/// A user's notification channel
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct NotificationChannel {
+    /// Kind of the channel
+    #[schema(value_type = ChannelKind)]
+    pub kind: String,
+    /// Where messages are sent: a Telegram chat ID or an email address
+    #[schema(example = "123456789")]
+    pub target: String,
+    /// Whether anything is sent on the channel
+    pub enabled: bool,
+    /// Whether alert firings are sent
+    pub alerts: bool,
+    /// USD value from which detected transactions are sent; none are if `null`
+    #[schema(example = 1000.0)]
+    pub large_transaction_usd: Option<f64>,
+    /// Whether daily portfolio reports are sent
+    pub daily_summary: bool,
+    /// Whether weekly portfolio reports are sent
+    pub weekly_summary: bool,
+    /// Whether whale movements of the user's wallets are sent
+    pub whales: bool,
+    /// When the channel was configured
+    pub created_at: DateTime<Utc>,
+    /// When the channel was last changed
+    pub updated_at: DateTime<Utc>,
+}
+
+/// Request payload for configuring a notification channel
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ConfigureChannel {
+    /// Where messages are sent: for Telegram, a numeric chat ID or an `@channel` name;
+    /// for email, an address
+    #[schema(example = "123456789")]
+    pub target: String,
+    /// Whether anything is sent on the channel; defaults to `true`
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Whether alert firings are sent; defaults to `true`
+    #[serde(default = "default_true")]
+    pub alerts: bool,
+    /// Send detected transactions worth at least this many USD; none are if omitted
+    #[schema(example = 1000.0)]
+    pub large_transaction_usd: Option<f64>,
+    /// Send daily portfolio reports; defaults to `false`
+    #[serde(default)]
+    pub daily_summary: bool,
+    /// Send weekly portfolio reports; defaults to `false`
+    #[serde(default)]
+    pub weekly_summary: bool,
+    /// Send whale movements of your wallets; defaults to `false`
+    #[serde(default)]
+    pub whales: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// Columns selected into a [`NotificationChannel`]
+const CHANNEL_COLUMNS: &str =
+    "kind, target, enabled, alerts, large_transaction_usd, daily_summary, weekly_summary, \
+     whales, created_at, updated_at";
+
+/// Lists the notification channels of `user_id`
+pub async fn list_channels(
+    pool: &PgPool,
+    secrets: &SecretBox,
+    user_id: Uuid,
+) -> Result<Vec<NotificationChannel>, sqlx::Error> {
+    let mut channels = sqlx::query_as::<_, NotificationChannel>(&format!(
+        "SELECT {CHANNEL_COLUMNS} FROM notification_channels WHERE user_id = $1 ORDER BY kind"
+    ))
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    for channel in &mut channels {
+        let slot = Slot::channel_target(user_id, &channel.kind);
+        channel.target = secrets.open(&channel.target, &slot)?;
+    }
+
+    Ok(channels)
+}
+
+/// Validates and stores the channel of `kind` for `user_id`, replacing any existing one
+pub async fn configure_channel(
+    pool: &PgPool,
+    secrets: &SecretBox,
+    user_id: Uuid,
+    kind: ChannelKind,
+    request: &ConfigureChannel,
+) -> Result<NotificationChannel, AppError> {
+    let target = request.target.trim();
+    match kind {
+        ChannelKind::Telegram => {
+            if !is_telegram_chat(target) {
+                return Err(AppError::UnprocessableEntity(
+                    "Telegram target must be a numeric chat ID or an @channel name".to_string(),
+                ));
+            }
+        }
+        ChannelKind::Email => {
+            if target.parse::<lettre::Address>().is_err() {
+                return Err(AppError::UnprocessableEntity(
+                    "Email target must be an email address".to_string(),
+                ));
+            }
+        }
+    }
+    if let Some(threshold) = request.large_transaction_usd {
+        if !threshold.is_finite() || threshold <= 0.0 {
+            return Err(AppError::UnprocessableEntity(
+                "large_transaction_usd must be a positive number".to_string(),
+            ));
+        }
+    }
+
+    let mut channel = sqlx::query_as::<_, NotificationChannel>(&format!(
+        r#"
+        INSERT INTO notification_channels (
+            user_id, kind, target, enabled, alerts, large_transaction_usd, daily_summary,
+            weekly_summary, whales
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+        ON CONFLICT (user_id, kind) DO UPDATE
+        SET target = EXCLUDED.target,
+            enabled = EXCLUDED.enabled,
+            alerts = EXCLUDED.alerts,
+            large_transaction_usd = EXCLUDED.large_transaction_usd,
+            daily_summary = EXCLUDED.daily_summary,
+            weekly_summary = EXCLUDED.weekly_summary,
+            whales = EXCLUDED.whales
+        RETURNING {CHANNEL_COLUMNS}
+        "#
+    ))
+    .bind(user_id)
+    .bind(kind.as_str())
+    .bind(secrets.seal(target, &Slot::channel_target(user_id, kind.as_str())))
+    .bind(request.enabled)
+    .bind(request.alerts)
+    .bind(request.large_transaction_usd)
+    .bind(request.daily_summary)
+    .bind(request.weekly_summary)
+    .bind(request.whales)
+    .fetch_one(pool)
+    .await?;
+    channel.target = target.to_string();
+
+    Ok(channel)
+}
+
+/// Whether `target` is a Telegram chat ID or public channel name
+fn is_telegram_chat(target: &str) -> bool {
+    match target.strip_prefix('@') {
+        Some(name) => {
+            name.len() >= 5 && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+        }
+        None => {
+            let digits = target.strip_prefix('-').unwrap_or(target);
+            !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit())
+        }
+    }
+}
+
+/// Removes the channel of `kind` of `user_id`, returning `404 Not Found` if there is none
+pub async fn delete_channel(
+    pool: &PgPool,
+    user_id: Uuid,
+    kind: ChannelKind,
+) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM notification_channels WHERE user_id = $1 AND kind = $2")
+        .bind(user_id)
+        .bind(kind.as_str())
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "No {} notification channel configured",
+            kind.as_str()
+        )));
+    }
+
+    Ok(())
+}
+
+/// Which of a user's channels receive a message
+#[derive(Debug, Clone, Copy)]
+enum Audience {
+    /// Channels sending alert firings
+    Alerts,
+    /// Channels whose large transaction threshold a transaction of this USD value reaches
+    LargeTransaction(f64),
+    /// Channels sending the reports of a period
+    Report(ReportPeriod),
+    /// Channels sending whale movements
+    Whales,
+    /// Every channel, for security notices
+    Security,
+}
+
+/// Queues `message` on every enabled channel of `user_id` in `audience`, each with the
+/// job sending it; returns the number of notifications queued
+async fn queue(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    audience: Audience,
+    message: &Rendered,
+) -> Result<usize, sqlx::Error> {
+    let (audience, value_usd) = match audience {
+        Audience::Alerts => ("alerts", None),
+        Audience::LargeTransaction(value_usd) => ("large_transaction", Some(value_usd)),
+        Audience::Report(ReportPeriod::Daily) => ("daily_report", None),
+        Audience::Report(ReportPeriod::Weekly) => ("weekly_report", None),
+        Audience::Whales => ("whales", None),
+        Audience::Security => ("security", None),
+    };
+    let notifications = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO notifications (id, user_id, kind, target, subject, message)
+        SELECT gen_random_uuid(), user_id, kind, target, $2, $3
+        FROM notification_channels
+        WHERE user_id = $1
+          AND enabled
+          AND CASE $4
+                  WHEN 'alerts' THEN alerts
+                  WHEN 'large_transaction' THEN large_transaction_usd <= $5
+                  WHEN 'daily_report' THEN daily_summary
+                  WHEN 'weekly_report' THEN weekly_summary
+                  WHEN 'whales' THEN whales
+                  ELSE TRUE
+              END
+        RETURNING id
+        "#,
+    )
+    .bind(user_id)
+    .bind(&message.subject)
+    .bind(&message.body)
+    .bind(audience)
+    .bind(value_usd)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    for &notification_id in &notifications {
+        jobs::enqueue(&mut *conn, &Job::SendNotification { notification_id }).await?;
+    }
+    Ok(notifications.len())
+}
+
+/// Queues an alert firing described by `message` on every enabled channel of `user_id`
+/// that wants alert firings
+///
+/// Takes a connection so the notifications are recorded in the transaction recording
+/// the firing. Returns the number of notifications queued.
+pub async fn notify_alert(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    message: &str,
+) -> Result<usize, sqlx::Error> {
+    let message = templates::ALERT.render(&[("message", message)]);
+    queue(conn, user_id, Audience::Alerts, &message).await
+}
+
+/// Queues a rendered report of `period` on every enabled channel of `user_id` that
+/// wants the period's reports
+///
+/// Takes a connection so the notifications are recorded in the transaction recording
+/// the report. Returns the number of notifications queued.
+pub async fn notify_report(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    period: ReportPeriod,
+    message: &Rendered,
+) -> Result<usize, sqlx::Error> {
+    queue(conn, user_id, Audience::Report(period), message).await
+}
+
+/// Queues a rendered whale movement on every enabled channel of `user_id` that wants
+/// whale movements; returns the number of notifications queued
+pub async fn notify_whale_movement(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    message: &Rendered,
+) -> Result<usize, sqlx::Error> {
+    queue(conn, user_id, Audience::Whales, message).await
+}
+
+/// Queues a security notice on every enabled channel of `user_id`, whatever it was
+/// configured to send; returns the number of notifications queued
+pub async fn notify_security(
+    conn: &mut PgConnection,
+    user_id: Uuid,
+    message: &Rendered,
+) -> Result<usize, sqlx::Error> {
+    queue(conn, user_id, Audience::Security, message).await
+}
+
+/// Queues a check of newly recorded transactions against the large transaction
+/// thresholds of the wallet owner's channels, if any has one
+///
+/// Valuing the transactions needs the price feed, so the check runs as a job.
+pub async fn queue_large_transaction_check(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    transactions: &[DetectedTransaction],
+) -> Result<(), sqlx::Error> {
+    if transactions.is_empty() {
+        return Ok(());
+    }
+
+    let wanted = sqlx::query_scalar::<_, bool>(
+        r#"
+        SELECT EXISTS (
+            SELECT 1
+            FROM notification_channels c
+            JOIN wallets w ON w.user_id = c.user_id
+            WHERE w.id = $1 AND c.enabled AND c.large_transaction_usd IS NOT NULL
+        )
+        "#,
+    )
+    .bind(wallet_id)
+    .fetch_one(pool)
+    .await?;
+    if wanted {
+        jobs::enqueue(
+            pool,
+            &Job::NotifyLargeTransactions {
+                wallet_id,
+                transactions: transactions.to_vec(),
+            },
+        )
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Values newly recorded transactions of a wallet and queues a notification for each
+/// one reaching a channel's threshold; returns how many transactions were notified
+///
+/// Transactions of tokens without a known price are skipped.
+pub async fn notify_large_transactions(
+    pool: &PgPool,
+    prices: &dyn PriceSource,
+    wallet_id: Uuid,
+    transactions: &[DetectedTransaction],
+) -> Result<usize, AppError> {
+    let wallet = sqlx::query_as::<_, (Uuid, String, Option<String>)>(
+        "SELECT user_id, address, name FROM wallets WHERE id = $1",
+    )
+    .bind(wallet_id)
+    .fetch_optional(pool)
+    .await?;
+    // Deleted since the transactions were recorded
+    let Some((user_id, address, name)) = wallet else {
+        return Ok(0);
+    };
+
+    let mut mints: Vec<String> = transactions
+        .iter()
+        .map(|tx| tx.token_address.clone())
+        .collect();
+    mints.sort();
+    mints.dedup();
+    let prices = prices.prices_usd(&mints).await?;
+
+    let hashes: Vec<&str> = transactions
+        .iter()
+        .map(|tx| tx.transaction_hash.as_str())
+        .collect();
+    let symbols: HashMap<(String, String), String> = sqlx::query_as::<_, (String, String, String)>(
+        r#"
+            SELECT transaction_hash, token_address, token_symbol
+            FROM transactions
+            WHERE wallet_id = $1 AND transaction_hash = ANY($2)
+            "#,
+    )
+    .bind(wallet_id)
+    .bind(&hashes)
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|(hash, mint, symbol)| ((hash, mint), symbol))
+    .collect();
+
+    let label = name.unwrap_or(address);
+    let mut notified = 0;
+    for tx in transactions {
+        let Some(price) = prices.get(&tx.token_address) else {
+            continue;
+        };
+        let value_usd = tx.amount.abs().to_f64() * price;
+
+        let symbol = symbols
+            .get(&(tx.transaction_hash.clone(), tx.token_address.clone()))
+            .unwrap_or(&tx.token_address);
+        let message = templates::LARGE_TRANSACTION.render(&[
+            ("wallet", &label),
+            (
+                "direction",
+                if tx.amount.is_negative() {
+                    "sent"
+                } else {
+                    "received"
+                },
+            ),
+            ("amount", &tx.amount.abs().to_string()),
+            ("symbol", symbol),
+            ("value", &format!("${value_usd:.2}")),
+            ("hash", &tx.transaction_hash),
+        ]);
+
+        let mut conn = pool.acquire().await?;
+        let audience = Audience::LargeTransaction(value_usd);
+        if queue(&mut conn, user_id, audience, &message).await? > 0 {
+            notified += 1;
+        }
+    }
+
+    if notified > 0 {
+        info!(
+            "Queued notifications for {} large transactions of wallet {}",
+            notified, wallet_id
+        );
+    }
+    Ok(notified)
+}
+
+/// Error sending a message
+#[derive(Debug, Error)]
+pub enum SendError {
+    /// The request could not be sent or its response not read
+    #[error("{0}")]
+    Http(#[from] reqwest::Error),
+    /// The message could not be built, or the chat service or mail server refused it
+    #[error("{0}")]
+    Rejected(String),
+    /// No sender is configured for the channel kind
+    #[error("{} notifications are not configured", .0.as_str())]
+    NotConfigured(ChannelKind),
+}
+
+/// Result of a send attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SendOutcome {
+    /// The message was sent
+    Sent,
+    /// The notification was sent before or deleted; nothing was sent
+    Cancelled,
+    /// The attempt failed for the given reason
+    Failed(String),
+}
+
+/// The configured senders, one per channel kind
+#[derive(Debug, Clone, Default)]
+pub struct Notifier {
+    telegram: Option<TelegramSender>,
+    email: Option<EmailSender>,
+    secrets: SecretBox,
+}
+
+impl Notifier {
+    /// Creates the senders enabled in `config`
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            telegram: config
+                .telegram_bot_token
+                .as_ref()
+                .map(|token| TelegramSender::new(&config.telegram_api_url, token)),
+            email: config.smtp_url.as_ref().and_then(|url| {
+                EmailSender::new(url, &config.smtp_from)
+                    .inspect_err(|err| warn!("Email notifications are disabled: {}", err))
+                    .ok()
+            }),
+            secrets: SecretBox::default(),
+        }
+    }
+
+    /// Opens the stored targets of notifications with `secrets`
+    pub fn with_secrets(mut self, secrets: SecretBox) -> Self {
+        self.secrets = secrets;
+        self
+    }
+
+    /// Sends a queued notification once and records the outcome on it
+    ///
+    /// A failed notification stays pending while `retry_in` is given, and is marked
+    /// failed otherwise.
+    pub async fn deliver(
+        &self,
+        pool: &PgPool,
+        notification_id: Uuid,
+        retry_in: Option<Duration>,
+    ) -> Result<SendOutcome, sqlx::Error> {
+        let notification =
+            sqlx::query_as::<_, (Uuid, String, String, Option<String>, String, i32)>(
+                r#"
+                SELECT user_id, kind, target, subject, message, attempts
+                FROM notifications
+                WHERE id = $1 AND status <> 'sent'
+                "#,
+            )
+            .bind(notification_id)
+            .fetch_optional(pool)
+            .await?;
+        let Some((user_id, kind, target, subject, message, attempts)) = notification else {
+            return Ok(SendOutcome::Cancelled);
+        };
+        let target = self
+            .secrets
+            .open(&target, &Slot::channel_target(user_id, &kind))?;
+
+        let result = match kind.as_str() {
+            "telegram" => match &self.telegram {
+                Some(sender) => sender.send(&target, &message).await,
+                None => Err(SendError::NotConfigured(ChannelKind::Telegram)),
+            },
+            "email" => match &self.email {
+                Some(sender) => {
+                    let subject = subject.as_deref().unwrap_or("Degen notification");
+                    sender.send(&target, subject, &message).await
+                }
+                None => Err(SendError::NotConfigured(ChannelKind::Email)),
+            },
+            other => Err(SendError::Rejected(format!("Unknown channel kind {other}"))),
+        };
+
+        let attempts = attempts + 1;
+        let error = match result {
+            Ok(()) => {
+                sqlx::query(
+                    r#"
+                    UPDATE notifications
+                    SET status = 'sent', attempts = $2, last_error = NULL, sent_at = NOW()
+                    WHERE id = $1
+                    "#,
+                )
+                .bind(notification_id)
+                .bind(attempts)
+                .execute(pool)
+                .await?;
+                return Ok(SendOutcome::Sent);
+            }
+            Err(err) => err.to_string(),
+        };
+
+        let status = match retry_in {
+            Some(_) => {
+                debug!(
+                    "Notification {} failed, will retry: {}",
+                    notification_id, error
+                );
+                "pending"
+            }
+            None => {
+                warn!(
+                    "Giving up on notification {} after {} attempts: {}",
+                    notification_id, attempts, error
+                );
+                "failed"
+            }
+        };
+        sqlx::query(
+            "UPDATE notifications SET status = $2, attempts = $3, last_error = $4 WHERE id = $1",
+        )
+        .bind(notification_id)
+        .bind(status)
+        .bind(attempts)
+        .bind(&error)
+        .execute(pool)
+        .await?;
+
+        Ok(SendOutcome::Failed(error))
+    }
+}
@@ -0,0 +1,54 @@
+//! Telegram Bot API sender.
+
+use std::time::Duration;
+
+use serde_json::{json, Value};
+
+use super::SendError;
+
+/// Sends messages through the Telegram Bot API
+#[derive(Debug, Clone)]
+pub struct TelegramSender {
+    http: reqwest::Client,
+    api_url: String,
+    bot_token: String,
+}
+
+impl TelegramSender {
+    /// Creates a sender for the bot with `bot_token`, calling the Bot API at `api_url`
+    pub fn new(api_url: impl Into<String>, bot_token: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .expect("Failed to build HTTP client"),
+            api_url: api_url.into().trim_end_matches('/').to_string(),
+            bot_token: bot_token.into(),
+        }
+    }
+
+    /// Sends `text` to the chat `chat_id`
+    pub async fn send(&self, chat_id: &str, text: &str) -> Result<(), SendError> {
+        let response = self
+            .http
+            .post(format!(
+                "{}/bot{}/sendMessage",
+                self.api_url, self.bot_token
+            ))
+            .json(&json!({ "chat_id": chat_id, "text": text }))
+            .send()
+            .await?;
+
+        let status = response.status();
+        if status.is_success() {
+            return Ok(());
+        }
+        let description = response
+            .json::<Value>()
+            .await
+            .ok()
+            .and_then(|body| body["description"].as_str().map(str::to_string))
+            .unwrap_or_else(|| status.to_string());
+        Err(SendError::Rejected(format!("Telegram: {description}")))
+    }
+}
@@ -0,0 +1,104 @@
+//! Message templates.
+//!
+//! Templates are plain text with `{{name}}` placeholders, filled in when a notification
+//! is queued. Every channel sends the body; email also uses the subject.
+
+/// Subject and body of a kind of message, with `{{name}}` placeholders
+#[derive(Debug, Clone, Copy)]
+pub struct Template {
+    /// Subject line, used by email
+    pub subject: &'static str,
+    /// Message text
+    pub body: &'static str,
+}
+
+/// A template filled in with values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Rendered {
+    /// Subject line, used by email
+    pub subject: String,
+    /// Message text
+    pub body: String,
+}
+
+/// An alert fired; `message` describes the firing
+pub const ALERT: Template = Template {
+    subject: "Degen alert fired",
+    body: "{{message}}",
+};
+
+/// A detected transaction reached a channel's large transaction threshold
+pub const LARGE_TRANSACTION: Template = Template {
+    subject: "Large transaction in {{wallet}}",
+    body: "{{wallet}} {{direction}} {{amount}} {{symbol}} ({{value}}) in transaction {{hash}}",
+};
+
+/// A detected transaction reached the server's whale threshold
+pub const WHALE_MOVEMENT: Template = Template {
+    subject: "Whale movement in {{wallet}}: {{value}}",
+    body: "Whale movement: {{wallet}} {{direction}} {{amount}} {{symbol}} ({{value}}) in \
+           transaction {{hash}}",
+};
+
+/// Sign-ins to the account were locked after repeated failures
+pub const ACCOUNT_LOCKED: Template = Template {
+    subject: "Sign-ins to your Degen account are locked",
+    body: "After {{failures}} failed sign-ins with {{address}} from {{client}}, signing in \
+           from there is locked until {{until}}. If this was not you, someone may be trying \
+           to break into your account.",
+};
+
+/// Sign-ins to the account from every client were locked after repeated failures
+pub const ACCOUNT_LOCKED_EVERYWHERE: Template = Template {
+    subject: "Sign-ins to your Degen account are locked",
+    body: "After {{failures}} failed sign-ins with {{address}} from several clients, \
+           signing in is locked everywhere until {{until}}. Someone may be trying to break \
+           into your account.",
+};
+
+/// Daily or weekly portfolio report; `movers` and `wallets` are preformatted lists
+pub const REPORT: Template = Template {
+    subject: "Your {{period}} Degen report for {{date}}: {{total}}",
+    body: "Your portfolio is worth {{total}} ({{change}} since {{since}}).\n\
+           Realized PnL: {{realized_pnl}}\n\
+           \n\
+           {{movers}}Wallets:\n\
+           {{wallets}}\n\
+           \n\
+           You receive this report because {{period}} reports are enabled on this channel.",
+};
+
+impl Template {
+    /// Fills in the subject and body with `values`
+    pub fn render(&self, values: &[(&str, &str)]) -> Rendered {
+        Rendered {
+            subject: render(self.subject, values),
+            body: render(self.body, values),
+        }
+    }
+}
+
+/// Replaces every `{{name}}` in `template` with its value
+///
+/// Placeholders without a value are kept as they are. Values are inserted verbatim and
+/// not expanded again, so they may contain braces.
+pub fn render(template: &str, values: &[(&str, &str)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        rendered.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        let name = after[..end].trim();
+        match values.iter().find(|(key, _)| *key == name) {
+            Some((_, value)) => rendered.push_str(value),
+            None => rendered.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    rendered.push_str(rest);
+    rendered
+}
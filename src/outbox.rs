@@ -0,0 +1,189 @@
+//! Transactional outbox of events for webhooks and notifications.
+//!
+//! A change others must hear about, such as newly recorded transactions or a flagged
+//! whale movement, records an [`OutboxEvent`] in the `outbox` table in the same
+//! database transaction as the change itself. The relay, run by the
+//! [job worker](crate::jobs) before every batch of jobs, fans pending events out to
+//! webhook deliveries, notification checks and whale detection, and marks them
+//! processed. An event is therefore never lost when the process stops between
+//! committing a change and queuing its deliveries; it is relayed at least once.
+
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::{PgConnection, PgPool};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+use crate::webhooks::{self, DetectedTransaction};
+use crate::whales::{self, WhaleEvent};
+
+/// Attempts at relaying an event before it is given up on
+const MAX_ATTEMPTS: i32 = 5;
+
+/// Maximum number of events relayed per cycle
+const BATCH_SIZE: i64 = 100;
+
+/// How long processed events are kept before being pruned
+const PROCESSED_RETENTION: Duration = Duration::from_secs(24 * 3600);
+
+/// An event awaiting fan-out
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutboxEvent {
+    /// Transactions not seen before were recorded for the wallet
+    TransactionsDetected {
+        /// The newly recorded transaction rows
+        transactions: Vec<DetectedTransaction>,
+        /// Value from which the transactions are checked for whale movements
+        whale_threshold_usd: Option<f64>,
+    },
+    /// A newly recorded transaction was flagged as a whale movement
+    WhaleMovement {
+        /// The flagged movement
+        event: WhaleEvent,
+    },
+}
+
+impl OutboxEvent {
+    /// Name of the event as stored in `outbox.event_type`
+    pub fn event_type(&self) -> &'static str {
+        match self {
+            Self::TransactionsDetected { .. } => "transactions_detected",
+            Self::WhaleMovement { .. } => "whale_movement",
+        }
+    }
+}
+
+/// Records an event of a wallet for the relay
+///
+/// Takes a connection so the event commits or rolls back with the change it announces.
+pub async fn record(
+    conn: &mut PgConnection,
+    wallet_id: Uuid,
+    event: &OutboxEvent,
+) -> Result<(), sqlx::Error> {
+    let payload = serde_json::to_value(event).map_err(|err| sqlx::Error::Encode(Box::new(err)))?;
+    sqlx::query("INSERT INTO outbox (id, wallet_id, event_type, payload) VALUES ($1, $2, $3, $4)")
+        .bind(Uuid::now_v7())
+        .bind(wallet_id)
+        .bind(event.event_type())
+        .bind(payload)
+        .execute(conn)
+        .await?;
+    Ok(())
+}
+
+/// Pending event claimed by the relay
+#[derive(sqlx::FromRow)]
+struct PendingEvent {
+    id: Uuid,
+    wallet_id: Uuid,
+    payload: Value,
+    attempts: i32,
+}
+
+/// Fans a batch of pending events out and returns how many were relayed
+///
+/// Events are claimed with `FOR UPDATE SKIP LOCKED`, so several instances relay
+/// without handling an event twice. A failed event is tried again by later cycles
+/// until it runs out of attempts.
+pub async fn relay(pool: &PgPool) -> Result<usize, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let pending = sqlx::query_as::<_, PendingEvent>(
+        r#"
+        SELECT id, wallet_id, payload, attempts
+        FROM outbox
+        WHERE processed_at IS NULL
+        ORDER BY created_at
+        LIMIT $1
+        FOR UPDATE SKIP LOCKED
+        "#,
+    )
+    .bind(BATCH_SIZE)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    let mut relayed = 0;
+    for event in pending {
+        let (error, retryable) = match serde_json::from_value::<OutboxEvent>(event.payload) {
+            Ok(payload) => (
+                dispatch(pool, event.wallet_id, &payload)
+                    .await
+                    .err()
+                    .map(|err| err.to_string()),
+                true,
+            ),
+            // Not retried: another attempt cannot make the payload readable
+            Err(err) => (Some(format!("Unreadable event: {err}")), false),
+        };
+
+        let attempts = event.attempts + 1;
+        let processed = match &error {
+            None => {
+                relayed += 1;
+                true
+            }
+            Some(error) if retryable && attempts < MAX_ATTEMPTS => {
+                debug!("Relaying outbox event {} failed: {}", event.id, error);
+                false
+            }
+            Some(error) => {
+                warn!(
+                    "Giving up on outbox event {} after {} attempts: {}",
+                    event.id, attempts, error
+                );
+                true
+            }
+        };
+        sqlx::query(
+            r#"
+            UPDATE outbox
+            SET attempts = $2,
+                last_error = $3,
+                processed_at = CASE WHEN $4 THEN NOW() END
+            WHERE id = $1
+            "#,
+        )
+        .bind(event.id)
+        .bind(attempts)
+        .bind(&error)
+        .bind(processed)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok(relayed)
+}
+
+/// Queues the webhook deliveries, notifications and follow-up jobs of an event
+async fn dispatch(pool: &PgPool, wallet_id: Uuid, event: &OutboxEvent) -> Result<(), sqlx::Error> {
+    match event {
+        OutboxEvent::TransactionsDetected {
+            transactions,
+            whale_threshold_usd,
+        } => {
+            webhooks::notify_transactions_detected(
+                pool,
+                wallet_id,
+                transactions,
+                *whale_threshold_usd,
+            )
+            .await
+        }
+        OutboxEvent::WhaleMovement { event } => whales::notify(pool, event).await,
+    }
+}
+
+/// Deletes processed events older than the retention period and returns how many
+pub async fn prune(pool: &PgPool) -> Result<u64, sqlx::Error> {
+    let result =
+        sqlx::query("DELETE FROM outbox WHERE processed_at < NOW() - make_interval(secs => $1)")
+            .bind(PROCESSED_RETENTION.as_secs_f64())
+            .execute(pool)
+            .await?;
+
+    Ok(result.rows_affected())
+}
@@ -0,0 +1,115 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::AppError;
+
+/// Default number of items per page
+pub const DEFAULT_PER_PAGE: i64 = 50;
+
+/// Maximum number of items per page
+pub const MAX_PER_PAGE: i64 = 100;
+
+/// Position in a list ordered newest first by `(created_at, id)`
+///
+/// Cursors are handed to clients as opaque strings; the next page holds the rows
+/// strictly after the cursor, so rows inserted mid-scan are neither skipped nor
+/// repeated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cursor {
+    /// Creation time of the last row of the previous page
+    pub created_at: DateTime<Utc>,
+    /// ID of the last row of the previous page, breaking ties on `created_at`
+    pub id: Uuid,
+}
+
+impl Cursor {
+    /// Creates a cursor pointing after the given row
+    pub fn new(created_at: DateTime<Utc>, id: Uuid) -> Self {
+        Self { created_at, id }
+    }
+
+    /// Encodes the cursor as an opaque base58 string
+    pub fn encode(&self) -> String {
+        let raw = format!("{}:{}", self.created_at.timestamp_micros(), self.id);
+        bs58::encode(raw).into_string()
+    }
+
+    /// Decodes a cursor previously returned by [`Cursor::encode`]
+    pub fn decode(cursor: &str) -> Result<Self, AppError> {
+        let invalid = || AppError::BadRequest("Invalid pagination cursor".to_string());
+
+        let raw = bs58::decode(cursor).into_vec().map_err(|_| invalid())?;
+        let raw = String::from_utf8(raw).map_err(|_| invalid())?;
+        let (micros, id) = raw.split_once(':').ok_or_else(invalid)?;
+
+        let created_at = micros
+            .parse::<i64>()
+            .ok()
+            .and_then(DateTime::from_timestamp_micros)
+            .ok_or_else(invalid)?;
+        let id = id.parse::<Uuid>().map_err(|_| invalid())?;
+
+        Ok(Self { created_at, id })
+    }
+}
+
+/// Direction of a sorted list
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    /// Smallest first
+    Asc,
+    /// Largest first
+    #[default]
+    Desc,
+}
+
+impl SortOrder {
+    /// SQL keyword of the direction
+    pub fn as_sql(&self) -> &'static str {
+        match self {
+            Self::Asc => "ASC",
+            Self::Desc => "DESC",
+        }
+    }
+}
+
+/// Query parameters of cursor-paginated list endpoints
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CursorParams {
+    /// Cursor returned as `next_cursor` by the previous page; omit for the first page
+    pub cursor: Option<String>,
+    /// Number of items per page (max 100)
+    #[serde(default = "default_per_page")]
+    pub per_page: i64,
+}
+
+/// Default for `per_page` query parameters
+pub fn default_per_page() -> i64 {
+    DEFAULT_PER_PAGE
+}
+
+/// Clamps a requested page size to `1..=MAX_PER_PAGE`
+pub fn clamp_per_page(per_page: i64) -> i64 {
+    per_page.clamp(1, MAX_PER_PAGE)
+}
+
+/// Splits one page off rows fetched with `LIMIT per_page + 1`
+///
+/// Returns the page and the cursor of the next page, if there is one.
+pub fn next_page<T>(
+    mut rows: Vec<T>,
+    per_page: i64,
+    key: impl Fn(&T) -> Cursor,
+) -> (Vec<T>, Option<String>) {
+    let per_page = per_page as usize;
+    if rows.len() <= per_page {
+        return (rows, None);
+    }
+
+    rows.truncate(per_page);
+    let next_cursor = rows.last().map(|row| key(row).encode());
+    (rows, next_cursor)
+}
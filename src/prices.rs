@@ -0,0 +1,275 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::Deserialize;
+use thiserror::Error;
+use tracing::debug;
+
+use crate::cache::{self, Cache, MokaCache};
+use crate::resilience::{
+    CircuitBreaker, CircuitOpen, DEFAULT_FAILURE_THRESHOLD, DEFAULT_OPEN_DURATION,
+};
+use crate::AppError;
+
+/// Default Jupiter price API endpoint
+pub const DEFAULT_PRICE_API_URL: &str = "https://lite-api.jup.ag/price/v3";
+
+/// Errors that can occur while fetching token prices
+#[derive(Debug, Error)]
+pub enum PriceError {
+    /// The HTTP request to the price API failed
+    #[error("Price request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The price API is failing and requests are rejected until it recovers
+    #[error(transparent)]
+    Unavailable(#[from] CircuitOpen),
+}
+
+impl From<PriceError> for AppError {
+    fn from(err: PriceError) -> Self {
+        match err {
+            PriceError::Http(err) => err.into(),
+            PriceError::Unavailable(open) => open.into(),
+        }
+    }
+}
+
+/// A source of current USD prices for SPL token mints
+///
+/// Implementations return prices only for the mints they know about; mints
+/// missing from the result have no known price.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    /// Fetches the current USD price of each of the given mints
+    async fn prices_usd(&self, mints: &[String]) -> Result<HashMap<String, f64>, PriceError>;
+
+    /// Fetches the 24-hour price change of each of the given mints, in percent
+    ///
+    /// Sources that do not track price history return no changes.
+    async fn price_changes_24h(
+        &self,
+        _mints: &[String],
+    ) -> Result<HashMap<String, f64>, PriceError> {
+        Ok(HashMap::new())
+    }
+}
+
+/// Price source backed by the Jupiter price API
+///
+/// Requests are guarded by a [`CircuitBreaker`], so an outage of the API answers
+/// with [`PriceError::Unavailable`] rather than a timeout per request.
+#[derive(Debug, Clone)]
+pub struct JupiterPriceSource {
+    http: reqwest::Client,
+    url: String,
+    breaker: Arc<CircuitBreaker>,
+}
+
+/// Per-mint entry of a Jupiter price API response
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterPrice {
+    usd_price: f64,
+    price_change_24h: Option<f64>,
+}
+
+impl JupiterPriceSource {
+    /// Jupiter accepts at most this many mints per request
+    const MAX_IDS_PER_REQUEST: usize = 50;
+
+    /// Creates a price source for the given Jupiter price API endpoint
+    pub fn new(url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            url: url.into(),
+            breaker: Arc::new(CircuitBreaker::new(
+                "Price API",
+                DEFAULT_FAILURE_THRESHOLD,
+                DEFAULT_OPEN_DURATION,
+            )),
+        }
+    }
+
+    /// Guards requests with the given breaker instead of the default one
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Arc::new(breaker);
+        self
+    }
+
+    /// Fetches the Jupiter price entries of the given mints
+    async fn fetch(&self, mints: &[String]) -> Result<HashMap<String, JupiterPrice>, PriceError> {
+        self.breaker
+            .call(|| self.fetch_chunks(mints), |_| true)
+            .await?
+    }
+
+    /// Requests the price entries in chunks the API accepts
+    async fn fetch_chunks(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, JupiterPrice>, PriceError> {
+        let mut prices = HashMap::new();
+
+        for chunk in mints.chunks(Self::MAX_IDS_PER_REQUEST) {
+            let response: HashMap<String, Option<JupiterPrice>> = self
+                .http
+                .get(&self.url)
+                .query(&[("ids", chunk.join(","))])
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            prices.extend(
+                response
+                    .into_iter()
+                    .filter_map(|(mint, price)| Some((mint, price?))),
+            );
+        }
+
+        Ok(prices)
+    }
+}
+
+#[async_trait]
+impl PriceSource for JupiterPriceSource {
+    async fn prices_usd(&self, mints: &[String]) -> Result<HashMap<String, f64>, PriceError> {
+        Ok(self
+            .fetch(mints)
+            .await?
+            .into_iter()
+            .map(|(mint, price)| (mint, price.usd_price))
+            .collect())
+    }
+
+    async fn price_changes_24h(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, f64>, PriceError> {
+        Ok(self
+            .fetch(mints)
+            .await?
+            .into_iter()
+            .filter_map(|(mint, price)| Some((mint, price.price_change_24h?)))
+            .collect())
+    }
+}
+
+/// Wraps a price source and caches each mint's price for a fixed TTL
+pub struct CachedPriceSource<S> {
+    inner: S,
+    ttl: Duration,
+    cache: Arc<dyn Cache>,
+}
+
+impl<S: PriceSource> CachedPriceSource<S> {
+    /// Creates a cache in front of `inner` that keeps prices for `ttl` in process
+    /// memory
+    pub fn new(inner: S, ttl: Duration) -> Self {
+        Self {
+            inner,
+            ttl,
+            cache: Arc::new(MokaCache::default()),
+        }
+    }
+
+    /// Keeps the prices in `cache` instead, e.g. one shared with other instances
+    pub fn with_cache(mut self, cache: Arc<dyn Cache>) -> Self {
+        self.cache = cache;
+        self
+    }
+}
+
+/// Cache key of a mint's USD price
+fn price_key(mint: &str) -> String {
+    format!("price_usd:{mint}")
+}
+
+#[async_trait]
+impl<S: PriceSource> PriceSource for CachedPriceSource<S> {
+    async fn prices_usd(&self, mints: &[String]) -> Result<HashMap<String, f64>, PriceError> {
+        let mut prices = HashMap::new();
+        let mut missing = Vec::new();
+
+        for mint in mints {
+            match cache::get_json::<f64>(self.cache.as_ref(), &price_key(mint)).await {
+                Some(price) => {
+                    prices.insert(mint.clone(), price);
+                }
+                None => missing.push(mint.clone()),
+            }
+        }
+
+        if !missing.is_empty() {
+            debug!("Fetching {} uncached token prices", missing.len());
+            let fetched = self.inner.prices_usd(&missing).await?;
+
+            for (mint, price) in fetched {
+                cache::set_json(self.cache.as_ref(), &price_key(&mint), &price, self.ttl).await;
+                prices.insert(mint, price);
+            }
+        }
+
+        Ok(prices)
+    }
+
+    /// Price changes are only requested for single-token views, so they are not cached
+    async fn price_changes_24h(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, f64>, PriceError> {
+        self.inner.price_changes_24h(mints).await
+    }
+}
+
+/// Price source returning a fixed set of prices, for tests and offline use
+#[derive(Debug, Clone, Default)]
+pub struct StaticPriceSource {
+    prices: HashMap<String, f64>,
+    changes_24h: HashMap<String, f64>,
+}
+
+impl StaticPriceSource {
+    /// Creates a price source that always returns the given prices
+    pub fn new(prices: HashMap<String, f64>) -> Self {
+        Self {
+            prices,
+            changes_24h: HashMap::new(),
+        }
+    }
+
+    /// Also returns the given 24-hour price changes, in percent
+    pub fn with_changes_24h(mut self, changes_24h: HashMap<String, f64>) -> Self {
+        self.changes_24h = changes_24h;
+        self
+    }
+}
+
+#[async_trait]
+impl PriceSource for StaticPriceSource {
+    async fn prices_usd(&self, mints: &[String]) -> Result<HashMap<String, f64>, PriceError> {
+        Ok(mints
+            .iter()
+            .filter_map(|mint| Some((mint.clone(), *self.prices.get(mint)?)))
+            .collect())
+    }
+
+    async fn price_changes_24h(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, f64>, PriceError> {
+        Ok(mints
+            .iter()
+            .filter_map(|mint| Some((mint.clone(), *self.changes_24h.get(mint)?)))
+            .collect())
+    }
+}
@@ -0,0 +1,204 @@
+//! Pump.fun bonding curve instructions.
+//!
+//! Pump.fun tokens launch on a bonding curve the program itself makes a market on.
+//! Buys and sells against the curve are decoded from the Anchor discriminators that
+//! prefix the instruction data, so they are classified as swaps like trades on any
+//! AMM. Once the curve completes, the token's liquidity is migrated to an AMM and the
+//! token is `graduated`; until then it is `bonding`. The status is recorded in the
+//! `tokens` table as transactions involving the token are ingested.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+/// The pump.fun bonding curve program
+pub const PUMP_FUN_PROGRAM_ID: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+
+/// Discriminators of `buy` and `buy_exact_sol_in`
+const BUY_DISCRIMINATORS: [[u8; 8]; 2] = [
+    [102, 6, 61, 18, 1, 218, 235, 234],
+    [56, 252, 116, 8, 158, 223, 205, 95],
+];
+
+/// Discriminator of `sell`
+const SELL_DISCRIMINATOR: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+
+/// Discriminators of `withdraw`, which moved a completed curve's liquidity to Raydium,
+/// and `migrate`, which moves it to PumpSwap
+const MIGRATE_DISCRIMINATORS: [[u8; 8]; 2] = [
+    [183, 18, 70, 156, 148, 109, 161, 34],
+    [155, 234, 231, 146, 236, 158, 162, 30],
+];
+
+/// Position of the mint among the accounts of every decoded instruction
+const MINT_ACCOUNT_INDEX: usize = 2;
+
+/// What a pump.fun instruction did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PumpAction {
+    /// Bought tokens from the bonding curve
+    Buy,
+    /// Sold tokens to the bonding curve
+    Sell,
+    /// Migrated the completed curve's liquidity to an AMM
+    Migrate,
+}
+
+/// A decoded pump.fun instruction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PumpInstruction {
+    /// What the instruction did
+    pub action: PumpAction,
+    /// Mint of the token traded or migrated
+    pub mint: String,
+}
+
+impl PumpInstruction {
+    /// Decodes an instruction as listed by RPC or Helius: its `programId`, its
+    /// `accounts` and its base58 `data`
+    ///
+    /// Returns `None` for instructions of other programs and for pump.fun instructions
+    /// other than trades and migrations.
+    pub fn decode(instruction: &Value) -> Option<Self> {
+        if instruction["programId"].as_str() != Some(PUMP_FUN_PROGRAM_ID) {
+            return None;
+        }
+        let data = bs58::decode(instruction["data"].as_str()?)
+            .into_vec()
+            .ok()?;
+        let discriminator: [u8; 8] = data.get(..8)?.try_into().ok()?;
+
+        let action = if BUY_DISCRIMINATORS.contains(&discriminator) {
+            PumpAction::Buy
+        } else if discriminator == SELL_DISCRIMINATOR {
+            PumpAction::Sell
+        } else if MIGRATE_DISCRIMINATORS.contains(&discriminator) {
+            PumpAction::Migrate
+        } else {
+            return None;
+        };
+        let mint = instruction["accounts"][MINT_ACCOUNT_INDEX].as_str()?;
+
+        Some(Self {
+            action,
+            mint: mint.to_string(),
+        })
+    }
+
+    /// Whether the instruction traded against the bonding curve
+    pub fn is_trade(&self) -> bool {
+        matches!(self.action, PumpAction::Buy | PumpAction::Sell)
+    }
+
+    /// Status of the token after the instruction
+    pub fn status(&self) -> BondingStatus {
+        match self.action {
+            PumpAction::Buy | PumpAction::Sell => BondingStatus::Bonding,
+            PumpAction::Migrate => BondingStatus::Graduated,
+        }
+    }
+}
+
+/// Where a pump.fun token trades
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BondingStatus {
+    /// Still trading on its bonding curve
+    Bonding,
+    /// Migrated to an AMM after its curve completed
+    Graduated,
+}
+
+impl BondingStatus {
+    /// Name of the status as stored and returned by the API
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Bonding => "bonding",
+            Self::Graduated => "graduated",
+        }
+    }
+
+    /// Status with the given stored name, if there is one
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Bonding, Self::Graduated]
+            .into_iter()
+            .find(|status| status.as_str() == name)
+    }
+}
+
+/// Decodes the pump.fun instructions of a `jsonParsed` transaction, inner instructions
+/// included
+pub fn instructions(transaction: &Value) -> Vec<PumpInstruction> {
+    let inner = transaction["meta"]["innerInstructions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .flat_map(|group| group["instructions"].as_array().into_iter().flatten());
+    transaction["transaction"]["message"]["instructions"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .chain(inner)
+        .filter_map(PumpInstruction::decode)
+        .collect()
+}
+
+/// Decodes the pump.fun instructions of an enhanced transaction's `instructions`, which
+/// nest their inner instructions
+pub fn enhanced_instructions(instructions: &[Value]) -> Vec<PumpInstruction> {
+    instructions
+        .iter()
+        .flat_map(|instruction| {
+            std::iter::once(instruction).chain(
+                instruction["innerInstructions"]
+                    .as_array()
+                    .into_iter()
+                    .flatten(),
+            )
+        })
+        .filter_map(PumpInstruction::decode)
+        .collect()
+}
+
+/// Records the bonding status the instructions leave their tokens in
+///
+/// Graduation is final, so a graduated token stays graduated whatever is recorded
+/// later. Tokens not cached yet are added with stale metadata, to be fetched when
+/// first needed.
+pub async fn record_status(
+    pool: &PgPool,
+    instructions: &[PumpInstruction],
+) -> Result<(), sqlx::Error> {
+    for instruction in instructions {
+        sqlx::query(
+            r#"
+            INSERT INTO tokens (mint, bonding_status, fetched_at)
+            VALUES ($1, $2, '-infinity')
+            ON CONFLICT (mint) DO UPDATE
+            SET bonding_status = EXCLUDED.bonding_status
+            WHERE tokens.bonding_status IS DISTINCT FROM 'graduated'
+              AND tokens.bonding_status IS DISTINCT FROM EXCLUDED.bonding_status
+            "#,
+        )
+        .bind(&instruction.mint)
+        .bind(instruction.status().as_str())
+        .execute(pool)
+        .await?;
+    }
+
+    Ok(())
+}
+
+/// Bonding status of a token, or `None` if it was not seen trading on pump.fun
+pub async fn status(pool: &PgPool, mint: &str) -> Result<Option<BondingStatus>, sqlx::Error> {
+    let status = sqlx::query_scalar::<_, Option<String>>(
+        "SELECT bonding_status FROM tokens WHERE mint = $1",
+    )
+    .bind(mint)
+    .fetch_optional(pool)
+    .await?
+    .flatten();
+
+    Ok(status.as_deref().and_then(BondingStatus::from_name))
+}
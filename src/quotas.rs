@@ -0,0 +1,394 @@
+//! Plan-based quotas of tracked wallets and on-demand syncs.
+//!
+//! Every user is on a [`Plan`], `DEFAULT_PLAN` unless an admin or a
+//! [Stripe subscription](crate::billing) assigned another one, which limits how many
+//! wallets they track and how many syncs they request per UTC day. The limits of each
+//! plan are kept in the `plans` table and changed through `PUT /admin/plans/{plan}`;
+//! admins can also override either limit for a single user through
+//! `PUT /admin/users/{id}/quota`. Adding a wallet beyond the limit answers `403` with
+//! the code `wallet_quota_exceeded` until the user deletes one through
+//! `DELETE /wallets/{id}`, and syncing beyond it `429` with the code
+//! `sync_quota_exceeded`. Demo mode has no quotas.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::error::ValidationErrors;
+use crate::repository::WalletFilter;
+use crate::{AppError, AppState};
+
+/// A set of quotas users can be put on
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Plan {
+    /// Few wallets and syncs, for trying the API out
+    Free,
+    /// Enough for active traders and small bots
+    Pro,
+    /// No limits; the default, so self-hosted instances are not limited
+    #[default]
+    Unlimited,
+}
+
+impl Plan {
+    /// Every plan, from the most to the least limited
+    pub const ALL: [Plan; 3] = [Self::Free, Self::Pro, Self::Unlimited];
+
+    /// Name of the plan in configuration and the admin endpoints
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Free => "free",
+            Self::Pro => "pro",
+            Self::Unlimited => "unlimited",
+        }
+    }
+}
+
+impl fmt::Display for Plan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Plan {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim().to_ascii_lowercase();
+        Self::ALL
+            .into_iter()
+            .find(|plan| plan.as_str() == s)
+            .ok_or_else(|| format!("Unknown plan {s:?}"))
+    }
+}
+
+/// A user's quotas and how much of them is used
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct UserQuota {
+    /// ID of the user
+    pub user_id: Uuid,
+    /// Plan the user is on
+    pub plan: Plan,
+    /// Most wallets the user tracks, or `null` for no limit
+    pub max_wallets: Option<i64>,
+    /// Most syncs the user requests per UTC day, or `null` for no limit
+    pub max_syncs_per_day: Option<i64>,
+    /// Wallets the user tracks
+    pub wallets: i64,
+    /// Syncs the user requested today
+    pub syncs_today: i64,
+}
+
+/// Request body assigning a user's plan and quota overrides
+///
+/// Replaces the previous assignment: omitted or `null` fields fall back to
+/// `DEFAULT_PLAN` and the limits of the plan.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct SetUserQuota {
+    /// Plan to put the user on
+    pub plan: Option<Plan>,
+    /// Most wallets the user tracks, instead of the plan's limit
+    pub max_wallets: Option<i64>,
+    /// Most syncs the user requests per UTC day, instead of the plan's limit
+    pub max_syncs_per_day: Option<i64>,
+}
+
+impl SetUserQuota {
+    /// Checks that the overrides are not negative
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        for (field, limit) in [
+            ("max_wallets", self.max_wallets),
+            ("max_syncs_per_day", self.max_syncs_per_day),
+        ] {
+            if limit.is_some_and(|limit| limit < 0) {
+                errors.add(field, "Must not be negative");
+            }
+        }
+        errors.into_result()
+    }
+}
+
+/// Limits in effect for a user
+struct Limits {
+    plan: Plan,
+    max_wallets: Option<i64>,
+    max_syncs_per_day: Option<i64>,
+}
+
+/// Loads the plan and overrides of a user, or `None` if there is no such user
+async fn limits(state: &AppState, user_id: Uuid) -> Result<Option<Limits>, AppError> {
+    let row = sqlx::query_as::<_, (Option<String>, Option<i64>, Option<i64>)>(
+        r#"
+        SELECT u.plan,
+               COALESCE(u.max_wallets, p.max_wallets),
+               COALESCE(u.max_syncs_per_day, p.max_syncs_per_day)
+        FROM users u
+        LEFT JOIN plans p ON p.name = COALESCE(u.plan, $2)
+        WHERE u.id = $1
+        "#,
+    )
+    .bind(user_id)
+    .bind(state.config.default_plan.as_str())
+    .fetch_optional(&state.db_pool)
+    .await?;
+
+    Ok(row.map(|(plan, max_wallets, max_syncs_per_day)| Limits {
+        // The column only takes the plans' names, so an unknown one is not expected
+        plan: plan
+            .and_then(|plan| plan.parse().ok())
+            .unwrap_or(state.config.default_plan),
+        max_wallets,
+        max_syncs_per_day,
+    }))
+}
+
+/// A claim on one more wallet of a user, held until the wallet is stored
+///
+/// Holds a lock on the user's row, so concurrent additions by the same user wait for
+/// each other instead of all passing the check. Dropping it without
+/// [`WalletSlot::release`] releases the lock as well.
+#[derive(Debug)]
+pub struct WalletSlot(Option<Transaction<'static, Postgres>>);
+
+impl WalletSlot {
+    /// Releases the lock once the wallet is stored
+    pub async fn release(self) -> Result<(), AppError> {
+        if let Some(tx) = self.0 {
+            tx.commit().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Claims a slot for another wallet of the user, failing with `wallet_quota_exceeded`
+/// if the user may not track another wallet
+pub async fn reserve_wallet(state: &AppState, user_id: Uuid) -> Result<WalletSlot, AppError> {
+    if state.config.is_demo() {
+        return Ok(WalletSlot(None));
+    }
+    let Some(Limits {
+        plan,
+        max_wallets: Some(max_wallets),
+        ..
+    }) = limits(state, user_id).await?
+    else {
+        return Ok(WalletSlot(None));
+    };
+
+    // NO KEY UPDATE still lets the wallet insert check its foreign key to the user
+    let mut tx = state.db_pool.begin().await?;
+    sqlx::query("SELECT 1 FROM users WHERE id = $1 FOR NO KEY UPDATE")
+        .bind(user_id)
+        .execute(&mut *tx)
+        .await?;
+    // Counted on the primary, as a replica may not have the latest wallets yet
+    let wallets = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM wallets WHERE user_id = $1")
+        .bind(user_id)
+        .fetch_one(&mut *tx)
+        .await?;
+    if wallets >= max_wallets {
+        return Err(AppError::WalletQuotaExceeded(format!(
+            "The {plan} plan tracks at most {max_wallets} wallets; remove one to add another"
+        )));
+    }
+
+    Ok(WalletSlot(Some(tx)))
+}
+
+/// Counts a sync requested by the user, failing with `sync_quota_exceeded` if
+/// today's syncs are used up
+pub async fn consume_sync(state: &AppState, user_id: Uuid) -> Result<(), AppError> {
+    if state.config.is_demo() {
+        return Ok(());
+    }
+    let Some(limits) = limits(state, user_id).await? else {
+        return Ok(());
+    };
+
+    let counted = sqlx::query_scalar::<_, i64>(
+        r#"
+        INSERT INTO sync_usage (user_id, day, syncs)
+        SELECT $1, (NOW() AT TIME ZONE 'UTC')::DATE, 1
+        WHERE $2::BIGINT IS NULL OR $2 > 0
+        ON CONFLICT (user_id, day) DO UPDATE SET syncs = sync_usage.syncs + 1
+        WHERE $2::BIGINT IS NULL OR sync_usage.syncs < $2
+        RETURNING syncs
+        "#,
+    )
+    .bind(user_id)
+    .bind(limits.max_syncs_per_day)
+    .fetch_optional(&state.db_pool)
+    .await?;
+
+    match (counted, limits.max_syncs_per_day) {
+        (None, Some(max_syncs)) => Err(AppError::SyncQuotaExceeded(format!(
+            "The {} plan allows {max_syncs} syncs per day; the quota resets at midnight UTC",
+            limits.plan
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// A user's quotas and usage, or `None` if there is no such user
+pub async fn get(state: &AppState, user_id: Uuid) -> Result<Option<UserQuota>, AppError> {
+    let Some(limits) = limits(state, user_id).await? else {
+        return Ok(None);
+    };
+
+    let wallets = state
+        .wallets
+        .count(user_id, &WalletFilter::default())
+        .await?;
+    let syncs_today = sqlx::query_scalar::<_, i64>(
+        "SELECT syncs FROM sync_usage WHERE user_id = $1 AND day = (NOW() AT TIME ZONE 'UTC')::DATE",
+    )
+    .bind(user_id)
+    .fetch_optional(&state.db_pool)
+    .await?
+    .unwrap_or(0);
+
+    Ok(Some(UserQuota {
+        user_id,
+        plan: limits.plan,
+        max_wallets: limits.max_wallets,
+        max_syncs_per_day: limits.max_syncs_per_day,
+        wallets,
+        syncs_today,
+    }))
+}
+
+/// Assigns a user's plan and overrides, returning the resulting quotas
+///
+/// Returns `None` if there is no such user.
+pub async fn set(
+    state: &AppState,
+    user_id: Uuid,
+    quota: &SetUserQuota,
+) -> Result<Option<UserQuota>, AppError> {
+    let updated = sqlx::query(
+        "UPDATE users SET plan = $2, max_wallets = $3, max_syncs_per_day = $4 WHERE id = $1",
+    )
+    .bind(user_id)
+    .bind(quota.plan.map(|plan| plan.as_str()))
+    .bind(quota.max_wallets)
+    .bind(quota.max_syncs_per_day)
+    .execute(&state.db_pool)
+    .await?;
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+
+    get(state, user_id).await
+}
+
+/// Limits of a plan and the Stripe price it is sold at
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct PlanLimits {
+    /// The plan
+    pub plan: Plan,
+    /// Most wallets a user on the plan tracks, or `null` for no limit
+    pub max_wallets: Option<i64>,
+    /// Most syncs a user on the plan requests per UTC day, or `null` for no limit
+    pub max_syncs_per_day: Option<i64>,
+    /// Stripe price whose active subscriptions put users on the plan
+    pub stripe_price_id: Option<String>,
+}
+
+/// Request body changing a plan's limits and Stripe price
+///
+/// Replaces the previous settings: omitted or `null` limits mean no limit.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct SetPlanLimits {
+    /// Most wallets a user on the plan tracks
+    pub max_wallets: Option<i64>,
+    /// Most syncs a user on the plan requests per UTC day
+    pub max_syncs_per_day: Option<i64>,
+    /// Stripe price whose active subscriptions put users on the plan
+    pub stripe_price_id: Option<String>,
+}
+
+impl SetPlanLimits {
+    /// Checks that the limits are not negative and the price ID is not blank
+    pub fn validate(&self) -> Result<(), ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        for (field, limit) in [
+            ("max_wallets", self.max_wallets),
+            ("max_syncs_per_day", self.max_syncs_per_day),
+        ] {
+            if limit.is_some_and(|limit| limit < 0) {
+                errors.add(field, "Must not be negative");
+            }
+        }
+        if self
+            .stripe_price_id
+            .as_deref()
+            .is_some_and(|price| price.trim().is_empty())
+        {
+            errors.add("stripe_price_id", "Must not be blank");
+        }
+        errors.into_result()
+    }
+}
+
+type PlanRow = (String, Option<i64>, Option<i64>, Option<String>);
+
+fn plan_limits(
+    (name, max_wallets, max_syncs_per_day, stripe_price_id): PlanRow,
+) -> Option<PlanLimits> {
+    Some(PlanLimits {
+        plan: name.parse().ok()?,
+        max_wallets,
+        max_syncs_per_day,
+        stripe_price_id,
+    })
+}
+
+/// Limits of every plan, from the most to the least limited
+pub async fn plans(state: &AppState) -> Result<Vec<PlanLimits>, AppError> {
+    let rows = sqlx::query_as::<_, PlanRow>(
+        "SELECT name, max_wallets, max_syncs_per_day, stripe_price_id FROM plans",
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let mut plans: Vec<PlanLimits> = rows.into_iter().filter_map(plan_limits).collect();
+    plans.sort_by_key(|limits| Plan::ALL.iter().position(|plan| *plan == limits.plan));
+    Ok(plans)
+}
+
+/// Changes a plan's limits and Stripe price, returning the new settings
+///
+/// Fails with a conflict if another plan is already sold at the price.
+pub async fn set_plan(
+    state: &AppState,
+    plan: Plan,
+    limits: &SetPlanLimits,
+) -> Result<PlanLimits, AppError> {
+    let row = sqlx::query_as::<_, PlanRow>(
+        r#"
+        INSERT INTO plans (name, max_wallets, max_syncs_per_day, stripe_price_id)
+        VALUES ($1, $2, $3, $4)
+        ON CONFLICT (name) DO UPDATE
+        SET max_wallets = EXCLUDED.max_wallets,
+            max_syncs_per_day = EXCLUDED.max_syncs_per_day,
+            stripe_price_id = EXCLUDED.stripe_price_id
+        RETURNING name, max_wallets, max_syncs_per_day, stripe_price_id
+        "#,
+    )
+    .bind(plan.as_str())
+    .bind(limits.max_wallets)
+    .bind(limits.max_syncs_per_day)
+    .bind(limits.stripe_price_id.as_deref().map(str::trim))
+    .fetch_one(&state.db_pool)
+    .await?;
+
+    plan_limits(row).ok_or_else(|| AppError::InternalServerError(format!("Unknown plan {plan}")))
+}
@@ -0,0 +1,328 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue, Request},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+
+use crate::auth::{api_key_from_headers, hash_api_key, is_valid_credential};
+use crate::client_ip::ClientIp;
+use crate::{AppError, AppState};
+
+/// Header carrying the number of requests allowed per window
+pub const RATE_LIMIT_LIMIT_HEADER: &str = "x-ratelimit-limit";
+
+/// Header carrying the number of requests left in the current window
+pub const RATE_LIMIT_REMAINING_HEADER: &str = "x-ratelimit-remaining";
+
+/// Header carrying the number of seconds until the current window resets
+pub const RATE_LIMIT_RESET_HEADER: &str = "x-ratelimit-reset";
+
+/// Paths that are never limited, so probes keep working under load
+const EXEMPT_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// Length of a rate limit window
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Most clients counted one by one; while this many have open windows, requests of
+/// further clients share the window of [`OVERFLOW_CLIENT`]
+const MAX_CLIENTS: usize = 100_000;
+
+/// Client whose window is shared by the clients beyond [`MAX_CLIENTS`]
+const OVERFLOW_CLIENT: &str = "overflow";
+
+/// Requests counted within one window
+#[derive(Debug, Clone, Copy)]
+struct Window {
+    started: Instant,
+    count: u32,
+}
+
+impl Window {
+    fn new(now: Instant) -> Self {
+        Self {
+            started: now,
+            count: 0,
+        }
+    }
+
+    /// Starts a new window if this one has elapsed
+    fn roll(&mut self, now: Instant) {
+        if now.duration_since(self.started) >= WINDOW {
+            *self = Self::new(now);
+        }
+    }
+
+    /// Time until the window resets
+    fn reset_in(&self, now: Instant) -> Duration {
+        WINDOW.saturating_sub(now.duration_since(self.started))
+    }
+}
+
+/// Windows of the individual clients and of all clients together
+#[derive(Debug)]
+struct Windows {
+    clients: HashMap<String, Window>,
+    global: Window,
+    last_purge: Instant,
+}
+
+/// Outcome of counting a request against the limits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimitDecision {
+    /// Whether the request may proceed
+    pub allowed: bool,
+    /// Requests allowed per window by the limit that applies
+    pub limit: u32,
+    /// Requests left in the current window
+    pub remaining: u32,
+    /// Time until the current window resets
+    pub reset_in: Duration,
+}
+
+/// Fixed-window request counter enforcing a per-client and a global limit
+///
+/// Clients are identified by their credential once it is known to be valid, or else
+/// by IP address. A limit of `0` disables it. Counts are kept in process memory, so every
+/// instance of the server enforces the limits on its own.
+#[derive(Debug)]
+pub struct RateLimiter {
+    per_client: u32,
+    global: u32,
+    windows: Mutex<Windows>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing the given numbers of requests per minute
+    pub fn new(per_client_per_minute: u32, global_per_minute: u32) -> Self {
+        let now = Instant::now();
+        Self {
+            per_client: per_client_per_minute,
+            global: global_per_minute,
+            windows: Mutex::new(Windows {
+                clients: HashMap::new(),
+                global: Window::new(now),
+                last_purge: now,
+            }),
+        }
+    }
+
+    /// Whether any limit is enforced
+    pub fn is_enabled(&self) -> bool {
+        self.per_client > 0 || self.global > 0
+    }
+
+    /// Counts a request of `client`, unless it exceeds a limit
+    ///
+    /// When both limits apply, the reported limit is the one with fewer requests
+    /// remaining.
+    pub fn check(&self, client: &str) -> RateLimitDecision {
+        let now = Instant::now();
+        let mut guard = self.windows.lock().unwrap_or_else(|err| err.into_inner());
+        let windows = &mut *guard;
+
+        windows.global.roll(now);
+        let global = windows.global;
+        let client_window = windows.client(client, now);
+
+        let mut decisions = Vec::with_capacity(2);
+        if self.per_client > 0 {
+            decisions.push(decide(self.per_client, client_window, now));
+        }
+        if self.global > 0 {
+            decisions.push(decide(self.global, &global, now));
+        }
+
+        let allowed = decisions.iter().all(|d| d.allowed);
+        if allowed {
+            client_window.count += 1;
+            windows.global.count += 1;
+            for decision in &mut decisions {
+                decision.remaining -= 1;
+            }
+        }
+
+        strictest(decisions)
+    }
+
+    /// Moves a request counted for `from` to `to`, which has no open window yet, e.g.
+    /// once the credential it carries turned out to be valid
+    fn reassign(&self, from: &str, to: &str) -> RateLimitDecision {
+        let now = Instant::now();
+        let mut guard = self.windows.lock().unwrap_or_else(|err| err.into_inner());
+        let windows = &mut *guard;
+
+        if let Some(window) = windows.clients.get_mut(from) {
+            window.count = window.count.saturating_sub(1);
+        }
+        let global = windows.global;
+        let client_window = windows.client(to, now);
+        client_window.count += 1;
+
+        let mut decisions = Vec::with_capacity(2);
+        if self.per_client > 0 {
+            decisions.push(counted(self.per_client, client_window, now));
+        }
+        if self.global > 0 {
+            decisions.push(counted(self.global, &global, now));
+        }
+        strictest(decisions)
+    }
+
+    /// Whether `client` has an open window
+    fn is_counting(&self, client: &str) -> bool {
+        let guard = self.windows.lock().unwrap_or_else(|err| err.into_inner());
+        guard
+            .clients
+            .get(client)
+            .is_some_and(|window| window.started.elapsed() < WINDOW)
+    }
+}
+
+impl Windows {
+    /// The current window of `client`, or of [`OVERFLOW_CLIENT`] for new clients while
+    /// [`MAX_CLIENTS`] are counted
+    ///
+    /// Elapsed windows are purged at most once per [`WINDOW`], so a full map is not
+    /// scanned again for every new client.
+    fn client(&mut self, client: &str, now: Instant) -> &mut Window {
+        if now.duration_since(self.last_purge) >= WINDOW {
+            self.clients
+                .retain(|_, window| now.duration_since(window.started) < WINDOW);
+            self.last_purge = now;
+        }
+
+        let client = if self.clients.len() >= MAX_CLIENTS && !self.clients.contains_key(client) {
+            OVERFLOW_CLIENT
+        } else {
+            client
+        };
+        let window = self
+            .clients
+            .entry(client.to_string())
+            .or_insert_with(|| Window::new(now));
+        window.roll(now);
+        window
+    }
+}
+
+/// The decision of the limit with the fewest requests remaining, preferring denials
+fn strictest(decisions: Vec<RateLimitDecision>) -> RateLimitDecision {
+    decisions
+        .into_iter()
+        .min_by_key(|d| (d.allowed, d.remaining))
+        .unwrap_or(RateLimitDecision {
+            allowed: true,
+            limit: 0,
+            remaining: 0,
+            reset_in: Duration::ZERO,
+        })
+}
+
+/// Reports a request already counted in `window` against one limit
+fn counted(limit: u32, window: &Window, now: Instant) -> RateLimitDecision {
+    RateLimitDecision {
+        allowed: true,
+        limit,
+        remaining: limit.saturating_sub(window.count),
+        reset_in: window.reset_in(now),
+    }
+}
+
+/// Decides a request against one limit, before counting it
+fn decide(limit: u32, window: &Window, now: Instant) -> RateLimitDecision {
+    let allowed = window.count < limit;
+    RateLimitDecision {
+        allowed,
+        limit,
+        // One more is taken off by the caller once the request is counted
+        remaining: limit.saturating_sub(window.count),
+        reset_in: window.reset_in(now),
+    }
+}
+
+/// Counts a request under the client sending it: a hash of its credential if that is
+/// valid, or else its IP address
+///
+/// Credentials are checked like [`crate::auth`] does, unless the client already has
+/// an open window, which only valid credentials get. Requests with made-up or revoked
+/// API keys therefore count against their IP, and cannot get a fresh allowance by
+/// changing the key. They are counted before the credential is looked up, so an IP
+/// over its limit causes no more lookups; a request whose credential is found valid
+/// is moved to the credential's window. The IP is the [`ClientIp`], which only
+/// trusted proxies can set through forwarding headers. Requests without either share
+/// one bucket.
+async fn count_request<B>(
+    limiter: &RateLimiter,
+    state: &AppState,
+    request: &Request<B>,
+) -> RateLimitDecision {
+    let ip = match request.extensions().get::<ClientIp>() {
+        Some(ClientIp(ip)) => format!("ip:{ip}"),
+        None => "ip:unknown".to_string(),
+    };
+    let Some(credential) = api_key_from_headers(request.headers()) else {
+        return limiter.check(&ip);
+    };
+
+    let key = format!("key:{}", hash_api_key(credential));
+    if limiter.is_counting(&key) {
+        return limiter.check(&key);
+    }
+    let decision = limiter.check(&ip);
+    if decision.allowed && is_valid_credential(state, credential).await {
+        return limiter.reassign(&ip, &key);
+    }
+    decision
+}
+
+/// Adds the `X-RateLimit-*` headers describing a decision
+fn insert_headers(headers: &mut HeaderMap, decision: &RateLimitDecision) {
+    let reset_secs = decision.reset_in.as_secs_f64().ceil() as u64;
+    headers.insert(RATE_LIMIT_LIMIT_HEADER, HeaderValue::from(decision.limit));
+    headers.insert(
+        RATE_LIMIT_REMAINING_HEADER,
+        HeaderValue::from(decision.remaining),
+    );
+    headers.insert(RATE_LIMIT_RESET_HEADER, HeaderValue::from(reset_secs));
+}
+
+/// Middleware enforcing the limits of a [`RateLimiter`]
+///
+/// Requests over a limit are rejected with `429 Too Many Requests` and a
+/// `Retry-After` header. Every limited response carries `X-RateLimit-Limit`,
+/// `X-RateLimit-Remaining` and `X-RateLimit-Reset`. Health probes are exempt.
+pub async fn rate_limit_middleware<B>(
+    State((limiter, state)): State<(Arc<RateLimiter>, AppState)>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    if !limiter.is_enabled() || EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let decision = count_request(&limiter, &state, &request).await;
+
+    let mut response = if decision.allowed {
+        next.run(request).await
+    } else {
+        let retry_after = decision.reset_in.as_secs_f64().ceil() as u64;
+        let mut response = AppError::TooManyRequests(format!(
+            "Rate limit of {} requests per minute exceeded, retry in {retry_after}s",
+            decision.limit
+        ))
+        .into_response();
+        response.headers_mut().insert(
+            axum::http::header::RETRY_AFTER,
+            HeaderValue::from(retry_after),
+        );
+        response
+    };
+
+    insert_headers(response.headers_mut(), &decision);
+    response
+}
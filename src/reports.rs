@@ -0,0 +1,439 @@
+//! Scheduled daily and weekly portfolio reports.
+//!
+//! On each run of a period's cron schedule, every user with wallets gets a
+//! [`Report`] job: the portfolio's value and its change over the period, the largest
+//! price moves of the tokens held since the previous report, and the PnL realized in
+//! the period. Reports are kept for browsing at `GET /reports` and pushed to the
+//! user's [notification channels](crate::notifications) that opted in.
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use chrono::{DateTime, Days, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analytics::{self, CostBasisMethod};
+use crate::jobs::{self, Job};
+use crate::models::Holding;
+use crate::money::Usd;
+use crate::notifications::{self, templates};
+use crate::scheduler::{self, LeaderLock};
+use crate::{AppError, AppState};
+
+/// Number of top gainers and losers listed in a report
+const TOP_MOVERS: usize = 3;
+
+/// Period a report covers
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportPeriod {
+    /// The day before the report
+    Daily,
+    /// The week before the report
+    Weekly,
+}
+
+impl ReportPeriod {
+    /// Name of the period as stored in `reports.period`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Daily => "daily",
+            Self::Weekly => "weekly",
+        }
+    }
+
+    /// Length of the period in days
+    pub fn days(&self) -> u64 {
+        match self {
+            Self::Daily => 1,
+            Self::Weekly => 7,
+        }
+    }
+}
+
+/// A token whose price moved since the previous report
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenMove {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// USD price per token at the previous report
+    pub previous_price_usd: f64,
+    /// USD price per token at this report
+    pub price_usd: f64,
+    /// Price change in percent
+    #[schema(example = 12.5)]
+    pub change_percent: f64,
+}
+
+/// Value of one wallet at the time of a report
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WalletValue {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Name of the wallet, if set
+    pub name: Option<String>,
+    /// Address of the wallet
+    pub address: String,
+    /// Total USD value of the positions with a known price
+    #[schema(value_type = f64)]
+    pub value_usd: Usd,
+}
+
+/// A generated portfolio report
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct Report {
+    /// Unique identifier of the report
+    pub id: Uuid,
+    /// Period the report covers
+    #[schema(value_type = ReportPeriod)]
+    pub period: String,
+    /// First UTC day of the period
+    #[schema(value_type = String, format = Date, example = "2025-07-18")]
+    pub period_start: NaiveDate,
+    /// UTC day the report was generated, ending the period
+    #[schema(value_type = String, format = Date, example = "2025-07-19")]
+    pub period_end: NaiveDate,
+    /// Total USD value of the portfolio
+    #[schema(value_type = f64)]
+    pub total_value_usd: Usd,
+    /// Total USD value on the first day of the period, from the wallet snapshots
+    #[schema(value_type = Option<f64>)]
+    pub previous_value_usd: Option<Usd>,
+    /// Change of the total value over the period, in USD
+    #[schema(value_type = Option<f64>)]
+    pub change_usd: Option<Usd>,
+    /// Change of the total value over the period, in percent
+    pub change_percent: Option<f64>,
+    /// Profit realized by sells during the period, in USD (FIFO)
+    #[schema(value_type = f64)]
+    pub realized_pnl_usd: Usd,
+    /// Held tokens whose price rose the most since the previous report
+    #[sqlx(json)]
+    pub top_gainers: Vec<TokenMove>,
+    /// Held tokens whose price fell the most since the previous report
+    #[sqlx(json)]
+    pub top_losers: Vec<TokenMove>,
+    /// Value of each wallet
+    #[sqlx(json)]
+    pub wallets: Vec<WalletValue>,
+    /// Positions across all wallets
+    #[sqlx(json)]
+    pub holdings: Vec<Holding>,
+    /// When the report was generated
+    pub created_at: DateTime<Utc>,
+}
+
+/// Columns selected into a [`Report`]
+pub const REPORT_COLUMNS: &str = "id, period, period_start, period_end, total_value_usd, \
+     previous_value_usd, change_usd, change_percent, realized_pnl_usd, top_gainers, \
+     top_losers, wallets, holdings, created_at";
+
+/// Looks up a report of `user_id`, returning `404 Not Found` if there is none
+pub async fn find_report(
+    pool: &PgPool,
+    user_id: Uuid,
+    report_id: Uuid,
+) -> Result<Report, AppError> {
+    sqlx::query_as::<_, Report>(&format!(
+        "SELECT {REPORT_COLUMNS} FROM reports WHERE id = $1 AND user_id = $2"
+    ))
+    .bind(report_id)
+    .bind(user_id)
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound(format!("Report with ID {report_id} not found")))
+}
+
+/// Spawns the background task queueing the reports of `period` at every time of
+/// `schedule`
+///
+/// Only the instance holding `leader` queues.
+pub fn spawn_report_scheduler(
+    state: AppState,
+    period: ReportPeriod,
+    schedule: cron::Schedule,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!("Starting {} report scheduler", period.as_str());
+
+    tokio::spawn(async move {
+        for next in schedule.upcoming(Utc) {
+            let wait = (next - Utc::now()).to_std().unwrap_or_default();
+            tokio::time::sleep(wait).await;
+            if !scheduler::is_leader(&leader).await {
+                continue;
+            }
+
+            match run_report_cycle(&state.db_pool, period).await {
+                Ok(queued) if queued > 0 => {
+                    info!("Queued {} {} reports", queued, period.as_str())
+                }
+                Ok(_) => {}
+                Err(err) => error!("{} report cycle failed: {}", period.as_str(), err),
+            }
+        }
+    })
+}
+
+/// Queues today's report of `period` for every user with wallets that has none yet
+/// and returns how many were queued
+pub async fn run_report_cycle(pool: &PgPool, period: ReportPeriod) -> Result<usize, sqlx::Error> {
+    let due = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT DISTINCT w.user_id
+        FROM wallets w
+        WHERE NOT EXISTS (
+            SELECT 1 FROM reports r
+            WHERE r.user_id = w.user_id
+              AND r.period = $1
+              AND r.period_end = (NOW() AT TIME ZONE 'UTC')::DATE
+        )
+        "#,
+    )
+    .bind(period.as_str())
+    .fetch_all(pool)
+    .await?;
+
+    let mut queued = 0;
+    for user_id in due {
+        if jobs::enqueue(pool, &Job::GenerateReport { user_id, period }).await? {
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Generates today's report of `period` for `user_id` and queues it on the channels
+/// that opted in; returns `None` if today's report already exists
+pub async fn generate_report(
+    state: &AppState,
+    user_id: Uuid,
+    period: ReportPeriod,
+) -> Result<Option<Report>, AppError> {
+    let pool = &state.db_pool;
+    let period_end = Utc::now().date_naive();
+    let period_start = period_end - Days::new(period.days());
+
+    let wallets = sqlx::query_as::<_, (Uuid, String, Option<String>)>(
+        "SELECT id, address, name FROM wallets WHERE user_id = $1 ORDER BY created_at, id",
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await?;
+    let wallet_ids: Vec<Uuid> = wallets.iter().map(|(id, _, _)| *id).collect();
+
+    let portfolio = analytics::wallets_portfolio(pool, state.prices.as_ref(), &wallet_ids).await?;
+    let mut wallet_values = Vec::with_capacity(wallets.len());
+    for (wallet_id, address, name) in wallets {
+        let holdings = analytics::wallet_holdings(pool, state.prices.as_ref(), wallet_id).await?;
+        wallet_values.push(WalletValue {
+            wallet_id,
+            name,
+            address,
+            value_usd: holdings.total_value_usd,
+        });
+    }
+
+    let previous_value_usd = sqlx::query_scalar::<_, Option<Usd>>(
+        r#"
+        SELECT SUM(total_value_usd)
+        FROM snapshots
+        WHERE wallet_id = ANY($1) AND snapshot_date = $2
+        "#,
+    )
+    .bind(&wallet_ids)
+    .bind(period_start)
+    .fetch_one(pool)
+    .await?;
+    let total_value_usd = portfolio.total_value_usd;
+    let change_usd = previous_value_usd.map(|previous| total_value_usd - previous);
+    let change_percent = previous_value_usd
+        .zip(change_usd)
+        .filter(|(previous, _)| previous.is_positive())
+        .and_then(|(previous, change)| change.percent_of(previous));
+
+    // Sells during the period, priced at the time of the trade
+    let since = period_start.and_time(chrono::NaiveTime::MIN).and_utc();
+    let realized_pnl_usd: Usd = analytics::load_wallets_token_trades(pool, &wallet_ids)
+        .await?
+        .iter()
+        .flat_map(|token| analytics::disposals(&token.trades, CostBasisMethod::default()))
+        .filter(|disposal| disposal.sold_at >= since)
+        .map(|disposal| disposal.gain_usd())
+        .sum();
+
+    let previous_holdings = sqlx::query_scalar::<_, sqlx::types::Json<Vec<Holding>>>(
+        r#"
+        SELECT holdings FROM reports
+        WHERE user_id = $1 AND period = $2 AND period_end < $3
+        ORDER BY period_end DESC
+        LIMIT 1
+        "#,
+    )
+    .bind(user_id)
+    .bind(period.as_str())
+    .bind(period_end)
+    .fetch_optional(pool)
+    .await?
+    .map(|holdings| holdings.0)
+    .unwrap_or_default();
+    let (top_gainers, top_losers) = top_movers(&previous_holdings, &portfolio.holdings);
+
+    let mut tx = pool.begin().await?;
+    let report = sqlx::query_as::<_, Report>(&format!(
+        r#"
+        INSERT INTO reports (
+            id, user_id, period, period_start, period_end, total_value_usd,
+            previous_value_usd, change_usd, change_percent, realized_pnl_usd,
+            top_gainers, top_losers, wallets, holdings
+        )
+        VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13, $14)
+        ON CONFLICT (user_id, period, period_end) DO NOTHING
+        RETURNING {REPORT_COLUMNS}
+        "#
+    ))
+    .bind(Uuid::now_v7())
+    .bind(user_id)
+    .bind(period.as_str())
+    .bind(period_start)
+    .bind(period_end)
+    .bind(total_value_usd)
+    .bind(previous_value_usd)
+    .bind(change_usd)
+    .bind(change_percent)
+    .bind(realized_pnl_usd)
+    .bind(sqlx::types::Json(&top_gainers))
+    .bind(sqlx::types::Json(&top_losers))
+    .bind(sqlx::types::Json(&wallet_values))
+    .bind(sqlx::types::Json(&portfolio.holdings))
+    .fetch_optional(&mut *tx)
+    .await?;
+    let Some(report) = report else {
+        return Ok(None);
+    };
+
+    notifications::notify_report(&mut tx, user_id, period, &render(&report)).await?;
+    tx.commit().await?;
+
+    Ok(Some(report))
+}
+
+/// Held tokens with the largest price rises and falls since the previous report's
+/// holdings, largest first
+fn top_movers(previous: &[Holding], current: &[Holding]) -> (Vec<TokenMove>, Vec<TokenMove>) {
+    let previous_prices: HashMap<&str, f64> = previous
+        .iter()
+        .filter_map(|h| Some((h.token_address.as_str(), h.price_usd?)))
+        .filter(|(_, price)| *price > 0.0)
+        .collect();
+
+    let mut moves: Vec<TokenMove> = current
+        .iter()
+        .filter_map(|holding| {
+            let previous_price_usd = *previous_prices.get(holding.token_address.as_str())?;
+            let price_usd = holding.price_usd?;
+            Some(TokenMove {
+                token_address: holding.token_address.clone(),
+                token_symbol: holding.token_symbol.clone(),
+                previous_price_usd,
+                price_usd,
+                change_percent: (price_usd / previous_price_usd - 1.0) * 100.0,
+            })
+        })
+        .collect();
+    moves.sort_by(|a, b| {
+        b.change_percent
+            .partial_cmp(&a.change_percent)
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let gainers = moves
+        .iter()
+        .filter(|m| m.change_percent > 0.0)
+        .take(TOP_MOVERS)
+        .cloned()
+        .collect();
+    let losers = moves
+        .iter()
+        .rev()
+        .filter(|m| m.change_percent < 0.0)
+        .take(TOP_MOVERS)
+        .cloned()
+        .collect();
+    (gainers, losers)
+}
+
+/// Renders a report into the message sent on notification channels
+fn render(report: &Report) -> templates::Rendered {
+    let cents = |value: Usd| value.decimal().abs().round_dp(2);
+    let usd = |value: Usd| {
+        format!(
+            "{}${:.2}",
+            if value.is_negative() { "-" } else { "" },
+            cents(value)
+        )
+    };
+    let signed_usd = |value: Usd| {
+        format!(
+            "{}${:.2}",
+            if value.is_negative() { "-" } else { "+" },
+            cents(value)
+        )
+    };
+
+    let change = match (report.change_usd, report.change_percent) {
+        (Some(change), Some(percent)) => format!("{}, {percent:+.1}%", signed_usd(change)),
+        (Some(change), None) => signed_usd(change),
+        _ => "no value recorded".to_string(),
+    };
+    let movers = |title: &str, moves: &[TokenMove]| match moves.is_empty() {
+        true => String::new(),
+        false => {
+            let lines: Vec<String> = moves
+                .iter()
+                .map(|m| format!("- {} {:+.1}%", m.token_symbol, m.change_percent))
+                .collect();
+            format!("{title}:\n{}\n\n", lines.join("\n"))
+        }
+    };
+    let wallets: Vec<String> = report
+        .wallets
+        .iter()
+        .map(|w| {
+            format!(
+                "- {}: {}",
+                w.name.as_deref().unwrap_or(&w.address),
+                usd(w.value_usd)
+            )
+        })
+        .collect();
+
+    templates::REPORT.render(&[
+        ("period", &report.period),
+        ("date", &report.period_end.to_string()),
+        ("since", &report.period_start.to_string()),
+        ("total", &usd(report.total_value_usd)),
+        ("change", &change),
+        ("realized_pnl", &signed_usd(report.realized_pnl_usd)),
+        (
+            "movers",
+            &format!(
+                "{}{}",
+                movers("Top gainers", &report.top_gainers),
+                movers("Top losers", &report.top_losers)
+            ),
+        ),
+        ("wallets", &wallets.join("\n")),
+    ])
+}
@@ -0,0 +1,338 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::RwLock;
+use uuid::Uuid;
+
+use super::{
+    NewTransaction, RepositoryError, TransactionQuery, TransactionRepository, TransactionStream,
+    WalletFilter, WalletQuery, WalletRepository, WalletSort,
+};
+use crate::classify::TransactionCategory;
+use crate::models::{CreateWallet, Transaction, UpdateWallet, Wallet};
+use crate::money::Usd;
+use crate::pagination::{Cursor, SortOrder};
+
+/// A stored wallet together with its owner
+#[derive(Debug, Clone)]
+struct OwnedWallet {
+    user_id: Uuid,
+    wallet: Wallet,
+}
+
+/// Wallet and transaction storage kept in process memory
+///
+/// Behaves like the Postgres repositories, including ordering and cursor
+/// semantics, but keeps nothing across restarts. Names are compared by code point
+/// rather than by the database collation.
+#[derive(Debug, Default)]
+pub struct InMemoryRepository {
+    wallets: RwLock<HashMap<Uuid, OwnedWallet>>,
+    transactions: RwLock<HashMap<Uuid, Vec<Transaction>>>,
+}
+
+impl InMemoryRepository {
+    /// Creates an empty repository
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a transaction of a wallet, e.g. to seed demo data
+    pub async fn insert_transaction(&self, wallet_id: Uuid, transaction: Transaction) {
+        self.transactions
+            .write()
+            .await
+            .entry(wallet_id)
+            .or_default()
+            .push(transaction);
+    }
+
+    /// A wallet's transactions after the cursor, newest first
+    async fn transactions_after(
+        &self,
+        wallet_id: Uuid,
+        cursor: Option<Cursor>,
+        category: Option<TransactionCategory>,
+    ) -> Vec<Transaction> {
+        let transactions = self.transactions.read().await;
+        let mut matching: Vec<Transaction> = transactions
+            .get(&wallet_id)
+            .into_iter()
+            .flatten()
+            .filter(|t| after_cursor(cursor, t.created_at, t.id))
+            .filter(|t| {
+                category.is_none_or(|category| t.category.as_deref() == Some(category.as_str()))
+            })
+            .cloned()
+            .collect();
+        matching.sort_by_key(|t| std::cmp::Reverse((t.created_at, t.id)));
+        matching
+    }
+}
+
+impl WalletFilter {
+    /// Whether the wallet meets every condition
+    fn matches(&self, wallet: &Wallet) -> bool {
+        let name_matches = match &self.name_contains {
+            Some(text) => wallet
+                .name
+                .as_deref()
+                .is_some_and(|name| name.to_lowercase().contains(&text.to_lowercase())),
+            None => true,
+        };
+
+        name_matches
+            && self
+                .created_after
+                .is_none_or(|after| wallet.created_at >= after)
+            && self
+                .created_before
+                .is_none_or(|before| wallet.created_at < before)
+    }
+}
+
+/// Whether the row lies strictly after the cursor in newest-first order
+fn after_cursor(cursor: Option<Cursor>, created_at: chrono::DateTime<Utc>, id: Uuid) -> bool {
+    cursor.is_none_or(|c| (created_at, id) < (c.created_at, c.id))
+}
+
+/// Orders wallets like the Postgres repository: unnamed wallets last when sorting
+/// by name, ties broken by creation time and ID
+fn compare_wallets(a: &Wallet, b: &Wallet, sort: WalletSort, order: SortOrder) -> Ordering {
+    let directed = |ordering: Ordering| match order {
+        SortOrder::Asc => ordering,
+        SortOrder::Desc => ordering.reverse(),
+    };
+    let by_creation = directed((a.created_at, a.id).cmp(&(b.created_at, b.id)));
+
+    match sort {
+        WalletSort::CreatedAt => by_creation,
+        WalletSort::Name => match (&a.name, &b.name) {
+            (Some(a), Some(b)) => directed(a.cmp(b)),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        }
+        .then(by_creation),
+    }
+}
+
+#[async_trait]
+impl WalletRepository for InMemoryRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        wallet: &CreateWallet,
+    ) -> Result<Wallet, RepositoryError> {
+        let now = Utc::now();
+        let wallet = Wallet {
+            id: Uuid::now_v7(),
+            address: wallet.address.to_string(),
+            domain: wallet.domain.clone(),
+            name: wallet.name.clone(),
+            notes: wallet.notes.clone(),
+            metadata: wallet.metadata.clone(),
+            leaderboard_opt_out: false,
+            last_synced_at: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        self.wallets.write().await.insert(
+            wallet.id,
+            OwnedWallet {
+                user_id,
+                wallet: wallet.clone(),
+            },
+        );
+
+        Ok(wallet)
+    }
+
+    async fn find(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        Ok(self
+            .wallets
+            .read()
+            .await
+            .get(&wallet_id)
+            .filter(|owned| owned.user_id == user_id)
+            .map(|owned| owned.wallet.clone()))
+    }
+
+    async fn find_by_address(
+        &self,
+        user_id: Uuid,
+        address: &str,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        Ok(self
+            .wallets
+            .read()
+            .await
+            .values()
+            .find(|owned| owned.user_id == user_id && owned.wallet.address == address)
+            .map(|owned| owned.wallet.clone()))
+    }
+
+    async fn update(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+        changes: &UpdateWallet,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let mut wallets = self.wallets.write().await;
+        let Some(owned) = wallets
+            .get_mut(&wallet_id)
+            .filter(|owned| owned.user_id == user_id)
+        else {
+            return Ok(None);
+        };
+
+        let wallet = &mut owned.wallet;
+        if let Some(name) = &changes.name {
+            wallet.name = name.clone();
+        }
+        if let Some(notes) = &changes.notes {
+            wallet.notes = notes.clone();
+        }
+        if let Some(metadata) = &changes.metadata {
+            wallet.metadata = metadata.clone();
+        }
+        if let Some(opt_out) = changes.leaderboard_opt_out {
+            wallet.leaderboard_opt_out = opt_out;
+        }
+        wallet.updated_at = Utc::now();
+
+        Ok(Some(wallet.clone()))
+    }
+
+    async fn delete(&self, user_id: Uuid, wallet_id: Uuid) -> Result<bool, RepositoryError> {
+        let mut wallets = self.wallets.write().await;
+        if wallets
+            .get(&wallet_id)
+            .is_none_or(|owned| owned.user_id != user_id)
+        {
+            return Ok(false);
+        }
+
+        wallets.remove(&wallet_id);
+        self.transactions.write().await.remove(&wallet_id);
+        Ok(true)
+    }
+
+    async fn count(&self, user_id: Uuid, filter: &WalletFilter) -> Result<i64, RepositoryError> {
+        Ok(self
+            .wallets
+            .read()
+            .await
+            .values()
+            .filter(|owned| owned.user_id == user_id && filter.matches(&owned.wallet))
+            .count() as i64)
+    }
+
+    async fn list(
+        &self,
+        user_id: Uuid,
+        query: &WalletQuery,
+    ) -> Result<Vec<Wallet>, RepositoryError> {
+        let mut wallets: Vec<Wallet> = self
+            .wallets
+            .read()
+            .await
+            .values()
+            .filter(|owned| owned.user_id == user_id && query.filter.matches(&owned.wallet))
+            .filter(|owned| after_cursor(query.cursor, owned.wallet.created_at, owned.wallet.id))
+            .map(|owned| owned.wallet.clone())
+            .collect();
+        wallets.sort_by(|a, b| compare_wallets(a, b, query.sort, query.order));
+
+        Ok(wallets
+            .into_iter()
+            .skip(query.offset.max(0) as usize)
+            .take(query.limit.max(0) as usize)
+            .collect())
+    }
+}
+
+#[async_trait]
+impl TransactionRepository for InMemoryRepository {
+    async fn list(
+        &self,
+        wallet_id: Uuid,
+        query: &TransactionQuery,
+    ) -> Result<Vec<Transaction>, RepositoryError> {
+        let mut page = self
+            .transactions_after(wallet_id, query.cursor, query.category)
+            .await;
+        page.truncate(query.limit.max(0) as usize);
+
+        Ok(page)
+    }
+
+    async fn stream<'a>(
+        &'a self,
+        wallet_id: Uuid,
+        cursor: Option<Cursor>,
+        category: Option<TransactionCategory>,
+    ) -> Result<TransactionStream<'a>, RepositoryError> {
+        let transactions = self.transactions_after(wallet_id, cursor, category).await;
+        Ok(Box::pin(tokio_stream::iter(
+            transactions.into_iter().map(Ok),
+        )))
+    }
+
+    async fn bulk_insert(
+        &self,
+        wallet_id: Uuid,
+        rows: &[NewTransaction],
+    ) -> Result<u64, RepositoryError> {
+        let mut transactions = self.transactions.write().await;
+        let recorded = transactions.entry(wallet_id).or_default();
+
+        let mut inserted = 0;
+        for row in rows {
+            let existing = recorded.iter_mut().find(|t| {
+                t.transaction_hash == row.transaction_hash && t.token_address == row.token_address
+            });
+            match existing {
+                Some(transaction) => {
+                    transaction.amount = row.amount;
+                    transaction.block_number = row.block_number;
+                    transaction.block_time = row.block_time;
+                    transaction.category = Some(row.category.as_str().to_string());
+                    transaction.swap = row.swap.clone();
+                    transaction.counterparty = row.counterparty.clone();
+                }
+                None => {
+                    let now = Utc::now();
+                    recorded.push(Transaction {
+                        id: Uuid::now_v7(),
+                        token_address: row.token_address.clone(),
+                        token_symbol: row.token_symbol.clone(),
+                        token_name: None,
+                        logo_uri: None,
+                        amount: row.amount,
+                        buy_price_usd: Usd::ZERO,
+                        transaction_hash: row.transaction_hash.clone(),
+                        block_number: row.block_number,
+                        block_time: row.block_time,
+                        category: Some(row.category.as_str().to_string()),
+                        swap: row.swap.clone(),
+                        counterparty: row.counterparty.clone(),
+                        counterparty_label: None,
+                        counterparty_category: None,
+                        created_at: now,
+                    });
+                    inserted += 1;
+                }
+            }
+        }
+
+        Ok(inserted)
+    }
+}
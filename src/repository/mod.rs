@@ -0,0 +1,671 @@
+//! Storage of wallets and transactions behind traits.
+//!
+//! Handlers reach wallet and transaction rows through [`WalletRepository`] and
+//! [`TransactionRepository`] rather than through SQL, so [`AppState`](crate::AppState)
+//! can be given another implementation, e.g. a fake in tests. The Postgres
+//! implementations are [`PgWalletRepository`] and [`PgTransactionRepository`];
+//! [`InMemoryRepository`] implements both traits without a database, and with the
+//! `sqlite` feature `SqliteRepository` implements both on a SQLite file.
+
+use std::pin::Pin;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use thiserror::Error;
+use tokio_stream::{Stream, StreamExt};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::classify::{SwapDetails, TransactionCategory};
+use crate::models::{CreateWallet, Transaction, UpdateWallet, Wallet};
+use crate::money::TokenAmount;
+use crate::pagination::{Cursor, SortOrder};
+use crate::AppError;
+
+/// HashMap-backed repository for tests and demo mode
+pub mod memory;
+
+/// SQLite-backed repository for lightweight self-hosting
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+
+pub use memory::InMemoryRepository;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteRepository;
+
+/// Errors that can occur while reading or writing through a repository
+#[derive(Debug, Error)]
+pub enum RepositoryError {
+    /// The database query failed
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+
+    /// A non-database backend failed
+    #[error("Storage error: {0}")]
+    Backend(String),
+}
+
+impl From<RepositoryError> for AppError {
+    fn from(err: RepositoryError) -> Self {
+        match err {
+            RepositoryError::Database(err) => err.into(),
+            RepositoryError::Backend(message) => AppError::InternalServerError(message),
+        }
+    }
+}
+
+/// Column wallet lists are sorted by
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WalletSort {
+    /// Wallet name, unnamed wallets last
+    Name,
+    /// When the wallet was added
+    #[default]
+    CreatedAt,
+}
+
+/// Conditions a listed wallet must meet
+#[derive(Debug, Clone, Default)]
+pub struct WalletFilter {
+    /// Only wallets whose name contains this text, ignoring case
+    pub name_contains: Option<String>,
+    /// Only wallets added at or after this time
+    pub created_after: Option<DateTime<Utc>>,
+    /// Only wallets added before this time
+    pub created_before: Option<DateTime<Utc>>,
+}
+
+/// A page of a user's wallets to fetch
+#[derive(Debug, Clone, Default)]
+pub struct WalletQuery {
+    /// Conditions the wallets must meet
+    pub filter: WalletFilter,
+    /// Column to sort by
+    pub sort: WalletSort,
+    /// Sort direction
+    pub order: SortOrder,
+    /// Only wallets strictly after this position in the default order, newest first
+    pub cursor: Option<Cursor>,
+    /// Maximum number of wallets to return
+    pub limit: i64,
+    /// Number of matching wallets to skip
+    pub offset: i64,
+}
+
+/// A page of a wallet's transactions to fetch, newest first
+#[derive(Debug, Clone, Default)]
+pub struct TransactionQuery {
+    /// Only transactions strictly after this position
+    pub cursor: Option<Cursor>,
+    /// Only transactions of this category
+    pub category: Option<TransactionCategory>,
+    /// Maximum number of transactions to return
+    pub limit: i64,
+}
+
+/// A balance change of a wallet to record, as ingested from the chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewTransaction {
+    /// Signature of the on-chain transaction
+    pub transaction_hash: String,
+    /// Slot the transaction was processed in
+    pub block_number: i64,
+    /// When the transaction was processed on-chain, if known
+    pub block_time: Option<DateTime<Utc>>,
+    /// Mint address of the token
+    pub token_address: String,
+    /// Token symbol, empty if unknown
+    pub token_symbol: String,
+    /// Signed token amount
+    pub amount: TokenAmount,
+    /// Category assigned by the classifier
+    pub category: TransactionCategory,
+    /// The decoded swap of the transaction, if any
+    pub swap: Option<SwapDetails>,
+    /// Account on the other side of the balance change, if apparent
+    pub counterparty: Option<String>,
+}
+
+/// Transactions read one by one as they arrive from storage
+pub type TransactionStream<'a> =
+    Pin<Box<dyn Stream<Item = Result<Transaction, RepositoryError>> + Send + 'a>>;
+
+/// Storage of users' wallets
+///
+/// Every method is scoped to the owning user: wallets of other users are never
+/// returned or modified.
+#[async_trait]
+pub trait WalletRepository: Send + Sync {
+    /// Stores a new wallet for `user_id`
+    async fn create(&self, user_id: Uuid, wallet: &CreateWallet)
+        -> Result<Wallet, RepositoryError>;
+
+    /// Looks up a wallet by ID
+    async fn find(&self, user_id: Uuid, wallet_id: Uuid)
+        -> Result<Option<Wallet>, RepositoryError>;
+
+    /// Looks up a wallet by its base58 address
+    async fn find_by_address(
+        &self,
+        user_id: Uuid,
+        address: &str,
+    ) -> Result<Option<Wallet>, RepositoryError>;
+
+    /// Applies the changes to a wallet, returning `None` if it does not exist
+    async fn update(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+        changes: &UpdateWallet,
+    ) -> Result<Option<Wallet>, RepositoryError>;
+
+    /// Deletes a wallet with its transactions, returning whether it existed
+    async fn delete(&self, user_id: Uuid, wallet_id: Uuid) -> Result<bool, RepositoryError>;
+
+    /// Counts the wallets matching the filter
+    async fn count(&self, user_id: Uuid, filter: &WalletFilter) -> Result<i64, RepositoryError>;
+
+    /// Estimates the number of wallets matching the filter without counting them
+    ///
+    /// Backends without statistics to estimate from count exactly.
+    async fn estimate_count(
+        &self,
+        user_id: Uuid,
+        filter: &WalletFilter,
+    ) -> Result<i64, RepositoryError> {
+        self.count(user_id, filter).await
+    }
+
+    /// Fetches a page of the wallets matching the query
+    async fn list(
+        &self,
+        user_id: Uuid,
+        query: &WalletQuery,
+    ) -> Result<Vec<Wallet>, RepositoryError>;
+}
+
+/// Storage of wallets' recorded transactions
+#[async_trait]
+pub trait TransactionRepository: Send + Sync {
+    /// Fetches a page of a wallet's transactions, newest first
+    async fn list(
+        &self,
+        wallet_id: Uuid,
+        query: &TransactionQuery,
+    ) -> Result<Vec<Transaction>, RepositoryError>;
+
+    /// Streams all of a wallet's transactions after `cursor`, newest first, without
+    /// loading them into memory at once
+    async fn stream<'a>(
+        &'a self,
+        wallet_id: Uuid,
+        cursor: Option<Cursor>,
+        category: Option<TransactionCategory>,
+    ) -> Result<TransactionStream<'a>, RepositoryError>;
+
+    /// Records many balance changes of a wallet at once, returning how many were new
+    ///
+    /// Has the same idempotent semantics as syncing: a `(signature, mint)` already
+    /// recorded is rewritten only if its data changed, and repeated within `rows`
+    /// only its last occurrence is kept.
+    async fn bulk_insert(
+        &self,
+        wallet_id: Uuid,
+        rows: &[NewTransaction],
+    ) -> Result<u64, RepositoryError>;
+}
+
+/// Wallet repository backed by Postgres
+#[derive(Debug, Clone)]
+pub struct PgWalletRepository {
+    pool: PgPool,
+    read_pool: PgPool,
+}
+
+impl PgWalletRepository {
+    /// Creates a repository using the given pool for reads and writes
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            read_pool: pool.clone(),
+            pool,
+        }
+    }
+
+    /// Sends list and count queries to a read replica instead of the primary
+    pub fn with_read_pool(mut self, read_pool: PgPool) -> Self {
+        self.read_pool = read_pool;
+        self
+    }
+}
+
+/// Escapes the `LIKE` wildcards in user input so it matches literally
+fn escape_like(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('%', "\\%")
+        .replace('_', "\\_")
+}
+
+/// Filters shared by the count and the page query; every value is a bind parameter
+const WALLET_FILTERS: &str = r#"
+    WHERE user_id = $1
+      AND ($2::TEXT IS NULL OR name ILIKE $2 ESCAPE '\')
+      AND ($3::TIMESTAMPTZ IS NULL OR created_at >= $3)
+      AND ($4::TIMESTAMPTZ IS NULL OR created_at < $4)
+"#;
+
+impl WalletFilter {
+    /// `ILIKE` (or SQLite `LIKE`) pattern matching names that contain `name_contains`
+    fn name_pattern(&self) -> Option<String> {
+        self.name_contains
+            .as_deref()
+            .map(|text| format!("%{}%", escape_like(text)))
+    }
+}
+
+#[async_trait]
+impl WalletRepository for PgWalletRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        wallet: &CreateWallet,
+    ) -> Result<Wallet, RepositoryError> {
+        let now = Utc::now();
+
+        let wallet = sqlx::query_as::<_, Wallet>(
+            r#"
+            INSERT INTO wallets (
+                id, user_id, address, domain, name, notes, metadata, created_at, updated_at
+            )
+            VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+            RETURNING id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+            "#,
+        )
+        .bind(Uuid::now_v7())
+        .bind(user_id)
+        .bind(wallet.address.as_str())
+        .bind(&wallet.domain)
+        .bind(&wallet.name)
+        .bind(&wallet.notes)
+        .bind(&wallet.metadata)
+        .bind(now)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn find(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let wallet = sqlx::query_as::<_, Wallet>(
+            r#"
+            SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+            FROM wallets
+            WHERE id = $1 AND user_id = $2
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn find_by_address(
+        &self,
+        user_id: Uuid,
+        address: &str,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let wallet = sqlx::query_as::<_, Wallet>(
+            r#"
+            SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+            FROM wallets
+            WHERE address = $1 AND user_id = $2
+            "#,
+        )
+        .bind(address)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn update(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+        changes: &UpdateWallet,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let wallet = sqlx::query_as::<_, Wallet>(
+            r#"
+            UPDATE wallets
+            SET name = CASE WHEN $3 THEN $4 ELSE name END,
+                notes = CASE WHEN $5 THEN $6 ELSE notes END,
+                metadata = CASE WHEN $7 THEN $8 ELSE metadata END,
+                leaderboard_opt_out = COALESCE($9, leaderboard_opt_out)
+            WHERE id = $1 AND user_id = $2
+            RETURNING id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+            "#,
+        )
+        .bind(wallet_id)
+        .bind(user_id)
+        .bind(changes.name.is_some())
+        .bind(changes.name.clone().flatten())
+        .bind(changes.notes.is_some())
+        .bind(changes.notes.clone().flatten())
+        .bind(changes.metadata.is_some())
+        .bind(changes.metadata.clone().flatten())
+        .bind(changes.leaderboard_opt_out)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn delete(&self, user_id: Uuid, wallet_id: Uuid) -> Result<bool, RepositoryError> {
+        let result = sqlx::query("DELETE FROM wallets WHERE id = $1 AND user_id = $2")
+            .bind(wallet_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count(&self, user_id: Uuid, filter: &WalletFilter) -> Result<i64, RepositoryError> {
+        let total =
+            sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM wallets {WALLET_FILTERS}"))
+                .bind(user_id)
+                .bind(filter.name_pattern())
+                .bind(filter.created_after)
+                .bind(filter.created_before)
+                .fetch_one(&self.read_pool)
+                .await?;
+
+        Ok(total)
+    }
+
+    /// Reads the planner's row estimate for the count query, which costs about as
+    /// much as planning it however many wallets match
+    async fn estimate_count(
+        &self,
+        user_id: Uuid,
+        filter: &WalletFilter,
+    ) -> Result<i64, RepositoryError> {
+        let plan = sqlx::query_scalar::<_, serde_json::Value>(&format!(
+            "EXPLAIN (FORMAT JSON) SELECT 1 FROM wallets {WALLET_FILTERS}"
+        ))
+        .bind(user_id)
+        .bind(filter.name_pattern())
+        .bind(filter.created_after)
+        .bind(filter.created_before)
+        .fetch_one(&self.read_pool)
+        .await?;
+
+        let rows = plan[0]["Plan"]["Plan Rows"].as_f64().ok_or_else(|| {
+            RepositoryError::Backend("Query plan has no row estimate".to_string())
+        })?;
+        Ok(rows.round() as i64)
+    }
+
+    async fn list(
+        &self,
+        user_id: Uuid,
+        query: &WalletQuery,
+    ) -> Result<Vec<Wallet>, RepositoryError> {
+        // Only whitelisted columns and directions are interpolated
+        let direction = query.order.as_sql();
+        let order_by = match query.sort {
+            WalletSort::CreatedAt => format!("created_at {direction}, id {direction}"),
+            WalletSort::Name => {
+                format!("name {direction} NULLS LAST, created_at {direction}, id {direction}")
+            }
+        };
+
+        let wallets = sqlx::query_as::<_, Wallet>(&format!(
+            r#"
+            SELECT id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at
+            FROM wallets
+            {WALLET_FILTERS}
+              AND ($5::TIMESTAMPTZ IS NULL OR (created_at, id) < ($5, $6))
+            ORDER BY {order_by}
+            LIMIT $7 OFFSET $8
+            "#
+        ))
+        .bind(user_id)
+        .bind(query.filter.name_pattern())
+        .bind(query.filter.created_after)
+        .bind(query.filter.created_before)
+        .bind(query.cursor.map(|c| c.created_at))
+        .bind(query.cursor.map(|c| c.id))
+        .bind(query.limit)
+        .bind(query.offset)
+        .fetch_all(&self.read_pool)
+        .await?;
+
+        Ok(wallets)
+    }
+}
+
+/// Lists a wallet's transactions newest first, after an optional cursor
+const LIST_TRANSACTIONS_SQL: &str = r#"
+    SELECT t.id, t.token_address, t.token_symbol, t.amount,
+           t.buy_price_usd, t.transaction_hash, t.block_number,
+           t.block_time, t.category, t.swap, t.counterparty,
+           l.label AS counterparty_label, l.category AS counterparty_category, t.created_at
+    FROM transactions t
+    LEFT JOIN address_labels l ON l.address = t.counterparty
+    WHERE t.wallet_id = $1
+      AND ($2::TIMESTAMPTZ IS NULL OR (t.created_at, t.id) < ($2, $3))
+      AND ($4::TEXT IS NULL OR t.category = $4)
+    ORDER BY t.created_at DESC, t.id DESC
+    LIMIT $5
+"#;
+
+/// Quotes a value as a `COPY ... (FORMAT csv)` field, so it is never read as NULL
+fn copy_text(value: &str) -> String {
+    format!("\"{}\"", value.replace('"', "\"\""))
+}
+
+/// Encodes a row of the bulk load staging table as one line of CSV
+fn copy_row(out: &mut String, wallet_id: Uuid, row: &NewTransaction) {
+    use std::fmt::Write;
+
+    // An unquoted empty field is NULL
+    let block_time = row.block_time.map(|t| t.to_rfc3339()).unwrap_or_default();
+    let swap = row
+        .swap
+        .as_ref()
+        .map(|swap| copy_text(&serde_json::to_string(swap).unwrap_or_default()))
+        .unwrap_or_default();
+    let counterparty = row
+        .counterparty
+        .as_deref()
+        .map(copy_text)
+        .unwrap_or_default();
+    let _ = writeln!(
+        out,
+        "{},{},{},{},{},{},{},{},{},{},{}",
+        Uuid::now_v7(),
+        wallet_id,
+        copy_text(&row.token_address),
+        copy_text(&row.token_symbol),
+        row.amount,
+        copy_text(&row.transaction_hash),
+        row.block_number,
+        block_time,
+        copy_text(row.category.as_str()),
+        swap,
+        counterparty,
+    );
+}
+
+/// Bytes of CSV sent to the server per `COPY` data message
+const COPY_CHUNK_BYTES: usize = 1 << 20;
+
+/// Transaction repository backed by Postgres
+#[derive(Debug, Clone)]
+pub struct PgTransactionRepository {
+    pool: PgPool,
+    read_pool: PgPool,
+}
+
+impl PgTransactionRepository {
+    /// Creates a repository using the given pool for reads and writes
+    pub fn new(pool: PgPool) -> Self {
+        Self {
+            read_pool: pool.clone(),
+            pool,
+        }
+    }
+
+    /// Sends list and count queries to a read replica instead of the primary
+    pub fn with_read_pool(mut self, read_pool: PgPool) -> Self {
+        self.read_pool = read_pool;
+        self
+    }
+}
+
+#[async_trait]
+impl TransactionRepository for PgTransactionRepository {
+    async fn list(
+        &self,
+        wallet_id: Uuid,
+        query: &TransactionQuery,
+    ) -> Result<Vec<Transaction>, RepositoryError> {
+        let transactions = sqlx::query_as::<_, Transaction>(LIST_TRANSACTIONS_SQL)
+            .bind(wallet_id)
+            .bind(query.cursor.map(|c| c.created_at))
+            .bind(query.cursor.map(|c| c.id))
+            .bind(query.category.map(|c| c.as_str()))
+            .bind(query.limit)
+            .fetch_all(&self.read_pool)
+            .await?;
+
+        Ok(transactions)
+    }
+
+    /// Rows are read from a server-side cursor, holding a pooled connection until the
+    /// stream ends or is dropped
+    async fn stream<'a>(
+        &'a self,
+        wallet_id: Uuid,
+        cursor: Option<Cursor>,
+        category: Option<TransactionCategory>,
+    ) -> Result<TransactionStream<'a>, RepositoryError> {
+        let transactions = sqlx::query_as::<_, Transaction>(LIST_TRANSACTIONS_SQL)
+            .bind(wallet_id)
+            .bind(cursor.map(|c| c.created_at))
+            .bind(cursor.map(|c| c.id))
+            .bind(category.map(|c| c.as_str()))
+            // LIMIT NULL returns every row
+            .bind(None::<i64>)
+            .fetch(&self.read_pool)
+            .map(|row| row.map_err(RepositoryError::from));
+
+        Ok(Box::pin(transactions))
+    }
+
+    /// Rows are streamed into a temporary table with `COPY FROM STDIN` and merged
+    /// into `transactions` by a single statement, so the holdings triggers run once
+    /// for the whole batch. Everything happens in one database transaction.
+    async fn bulk_insert(
+        &self,
+        wallet_id: Uuid,
+        rows: &[NewTransaction],
+    ) -> Result<u64, RepositoryError> {
+        if rows.is_empty() {
+            return Ok(0);
+        }
+
+        let mut tx = self.pool.begin().await?;
+        sqlx::query(
+            r#"
+            CREATE TEMP TABLE transactions_staging (
+                seq BIGSERIAL,
+                id UUID NOT NULL,
+                wallet_id UUID NOT NULL,
+                token_address TEXT NOT NULL,
+                token_symbol TEXT NOT NULL,
+                amount NUMERIC NOT NULL,
+                transaction_hash TEXT NOT NULL,
+                block_number BIGINT NOT NULL,
+                block_time TIMESTAMPTZ,
+                category TEXT NOT NULL,
+                swap JSONB,
+                counterparty TEXT
+            ) ON COMMIT DROP
+            "#,
+        )
+        .execute(&mut *tx)
+        .await?;
+
+        let mut copy = tx
+            .copy_in_raw(
+                r#"
+                COPY transactions_staging (
+                    id, wallet_id, token_address, token_symbol, amount,
+                    transaction_hash, block_number, block_time, category, swap,
+                    counterparty
+                ) FROM STDIN (FORMAT csv)
+                "#,
+            )
+            .await?;
+        let mut chunk = String::new();
+        for row in rows {
+            copy_row(&mut chunk, wallet_id, row);
+            if chunk.len() >= COPY_CHUNK_BYTES {
+                copy.send(std::mem::take(&mut chunk).into_bytes()).await?;
+            }
+        }
+        if !chunk.is_empty() {
+            copy.send(chunk.into_bytes()).await?;
+        }
+        copy.finish().await?;
+
+        let inserted = sqlx::query_scalar::<_, i64>(
+            r#"
+            WITH written AS (
+                INSERT INTO transactions (
+                    id, wallet_id, token_address, token_symbol, amount,
+                    buy_price_usd, buy_price_sol, transaction_hash, block_number, block_time,
+                    category, swap, counterparty
+                )
+                SELECT DISTINCT ON (transaction_hash, token_address)
+                    id, wallet_id, token_address, token_symbol, amount,
+                    0, 0, transaction_hash, block_number, block_time, category, swap,
+                    counterparty
+                FROM transactions_staging
+                ORDER BY transaction_hash, token_address, seq DESC
+                ON CONFLICT ON CONSTRAINT transactions_wallet_hash_token_key DO UPDATE
+                SET amount = EXCLUDED.amount,
+                    block_number = EXCLUDED.block_number,
+                    block_time = EXCLUDED.block_time,
+                    category = EXCLUDED.category,
+                    swap = EXCLUDED.swap,
+                    counterparty = EXCLUDED.counterparty
+                WHERE (transactions.amount, transactions.block_number, transactions.block_time,
+                       transactions.category, transactions.swap, transactions.counterparty)
+                      IS DISTINCT FROM
+                      (EXCLUDED.amount, EXCLUDED.block_number, EXCLUDED.block_time,
+                       EXCLUDED.category, EXCLUDED.swap, EXCLUDED.counterparty)
+                RETURNING (xmax = 0) AS inserted
+            )
+            SELECT COUNT(*) FILTER (WHERE inserted) FROM written
+            "#,
+        )
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+        Ok(inserted as u64)
+    }
+}
@@ -0,0 +1,395 @@
+use std::str::FromStr;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+use sqlx::SqlitePool;
+use tokio_stream::StreamExt;
+use uuid::Uuid;
+
+use super::{
+    NewTransaction, RepositoryError, TransactionQuery, TransactionRepository, TransactionStream,
+    WalletFilter, WalletQuery, WalletRepository, WalletSort,
+};
+use crate::classify::{SwapDetails, TransactionCategory};
+use crate::models::{CreateWallet, Transaction, UpdateWallet, Wallet};
+use crate::money::{TokenAmount, Usd};
+use crate::pagination::Cursor;
+
+/// Wallet and transaction storage in a SQLite database
+///
+/// Behaves like the Postgres repositories, including ordering and cursor
+/// semantics, on the schema of `migrations_sqlite/`. Names are filtered with
+/// SQLite's `LIKE`, which ignores case only for ASCII letters, and there are no
+/// holdings triggers: holdings are computed by the Postgres-only modules.
+#[derive(Debug, Clone)]
+pub struct SqliteRepository {
+    pool: SqlitePool,
+}
+
+impl SqliteRepository {
+    /// Creates a repository using the given pool
+    pub fn new(pool: SqlitePool) -> Self {
+        Self { pool }
+    }
+}
+
+/// Filters shared by the count and the page query; every value is a bind parameter
+const WALLET_FILTERS: &str = r#"
+    WHERE user_id = ?1
+      AND (?2 IS NULL OR name LIKE ?2 ESCAPE '\')
+      AND (?3 IS NULL OR created_at >= ?3)
+      AND (?4 IS NULL OR created_at < ?4)
+"#;
+
+/// Columns of a wallet, in the order of [`Wallet`]
+const WALLET_COLUMNS: &str = "id, address, domain, name, notes, metadata, leaderboard_opt_out, last_synced_at, created_at, updated_at";
+
+/// Lists a wallet's transactions newest first, after an optional cursor
+const LIST_TRANSACTIONS_SQL: &str = r#"
+    SELECT t.id, t.token_address, t.token_symbol, t.amount,
+           t.buy_price_usd, t.transaction_hash, t.block_number,
+           t.block_time, t.category, t.swap, t.counterparty,
+           l.label AS counterparty_label, l.category AS counterparty_category, t.created_at
+    FROM transactions t
+    LEFT JOIN address_labels l ON l.address = t.counterparty
+    WHERE t.wallet_id = ?1
+      AND (?2 IS NULL OR (t.created_at, t.id) < (?2, ?3))
+      AND (?4 IS NULL OR t.category = ?4)
+    ORDER BY t.created_at DESC, t.id DESC
+    LIMIT ?5
+"#;
+
+/// A transaction as stored in SQLite, which has no decimal type
+#[derive(sqlx::FromRow)]
+struct TransactionRow {
+    id: Uuid,
+    token_address: String,
+    token_symbol: String,
+    amount: String,
+    buy_price_usd: String,
+    transaction_hash: String,
+    block_number: i64,
+    block_time: Option<DateTime<Utc>>,
+    category: Option<String>,
+    #[sqlx(json(nullable))]
+    swap: Option<SwapDetails>,
+    counterparty: Option<String>,
+    counterparty_label: Option<String>,
+    counterparty_category: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+impl TryFrom<TransactionRow> for Transaction {
+    type Error = RepositoryError;
+
+    fn try_from(row: TransactionRow) -> Result<Self, Self::Error> {
+        let decimal = |text: &str| {
+            Decimal::from_str(text).map_err(|err| {
+                RepositoryError::Backend(format!("Invalid amount {text:?} stored: {err}"))
+            })
+        };
+
+        Ok(Transaction {
+            id: row.id,
+            token_address: row.token_address,
+            token_symbol: row.token_symbol,
+            token_name: None,
+            logo_uri: None,
+            amount: TokenAmount::new(decimal(&row.amount)?),
+            buy_price_usd: Usd::new(decimal(&row.buy_price_usd)?),
+            transaction_hash: row.transaction_hash,
+            block_number: row.block_number,
+            block_time: row.block_time,
+            category: row.category,
+            swap: row.swap,
+            counterparty: row.counterparty,
+            counterparty_label: row.counterparty_label,
+            counterparty_category: row.counterparty_category,
+            created_at: row.created_at,
+        })
+    }
+}
+
+/// Binds the wallet filters to `?1` to `?4`
+fn bind_filter<'q, O>(
+    query: sqlx::query::QueryAs<'q, sqlx::Sqlite, O, sqlx::sqlite::SqliteArguments<'q>>,
+    user_id: Uuid,
+    filter: &WalletFilter,
+) -> sqlx::query::QueryAs<'q, sqlx::Sqlite, O, sqlx::sqlite::SqliteArguments<'q>> {
+    query
+        .bind(user_id)
+        .bind(filter.name_pattern())
+        .bind(filter.created_after)
+        .bind(filter.created_before)
+}
+
+#[async_trait]
+impl WalletRepository for SqliteRepository {
+    async fn create(
+        &self,
+        user_id: Uuid,
+        wallet: &CreateWallet,
+    ) -> Result<Wallet, RepositoryError> {
+        let now = Utc::now();
+
+        let wallet = sqlx::query_as::<_, Wallet>(&format!(
+            r#"
+            INSERT INTO wallets (
+                id, user_id, address, domain, name, notes, metadata, created_at, updated_at
+            )
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?8)
+            RETURNING {WALLET_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::now_v7())
+        .bind(user_id)
+        .bind(wallet.address.as_str())
+        .bind(&wallet.domain)
+        .bind(&wallet.name)
+        .bind(&wallet.notes)
+        .bind(&wallet.metadata)
+        .bind(now)
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn find(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let wallet = sqlx::query_as::<_, Wallet>(&format!(
+            "SELECT {WALLET_COLUMNS} FROM wallets WHERE id = ?1 AND user_id = ?2"
+        ))
+        .bind(wallet_id)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn find_by_address(
+        &self,
+        user_id: Uuid,
+        address: &str,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let wallet = sqlx::query_as::<_, Wallet>(&format!(
+            "SELECT {WALLET_COLUMNS} FROM wallets WHERE address = ?1 AND user_id = ?2"
+        ))
+        .bind(address)
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn update(
+        &self,
+        user_id: Uuid,
+        wallet_id: Uuid,
+        changes: &UpdateWallet,
+    ) -> Result<Option<Wallet>, RepositoryError> {
+        let wallet = sqlx::query_as::<_, Wallet>(&format!(
+            r#"
+            UPDATE wallets
+            SET name = CASE WHEN ?3 THEN ?4 ELSE name END,
+                notes = CASE WHEN ?5 THEN ?6 ELSE notes END,
+                metadata = CASE WHEN ?7 THEN ?8 ELSE metadata END,
+                leaderboard_opt_out = COALESCE(?9, leaderboard_opt_out),
+                updated_at = ?10
+            WHERE id = ?1 AND user_id = ?2
+            RETURNING {WALLET_COLUMNS}
+            "#
+        ))
+        .bind(wallet_id)
+        .bind(user_id)
+        .bind(changes.name.is_some())
+        .bind(changes.name.clone().flatten())
+        .bind(changes.notes.is_some())
+        .bind(changes.notes.clone().flatten())
+        .bind(changes.metadata.is_some())
+        .bind(changes.metadata.clone().flatten())
+        .bind(changes.leaderboard_opt_out)
+        .bind(Utc::now())
+        .fetch_optional(&self.pool)
+        .await?;
+
+        Ok(wallet)
+    }
+
+    async fn delete(&self, user_id: Uuid, wallet_id: Uuid) -> Result<bool, RepositoryError> {
+        // The pool enables foreign keys, so the transactions are deleted with the wallet
+        let result = sqlx::query("DELETE FROM wallets WHERE id = ?1 AND user_id = ?2")
+            .bind(wallet_id)
+            .bind(user_id)
+            .execute(&self.pool)
+            .await?;
+
+        Ok(result.rows_affected() > 0)
+    }
+
+    async fn count(&self, user_id: Uuid, filter: &WalletFilter) -> Result<i64, RepositoryError> {
+        let (total,) = bind_filter(
+            sqlx::query_as::<_, (i64,)>(&format!("SELECT COUNT(*) FROM wallets {WALLET_FILTERS}")),
+            user_id,
+            filter,
+        )
+        .fetch_one(&self.pool)
+        .await?;
+
+        Ok(total)
+    }
+
+    async fn list(
+        &self,
+        user_id: Uuid,
+        query: &WalletQuery,
+    ) -> Result<Vec<Wallet>, RepositoryError> {
+        // Only whitelisted columns and directions are interpolated
+        let direction = query.order.as_sql();
+        let order_by = match query.sort {
+            WalletSort::CreatedAt => format!("created_at {direction}, id {direction}"),
+            WalletSort::Name => {
+                format!("name {direction} NULLS LAST, created_at {direction}, id {direction}")
+            }
+        };
+
+        let sql = format!(
+            r#"
+            SELECT {WALLET_COLUMNS}
+            FROM wallets
+            {WALLET_FILTERS}
+              AND (?5 IS NULL OR (created_at, id) < (?5, ?6))
+            ORDER BY {order_by}
+            LIMIT ?7 OFFSET ?8
+            "#
+        );
+        let wallets = bind_filter(sqlx::query_as::<_, Wallet>(&sql), user_id, &query.filter)
+            .bind(query.cursor.map(|c| c.created_at))
+            .bind(query.cursor.map(|c| c.id))
+            .bind(query.limit)
+            .bind(query.offset)
+            .fetch_all(&self.pool)
+            .await?;
+
+        Ok(wallets)
+    }
+}
+
+#[async_trait]
+impl TransactionRepository for SqliteRepository {
+    async fn list(
+        &self,
+        wallet_id: Uuid,
+        query: &TransactionQuery,
+    ) -> Result<Vec<Transaction>, RepositoryError> {
+        sqlx::query_as::<_, TransactionRow>(LIST_TRANSACTIONS_SQL)
+            .bind(wallet_id)
+            .bind(query.cursor.map(|c| c.created_at))
+            .bind(query.cursor.map(|c| c.id))
+            .bind(query.category.map(|c| c.as_str()))
+            .bind(query.limit)
+            .fetch_all(&self.pool)
+            .await?
+            .into_iter()
+            .map(Transaction::try_from)
+            .collect()
+    }
+
+    async fn stream<'a>(
+        &'a self,
+        wallet_id: Uuid,
+        cursor: Option<Cursor>,
+        category: Option<TransactionCategory>,
+    ) -> Result<TransactionStream<'a>, RepositoryError> {
+        let transactions = sqlx::query_as::<_, TransactionRow>(LIST_TRANSACTIONS_SQL)
+            .bind(wallet_id)
+            .bind(cursor.map(|c| c.created_at))
+            .bind(cursor.map(|c| c.id))
+            .bind(category.map(|c| c.as_str()))
+            // A negative LIMIT returns every row
+            .bind(-1i64)
+            .fetch(&self.pool)
+            .map(|row| Transaction::try_from(row?));
+
+        Ok(Box::pin(transactions))
+    }
+
+    /// Rows are upserted one by one in a single database transaction, which SQLite
+    /// commits about as fast as one statement
+    async fn bulk_insert(
+        &self,
+        wallet_id: Uuid,
+        rows: &[NewTransaction],
+    ) -> Result<u64, RepositoryError> {
+        let mut tx = self.pool.begin().await?;
+        let now = Utc::now();
+
+        let mut inserted = 0;
+        for row in rows {
+            let existing = sqlx::query_as::<_, (i64,)>(
+                r#"
+                SELECT COUNT(*) FROM transactions
+                WHERE wallet_id = ?1 AND transaction_hash = ?2 AND token_address = ?3
+                "#,
+            )
+            .bind(wallet_id)
+            .bind(&row.transaction_hash)
+            .bind(&row.token_address)
+            .fetch_one(&mut *tx)
+            .await?;
+
+            // A later occurrence of the same balance change within `rows` overwrites it
+            sqlx::query(
+                r#"
+                INSERT INTO transactions (
+                    id, wallet_id, token_address, token_symbol, amount,
+                    transaction_hash, block_number, block_time, category, swap,
+                    counterparty, created_at, updated_at
+                )
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?12)
+                ON CONFLICT (wallet_id, transaction_hash, token_address) DO UPDATE
+                SET amount = excluded.amount,
+                    block_number = excluded.block_number,
+                    block_time = excluded.block_time,
+                    category = excluded.category,
+                    swap = excluded.swap,
+                    counterparty = excluded.counterparty,
+                    updated_at = excluded.updated_at
+                WHERE transactions.amount IS NOT excluded.amount
+                   OR transactions.block_number IS NOT excluded.block_number
+                   OR transactions.block_time IS NOT excluded.block_time
+                   OR transactions.category IS NOT excluded.category
+                   OR transactions.swap IS NOT excluded.swap
+                   OR transactions.counterparty IS NOT excluded.counterparty
+                "#,
+            )
+            .bind(Uuid::now_v7())
+            .bind(wallet_id)
+            .bind(&row.token_address)
+            .bind(&row.token_symbol)
+            .bind(row.amount.decimal().normalize().to_string())
+            .bind(&row.transaction_hash)
+            .bind(row.block_number)
+            .bind(row.block_time)
+            .bind(row.category.as_str())
+            .bind(row.swap.as_ref().map(sqlx::types::Json))
+            .bind(&row.counterparty)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+
+            if existing.0 == 0 {
+                inserted += 1;
+            }
+        }
+
+        tx.commit().await?;
+        Ok(inserted)
+    }
+}
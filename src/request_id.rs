@@ -0,0 +1,74 @@
+use axum::{
+    http::{HeaderValue, Request},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{info_span, Instrument};
+use uuid::Uuid;
+
+/// Header carrying the request ID, both on requests and responses
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Longest client-supplied request ID that is propagated instead of replaced
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// Request ID of the request being handled by the current task
+    static CURRENT_REQUEST_ID: RequestId;
+}
+
+/// Identifier of a single request, propagated from `X-Request-Id` or generated
+///
+/// Available to handlers as a request extension and to any code running within
+/// the request via [`current`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestId(pub String);
+
+impl RequestId {
+    /// Uses the client's `X-Request-Id` if it is a sensible value, otherwise generates one
+    fn from_header(value: Option<&HeaderValue>) -> Self {
+        value
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|id| {
+                !id.is_empty()
+                    && id.len() <= MAX_REQUEST_ID_LEN
+                    && id.bytes().all(|b| b.is_ascii_graphic())
+            })
+            .map(|id| Self(id.to_string()))
+            .unwrap_or_else(|| Self(Uuid::now_v7().to_string()))
+    }
+}
+
+/// Request ID of the request currently being handled, if any
+pub fn current() -> Option<String> {
+    CURRENT_REQUEST_ID.try_with(|id| id.0.clone()).ok()
+}
+
+/// Middleware assigning every request an ID
+///
+/// The ID is attached to the tracing span of the request, exposed through
+/// [`current`] while the request is handled, and echoed in the `X-Request-Id`
+/// response header.
+pub async fn request_id_middleware<B>(mut request: Request<B>, next: Next<B>) -> Response {
+    let request_id = RequestId::from_header(request.headers().get(REQUEST_ID_HEADER));
+    request.extensions_mut().insert(request_id.clone());
+
+    let span = info_span!(
+        "request",
+        request_id = %request_id.0,
+        method = %request.method(),
+        uri = %request.uri(),
+    );
+
+    let header = HeaderValue::from_str(&request_id.0).ok();
+    let mut response = CURRENT_REQUEST_ID
+        .scope(request_id, next.run(request).instrument(span))
+        .await;
+
+    if let Some(header) = header {
+        response.headers_mut().insert(REQUEST_ID_HEADER, header);
+    }
+
+    response
+}
@@ -0,0 +1,196 @@
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+use tracing::warn;
+
+use crate::AppError;
+
+/// Consecutive failures after which a breaker opens, unless configured otherwise
+pub const DEFAULT_FAILURE_THRESHOLD: u32 = 5;
+
+/// How long an open breaker rejects calls, unless configured otherwise
+pub const DEFAULT_OPEN_DURATION: Duration = Duration::from_secs(30);
+
+/// A call was rejected without being attempted because its breaker is open
+#[derive(Debug, Clone, Error)]
+#[error("{name} is unavailable, retry in {}s", retry_in.as_secs().max(1))]
+pub struct CircuitOpen {
+    /// Name of the integration behind the breaker
+    pub name: String,
+    /// Time until the breaker lets a trial call through
+    pub retry_in: Duration,
+}
+
+impl From<CircuitOpen> for AppError {
+    fn from(err: CircuitOpen) -> Self {
+        AppError::ServiceUnavailable(err.to_string())
+    }
+}
+
+/// State of a breaker
+#[derive(Debug, Clone, Copy)]
+enum State {
+    /// Calls go through; counts the failures since the last success
+    Closed { failures: u32 },
+    /// Calls are rejected until the given time
+    Open { until: Instant },
+    /// A trial call started at the given time decides whether to close again
+    HalfOpen { trial_started: Instant },
+}
+
+/// Circuit breaker guarding calls to an external integration
+///
+/// After `failure_threshold` consecutive failures the breaker opens and rejects
+/// calls with [`CircuitOpen`] for `open_for`, so an outage fails fast instead of
+/// tying up requests until they time out. A single trial call is then let through:
+/// success closes the breaker, failure opens it again. Callers decide what counts
+/// as a failure, e.g. timeouts and server errors but not "not found".
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    name: String,
+    failure_threshold: u32,
+    open_for: Duration,
+    state: Mutex<State>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker; a threshold of `0` never opens it
+    pub fn new(name: impl Into<String>, failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            name: name.into(),
+            failure_threshold,
+            open_for,
+            state: Mutex::new(State::Closed { failures: 0 }),
+        }
+    }
+
+    /// Name of the integration behind the breaker
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether calls are currently rejected
+    pub fn is_open(&self) -> bool {
+        let state = *self.lock();
+        match state {
+            State::Closed { .. } => false,
+            State::Open { until } => Instant::now() < until,
+            State::HalfOpen { .. } => true,
+        }
+    }
+
+    /// Claims permission for a call, which must be followed by [`Self::record`]
+    pub fn permit(&self) -> Result<(), CircuitOpen> {
+        let now = Instant::now();
+        let mut state = self.lock();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now < until => Err(self.open_error(until - now)),
+            // A trial whose caller never reported back does not block forever
+            State::HalfOpen { trial_started } if now < trial_started + self.open_for => {
+                Err(self.open_error(trial_started + self.open_for - now))
+            }
+            State::Open { .. } | State::HalfOpen { .. } => {
+                *state = State::HalfOpen { trial_started: now };
+                Ok(())
+            }
+        }
+    }
+
+    /// Records the outcome of a permitted call
+    pub fn record(&self, success: bool) {
+        let mut state = self.lock();
+        *state = match (*state, success) {
+            (_, true) => State::Closed { failures: 0 },
+            (State::Closed { .. }, false) if self.failure_threshold == 0 => {
+                State::Closed { failures: 0 }
+            }
+            (State::Closed { failures }, false) if failures + 1 < self.failure_threshold => {
+                State::Closed {
+                    failures: failures + 1,
+                }
+            }
+            (_, false) => {
+                warn!(
+                    "Circuit breaker for {} opened for {:?}",
+                    self.name, self.open_for
+                );
+                State::Open {
+                    until: Instant::now() + self.open_for,
+                }
+            }
+        };
+    }
+
+    /// Runs `call` unless the breaker is open, counting it as failed when
+    /// `is_failure` says so
+    pub async fn call<T, E, Fut>(
+        &self,
+        call: impl FnOnce() -> Fut,
+        is_failure: impl FnOnce(&E) -> bool,
+    ) -> Result<Result<T, E>, CircuitOpen>
+    where
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.permit()?;
+        let result = call().await;
+        let failed = result.as_ref().err().is_some_and(is_failure);
+        self.record(!failed);
+        Ok(result)
+    }
+
+    fn open_error(&self, retry_in: Duration) -> CircuitOpen {
+        CircuitOpen {
+            name: self.name.clone(),
+            retry_in,
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|err| err.into_inner())
+    }
+}
+
+/// Breakers created on demand per key, e.g. per host of a webhook subscriber, so
+/// one failing destination does not cut off the others
+#[derive(Debug)]
+pub struct CircuitBreakers {
+    failure_threshold: u32,
+    open_for: Duration,
+    breakers: Mutex<HashMap<String, Arc<CircuitBreaker>>>,
+}
+
+impl CircuitBreakers {
+    /// Creates an empty set whose breakers use the given settings
+    pub fn new(failure_threshold: u32, open_for: Duration) -> Self {
+        Self {
+            failure_threshold,
+            open_for,
+            breakers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// The breaker for `key`, created closed on first use
+    pub fn get(&self, key: &str) -> Arc<CircuitBreaker> {
+        let mut breakers = self.breakers.lock().unwrap_or_else(|err| err.into_inner());
+        breakers
+            .entry(key.to_string())
+            .or_insert_with(|| {
+                Arc::new(CircuitBreaker::new(
+                    key,
+                    self.failure_threshold,
+                    self.open_for,
+                ))
+            })
+            .clone()
+    }
+}
+
+impl Default for CircuitBreakers {
+    fn default() -> Self {
+        Self::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_OPEN_DURATION)
+    }
+}
@@ -0,0 +1,221 @@
+//! Data retention: periodic pruning of old rows.
+//!
+//! Four kinds of data grow without bound and lose their value with age: finished
+//! outgoing webhook deliveries with their raw payloads, dead-lettered deliveries and
+//! notifications with the jobs that carried them, daily wallet snapshots, and
+//! transactions of archived wallets. Each is kept for a configured number of months, or
+//! forever if that is `0`. In a dry run the rows are only counted. Every run is recorded
+//! in `retention_runs` with the number of rows it pruned per kind.
+//!
+//! An *archived* wallet is one no user owns, `wallets.user_id IS NULL`, because it was
+//! tracked before multi-user support. There is no separate archived flag.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::scheduler::{self, LeaderLock};
+
+/// Finished webhook deliveries older than `$1` months
+const WEBHOOK_DELIVERIES: &str = r#"
+    webhook_deliveries
+    WHERE status <> 'pending'
+      AND created_at < NOW() - make_interval(months => $1)
+"#;
+
+/// Dead letters given up on more than `$1` months ago, retried or not
+const DEAD_LETTERS: &str = r#"
+    dead_letters
+    WHERE failed_at < NOW() - make_interval(months => $1)
+"#;
+
+/// Snapshots of days more than `$1` months ago
+const SNAPSHOTS: &str = r#"
+    snapshots
+    WHERE snapshot_date < (NOW() - make_interval(months => $1))::DATE
+"#;
+
+/// Transactions of archived wallets made more than `$1` months ago
+const ARCHIVED_TRANSACTIONS: &str = r#"
+    transactions
+    WHERE wallet_id IN (SELECT id FROM wallets WHERE user_id IS NULL)
+      AND COALESCE(block_time, created_at) < NOW() - make_interval(months => $1)
+"#;
+
+/// How long each kind of data is kept
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RetentionPolicy {
+    /// Months finished webhook deliveries are kept; `0` keeps them forever
+    pub webhook_delivery_months: u32,
+    /// Months dead letters are kept; `0` keeps them forever
+    pub dead_letter_months: u32,
+    /// Months daily wallet snapshots are kept; `0` keeps them forever
+    pub snapshot_months: u32,
+    /// Months transactions of archived wallets are kept; `0` keeps them forever
+    pub archived_transaction_months: u32,
+    /// Whether rows are only counted, not deleted
+    pub dry_run: bool,
+}
+
+/// Rows a run of the retention job pruned, or would have in a dry run
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct RetentionReport {
+    /// Whether rows were only counted, not deleted
+    pub dry_run: bool,
+    /// Finished webhook deliveries
+    pub webhook_deliveries: i64,
+    /// Dead letters
+    pub dead_letters: i64,
+    /// Daily wallet snapshots
+    pub snapshots: i64,
+    /// Transactions of archived wallets
+    pub transactions: i64,
+}
+
+/// A recorded run of the retention job
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct RetentionRun {
+    /// Rows the run pruned
+    #[serde(flatten)]
+    #[sqlx(flatten)]
+    pub report: RetentionReport,
+    /// When the run finished
+    pub created_at: DateTime<Utc>,
+}
+
+/// Deletes, or in a dry run counts, the rows of `rows` older than `months`
+async fn prune_rows(
+    pool: &PgPool,
+    rows: &str,
+    months: u32,
+    dry_run: bool,
+) -> Result<i64, sqlx::Error> {
+    if months == 0 {
+        return Ok(0);
+    }
+    let months = i32::try_from(months).unwrap_or(i32::MAX);
+
+    if dry_run {
+        return sqlx::query_scalar::<_, i64>(&format!("SELECT COUNT(*) FROM {rows}"))
+            .bind(months)
+            .fetch_one(pool)
+            .await;
+    }
+    let result = sqlx::query(&format!("DELETE FROM {rows}"))
+        .bind(months)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() as i64)
+}
+
+/// Applies the policy once, records the run and returns what it pruned
+///
+/// Holdings of archived wallets are left as they were; `rebuild-holdings` recomputes
+/// them from the remaining transactions.
+pub async fn prune(
+    pool: &PgPool,
+    policy: &RetentionPolicy,
+) -> Result<RetentionReport, sqlx::Error> {
+    let dry_run = policy.dry_run;
+    let report = RetentionReport {
+        dry_run,
+        webhook_deliveries: prune_rows(
+            pool,
+            WEBHOOK_DELIVERIES,
+            policy.webhook_delivery_months,
+            dry_run,
+        )
+        .await?,
+        dead_letters: prune_rows(pool, DEAD_LETTERS, policy.dead_letter_months, dry_run).await?,
+        snapshots: prune_rows(pool, SNAPSHOTS, policy.snapshot_months, dry_run).await?,
+        transactions: prune_rows(
+            pool,
+            ARCHIVED_TRANSACTIONS,
+            policy.archived_transaction_months,
+            dry_run,
+        )
+        .await?,
+    };
+
+    sqlx::query(
+        r#"
+        INSERT INTO retention_runs
+            (id, dry_run, webhook_deliveries, dead_letters, snapshots, transactions)
+        VALUES ($1, $2, $3, $4, $5, $6)
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(report.dry_run)
+    .bind(report.webhook_deliveries)
+    .bind(report.dead_letters)
+    .bind(report.snapshots)
+    .bind(report.transactions)
+    .execute(pool)
+    .await?;
+
+    Ok(report)
+}
+
+/// The most recent run of the retention job, if it ever ran
+pub async fn last_run(pool: &PgPool) -> Result<Option<RetentionRun>, sqlx::Error> {
+    sqlx::query_as::<_, RetentionRun>(
+        r#"
+        SELECT dry_run, webhook_deliveries, dead_letters, snapshots, transactions, created_at
+        FROM retention_runs
+        ORDER BY created_at DESC, id DESC
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+/// Spawns the background task applying the retention policy every `interval`
+///
+/// Only the instance holding `leader` prunes.
+pub fn spawn_retention_job(
+    pool: PgPool,
+    policy: RetentionPolicy,
+    interval: Duration,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!(
+        "Starting data retention job with interval {:?}: {:?}",
+        interval, policy
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if !scheduler::is_leader(&leader).await {
+                continue;
+            }
+
+            match prune(&pool, &policy).await {
+                Ok(report) => info!(
+                    "{} {} webhook deliveries, {} dead letters, {} snapshots and {} archived transactions",
+                    if report.dry_run {
+                        "Retention dry run would prune"
+                    } else {
+                        "Pruned"
+                    },
+                    report.webhook_deliveries,
+                    report.dead_letters,
+                    report.snapshots,
+                    report.transactions
+                ),
+                Err(err) => error!("Data retention run failed: {}", err),
+            }
+        }
+    })
+}
@@ -0,0 +1,338 @@
+//! Token risk scoring ("rug check").
+//!
+//! A mint's risk score is computed from on-chain facts read through the Solana RPC:
+//! whether the mint and freeze authorities are still set, how much of the supply the
+//! ten largest holders own, and whether any of the supply sits in a liquidity pool of
+//! a known AMM. Each fact that looks risky adds points to the score, from `0` (none)
+//! to `100` (all). Scores are cached for `TOKEN_RISK_TTL_SECS`.
+//!
+//! Pools are recognised by the program owning the holder of a token account, so
+//! liquidity on an AMM not listed in [`AMM_PROGRAMS`] counts as a regular holder.
+//! Whether LP tokens are burned or locked is not checked.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::cache::{self, Cache};
+use crate::sync::{format_units, SolanaRpcClient};
+use crate::AppError;
+
+/// Programs owning the accounts that hold AMM pool liquidity
+pub const AMM_PROGRAMS: &[&str] = &[
+    // Raydium AMM v4, CPMM and CLMM
+    "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8",
+    "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C",
+    "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK",
+    // Orca Whirlpools
+    "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc",
+    // Meteora DLMM and dynamic AMM
+    "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo",
+    "Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB",
+    // Pump.fun bonding curves
+    "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P",
+];
+
+/// Pool authorities that are not accounts owned by their AMM program
+const AMM_AUTHORITIES: &[&str] = &[
+    // Raydium AMM v4
+    "5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1",
+];
+
+/// Points added while the mint authority can mint more tokens
+const MINT_AUTHORITY_POINTS: u8 = 30;
+
+/// Points added while the freeze authority can freeze holders' accounts
+const FREEZE_AUTHORITY_POINTS: u8 = 25;
+
+/// Points added when no liquidity pool holds the token
+const NO_POOL_POINTS: u8 = 20;
+
+/// Points added when the top 10 holders own at least half of the supply
+const CONCENTRATED_POINTS: u8 = 25;
+
+/// Points added when the top 10 holders own at least a quarter of the supply
+const SOMEWHAT_CONCENTRATED_POINTS: u8 = 10;
+
+/// Number of largest holders whose share of the supply is reported
+const TOP_HOLDERS: usize = 10;
+
+/// Cache key of a mint's risk score
+fn risk_key(mint: &str) -> String {
+    format!("token_risk:{mint}")
+}
+
+/// Overall risk of a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskLevel {
+    /// Score below 25
+    Low,
+    /// Score from 25 to 49
+    Medium,
+    /// Score of 50 or more
+    High,
+}
+
+impl RiskLevel {
+    /// Level of a risk score
+    pub fn from_score(score: u8) -> Self {
+        match score {
+            0..=24 => Self::Low,
+            25..=49 => Self::Medium,
+            _ => Self::High,
+        }
+    }
+}
+
+/// Whether any of a token's supply is held by a liquidity pool
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LpStatus {
+    /// A pool of a known AMM is among the largest holders
+    Pooled,
+    /// No pool of a known AMM is among the largest holders
+    NotFound,
+}
+
+/// A risky fact found about a token
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum RiskFactorKind {
+    /// The mint authority can mint more tokens
+    MintAuthority,
+    /// The freeze authority can freeze holders' accounts
+    FreezeAuthority,
+    /// The largest holders own much of the supply
+    HolderConcentration,
+    /// The token is not in a known liquidity pool
+    NoLiquidityPool,
+}
+
+/// A risky fact and the points it adds to the score
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct RiskFactor {
+    /// What was found
+    pub kind: RiskFactorKind,
+    /// Points added to the score
+    #[schema(example = 30)]
+    pub points: u8,
+    /// Human-readable explanation
+    #[schema(example = "The mint authority can mint more tokens")]
+    pub description: String,
+}
+
+/// On-chain facts a risk score is computed from
+#[derive(Debug, Clone, PartialEq)]
+pub struct RiskFacts {
+    /// Mint authority, if set
+    pub mint_authority: Option<String>,
+    /// Freeze authority, if set
+    pub freeze_authority: Option<String>,
+    /// Percentage of the supply owned by the 10 largest holders other than pools
+    pub top_10_holder_percent: f64,
+    /// Percentage of the supply held by pools of known AMMs
+    pub liquidity_pool_percent: f64,
+}
+
+/// Risk assessment of a token mint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+pub struct TokenRisk {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub mint: String,
+    /// Sum of the points of the risk factors, from 0 to 100
+    #[schema(example = 20)]
+    pub score: u8,
+    /// Overall risk
+    pub level: RiskLevel,
+    /// Risky facts found, each adding to the score
+    pub factors: Vec<RiskFactor>,
+    /// Address allowed to mint more tokens, if any
+    pub mint_authority: Option<String>,
+    /// Address allowed to freeze holders' accounts, if any
+    pub freeze_authority: Option<String>,
+    /// Total supply as a decimal string
+    #[schema(example = "88867210751446.33")]
+    pub supply: String,
+    /// Number of decimals of the mint
+    #[schema(example = 5)]
+    pub decimals: u32,
+    /// Percentage of the supply owned by the 10 largest holders other than pools
+    #[schema(example = 31.4)]
+    pub top_10_holder_percent: f64,
+    /// Whether a liquidity pool of a known AMM holds the token
+    pub lp_status: LpStatus,
+    /// Percentage of the supply held by pools of known AMMs
+    #[schema(example = 2.5)]
+    pub liquidity_pool_percent: f64,
+    /// When the on-chain facts were read
+    pub checked_at: DateTime<Utc>,
+}
+
+/// Scores a token's on-chain facts
+pub fn score(facts: &RiskFacts) -> Vec<RiskFactor> {
+    let mut factors = Vec::new();
+    if facts.mint_authority.is_some() {
+        factors.push(RiskFactor {
+            kind: RiskFactorKind::MintAuthority,
+            points: MINT_AUTHORITY_POINTS,
+            description: "The mint authority can mint more tokens".to_string(),
+        });
+    }
+    if facts.freeze_authority.is_some() {
+        factors.push(RiskFactor {
+            kind: RiskFactorKind::FreezeAuthority,
+            points: FREEZE_AUTHORITY_POINTS,
+            description: "The freeze authority can freeze holders' accounts".to_string(),
+        });
+    }
+    let concentration_points = match facts.top_10_holder_percent {
+        percent if percent >= 50.0 => CONCENTRATED_POINTS,
+        percent if percent >= 25.0 => SOMEWHAT_CONCENTRATED_POINTS,
+        _ => 0,
+    };
+    if concentration_points > 0 {
+        factors.push(RiskFactor {
+            kind: RiskFactorKind::HolderConcentration,
+            points: concentration_points,
+            description: format!(
+                "The top {TOP_HOLDERS} holders own {:.1}% of the supply",
+                facts.top_10_holder_percent
+            ),
+        });
+    }
+    if facts.liquidity_pool_percent == 0.0 {
+        factors.push(RiskFactor {
+            kind: RiskFactorKind::NoLiquidityPool,
+            points: NO_POOL_POINTS,
+            description: "No liquidity pool of a known AMM holds the token".to_string(),
+        });
+    }
+    factors
+}
+
+/// Returns the risk assessment of `mint`, served from `cache` if it was computed
+/// within `ttl`
+///
+/// Returns `None` if the account does not exist, and `422 Unprocessable Entity` if it
+/// is not a token mint.
+pub async fn token_risk(
+    rpc: &SolanaRpcClient,
+    cache: &dyn Cache,
+    mint: &str,
+    ttl: Duration,
+) -> Result<Option<TokenRisk>, AppError> {
+    if let Some(risk) = cache::get_json::<TokenRisk>(cache, &risk_key(mint)).await {
+        return Ok(Some(risk));
+    }
+
+    let Some(account) = rpc.get_account_info(mint).await? else {
+        return Ok(None);
+    };
+    let parsed = &account["data"]["parsed"];
+    let is_token_program = matches!(
+        account["data"]["program"].as_str(),
+        Some("spl-token" | "spl-token-2022")
+    );
+    if !is_token_program || parsed["type"] != "mint" {
+        return Err(AppError::UnprocessableEntity(format!(
+            "{mint} is not a token mint"
+        )));
+    }
+    let info = &parsed["info"];
+    let authority = |key: &str| info[key].as_str().map(str::to_string);
+    let supply = info["supply"]
+        .as_str()
+        .and_then(|raw| raw.parse::<u64>().ok())
+        .ok_or_else(|| AppError::UpstreamError(format!("Mint {mint} has no readable supply")))?;
+    let decimals = info["decimals"].as_u64().unwrap_or_default() as u32;
+
+    let largest = rpc.get_token_largest_accounts(mint).await?;
+    let token_accounts: Vec<String> = largest.iter().map(|a| a.address.clone()).collect();
+    let holders: Vec<Option<String>> = rpc
+        .get_multiple_accounts(&token_accounts)
+        .await?
+        .iter()
+        .map(|account| {
+            account.as_ref().and_then(|a| {
+                a["data"]["parsed"]["info"]["owner"]
+                    .as_str()
+                    .map(str::to_string)
+            })
+        })
+        .collect();
+    let owners: Vec<String> = holders.iter().flatten().cloned().collect();
+    let owner_programs: Vec<Option<String>> = if owners.is_empty() {
+        Vec::new()
+    } else {
+        rpc.get_multiple_accounts(&owners)
+            .await?
+            .iter()
+            .map(|account| program_of(account.as_ref()))
+            .collect()
+    };
+
+    let mut owner_programs = owner_programs.into_iter();
+    let mut pooled: u64 = 0;
+    let mut top_holders = Vec::new();
+    for (balance, holder) in largest.iter().zip(&holders) {
+        let amount = balance.amount.parse::<u64>().unwrap_or_default();
+        let in_pool = match holder {
+            Some(holder) => {
+                let program = owner_programs.next().flatten();
+                AMM_AUTHORITIES.contains(&holder.as_str())
+                    || program.is_some_and(|p| AMM_PROGRAMS.contains(&p.as_str()))
+            }
+            None => false,
+        };
+        if in_pool {
+            pooled += amount;
+        } else {
+            top_holders.push(amount);
+        }
+    }
+    let top: u64 = top_holders.iter().take(TOP_HOLDERS).sum();
+    let percent = |amount: u64| match supply {
+        0 => 0.0,
+        supply => amount as f64 / supply as f64 * 100.0,
+    };
+
+    let facts = RiskFacts {
+        mint_authority: authority("mintAuthority"),
+        freeze_authority: authority("freezeAuthority"),
+        top_10_holder_percent: percent(top),
+        liquidity_pool_percent: percent(pooled),
+    };
+    let factors = score(&facts);
+    let score = factors.iter().map(|f| f.points).sum::<u8>().min(100);
+    let risk = TokenRisk {
+        mint: mint.to_string(),
+        score,
+        level: RiskLevel::from_score(score),
+        factors,
+        mint_authority: facts.mint_authority,
+        freeze_authority: facts.freeze_authority,
+        supply: format_units(i128::from(supply), decimals),
+        decimals,
+        top_10_holder_percent: facts.top_10_holder_percent,
+        lp_status: if pooled > 0 {
+            LpStatus::Pooled
+        } else {
+            LpStatus::NotFound
+        },
+        liquidity_pool_percent: facts.liquidity_pool_percent,
+        checked_at: Utc::now(),
+    };
+
+    cache::set_json(cache, &risk_key(mint), &risk, ttl).await;
+    Ok(Some(risk))
+}
+
+/// Program owning an account, if it exists
+fn program_of(account: Option<&Value>) -> Option<String> {
+    account?["owner"].as_str().map(str::to_string)
+}
@@ -0,0 +1,1261 @@
+use axum::{
+    body::Body,
+    error_handling::HandleErrorLayer,
+    extract::{DefaultBodyLimit, State},
+    http::{header, HeaderValue, Method, Request, StatusCode},
+    middleware::{self, Next},
+    response::{Html, IntoResponse, Json, Response},
+    routing::{delete, get, post, put},
+    Router,
+};
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower::timeout::TimeoutLayer;
+use tower::{BoxError, ServiceBuilder};
+use tower_http::compression::CompressionLayer;
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+use tower_http::limit::RequestBodyLimitLayer;
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+use utoipa_swagger_ui::SwaggerUi;
+
+use crate::account::{AccountDeletion, DeletionStatus};
+use crate::alerts::{Alert, AlertCondition, AlertEvent, CreateAlert, PriceDirection, UpdateAlert};
+use crate::allocation::{AssetCategory, CategoryAllocation, TokenAllocation, WalletAllocation};
+use crate::allowlist::{admin_allowlist_middleware, AdminAllowlist};
+use crate::analytics::{ClosedTrade, CostBasisMethod, TokenPnl, TradeStats, WalletPnl};
+use crate::auth::roles::{Role, SetUserRole, UserRole};
+use crate::auth::sessions::{LoginRequest, LogoutRequest, RefreshRequest, TokenResponse};
+use crate::auth::siws::{NonceRequest, NonceResponse, VerifyRequest};
+use crate::balances::{TokenBalance, WalletBalances};
+use crate::billing::StripeEventReport;
+use crate::candles::{Candle, CandleQuote, PriceCandles};
+use crate::classify::{SwapDetails, TransactionCategory};
+use crate::client_ip::client_ip_middleware;
+use crate::conditional::http_date;
+use crate::dlq::DeadLetter;
+use crate::events::{WalletEvent, WalletEventKind};
+use crate::export::ExportFormat;
+use crate::features::{require_feature, Feature, FeatureStatus, SetFeature};
+use crate::fees::FeeSummary;
+use crate::fiat::currency_middleware;
+use crate::groups::{CreateWalletGroup, GroupPortfolio, UpdateWalletGroup, WalletGroup};
+use crate::handlers::{
+    add_wallet, block_spam_token, cancel_account_deletion, configure_notification_channel,
+    create_alert, create_group, create_share_link, create_user, create_webhook_subscription,
+    delete_account, delete_address_label, delete_alert, delete_group, delete_notification_channel,
+    delete_wallet, delete_webhook_subscription, export_account, export_holdings,
+    export_transactions, get_admin_stats, get_alert, get_allocation, get_balances, get_fees,
+    get_group, get_group_portfolio, get_history, get_holdings, get_leaderboard, get_pnl,
+    get_portfolio, get_portfolio_widget, get_public_portfolio, get_read_only, get_report,
+    get_sync_status, get_tax_report, get_token, get_token_candles, get_token_risk, get_trade_stats,
+    get_usage, get_user_quota, get_wallet, get_wallet_by_address, helius_webhook,
+    list_address_labels, list_alert_events, list_alerts, list_dead_letters, list_features,
+    list_groups, list_notification_channels, list_plans, list_reports, list_share_links,
+    list_spam_tokens, list_transactions, list_wallets, list_webhook_deliveries,
+    list_webhook_subscriptions, list_whale_events, login, logout, refresh_token, retry_dead_letter,
+    revoke_share_link, set_address_label, set_feature, set_plan_limits, set_read_only,
+    set_user_quota, set_user_role, siws_nonce, siws_verify, stripe_webhook, sync_wallet,
+    unblock_spam_token, update_alert, update_group, update_wallet, wallet_events,
+};
+use crate::handlers::{
+    PaginatedDeadLetters, PaginatedReports, PaginatedTransactions, PaginatedWallets,
+    PaginatedWebhookDeliveries, PaginatedWhaleEvents,
+};
+use crate::health::{self, DatabaseStatus, HealthStatus, ReadinessStatus};
+use crate::helius::WebhookReport;
+use crate::idempotency::idempotency_middleware;
+use crate::labels::{AddressLabel, LabelCategory, SetAddressLabel};
+use crate::leaderboard::{Leaderboard, LeaderboardEntry};
+use crate::logging::request_log_middleware;
+use crate::maintenance::{read_only_middleware, ReadOnlyStatus};
+use crate::metering::{usage_middleware, EndpointUsage, KeyUsage, UsageReport, UsageTotals};
+use crate::models::{
+    CreateUser, CreateWallet, CreatedUser, Holding, Portfolio, Transaction, UpdateWallet, User,
+    Wallet, WalletHoldings,
+};
+use crate::negotiate::msgpack_middleware;
+use crate::notifications::{ChannelKind, ConfigureChannel, NotificationChannel};
+use crate::pagination::SortOrder;
+use crate::pumpfun::BondingStatus;
+use crate::quotas::{Plan, PlanLimits, SetPlanLimits, SetUserQuota, UserQuota};
+use crate::rate_limit::{rate_limit_middleware, RateLimiter};
+use crate::reports::{Report, ReportPeriod, TokenMove, WalletValue};
+use crate::repository::WalletSort;
+use crate::request_id::request_id_middleware;
+use crate::retention::{RetentionReport, RetentionRun};
+use crate::risk::{LpStatus, RiskFactor, RiskFactorKind, RiskLevel, TokenRisk};
+use crate::rpc::EndpointHealth;
+use crate::share::{CreatedShareLink, PortfolioWidget, PublicPortfolio, ShareLink, WidgetHolding};
+use crate::snapshots::{SnapshotPoint, WalletHistory};
+use crate::spam::{BlockSpamToken, SpamToken};
+use crate::stats::{CacheStats, ServerStats};
+use crate::sync_state::{SyncKind, SyncState, SyncStatus};
+use crate::tokens::{TokenDetails, TokenMetadata};
+use crate::webhooks::{
+    CreateWebhookSubscription, CreatedWebhookSubscription, DetectedTransaction, WebhookDelivery,
+    WebhookEventType, WebhookSubscription,
+};
+use crate::whales::WhaleEvent;
+use crate::{AppError, AppState, Config, SyncReport};
+
+/// API documentation
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        crate::health::healthz,
+        crate::health::readyz,
+        crate::handlers::create_user,
+        crate::handlers::siws_nonce,
+        crate::handlers::siws_verify,
+        crate::handlers::login,
+        crate::handlers::refresh_token,
+        crate::handlers::logout,
+        crate::handlers::add_wallet,
+        crate::handlers::get_wallet,
+        crate::handlers::update_wallet,
+        crate::handlers::delete_wallet,
+        crate::handlers::get_wallet_by_address,
+        crate::handlers::list_wallets,
+        crate::handlers::list_transactions,
+        crate::handlers::sync_wallet,
+        crate::handlers::get_sync_status,
+        crate::handlers::get_holdings,
+        crate::handlers::get_balances,
+        crate::handlers::get_allocation,
+        crate::handlers::export_transactions,
+        crate::handlers::export_holdings,
+        crate::handlers::get_pnl,
+        crate::handlers::get_trade_stats,
+        crate::handlers::get_fees,
+        crate::handlers::get_tax_report,
+        crate::handlers::get_history,
+        crate::handlers::get_portfolio,
+        crate::handlers::get_token,
+        crate::handlers::get_token_risk,
+        crate::handlers::get_token_candles,
+        crate::handlers::list_spam_tokens,
+        crate::handlers::block_spam_token,
+        crate::handlers::unblock_spam_token,
+        crate::handlers::create_group,
+        crate::handlers::list_groups,
+        crate::handlers::get_group,
+        crate::handlers::update_group,
+        crate::handlers::delete_group,
+        crate::handlers::get_group_portfolio,
+        crate::handlers::create_share_link,
+        crate::handlers::list_share_links,
+        crate::handlers::revoke_share_link,
+        crate::handlers::get_public_portfolio,
+        crate::handlers::get_portfolio_widget,
+        crate::handlers::create_alert,
+        crate::handlers::list_alerts,
+        crate::handlers::get_alert,
+        crate::handlers::update_alert,
+        crate::handlers::delete_alert,
+        crate::handlers::list_alert_events,
+        crate::handlers::list_notification_channels,
+        crate::handlers::configure_notification_channel,
+        crate::handlers::delete_notification_channel,
+        crate::handlers::list_reports,
+        crate::handlers::get_report,
+        crate::handlers::wallet_events,
+        crate::handlers::list_whale_events,
+        crate::handlers::get_leaderboard,
+        crate::handlers::helius_webhook,
+        crate::handlers::stripe_webhook,
+        crate::handlers::create_webhook_subscription,
+        crate::handlers::list_webhook_subscriptions,
+        crate::handlers::delete_webhook_subscription,
+        crate::handlers::list_webhook_deliveries,
+        crate::handlers::list_address_labels,
+        crate::handlers::set_address_label,
+        crate::handlers::delete_address_label,
+        crate::handlers::get_admin_stats,
+        crate::handlers::get_read_only,
+        crate::handlers::set_read_only,
+        crate::handlers::list_features,
+        crate::handlers::set_feature,
+        crate::handlers::list_dead_letters,
+        crate::handlers::retry_dead_letter,
+        crate::handlers::get_user_quota,
+        crate::handlers::set_user_quota,
+        crate::handlers::set_user_role,
+        crate::handlers::list_plans,
+        crate::handlers::set_plan_limits,
+        crate::handlers::export_account,
+        crate::handlers::get_usage,
+        crate::handlers::delete_account,
+        crate::handlers::cancel_account_deletion,
+    ),
+    components(schemas(
+        Wallet,
+        CreateWallet,
+        UpdateWallet,
+        PaginatedWallets,
+        WalletSort,
+        SortOrder,
+        Transaction,
+        PaginatedTransactions,
+        TransactionCategory,
+        SwapDetails,
+        ExportFormat,
+        SyncReport,
+        SyncStatus,
+        SyncState,
+        SyncKind,
+        Holding,
+        WalletHoldings,
+        TokenBalance,
+        WalletBalances,
+        Portfolio,
+        CostBasisMethod,
+        TokenPnl,
+        WalletAllocation,
+        TokenAllocation,
+        CategoryAllocation,
+        AssetCategory,
+        WalletPnl,
+        TradeStats,
+        ClosedTrade,
+        FeeSummary,
+        SnapshotPoint,
+        WalletHistory,
+        User,
+        CreateUser,
+        CreatedUser,
+        NonceRequest,
+        NonceResponse,
+        VerifyRequest,
+        TokenResponse,
+        LoginRequest,
+        RefreshRequest,
+        LogoutRequest,
+        HealthStatus,
+        ReadinessStatus,
+        DatabaseStatus,
+        EndpointHealth,
+        WebhookReport,
+        WebhookEventType,
+        WebhookSubscription,
+        CreateWebhookSubscription,
+        CreatedWebhookSubscription,
+        WebhookDelivery,
+        PaginatedWebhookDeliveries,
+        DetectedTransaction,
+        WalletEvent,
+        WalletEventKind,
+        WhaleEvent,
+        PaginatedWhaleEvents,
+        Leaderboard,
+        LeaderboardEntry,
+        TokenMetadata,
+        TokenDetails,
+        BondingStatus,
+        TokenRisk,
+        PriceCandles,
+        Candle,
+        CandleQuote,
+        SpamToken,
+        BlockSpamToken,
+        RiskLevel,
+        RiskFactor,
+        RiskFactorKind,
+        LpStatus,
+        WalletGroup,
+        CreateWalletGroup,
+        UpdateWalletGroup,
+        GroupPortfolio,
+        ShareLink,
+        CreatedShareLink,
+        PublicPortfolio,
+        PortfolioWidget,
+        WidgetHolding,
+        Alert,
+        AlertCondition,
+        PriceDirection,
+        CreateAlert,
+        UpdateAlert,
+        AlertEvent,
+        ChannelKind,
+        NotificationChannel,
+        ConfigureChannel,
+        Report,
+        ReportPeriod,
+        TokenMove,
+        WalletValue,
+        PaginatedReports,
+        AddressLabel,
+        SetAddressLabel,
+        ServerStats,
+        ReadOnlyStatus,
+        Feature,
+        FeatureStatus,
+        SetFeature,
+        DeadLetter,
+        PaginatedDeadLetters,
+        Plan,
+        UserQuota,
+        SetUserQuota,
+        Role,
+        SetUserRole,
+        UserRole,
+        PlanLimits,
+        SetPlanLimits,
+        StripeEventReport,
+        RetentionRun,
+        RetentionReport,
+        AccountDeletion,
+        DeletionStatus,
+        UsageReport,
+        UsageTotals,
+        KeyUsage,
+        EndpointUsage,
+        CacheStats,
+        LabelCategory
+    )),
+    modifiers(&SecurityAddon, &VersionPrefixAddon),
+    tags(
+        (name = "wallets", description = "Wallet management endpoints"),
+        (name = "health", description = "Liveness and readiness probes"),
+        (name = "tokens", description = "Token metadata and market data"),
+        (name = "groups", description = "Named wallet groups and their combined portfolio"),
+        (name = "alerts", description = "Alerts on token prices and wallet values"),
+        (name = "notifications", description = "Telegram and email notifications of alerts, large transactions and reports"),
+        (name = "reports", description = "Daily and weekly portfolio reports"),
+        (name = "leaderboard", description = "Tracked wallets ranked by PnL"),
+        (name = "webhooks", description = "Outgoing webhooks for portfolio events"),
+        (name = "account", description = "Export and deletion of the caller's account data"),
+        (name = "admin", description = "Server administration, authenticated by the admin API key")
+    )
+)]
+pub struct ApiDoc;
+
+/// Registers the API key security schemes referenced by the protected endpoints
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        if let Some(components) = openapi.components.as_mut() {
+            components.add_security_scheme(
+                "api_key",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+            components.add_security_scheme(
+                "admin_key",
+                SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+            );
+        }
+    }
+}
+
+/// Documents the versioned paths under their `/api/v1` prefix
+struct VersionPrefixAddon;
+
+impl Modify for VersionPrefixAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let paths = std::mem::take(&mut openapi.paths.paths);
+        openapi.paths.paths = paths
+            .into_iter()
+            .map(|(path, item)| {
+                if UNVERSIONED_PATHS.contains(&path.as_str()) {
+                    (path, item)
+                } else {
+                    (format!("{}{path}", ApiVersion::V1.prefix()), item)
+                }
+            })
+            .collect();
+    }
+}
+
+/// Serve the OpenAPI documentation as HTML
+async fn serve_docs() -> impl IntoResponse {
+    Html(
+        r#"
+        <!DOCTYPE html>
+        <html>
+            <head>
+                <title>Degen API Documentation</title>
+                <meta charset="utf-8"/>
+                <meta name="viewport" content="width=device-width, initial-scale=1">
+                <style>
+                    body { margin: 0; padding: 20px; font-family: Arial, sans-serif; }
+                    h1 { color: #333; }
+                    .endpoint { margin-bottom: 20px; padding: 15px; background: #f5f5f5; border-radius: 5px; }
+                    .method { font-weight: bold; color: #fff; padding: 3px 8px; border-radius: 3px; display: inline-block; margin-right: 10px; }
+                    .get { background: #61affe; }
+                    .post { background: #49cc90; }
+                    .patch { background: #50e3c2; }
+                    .put { background: #fca130; }
+                    .delete { background: #f93e3e; }
+                    .path { font-family: monospace; font-size: 16px; }
+                    .description { margin: 10px 0; }
+                </style>
+            </head>
+            <body>
+                <h1>Degen API Documentation</h1>
+
+                <p>Apart from the probes, every endpoint is served under <code>/api/v1</code>, e.g.
+                <code>GET /api/v1/wallets</code>. The unprefixed paths still work but are deprecated:
+                their responses carry <code>Deprecation</code>, <code>Sunset</code> and a
+                <code>Link</code> to the <code>/api/v1</code> path.</p>
+
+                <p>Responses are JSON. Send <code>Accept: application/msgpack</code> to receive the
+                same payloads encoded as MessagePack.</p>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/healthz</span></div>
+                    <div class="description">Liveness probe, always 200</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/readyz</span></div>
+                    <div class="description">Readiness probe: database latency and migration version (503 when unavailable)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/users</span></div>
+                    <div class="description">Create a user and receive its API key</div>
+                    <div>All wallet endpoints require the key as <code>Authorization: Bearer &lt;key&gt;</code></div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/me/export</span></div>
+                    <div class="description">Download all of the caller's data as one streamed JSON document</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/me</span></div>
+                    <div class="description">Request the deletion of the caller's account; <code>?confirm=&lt;token&gt;</code> schedules the purge after a grace period</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/me/deletion</span></div>
+                    <div class="description">Cancel a requested or scheduled account deletion</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/auth/siws/nonce</span></div>
+                    <div class="description">Request a Sign-In-With-Solana message to sign</div>
+                    <div>Example request body: {"address": "7xKXtg2CW87d97TXJSDpbD5jBkheTqA83TZRuJosgAsU"}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/auth/siws/verify</span></div>
+                    <div class="description">Submit the signed message and receive a JWT, usable in place of an API key, and a refresh token</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/auth/login</span></div>
+                    <div class="description">Exchange an API key for a short-lived JWT and a refresh token</div>
+                    <div>Example request body: {"api_key": "dgn_..."}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/auth/refresh</span></div>
+                    <div class="description">Trade a refresh token for new tokens; each refresh token works once</div>
+                    <div>Example request body: {"refresh_token": "dgr_..."}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/auth/logout</span></div>
+                    <div class="description">Revoke the session of a refresh token, or all your sessions with "all": true</div>
+                    <div>Example request body: {"refresh_token": "dgr_...", "all": false}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets</span></div>
+                    <div class="description">List all wallets (?per_page=50&amp;cursor=&lt;next_cursor&gt;, or legacy ?page=1); filter with ?name_contains=, ?created_after=, ?created_before= and order with ?sort=name|created_at&amp;order=asc|desc</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id</span></div>
+                    <div class="description">Get wallet by ID</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method patch">PATCH</span> <span class="path">/wallets/:id</span></div>
+                    <div class="description">Update a wallet's name, notes or metadata; null clears a field</div>
+                    <div>Example request body: {"notes": "Funded from CEX", "metadata": {"strategy": "copy"}}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/wallets/:id</span></div>
+                    <div class="description">Stop tracking a wallet and delete its transactions</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/by-address/:address</span></div>
+                    <div class="description">Get one of your wallets by its base58 address</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/wallets</span></div>
+                    <div class="description">Create a new wallet</div>
+                    <div>Example request body: {"address": "0x...", "name": "My Wallet", "notes": "Funded from CEX", "metadata": {"strategy": "copy"}}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/wallets/:id/sync</span></div>
+                    <div class="description">Sync wallet transactions from the Solana RPC</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/sync-status</span></div>
+                    <div class="description">Whether the wallet is syncing, its progress and the last error</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/transactions</span></div>
+                    <div class="description">List wallet transactions, newest first (?per_page=50&amp;cursor=&lt;next_cursor&gt;), or stream them all with <code>Accept: application/x-ndjson</code></div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/holdings</span></div>
+                    <div class="description">Get wallet holdings valued in USD</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/balances</span></div>
+                    <div class="description">Read live SOL and SPL token balances from RPC and reconcile them against the computed holdings</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/allocation</span></div>
+                    <div class="description">Get the percentage allocation by token and by SOL, stablecoins and memecoins (?dust_threshold_usd=1)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/transactions/export</span></div>
+                    <div class="description">Download wallet transactions as CSV (?format=csv, optional ?category=)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/holdings/export</span></div>
+                    <div class="description">Download wallet holdings valued in USD as CSV (?format=csv)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/pnl</span></div>
+                    <div class="description">Get realized and unrealized PnL per token (?method=fifo|lifo|avg)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/stats</span></div>
+                    <div class="description">Get trade statistics: win rate, hold times, largest win and loss, fees paid</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/fees</span></div>
+                    <div class="description">Sum up network fees, priority fees and Jito tips paid (?period=30d)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/tax-report</span></div>
+                    <div class="description">Download the realized gains of a year's sells as CSV (?year=2024&amp;method=fifo|lifo|avg)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/history</span></div>
+                    <div class="description">Get the wallet's daily total value for charting (?range=30d)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/events</span></div>
+                    <div class="description">Server-Sent Events stream of the wallet's activity</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/wallets/:id/share</span></div>
+                    <div class="description">Create a revocable link sharing a read-only snapshot of the wallet's holdings and PnL</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/wallets/:id/share</span></div>
+                    <div class="description">List the wallet's share links</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/wallets/:id/share/:link_id</span></div>
+                    <div class="description">Revoke a share link</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/public/portfolio/:token</span></div>
+                    <div class="description">Read a shared wallet's holdings and PnL; no API key needed (?method=fifo|lifo|avg)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/public/widget/:token</span></div>
+                    <div class="description">Cacheable total value, 24h change and top 5 holdings of a shared wallet, for embedding; no API key needed</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/events/whale</span></div>
+                    <div class="description">List transactions of your wallets flagged as whale movements</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/leaderboard</span></div>
+                    <div class="description">Rank tracked wallets by PnL over a period (?period=7d); opt out per wallet</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/portfolio</span></div>
+                    <div class="description">Get holdings merged across all of your wallets, with totals in USD and SOL</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/tokens/:mint</span></div>
+                    <div class="description">Get a token's metadata, price, 24h change and tracked holder count</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/tokens/:mint/risk</span></div>
+                    <div class="description">Score a token's rug risk from its authorities, holder concentration and liquidity pools</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/tokens/:mint/candles</span></div>
+                    <div class="description">Get a token's OHLCV price candles over a range, for charting</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/spam-tokens</span></div>
+                    <div class="description">List the tokens you blocked as spam</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/spam-tokens/:mint</span></div>
+                    <div class="description">Block a token as spam, hiding it from your holdings</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/spam-tokens/:mint</span></div>
+                    <div class="description">Unblock a spam token</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/groups</span></div>
+                    <div class="description">Create a named group of your wallets; a wallet can be in several groups</div>
+                    <div>Example request body: {"name": "Whales I copy", "wallet_ids": ["..."]}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/groups</span></div>
+                    <div class="description">List your wallet groups</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/groups/:id</span></div>
+                    <div class="description">Get a group and its member wallet IDs</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method patch">PATCH</span> <span class="path">/groups/:id</span></div>
+                    <div class="description">Rename a group and/or replace its members</div>
+                    <div>Example request body: {"name": "My wallets", "wallet_ids": ["..."]}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/groups/:id</span></div>
+                    <div class="description">Delete a group; its wallets are kept</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/groups/:id/portfolio</span></div>
+                    <div class="description">Get the group's combined holdings and PnL (?method=fifo|lifo|avg)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/alerts</span></div>
+                    <div class="description">Create an alert on a token price or a wallet's value</div>
+                    <div>Example request body: {"condition": {"type": "token_price", "mint": "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263", "direction": "above", "price_usd": 0.00003}}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/alerts</span></div>
+                    <div class="description">List your alerts</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/alerts/:id</span></div>
+                    <div class="description">Get an alert</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method patch">PATCH</span> <span class="path">/alerts/:id</span></div>
+                    <div class="description">Replace an alert's condition or enable/disable it</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/alerts/:id</span></div>
+                    <div class="description">Delete an alert and its events</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/alerts/:id/events</span></div>
+                    <div class="description">Most recent firings of an alert, newest first</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/notifications/channels</span></div>
+                    <div class="description">List your notification channels</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/notifications/channels/:kind</span></div>
+                    <div class="description">Send alerts, large transactions and reports to a Telegram chat or email address</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/notifications/channels/:kind</span></div>
+                    <div class="description">Stop notifications on a channel</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/reports</span></div>
+                    <div class="description">List your daily and weekly portfolio reports</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/reports/:id</span></div>
+                    <div class="description">Get a portfolio report</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/webhooks/helius</span></div>
+                    <div class="description">Receive Helius enhanced transactions (authenticated by the shared webhook secret)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/webhooks/stripe</span></div>
+                    <div class="description">Receive Stripe subscription events putting users on plans (verified by the Stripe-Signature header)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/webhooks/subscriptions</span></div>
+                    <div class="description">Register a URL for portfolio events (transaction_detected, balance_threshold_crossed)</div>
+                    <div>Example request body: {"url": "https://example.com/hooks", "events": ["transaction_detected"]}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/webhooks/subscriptions</span></div>
+                    <div class="description">List registered webhooks</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/webhooks/subscriptions/:id</span></div>
+                    <div class="description">Delete a webhook</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/webhooks/subscriptions/:id/deliveries</span></div>
+                    <div class="description">Delivery log of a webhook, newest first</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/address-labels</span></div>
+                    <div class="description">List the known exchange, bridge and protocol addresses transactions are labeled with (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/admin/address-labels/:address</span></div>
+                    <div class="description">Label an address (admin API key)</div>
+                    <div>Example request body: {"label": "Binance Hot Wallet", "category": "exchange"}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method delete">DELETE</span> <span class="path">/admin/address-labels/:address</span></div>
+                    <div class="description">Remove an address label (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/stats</span></div>
+                    <div class="description">Instance statistics: users, wallets, transactions, sync backlog, cache hit rates and database size (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/read-only</span></div>
+                    <div class="description">Whether the API is in read-only maintenance mode (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/admin/read-only</span></div>
+                    <div class="description">Switch read-only maintenance mode on or off: writes answer 503 while reads keep working (admin API key)</div>
+                    <div>Example request body: {"enabled": true}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/features</span></div>
+                    <div class="description">Feature flags: whether sync, share links and Helius webhooks are on (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/admin/features/{feature}</span></div>
+                    <div class="description">Switch a feature on or off on every instance; null restores the configured state (admin API key)</div>
+                    <div>Example request body: {"enabled": false}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/dlq</span></div>
+                    <div class="description">Dead-letter queue: webhook deliveries and notifications that ran out of attempts (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method post">POST</span> <span class="path">/admin/dlq/:id/retry</span></div>
+                    <div class="description">Queue a dead-lettered delivery again with fresh attempts (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/users/:id/quota</span></div>
+                    <div class="description">A user's plan, wallet and daily sync limits, and usage (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/admin/users/:id/quota</span></div>
+                    <div class="description">Put a user on a plan and override its limits (admin API key)</div>
+                    <div>Example request body: {"plan": "pro", "max_wallets": 1000}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/admin/users/:id/role</span></div>
+                    <div class="description">Give a user the admin, user or readonly role, revoking their sessions (admin API key or admin role)</div>
+                    <div>Example request body: {"role": "readonly"}</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method get">GET</span> <span class="path">/admin/plans</span></div>
+                    <div class="description">Limits and Stripe price of every plan (admin API key)</div>
+                </div>
+
+                <div class="endpoint">
+                    <div><span class="method put">PUT</span> <span class="path">/admin/plans/:plan</span></div>
+                    <div class="description">Change a plan's limits and the Stripe price it is sold at (admin API key)</div>
+                    <div>Example request body: {"max_wallets": 500, "max_syncs_per_day": 5000, "stripe_price_id": "price_1Pro"}</div>
+                </div>
+
+                <div style="margin-top: 30px;">
+                    <h3>Interactive Documentation</h3>
+                    <p>For an interactive API documentation, visit the <a href="/swagger-ui">Swagger UI</a>.</p>
+                    <p>Or download the <a href="/openapi.json">OpenAPI specification</a>.</p>
+                </div>
+            </body>
+        </html>
+        "#,
+    )
+}
+
+/// Serve the OpenAPI JSON specification
+async fn serve_openapi() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+/// Body of requests as seen by handlers and route middleware, capped at the
+/// configured `max_request_body_bytes`
+pub type RequestBody = http_body::Limited<Body>;
+
+/// Gives the `413` responses of the body limit layer the standard error payload
+async fn payload_too_large_as_json<B>(
+    State(max_bytes): State<usize>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let response = next.run(request).await;
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"application/json"));
+
+    if response.status() == StatusCode::PAYLOAD_TOO_LARGE && !is_json {
+        return AppError::PayloadTooLarge(format!("Request body exceeds {max_bytes} bytes"))
+            .into_response();
+    }
+    response
+}
+
+/// Version of the API; each is served under its own path prefix
+///
+/// Breaking changes to request or response schemas ship as a new version, while
+/// existing versions keep their routes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ApiVersion {
+    /// The first versioned API, under `/api/v1`
+    V1,
+}
+
+impl ApiVersion {
+    /// Path prefix the version is served under
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::V1 => "/api/v1",
+        }
+    }
+}
+
+/// Paths that are not versioned: probes are polled by infrastructure, not API clients
+const UNVERSIONED_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// Builds the routes of one API version, relative to its prefix
+pub fn api_router(version: ApiVersion, state: &AppState) -> Router<AppState, RequestBody> {
+    // Values of these routes' responses are displayed in the requested currency
+    let in_currency = || middleware::from_fn_with_state(state.clone(), currency_middleware);
+    // These routes answer 503 while their feature is switched off
+    let requires = |feature: Feature| {
+        middleware::from_fn_with_state((state.features.clone(), feature), require_feature)
+    };
+
+    match version {
+        ApiVersion::V1 => Router::new()
+            .route("/users", post(create_user))
+            .route("/me", delete(delete_account))
+            .route("/me/export", get(export_account))
+            .route("/me/usage", get(get_usage))
+            .route("/me/deletion", delete(cancel_account_deletion))
+            .route("/auth/siws/nonce", post(siws_nonce))
+            .route("/auth/siws/verify", post(siws_verify))
+            .route("/auth/login", post(login))
+            .route("/auth/refresh", post(refresh_token))
+            .route("/auth/logout", post(logout))
+            .route(
+                "/wallets",
+                post(add_wallet)
+                    .layer(middleware::from_fn_with_state(
+                        state.clone(),
+                        idempotency_middleware,
+                    ))
+                    .get(list_wallets),
+            )
+            .route(
+                "/wallets/:id",
+                get(get_wallet).patch(update_wallet).delete(delete_wallet),
+            )
+            .route("/wallets/by-address/:address", get(get_wallet_by_address))
+            .route(
+                "/wallets/:id/sync",
+                post(sync_wallet).layer(requires(Feature::Sync)),
+            )
+            .route("/wallets/:id/sync-status", get(get_sync_status))
+            .route(
+                "/wallets/:id/transactions",
+                get(list_transactions).layer(in_currency()),
+            )
+            .route("/wallets/:id/transactions/export", get(export_transactions))
+            .route(
+                "/wallets/:id/holdings",
+                get(get_holdings).layer(in_currency()),
+            )
+            .route("/wallets/:id/holdings/export", get(export_holdings))
+            .route("/wallets/:id/balances", get(get_balances))
+            .route(
+                "/wallets/:id/allocation",
+                get(get_allocation).layer(in_currency()),
+            )
+            .route("/wallets/:id/pnl", get(get_pnl).layer(in_currency()))
+            .route(
+                "/wallets/:id/stats",
+                get(get_trade_stats).layer(in_currency()),
+            )
+            .route("/wallets/:id/fees", get(get_fees).layer(in_currency()))
+            .route("/wallets/:id/tax-report", get(get_tax_report))
+            .route(
+                "/wallets/:id/history",
+                get(get_history).layer(in_currency()),
+            )
+            .route("/wallets/:id/events", get(wallet_events))
+            .route(
+                "/wallets/:id/share",
+                post(create_share_link)
+                    .get(list_share_links)
+                    .layer(requires(Feature::ShareLinks)),
+            )
+            .route(
+                "/wallets/:id/share/:link_id",
+                delete(revoke_share_link).layer(requires(Feature::ShareLinks)),
+            )
+            .route(
+                "/public/portfolio/:token",
+                get(get_public_portfolio)
+                    .layer(in_currency())
+                    .layer(requires(Feature::ShareLinks)),
+            )
+            .route(
+                "/public/widget/:token",
+                get(get_portfolio_widget)
+                    .layer(in_currency())
+                    .layer(requires(Feature::ShareLinks)),
+            )
+            .route("/events/whale", get(list_whale_events).layer(in_currency()))
+            .route("/leaderboard", get(get_leaderboard).layer(in_currency()))
+            .route("/portfolio", get(get_portfolio).layer(in_currency()))
+            .route("/tokens/:mint", get(get_token).layer(in_currency()))
+            .route("/tokens/:mint/risk", get(get_token_risk))
+            .route("/tokens/:mint/candles", get(get_token_candles))
+            .route("/spam-tokens", get(list_spam_tokens))
+            .route(
+                "/spam-tokens/:mint",
+                put(block_spam_token).delete(unblock_spam_token),
+            )
+            .route("/groups", post(create_group).get(list_groups))
+            .route(
+                "/groups/:id",
+                get(get_group).patch(update_group).delete(delete_group),
+            )
+            .route(
+                "/groups/:id/portfolio",
+                get(get_group_portfolio).layer(in_currency()),
+            )
+            .route("/alerts", post(create_alert).get(list_alerts))
+            .route(
+                "/alerts/:id",
+                get(get_alert).patch(update_alert).delete(delete_alert),
+            )
+            .route(
+                "/alerts/:id/events",
+                get(list_alert_events).layer(in_currency()),
+            )
+            .route("/notifications/channels", get(list_notification_channels))
+            .route(
+                "/notifications/channels/:kind",
+                put(configure_notification_channel).delete(delete_notification_channel),
+            )
+            .route("/reports", get(list_reports).layer(in_currency()))
+            .route("/reports/:id", get(get_report).layer(in_currency()))
+            .route(
+                "/webhooks/helius",
+                post(helius_webhook).layer(requires(Feature::HeliusWebhooks)),
+            )
+            .route("/webhooks/stripe", post(stripe_webhook))
+            .route(
+                "/webhooks/subscriptions",
+                post(create_webhook_subscription).get(list_webhook_subscriptions),
+            )
+            .route(
+                "/webhooks/subscriptions/:id",
+                delete(delete_webhook_subscription),
+            )
+            .route(
+                "/webhooks/subscriptions/:id/deliveries",
+                get(list_webhook_deliveries),
+            )
+            .route("/admin/address-labels", get(list_address_labels))
+            .route(
+                "/admin/address-labels/:address",
+                put(set_address_label).delete(delete_address_label),
+            )
+            .route("/admin/stats", get(get_admin_stats))
+            .route("/admin/read-only", get(get_read_only).put(set_read_only))
+            .route("/admin/features", get(list_features))
+            .route("/admin/features/:feature", put(set_feature))
+            .route("/admin/dlq", get(list_dead_letters))
+            .route("/admin/dlq/:id/retry", post(retry_dead_letter))
+            .route(
+                "/admin/users/:id/quota",
+                get(get_user_quota).put(set_user_quota),
+            )
+            .route("/admin/users/:id/role", put(set_user_role))
+            .route("/admin/plans", get(list_plans))
+            .route("/admin/plans/:plan", put(set_plan_limits)),
+    }
+}
+
+/// Marks responses to the unprefixed legacy paths as deprecated
+///
+/// Adds `Deprecation: true`, a `Link` to the `/api/v1` successor and, if a date is
+/// configured, the `Sunset` after which the legacy paths may be removed.
+async fn deprecated_path_middleware<B>(
+    State(sunset): State<Option<DateTime<Utc>>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let successor = format!(
+        "<{}{}>; rel=\"successor-version\"",
+        ApiVersion::V1.prefix(),
+        request.uri().path()
+    );
+    let mut response = next.run(request).await;
+
+    let headers = response.headers_mut();
+    headers.insert("deprecation", HeaderValue::from_static("true"));
+    if let Ok(link) = HeaderValue::from_str(&successor) {
+        headers.insert(header::LINK, link);
+    }
+    if let Some(sunset) = sunset.and_then(|sunset| HeaderValue::from_str(&http_date(sunset)).ok()) {
+        headers.insert("sunset", sunset);
+    }
+
+    response
+}
+
+/// Response headers cross-origin clients may read besides the CORS-safelisted ones
+const CORS_EXPOSED_HEADERS: [&str; 7] = [
+    "etag",
+    "retry-after",
+    crate::request_id::REQUEST_ID_HEADER,
+    crate::rate_limit::RATE_LIMIT_LIMIT_HEADER,
+    crate::rate_limit::RATE_LIMIT_REMAINING_HEADER,
+    crate::rate_limit::RATE_LIMIT_RESET_HEADER,
+    crate::idempotency::IDEMPOTENT_REPLAYED_HEADER,
+];
+
+/// The CORS policy configured with the `CORS_*` settings
+///
+/// Without allowed origins, no cross-origin request is allowed. Entries that are not
+/// valid origins, methods or header names are skipped.
+fn cors_layer(config: &Config) -> CorsLayer {
+    let is_wildcard = |list: &[String]| list.iter().any(|item| item == "*");
+
+    let origins = if is_wildcard(&config.cors_allowed_origins) {
+        AllowOrigin::from(Any)
+    } else {
+        AllowOrigin::list(
+            config
+                .cors_allowed_origins
+                .iter()
+                .filter_map(|origin| HeaderValue::from_str(origin.trim_end_matches('/')).ok()),
+        )
+    };
+    let methods = AllowMethods::list(
+        config
+            .cors_allowed_methods
+            .iter()
+            .filter_map(|method| Method::from_bytes(method.to_ascii_uppercase().as_bytes()).ok()),
+    );
+    let headers = if is_wildcard(&config.cors_allowed_headers) {
+        AllowHeaders::from(Any)
+    } else {
+        AllowHeaders::list(
+            config
+                .cors_allowed_headers
+                .iter()
+                .filter_map(|name| name.parse().ok()),
+        )
+    };
+
+    // Browsers refuse credentials with wildcards, and tower-http panics on them
+    let wildcard =
+        is_wildcard(&config.cors_allowed_origins) || is_wildcard(&config.cors_allowed_headers);
+    if config.cors_allow_credentials && wildcard {
+        tracing::warn!("CORS_ALLOW_CREDENTIALS is ignored with a wildcard origin or header");
+    }
+
+    CorsLayer::new()
+        .allow_origin(origins)
+        .allow_methods(methods)
+        .allow_headers(headers)
+        .allow_credentials(config.cors_allow_credentials && !wildcard)
+        .expose_headers(CORS_EXPOSED_HEADERS.map(header::HeaderName::from_static))
+        // CORS replaces any Vary set by inner layers, so it lists theirs too:
+        // compression varies with Accept-Encoding and MessagePack with Accept
+        .vary([
+            header::ORIGIN,
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+            header::ACCESS_CONTROL_REQUEST_HEADERS,
+            header::ACCEPT,
+            header::ACCEPT_ENCODING,
+        ])
+}
+
+/// Answers requests the timeout or concurrency limit cut short
+async fn overload_error(err: BoxError) -> AppError {
+    if err.is::<tower::timeout::error::Elapsed>() {
+        AppError::Timeout("The request took too long to complete".to_string())
+    } else if err.is::<tower::load_shed::error::Overloaded>() {
+        AppError::ServiceUnavailable(
+            "The server is handling too many requests; please try again shortly".to_string(),
+        )
+    } else {
+        AppError::InternalServerError(err.to_string())
+    }
+}
+
+/// Builds the application router for the given database pool
+///
+/// The configuration is read from the environment, see [`Config::from_env`].
+pub fn create_app(pool: PgPool) -> Router {
+    create_app_with_state(AppState::new(pool, Config::from_env()))
+}
+
+/// Builds the application router (routes, documentation, CORS) around an existing state
+pub fn create_app_with_state(state: AppState) -> Router {
+    let cors = cors_layer(&state.config);
+
+    let rate_limiter = Arc::new(RateLimiter::new(
+        state.config.rate_limit_per_minute,
+        state.config.rate_limit_global_per_minute,
+    ));
+    let max_body_bytes = state.config.max_request_body_bytes;
+    // Server-sent event streams and tiny bodies are never compressed
+    let compression = CompressionLayer::new().gzip(state.config.response_compression);
+
+    // Slow RPC-backed requests must not tie up the server: requests beyond the
+    // concurrency limit are shed with 503, and those taking too long end with 504.
+    // The limit is shared by the versioned and legacy paths; probes are exempt.
+    let limits = ServiceBuilder::new()
+        .layer(HandleErrorLayer::new(overload_error))
+        .option_layer(state.config.concurrency_limit().map(|max| {
+            ServiceBuilder::new()
+                .load_shed()
+                .layer(GlobalConcurrencyLimitLayer::new(max))
+        }))
+        .option_layer(state.config.request_timeout().map(TimeoutLayer::new))
+        // Routes never fail, but the optional layers above share one error type
+        .map_err(|err: Infallible| -> BoxError { match err {} });
+
+    // Create Swagger UI
+    let swagger_ui = SwaggerUi::new("/swagger-ui").url("/api-doc/openapi.json", ApiDoc::openapi());
+
+    Router::new()
+        .merge(swagger_ui)
+        .route("/docs", get(serve_docs))
+        .route("/openapi.json", get(serve_openapi))
+        .route("/healthz", get(health::healthz))
+        .route("/readyz", get(health::readyz))
+        .nest(
+            ApiVersion::V1.prefix(),
+            api_router(ApiVersion::V1, &state).layer(limits.clone()),
+        )
+        // Unprefixed paths from before versioning keep working, marked deprecated
+        .merge(api_router(ApiVersion::V1, &state).layer(limits).layer(
+            middleware::from_fn_with_state(
+                state.config.legacy_api_sunset,
+                deprecated_path_middleware,
+            ),
+        ))
+        .with_state(state.clone())
+        .layer(middleware::from_fn_with_state(
+            state.usage.clone(),
+            usage_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.read_only.clone(),
+            read_only_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(AdminAllowlist::from_config(&state.config)),
+            admin_allowlist_middleware,
+        ))
+        // The body limit below replaces axum's fixed default
+        .layer(DefaultBodyLimit::disable())
+        .layer(middleware::from_fn_with_state(
+            (rate_limiter, state.clone()),
+            rate_limit_middleware,
+        ))
+        .layer(RequestBodyLimitLayer::new(max_body_bytes))
+        .layer(middleware::from_fn_with_state(
+            max_body_bytes,
+            payload_too_large_as_json,
+        ))
+        .layer(middleware::from_fn(msgpack_middleware))
+        .layer(compression)
+        .layer(cors)
+        .layer(middleware::from_fn(request_log_middleware))
+        .layer(middleware::from_fn_with_state(
+            Arc::new(state.config.trusted_proxies.clone()),
+            client_ip_middleware,
+        ))
+        .layer(middleware::from_fn(request_id_middleware))
+}
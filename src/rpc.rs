@@ -0,0 +1,178 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// Delay before the first retry of a failed or throttled endpoint
+const BASE_BACKOFF: Duration = Duration::from_millis(250);
+
+/// Longest an endpoint is set aside after repeated failures
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Delay before retrying an endpoint after `failures` consecutive failures, doubling
+/// with each one
+pub fn backoff(failures: u32) -> Duration {
+    BASE_BACKOFF
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Health and request pacing of one endpoint
+#[derive(Debug)]
+struct EndpointState {
+    /// Failures since the last successful call
+    consecutive_failures: u32,
+    /// The endpoint is not used before this time unless every endpoint is set aside
+    retry_at: Option<Instant>,
+    /// Earliest time the next request may be sent under the rate limit
+    next_request_at: Instant,
+}
+
+/// A JSON-RPC endpoint together with its health
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    state: Mutex<EndpointState>,
+}
+
+/// Snapshot of an endpoint's health
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct EndpointHealth {
+    /// URL of the endpoint
+    pub url: String,
+    /// Whether the endpoint is currently used for calls
+    pub healthy: bool,
+    /// Failures since the last successful call
+    pub consecutive_failures: u32,
+}
+
+/// Solana RPC endpoints in order of preference, with health tracking and a
+/// per-endpoint rate limit
+///
+/// Calls go to the first healthy endpoint. One that fails or answers
+/// `429 Too Many Requests` is set aside with exponential backoff, so calls fail over
+/// to the next; once every endpoint is set aside, the one available soonest is used.
+/// A successful call makes an endpoint healthy again.
+#[derive(Debug)]
+pub struct RpcEndpoints {
+    endpoints: Vec<Endpoint>,
+    min_interval: Option<Duration>,
+}
+
+impl RpcEndpoints {
+    /// Creates the set from URLs in order of preference, each allowed
+    /// `requests_per_second` requests; `0` disables the limit
+    ///
+    /// # Panics
+    ///
+    /// Panics if `urls` is empty.
+    pub fn new(urls: Vec<String>, requests_per_second: u32) -> Self {
+        assert!(!urls.is_empty(), "At least one RPC endpoint is required");
+        let now = Instant::now();
+        Self {
+            endpoints: urls
+                .into_iter()
+                .map(|url| Endpoint {
+                    url,
+                    state: Mutex::new(EndpointState {
+                        consecutive_failures: 0,
+                        retry_at: None,
+                        next_request_at: now,
+                    }),
+                })
+                .collect(),
+            min_interval: (requests_per_second > 0)
+                .then(|| Duration::from_secs(1) / requests_per_second),
+        }
+    }
+
+    /// Number of endpoints
+    pub fn len(&self) -> usize {
+        self.endpoints.len()
+    }
+
+    /// Always `false`: there is at least one endpoint
+    pub fn is_empty(&self) -> bool {
+        self.endpoints.is_empty()
+    }
+
+    /// URL of the endpoint at `index`
+    pub fn url(&self, index: usize) -> &str {
+        &self.endpoints[index].url
+    }
+
+    /// Picks the endpoint for the next call and reserves a request under its rate
+    /// limit, returning its index and how long to wait before sending
+    pub fn acquire(&self) -> (usize, Duration) {
+        let now = Instant::now();
+        let states: Vec<_> = self
+            .endpoints
+            .iter()
+            .map(|e| e.state.lock().unwrap_or_else(|err| err.into_inner()))
+            .collect();
+
+        let index = states
+            .iter()
+            .position(|s| s.retry_at.is_none_or(|at| at <= now))
+            .unwrap_or_else(|| {
+                (0..states.len())
+                    .min_by_key(|&i| states[i].retry_at)
+                    .unwrap_or_default()
+            });
+        drop(states);
+
+        let mut state = self.lock(index);
+        let send_at = state
+            .retry_at
+            .unwrap_or(now)
+            .max(state.next_request_at)
+            .max(now);
+        if let Some(interval) = self.min_interval {
+            state.next_request_at = send_at + interval;
+        }
+        (index, send_at - now)
+    }
+
+    /// Records a successful call, making the endpoint healthy again
+    pub fn record_success(&self, index: usize) {
+        let mut state = self.lock(index);
+        state.consecutive_failures = 0;
+        state.retry_at = None;
+    }
+
+    /// Records a failed call, setting the endpoint aside for `retry_after` if given,
+    /// or else for an exponentially growing backoff
+    pub fn record_failure(&self, index: usize, retry_after: Option<Duration>) {
+        let mut state = self.lock(index);
+        state.consecutive_failures += 1;
+        let delay = retry_after
+            .unwrap_or_else(|| backoff(state.consecutive_failures))
+            .min(MAX_BACKOFF);
+        state.retry_at = Some(Instant::now() + delay);
+    }
+
+    /// Current health of every endpoint, in order of preference
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        let now = Instant::now();
+        self.endpoints
+            .iter()
+            .enumerate()
+            .map(|(index, endpoint)| {
+                let state = self.lock(index);
+                EndpointHealth {
+                    url: endpoint.url.clone(),
+                    healthy: state.retry_at.is_none_or(|at| at <= now),
+                    consecutive_failures: state.consecutive_failures,
+                }
+            })
+            .collect()
+    }
+
+    fn lock(&self, index: usize) -> std::sync::MutexGuard<'_, EndpointState> {
+        self.endpoints[index]
+            .state
+            .lock()
+            .unwrap_or_else(|err| err.into_inner())
+    }
+}
@@ -0,0 +1,148 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::{Connection, PgConnection, PgPool};
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::{error, info, warn};
+use uuid::Uuid;
+
+use crate::features::Feature;
+use crate::jobs::{self, Job};
+use crate::AppState;
+
+/// Advisory lock key held by the instance running the periodic schedulers
+pub const SCHEDULER_LOCK_KEY: i64 = 0x6465_6765_6e5f_0001;
+
+/// Leader election between instances through a Postgres session advisory lock
+///
+/// The instance whose connection holds the lock is the leader. The lock is released
+/// when that connection closes, e.g. because the instance stopped, after which the
+/// next instance asking becomes leader. Periodic jobs check [`Self::acquire`] before
+/// each run, so exactly one instance runs them.
+pub struct LeaderLock {
+    pool: PgPool,
+    key: i64,
+    /// Connection holding the lock while this instance leads, kept out of the pool
+    connection: Mutex<Option<PgConnection>>,
+}
+
+impl LeaderLock {
+    /// Creates a lock on `key` that is not held yet
+    pub fn new(pool: PgPool, key: i64) -> Self {
+        Self {
+            pool,
+            key,
+            connection: Mutex::new(None),
+        }
+    }
+
+    /// Whether this instance leads, taking the lock if it is free
+    ///
+    /// Leadership is lost if the connection holding the lock breaks, as Postgres
+    /// releases the lock with it.
+    pub async fn acquire(&self) -> Result<bool, sqlx::Error> {
+        let mut held = self.connection.lock().await;
+        if let Some(connection) = held.as_mut() {
+            if connection.ping().await.is_ok() {
+                return Ok(true);
+            }
+            warn!("Lost the scheduler lock with its database connection");
+            *held = None;
+        }
+
+        let mut connection = self.pool.acquire().await?.detach();
+        let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+            .bind(self.key)
+            .fetch_one(&mut connection)
+            .await?;
+        if locked {
+            info!("This instance now runs the periodic schedulers");
+            *held = Some(connection);
+        }
+        Ok(locked)
+    }
+
+    /// Gives up leadership, if held, so another instance can take over
+    pub async fn release(&self) -> Result<(), sqlx::Error> {
+        if let Some(mut connection) = self.connection.lock().await.take() {
+            sqlx::query("SELECT pg_advisory_unlock($1)")
+                .bind(self.key)
+                .execute(&mut connection)
+                .await?;
+            connection.close().await?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether this instance should run a periodic job now, logging lock errors
+pub(crate) async fn is_leader(leader: &LeaderLock) -> bool {
+    match leader.acquire().await {
+        Ok(leading) => leading,
+        Err(err) => {
+            error!("Checking the scheduler lock failed: {}", err);
+            false
+        }
+    }
+}
+
+/// Spawns the background task that periodically re-syncs all tracked wallets
+///
+/// Every `interval`, wallets whose `last_synced_at` is older than the interval (or
+/// that were never synced) get a sync job, least recently synced first. The job
+/// worker runs them, retrying failures. Only the instance holding `leader` queues.
+pub fn spawn_sync_scheduler(
+    state: AppState,
+    interval: Duration,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!(
+        "Starting wallet sync scheduler with interval {:?}",
+        interval
+    );
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if !state.features.is_enabled(Feature::Sync) || !is_leader(&leader).await {
+                continue;
+            }
+
+            match run_sync_cycle(&state, interval).await {
+                Ok(queued) if queued > 0 => info!("Queued syncs of {} wallets", queued),
+                Ok(_) => {}
+                Err(err) => error!("Scheduled sync cycle failed: {}", err),
+            }
+        }
+    })
+}
+
+/// Queues a sync of every wallet that is due for a refresh and returns how many were
+/// queued; wallets whose sync is already queued are skipped
+pub async fn run_sync_cycle(state: &AppState, interval: Duration) -> Result<usize, sqlx::Error> {
+    let due = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT id
+        FROM wallets
+        WHERE last_synced_at IS NULL
+           OR last_synced_at < NOW() - make_interval(secs => $1)
+        ORDER BY last_synced_at NULLS FIRST
+        "#,
+    )
+    .bind(interval.as_secs_f64())
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let mut queued = 0;
+    for wallet_id in due {
+        if jobs::enqueue(&state.db_pool, &Job::SyncWallet { wallet_id }).await? {
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
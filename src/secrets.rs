@@ -0,0 +1,287 @@
+//! Encryption at rest of user-supplied integration credentials.
+//!
+//! Notification channel targets, such as Telegram chat IDs and email addresses, and
+//! webhook signing secrets are sealed with AES-256-GCM under the master key of
+//! `SECRETS_MASTER_KEY` (or the file named by `SECRETS_MASTER_KEY_FILE`, e.g. one
+//! written by a KMS or secrets manager) before they are stored, and opened again only
+//! where they are used. A sealed value is stored as
+//!
+//! ```text
+//! enc:v2:<hex 96-bit nonce><hex ciphertext and tag>
+//! ```
+//!
+//! The table, column and row a value is sealed for are [bound](Slot) to it as
+//! associated data, so it cannot be opened once copied to another row, such as the
+//! channel of another user. Values without the prefix were stored before a master key
+//! was configured and are read as they are, and `enc:v1:` values were sealed without a
+//! slot; [`seal_stored`] seals both for their slot at startup. Without a master key new
+//! values are stored unencrypted too, which suits development only.
+
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Key, Nonce};
+use sqlx::PgPool;
+use thiserror::Error;
+use uuid::Uuid;
+
+use crate::config::Config;
+
+/// Prefix of sealed values
+const SEALED_PREFIX: &str = "enc:v2:";
+
+/// Prefix of values sealed before they were bound to their slot
+const UNBOUND_PREFIX: &str = "enc:v1:";
+
+/// Length of AES-GCM nonces in bytes
+const NONCE_LEN: usize = 12;
+
+/// Errors sealing and opening credentials
+#[derive(Debug, Error)]
+pub enum SecretError {
+    /// The master key is not 64 hex characters
+    #[error("SECRETS_MASTER_KEY must be 64 hex characters (32 bytes)")]
+    InvalidKey,
+
+    /// The file that should hold the master key could not be read
+    #[error("Failed to read SECRETS_MASTER_KEY_FILE {path}: {source}")]
+    KeyFile {
+        /// Path of the key file
+        path: PathBuf,
+        /// Why it could not be read
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// A sealed value was found but no master key is configured
+    #[error("Stored credential is encrypted but no SECRETS_MASTER_KEY is configured")]
+    NoKey,
+
+    /// A sealed value is not valid hex or is too short
+    #[error("Stored credential is malformed")]
+    Malformed,
+
+    /// A sealed value was not sealed with the master key, or was tampered with
+    #[error("Stored credential could not be decrypted with the configured master key")]
+    Decrypt,
+}
+
+impl From<SecretError> for sqlx::Error {
+    fn from(err: SecretError) -> Self {
+        sqlx::Error::Decode(Box::new(err))
+    }
+}
+
+/// Table, column and row a sealed value is stored in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slot {
+    table: &'static str,
+    column: &'static str,
+    row: String,
+}
+
+impl Slot {
+    /// Target of the notification channel of `kind` of `user_id`
+    ///
+    /// Notifications are queued with a copy of their channel's target, which stays
+    /// bound to the channel.
+    pub fn channel_target(user_id: Uuid, kind: &str) -> Self {
+        Self {
+            table: "notification_channels",
+            column: "target",
+            row: format!("{user_id}/{kind}"),
+        }
+    }
+
+    /// Signing secret of the webhook subscription `subscription_id`
+    pub fn webhook_secret(subscription_id: Uuid) -> Self {
+        Self {
+            table: "webhook_subscriptions",
+            column: "secret",
+            row: subscription_id.to_string(),
+        }
+    }
+
+    /// The associated data binding a sealed value to the slot
+    fn aad(&self) -> Vec<u8> {
+        format!("{}.{}:{}", self.table, self.column, self.row).into_bytes()
+    }
+}
+
+/// Seals and opens stored credentials under the master key, if one is configured
+#[derive(Clone, Default)]
+pub struct SecretBox {
+    cipher: Option<Arc<Aes256Gcm>>,
+}
+
+impl fmt::Debug for SecretBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SecretBox")
+            .field("enabled", &self.is_enabled())
+            .finish()
+    }
+}
+
+impl SecretBox {
+    /// Creates a box sealing with a 32-byte master key
+    pub fn new(master_key: &[u8; 32]) -> Self {
+        Self {
+            cipher: Some(Arc::new(Aes256Gcm::new(Key::<Aes256Gcm>::from_slice(
+                master_key,
+            )))),
+        }
+    }
+
+    /// Creates a box sealing with a master key of 64 hex characters
+    pub fn from_hex(master_key: &str) -> Result<Self, SecretError> {
+        let mut key = [0u8; 32];
+        hex::decode_to_slice(master_key.trim(), &mut key).map_err(|_| SecretError::InvalidKey)?;
+        Ok(Self::new(&key))
+    }
+
+    /// Creates a box with the master key of the configuration, or one storing values
+    /// unencrypted if none is configured
+    pub fn from_config(config: &Config) -> Result<Self, SecretError> {
+        if let Some(key) = &config.secrets_master_key {
+            return Self::from_hex(key);
+        }
+        match &config.secrets_master_key_file {
+            Some(path) => {
+                let key = fs::read_to_string(path).map_err(|source| SecretError::KeyFile {
+                    path: path.clone(),
+                    source,
+                })?;
+                Self::from_hex(&key)
+            }
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Whether values are sealed before they are stored
+    pub fn is_enabled(&self) -> bool {
+        self.cipher.is_some()
+    }
+
+    /// Seals `plaintext` for storage in `slot`, or returns it as it is without a master
+    /// key
+    pub fn seal(&self, plaintext: &str, slot: &Slot) -> String {
+        let Some(cipher) = &self.cipher else {
+            return plaintext.to_string();
+        };
+
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: plaintext.as_bytes(),
+            aad: &slot.aad(),
+        };
+        let ciphertext = cipher
+            .encrypt(&nonce, payload)
+            .expect("AES-GCM encrypts inputs of any realistic size");
+        format!(
+            "{SEALED_PREFIX}{}{}",
+            hex::encode(nonce),
+            hex::encode(ciphertext)
+        )
+    }
+
+    /// Opens a value stored in `slot`; values stored unencrypted are returned as they are
+    pub fn open(&self, stored: &str, slot: &Slot) -> Result<String, SecretError> {
+        let (sealed, aad) = if let Some(sealed) = stored.strip_prefix(SEALED_PREFIX) {
+            (sealed, slot.aad())
+        } else if let Some(sealed) = stored.strip_prefix(UNBOUND_PREFIX) {
+            (sealed, Vec::new())
+        } else {
+            return Ok(stored.to_string());
+        };
+        let cipher = self.cipher.as_ref().ok_or(SecretError::NoKey)?;
+
+        let bytes = hex::decode(sealed).map_err(|_| SecretError::Malformed)?;
+        if bytes.len() < NONCE_LEN {
+            return Err(SecretError::Malformed);
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let payload = Payload {
+            msg: ciphertext,
+            aad: &aad,
+        };
+        let plaintext = cipher
+            .decrypt(Nonce::from_slice(nonce), payload)
+            .map_err(|_| SecretError::Decrypt)?;
+        String::from_utf8(plaintext).map_err(|_| SecretError::Decrypt)
+    }
+}
+
+/// Whether a stored value is sealed
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(SEALED_PREFIX) || stored.starts_with(UNBOUND_PREFIX)
+}
+
+/// Seals the credentials stored unencrypted, e.g. before a master key was configured,
+/// or sealed without their slot, and returns how many were sealed
+///
+/// Does nothing without a master key. Each value is only replaced if it did not change
+/// in the meantime, so this is safe to run while the API is serving.
+pub async fn seal_stored(pool: &PgPool, secrets: &SecretBox) -> Result<u64, sqlx::Error> {
+    if !secrets.is_enabled() {
+        return Ok(0);
+    }
+
+    let mut sealed = 0;
+    let channels = sqlx::query_as::<_, (Uuid, String, String)>(
+        "SELECT user_id, kind, target FROM notification_channels WHERE target NOT LIKE 'enc:v2:%'",
+    )
+    .fetch_all(pool)
+    .await?;
+    for (user_id, kind, target) in channels {
+        let slot = Slot::channel_target(user_id, &kind);
+        sealed += sqlx::query(
+            "UPDATE notification_channels SET target = $4 WHERE user_id = $1 AND kind = $2 AND target = $3",
+        )
+        .bind(user_id)
+        .bind(&kind)
+        .bind(&target)
+        .bind(secrets.seal(&secrets.open(&target, &slot)?, &slot))
+        .execute(pool)
+        .await?
+        .rows_affected();
+    }
+
+    let notifications = sqlx::query_as::<_, (Uuid, Uuid, String, String)>(
+        "SELECT id, user_id, kind, target FROM notifications WHERE target NOT LIKE 'enc:v2:%'",
+    )
+    .fetch_all(pool)
+    .await?;
+    for (id, user_id, kind, target) in notifications {
+        let slot = Slot::channel_target(user_id, &kind);
+        sealed += sqlx::query("UPDATE notifications SET target = $3 WHERE id = $1 AND target = $2")
+            .bind(id)
+            .bind(&target)
+            .bind(secrets.seal(&secrets.open(&target, &slot)?, &slot))
+            .execute(pool)
+            .await?
+            .rows_affected();
+    }
+
+    let subscriptions = sqlx::query_as::<_, (Uuid, String)>(
+        "SELECT id, secret FROM webhook_subscriptions WHERE secret NOT LIKE 'enc:v2:%'",
+    )
+    .fetch_all(pool)
+    .await?;
+    for (id, secret) in subscriptions {
+        let slot = Slot::webhook_secret(id);
+        sealed += sqlx::query(
+            "UPDATE webhook_subscriptions SET secret = $3 WHERE id = $1 AND secret = $2",
+        )
+        .bind(id)
+        .bind(&secret)
+        .bind(secrets.seal(&secrets.open(&secret, &slot)?, &slot))
+        .execute(pool)
+        .await?
+        .rows_affected();
+    }
+
+    Ok(sealed)
+}
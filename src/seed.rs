@@ -0,0 +1,406 @@
+//! Demo data for trying the API without syncing real wallets.
+//!
+//! Seeding creates a demo user owning a few wallets with made-up but plausible
+//! histories: each wallet is funded with SOL, swaps part of it into popular memecoins
+//! through Jupiter, takes profit on some of them, pays priority fees and now and then
+//! receives an airdrop or sends SOL away. Hourly price candles of the memecoins are
+//! stored for the last week, and holdings are rebuilt from the seeded transactions.
+//!
+//! The data is generated by [`Fixtures`] from a seed, so the same seed always yields
+//! the same addresses, signatures, amounts and prices, dated relative to the time of
+//! seeding.
+
+use chrono::{DateTime, Duration, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rust_decimal::Decimal;
+use uuid::Uuid;
+
+use crate::auth;
+use crate::candles::{self, Candle, CandleInterval, CandleQuote};
+use crate::classify::{SwapDetails, TransactionCategory, JUPITER_PROGRAM_ID};
+use crate::error::AppError;
+use crate::holdings;
+use crate::models::{CreateWallet, Wallet, WalletAddress};
+use crate::money::TokenAmount;
+use crate::repository::NewTransaction;
+use crate::sync::NATIVE_SOL_MINT;
+use crate::AppState;
+
+/// Number of wallets seeded unless asked otherwise
+pub const DEFAULT_DEMO_WALLETS: usize = 3;
+
+/// Seed the demo data is generated from unless asked otherwise
+pub const DEFAULT_SEED: u64 = 420;
+
+/// Memecoins the demo wallets trade
+pub const DEMO_TOKENS: [DemoToken; 3] = [
+    DemoToken {
+        mint: "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263",
+        symbol: "BONK",
+        decimals: 5,
+        price_sol: 0.000_000_15,
+    },
+    DemoToken {
+        mint: "EKpQGSJtjMFqKZ9KQanSqYXRcF8fBopzLHYxdM65zcjm",
+        symbol: "WIF",
+        decimals: 6,
+        price_sol: 0.012,
+    },
+    DemoToken {
+        mint: "7GCihgDB8fe6KNjn2MYtkzZcRjQy3t9GHdC8uHYmW2hr",
+        symbol: "POPCAT",
+        decimals: 9,
+        price_sol: 0.005,
+    },
+];
+
+/// Decimals of native SOL
+const SOL_DECIMALS: u32 = 9;
+
+/// Slot processed at the time of seeding; earlier slots are roughly 400ms apart
+const CURRENT_SLOT: i64 = 290_000_000;
+
+/// Number of hourly candles stored per memecoin, one week's worth
+const DEMO_CANDLES: usize = 7 * 24;
+
+/// A memecoin the demo wallets trade
+#[derive(Debug, Clone, Copy)]
+pub struct DemoToken {
+    /// Mint address
+    pub mint: &'static str,
+    /// Ticker symbol
+    pub symbol: &'static str,
+    /// Decimals of the mint
+    pub decimals: u32,
+    /// Rough price in SOL that trades and candles move around
+    pub price_sol: f64,
+}
+
+/// How much demo data to seed
+#[derive(Debug, Clone, Copy)]
+pub struct SeedOptions {
+    /// Number of wallets to create
+    pub wallets: usize,
+    /// Seed the data is generated from
+    pub seed: u64,
+}
+
+impl Default for SeedOptions {
+    fn default() -> Self {
+        Self {
+            wallets: DEFAULT_DEMO_WALLETS,
+            seed: DEFAULT_SEED,
+        }
+    }
+}
+
+/// What seeding created
+#[derive(Debug)]
+pub struct SeedReport {
+    /// ID of the demo user owning the wallets
+    pub user_id: Uuid,
+    /// API key of the demo user
+    pub api_key: String,
+    /// The seeded wallets
+    pub wallets: Vec<Wallet>,
+    /// Number of transactions recorded across the wallets
+    pub transactions: u64,
+    /// Number of price candles stored across the memecoins
+    pub candles: usize,
+}
+
+/// Generator of deterministic demo data
+#[derive(Debug, Clone)]
+pub struct Fixtures {
+    rng: StdRng,
+    now: DateTime<Utc>,
+}
+
+impl Fixtures {
+    /// Creates a generator of the data of `seed`, dated back from `now`
+    pub fn new(seed: u64, now: DateTime<Utc>) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            now,
+        }
+    }
+
+    /// A valid base58 wallet or mint address
+    pub fn address(&mut self) -> String {
+        address(&mut self.rng)
+    }
+
+    /// A base58 transaction signature
+    pub fn signature(&mut self) -> String {
+        base58(&mut self.rng, 64)
+    }
+
+    /// A wallet's history over the last 30 days, oldest first
+    pub fn wallet_history(&mut self) -> Vec<NewTransaction> {
+        let mut time = self.now - Duration::days(30);
+        let mut rows = Vec::new();
+
+        // Funded from an exchange
+        let funding = self.rng.gen_range(5.0..50.0);
+        let exchange = self.address();
+        rows.push(self.transfer(
+            time,
+            NATIVE_SOL_MINT,
+            "SOL",
+            sol(funding),
+            TransactionCategory::TransferIn,
+            Some(exchange),
+        ));
+
+        let mut sol_left = funding;
+        for token in DEMO_TOKENS {
+            if self.rng.gen_bool(0.25) {
+                continue;
+            }
+            time += Duration::minutes(self.rng.gen_range(60..4 * 24 * 60));
+            let spent = sol_left * self.rng.gen_range(0.1..0.3);
+            sol_left -= spent;
+            let bought = spent / token.price_sol * self.rng.gen_range(0.97..1.0);
+            rows.extend(self.swap(time, token, true, spent, bought));
+
+            // Take profit on some positions
+            if self.rng.gen_bool(0.5) {
+                time += Duration::minutes(self.rng.gen_range(60..3 * 24 * 60));
+                let sold = bought * self.rng.gen_range(0.2..0.6);
+                let received = sold * token.price_sol * self.rng.gen_range(0.8..2.5);
+                sol_left += received;
+                rows.extend(self.swap(time, token, false, sold, received));
+            }
+
+            // Priority fees and tips paid to land the trades
+            let tip = self.rng.gen_range(0.0001..0.005);
+            sol_left -= tip;
+            rows.push(self.transfer(
+                time + Duration::seconds(1),
+                NATIVE_SOL_MINT,
+                "SOL",
+                -sol(tip),
+                TransactionCategory::Fee,
+                None,
+            ));
+        }
+
+        // Memecoins are airdropped to active wallets now and then
+        if self.rng.gen_bool(0.3) {
+            let token = DEMO_TOKENS[self.rng.gen_range(0..DEMO_TOKENS.len())];
+            let dropped = self.rng.gen_range(0.01..0.1) / token.price_sol;
+            time += Duration::minutes(self.rng.gen_range(10..24 * 60));
+            rows.push(self.transfer(
+                time,
+                token.mint,
+                token.symbol,
+                amount(dropped, token.decimals),
+                TransactionCategory::Airdrop,
+                None,
+            ));
+        }
+
+        // Some profits are moved to another wallet
+        if self.rng.gen_bool(0.3) {
+            let sent = sol_left * self.rng.gen_range(0.1..0.5);
+            let destination = self.address();
+            time += Duration::minutes(self.rng.gen_range(10..24 * 60));
+            rows.push(self.transfer(
+                time,
+                NATIVE_SOL_MINT,
+                "SOL",
+                -sol(sent),
+                TransactionCategory::TransferOut,
+                Some(destination),
+            ));
+        }
+
+        rows
+    }
+
+    /// Hourly candles of `token` over the last week, quoted in SOL
+    ///
+    /// Prices walk randomly around the token's rough price.
+    pub fn candles(&mut self, token: &DemoToken) -> Vec<Candle> {
+        let interval = CandleInterval::OneHour;
+        let first = interval.open_time(self.now)
+            - Duration::seconds(interval.seconds() * (DEMO_CANDLES as i64 - 1));
+
+        let mut price = token.price_sol * self.rng.gen_range(0.7..1.3);
+        (0..DEMO_CANDLES)
+            .map(|index| {
+                let open = price;
+                // Pulled back towards the rough price so the walk does not drift off
+                let pull = (token.price_sol / open).ln() * 0.05;
+                price = open * (pull + self.rng.gen_range(-0.04..0.04)).exp();
+                Candle {
+                    open_time: first + Duration::seconds(interval.seconds() * index as i64),
+                    open,
+                    high: open.max(price) * self.rng.gen_range(1.0..1.02),
+                    low: open.min(price) * self.rng.gen_range(0.98..1.0),
+                    close: price,
+                    volume: self.rng.gen_range(50.0..2_000.0) / token.price_sol,
+                }
+            })
+            .collect()
+    }
+
+    /// Both balance changes of a Jupiter swap, buying `token` with SOL or selling it
+    /// for SOL
+    fn swap(
+        &mut self,
+        time: DateTime<Utc>,
+        token: DemoToken,
+        buy: bool,
+        input_amount: f64,
+        output_amount: f64,
+    ) -> [NewTransaction; 2] {
+        let sol_side = (NATIVE_SOL_MINT, "SOL", SOL_DECIMALS);
+        let token_side = (token.mint, token.symbol, token.decimals);
+        let (input, output) = if buy {
+            (sol_side, token_side)
+        } else {
+            (token_side, sol_side)
+        };
+        let input_amount = amount(input_amount, input.2);
+        let output_amount = amount(output_amount, output.2);
+        let swap = SwapDetails {
+            program: "Jupiter".to_string(),
+            route: vec!["Raydium AMM".to_string()],
+            input_mint: input.0.to_string(),
+            input_amount: input_amount.decimal().to_string(),
+            output_mint: output.0.to_string(),
+            output_amount: output_amount.decimal().to_string(),
+        };
+
+        let hash = self.signature();
+        let row = |(mint, symbol, _): (&str, &str, u32), amount, category| NewTransaction {
+            transaction_hash: hash.clone(),
+            block_number: slot_at(self.now, time),
+            block_time: Some(time),
+            token_address: mint.to_string(),
+            token_symbol: symbol.to_string(),
+            amount,
+            category,
+            swap: Some(swap.clone()),
+            counterparty: Some(JUPITER_PROGRAM_ID.to_string()),
+        };
+        [
+            row(input, -input_amount, TransactionCategory::SwapSell),
+            row(output, output_amount, TransactionCategory::SwapBuy),
+        ]
+    }
+
+    /// A balance change that is a transaction of its own
+    fn transfer(
+        &mut self,
+        time: DateTime<Utc>,
+        mint: &str,
+        symbol: &str,
+        amount: TokenAmount,
+        category: TransactionCategory,
+        counterparty: Option<String>,
+    ) -> NewTransaction {
+        NewTransaction {
+            transaction_hash: self.signature(),
+            block_number: slot_at(self.now, time),
+            block_time: Some(time),
+            token_address: mint.to_string(),
+            token_symbol: symbol.to_string(),
+            amount,
+            category,
+            swap: None,
+            counterparty,
+        }
+    }
+}
+
+/// Creates a demo user with wallets, their transaction histories and price candles
+///
+/// Candles are quoted in SOL, so they are only stored if the configured candle source
+/// quotes in SOL too; otherwise charts are fetched from it as usual.
+pub async fn seed_demo(state: &AppState, options: &SeedOptions) -> Result<SeedReport, AppError> {
+    let (user, api_key) = auth::create_user(&state.db_pool, Some("Demo")).await?;
+    let mut fixtures = Fixtures::new(options.seed, Utc::now());
+
+    let mut report = SeedReport {
+        user_id: user.id,
+        api_key,
+        wallets: Vec::with_capacity(options.wallets),
+        transactions: 0,
+        candles: 0,
+    };
+    for index in 0..options.wallets {
+        let address = WalletAddress::parse(&fixtures.address())
+            .expect("Fixture addresses are valid wallet addresses");
+        let wallet = state
+            .wallets
+            .create(
+                user.id,
+                &CreateWallet {
+                    address,
+                    domain: None,
+                    name: Some(format!("Demo wallet {}", index + 1)),
+                    notes: Some("Seeded demo data".to_string()),
+                    metadata: None,
+                },
+            )
+            .await?;
+
+        let rows = fixtures.wallet_history();
+        report.transactions += state.transactions.bulk_insert(wallet.id, &rows).await?;
+        report.wallets.push(wallet);
+    }
+    holdings::rebuild(&state.db_pool).await?;
+
+    if state.candles.quote() == CandleQuote::Sol {
+        for token in &DEMO_TOKENS {
+            let series = fixtures.candles(token);
+            candles::store(
+                &state.db_pool,
+                state.candles.name(),
+                token.mint,
+                CandleInterval::OneHour,
+                series[0].open_time,
+                &series,
+            )
+            .await?;
+            report.candles += series.len();
+        }
+    }
+
+    Ok(report)
+}
+
+/// A random valid base58 wallet or mint address, for data that only has to be unique
+pub fn random_address() -> String {
+    address(&mut rand::thread_rng())
+}
+
+/// Base58 encoding of 32 bytes, which any address is
+fn address(rng: &mut impl Rng) -> String {
+    base58(rng, 32)
+}
+
+/// Base58 encoding of `len` random bytes
+fn base58(rng: &mut impl Rng, len: usize) -> String {
+    let bytes: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+    bs58::encode(bytes).into_string()
+}
+
+/// Slot processed at `time`, counting back from [`CURRENT_SLOT`] at `now`
+fn slot_at(now: DateTime<Utc>, time: DateTime<Utc>) -> i64 {
+    CURRENT_SLOT - (now - time).num_milliseconds() / 400
+}
+
+/// `value` SOL at full precision
+fn sol(value: f64) -> TokenAmount {
+    amount(value, SOL_DECIMALS)
+}
+
+/// `value` rounded to a token's decimals
+fn amount(value: f64, decimals: u32) -> TokenAmount {
+    let value = Decimal::from_f64_retain(value).unwrap_or_default();
+    TokenAmount::new(value.round_dp(decimals).normalize())
+}
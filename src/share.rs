@@ -0,0 +1,242 @@
+//! Public read-only share links of wallet portfolios.
+//!
+//! A wallet's owner creates a link and hands out its token; anyone holding the token
+//! can read a snapshot of the wallet's holdings and PnL without an API key, until the
+//! owner revokes the link. The token grants nothing else: not the wallet's
+//! transactions, name, notes or metadata, nor any other wallet. Only its hash is
+//! stored, like API keys.
+//!
+//! The same token reads a widget of the portfolio: its total value, 24h change and
+//! largest holdings, small and cacheable enough to embed in a Notion page or a
+//! personal site.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analytics::{CostBasisMethod, TokenPnl};
+use crate::auth::hash_api_key;
+use crate::models::Holding;
+use crate::money::Usd;
+use crate::AppError;
+
+/// Prefix of share link tokens, so they are recognizable and not mistaken for API keys
+const SHARE_TOKEN_PREFIX: &str = "shr_";
+
+/// Number of holdings a widget lists
+pub const WIDGET_TOP_HOLDINGS: usize = 5;
+
+/// A link sharing a wallet's portfolio
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct ShareLink {
+    /// Unique identifier of the link
+    pub id: Uuid,
+    /// ID of the shared wallet
+    pub wallet_id: Uuid,
+    /// When the link was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// A newly created share link together with its token
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreatedShareLink {
+    /// The created link
+    #[serde(flatten)]
+    pub link: ShareLink,
+    /// Token of the link, read at `GET /public/portfolio/{token}`; only returned once
+    pub token: String,
+}
+
+/// Read-only snapshot of a shared wallet's portfolio
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct PublicPortfolio {
+    /// Base58 address of the shared wallet
+    pub wallet_address: String,
+    /// Tokens held, excluding fully sold positions and spam
+    pub holdings: Vec<Holding>,
+    /// Sum of the priced holdings' values, in USD
+    #[schema(value_type = f64)]
+    pub total_value_usd: Usd,
+    /// Cost-basis method used for the PnL
+    pub method: CostBasisMethod,
+    /// Per-token PnL
+    pub tokens: Vec<TokenPnl>,
+    /// Sum of realized PnL across tokens, in USD
+    #[schema(value_type = f64)]
+    pub total_realized_pnl_usd: Usd,
+    /// Sum of unrealized PnL across tokens with a known price, in USD
+    #[schema(value_type = f64)]
+    pub total_unrealized_pnl_usd: Usd,
+    /// When the snapshot was computed
+    pub generated_at: DateTime<Utc>,
+}
+
+/// A holding listed by a portfolio widget
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct WidgetHolding {
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// URI of the token's logo image, if known
+    pub logo_uri: Option<String>,
+    /// Current USD value of the position
+    #[schema(example = 32.25)]
+    pub value_usd: f64,
+    /// Share of the portfolio's total value, in percent
+    #[schema(example = 64.5)]
+    pub percent: f64,
+    /// Price change over the last 24 hours in percent, if available
+    #[schema(example = -3.2)]
+    pub price_change_24h: Option<f64>,
+}
+
+/// Minimal snapshot of a shared portfolio, for embedding in web pages
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct PortfolioWidget {
+    /// Sum of the priced holdings' values, in USD
+    #[schema(example = 50.0)]
+    pub total_value_usd: f64,
+    /// Change of the total value over the last 24 hours in USD, from the holdings'
+    /// price changes
+    #[schema(example = -1.6)]
+    pub change_24h_usd: f64,
+    /// Change of the total value over the last 24 hours in percent; `null` without a
+    /// value 24 hours ago
+    #[schema(example = -3.1)]
+    pub change_24h_percent: Option<f64>,
+    /// The largest holdings by value, at most five
+    pub top_holdings: Vec<WidgetHolding>,
+    /// When the snapshot was computed
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Summarizes priced holdings into a widget
+///
+/// The 24h change assumes the amounts held did not change: each holding's value 24
+/// hours ago is derived from its price change, and holdings without a known change
+/// count as unchanged.
+pub fn widget(holdings: &[Holding], changes_24h: &HashMap<String, f64>) -> PortfolioWidget {
+    let mut priced: Vec<(&Holding, f64)> = holdings
+        .iter()
+        .filter_map(|holding| Some((holding, holding.value_usd?.to_f64())))
+        .filter(|(_, value)| *value > 0.0)
+        .collect();
+    priced.sort_by(|(_, a), (_, b)| b.total_cmp(a));
+
+    let total_value_usd: f64 = priced.iter().map(|(_, value)| value).sum();
+    let value_24h_ago: f64 = priced
+        .iter()
+        .map(
+            |(holding, value)| match changes_24h.get(&holding.token_address) {
+                Some(change) if *change > -100.0 => value / (1.0 + change / 100.0),
+                _ => *value,
+            },
+        )
+        .sum();
+    let change_24h_usd = total_value_usd - value_24h_ago;
+
+    let top_holdings = priced
+        .into_iter()
+        .take(WIDGET_TOP_HOLDINGS)
+        .map(|(holding, value_usd)| WidgetHolding {
+            token_symbol: holding.token_symbol.clone(),
+            logo_uri: holding.logo_uri.clone(),
+            value_usd,
+            percent: value_usd / total_value_usd * 100.0,
+            price_change_24h: changes_24h.get(&holding.token_address).copied(),
+        })
+        .collect();
+
+    PortfolioWidget {
+        total_value_usd,
+        change_24h_usd,
+        change_24h_percent: (value_24h_ago > 0.0).then(|| change_24h_usd / value_24h_ago * 100.0),
+        top_holdings,
+        generated_at: Utc::now(),
+    }
+}
+
+/// A wallet reached through a share link
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct SharedWallet {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// ID of the wallet's owner, whose spam filter applies
+    pub user_id: Uuid,
+    /// Address of the wallet
+    pub address: String,
+}
+
+/// Generates a new random share link token
+fn generate_token() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("{SHARE_TOKEN_PREFIX}{}", bs58::encode(bytes).into_string())
+}
+
+/// Creates a share link of the wallet, returning it together with its token
+pub async fn create_link(pool: &PgPool, wallet_id: Uuid) -> Result<CreatedShareLink, AppError> {
+    let token = generate_token();
+    let link = sqlx::query_as::<_, ShareLink>(
+        r#"
+        INSERT INTO share_links (id, wallet_id, token_hash)
+        VALUES ($1, $2, $3)
+        RETURNING id, wallet_id, created_at
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(wallet_id)
+    .bind(hash_api_key(&token))
+    .fetch_one(pool)
+    .await?;
+
+    Ok(CreatedShareLink { link, token })
+}
+
+/// Share links of the wallet, newest first
+pub async fn list_links(pool: &PgPool, wallet_id: Uuid) -> Result<Vec<ShareLink>, AppError> {
+    Ok(sqlx::query_as::<_, ShareLink>(
+        r#"
+        SELECT id, wallet_id, created_at
+        FROM share_links
+        WHERE wallet_id = $1
+        ORDER BY created_at DESC, id DESC
+        "#,
+    )
+    .bind(wallet_id)
+    .fetch_all(pool)
+    .await?)
+}
+
+/// Revokes a share link of the wallet, returning whether it existed
+pub async fn revoke_link(pool: &PgPool, wallet_id: Uuid, link_id: Uuid) -> Result<bool, AppError> {
+    let result = sqlx::query("DELETE FROM share_links WHERE id = $1 AND wallet_id = $2")
+        .bind(link_id)
+        .bind(wallet_id)
+        .execute(pool)
+        .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Looks up the wallet shared by `token`, returning `404 Not Found` for unknown and
+/// revoked tokens alike
+pub async fn shared_wallet(pool: &PgPool, token: &str) -> Result<SharedWallet, AppError> {
+    sqlx::query_as::<_, SharedWallet>(
+        r#"
+        SELECT w.id AS wallet_id, w.user_id, w.address
+        FROM share_links s
+        JOIN wallets w ON w.id = s.wallet_id
+        WHERE s.token_hash = $1 AND w.user_id IS NOT NULL
+        "#,
+    )
+    .bind(hash_api_key(token))
+    .fetch_optional(pool)
+    .await?
+    .ok_or_else(|| AppError::NotFound("Share link not found".to_string()))
+}
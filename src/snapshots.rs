@@ -0,0 +1,203 @@
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tokio::task::JoinHandle;
+use tracing::{error, info};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analytics;
+use crate::jobs::{self, Job};
+use crate::money::Usd;
+use crate::scheduler::{self, LeaderLock};
+use crate::{AppError, AppState};
+
+/// Longest history range that can be requested, in days
+pub const MAX_HISTORY_DAYS: u32 = 3650;
+
+/// Length of a history time series, written as a number of days such as `30d`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct HistoryRange {
+    days: u32,
+}
+
+impl HistoryRange {
+    /// Creates a range covering the given number of days
+    pub fn days(days: u32) -> Self {
+        Self { days }
+    }
+
+    /// Number of days covered by the range
+    pub fn num_days(&self) -> u32 {
+        self.days
+    }
+}
+
+impl Default for HistoryRange {
+    fn default() -> Self {
+        Self::days(30)
+    }
+}
+
+impl TryFrom<String> for HistoryRange {
+    type Error = String;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        let days = value
+            .strip_suffix('d')
+            .and_then(|days| days.parse::<u32>().ok())
+            .filter(|days| (1..=MAX_HISTORY_DAYS).contains(days))
+            .ok_or_else(|| {
+                format!("Invalid range {value:?}: expected 1d to {MAX_HISTORY_DAYS}d, e.g. 30d")
+            })?;
+
+        Ok(Self::days(days))
+    }
+}
+
+impl From<HistoryRange> for String {
+    fn from(range: HistoryRange) -> Self {
+        range.to_string()
+    }
+}
+
+impl fmt::Display for HistoryRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}d", self.days)
+    }
+}
+
+/// Total value of a wallet on one day
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct SnapshotPoint {
+    /// UTC day of the snapshot
+    #[schema(value_type = String, format = Date, example = "2025-07-19")]
+    pub date: NaiveDate,
+    /// Total USD value of the positions with a known price on that day
+    #[schema(value_type = f64)]
+    pub total_value_usd: Usd,
+}
+
+/// Time series of a wallet's daily total value
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct WalletHistory {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Requested range
+    #[schema(value_type = String, example = "30d")]
+    pub range: HistoryRange,
+    /// Daily snapshots in chronological order; days without a snapshot are omitted
+    pub points: Vec<SnapshotPoint>,
+}
+
+/// Spawns the background task that records a daily value snapshot of every wallet
+///
+/// Every `interval`, wallets without a snapshot for the current UTC day get a snapshot
+/// job, so a day missed while the server was down or the price feed failed is caught
+/// up on the next run. Only the instance holding `leader` queues.
+pub fn spawn_snapshot_job(
+    state: AppState,
+    interval: Duration,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!("Starting wallet snapshot job with interval {:?}", interval);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            ticker.tick().await;
+            if !scheduler::is_leader(&leader).await {
+                continue;
+            }
+
+            match run_snapshot_cycle(&state).await {
+                Ok(queued) if queued > 0 => info!("Queued {} wallet snapshots", queued),
+                Ok(_) => {}
+                Err(err) => error!("Wallet snapshot cycle failed: {}", err),
+            }
+        }
+    })
+}
+
+/// Queues today's snapshot of every wallet that has none yet and returns how many were
+/// queued; wallets whose snapshot is already queued are skipped
+pub async fn run_snapshot_cycle(state: &AppState) -> Result<usize, sqlx::Error> {
+    let due = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        SELECT w.id
+        FROM wallets w
+        WHERE NOT EXISTS (
+            SELECT 1 FROM snapshots s
+            WHERE s.wallet_id = w.id
+              AND s.snapshot_date = (NOW() AT TIME ZONE 'UTC')::DATE
+        )
+        ORDER BY w.created_at
+        "#,
+    )
+    .fetch_all(&state.db_pool)
+    .await?;
+
+    let mut queued = 0;
+    for wallet_id in due {
+        if jobs::enqueue(&state.db_pool, &Job::RecordSnapshot { wallet_id }).await? {
+            queued += 1;
+        }
+    }
+
+    Ok(queued)
+}
+
+/// Values a wallet and records it as today's snapshot, unless there is one already;
+/// returns whether a snapshot was recorded
+pub async fn record_snapshot(state: &AppState, wallet_id: Uuid) -> Result<bool, AppError> {
+    let holdings =
+        analytics::wallet_holdings(&state.db_pool, state.prices.as_ref(), wallet_id).await?;
+
+    let result = sqlx::query(
+        r#"
+        INSERT INTO snapshots (wallet_id, snapshot_date, total_value_usd)
+        VALUES ($1, (NOW() AT TIME ZONE 'UTC')::DATE, $2)
+        ON CONFLICT (wallet_id, snapshot_date) DO NOTHING
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(holdings.total_value_usd)
+    .execute(&state.db_pool)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Loads a wallet's daily snapshots covering the last `range` days, today included
+pub async fn wallet_history(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    range: HistoryRange,
+) -> Result<WalletHistory, sqlx::Error> {
+    let points = sqlx::query_as::<_, SnapshotPoint>(
+        r#"
+        SELECT snapshot_date AS date, total_value_usd
+        FROM snapshots
+        WHERE wallet_id = $1
+          AND snapshot_date > (NOW() AT TIME ZONE 'UTC')::DATE - $2::INTEGER
+        ORDER BY snapshot_date
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(range.num_days() as i32)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(WalletHistory {
+        wallet_id,
+        range,
+        points,
+    })
+}
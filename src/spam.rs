@@ -0,0 +1,169 @@
+//! Spam token filtering.
+//!
+//! Scam tokens are airdropped to wallets in bulk and clutter their holdings. A holding
+//! is spam if its user blocked the mint, if it is priced below the configured minimum
+//! value, or, with heuristics enabled, if its name or symbol reads like an ad: a link
+//! or a call to claim a reward.
+
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::models::Holding;
+use crate::{AppError, Config};
+
+/// Longest accepted blocklist reason, in characters
+pub const MAX_REASON_LENGTH: usize = 256;
+
+/// Fragments of links, which legitimate tokens do not put in their name or symbol
+const LINK_MARKERS: [&str; 9] = [
+    "http", "www.", ".com", ".io", ".xyz", ".net", ".org", ".app", "t.me",
+];
+
+/// Words scam tokens use to lure holders to a drainer site
+const LURE_WORDS: [&str; 6] = ["claim", "airdrop", "reward", "voucher", "visit", "gift"];
+
+/// A token on a user's blocklist
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct SpamToken {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Why the token was blocked
+    pub reason: Option<String>,
+    /// When the token was blocked
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request payload for blocking a token
+#[derive(Debug, Default, Serialize, Deserialize, ToSchema)]
+pub struct BlockSpamToken {
+    /// Why the token is blocked, for your own reference
+    #[schema(example = "Drainer airdrop")]
+    pub reason: Option<String>,
+}
+
+/// Rules deciding which holdings are spam
+#[derive(Debug, Clone, Default)]
+pub struct SpamFilter {
+    /// Mints the user blocked
+    pub blocked: HashSet<String>,
+    /// USD value below which priced holdings are spam
+    pub min_value_usd: Option<f64>,
+    /// Whether names and symbols are checked for links and lures
+    pub heuristics: bool,
+}
+
+impl SpamFilter {
+    /// Loads the filter of `user_id` with the server's configured thresholds
+    pub async fn load(pool: &PgPool, config: &Config, user_id: Uuid) -> Result<Self, sqlx::Error> {
+        let blocked = sqlx::query_scalar::<_, String>(
+            "SELECT token_address FROM spam_tokens WHERE user_id = $1",
+        )
+        .bind(user_id)
+        .fetch_all(pool)
+        .await?
+        .into_iter()
+        .collect();
+
+        Ok(Self {
+            blocked,
+            min_value_usd: config.spam_min_value_usd,
+            heuristics: config.spam_heuristics,
+        })
+    }
+
+    /// Whether the holding is spam; holdings without a price are only spam if blocked or
+    /// by their labels
+    pub fn is_spam(&self, holding: &Holding) -> bool {
+        if self.blocked.contains(&holding.token_address) {
+            return true;
+        }
+        if let (Some(min), Some(value)) = (self.min_value_usd, holding.value_usd) {
+            if value.to_f64() < min {
+                return true;
+            }
+        }
+        self.heuristics
+            && (looks_like_spam(&holding.token_symbol)
+                || holding.token_name.as_deref().is_some_and(looks_like_spam))
+    }
+}
+
+/// Whether a token name or symbol contains a link or a lure to claim something
+pub fn looks_like_spam(label: &str) -> bool {
+    let label = label.to_lowercase();
+    LINK_MARKERS
+        .iter()
+        .chain(LURE_WORDS.iter())
+        .any(|marker| label.contains(marker))
+}
+
+/// Lists the tokens `user_id` blocked, most recent first
+pub async fn list_blocked(pool: &PgPool, user_id: Uuid) -> Result<Vec<SpamToken>, sqlx::Error> {
+    sqlx::query_as::<_, SpamToken>(
+        r#"
+        SELECT token_address, reason, created_at
+        FROM spam_tokens
+        WHERE user_id = $1
+        ORDER BY created_at DESC, token_address
+        "#,
+    )
+    .bind(user_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Adds a mint to the blocklist of `user_id`, replacing the reason if it is already on it
+pub async fn block(
+    pool: &PgPool,
+    user_id: Uuid,
+    token_address: &str,
+    request: &BlockSpamToken,
+) -> Result<SpamToken, AppError> {
+    let reason = request
+        .reason
+        .as_deref()
+        .map(str::trim)
+        .filter(|reason| !reason.is_empty());
+    if reason.is_some_and(|reason| reason.chars().count() > MAX_REASON_LENGTH) {
+        return Err(AppError::UnprocessableEntity(format!(
+            "Reason must be at most {MAX_REASON_LENGTH} characters"
+        )));
+    }
+
+    Ok(sqlx::query_as::<_, SpamToken>(
+        r#"
+        INSERT INTO spam_tokens (user_id, token_address, reason)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (user_id, token_address) DO UPDATE SET reason = EXCLUDED.reason
+        RETURNING token_address, reason, created_at
+        "#,
+    )
+    .bind(user_id)
+    .bind(token_address)
+    .bind(reason)
+    .fetch_one(pool)
+    .await?)
+}
+
+/// Removes a mint from the blocklist of `user_id`
+pub async fn unblock(pool: &PgPool, user_id: Uuid, token_address: &str) -> Result<(), AppError> {
+    let result = sqlx::query("DELETE FROM spam_tokens WHERE user_id = $1 AND token_address = $2")
+        .bind(user_id)
+        .bind(token_address)
+        .execute(pool)
+        .await?;
+
+    if result.rows_affected() == 0 {
+        return Err(AppError::NotFound(format!(
+            "Token {token_address} is not blocked"
+        )));
+    }
+
+    Ok(())
+}
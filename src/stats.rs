@@ -0,0 +1,122 @@
+//! Statistics of the instance for its operators.
+//!
+//! Counts are read from the database on every request: they are meant for an
+//! operator's dashboard or a periodic scrape, not for hot paths. Cache hit rates count
+//! lookups since the process started and are per instance.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+
+use crate::cache::{CacheCounts, CacheMetrics};
+use crate::retention::{self, RetentionRun};
+use crate::AppError;
+
+/// Days within which a user must have authenticated a request to count as active
+pub const ACTIVE_USER_DAYS: i32 = 30;
+
+/// Lookups of one kind of cache entry
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct CacheStats {
+    /// Lookups answered from the cache
+    pub hits: u64,
+    /// Lookups of missing or expired entries
+    pub misses: u64,
+    /// Share of lookups answered from the cache, from 0 to 1; `null` before any lookup
+    #[schema(example = 0.93)]
+    pub hit_rate: Option<f64>,
+}
+
+impl From<CacheCounts> for CacheStats {
+    fn from(counts: CacheCounts) -> Self {
+        Self {
+            hits: counts.hits,
+            misses: counts.misses,
+            hit_rate: counts.hit_rate(),
+        }
+    }
+}
+
+/// Statistics of the instance
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ServerStats {
+    /// Registered users
+    pub total_users: i64,
+    /// Users who authenticated a request within the last 30 days
+    pub active_users: i64,
+    /// Tracked wallets, of all users
+    pub total_wallets: i64,
+    /// Stored transactions, of all wallets
+    pub total_transactions: i64,
+    /// Wallet syncs queued or running
+    pub sync_backlog: i64,
+    /// Jobs of any kind queued or running
+    pub pending_jobs: i64,
+    /// Jobs that failed for good
+    pub failed_jobs: i64,
+    /// Cache lookups since the process started, by kind of entry, e.g. `price_usd`
+    pub cache: BTreeMap<String, CacheStats>,
+    /// Size of the database on disk, in bytes
+    #[schema(example = 73400320)]
+    pub database_size_bytes: i64,
+    /// Rows pruned by the latest run of the data retention job, if it ever ran
+    pub last_retention_run: Option<RetentionRun>,
+    /// When the statistics were collected
+    pub generated_at: DateTime<Utc>,
+}
+
+/// Counts read from the database in one query
+#[derive(Debug, sqlx::FromRow)]
+struct Counts {
+    total_users: i64,
+    active_users: i64,
+    total_wallets: i64,
+    total_transactions: i64,
+    sync_backlog: i64,
+    pending_jobs: i64,
+    failed_jobs: i64,
+    database_size_bytes: i64,
+}
+
+/// Collects the instance's statistics
+pub async fn collect(pool: &PgPool, cache: &CacheMetrics) -> Result<ServerStats, AppError> {
+    let counts = sqlx::query_as::<_, Counts>(
+        r#"
+        SELECT
+            (SELECT COUNT(*) FROM users) AS total_users,
+            (SELECT COUNT(*) FROM users
+             WHERE last_seen_at > NOW() - make_interval(days => $1)) AS active_users,
+            (SELECT COUNT(*) FROM wallets) AS total_wallets,
+            (SELECT COUNT(*) FROM transactions) AS total_transactions,
+            (SELECT COUNT(*) FROM jobs
+             WHERE kind = 'sync_wallet' AND status IN ('pending', 'running')) AS sync_backlog,
+            (SELECT COUNT(*) FROM jobs WHERE status IN ('pending', 'running')) AS pending_jobs,
+            (SELECT COUNT(*) FROM jobs WHERE status = 'failed') AS failed_jobs,
+            pg_database_size(current_database()) AS database_size_bytes
+        "#,
+    )
+    .bind(ACTIVE_USER_DAYS)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(ServerStats {
+        total_users: counts.total_users,
+        active_users: counts.active_users,
+        total_wallets: counts.total_wallets,
+        total_transactions: counts.total_transactions,
+        sync_backlog: counts.sync_backlog,
+        pending_jobs: counts.pending_jobs,
+        failed_jobs: counts.failed_jobs,
+        cache: cache
+            .counts()
+            .into_iter()
+            .map(|(kind, counts)| (kind, counts.into()))
+            .collect(),
+        database_size_bytes: counts.database_size_bytes,
+        last_retention_run: retention::last_run(pool).await?,
+        generated_at: Utc::now(),
+    })
+}
@@ -0,0 +1,1067 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::types::Json;
+use sqlx::{PgConnection, PgPool};
+use thiserror::Error;
+use tracing::{debug, info, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::classify::{self, TransactionFacts};
+use crate::events::{EventBus, WalletEventKind};
+use crate::fees::{self, TransactionFees};
+use crate::models::Wallet;
+use crate::money::TokenAmount;
+use crate::outbox::{self, OutboxEvent};
+use crate::pumpfun;
+use crate::repository::{NewTransaction, RepositoryError, TransactionRepository};
+use crate::resilience::{
+    CircuitBreaker, CircuitOpen, DEFAULT_FAILURE_THRESHOLD, DEFAULT_OPEN_DURATION,
+};
+use crate::rpc::{backoff, EndpointHealth, RpcEndpoints};
+use crate::sync_state::{self, BackfillCheckpoint, SyncKind, Watermark};
+use crate::webhooks::DetectedTransaction;
+use crate::AppError;
+
+/// Mint address used to record native SOL balance changes
+pub const NATIVE_SOL_MINT: &str = "So11111111111111111111111111111111111111112";
+
+/// Number of decimals of native SOL (lamports per SOL = 10^9)
+pub(crate) const NATIVE_SOL_DECIMALS: u32 = 9;
+
+/// Most signatures `getSignaturesForAddress` returns per call
+const MAX_SIGNATURES_PER_PAGE: usize = 1000;
+
+/// Balance changes a backfill collects before writing them in one bulk load
+pub const BACKFILL_BATCH_ROWS: usize = 5000;
+
+/// Errors that can occur while syncing a wallet from the Solana RPC
+#[derive(Debug, Error)]
+pub enum SyncError {
+    /// The HTTP request to the RPC node failed
+    #[error("RPC request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The RPC node returned a JSON-RPC error object
+    #[error("RPC error {code}: {message}")]
+    Rpc {
+        /// JSON-RPC error code
+        code: i64,
+        /// JSON-RPC error message
+        message: String,
+    },
+
+    /// Every attempt was throttled; the last by this endpoint
+    #[error("RPC endpoint {url} is rate limiting requests")]
+    RateLimited {
+        /// URL of the endpoint
+        url: String,
+    },
+
+    /// The RPC is failing and calls are rejected until it recovers
+    #[error(transparent)]
+    Unavailable(#[from] CircuitOpen),
+
+    /// The RPC response could not be interpreted
+    #[error("Invalid RPC response: {0}")]
+    InvalidResponse(String),
+
+    /// Persisting synced transactions failed
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+
+    /// Persisting synced transactions through a repository failed
+    #[error(transparent)]
+    Repository(#[from] RepositoryError),
+}
+
+impl SyncError {
+    /// Whether the error points at the RPC being down or overloaded, rather than at
+    /// the particular request
+    fn is_outage(&self) -> bool {
+        matches!(self, SyncError::Http(_) | SyncError::RateLimited { .. })
+    }
+}
+
+impl From<SyncError> for AppError {
+    fn from(err: SyncError) -> Self {
+        match err {
+            SyncError::Database(db_err) => db_err.into(),
+            SyncError::Repository(repo_err) => repo_err.into(),
+            SyncError::Unavailable(open) => open.into(),
+            SyncError::Http(http_err) => http_err.into(),
+            other => AppError::UpstreamError(other.to_string()),
+        }
+    }
+}
+
+/// Signature entry returned by `getSignaturesForAddress`
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignatureInfo {
+    /// Base58 transaction signature
+    pub signature: String,
+    /// Slot the transaction was processed in
+    pub slot: u64,
+    /// Transaction error, if it failed
+    pub err: Option<Value>,
+    /// Estimated production time as a Unix timestamp
+    pub block_time: Option<i64>,
+}
+
+/// Retries of a call beyond trying each endpoint once
+const RPC_RETRIES: u32 = 3;
+
+/// Minimal JSON-RPC client for the Solana endpoints used by the sync subsystem
+///
+/// Calls fail over between the configured endpoints and back off when throttled;
+/// see [`RpcEndpoints`]. When calls keep failing on every endpoint, a
+/// [`CircuitBreaker`] rejects further ones with [`SyncError::Unavailable`] for a
+/// while. Clones share the endpoints' health, rate limits and breaker.
+#[derive(Debug, Clone)]
+pub struct SolanaRpcClient {
+    http: reqwest::Client,
+    endpoints: Arc<RpcEndpoints>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl SolanaRpcClient {
+    /// Creates a client for the given RPC endpoint
+    pub fn new(url: impl Into<String>) -> Self {
+        Self::with_endpoints(vec![url.into()], 0)
+    }
+
+    /// Creates a client for several RPC endpoints in order of preference, each
+    /// allowed `requests_per_second` requests; `0` disables the limit
+    ///
+    /// # Panics
+    ///
+    /// Panics if `urls` is empty.
+    pub fn with_endpoints(urls: Vec<String>, requests_per_second: u32) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            endpoints: Arc::new(RpcEndpoints::new(urls, requests_per_second)),
+            breaker: Arc::new(CircuitBreaker::new(
+                "Solana RPC",
+                DEFAULT_FAILURE_THRESHOLD,
+                DEFAULT_OPEN_DURATION,
+            )),
+        }
+    }
+
+    /// Guards calls with the given breaker instead of the default one
+    pub fn with_circuit_breaker(mut self, breaker: CircuitBreaker) -> Self {
+        self.breaker = Arc::new(breaker);
+        self
+    }
+
+    /// The preferred RPC endpoint this client talks to
+    pub fn url(&self) -> &str {
+        self.endpoints.url(0)
+    }
+
+    /// Current health of the endpoints, in order of preference
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.endpoints.health()
+    }
+
+    /// Performs a JSON-RPC call and returns the `result` member
+    ///
+    /// Transport failures, server errors and throttling are retried on the next
+    /// available endpoint; JSON-RPC errors are returned as they are.
+    async fn call(&self, method: &str, params: Value) -> Result<Value, SyncError> {
+        let body = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+
+        self.breaker
+            .call(|| self.call_endpoints(method, &body), SyncError::is_outage)
+            .await?
+    }
+
+    /// Sends a call to the endpoints in turn until one answers
+    async fn call_endpoints(&self, method: &str, body: &Value) -> Result<Value, SyncError> {
+        let attempts = self.endpoints.len() as u32 + RPC_RETRIES;
+        let mut last_error = None;
+        for _ in 0..attempts {
+            let (index, wait) = self.endpoints.acquire();
+            if !wait.is_zero() {
+                tokio::time::sleep(wait).await;
+            }
+
+            let url = self.endpoints.url(index);
+            match self.send(url, body).await {
+                Ok(response) => {
+                    self.endpoints.record_success(index);
+                    return Self::result(method, response);
+                }
+                Err((err, retry_after)) => {
+                    warn!("RPC call {} to {} failed: {}", method, url, err);
+                    self.endpoints.record_failure(index, retry_after);
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.expect("At least one RPC attempt is made"))
+    }
+
+    /// Sends a request to one endpoint, returning a retryable failure together with
+    /// the delay the endpoint asked for, if any
+    async fn send(&self, url: &str, body: &Value) -> Result<Value, (SyncError, Option<Duration>)> {
+        let response = self
+            .http
+            .post(url)
+            .json(body)
+            .send()
+            .await
+            .map_err(|err| (err.into(), None))?;
+
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            let retry_after = response
+                .headers()
+                .get(reqwest::header::RETRY_AFTER)
+                .and_then(|value| value.to_str().ok())
+                .and_then(|value| value.trim().parse::<u64>().ok())
+                .map(Duration::from_secs);
+            return Err((
+                SyncError::RateLimited {
+                    url: url.to_string(),
+                },
+                retry_after,
+            ));
+        }
+
+        let response = response
+            .error_for_status()
+            .map_err(|err| (err.into(), None))?;
+        response.json().await.map_err(|err| (err.into(), None))
+    }
+
+    /// Extracts the `result` member of a JSON-RPC response
+    fn result(method: &str, response: Value) -> Result<Value, SyncError> {
+        if let Some(error) = response.get("error") {
+            return Err(SyncError::Rpc {
+                code: error["code"].as_i64().unwrap_or_default(),
+                message: error["message"].as_str().unwrap_or_default().to_string(),
+            });
+        }
+
+        response
+            .get("result")
+            .cloned()
+            .ok_or_else(|| SyncError::InvalidResponse(format!("{method}: missing result")))
+    }
+
+    /// Fetches confirmed signatures for an address, newest first
+    pub async fn get_signatures_for_address(
+        &self,
+        address: &str,
+        before: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SignatureInfo>, SyncError> {
+        self.get_signatures_for_address_until(address, before, None, limit)
+            .await
+    }
+
+    /// Fetches confirmed signatures for an address, newest first, stopping short of
+    /// `until` if it is listed
+    pub async fn get_signatures_for_address_until(
+        &self,
+        address: &str,
+        before: Option<&str>,
+        until: Option<&str>,
+        limit: usize,
+    ) -> Result<Vec<SignatureInfo>, SyncError> {
+        let mut options = json!({ "limit": limit, "commitment": "confirmed" });
+        if let Some(before) = before {
+            options["before"] = json!(before);
+        }
+        if let Some(until) = until {
+            options["until"] = json!(until);
+        }
+
+        let result = self
+            .call("getSignaturesForAddress", json!([address, options]))
+            .await?;
+
+        serde_json::from_value(result)
+            .map_err(|e| SyncError::InvalidResponse(format!("getSignaturesForAddress: {e}")))
+    }
+
+    /// Fetches a confirmed transaction in `jsonParsed` encoding
+    ///
+    /// Returns `None` if the node no longer has the transaction.
+    pub async fn get_transaction(&self, signature: &str) -> Result<Option<Value>, SyncError> {
+        let result = self
+            .call(
+                "getTransaction",
+                json!([
+                    signature,
+                    {
+                        "encoding": "jsonParsed",
+                        "commitment": "confirmed",
+                        "maxSupportedTransactionVersion": 0
+                    }
+                ]),
+            )
+            .await?;
+
+        Ok((!result.is_null()).then_some(result))
+    }
+
+    /// Fetches an account in `jsonParsed` encoding
+    ///
+    /// Returns `None` if the account does not exist.
+    pub async fn get_account_info(&self, address: &str) -> Result<Option<Value>, SyncError> {
+        let result = self
+            .call(
+                "getAccountInfo",
+                json!([address, { "encoding": "jsonParsed", "commitment": "confirmed" }]),
+            )
+            .await?;
+
+        Ok(result.get("value").filter(|v| !v.is_null()).cloned())
+    }
+
+    /// Fetches several accounts in `jsonParsed` encoding, in the order of `addresses`,
+    /// with `None` for the ones that do not exist
+    pub async fn get_multiple_accounts(
+        &self,
+        addresses: &[String],
+    ) -> Result<Vec<Option<Value>>, SyncError> {
+        let result = self
+            .call(
+                "getMultipleAccounts",
+                json!([addresses, { "encoding": "jsonParsed", "commitment": "confirmed" }]),
+            )
+            .await?;
+
+        let accounts = result["value"].as_array().ok_or_else(|| {
+            SyncError::InvalidResponse("getMultipleAccounts: missing value".to_string())
+        })?;
+        Ok(accounts
+            .iter()
+            .map(|account| (!account.is_null()).then(|| account.clone()))
+            .collect())
+    }
+
+    /// Fetches the largest token accounts of a mint, largest first
+    ///
+    /// Nodes return at most the 20 largest.
+    pub async fn get_token_largest_accounts(
+        &self,
+        mint: &str,
+    ) -> Result<Vec<TokenAccountBalance>, SyncError> {
+        let result = self
+            .call(
+                "getTokenLargestAccounts",
+                json!([mint, { "commitment": "confirmed" }]),
+            )
+            .await?;
+
+        serde_json::from_value(result["value"].clone())
+            .map_err(|e| SyncError::InvalidResponse(format!("getTokenLargestAccounts: {e}")))
+    }
+
+    /// Fetches the lamport balance of an account
+    pub async fn get_balance(&self, address: &str) -> Result<u64, SyncError> {
+        let result = self
+            .call(
+                "getBalance",
+                json!([address, { "commitment": "confirmed" }]),
+            )
+            .await?;
+
+        result["value"]
+            .as_u64()
+            .ok_or_else(|| SyncError::InvalidResponse("getBalance: missing value".to_string()))
+    }
+
+    /// Fetches the token accounts of `program_id` owned by `owner`, in `jsonParsed`
+    /// encoding
+    pub async fn get_token_accounts_by_owner(
+        &self,
+        owner: &str,
+        program_id: &str,
+    ) -> Result<Vec<Value>, SyncError> {
+        let result = self
+            .call(
+                "getTokenAccountsByOwner",
+                json!([
+                    owner,
+                    { "programId": program_id },
+                    { "encoding": "jsonParsed", "commitment": "confirmed" }
+                ]),
+            )
+            .await?;
+
+        result["value"].as_array().cloned().ok_or_else(|| {
+            SyncError::InvalidResponse("getTokenAccountsByOwner: missing value".to_string())
+        })
+    }
+}
+
+/// Balance of a token account, as returned by `getTokenLargestAccounts`
+#[derive(Debug, Clone, Deserialize)]
+pub struct TokenAccountBalance {
+    /// Address of the token account
+    pub address: String,
+    /// Raw balance in base units, as a decimal string
+    pub amount: String,
+}
+
+/// Net balance change of a single token for the synced wallet within one transaction
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TokenDelta {
+    /// Token mint address
+    pub mint: String,
+    /// Signed amount in whole tokens
+    pub amount: TokenAmount,
+    /// Account on the other side of the change, if one is apparent
+    pub counterparty: Option<String>,
+}
+
+/// Computes the wallet's per-mint balance changes in a `jsonParsed` transaction
+///
+/// SPL token changes are read from the pre/post token balances owned by `owner`.
+/// Native SOL changes are read from the lamport balances, excluding the network
+/// fee when the wallet paid it.
+pub fn token_deltas(transaction: &Value, owner: &str) -> Vec<TokenDelta> {
+    let meta = &transaction["meta"];
+    let mut balances: HashMap<(String, String), (i128, u32)> = HashMap::new();
+
+    for (key, sign) in [("preTokenBalances", -1), ("postTokenBalances", 1)] {
+        let Some(entries) = meta[key].as_array() else {
+            continue;
+        };
+
+        for entry in entries {
+            let (Some(holder), Some(mint), Some(raw)) = (
+                entry["owner"].as_str(),
+                entry["mint"].as_str(),
+                entry["uiTokenAmount"]["amount"]
+                    .as_str()
+                    .and_then(|a| a.parse::<i128>().ok()),
+            ) else {
+                continue;
+            };
+            let decimals = entry["uiTokenAmount"]["decimals"].as_u64().unwrap_or(0) as u32;
+
+            let balance = balances
+                .entry((holder.to_string(), mint.to_string()))
+                .or_insert((0, decimals));
+            balance.0 += sign * raw;
+        }
+    }
+
+    let mut deltas: Vec<TokenDelta> = balances
+        .iter()
+        .filter(|((holder, _), (raw, _))| holder == owner && *raw != 0)
+        .filter_map(|((_, mint), (raw, decimals))| {
+            Some(TokenDelta {
+                mint: mint.clone(),
+                amount: TokenAmount::from_raw(*raw, *decimals)?,
+                counterparty: counterparty(
+                    balances
+                        .iter()
+                        .filter(|((_, other), _)| other == mint)
+                        .map(|((holder, _), (raw, _))| (holder.as_str(), *raw)),
+                    owner,
+                    *raw,
+                ),
+            })
+        })
+        .collect();
+
+    let lamports = lamport_deltas(transaction);
+    if let Some(&(_, delta)) = lamports.iter().find(|(account, _)| *account == owner) {
+        if let Some(amount) = TokenAmount::from_raw(delta, NATIVE_SOL_DECIMALS) {
+            if !amount.is_zero() {
+                deltas.push(TokenDelta {
+                    mint: NATIVE_SOL_MINT.to_string(),
+                    amount,
+                    counterparty: counterparty(lamports.iter().copied(), owner, delta),
+                });
+            }
+        }
+    }
+
+    deltas.sort_by(|a, b| a.mint.cmp(&b.mint));
+    deltas
+}
+
+/// Lamport balance change of every account, ignoring the fee the fee payer paid
+fn lamport_deltas(transaction: &Value) -> Vec<(&str, i128)> {
+    let meta = &transaction["meta"];
+    let Some(keys) = transaction["transaction"]["message"]["accountKeys"].as_array() else {
+        return Vec::new();
+    };
+    let fee = meta["fee"].as_u64().unwrap_or(0) as i128;
+
+    keys.iter()
+        .enumerate()
+        .filter_map(|(index, key)| {
+            let account = key["pubkey"].as_str().or(key.as_str())?;
+            let pre = meta["preBalances"].get(index)?.as_u64()? as i128;
+            let post = meta["postBalances"].get(index)?.as_u64()? as i128;
+            let fee = if index == 0 { fee } else { 0 };
+            Some((account, post - pre + fee))
+        })
+        .collect()
+}
+
+/// The account other than `owner` whose balance moved opposite to `owner`'s change of
+/// `raw` by the most, given every account's change of the same token; ties go to the
+/// first account in address order
+pub(crate) fn counterparty<'a>(
+    changes: impl Iterator<Item = (&'a str, i128)>,
+    owner: &str,
+    raw: i128,
+) -> Option<String> {
+    changes
+        .filter(|(account, change)| *account != owner && change.signum() == -raw.signum())
+        .max_by(|(a, x), (b, y)| x.unsigned_abs().cmp(&y.unsigned_abs()).then(b.cmp(a)))
+        .map(|(account, _)| account.to_string())
+}
+
+/// Formats a raw integer token amount as a decimal string with `decimals` places
+pub fn format_units(raw: i128, decimals: u32) -> String {
+    let sign = if raw < 0 { "-" } else { "" };
+    let abs = raw.unsigned_abs();
+    let scale = 10u128.pow(decimals);
+    let whole = abs / scale;
+    let fraction = abs % scale;
+
+    if fraction == 0 {
+        return format!("{sign}{whole}");
+    }
+
+    let fraction = format!("{fraction:0width$}", width = decimals as usize);
+    format!("{sign}{whole}.{}", fraction.trim_end_matches('0'))
+}
+
+/// Parses a decimal string into a raw integer amount with `decimals` places, ignoring
+/// digits beyond them; the inverse of [`format_units`]
+pub fn parse_units(amount: &str, decimals: u32) -> Option<i128> {
+    let (negative, digits) = match amount.trim().strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, amount.trim()),
+    };
+    let (whole, fraction) = digits.split_once('.').unwrap_or((digits, ""));
+    if whole.is_empty() && fraction.is_empty()
+        || !whole
+            .chars()
+            .chain(fraction.chars())
+            .all(|c| c.is_ascii_digit())
+    {
+        return None;
+    }
+
+    let fraction: String = fraction
+        .chars()
+        .chain(std::iter::repeat('0'))
+        .take(decimals as usize)
+        .collect();
+    let raw = format!("{whole}{fraction}").parse::<i128>().ok()?;
+    Some(if negative { -raw } else { raw })
+}
+
+/// Summary of a completed wallet sync
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct SyncReport {
+    /// ID of the synced wallet
+    pub wallet_id: Uuid,
+    /// Number of signatures returned by the RPC node
+    pub signatures_fetched: usize,
+    /// Number of balance changes processed, including ones already recorded
+    pub transactions_upserted: usize,
+    /// Number of those balance changes that were not recorded before
+    pub transactions_inserted: usize,
+}
+
+/// Pulls recent confirmed transactions for a wallet and upserts them into `transactions`
+///
+/// The first sync fetches the `limit` most recent signatures; later ones only fetch
+/// the signatures after the newest one processed before, the wallet's watermark.
+/// Each transaction produces one row per token whose balance changed for the wallet,
+/// tagged with its [`TransactionCategory`].
+/// Re-syncing the same signatures never duplicates rows; see [`upsert_transaction`].
+/// On success the wallet's `last_synced_at` is bumped to now, and newly recorded
+/// transactions are announced on the event bus and, through the
+/// [outbox](crate::outbox), to the owner's `transaction_detected` webhooks, and checked
+/// for whale movements from `whale_threshold_usd`. Progress and outcome are recorded in
+/// `wallet_sync_state`; see [`sync_state`].
+pub async fn sync_wallet(
+    pool: &PgPool,
+    rpc: &SolanaRpcClient,
+    events: &dyn EventBus,
+    wallet: &Wallet,
+    limit: usize,
+    whale_threshold_usd: Option<f64>,
+) -> Result<SyncReport, SyncError> {
+    info!("Syncing wallet {} ({})", wallet.id, wallet.address);
+
+    sync_state::start(pool, wallet.id, SyncKind::Sync, None).await?;
+    let result = run_sync(pool, rpc, events, wallet, limit, whale_threshold_usd).await;
+    record_outcome(pool, wallet.id, &result).await;
+    result
+}
+
+/// Records how a run ended, logging rather than masking a failure to do so
+async fn record_outcome(pool: &PgPool, wallet_id: Uuid, result: &Result<SyncReport, SyncError>) {
+    let error = result.as_ref().err().map(ToString::to_string);
+    if let Err(err) = sync_state::finish(pool, wallet_id, error.as_deref()).await {
+        warn!(
+            "Failed to record sync state of wallet {}: {}",
+            wallet_id, err
+        );
+    }
+}
+
+/// Fetches and records the wallet's recent transactions
+async fn run_sync(
+    pool: &PgPool,
+    rpc: &SolanaRpcClient,
+    events: &dyn EventBus,
+    wallet: &Wallet,
+    limit: usize,
+    whale_threshold_usd: Option<f64>,
+) -> Result<SyncReport, SyncError> {
+    let watermark = sync_state::watermark(pool, wallet.id).await?;
+    let signatures = signatures_after(rpc, wallet, watermark.as_ref(), limit).await?;
+    sync_state::set_total(pool, wallet.id, signatures.len() as i64).await?;
+
+    let mut upserted = 0;
+    let mut detected = Vec::new();
+    for (processed, info) in signatures.iter().enumerate() {
+        if processed % sync_state::PROGRESS_INTERVAL == 0 && processed > 0 {
+            sync_state::progress(
+                pool,
+                wallet.id,
+                processed,
+                &signatures[processed - 1].signature,
+            )
+            .await?;
+        }
+        if info.err.is_some() {
+            continue;
+        }
+
+        let Some(transaction) = rpc.get_transaction(&info.signature).await? else {
+            warn!("Transaction {} not available from RPC", info.signature);
+            continue;
+        };
+
+        let (rows, inserted) =
+            record_transaction(pool, wallet, info, &transaction, whale_threshold_usd).await?;
+        upserted += rows;
+        detected.extend(inserted);
+    }
+
+    if let Some(last) = signatures.last() {
+        sync_state::progress(pool, wallet.id, signatures.len(), &last.signature).await?;
+    }
+    if let Some(newest) = signatures.first() {
+        sync_state::advance_watermark(pool, wallet.id, &newest.signature, newest.slot).await?;
+    }
+    sqlx::query("UPDATE wallets SET last_synced_at = NOW() WHERE id = $1")
+        .bind(wallet.id)
+        .execute(pool)
+        .await?;
+
+    if !detected.is_empty() {
+        events.publish(
+            wallet.id,
+            WalletEventKind::TransactionsDetected {
+                transactions: detected.clone(),
+            },
+        );
+    }
+    events.publish(
+        wallet.id,
+        WalletEventKind::WalletSynced {
+            signatures_fetched: signatures.len(),
+            transactions_upserted: upserted,
+        },
+    );
+
+    debug!(
+        "Wallet {} sync complete: {} signatures, {} rows",
+        wallet.id,
+        signatures.len(),
+        upserted
+    );
+
+    Ok(SyncReport {
+        wallet_id: wallet.id,
+        signatures_fetched: signatures.len(),
+        transactions_upserted: upserted,
+        transactions_inserted: detected.len(),
+    })
+}
+
+/// Lists the signatures a sync processes, newest first
+///
+/// Without a watermark, these are the `limit` most recent signatures. Otherwise they
+/// are every signature after the watermark, listed `limit` at a time; a sync that
+/// skipped a page would leave a gap the watermark then hides. Should the watermark no
+/// longer be listed, its fork having been dropped, listing stops at its slot instead.
+/// Signatures of the same slot are processed again, which ingestion's idempotency
+/// makes harmless.
+async fn signatures_after(
+    rpc: &SolanaRpcClient,
+    wallet: &Wallet,
+    watermark: Option<&Watermark>,
+    limit: usize,
+) -> Result<Vec<SignatureInfo>, SyncError> {
+    let Some(watermark) = watermark else {
+        return rpc
+            .get_signatures_for_address(&wallet.address, None, limit)
+            .await;
+    };
+
+    let mut signatures: Vec<SignatureInfo> = Vec::new();
+    loop {
+        let before = signatures.last().map(|info| info.signature.clone());
+        let page = rpc
+            .get_signatures_for_address_until(
+                &wallet.address,
+                before.as_deref(),
+                Some(&watermark.signature),
+                limit,
+            )
+            .await?;
+        let full = page.len() >= limit;
+
+        for info in page {
+            if info.signature == watermark.signature {
+                return Ok(signatures);
+            }
+            if info.slot < watermark.slot {
+                warn!(
+                    "Watermark {} of wallet {} is no longer listed; synced back to its slot {}",
+                    watermark.signature, wallet.id, watermark.slot
+                );
+                return Ok(signatures);
+            }
+            signatures.push(info);
+        }
+        if !full {
+            return Ok(signatures);
+        }
+    }
+}
+
+/// Loads a wallet's full transaction history
+///
+/// Pages through every signature of the wallet, not just the most recent `limit`,
+/// from the newest back to its first transaction, and writes the balance changes in
+/// bulk through [`TransactionRepository::bulk_insert`] every `batch_rows` changes,
+/// which is far faster than upserting them one by one. After each batch, progress is
+/// recorded in `wallet_sync_state` and a checkpoint in `backfill_checkpoints`, so a
+/// backfill that crashed or failed resumes where it stopped when run again; once it
+/// completes, the next one starts over from the newest signature. Calls the RPC
+/// throttles are retried with backoff rather than failing the backfill. Historical
+/// transactions are not announced on the event bus or to webhooks, and
+/// `last_synced_at` is left for regular syncs to maintain.
+pub async fn backfill_wallet(
+    pool: &PgPool,
+    rpc: &SolanaRpcClient,
+    transactions: &dyn TransactionRepository,
+    wallet: &Wallet,
+    batch_rows: usize,
+) -> Result<SyncReport, SyncError> {
+    info!("Backfilling wallet {} ({})", wallet.id, wallet.address);
+
+    sync_state::start(pool, wallet.id, SyncKind::Backfill, None).await?;
+    let result = run_backfill(pool, rpc, transactions, wallet, batch_rows).await;
+    record_outcome(pool, wallet.id, &result).await;
+    result
+}
+
+/// Pages through the wallet's signatures from its checkpoint, if any, loading their
+/// balance changes
+async fn run_backfill(
+    pool: &PgPool,
+    rpc: &SolanaRpcClient,
+    transactions: &dyn TransactionRepository,
+    wallet: &Wallet,
+    batch_rows: usize,
+) -> Result<SyncReport, SyncError> {
+    let checkpoint = sync_state::checkpoint(pool, wallet.id).await?;
+    let mut report = SyncReport {
+        wallet_id: wallet.id,
+        signatures_fetched: 0,
+        transactions_upserted: 0,
+        transactions_inserted: 0,
+    };
+    let mut before = None;
+    if let Some(checkpoint) = checkpoint {
+        info!(
+            "Resuming backfill of wallet {} before {} ({} signatures done)",
+            wallet.id, checkpoint.before_signature, checkpoint.signatures_processed
+        );
+        report.signatures_fetched = checkpoint.signatures_processed as usize;
+        report.transactions_upserted = checkpoint.transactions_upserted as usize;
+        report.transactions_inserted = checkpoint.transactions_inserted as usize;
+        before = Some(checkpoint.before_signature);
+    }
+
+    let mut batch = Vec::with_capacity(batch_rows);
+    loop {
+        let page = patiently(|| {
+            rpc.get_signatures_for_address(
+                &wallet.address,
+                before.as_deref(),
+                MAX_SIGNATURES_PER_PAGE,
+            )
+        })
+        .await?;
+        // A short page is the oldest one
+        let last_page = page.len() < MAX_SIGNATURES_PER_PAGE;
+
+        for (index, info) in page.iter().enumerate() {
+            if info.err.is_none() {
+                match patiently(|| rpc.get_transaction(&info.signature)).await? {
+                    Some(transaction) => {
+                        if let Some(paid) =
+                            TransactionFees::from_parsed(&transaction, &wallet.address)
+                        {
+                            let block_time = block_time(info, &transaction);
+                            fees::record(pool, wallet.id, &info.signature, block_time, &paid)
+                                .await?;
+                        }
+                        pumpfun::record_status(pool, &pumpfun::instructions(&transaction)).await?;
+                        batch.extend(transaction_rows(wallet, info, &transaction));
+                    }
+                    None => warn!("Transaction {} not available from RPC", info.signature),
+                }
+            }
+            report.signatures_fetched += 1;
+
+            if batch.len() >= batch_rows || index + 1 == page.len() {
+                report.transactions_inserted +=
+                    transactions.bulk_insert(wallet.id, &batch).await? as usize;
+                report.transactions_upserted += batch.len();
+                batch.clear();
+
+                sync_state::progress(pool, wallet.id, report.signatures_fetched, &info.signature)
+                    .await?;
+                let checkpoint = BackfillCheckpoint {
+                    before_signature: info.signature.clone(),
+                    signatures_processed: report.signatures_fetched as i64,
+                    transactions_upserted: report.transactions_upserted as i64,
+                    transactions_inserted: report.transactions_inserted as i64,
+                };
+                sync_state::save_checkpoint(pool, wallet.id, &checkpoint).await?;
+            }
+        }
+
+        if last_page {
+            break;
+        }
+        before = page.last().map(|info| info.signature.clone());
+    }
+
+    sync_state::set_total(pool, wallet.id, report.signatures_fetched as i64).await?;
+    sync_state::clear_checkpoint(pool, wallet.id).await?;
+    info!(
+        "Wallet {} backfill complete: {} signatures, {} rows, {} new",
+        wallet.id,
+        report.signatures_fetched,
+        report.transactions_upserted,
+        report.transactions_inserted
+    );
+
+    Ok(report)
+}
+
+/// Times a backfill retries an RPC call that was throttled or turned away by the
+/// open circuit breaker
+const BACKFILL_RPC_RETRIES: u32 = 5;
+
+/// Makes an RPC call, waiting out rate limits and an open circuit breaker up to
+/// [`BACKFILL_RPC_RETRIES`] times
+async fn patiently<T, F, Fut>(mut call: F) -> Result<T, SyncError>
+where
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, SyncError>>,
+{
+    let mut retries = 0;
+    loop {
+        let result = call().await;
+        let wait = match &result {
+            Err(SyncError::RateLimited { .. }) => backoff(retries + 1),
+            Err(SyncError::Unavailable(open)) => open.retry_in,
+            _ => return result,
+        };
+        if retries == BACKFILL_RPC_RETRIES {
+            return result;
+        }
+        warn!("RPC is throttling the backfill, retrying in {:?}", wait);
+        tokio::time::sleep(wait).await;
+        retries += 1;
+    }
+}
+
+/// Records the wallet's fees, balance changes and pump.fun status updates in a fetched
+/// transaction
+///
+/// The balance changes are written in one database transaction with the
+/// [outbox](crate::outbox) event announcing the newly inserted ones, which are checked
+/// for whale movements from `whale_threshold_usd` when relayed. Returns the number of
+/// balance changes upserted and the ones newly inserted.
+pub(crate) async fn record_transaction(
+    pool: &PgPool,
+    wallet: &Wallet,
+    info: &SignatureInfo,
+    transaction: &Value,
+    whale_threshold_usd: Option<f64>,
+) -> Result<(usize, Vec<DetectedTransaction>), sqlx::Error> {
+    let block_time = block_time(info, transaction);
+
+    if let Some(paid) = TransactionFees::from_parsed(transaction, &wallet.address) {
+        fees::record(pool, wallet.id, &info.signature, block_time, &paid).await?;
+    }
+    pumpfun::record_status(pool, &pumpfun::instructions(transaction)).await?;
+
+    let mut upserted = 0;
+    let mut detected = Vec::new();
+    let mut tx = pool.begin().await?;
+    for row in transaction_rows(wallet, info, transaction) {
+        let inserted = upsert_transaction(&mut tx, wallet.id, &row).await?;
+        upserted += 1;
+
+        if inserted {
+            detected.push(DetectedTransaction {
+                transaction_hash: row.transaction_hash,
+                token_address: row.token_address,
+                amount: row.amount,
+                block_time,
+            });
+        }
+    }
+    if !detected.is_empty() {
+        outbox::record(
+            &mut tx,
+            wallet.id,
+            &OutboxEvent::TransactionsDetected {
+                transactions: detected.clone(),
+                whale_threshold_usd,
+            },
+        )
+        .await?;
+    }
+    tx.commit().await?;
+
+    Ok((upserted, detected))
+}
+
+/// Block time of a fetched transaction, falling back to the one listed with its signature
+fn block_time(info: &SignatureInfo, transaction: &Value) -> Option<DateTime<Utc>> {
+    transaction["blockTime"]
+        .as_i64()
+        .or(info.block_time)
+        .and_then(|ts| Utc.timestamp_opt(ts, 0).single())
+}
+
+/// The wallet's classified balance changes in a fetched transaction
+fn transaction_rows(
+    wallet: &Wallet,
+    info: &SignatureInfo,
+    transaction: &Value,
+) -> Vec<NewTransaction> {
+    let block_time = block_time(info, transaction);
+
+    let facts = TransactionFacts::from_parsed(transaction, &wallet.address);
+    let deltas = token_deltas(transaction, &wallet.address);
+    deltas
+        .iter()
+        .map(|delta| NewTransaction {
+            transaction_hash: info.signature.clone(),
+            block_number: info.slot as i64,
+            block_time,
+            token_address: delta.mint.clone(),
+            token_symbol: known_symbol(&delta.mint).to_string(),
+            amount: delta.amount,
+            category: classify::classify(&facts, &deltas, delta),
+            swap: facts.swap.clone(),
+            counterparty: delta.counterparty.clone(),
+        })
+        .collect()
+}
+
+/// Symbol recorded for a mint before its metadata is known
+pub(crate) fn known_symbol(mint: &str) -> &'static str {
+    if mint == NATIVE_SOL_MINT {
+        "SOL"
+    } else {
+        ""
+    }
+}
+
+/// Inserts or updates the transaction row for a signature/mint pair
+///
+/// Ingestion is idempotent: every `(wallet, signature, mint)` has exactly one row, so
+/// ingesting the same signature again from any path never duplicates it. A row is only
+/// rewritten when the new data differs (e.g. a corrected slot or category); identical
+/// re-ingestion leaves it untouched. Returns `true` if the row was newly inserted.
+pub(crate) async fn upsert_transaction(
+    conn: &mut PgConnection,
+    wallet_id: Uuid,
+    row: &NewTransaction,
+) -> Result<bool, sqlx::Error> {
+    let inserted = sqlx::query_scalar::<_, bool>(
+        r#"
+        INSERT INTO transactions (
+            id, wallet_id, token_address, token_symbol, amount,
+            buy_price_usd, buy_price_sol, transaction_hash, block_number, block_time, category,
+            swap, counterparty
+        )
+        VALUES ($1, $2, $3, $4, $5::NUMERIC, 0, 0, $6, $7, $8, $9, $10, $11)
+        ON CONFLICT ON CONSTRAINT transactions_wallet_hash_token_key DO UPDATE
+        SET amount = EXCLUDED.amount,
+            block_number = EXCLUDED.block_number,
+            block_time = EXCLUDED.block_time,
+            category = EXCLUDED.category,
+            swap = EXCLUDED.swap,
+            counterparty = EXCLUDED.counterparty
+        WHERE (transactions.amount, transactions.block_number, transactions.block_time,
+               transactions.category, transactions.swap, transactions.counterparty)
+              IS DISTINCT FROM
+              (EXCLUDED.amount, EXCLUDED.block_number, EXCLUDED.block_time, EXCLUDED.category,
+               EXCLUDED.swap, EXCLUDED.counterparty)
+        RETURNING (xmax = 0) AS inserted
+        "#,
+    )
+    .bind(Uuid::now_v7())
+    .bind(wallet_id)
+    .bind(&row.token_address)
+    .bind(&row.token_symbol)
+    .bind(row.amount)
+    .bind(&row.transaction_hash)
+    .bind(row.block_number)
+    .bind(row.block_time)
+    .bind(row.category.as_str())
+    .bind(row.swap.as_ref().map(Json))
+    .bind(&row.counterparty)
+    .fetch_optional(conn)
+    .await?;
+
+    // No row is returned when an identical row already exists
+    Ok(inserted.unwrap_or(false))
+}
@@ -0,0 +1,401 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Signatures processed between progress updates of a running sync
+pub const PROGRESS_INTERVAL: usize = 100;
+
+/// Whether a wallet is being synced
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncState {
+    /// No sync is running; the last one, if any, succeeded
+    Idle,
+    /// A sync or backfill is in progress
+    Running,
+    /// The last sync or backfill failed; see `last_error`
+    Failed,
+}
+
+impl SyncState {
+    /// Value stored in `wallet_sync_state.status`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Idle => "idle",
+            Self::Running => "running",
+            Self::Failed => "failed",
+        }
+    }
+
+    fn from_db(value: &str) -> Self {
+        match value {
+            "running" => Self::Running,
+            "failed" => Self::Failed,
+            _ => Self::Idle,
+        }
+    }
+}
+
+/// Kind of run that syncs a wallet
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncKind {
+    /// Fetches the most recent signatures
+    Sync,
+    /// Loads the full history
+    Backfill,
+}
+
+impl SyncKind {
+    /// Value stored in `wallet_sync_state.kind`
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Sync => "sync",
+            Self::Backfill => "backfill",
+        }
+    }
+
+    fn from_db(value: &str) -> Self {
+        match value {
+            "backfill" => Self::Backfill,
+            _ => Self::Sync,
+        }
+    }
+}
+
+/// Progress and outcome of a wallet's latest sync or backfill
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct SyncStatus {
+    /// ID of the wallet
+    pub wallet_id: Uuid,
+    /// Whether a sync is running, or how the last one ended
+    pub state: SyncState,
+    /// Kind of the latest run; `null` if the wallet was never synced
+    pub kind: Option<SyncKind>,
+    /// Signature most recently processed by the latest run
+    pub last_signature: Option<String>,
+    /// Signatures processed so far by the latest run
+    pub signatures_processed: i64,
+    /// Signatures the latest run covers; `null` until a backfill has paged through them all
+    pub signatures_total: Option<i64>,
+    /// Share of the signatures processed, from 0 to 100, when the total is known
+    #[schema(example = 42.5)]
+    pub progress_percent: Option<f64>,
+    /// Why the last run failed
+    pub last_error: Option<String>,
+    /// When the latest run started
+    pub started_at: Option<DateTime<Utc>>,
+    /// When the latest run ended
+    pub finished_at: Option<DateTime<Utc>>,
+    /// Newest signature synced; later syncs only fetch the signatures after it
+    pub watermark_signature: Option<String>,
+}
+
+impl SyncStatus {
+    /// Status of a wallet that was never synced
+    fn never_synced(wallet_id: Uuid) -> Self {
+        Self {
+            wallet_id,
+            state: SyncState::Idle,
+            kind: None,
+            last_signature: None,
+            signatures_processed: 0,
+            signatures_total: None,
+            progress_percent: None,
+            last_error: None,
+            started_at: None,
+            finished_at: None,
+            watermark_signature: None,
+        }
+    }
+}
+
+/// Row of `wallet_sync_state`
+type SyncStateRow = (
+    String,
+    String,
+    Option<String>,
+    i64,
+    Option<i64>,
+    Option<String>,
+    DateTime<Utc>,
+    Option<DateTime<Utc>>,
+    Option<String>,
+);
+
+/// Reads a wallet's sync status
+pub async fn status(pool: &PgPool, wallet_id: Uuid) -> Result<SyncStatus, sqlx::Error> {
+    let row = sqlx::query_as::<_, SyncStateRow>(
+        r#"
+        SELECT status, kind, last_signature, signatures_processed, signatures_total,
+               last_error, started_at, finished_at, watermark_signature
+        FROM wallet_sync_state
+        WHERE wallet_id = $1
+        "#,
+    )
+    .bind(wallet_id)
+    .fetch_optional(pool)
+    .await?;
+
+    let Some((
+        state,
+        kind,
+        last_signature,
+        processed,
+        total,
+        last_error,
+        started_at,
+        finished_at,
+        watermark_signature,
+    )) = row
+    else {
+        return Ok(SyncStatus::never_synced(wallet_id));
+    };
+
+    let progress_percent = total.map(|total| {
+        if total == 0 {
+            100.0
+        } else {
+            (processed as f64 / total as f64 * 100.0).min(100.0)
+        }
+    });
+    Ok(SyncStatus {
+        wallet_id,
+        state: SyncState::from_db(&state),
+        kind: Some(SyncKind::from_db(&kind)),
+        last_signature,
+        signatures_processed: processed,
+        signatures_total: total,
+        progress_percent,
+        last_error,
+        started_at: Some(started_at),
+        finished_at,
+        watermark_signature,
+    })
+}
+
+/// Records that a run started, replacing the state of the previous one
+pub async fn start(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    kind: SyncKind,
+    signatures_total: Option<i64>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO wallet_sync_state (
+            wallet_id, status, kind, last_signature, signatures_processed, signatures_total,
+            last_error, started_at, finished_at, updated_at
+        )
+        VALUES ($1, 'running', $2, NULL, 0, $3, NULL, NOW(), NULL, NOW())
+        ON CONFLICT (wallet_id) DO UPDATE
+        SET status = EXCLUDED.status,
+            kind = EXCLUDED.kind,
+            last_signature = NULL,
+            signatures_processed = 0,
+            signatures_total = EXCLUDED.signatures_total,
+            last_error = NULL,
+            started_at = EXCLUDED.started_at,
+            finished_at = NULL,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(kind.as_str())
+    .bind(signatures_total)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records the number of signatures a running backfill covers, once listed
+pub async fn set_total(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    signatures_total: i64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE wallet_sync_state SET signatures_total = $2, updated_at = NOW() WHERE wallet_id = $1",
+    )
+    .bind(wallet_id)
+    .bind(signatures_total)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records how far a running sync has got
+pub async fn progress(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    signatures_processed: usize,
+    last_signature: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE wallet_sync_state
+        SET signatures_processed = $2, last_signature = $3, updated_at = NOW()
+        WHERE wallet_id = $1
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(signatures_processed as i64)
+    .bind(last_signature)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Records the end of a run, successful if `error` is `None`
+pub async fn finish(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE wallet_sync_state
+        SET status = CASE WHEN $2::TEXT IS NULL THEN 'idle' ELSE 'failed' END,
+            last_error = $2,
+            finished_at = NOW(),
+            updated_at = NOW()
+        WHERE wallet_id = $1
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(error)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Where an interrupted backfill resumes, with what it had done so far
+#[derive(Debug, Clone, PartialEq, Eq, sqlx::FromRow)]
+pub struct BackfillCheckpoint {
+    /// Oldest signature whose balance changes are written; the backfill resumes with
+    /// the signatures before it
+    pub before_signature: String,
+    /// Signatures processed
+    pub signatures_processed: i64,
+    /// Balance changes written
+    pub transactions_upserted: i64,
+    /// Balance changes written that were not recorded before
+    pub transactions_inserted: i64,
+}
+
+/// Reads the checkpoint of a wallet's unfinished backfill, if it has one
+pub async fn checkpoint(
+    pool: &PgPool,
+    wallet_id: Uuid,
+) -> Result<Option<BackfillCheckpoint>, sqlx::Error> {
+    sqlx::query_as::<_, BackfillCheckpoint>(
+        r#"
+        SELECT before_signature, signatures_processed, transactions_upserted,
+               transactions_inserted
+        FROM backfill_checkpoints
+        WHERE wallet_id = $1
+        "#,
+    )
+    .bind(wallet_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Records how far a running backfill has written the wallet's history
+pub async fn save_checkpoint(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    checkpoint: &BackfillCheckpoint,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO backfill_checkpoints (
+            wallet_id, before_signature, signatures_processed, transactions_upserted,
+            transactions_inserted
+        )
+        VALUES ($1, $2, $3, $4, $5)
+        ON CONFLICT (wallet_id) DO UPDATE
+        SET before_signature = EXCLUDED.before_signature,
+            signatures_processed = EXCLUDED.signatures_processed,
+            transactions_upserted = EXCLUDED.transactions_upserted,
+            transactions_inserted = EXCLUDED.transactions_inserted,
+            updated_at = NOW()
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(&checkpoint.before_signature)
+    .bind(checkpoint.signatures_processed)
+    .bind(checkpoint.transactions_upserted)
+    .bind(checkpoint.transactions_inserted)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Removes the checkpoint of a finished backfill, so the next one starts from the newest
+/// signature
+pub async fn clear_checkpoint(pool: &PgPool, wallet_id: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM backfill_checkpoints WHERE wallet_id = $1")
+        .bind(wallet_id)
+        .execute(pool)
+        .await?;
+
+    Ok(())
+}
+
+/// Newest signature processed by a wallet's syncs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Watermark {
+    /// The signature
+    pub signature: String,
+    /// Slot the signature was processed in
+    pub slot: u64,
+}
+
+/// Reads a wallet's watermark, if it was ever synced
+pub async fn watermark(pool: &PgPool, wallet_id: Uuid) -> Result<Option<Watermark>, sqlx::Error> {
+    let row = sqlx::query_as::<_, (Option<String>, Option<i64>)>(
+        "SELECT watermark_signature, watermark_slot FROM wallet_sync_state WHERE wallet_id = $1",
+    )
+    .bind(wallet_id)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(match row {
+        Some((Some(signature), Some(slot))) => Some(Watermark {
+            signature,
+            slot: slot as u64,
+        }),
+        _ => None,
+    })
+}
+
+/// Moves a wallet's watermark to the newest signature a sync processed
+pub async fn advance_watermark(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    signature: &str,
+    slot: u64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        UPDATE wallet_sync_state
+        SET watermark_signature = $2, watermark_slot = $3, updated_at = NOW()
+        WHERE wallet_id = $1
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(signature)
+    .bind(slot as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
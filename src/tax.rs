@@ -0,0 +1,62 @@
+use chrono::{DateTime, Datelike, Utc};
+use serde::Deserialize;
+
+use crate::analytics::{self, CostBasisMethod, Disposal, TokenTrades};
+use crate::export::ExportFormat;
+
+/// Earliest year a tax report can be requested for, when Solana's mainnet launched
+pub const MIN_TAX_YEAR: i32 = 2020;
+
+/// Query parameters of the tax report endpoint
+#[derive(Debug, Deserialize)]
+pub struct TaxReportParams {
+    /// Calendar year (UTC) of the sells to report
+    pub year: i32,
+    /// Cost-basis method (`fifo`, `lifo` or `avg`)
+    #[serde(default)]
+    pub method: CostBasisMethod,
+    /// File format, `csv` (default)
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// A realized gain or loss: tokens of one purchase lot sold in one trade
+#[derive(Debug, Clone, PartialEq)]
+pub struct RealizedGain {
+    /// Mint address of the token
+    pub token_address: String,
+    /// Token symbol, if known
+    pub token_symbol: String,
+    /// The sale and the purchase it is matched with
+    pub disposal: Disposal,
+}
+
+/// Realized gains of the sells made in `year`, ordered by the date sold
+///
+/// The cost-basis engine runs over the whole history of every token, so sells are
+/// matched with purchases made in earlier years.
+pub fn realized_gains(
+    token_trades: &[TokenTrades],
+    year: i32,
+    method: CostBasisMethod,
+) -> Vec<RealizedGain> {
+    let in_year = |time: DateTime<Utc>| time.year() == year;
+
+    let mut gains: Vec<RealizedGain> = token_trades
+        .iter()
+        .flat_map(|token| {
+            analytics::disposals(&token.trades, method)
+                .into_iter()
+                .filter(|disposal| in_year(disposal.sold_at))
+                .map(|disposal| RealizedGain {
+                    token_address: token.token_address.clone(),
+                    token_symbol: token.token_symbol.clone(),
+                    disposal,
+                })
+        })
+        .collect();
+    gains.sort_by(|a, b| {
+        (a.disposal.sold_at, &a.token_address).cmp(&(b.disposal.sold_at, &b.token_address))
+    });
+    gains
+}
@@ -0,0 +1,75 @@
+//! HTTPS serving for deployments without a reverse proxy.
+//!
+//! With `TLS_CERT_PATH` and `TLS_KEY_PATH` set, the server terminates TLS itself with
+//! rustls on `HOST:PORT`. If `HTTP_REDIRECT_PORT` is set too, plain HTTP requests to
+//! that port are permanently redirected to the same path over HTTPS.
+
+use std::io;
+use std::net::SocketAddr;
+use std::path::Path;
+
+use axum::{
+    http::{
+        header,
+        uri::{Authority, PathAndQuery},
+        HeaderMap, Uri,
+    },
+    response::{IntoResponse, Redirect},
+    Router,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use tracing::info;
+
+use crate::AppError;
+
+/// Loads the PEM-encoded certificate chain and private key
+pub async fn load_config(cert_path: &Path, key_path: &Path) -> io::Result<RustlsConfig> {
+    RustlsConfig::from_pem_file(cert_path, key_path)
+        .await
+        .map_err(|err| {
+            io::Error::new(
+                err.kind(),
+                format!(
+                    "Failed to load TLS certificate {} and key {}: {err}",
+                    cert_path.display(),
+                    key_path.display()
+                ),
+            )
+        })
+}
+
+/// The HTTPS URL a plain HTTP request is redirected to, or `None` without a valid
+/// `Host` header
+///
+/// The port is left out when it is the default HTTPS port.
+pub fn redirect_target(headers: &HeaderMap, uri: &Uri, https_port: u16) -> Option<String> {
+    // The port of the HTTP listener is dropped; IPv6 addresses keep their brackets
+    let authority: Authority = headers.get(header::HOST)?.to_str().ok()?.parse().ok()?;
+    let host = authority.host();
+
+    let path = uri.path_and_query().map_or("/", PathAndQuery::as_str);
+    Some(match https_port {
+        443 => format!("https://{host}{path}"),
+        port => format!("https://{host}:{port}{path}"),
+    })
+}
+
+/// An app redirecting every request to the same path on HTTPS at `https_port`
+pub fn redirect_app(https_port: u16) -> Router {
+    Router::new().fallback(move |headers: HeaderMap, uri: Uri| async move {
+        match redirect_target(&headers, &uri, https_port) {
+            Some(target) => Redirect::permanent(&target).into_response(),
+            None => {
+                AppError::BadRequest("Missing or invalid Host header".to_string()).into_response()
+            }
+        }
+    })
+}
+
+/// Serves the HTTP to HTTPS redirect on `addr` until the process exits
+pub async fn serve_redirect(addr: SocketAddr, https_port: u16) -> io::Result<()> {
+    info!("Redirecting HTTP on {} to HTTPS", addr);
+    axum_server::bind(addr)
+        .serve(redirect_app(https_port).into_make_service())
+        .await
+}
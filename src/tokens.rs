@@ -0,0 +1,418 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::{debug, warn};
+use utoipa::ToSchema;
+
+use crate::cache::{self, Cache};
+use crate::prices::PriceSource;
+use crate::pumpfun::{self, BondingStatus};
+use crate::AppError;
+
+/// Longest time token metadata is served from the in-process cache without
+/// checking the `tokens` table, so refreshes by other instances show up
+const MEMORY_METADATA_TTL: Duration = Duration::from_secs(600);
+
+/// Cache key of a mint's metadata
+fn metadata_key(mint: &str) -> String {
+    format!("token_metadata:{mint}")
+}
+
+/// Errors that can occur while fetching token metadata
+#[derive(Debug, Error)]
+pub enum MetadataError {
+    /// The HTTP request to the metadata API failed
+    #[error("Metadata request failed: {0}")]
+    Http(#[from] reqwest::Error),
+
+    /// The metadata API returned an error or an unexpected response
+    #[error("Invalid metadata response: {0}")]
+    InvalidResponse(String),
+}
+
+impl From<MetadataError> for AppError {
+    fn from(err: MetadataError) -> Self {
+        match err {
+            MetadataError::Http(err) => err.into(),
+            other => AppError::UpstreamError(other.to_string()),
+        }
+    }
+}
+
+/// Descriptive metadata of an SPL token mint
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct TokenMetadata {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub mint: String,
+    /// Ticker symbol, if known
+    #[schema(example = "BONK")]
+    pub symbol: Option<String>,
+    /// Display name, if known
+    #[schema(example = "Bonk")]
+    pub name: Option<String>,
+    /// Number of decimals of the mint, if known
+    #[schema(example = 5)]
+    pub decimals: Option<i32>,
+    /// URI of the token's logo image, if known
+    pub logo_uri: Option<String>,
+}
+
+/// Token-level view combining metadata, market data and tracked holders
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct TokenDetails {
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub mint: String,
+    /// Ticker symbol, if known
+    #[schema(example = "BONK")]
+    pub symbol: Option<String>,
+    /// Display name, if known
+    #[schema(example = "Bonk")]
+    pub name: Option<String>,
+    /// Number of decimals of the mint, if known
+    #[schema(example = 5)]
+    pub decimals: Option<i32>,
+    /// URI of the token's logo image, if known
+    pub logo_uri: Option<String>,
+    /// Current USD price, if available
+    #[schema(example = 0.0000215)]
+    pub price_usd: Option<f64>,
+    /// Price change over the last 24 hours in percent, if available
+    #[schema(example = -3.2)]
+    pub price_change_24h: Option<f64>,
+    /// Number of tracked wallets with a positive balance of the token
+    #[schema(example = 12)]
+    pub holder_count: i64,
+    /// Pump.fun bonding curve status, if the token was seen trading on pump.fun
+    pub bonding_status: Option<BondingStatus>,
+}
+
+/// A source of token metadata for SPL token mints
+///
+/// Implementations return metadata only for the mints they know about.
+#[async_trait]
+pub trait TokenMetadataSource: Send + Sync {
+    /// Fetches the metadata of each of the given mints
+    async fn metadata(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, TokenMetadata>, MetadataError>;
+}
+
+/// Metadata source backed by the Metaplex Digital Asset Standard (DAS) API
+///
+/// DAS is served by RPC providers such as Helius as the `getAssetBatch` JSON-RPC method.
+#[derive(Debug, Clone)]
+pub struct DasMetadataSource {
+    http: reqwest::Client,
+    url: String,
+}
+
+impl DasMetadataSource {
+    /// DAS accepts at most this many assets per `getAssetBatch` call
+    const MAX_IDS_PER_REQUEST: usize = 1000;
+
+    /// Creates a metadata source for the given DAS endpoint
+    pub fn new(url: impl Into<String>) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client");
+
+        Self {
+            http,
+            url: url.into(),
+        }
+    }
+}
+
+/// Extracts token metadata from a DAS asset
+fn parse_das_asset(asset: &Value) -> Option<TokenMetadata> {
+    let non_empty = |value: &Value| {
+        value
+            .as_str()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(str::to_string)
+    };
+
+    let metadata = &asset["content"]["metadata"];
+    Some(TokenMetadata {
+        mint: asset["id"].as_str()?.to_string(),
+        symbol: non_empty(&metadata["symbol"])
+            .or_else(|| non_empty(&asset["token_info"]["symbol"])),
+        name: non_empty(&metadata["name"]),
+        decimals: asset["token_info"]["decimals"]
+            .as_i64()
+            .and_then(|d| i32::try_from(d).ok()),
+        logo_uri: non_empty(&asset["content"]["links"]["image"]),
+    })
+}
+
+#[async_trait]
+impl TokenMetadataSource for DasMetadataSource {
+    async fn metadata(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, TokenMetadata>, MetadataError> {
+        let mut metadata = HashMap::new();
+
+        for chunk in mints.chunks(Self::MAX_IDS_PER_REQUEST) {
+            let response: Value = self
+                .http
+                .post(&self.url)
+                .json(&json!({
+                    "jsonrpc": "2.0",
+                    "id": 1,
+                    "method": "getAssetBatch",
+                    "params": { "ids": chunk },
+                }))
+                .send()
+                .await?
+                .error_for_status()?
+                .json()
+                .await?;
+
+            if let Some(error) = response.get("error") {
+                return Err(MetadataError::InvalidResponse(error.to_string()));
+            }
+            let assets = response["result"].as_array().ok_or_else(|| {
+                MetadataError::InvalidResponse("getAssetBatch: missing result".to_string())
+            })?;
+
+            metadata.extend(
+                assets
+                    .iter()
+                    .filter_map(parse_das_asset)
+                    .map(|token| (token.mint.clone(), token)),
+            );
+        }
+
+        Ok(metadata)
+    }
+}
+
+/// Metadata source returning a fixed set of tokens, for tests and offline use
+#[derive(Debug, Clone, Default)]
+pub struct StaticMetadataSource {
+    tokens: HashMap<String, TokenMetadata>,
+}
+
+impl StaticMetadataSource {
+    /// Creates a metadata source that always returns the given tokens
+    pub fn new(tokens: impl IntoIterator<Item = TokenMetadata>) -> Self {
+        Self {
+            tokens: tokens
+                .into_iter()
+                .map(|token| (token.mint.clone(), token))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl TokenMetadataSource for StaticMetadataSource {
+    async fn metadata(
+        &self,
+        mints: &[String],
+    ) -> Result<HashMap<String, TokenMetadata>, MetadataError> {
+        Ok(mints
+            .iter()
+            .filter_map(|mint| Some((mint.clone(), self.tokens.get(mint)?.clone())))
+            .collect())
+    }
+}
+
+/// Returns the metadata of the given mints, served from `cache` or else the `tokens`
+/// table
+///
+/// Mints that are missing or older than `ttl` are fetched from `source` and cached.
+/// Metadata is cosmetic, so a failing source is logged and stale or missing entries
+/// are returned as they are.
+pub async fn metadata_for(
+    pool: &PgPool,
+    cache: &dyn Cache,
+    source: &dyn TokenMetadataSource,
+    mints: &[String],
+    ttl: Duration,
+) -> Result<HashMap<String, TokenMetadata>, sqlx::Error> {
+    let memory_ttl = ttl.min(MEMORY_METADATA_TTL);
+    let mut tokens = HashMap::new();
+    let mut uncached = Vec::new();
+    for mint in mints {
+        match cache::get_json::<TokenMetadata>(cache, &metadata_key(mint)).await {
+            Some(token) => {
+                tokens.insert(mint.clone(), token);
+            }
+            None => uncached.push(mint.clone()),
+        }
+    }
+    if uncached.is_empty() {
+        return Ok(tokens);
+    }
+    let mints = &uncached[..];
+
+    let cached = sqlx::query_as::<
+        _,
+        (
+            String,
+            Option<String>,
+            Option<String>,
+            Option<i32>,
+            Option<String>,
+            bool,
+        ),
+    >(
+        r#"
+        SELECT mint, symbol, name, decimals, logo_uri,
+               fetched_at > NOW() - make_interval(secs => $2) AS fresh
+        FROM tokens
+        WHERE mint = ANY($1)
+        "#,
+    )
+    .bind(mints)
+    .bind(ttl.as_secs_f64())
+    .fetch_all(pool)
+    .await?;
+
+    let mut fresh = Vec::new();
+    for (mint, symbol, name, decimals, logo_uri, is_fresh) in cached {
+        let token = TokenMetadata {
+            mint: mint.clone(),
+            symbol,
+            name,
+            decimals,
+            logo_uri,
+        };
+        if is_fresh {
+            cache::set_json(cache, &metadata_key(&mint), &token, memory_ttl).await;
+            fresh.push(mint.clone());
+        }
+        tokens.insert(mint, token);
+    }
+
+    let missing: Vec<String> = mints
+        .iter()
+        .filter(|mint| !fresh.contains(mint))
+        .cloned()
+        .collect();
+    if missing.is_empty() {
+        return Ok(tokens);
+    }
+
+    debug!("Fetching metadata of {} tokens", missing.len());
+    match source.metadata(&missing).await {
+        Ok(mut fetched) => {
+            // Mints unknown to the source are cached empty so they are not re-fetched
+            // on every request
+            for mint in missing {
+                let token = fetched.remove(&mint).unwrap_or(TokenMetadata {
+                    mint,
+                    symbol: None,
+                    name: None,
+                    decimals: None,
+                    logo_uri: None,
+                });
+                store(pool, &token).await?;
+                cache::set_json(cache, &metadata_key(&token.mint), &token, memory_ttl).await;
+                tokens.insert(token.mint.clone(), token);
+            }
+        }
+        Err(err) => warn!("Token metadata fetch failed: {}", err),
+    }
+
+    Ok(tokens)
+}
+
+/// Inserts or refreshes a token's cached metadata
+async fn store(pool: &PgPool, token: &TokenMetadata) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        r#"
+        INSERT INTO tokens (mint, symbol, name, decimals, logo_uri, fetched_at)
+        VALUES ($1, $2, $3, $4, $5, NOW())
+        ON CONFLICT (mint) DO UPDATE
+        SET symbol = EXCLUDED.symbol,
+            name = EXCLUDED.name,
+            decimals = EXCLUDED.decimals,
+            logo_uri = EXCLUDED.logo_uri,
+            fetched_at = EXCLUDED.fetched_at
+        "#,
+    )
+    .bind(&token.mint)
+    .bind(&token.symbol)
+    .bind(&token.name)
+    .bind(token.decimals)
+    .bind(&token.logo_uri)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+/// Returns metadata, market data and holder count of a token mint
+///
+/// Returns `None` if neither the metadata source, the price source nor any
+/// tracked transaction knows the mint.
+pub async fn token_details(
+    pool: &PgPool,
+    cache: &dyn Cache,
+    prices: &dyn PriceSource,
+    source: &dyn TokenMetadataSource,
+    mint: &str,
+    ttl: Duration,
+) -> Result<Option<TokenDetails>, AppError> {
+    let mints = [mint.to_string()];
+    let metadata = metadata_for(pool, cache, source, &mints, ttl)
+        .await?
+        .remove(mint)
+        .unwrap_or(TokenMetadata {
+            mint: mint.to_string(),
+            symbol: None,
+            name: None,
+            decimals: None,
+            logo_uri: None,
+        });
+    let price_usd = prices.prices_usd(&mints).await?.remove(mint);
+    let price_change_24h = prices.price_changes_24h(&mints).await?.remove(mint);
+
+    let (tracked, holder_count) = sqlx::query_as::<_, (bool, i64)>(
+        r#"
+        SELECT COUNT(*) > 0, COUNT(*) FILTER (WHERE amount > 0)
+        FROM holdings
+        WHERE token_address = $1
+        "#,
+    )
+    .bind(mint)
+    .fetch_one(pool)
+    .await?;
+
+    let bonding_status = pumpfun::status(pool, mint).await?;
+
+    let known = tracked
+        || bonding_status.is_some()
+        || price_usd.is_some()
+        || metadata.symbol.is_some()
+        || metadata.name.is_some()
+        || metadata.decimals.is_some();
+    if !known {
+        return Ok(None);
+    }
+
+    Ok(Some(TokenDetails {
+        mint: metadata.mint,
+        symbol: metadata.symbol,
+        name: metadata.name,
+        decimals: metadata.decimals,
+        logo_uri: metadata.logo_uri,
+        price_usd,
+        price_change_24h,
+        holder_count,
+        bonding_status,
+    }))
+}
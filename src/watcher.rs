@@ -0,0 +1,297 @@
+//! Live watcher queuing syncs of wallets as soon as they move.
+//!
+//! The background scheduler re-syncs every wallet on an interval, whether or not it
+//! had any activity. With a Solana WebSocket endpoint configured, the watcher instead
+//! subscribes to each tracked wallet's account (`accountSubscribe`, notified when its
+//! SOL balance changes) and to the transactions mentioning it (`logsSubscribe`), and
+//! queues a sync of the wallet when either reports activity. Nodes limit the
+//! subscriptions a connection may hold, so at most `WS_MAX_SUBSCRIPTIONS` are opened;
+//! wallets beyond the cap are left to the scheduler.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::SinkExt;
+use serde_json::{json, Value};
+use sqlx::PgPool;
+use thiserror::Error;
+use tokio::task::JoinHandle;
+use tokio_stream::StreamExt;
+use tokio_tungstenite::tungstenite::{self, Message};
+use tracing::{debug, error, info, warn};
+use uuid::Uuid;
+
+use crate::jobs::{self, Job};
+use crate::rpc::backoff;
+use crate::scheduler::{is_leader, LeaderLock};
+use crate::AppState;
+
+/// Subscriptions opened per watched wallet: its account and its transactions
+pub const SUBSCRIPTIONS_PER_WALLET: usize = 2;
+
+/// How often the watcher checks for wallets added or removed since it subscribed
+pub const RESUBSCRIBE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Subscribe and unsubscribe methods of the subscriptions opened for each wallet
+const SUBSCRIPTIONS: [(&str, &str); SUBSCRIPTIONS_PER_WALLET] = [
+    ("accountSubscribe", "accountUnsubscribe"),
+    ("logsSubscribe", "logsUnsubscribe"),
+];
+
+/// Errors that can occur while watching wallets
+#[derive(Debug, Error)]
+pub enum WatcherError {
+    /// The WebSocket connection failed
+    #[error("WebSocket error: {0}")]
+    WebSocket(#[from] tungstenite::Error),
+
+    /// The node rejected a subscription or sent an unexpected message
+    #[error("Invalid WebSocket response: {0}")]
+    InvalidResponse(String),
+
+    /// Looking up wallets or queuing their syncs failed
+    #[error("Database error: {0}")]
+    Database(#[from] sqlx::Error),
+}
+
+/// Summary of a connection's run
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct WatchReport {
+    /// Number of distinct addresses watched when the connection ended
+    pub addresses_watched: usize,
+    /// Number of account and logs notifications received
+    pub notifications: usize,
+    /// Number of wallet syncs queued; syncs already queued are not counted
+    pub syncs_queued: usize,
+}
+
+/// Addresses to watch with at most `max_subscriptions` subscriptions, the wallets
+/// tracked the longest first, and the number of addresses tracked
+async fn watched_addresses(
+    pool: &PgPool,
+    max_subscriptions: usize,
+) -> Result<(HashSet<String>, i64), sqlx::Error> {
+    let rows: Vec<(String, i64)> = sqlx::query_as(
+        r#"
+        SELECT address, COUNT(*) OVER ()
+        FROM wallets
+        GROUP BY address
+        ORDER BY MIN(created_at), address
+        LIMIT $1
+        "#,
+    )
+    .bind((max_subscriptions / SUBSCRIPTIONS_PER_WALLET) as i64)
+    .fetch_all(pool)
+    .await?;
+
+    let tracked = rows.first().map_or(0, |(_, tracked)| *tracked);
+    Ok((
+        rows.into_iter().map(|(address, _)| address).collect(),
+        tracked,
+    ))
+}
+
+/// Queues a sync of every wallet tracking `address`, returning how many were queued
+async fn queue_syncs(pool: &PgPool, address: &str) -> Result<usize, sqlx::Error> {
+    let wallet_ids: Vec<Uuid> = sqlx::query_scalar("SELECT id FROM wallets WHERE address = $1")
+        .bind(address)
+        .fetch_all(pool)
+        .await?;
+
+    let mut queued = 0;
+    for wallet_id in wallet_ids {
+        if jobs::enqueue(pool, &Job::SyncWallet { wallet_id }).await? {
+            queued += 1;
+        }
+    }
+    Ok(queued)
+}
+
+/// A connection's subscriptions
+#[derive(Default)]
+struct Subscriptions {
+    /// ID of the next request sent
+    next_request: u64,
+    /// Address and method of each subscription request not answered yet
+    pending: HashMap<u64, (String, &'static str)>,
+    /// Address and unsubscribe method of each open subscription, by subscription ID
+    open: HashMap<u64, (String, &'static str)>,
+}
+
+impl Subscriptions {
+    /// Subscription requests for `address`
+    fn subscribe(&mut self, address: &str) -> Vec<Message> {
+        SUBSCRIPTIONS
+            .iter()
+            .map(|(method, unsubscribe)| {
+                self.next_request += 1;
+                self.pending
+                    .insert(self.next_request, (address.to_string(), unsubscribe));
+                let params = match *method {
+                    "accountSubscribe" => {
+                        json!([address, { "encoding": "base64", "commitment": "confirmed" }])
+                    }
+                    _ => json!([{ "mentions": [address] }, { "commitment": "confirmed" }]),
+                };
+                request(self.next_request, method, params)
+            })
+            .collect()
+    }
+
+    /// Unsubscribe requests for every subscription of `address`
+    fn unsubscribe(&mut self, address: &str) -> Vec<Message> {
+        let ids: Vec<u64> = self
+            .open
+            .iter()
+            .filter(|(_, (subscribed, _))| subscribed == address)
+            .map(|(id, _)| *id)
+            .collect();
+        ids.into_iter()
+            .filter_map(|id| self.open.remove(&id).map(|(_, method)| (id, method)))
+            .map(|(id, method)| {
+                self.next_request += 1;
+                request(self.next_request, method, json!([id]))
+            })
+            .collect()
+    }
+}
+
+/// A JSON-RPC request frame
+fn request(id: u64, method: &str, params: Value) -> Message {
+    Message::text(
+        json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string(),
+    )
+}
+
+/// Connects to the WebSocket endpoint at `url`, subscribes to the tracked wallets and
+/// queues a sync of each wallet reported active
+///
+/// Every `resubscribe_every`, wallets added since are subscribed and removed ones
+/// unsubscribed, within `max_subscriptions`. Returns when the connection closes.
+pub async fn watch(
+    pool: &PgPool,
+    url: &str,
+    max_subscriptions: usize,
+    resubscribe_every: Duration,
+) -> Result<WatchReport, WatcherError> {
+    let (mut socket, _) = tokio_tungstenite::connect_async(url).await?;
+    let mut subscriptions = Subscriptions::default();
+    let mut watched: HashSet<String> = HashSet::new();
+    let mut report = WatchReport::default();
+    let mut check = tokio::time::interval(resubscribe_every);
+
+    loop {
+        tokio::select! {
+            _ = check.tick() => {
+                let (wanted, tracked) = watched_addresses(pool, max_subscriptions).await?;
+                let mut requests = Vec::new();
+                for address in watched.difference(&wanted) {
+                    requests.extend(subscriptions.unsubscribe(address));
+                }
+                for address in wanted.difference(&watched) {
+                    requests.extend(subscriptions.subscribe(address));
+                }
+                if !requests.is_empty() {
+                    info!("Watching {} of {} wallet addresses", wanted.len(), tracked);
+                    if (wanted.len() as i64) < tracked {
+                        warn!(
+                            "Wallet watcher is capped at {} subscriptions; the other wallets are only synced by the scheduler",
+                            max_subscriptions
+                        );
+                    }
+                }
+                for message in requests {
+                    socket.feed(message).await?;
+                }
+                // Keeps idle connections from being dropped by proxies
+                socket.send(Message::Ping(Vec::new())).await?;
+                watched = wanted;
+                report.addresses_watched = watched.len();
+            }
+            message = socket.next() => {
+                let text = match message {
+                    None | Some(Ok(Message::Close(_))) => return Ok(report),
+                    Some(Ok(Message::Text(text))) => text,
+                    Some(Ok(_)) => continue,
+                    Some(Err(err)) => return Err(err.into()),
+                };
+                let message: Value = serde_json::from_str(&text)
+                    .map_err(|_| WatcherError::InvalidResponse(text.clone()))?;
+
+                if let Some(id) = message["id"].as_u64() {
+                    let Some((address, unsubscribe)) = subscriptions.pending.remove(&id) else {
+                        continue;
+                    };
+                    match message["result"].as_u64() {
+                        Some(subscription) => {
+                            subscriptions.open.insert(subscription, (address, unsubscribe));
+                        }
+                        None => warn!("Subscribing to {} failed: {}", address, message["error"]),
+                    }
+                    continue;
+                }
+
+                let subscription = message["params"]["subscription"].as_u64();
+                let Some((address, _)) = subscription.and_then(|id| subscriptions.open.get(&id)) else {
+                    continue;
+                };
+                report.notifications += 1;
+                let queued = queue_syncs(pool, address).await?;
+                if queued > 0 {
+                    debug!("Activity on {}; queued {} syncs", address, queued);
+                }
+                report.syncs_queued += queued;
+            }
+        }
+    }
+}
+
+/// Spawns the watcher connected to `url`
+///
+/// The connection is reopened and every wallet subscribed again whenever it drops,
+/// backing off after consecutive failures. With several instances, only the one
+/// holding `leader` watches.
+pub fn spawn_wallet_watcher(
+    state: AppState,
+    url: String,
+    leader: Arc<LeaderLock>,
+) -> JoinHandle<()> {
+    info!("Starting wallet watcher on {}", url);
+
+    tokio::spawn(async move {
+        let mut failures = 0;
+        loop {
+            if !is_leader(&leader).await {
+                tokio::time::sleep(RESUBSCRIBE_INTERVAL).await;
+                continue;
+            }
+
+            match watch(
+                &state.db_pool,
+                &url,
+                state.config.ws_max_subscriptions,
+                RESUBSCRIBE_INTERVAL,
+            )
+            .await
+            {
+                Ok(report) => {
+                    failures = 0;
+                    info!(
+                        "Wallet watcher connection closed after {} notifications",
+                        report.notifications
+                    );
+                }
+                Err(err) => {
+                    failures += 1;
+                    if failures > 1 {
+                        error!("Wallet watcher failed {} times: {}", failures, err);
+                    } else {
+                        warn!("Wallet watcher failed: {}", err);
+                    }
+                    tokio::time::sleep(backoff(failures)).await;
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,702 @@
+//! Outgoing webhooks for portfolio events.
+//!
+//! Users subscribe a URL to one or more [`WebhookEventType`]s. When an event occurs a
+//! row is recorded in `webhook_deliveries` for every matching subscription, together
+//! with a job that POSTs it to the subscriber, retried with exponential backoff by the
+//! [job queue](crate::jobs). Every delivery is signed with the subscription's secret:
+//!
+//! ```text
+//! X-Degen-Signature: sha256=<hex HMAC-SHA256(secret, "{X-Degen-Timestamp}.{body}")>
+//! ```
+//!
+//! Secrets are stored [sealed](crate::secrets) and only opened to sign deliveries.
+//!
+//! Subscriber URLs must resolve to public addresses, unless
+//! `WEBHOOK_ALLOW_PRIVATE_URLS` is set: loopback, private, link-local, unspecified and
+//! multicast addresses are rejected when a subscription is created and again by the
+//! resolver of every delivery, so a host cannot be rebound to an internal address after
+//! it was checked. Redirects are not followed.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use sqlx::PgPool;
+use thiserror::Error;
+use tracing::{debug, warn};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::analytics;
+use crate::jobs::{self, Job};
+use crate::money::TokenAmount;
+use crate::notifications;
+use crate::prices::PriceSource;
+use crate::resilience::CircuitBreakers;
+use crate::secrets::{SecretBox, Slot};
+use crate::whales::{self, WhaleEvent};
+use crate::AppError;
+
+/// Header naming the event type of a delivery
+pub const EVENT_HEADER: &str = "x-degen-event";
+
+/// Header carrying the ID of a delivery, stable across retries
+pub const DELIVERY_HEADER: &str = "x-degen-delivery";
+
+/// Header carrying the Unix timestamp included in the signature
+pub const TIMESTAMP_HEADER: &str = "x-degen-timestamp";
+
+/// Header carrying the HMAC-SHA256 signature of a delivery
+pub const SIGNATURE_HEADER: &str = "x-degen-signature";
+
+/// Deliveries are abandoned after this many failed attempts
+pub const MAX_ATTEMPTS: i32 = 8;
+
+/// Kinds of events a webhook can subscribe to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEventType {
+    /// A transaction not seen before was recorded for a wallet
+    TransactionDetected,
+    /// A wallet's total USD value crossed the subscription's threshold
+    BalanceThresholdCrossed,
+    /// A newly recorded transaction reached the server's whale threshold
+    WhaleMovement,
+}
+
+impl WebhookEventType {
+    /// Name of the event as stored and sent in `X-Degen-Event`
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::TransactionDetected => "transaction_detected",
+            Self::BalanceThresholdCrossed => "balance_threshold_crossed",
+            Self::WhaleMovement => "whale_movement",
+        }
+    }
+}
+
+/// A registered webhook
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WebhookSubscription {
+    /// Unique identifier of the subscription
+    pub id: Uuid,
+    /// URL deliveries are POSTed to
+    #[schema(example = "https://example.com/hooks/degen")]
+    pub url: String,
+    /// Event types delivered to this URL
+    #[schema(value_type = Vec<WebhookEventType>)]
+    pub event_types: Vec<String>,
+    /// Wallet the subscription is limited to; all of the user's wallets if `null`
+    pub wallet_id: Option<Uuid>,
+    /// USD value whose crossing triggers `balance_threshold_crossed`
+    pub threshold_usd: Option<f64>,
+    /// When the subscription was created
+    pub created_at: DateTime<Utc>,
+}
+
+/// Request payload for registering a webhook
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreateWebhookSubscription {
+    /// HTTP(S) URL deliveries are POSTed to
+    #[schema(example = "https://example.com/hooks/degen")]
+    pub url: String,
+    /// Event types to deliver
+    pub events: Vec<WebhookEventType>,
+    /// Limit the subscription to one wallet; required for `balance_threshold_crossed`
+    pub wallet_id: Option<Uuid>,
+    /// USD value of the wallet whose crossing triggers `balance_threshold_crossed`
+    pub threshold_usd: Option<f64>,
+}
+
+/// A newly registered webhook together with its signing secret
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct CreatedWebhookSubscription {
+    /// The registered subscription
+    #[serde(flatten)]
+    pub subscription: WebhookSubscription,
+    /// Key used to sign deliveries; only returned once
+    pub secret: String,
+}
+
+/// An entry of a subscription's delivery log
+#[derive(Debug, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WebhookDelivery {
+    /// Unique identifier of the delivery, sent as `X-Degen-Delivery`
+    pub id: Uuid,
+    /// Event type of the delivery
+    pub event_type: String,
+    /// JSON body sent to the subscriber
+    #[schema(value_type = Object)]
+    pub payload: Value,
+    /// `pending`, `succeeded` or `failed`
+    pub status: String,
+    /// Number of attempts made so far
+    pub attempts: i32,
+    /// When the next attempt is due, for pending deliveries
+    pub next_attempt_at: DateTime<Utc>,
+    /// HTTP status of the last attempt, if a response was received
+    pub last_status_code: Option<i32>,
+    /// Why the last attempt failed
+    pub last_error: Option<String>,
+    /// When the subscriber acknowledged the delivery
+    pub delivered_at: Option<DateTime<Utc>>,
+    /// When the event occurred
+    pub created_at: DateTime<Utc>,
+}
+
+/// A newly recorded transaction row, as reported in `transaction_detected` events
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+pub struct DetectedTransaction {
+    /// Signature of the on-chain transaction
+    pub transaction_hash: String,
+    /// Mint address of the token
+    pub token_address: String,
+    /// Signed token amount
+    pub amount: TokenAmount,
+    /// When the transaction was processed on-chain, if known
+    pub block_time: Option<DateTime<Utc>>,
+}
+
+/// Validates and stores a new subscription for `user_id`
+pub async fn create_subscription(
+    pool: &PgPool,
+    secrets: &SecretBox,
+    allow_private_urls: bool,
+    user_id: Uuid,
+    request: &CreateWebhookSubscription,
+) -> Result<CreatedWebhookSubscription, AppError> {
+    let url = reqwest::Url::parse(&request.url)
+        .map_err(|e| AppError::UnprocessableEntity(format!("Invalid webhook URL: {e}")))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(AppError::UnprocessableEntity(
+            "Webhook URL must use http or https".to_string(),
+        ));
+    }
+    if !allow_private_urls {
+        check_public_destination(&url)
+            .await
+            .map_err(|err| AppError::UnprocessableEntity(err.to_string()))?;
+    }
+    if request.events.is_empty() {
+        return Err(AppError::UnprocessableEntity(
+            "At least one event type is required".to_string(),
+        ));
+    }
+    if request
+        .events
+        .contains(&WebhookEventType::BalanceThresholdCrossed)
+        && (request.wallet_id.is_none() || request.threshold_usd.is_none())
+    {
+        return Err(AppError::UnprocessableEntity(
+            "balance_threshold_crossed requires wallet_id and threshold_usd".to_string(),
+        ));
+    }
+
+    if let Some(wallet_id) = request.wallet_id {
+        sqlx::query_scalar::<_, Uuid>("SELECT id FROM wallets WHERE id = $1 AND user_id = $2")
+            .bind(wallet_id)
+            .bind(user_id)
+            .fetch_optional(pool)
+            .await?
+            .ok_or_else(|| AppError::NotFound(format!("Wallet with ID {wallet_id} not found")))?;
+    }
+
+    let mut event_types: Vec<String> = request
+        .events
+        .iter()
+        .map(|event| event.as_str().to_string())
+        .collect();
+    event_types.sort();
+    event_types.dedup();
+
+    let id = Uuid::now_v7();
+    let secret = generate_secret();
+    let subscription = sqlx::query_as::<_, WebhookSubscription>(
+        r#"
+        INSERT INTO webhook_subscriptions (id, user_id, url, secret, event_types, wallet_id, threshold_usd)
+        VALUES ($1, $2, $3, $4, $5, $6, $7)
+        RETURNING id, url, event_types, wallet_id, threshold_usd, created_at
+        "#,
+    )
+    .bind(id)
+    .bind(user_id)
+    .bind(url.as_str())
+    .bind(secrets.seal(&secret, &Slot::webhook_secret(id)))
+    .bind(&event_types)
+    .bind(request.wallet_id)
+    .bind(request.threshold_usd)
+    .fetch_one(pool)
+    .await?;
+
+    Ok(CreatedWebhookSubscription {
+        subscription,
+        secret,
+    })
+}
+
+/// Generates a random signing secret
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("whsec_{}", hex::encode(bytes))
+}
+
+/// Queues an event for every subscription of the wallet's owner that wants it
+///
+/// `subscription_id` restricts the event to a single subscription, e.g. for
+/// threshold crossings evaluated per subscription. Each delivery is recorded together
+/// with the job sending it. Returns the number of deliveries queued.
+async fn enqueue(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    event: WebhookEventType,
+    data: Value,
+    subscription_id: Option<Uuid>,
+) -> Result<u64, sqlx::Error> {
+    let payload = json!({
+        "event": event,
+        "wallet_id": wallet_id,
+        "occurred_at": Utc::now(),
+        "data": data,
+    });
+
+    let mut tx = pool.begin().await?;
+    let deliveries = sqlx::query_scalar::<_, Uuid>(
+        r#"
+        INSERT INTO webhook_deliveries (id, subscription_id, event_type, payload)
+        SELECT gen_random_uuid(), s.id, $2, $3
+        FROM webhook_subscriptions s
+        JOIN wallets w ON w.user_id = s.user_id
+        WHERE w.id = $1
+          AND $2 = ANY(s.event_types)
+          AND (s.wallet_id IS NULL OR s.wallet_id = w.id)
+          AND ($4::UUID IS NULL OR s.id = $4)
+        RETURNING id
+        "#,
+    )
+    .bind(wallet_id)
+    .bind(event.as_str())
+    .bind(payload)
+    .bind(subscription_id)
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for &delivery_id in &deliveries {
+        jobs::enqueue(&mut *tx, &Job::DeliverWebhook { delivery_id }).await?;
+    }
+    tx.commit().await?;
+
+    Ok(deliveries.len() as u64)
+}
+
+/// Queues `transaction_detected` events for newly recorded transactions of a wallet,
+/// and checks of their value for large transaction notifications and, if
+/// `whale_threshold_usd` is set, whale movements
+pub async fn notify_transactions_detected(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    transactions: &[DetectedTransaction],
+    whale_threshold_usd: Option<f64>,
+) -> Result<(), sqlx::Error> {
+    if transactions.is_empty() {
+        return Ok(());
+    }
+
+    let queued = enqueue(
+        pool,
+        wallet_id,
+        WebhookEventType::TransactionDetected,
+        json!({ "transactions": transactions }),
+        None,
+    )
+    .await?;
+    debug!(
+        "Queued {} transaction_detected deliveries for wallet {}",
+        queued, wallet_id
+    );
+    notifications::queue_large_transaction_check(pool, wallet_id, transactions).await?;
+    whales::queue_detection(pool, wallet_id, transactions, whale_threshold_usd).await?;
+
+    Ok(())
+}
+
+/// Queues a `whale_movement` event for a flagged movement
+pub async fn notify_whale_movement(pool: &PgPool, event: &WhaleEvent) -> Result<(), sqlx::Error> {
+    enqueue(
+        pool,
+        event.wallet_id,
+        WebhookEventType::WhaleMovement,
+        json!({ "whale_event": event }),
+        None,
+    )
+    .await?;
+    Ok(())
+}
+
+/// Re-values a wallet and queues `balance_threshold_crossed` for every crossed threshold
+///
+/// The first check of a subscription only records the current value, so an event fires
+/// when the value moves across the threshold, not when it already is beyond it.
+pub async fn check_balance_thresholds(
+    pool: &PgPool,
+    prices: &dyn PriceSource,
+    wallet_id: Uuid,
+) -> Result<(), AppError> {
+    let subscriptions = sqlx::query_as::<_, (Uuid, f64, Option<f64>)>(
+        r#"
+        SELECT id, threshold_usd, last_balance_usd
+        FROM webhook_subscriptions
+        WHERE wallet_id = $1
+          AND threshold_usd IS NOT NULL
+          AND 'balance_threshold_crossed' = ANY(event_types)
+        "#,
+    )
+    .bind(wallet_id)
+    .fetch_all(pool)
+    .await?;
+
+    if subscriptions.is_empty() {
+        return Ok(());
+    }
+
+    let value_usd = analytics::wallet_holdings(pool, prices, wallet_id)
+        .await?
+        .total_value_usd
+        .to_f64();
+
+    for (subscription_id, threshold_usd, last_balance_usd) in subscriptions {
+        if let Some(previous) = last_balance_usd {
+            if (previous < threshold_usd) != (value_usd < threshold_usd) {
+                let direction = if value_usd >= threshold_usd {
+                    "above"
+                } else {
+                    "below"
+                };
+                enqueue(
+                    pool,
+                    wallet_id,
+                    WebhookEventType::BalanceThresholdCrossed,
+                    json!({
+                        "threshold_usd": threshold_usd,
+                        "previous_value_usd": previous,
+                        "value_usd": value_usd,
+                        "direction": direction,
+                    }),
+                    Some(subscription_id),
+                )
+                .await?;
+            }
+        }
+
+        sqlx::query("UPDATE webhook_subscriptions SET last_balance_usd = $2 WHERE id = $1")
+            .bind(subscription_id)
+            .bind(value_usd)
+            .execute(pool)
+            .await?;
+    }
+
+    Ok(())
+}
+
+/// Computes the `X-Degen-Signature` value of a delivery body
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{timestamp}.{body}").as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// HTTP client used for deliveries
+///
+/// Redirects are never followed. Unless `allow_private_urls` is set, host names only
+/// resolve to public addresses; [`deliver`] rejects private address literals itself.
+pub fn delivery_client(allow_private_urls: bool) -> reqwest::Client {
+    let builder = reqwest::Client::builder()
+        .timeout(Duration::from_secs(10))
+        .redirect(reqwest::redirect::Policy::none());
+    let builder = if allow_private_urls {
+        builder
+    } else {
+        builder.dns_resolver(Arc::new(PublicResolver))
+    };
+    builder.build().expect("Failed to build HTTP client")
+}
+
+/// Why a webhook URL may not be delivered to
+#[derive(Debug, Error)]
+pub enum DestinationError {
+    /// The URL has no host
+    #[error("Webhook URL has no host")]
+    NoHost,
+
+    /// The host could not be resolved
+    #[error("Webhook host {host} could not be resolved: {source}")]
+    Unresolved {
+        /// Host of the URL
+        host: String,
+        /// Why it could not be resolved
+        #[source]
+        source: std::io::Error,
+    },
+
+    /// The host is or resolves to an address that is not public
+    #[error("Webhook host {host} resolves to the non-public address {address}")]
+    NotPublic {
+        /// Host of the URL
+        host: String,
+        /// The offending address
+        address: IpAddr,
+    },
+}
+
+/// Whether `address` is reachable on the public internet, i.e. not loopback, private,
+/// link-local, shared, unspecified, broadcast, documentation or multicast
+pub fn is_public_address(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                // Shared address space of carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_address(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                // Unique local, fc00::/7
+                || (first & 0xfe00) == 0xfc00
+                // Link-local, fe80::/10
+                || (first & 0xffc0) == 0xfe80
+                // Documentation, 2001:db8::/32
+                || (first == 0x2001 && v6.segments()[1] == 0x0db8))
+        }
+    }
+}
+
+/// Resolves the host of `url` and fails unless every address it resolves to is public
+pub async fn check_public_destination(url: &reqwest::Url) -> Result<(), DestinationError> {
+    let host = url.host_str().ok_or(DestinationError::NoHost)?;
+    match literal_address(url) {
+        Some(address) => check_address(host, address),
+        None => resolve_public(host).await.map(drop),
+    }
+}
+
+/// The address `url` names directly instead of by a host name, if any
+fn literal_address(url: &reqwest::Url) -> Option<IpAddr> {
+    url.host_str()?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse()
+        .ok()
+}
+
+fn check_address(host: &str, address: IpAddr) -> Result<(), DestinationError> {
+    if is_public_address(address) {
+        Ok(())
+    } else {
+        Err(DestinationError::NotPublic {
+            host: host.to_string(),
+            address,
+        })
+    }
+}
+
+/// Resolves `host`, failing if any of its addresses is not public
+async fn resolve_public(host: &str) -> Result<Vec<SocketAddr>, DestinationError> {
+    let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host, 0))
+        .await
+        .map_err(|source| DestinationError::Unresolved {
+            host: host.to_string(),
+            source,
+        })?
+        .collect();
+    for address in &addresses {
+        check_address(host, address.ip())?;
+    }
+    Ok(addresses)
+}
+
+/// Resolver of the delivery client refusing hosts with non-public addresses
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        Box::pin(async move {
+            let addresses = resolve_public(name.as_str()).await?;
+            Ok(Box::new(addresses.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// Result of a delivery attempt
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeliveryOutcome {
+    /// The subscriber acknowledged the delivery
+    Delivered,
+    /// The delivery was acknowledged before or its subscription deleted; nothing was sent
+    Cancelled,
+    /// The attempt failed for the given reason
+    Failed(String),
+    /// The subscriber host's circuit breaker is open; nothing was sent, and the
+    /// delivery is due again after the delay
+    Deferred(Duration),
+}
+
+/// Attempts a delivery once and records the outcome on it
+///
+/// Deliveries are guarded by a circuit breaker per subscriber host. While a host's
+/// breaker is open its deliveries are put off until the breaker lets a trial
+/// through, without using up an attempt. A failed delivery is due again after
+/// `retry_in`, or marked failed if that is `None`.
+pub async fn deliver(
+    pool: &PgPool,
+    secrets: &SecretBox,
+    allow_private_urls: bool,
+    http: &reqwest::Client,
+    breakers: &CircuitBreakers,
+    delivery_id: Uuid,
+    retry_in: Option<Duration>,
+) -> Result<DeliveryOutcome, sqlx::Error> {
+    let delivery = sqlx::query_as::<_, (String, String, i32, Uuid, String, String)>(
+        r#"
+        SELECT d.event_type, d.payload::TEXT, d.attempts, s.id, s.url, s.secret
+        FROM webhook_deliveries d
+        JOIN webhook_subscriptions s ON s.id = d.subscription_id
+        WHERE d.id = $1 AND d.status <> 'succeeded'
+        "#,
+    )
+    .bind(delivery_id)
+    .fetch_optional(pool)
+    .await?;
+    let Some((event_type, body, attempts, subscription_id, url, secret)) = delivery else {
+        return Ok(DeliveryOutcome::Cancelled);
+    };
+    let secret = secrets.open(&secret, &Slot::webhook_secret(subscription_id))?;
+
+    let host = reqwest::Url::parse(&url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_else(|| url.clone());
+    let breaker = breakers.get(&host);
+    if let Err(open) = breaker.permit() {
+        debug!("Putting off webhook delivery {}: {}", delivery_id, open);
+        sqlx::query(
+            "UPDATE webhook_deliveries SET next_attempt_at = NOW() + make_interval(secs => $2) WHERE id = $1",
+        )
+        .bind(delivery_id)
+        .bind(open.retry_in.as_secs_f64())
+        .execute(pool)
+        .await?;
+        return Ok(DeliveryOutcome::Deferred(open.retry_in));
+    }
+
+    // Host names are checked by the resolver of the client, address literals here
+    let blocked = match reqwest::Url::parse(&url) {
+        Ok(parsed) if !allow_private_urls => {
+            literal_address(&parsed).and_then(|address| check_address(&host, address).err())
+        }
+        _ => None,
+    };
+    let (status_code, error) = match blocked {
+        Some(err) => (None, Some(err.to_string())),
+        None => {
+            let timestamp = Utc::now().timestamp();
+            let result = http
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header(EVENT_HEADER, &event_type)
+                .header(DELIVERY_HEADER, delivery_id.to_string())
+                .header(TIMESTAMP_HEADER, timestamp.to_string())
+                .header(SIGNATURE_HEADER, sign(&secret, timestamp, &body))
+                .body(body)
+                .send()
+                .await;
+
+            let (status_code, error) = match result {
+                Ok(response) if response.status().is_success() => {
+                    (Some(response.status().as_u16() as i32), None)
+                }
+                Ok(response) => (
+                    Some(response.status().as_u16() as i32),
+                    Some(format!("Subscriber responded with {}", response.status())),
+                ),
+                Err(err) => (None, Some(err.to_string())),
+            };
+            // Subscribers answering with a client error are up; only outages trip the
+            // breaker
+            breaker.record(status_code.is_some_and(|code| code < 500));
+            (status_code, error)
+        }
+    };
+
+    let attempts = attempts + 1;
+    let Some(error) = error else {
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'succeeded', attempts = $2, last_status_code = $3,
+                last_error = NULL, delivered_at = NOW()
+            WHERE id = $1
+            "#,
+        )
+        .bind(delivery_id)
+        .bind(attempts)
+        .bind(status_code)
+        .execute(pool)
+        .await?;
+        return Ok(DeliveryOutcome::Delivered);
+    };
+
+    let status = match retry_in {
+        Some(_) => {
+            debug!(
+                "Webhook delivery {} failed, will retry: {}",
+                delivery_id, error
+            );
+            "pending"
+        }
+        None => {
+            warn!(
+                "Giving up on webhook delivery {} after {} attempts: {}",
+                delivery_id, attempts, error
+            );
+            "failed"
+        }
+    };
+    sqlx::query(
+        r#"
+        UPDATE webhook_deliveries
+        SET status = $2, attempts = $3, last_status_code = $4, last_error = $5,
+            next_attempt_at = NOW() + make_interval(secs => $6)
+        WHERE id = $1
+        "#,
+    )
+    .bind(delivery_id)
+    .bind(status)
+    .bind(attempts)
+    .bind(status_code)
+    .bind(&error)
+    .bind(retry_in.unwrap_or_default().as_secs_f64())
+    .execute(pool)
+    .await?;
+
+    Ok(DeliveryOutcome::Failed(error))
+}
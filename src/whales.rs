@@ -0,0 +1,207 @@
+//! Whale movement detection.
+//!
+//! Newly recorded transactions worth at least `WHALE_THRESHOLD_USD` at current prices
+//! are flagged as whale movements. Each is stored in `whale_events` for browsing at
+//! `GET /events/whale` together with its [outbox](crate::outbox) event, which delivers
+//! it to `whale_movement` webhooks. It is also published on the
+//! [event bus](crate::events) and sent to the owner's
+//! [notification channels](crate::notifications) with `whales` enabled.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::PgPool;
+use tracing::info;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::events::WalletEventKind;
+use crate::jobs::{self, Job};
+use crate::money::{TokenAmount, Usd};
+use crate::notifications::{self, templates};
+use crate::outbox::{self, OutboxEvent};
+use crate::webhooks::{self, DetectedTransaction};
+use crate::{AppError, AppState};
+
+/// A transaction flagged as a whale movement
+#[derive(Debug, Clone, Serialize, Deserialize, sqlx::FromRow, ToSchema)]
+pub struct WhaleEvent {
+    /// Unique identifier of the event
+    pub id: Uuid,
+    /// Wallet that sent or received the tokens
+    pub wallet_id: Uuid,
+    /// Signature of the on-chain transaction
+    pub transaction_hash: String,
+    /// Mint address of the token
+    #[schema(example = "DezXAZ8z7PnrnRJjz3wXBoRgixCa6xjnB7YaB1pPB263")]
+    pub token_address: String,
+    /// Token symbol, if known
+    #[schema(example = "BONK")]
+    pub token_symbol: String,
+    /// Signed token amount as a decimal string; negative if the wallet sent the tokens
+    #[schema(value_type = String, example = "-5000000000")]
+    pub amount: TokenAmount,
+    /// USD price per token when the movement was detected
+    pub price_usd: f64,
+    /// USD value of the movement when it was detected
+    #[schema(value_type = f64, example = 125000.0)]
+    pub value_usd: Usd,
+    /// When the transaction was processed on-chain, if known
+    pub block_time: Option<DateTime<Utc>>,
+    /// When the movement was detected
+    pub created_at: DateTime<Utc>,
+}
+
+/// Columns selected into a [`WhaleEvent`]
+pub const WHALE_EVENT_COLUMNS: &str = "id, wallet_id, transaction_hash, token_address, \
+     token_symbol, amount, price_usd, value_usd, block_time, created_at";
+
+/// Queues a check of newly recorded transactions of a wallet against
+/// `whale_threshold_usd`, if detection is enabled
+///
+/// Valuing the transactions needs the price feed, so the check runs as a job.
+pub async fn queue_detection(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    transactions: &[DetectedTransaction],
+    whale_threshold_usd: Option<f64>,
+) -> Result<(), sqlx::Error> {
+    if whale_threshold_usd.is_none() || transactions.is_empty() {
+        return Ok(());
+    }
+
+    jobs::enqueue(
+        pool,
+        &Job::DetectWhaleMovements {
+            wallet_id,
+            transactions: transactions.to_vec(),
+        },
+    )
+    .await?;
+    Ok(())
+}
+
+/// Values newly recorded transactions of a wallet and flags the ones worth at least
+/// `WHALE_THRESHOLD_USD`; returns the movements flagged
+///
+/// Transactions of tokens without a known price, and ones flagged before, are skipped.
+pub async fn detect(
+    state: &AppState,
+    wallet_id: Uuid,
+    transactions: &[DetectedTransaction],
+) -> Result<Vec<WhaleEvent>, AppError> {
+    let Some(threshold_usd) = state.config.whale_threshold_usd.map(Usd::from_f64) else {
+        return Ok(Vec::new());
+    };
+    let pool = &state.db_pool;
+
+    let mut mints: Vec<String> = transactions
+        .iter()
+        .map(|tx| tx.token_address.clone())
+        .collect();
+    mints.sort();
+    mints.dedup();
+    let prices = state.prices.prices_usd(&mints).await?;
+
+    let mut flagged = Vec::new();
+    for tx in transactions {
+        let Some(&price_usd) = prices.get(&tx.token_address) else {
+            continue;
+        };
+        let value_usd = tx.amount.abs() * Usd::from_f64(price_usd);
+        if value_usd < threshold_usd {
+            continue;
+        }
+
+        // The movement and its announcement are recorded together
+        let mut db_tx = pool.begin().await?;
+        // The recorded row supplies the symbol, and is gone if the wallet was deleted
+        let event = sqlx::query_as::<_, WhaleEvent>(&format!(
+            r#"
+            INSERT INTO whale_events (
+                id, wallet_id, transaction_hash, token_address, token_symbol, amount,
+                price_usd, value_usd, block_time
+            )
+            SELECT $1, wallet_id, transaction_hash, token_address, token_symbol, amount,
+                   $5, $6, block_time
+            FROM transactions
+            WHERE wallet_id = $2 AND transaction_hash = $3 AND token_address = $4
+            LIMIT 1
+            ON CONFLICT (wallet_id, transaction_hash, token_address) DO NOTHING
+            RETURNING {WHALE_EVENT_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::now_v7())
+        .bind(wallet_id)
+        .bind(&tx.transaction_hash)
+        .bind(&tx.token_address)
+        .bind(price_usd)
+        .bind(value_usd)
+        .fetch_optional(&mut *db_tx)
+        .await?;
+        if let Some(event) = event {
+            outbox::record(
+                &mut db_tx,
+                wallet_id,
+                &OutboxEvent::WhaleMovement {
+                    event: event.clone(),
+                },
+            )
+            .await?;
+            db_tx.commit().await?;
+            state.events.publish(
+                event.wallet_id,
+                WalletEventKind::WhaleMovement {
+                    event: event.clone(),
+                },
+            );
+            flagged.push(event);
+        }
+    }
+
+    if !flagged.is_empty() {
+        info!(
+            "Flagged {} whale movements of wallet {}",
+            flagged.len(),
+            wallet_id
+        );
+    }
+    Ok(flagged)
+}
+
+/// Queues a flagged movement for webhooks and notification channels; relayed from
+/// the [outbox](crate::outbox)
+pub async fn notify(pool: &PgPool, event: &WhaleEvent) -> Result<(), sqlx::Error> {
+    webhooks::notify_whale_movement(pool, event).await?;
+
+    let wallet = sqlx::query_as::<_, (Uuid, String, Option<String>)>(
+        "SELECT user_id, address, name FROM wallets WHERE id = $1",
+    )
+    .bind(event.wallet_id)
+    .fetch_one(pool)
+    .await?;
+    let (user_id, address, name) = wallet;
+
+    let amount = event.amount;
+    let message = templates::WHALE_MOVEMENT.render(&[
+        ("wallet", name.as_deref().unwrap_or(&address)),
+        (
+            "direction",
+            if amount.is_negative() {
+                "sent"
+            } else {
+                "received"
+            },
+        ),
+        ("amount", &amount.abs().to_string()),
+        ("symbol", &event.token_symbol),
+        (
+            "value",
+            &format!("${:.2}", event.value_usd.decimal().round_dp(2)),
+        ),
+        ("hash", &event.transaction_hash),
+    ]);
+    let mut conn = pool.acquire().await?;
+    notifications::notify_whale_movement(&mut conn, user_id, &message).await?;
+
+    Ok(())
+}
@@ -1,23 +1,42 @@
 use axum::{
     body::Body,
-    http::{Method, Request, StatusCode},
-    Router,
+    http::{HeaderMap, Method, Request, StatusCode},
+    Json, Router,
 };
 use degen::{
-    handlers::{add_wallet, get_wallet, list_wallets},
     models::{CreateWallet, Wallet},
-    AppState,
+    money::Usd,
+    AppState, Config,
 };
 use hyper::body::to_bytes;
 use serde::de::DeserializeOwned;
+use serde_json::json;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
 
 use sqlx::{postgres::PgPoolOptions, PgPool};
 
 use tower::ServiceExt;
 use uuid::Uuid;
 
+/// API key of the user every test database is seeded with; sent by default on all requests
+pub const TEST_API_KEY: &str = "dgn_test_api_key";
+
 /// Creates a test application with a fresh database connection
 pub async fn create_test_app() -> (Router, PgPool) {
+    create_test_app_with_config(Config::default()).await
+}
+
+/// Creates a test application with a fresh database connection and the given configuration
+pub async fn create_test_app_with_config(config: Config) -> (Router, PgPool) {
+    let pool = create_test_pool().await;
+    let app = degen::create_app_with_state(AppState::new(pool.clone(), config));
+
+    (app, pool)
+}
+
+/// Creates a connection pool to a fresh, migrated test database
+pub async fn create_test_pool() -> PgPool {
     // Load environment variables
     dotenv::dotenv().ok();
 
@@ -34,18 +53,9 @@ pub async fn create_test_app() -> (Router, PgPool) {
 
         // Reset the database for the test
         reset_test_database(&pool).await;
+        seed_test_user(&pool).await;
 
-        let state = degen::AppState {
-            db_pool: pool.clone(),
-        };
-        let app = Router::new()
-            .route(
-                "/wallets",
-                axum::routing::post(degen::add_wallet).get(degen::list_wallets),
-            )
-            .route("/wallets/:id", axum::routing::get(degen::get_wallet))
-            .with_state(state);
-        return (app, pool);
+        return pool;
     }
 
     // Local development: Create a new test database
@@ -55,14 +65,10 @@ pub async fn create_test_app() -> (Router, PgPool) {
 
     // Parse the database URL to extract the base URL (without the database name)
     let base_url = db_url
-        .rsplitn(2, '/')
-        .nth(1)
+        .rsplit_once('/')
+        .map(|(base, _)| base)
         .expect("Invalid database URL format");
 
-    // Create a unique test database name
-    let test_db_name = format!("test_{}", Uuid::new_v4().to_string().replace("-", ""));
-    let test_db_url = format!("{}/{}", base_url, test_db_name);
-
     // Connect to the postgres database to create the test database
     let root_pool = PgPoolOptions::new()
         .max_connections(1)
@@ -70,6 +76,10 @@ pub async fn create_test_app() -> (Router, PgPool) {
         .await
         .expect("Failed to connect to database");
 
+    // Create a unique test database name
+    let test_db_name = test_database_name(&root_pool).await;
+    let test_db_url = format!("{}/{}", base_url, test_db_name);
+
     // Create the test database
     sqlx::query(&format!("CREATE DATABASE {}", test_db_name))
         .execute(&root_pool)
@@ -89,47 +99,63 @@ pub async fn create_test_app() -> (Router, PgPool) {
         .await
         .expect("Failed to run migrations");
 
-    // Set up cleanup when the test is done
-    let root_pool_clone = root_pool.clone();
-    let test_db_name_clone = test_db_name.clone();
-    tokio::spawn(async move {
-        // Wait for the test to complete
-        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    seed_test_user(&pool).await;
 
-        // Close all connections to the test database
-        sqlx::query("SELECT pg_terminate_backend(pid) FROM pg_stat_activity WHERE datname = $1")
-            .bind(&test_db_name_clone)
-            .execute(&root_pool_clone)
-            .await
-            .ok();
+    pool
+}
+
+/// Test databases older than this are left over from earlier runs
+const STALE_TEST_DATABASE_SECS: u64 = 600;
 
-        // Drop the test database
-        sqlx::query(&format!("DROP DATABASE IF EXISTS {}", test_db_name_clone))
-            .execute(&root_pool_clone)
+/// Names a new test database after dropping those left over from earlier runs
+///
+/// Nothing knows when a test is done with its database, so each one is dropped by a
+/// later test once it is old enough that no test can still be using it. The name
+/// carries its creation time for that.
+pub async fn test_database_name(root_pool: &PgPool) -> String {
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs();
+
+    let stale = sqlx::query_scalar::<_, String>(
+        r#"
+        SELECT datname FROM pg_database
+        WHERE datname ~ '^test_[0-9]+_'
+          AND split_part(datname, '_', 2)::BIGINT < $1
+        LIMIT 20
+        "#,
+    )
+    .bind(now.saturating_sub(STALE_TEST_DATABASE_SECS) as i64)
+    .fetch_all(root_pool)
+    .await
+    .unwrap_or_default();
+    for name in stale {
+        // Best effort: another test may be dropping it at the same time
+        sqlx::query(&format!("DROP DATABASE IF EXISTS {name} WITH (FORCE)"))
+            .execute(root_pool)
             .await
             .ok();
-    });
+    }
 
-    // Create the application with the test database
-    let state = AppState {
-        db_pool: pool.clone(),
-    };
-    let app = Router::new()
-        .route(
-            "/wallets",
-            axum::routing::post(add_wallet).get(list_wallets),
-        )
-        .route("/wallets/:id", axum::routing::get(get_wallet))
-        .with_state(state);
+    format!("test_{now}_{}", Uuid::new_v4().simple())
+}
 
-    (app, pool)
+/// Creates the default test user owning [`TEST_API_KEY`]
+async fn seed_test_user(pool: &PgPool) {
+    sqlx::query("INSERT INTO users (id, name, api_key_hash) VALUES ($1, 'test', $2)")
+        .bind(Uuid::now_v7())
+        .bind(degen::auth::hash_api_key(TEST_API_KEY))
+        .execute(pool)
+        .await
+        .expect("Failed to create test user");
 }
 
 /// Resets the test database to a clean state
 #[allow(dead_code)]
 pub async fn reset_test_database(pool: &PgPool) {
     // Disable foreign key checks temporarily
-    sqlx::query("TRUNCATE TABLE wallets CASCADE")
+    sqlx::query("TRUNCATE TABLE users, wallets, idempotency_keys CASCADE")
         .execute(pool)
         .await
         .expect("Failed to clear test data");
@@ -141,14 +167,25 @@ pub async fn reset_test_database(pool: &PgPool) {
         .ok(); // This is best effort, not critical if it fails
 }
 
-/// Helper function to make test requests and return the raw response
+/// Helper function to make test requests as the default test user and return the raw response
 pub async fn make_request_raw<B: serde::Serialize + ?Sized>(
     app: &Router,
     method: &str,
     uri: &str,
     body: Option<&B>,
 ) -> axum::response::Response {
-    let request = build_request(method, uri, body);
+    make_request_raw_as(app, Some(TEST_API_KEY), method, uri, body).await
+}
+
+/// Helper function to make test requests with the given API key (or none) and return the raw response
+pub async fn make_request_raw_as<B: serde::Serialize + ?Sized>(
+    app: &Router,
+    api_key: Option<&str>,
+    method: &str,
+    uri: &str,
+    body: Option<&B>,
+) -> axum::response::Response {
+    let request = build_request(method, uri, body, api_key);
     app.clone().oneshot(request).await.unwrap()
 }
 
@@ -157,25 +194,31 @@ fn build_request<B: serde::Serialize + ?Sized>(
     method: &str,
     uri: &str,
     body: Option<&B>,
+    api_key: Option<&str>,
 ) -> hyper::Request<hyper::Body> {
+    let mut builder = Request::builder().uri(uri);
+    if let Some(api_key) = api_key {
+        builder = builder.header("authorization", format!("Bearer {api_key}"));
+    }
+
     match method {
-        "GET" => Request::builder()
-            .method(Method::GET)
-            .uri(uri)
-            .body(Body::empty())
-            .unwrap(),
-        "POST" => {
+        "GET" => builder.method(Method::GET).body(Body::empty()).unwrap(),
+        "POST" | "PATCH" | "PUT" => {
             let body_bytes = match body {
                 Some(b) => Body::from(serde_json::to_vec(b).unwrap()),
                 None => Body::empty(),
             };
-            Request::builder()
-                .method(Method::POST)
-                .uri(uri)
+            builder
+                .method(match method {
+                    "POST" => Method::POST,
+                    "PATCH" => Method::PATCH,
+                    _ => Method::PUT,
+                })
                 .header("content-type", "application/json")
                 .body(body_bytes)
                 .unwrap()
         }
+        "DELETE" => builder.method(Method::DELETE).body(Body::empty()).unwrap(),
         _ => panic!("Unsupported HTTP method: {}", method),
     }
 }
@@ -201,12 +244,25 @@ pub async fn make_request<B: serde::Serialize + ?Sized, T: DeserializeOwned>(
     (status, body)
 }
 
+/// Generates a random, valid base58-encoded 32-byte wallet address
+pub fn random_address() -> String {
+    degen::seed::random_address()
+}
+
+/// An exact amount of US dollars written as a decimal
+pub fn usd(amount: &str) -> Usd {
+    amount.parse().expect("Invalid test USD amount")
+}
+
 /// Helper function to create a test wallet
 pub async fn create_test_wallet(app: &Router, address: &str, name: Option<&str>) -> Wallet {
     // Create a wallet with the given address and name
     let wallet = CreateWallet {
-        address: address.to_string(),
+        address: address.parse().expect("Invalid test wallet address"),
+        domain: None,
         name: name.map(|s| s.to_string()),
+        notes: None,
+        metadata: None,
     };
 
     // Make a request to create the wallet
@@ -222,3 +278,529 @@ pub async fn create_test_wallet(app: &Router, address: &str, name: Option<&str>)
 
     wallet
 }
+
+/// Starts a mock Solana JSON-RPC server and returns its URL
+///
+/// `getSignaturesForAddress` returns `signatures`; `getTransaction` returns the entry
+/// in `transactions` keyed by the requested signature, or `null`.
+pub async fn spawn_mock_rpc(
+    signatures: serde_json::Value,
+    transactions: HashMap<String, serde_json::Value>,
+) -> String {
+    let transactions = Arc::new(transactions);
+
+    let handler = move |Json(request): Json<serde_json::Value>| {
+        let signatures = signatures.clone();
+        let transactions = transactions.clone();
+        async move {
+            let result = match request["method"].as_str() {
+                Some("getSignaturesForAddress") => signatures,
+                Some("getTransaction") => request["params"][0]
+                    .as_str()
+                    .and_then(|sig| transactions.get(sig).cloned())
+                    .unwrap_or(serde_json::Value::Null),
+                _ => serde_json::Value::Null,
+            };
+            Json(json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }))
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/", axum::routing::post(handler));
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    format!("http://{addr}")
+}
+
+/// Starts a mock Solana JSON-RPC server serving accounts and returns its URL and the
+/// number of calls it received
+///
+/// `getAccountInfo` and `getMultipleAccounts` return the entries of `accounts` keyed
+/// by address, or `null`; `getTokenLargestAccounts` returns `largest_accounts`.
+pub async fn spawn_account_rpc(
+    accounts: HashMap<String, serde_json::Value>,
+    largest_accounts: serde_json::Value,
+) -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+    let accounts = Arc::new(accounts);
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    let counter = calls.clone();
+    let handler = move |Json(request): Json<serde_json::Value>| {
+        let accounts = accounts.clone();
+        let largest_accounts = largest_accounts.clone();
+        counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        async move {
+            let account = |address: &serde_json::Value| {
+                address
+                    .as_str()
+                    .and_then(|address| accounts.get(address).cloned())
+                    .unwrap_or(serde_json::Value::Null)
+            };
+            let value = match request["method"].as_str() {
+                Some("getAccountInfo") => account(&request["params"][0]),
+                Some("getMultipleAccounts") => request["params"][0]
+                    .as_array()
+                    .map(|addresses| addresses.iter().map(account).collect())
+                    .unwrap_or_default(),
+                Some("getTokenLargestAccounts") => largest_accounts,
+                _ => serde_json::Value::Null,
+            };
+            Json(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": { "context": { "slot": 1 }, "value": value }
+            }))
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/", axum::routing::post(handler));
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    (format!("http://{addr}"), calls)
+}
+
+/// Transactions of one wallet on a mock Solana node, served by [`spawn_ledger_rpc`]
+///
+/// Every transaction credits the wallet with 1 SOL.
+#[derive(Debug, Default)]
+pub struct MockLedger {
+    /// Signatures and their slots, newest first
+    pub signatures: Mutex<Vec<(String, u64)>>,
+    /// Signatures whose `getTransaction` fails with an internal error
+    pub broken: Mutex<HashSet<String>>,
+    /// Number of times each signature's transaction was fetched
+    pub fetches: Mutex<HashMap<String, usize>>,
+}
+
+impl MockLedger {
+    /// Adds a transaction newer than all others
+    pub fn push(&self, signature: &str, slot: u64) {
+        self.signatures
+            .lock()
+            .unwrap()
+            .insert(0, (signature.to_string(), slot));
+    }
+
+    /// Number of times a signature's transaction was fetched
+    pub fn fetches(&self, signature: &str) -> usize {
+        self.fetches
+            .lock()
+            .unwrap()
+            .get(signature)
+            .copied()
+            .unwrap_or(0)
+    }
+}
+
+/// Starts a mock Solana JSON-RPC server over `ledger`, the transactions of `address`,
+/// and returns its URL
+///
+/// `getSignaturesForAddress` honours `before`, `until` and `limit` like a real node,
+/// listing everything before `before` if `until` is not in the ledger.
+pub async fn spawn_ledger_rpc(address: &str, ledger: Arc<MockLedger>) -> String {
+    let address = address.to_string();
+
+    let handler = move |Json(request): Json<serde_json::Value>| {
+        let address = address.clone();
+        let ledger = ledger.clone();
+        async move {
+            let params = &request["params"];
+            let mut response = match request["method"].as_str() {
+                Some("getSignaturesForAddress") => {
+                    let signatures = ledger.signatures.lock().unwrap().clone();
+                    let options = &params[1];
+                    let position = |key: &str| {
+                        options[key].as_str().and_then(|signature| {
+                            signatures.iter().position(|(s, _)| s == signature)
+                        })
+                    };
+                    let start = position("before").map_or(0, |index| index + 1);
+                    let end = position("until")
+                        .filter(|end| *end >= start)
+                        .unwrap_or(signatures.len());
+                    let limit = options["limit"].as_u64().unwrap_or(1000) as usize;
+                    let page: Vec<serde_json::Value> = signatures[start..end]
+                        .iter()
+                        .take(limit)
+                        .map(|(signature, slot)| {
+                            json!({ "signature": signature, "slot": slot, "err": null })
+                        })
+                        .collect();
+                    json!({ "result": page })
+                }
+                Some("getTransaction") => {
+                    let signature = params[0].as_str().unwrap_or_default().to_string();
+                    *ledger
+                        .fetches
+                        .lock()
+                        .unwrap()
+                        .entry(signature.clone())
+                        .or_default() += 1;
+                    if ledger.broken.lock().unwrap().contains(&signature) {
+                        json!({ "error": { "code": -32603, "message": "Internal error" } })
+                    } else {
+                        json!({ "result": {
+                            "blockTime": 1721408400,
+                            "meta": {
+                                "err": null,
+                                "fee": 0,
+                                "preBalances": [0u64],
+                                "postBalances": [1_000_000_000u64],
+                                "preTokenBalances": [],
+                                "postTokenBalances": []
+                            },
+                            "transaction": { "message": { "accountKeys": [
+                                { "pubkey": address, "signer": false, "writable": true }
+                            ] } }
+                        } })
+                    }
+                }
+                _ => json!({ "result": null }),
+            };
+            response["jsonrpc"] = json!("2.0");
+            response["id"] = request["id"].clone();
+            Json(response)
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/", axum::routing::post(handler));
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    format!("http://{addr}")
+}
+
+/// Starts a mock Solana JSON-RPC server serving an owner's balances and returns its URL
+///
+/// `getBalance` returns `lamports`; `getTokenAccountsByOwner` returns the accounts in
+/// `token_accounts` of the requested program, or none.
+pub async fn spawn_balance_rpc(
+    lamports: u64,
+    token_accounts: HashMap<String, serde_json::Value>,
+) -> String {
+    let token_accounts = Arc::new(token_accounts);
+
+    let handler = move |Json(request): Json<serde_json::Value>| {
+        let token_accounts = token_accounts.clone();
+        async move {
+            let value = match request["method"].as_str() {
+                Some("getBalance") => json!(lamports),
+                Some("getTokenAccountsByOwner") => request["params"][1]["programId"]
+                    .as_str()
+                    .and_then(|program| token_accounts.get(program).cloned())
+                    .unwrap_or_else(|| json!([])),
+                _ => serde_json::Value::Null,
+            };
+            Json(json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "result": { "context": { "slot": 1 }, "value": value }
+            }))
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/", axum::routing::post(handler));
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    format!("http://{addr}")
+}
+
+/// Starts an RPC node that answers every call with `429 Too Many Requests` and
+/// returns its base URL and the number of calls it received
+pub async fn spawn_throttling_rpc() -> (String, Arc<std::sync::atomic::AtomicUsize>) {
+    let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+
+    let counter = calls.clone();
+    let handler = move || {
+        let counter = counter.clone();
+        async move {
+            counter.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+            StatusCode::TOO_MANY_REQUESTS
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/", axum::routing::post(handler));
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    (format!("http://{addr}"), calls)
+}
+
+/// Requests received by a mock webhook receiver: headers and body of each delivery
+pub type ReceivedWebhooks = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+/// Starts a mock webhook receiver and returns its base URL and the deliveries it received
+///
+/// `POST /ok` answers `200 OK`; `POST /fail` answers `500 Internal Server Error`;
+/// `POST /redirect` redirects to `/ok`.
+pub async fn spawn_webhook_receiver() -> (String, ReceivedWebhooks) {
+    let received: ReceivedWebhooks = Arc::default();
+
+    let record = |status: StatusCode| {
+        let received = received.clone();
+        move |headers: HeaderMap, body: String| {
+            let received = received.clone();
+            async move {
+                received.lock().unwrap().push((headers, body));
+                status
+            }
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new()
+        .route("/ok", axum::routing::post(record(StatusCode::OK)))
+        .route(
+            "/fail",
+            axum::routing::post(record(StatusCode::INTERNAL_SERVER_ERROR)),
+        )
+        .route(
+            "/redirect",
+            axum::routing::post(|| async {
+                (StatusCode::TEMPORARY_REDIRECT, [("location", "/ok")])
+            }),
+        );
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    (format!("http://{addr}"), received)
+}
+
+/// Messages received by a mock Telegram Bot API: the bot path segment and JSON body
+/// of each `sendMessage` call
+pub type SentTelegramMessages = Arc<Mutex<Vec<(String, serde_json::Value)>>>;
+
+/// Starts a mock Telegram Bot API and returns its base URL and the messages it received
+///
+/// `sendMessage` succeeds except for chat `-1`, which it rejects as not found.
+pub async fn spawn_telegram_api() -> (String, SentTelegramMessages) {
+    let sent: SentTelegramMessages = Arc::default();
+
+    let record = {
+        let sent = sent.clone();
+        move |axum::extract::Path(bot): axum::extract::Path<String>,
+              Json(body): Json<serde_json::Value>| {
+            let sent = sent.clone();
+            async move {
+                let rejected = body["chat_id"] == "-1";
+                sent.lock().unwrap().push((bot, body));
+                if rejected {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        Json(json!({ "ok": false, "description": "Bad Request: chat not found" })),
+                    )
+                } else {
+                    (StatusCode::OK, Json(json!({ "ok": true, "result": {} })))
+                }
+            }
+        }
+    };
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let app = Router::new().route("/:bot/sendMessage", axum::routing::post(record));
+    tokio::spawn(async move {
+        axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service())
+            .await
+            .unwrap();
+    });
+
+    (format!("http://{addr}"), sent)
+}
+
+/// Emails received by a mock SMTP server, as the raw message data of each
+pub type ReceivedEmails = Arc<Mutex<Vec<String>>>;
+
+/// Starts a mock SMTP server accepting every message and returns its `smtp://` URL and
+/// the emails it received
+pub async fn spawn_smtp_server() -> (String, ReceivedEmails) {
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+    let received: ReceivedEmails = Arc::default();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let emails = received.clone();
+    tokio::spawn(async move {
+        while let Ok((socket, _)) = listener.accept().await {
+            let emails = emails.clone();
+            tokio::spawn(async move {
+                let (reader, mut writer) = socket.into_split();
+                let mut lines = BufReader::new(reader).lines();
+                writer.write_all(b"220 localhost ESMTP\r\n").await?;
+                while let Some(line) = lines.next_line().await? {
+                    let command = line.to_ascii_uppercase();
+                    let reply: &[u8] = if command.starts_with("EHLO") || command.starts_with("HELO")
+                    {
+                        b"250 localhost\r\n"
+                    } else if command.starts_with("DATA") {
+                        writer.write_all(b"354 End data with .\r\n").await?;
+                        let mut data = String::new();
+                        while let Some(line) = lines.next_line().await? {
+                            if line == "." {
+                                break;
+                            }
+                            data.push_str(&line);
+                            data.push('\n');
+                        }
+                        emails.lock().unwrap().push(data);
+                        b"250 OK\r\n"
+                    } else if command.starts_with("QUIT") {
+                        writer.write_all(b"221 Bye\r\n").await?;
+                        break;
+                    } else {
+                        b"250 OK\r\n"
+                    };
+                    writer.write_all(reply).await?;
+                }
+                Ok::<_, std::io::Error>(())
+            });
+        }
+    });
+
+    (format!("smtp://{addr}"), received)
+}
+
+/// Inserts a transaction row for a wallet directly into the database
+///
+/// `amount` is signed (negative for tokens leaving the wallet) and `price_usd` is
+/// the per-token USD price at the time of the transaction.
+pub async fn insert_test_transaction(
+    pool: &PgPool,
+    wallet_id: Uuid,
+    token_address: &str,
+    token_symbol: &str,
+    amount: &str,
+    price_usd: &str,
+) -> Uuid {
+    let id = Uuid::now_v7();
+    sqlx::query(
+        r#"
+        INSERT INTO transactions (
+            id, wallet_id, token_address, token_symbol, amount,
+            buy_price_usd, buy_price_sol, transaction_hash, block_number, block_time
+        )
+        VALUES ($1, $2, $3, $4, $5::NUMERIC, $6::NUMERIC, 0, $7, 0, NOW())
+        "#,
+    )
+    .bind(id)
+    .bind(wallet_id)
+    .bind(token_address)
+    .bind(token_symbol)
+    .bind(amount)
+    .bind(price_usd)
+    .bind(id.to_string())
+    .execute(pool)
+    .await
+    .expect("Failed to insert test transaction");
+
+    id
+}
+
+/// Starts a mock Solana WebSocket endpoint and returns its URL and the subscription
+/// requests received, as method and address
+///
+/// It accepts one connection, answers every subscription with a new ID and, once
+/// `subscriptions` have been made, sends a `logsNotification` for the first
+/// `logsSubscribe` and closes the connection.
+pub async fn spawn_ws_rpc(subscriptions: usize) -> (String, Arc<Mutex<Vec<(String, String)>>>) {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let recorded = received.clone();
+
+    tokio::spawn(async move {
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut socket = tokio_tungstenite::accept_async(stream).await.unwrap();
+        let mut logs_subscription = None;
+        let mut subscribed = 0;
+        while let Some(Ok(message)) = socket.next().await {
+            let Message::Text(text) = message else {
+                continue;
+            };
+            let request: serde_json::Value = serde_json::from_str(&text).unwrap();
+            let method = request["method"].as_str().unwrap_or_default().to_string();
+            let params = &request["params"];
+            let address = params[0]
+                .as_str()
+                .or(params[0]["mentions"][0].as_str())
+                .unwrap_or_default()
+                .to_string();
+            subscribed += 1;
+            let subscription = 100 + subscribed as u64;
+            if method == "logsSubscribe" && logs_subscription.is_none() {
+                logs_subscription = Some(subscription);
+            }
+            recorded.lock().unwrap().push((method, address));
+            let reply = json!({ "jsonrpc": "2.0", "id": request["id"], "result": subscription });
+            socket.send(Message::text(reply.to_string())).await.unwrap();
+
+            if subscribed == subscriptions {
+                let notification = json!({
+                    "jsonrpc": "2.0",
+                    "method": "logsNotification",
+                    "params": {
+                        "subscription": logs_subscription,
+                        "result": { "context": { "slot": 1 }, "value": { "signature": "sig", "err": null, "logs": [] } }
+                    }
+                });
+                socket
+                    .send(Message::text(notification.to_string()))
+                    .await
+                    .unwrap();
+                socket.close(None).await.unwrap();
+                break;
+            }
+        }
+    });
+
+    (format!("ws://{addr}"), received)
+}